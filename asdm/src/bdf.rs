@@ -0,0 +1,881 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Decoding visibilities stored in the Binary Data Format (BDF).
+
+The visibilities of an ASDM are stored in BDF files in its `ASDMBinary`
+directory, one for each row of the `Main` table. A BDF file is a MIME
+`multipart/mixed` document. Its first part is an XML `sdmDataHeader`, which
+describes the layout of the data: the number of antennas, the basebands and
+spectral windows, the polarization products of each window, and the order of
+the axes of each kind of binary data. Each later part is a
+`multipart/related` *subset* holding one integration: an XML
+`sdmDataSubsetHeader`, which gives the time and length of the integration
+and the element type of the data, followed by binary parts such as
+`crossData.bin` and `flags.bin`.
+
+A `BdfReader` reads the parts of a file one at a time, so that only one
+integration is in memory at once, and delivers each as a `VisChunk` by
+implementing `LiveVisSource`. Since a `VisChunk` has a single number of
+channels, a reader delivers one spectral window at a time, chosen with
+`set_spectral_window`. The limitations are:
+
+- Only cross-correlations are decoded; autocorrelations are skipped.
+- The axes of the cross-correlation data must be `BAL` (the baselines)
+  followed by some of `BAB`, `SPW`, `BIN`, `APC`, `SPP`, and `POL`, in that
+  order, which is how ALMA and the VLA write them. Only the first bin and
+  the first atmospheric phase correction are read.
+- Flags must have `BAL` as their first axis, followed by some of `ANT`,
+  `BAB`, `SPW`, and `POL`, in that order. A nonzero flag word flags every
+  channel of the products it applies to.
+
+Baselines are ordered as in the BDF: (0, 1), (0, 2), (1, 2), (0, 3), and so
+on. Antenna numbers are the indices of the antennas in the list given by the
+`ConfigDescription` table, not the numbers of `Antenna` table rows.
+
+*/
+
+use failure::{err_msg, Error};
+use roxmltree::{Document, Node};
+use rubbl_core::units::{MjdSeconds, Seconds};
+use rubbl_core::Complex;
+use rubbl_visdata::baseline::{TriangleOrder, TriangularBaselines};
+use rubbl_visdata::streaming::{LiveVisSource, VisChunk};
+use rubbl_visdata::{BasePol, VisPol};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// The axes that may follow `BAL` in the cross-correlation data, in order.
+const CROSS_AXES: &[&str] = &["BAB", "SPW", "BIN", "APC", "SPP", "POL"];
+
+/// The axes that may follow `BAL` in the flags, in order.
+const FLAG_AXES: &[&str] = &["ANT", "BAB", "SPW", "POL"];
+
+/// A spectral window, as described by the header of a BDF file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BdfSpectralWindow {
+    /// The index of the window's baseband among those in the header.
+    pub baseband_index: usize,
+
+    /// The name of the window's baseband, such as `BB_1`.
+    pub baseband_name: String,
+
+    /// The number of channels.
+    pub num_spectral_point: usize,
+
+    /// The number of bins.
+    pub num_bin: usize,
+
+    /// The polarization products of the cross-correlations.
+    pub cross_pol_products: Vec<VisPol>,
+
+    /// The factor by which integer visibilities must be divided.
+    pub scale_factor: f64,
+}
+
+/// The contents of the `sdmDataHeader` of a BDF file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BdfHeader {
+    /// The start of the data.
+    pub start_time: MjdSeconds,
+
+    /// The number of antennas.
+    pub num_antenna: usize,
+
+    /// The kinds of correlations present, such as `CROSS_AND_AUTO`.
+    pub correlation_mode: String,
+
+    /// Whether the binary data are big-endian.
+    pub big_endian: bool,
+
+    /// The spectral windows, in order of baseband and then of window.
+    pub spectral_windows: Vec<BdfSpectralWindow>,
+
+    /// The number of atmospheric phase corrections applied, each of which
+    /// gets its own copy of the data.
+    pub num_apc: usize,
+
+    /// The axes of the cross-correlation data, if there are any.
+    pub cross_data_axes: Option<Vec<String>>,
+
+    /// The axes of the flags, if there are any.
+    pub flags_axes: Option<Vec<String>>,
+}
+
+impl BdfHeader {
+    /// Parse the XML document *text*.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let doc = Document::parse(text)?;
+        let root = doc.root_element();
+
+        if !root.has_tag_name("sdmDataHeader") {
+            return Err(err_msg(format!(
+                "expected a BDF sdmDataHeader, but found a {} element",
+                root.tag_name().name()
+            )));
+        }
+
+        let data_struct = child(root, "dataStruct")?;
+        let mut spectral_windows = Vec::new();
+
+        for (bb_index, baseband) in data_struct
+            .children()
+            .filter(|n| n.has_tag_name("baseband"))
+            .enumerate()
+        {
+            let baseband_name = baseband.attribute("name").unwrap_or("").to_owned();
+
+            for spw in baseband
+                .children()
+                .filter(|n| n.has_tag_name("spectralWindow"))
+            {
+                let cross_pol_products = match spw.attribute("crossPolProducts") {
+                    Some(text) => text
+                        .split_whitespace()
+                        .map(parse_vispol)
+                        .collect::<Result<_, _>>()?,
+                    None => Vec::new(),
+                };
+
+                spectral_windows.push(BdfSpectralWindow {
+                    baseband_index: bb_index,
+                    baseband_name: baseband_name.clone(),
+                    num_spectral_point: parse_attribute(spw, "numSpectralPoint")?,
+                    num_bin: parse_attribute(spw, "numBin")?,
+                    cross_pol_products: cross_pol_products,
+                    scale_factor: match spw.attribute("scaleFactor") {
+                        Some(_) => parse_attribute(spw, "scaleFactor")?,
+                        None => 1.,
+                    },
+                });
+            }
+        }
+
+        let axes = |name: &str| {
+            data_struct
+                .children()
+                .find(|n| n.has_tag_name(name))
+                .and_then(|n| n.attribute("axes"))
+                .map(|a| a.split_whitespace().map(|s| s.to_owned()).collect())
+        };
+
+        Ok(BdfHeader {
+            start_time: MjdSeconds(parse_text::<i64>(root, "startTime")? as f64 / 1e9),
+            num_antenna: parse_text(root, "numAntenna")?,
+            correlation_mode: child_text(root, "correlationMode")?.to_owned(),
+            big_endian: root.attribute("byteOrder") == Some("Big_Endian"),
+            spectral_windows: spectral_windows,
+            num_apc: match data_struct.attribute("apc") {
+                Some(apc) => ::std::cmp::max(apc.split_whitespace().count(), 1),
+                None => 1,
+            },
+            cross_data_axes: axes("crossData"),
+            flags_axes: axes("flags"),
+        })
+    }
+
+    /// Get the number of baselines.
+    pub fn num_baselines(&self) -> usize {
+        self.baselines().n_baselines()
+    }
+
+    /// Get the numbering of the baselines, which excludes autocorrelations.
+    pub fn baselines(&self) -> TriangularBaselines {
+        TriangularBaselines::new(self.num_antenna, false, TriangleOrder::Ant1Fastest)
+    }
+}
+
+/// Reads the visibilities of a BDF file.
+#[derive(Debug)]
+pub struct BdfReader<R: BufRead> {
+    inner: R,
+    boundary: String,
+    header: BdfHeader,
+    spw_index: usize,
+    done: bool,
+}
+
+impl BdfReader<BufReader<File>> {
+    /// Open the BDF file at *path*.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) => {
+                return Err(err_msg(format!(
+                    "failed to open \"{}\": {}",
+                    path.display(),
+                    e
+                )));
+            }
+        };
+
+        Self::new(BufReader::new(file))
+    }
+}
+
+impl<R: BufRead> BdfReader<R> {
+    /// Create a reader of the BDF document in *inner*, reading its header.
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let headers = read_headers(&mut inner)?;
+        let boundary = boundary_of(&headers)?;
+
+        // Skip the preamble.
+        let (_, delim) = read_body(&mut inner, &boundary)?;
+
+        if delim != Delimiter::Next {
+            return Err(err_msg("the BDF document has no parts"));
+        }
+
+        let part_headers = read_headers(&mut inner)?;
+        let (body, delim) = read_body(&mut inner, &boundary)?;
+        let header = BdfHeader::parse(&xml_text(&body, &part_headers)?)?;
+
+        if header.spectral_windows.is_empty() {
+            return Err(err_msg("the BDF header describes no spectral windows"));
+        }
+
+        Ok(BdfReader {
+            inner: inner,
+            boundary: boundary,
+            header: header,
+            spw_index: 0,
+            done: delim != Delimiter::Next,
+        })
+    }
+
+    /// Get the header of the document.
+    pub fn header(&self) -> &BdfHeader {
+        &self.header
+    }
+
+    /// Choose the spectral window whose visibilities are delivered, by its
+    /// index in `BdfHeader::spectral_windows`. The default is 0.
+    pub fn set_spectral_window(&mut self, index: usize) -> Result<&mut Self, Error> {
+        if index >= self.header.spectral_windows.len() {
+            return Err(err_msg(format!(
+                "the BDF data have {} spectral windows, so there is no window {}",
+                self.header.spectral_windows.len(),
+                index
+            )));
+        }
+
+        self.spw_index = index;
+        Ok(self)
+    }
+
+    /// Decode the subset held in the body of a `multipart/related` part,
+    /// returning `None` if it holds no cross-correlations.
+    fn decode_subset(&self, body: &[u8], boundary: &str) -> Result<Option<VisChunk>, Error> {
+        let mut cursor = body;
+        let (_, mut delim) = read_body(&mut cursor, boundary)?;
+        let mut subset_header = None;
+        let mut cross_data = None;
+        let mut flags = None;
+
+        while delim == Delimiter::Next {
+            let headers = read_headers(&mut cursor)?;
+            let (part, d) = read_body(&mut cursor, boundary)?;
+            delim = d;
+
+            let location = header_value(&headers, "Content-Location").unwrap_or("");
+            let content_type = header_value(&headers, "Content-Type").unwrap_or("");
+
+            if content_type.starts_with("text/xml") {
+                subset_header = Some(xml_text(&part, &headers)?);
+            } else if location.ends_with("crossData.bin") {
+                cross_data = Some(part);
+            } else if location.ends_with("flags.bin") {
+                flags = Some(part);
+            }
+        }
+
+        let subset_header = match subset_header {
+            Some(h) => h,
+            None => return Err(err_msg("a BDF subset has no sdmDataSubsetHeader")),
+        };
+
+        let doc = Document::parse(&subset_header)?;
+        let root = doc.root_element();
+
+        let cross_data = match cross_data {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+
+        let period = child(root, "schedulePeriodTime")?;
+        let time = parse_text::<i64>(period, "time")? as f64 / 1e9;
+        let interval = parse_text::<i64>(period, "interval")? as f64 / 1e9;
+        let element_type = child(root, "crossData")?
+            .attribute("type")
+            .unwrap_or("FLOAT32_TYPE");
+
+        let layout = CrossLayout::new(&self.header, self.spw_index)?;
+        let values = decode_values(&cross_data, element_type, self.header.big_endian)?;
+        let n_bl = self.header.num_baselines();
+
+        if values.len() != n_bl * layout.baseline_size {
+            return Err(err_msg(format!(
+                "the BDF cross-correlation data have {} values, but {} were expected",
+                values.len(),
+                n_bl * layout.baseline_size
+            )));
+        }
+
+        let flag_words = match (flags, self.header.flags_axes.as_ref()) {
+            (Some(f), Some(axes)) => Some((
+                FlagLayout::new(&self.header, axes, self.spw_index)?,
+                decode_u32s(&f, self.header.big_endian),
+            )),
+            _ => None,
+        };
+
+        let spw = &self.header.spectral_windows[self.spw_index];
+        let scale = match element_type {
+            "FLOAT32_TYPE" => 1.,
+            _ => 1. / spw.scale_factor,
+        };
+
+        let n_chan = spw.num_spectral_point;
+        let mut chunk = VisChunk::new(MjdSeconds(time), Seconds(interval), n_chan);
+        let mut data = vec![Complex::new(0f32, 0f32); n_chan];
+        let mut chan_flags = vec![false; n_chan];
+        let baselines = self.header.baselines();
+
+        for (bl, pair) in baselines.pairs().enumerate() {
+            let base = bl * layout.baseline_size + layout.spw_offset;
+
+            for (pol_index, &pol) in spw.cross_pol_products.iter().enumerate() {
+                for (chan, vis) in data.iter_mut().enumerate() {
+                    let i = base + 2 * (chan * layout.n_pol + pol_index);
+                    *vis = Complex::new((values[i] * scale) as f32, (values[i + 1] * scale) as f32);
+                }
+
+                let flagged = match flag_words {
+                    Some((ref l, ref words)) => words
+                        .get(l.index(bl, pol_index))
+                        .map(|&w| w != 0)
+                        .unwrap_or(false),
+                    None => false,
+                };
+
+                chan_flags.fill(flagged);
+
+                chunk.push(BasePol::new(pair.ant1, pair.ant2, pol), &data, &chan_flags);
+            }
+        }
+
+        Ok(Some(chunk))
+    }
+}
+
+impl<R: BufRead> LiveVisSource for BdfReader<R> {
+    fn next_chunk(&mut self) -> Result<Option<VisChunk>, Error> {
+        while !self.done {
+            let headers = read_headers(&mut self.inner)?;
+            let (body, delim) = read_body(&mut self.inner, &self.boundary)?;
+            self.done = delim != Delimiter::Next;
+
+            // Trailing whitespace after the last delimiter looks like an
+            // empty part.
+            if headers.is_empty() && body.iter().all(|b| b.is_ascii_whitespace()) {
+                continue;
+            }
+
+            let boundary = boundary_of(&headers)?;
+
+            if let Some(chunk) = self.decode_subset(&body, &boundary)? {
+                return Ok(Some(chunk));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Where the visibilities of one spectral window lie in the
+/// cross-correlation data.
+struct CrossLayout {
+    /// The number of values (real or imaginary parts) for each baseline.
+    baseline_size: usize,
+
+    /// The offset of the window's values within those of a baseline.
+    spw_offset: usize,
+
+    /// The number of polarization products of the window.
+    n_pol: usize,
+}
+
+impl CrossLayout {
+    fn new(header: &BdfHeader, spw_index: usize) -> Result<Self, Error> {
+        let axes = match header.cross_data_axes {
+            Some(ref a) => a,
+            None => return Err(err_msg("the BDF header does not describe any crossData")),
+        };
+
+        if axes.first().map(|s| s.as_str()) != Some("BAL") || !in_order(&axes[1..], CROSS_AXES) {
+            return Err(err_msg(format!(
+                "unsupported BDF crossData axes \"{}\"",
+                axes.join(" ")
+            )));
+        }
+
+        let has = |name: &str| axes.iter().any(|a| a == name);
+        let size_of = |spw: &BdfSpectralWindow| {
+            let mut n = 2;
+
+            if has("BIN") {
+                n *= spw.num_bin;
+            }
+            if has("APC") {
+                n *= header.num_apc;
+            }
+            if has("SPP") {
+                n *= spw.num_spectral_point;
+            }
+            if has("POL") {
+                n *= spw.cross_pol_products.len();
+            }
+
+            n
+        };
+
+        let spws = &header.spectral_windows;
+        let spw = &spws[spw_index];
+
+        if (!has("SPP") && spw.num_spectral_point != 1)
+            || (!has("POL") && spw.cross_pol_products.len() != 1)
+        {
+            return Err(err_msg(format!(
+                "BDF crossData axes \"{}\" do not match the spectral windows",
+                axes.join(" ")
+            )));
+        }
+
+        Ok(CrossLayout {
+            baseline_size: spws.iter().map(&size_of).sum(),
+            spw_offset: spws[..spw_index].iter().map(&size_of).sum(),
+            n_pol: spw.cross_pol_products.len(),
+        })
+    }
+}
+
+/// Where the flags of one spectral window lie in the flag words.
+struct FlagLayout {
+    /// The number of words for each baseline.
+    baseline_size: usize,
+
+    /// The offset of the window's words within those of a baseline.
+    spw_offset: usize,
+
+    /// Whether there is a word for each polarization product.
+    per_pol: bool,
+}
+
+impl FlagLayout {
+    fn new(header: &BdfHeader, axes: &[String], spw_index: usize) -> Result<Self, Error> {
+        if axes.first().map(|s| s.as_str()) != Some("BAL") || !in_order(&axes[1..], FLAG_AXES) {
+            return Err(err_msg(format!(
+                "unsupported BDF flags axes \"{}\"",
+                axes.join(" ")
+            )));
+        }
+
+        let has = |name: &str| axes.iter().any(|a| a == name);
+        let spws = &header.spectral_windows;
+        let mut baseline_size = 0;
+        let mut unit_start = 0;
+        let mut spw_offset = 0;
+
+        for (i, spw) in spws.iter().enumerate() {
+            // Without a SPW axis, each baseband has one set of words, which
+            // we take to be shaped by its first window. Without a BAB axis
+            // either, there is one set in all.
+            let new_unit = i == 0
+                || has("SPW")
+                || (has("BAB") && spws[i - 1].baseband_index != spw.baseband_index);
+
+            if new_unit {
+                unit_start = baseline_size;
+                baseline_size += if has("POL") {
+                    spw.cross_pol_products.len()
+                } else {
+                    1
+                };
+            }
+
+            if i == spw_index {
+                spw_offset = unit_start;
+            }
+        }
+
+        Ok(FlagLayout {
+            baseline_size: baseline_size,
+            spw_offset: spw_offset,
+            per_pol: has("POL"),
+        })
+    }
+
+    /// Get the index of the word that flags product *pol_index* of baseline
+    /// *bl*.
+    fn index(&self, bl: usize, pol_index: usize) -> usize {
+        bl * self.baseline_size + self.spw_offset + if self.per_pol { pol_index } else { 0 }
+    }
+}
+
+/// How a body of a multipart document ended.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Delimiter {
+    /// Another part follows.
+    Next,
+
+    /// The closing delimiter was found.
+    Close,
+
+    /// The input ended without a closing delimiter.
+    Eof,
+}
+
+/// Read MIME headers, up to the blank line that ends them.
+fn read_headers<R: BufRead>(r: &mut R) -> Result<Vec<(String, String)>, Error> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+
+        if r.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end();
+
+        if text.is_empty() {
+            // Blank lines before the first header are tolerated.
+            if headers.is_empty() {
+                continue;
+            }
+            break;
+        }
+
+        if text.starts_with(|c: char| c.is_whitespace()) {
+            if let Some(last) = headers.last_mut() {
+                last.1.push(' ');
+                last.1.push_str(text.trim());
+                continue;
+            }
+        }
+
+        match text.find(':') {
+            Some(idx) => headers.push((
+                text[..idx].trim().to_owned(),
+                text[idx + 1..].trim().to_owned(),
+            )),
+            None => {
+                return Err(err_msg(format!("malformed MIME header line \"{}\"", text)));
+            }
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Read a body up to the next delimiter for *boundary*, returning it and
+/// the kind of delimiter that ended it.
+fn read_body<R: BufRead>(r: &mut R, boundary: &str) -> Result<(Vec<u8>, Delimiter), Error> {
+    let delim = format!("--{}", boundary);
+    let close = format!("--{}--", boundary);
+    let mut body = Vec::new();
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+
+        if r.read_until(b'\n', &mut line)? == 0 {
+            return Ok((body, Delimiter::Eof));
+        }
+
+        let kind = {
+            let trimmed = trim_end(&line);
+
+            if trimmed == delim.as_bytes() {
+                Some(Delimiter::Next)
+            } else if trimmed == close.as_bytes() {
+                Some(Delimiter::Close)
+            } else {
+                None
+            }
+        };
+
+        if let Some(kind) = kind {
+            // The line break before a delimiter belongs to the delimiter.
+            if body.last() == Some(&b'\n') {
+                body.pop();
+
+                if body.last() == Some(&b'\r') {
+                    body.pop();
+                }
+            }
+
+            return Ok((body, kind));
+        }
+
+        body.extend_from_slice(&line);
+    }
+}
+
+fn trim_end(line: &[u8]) -> &[u8] {
+    let n = line
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    &line[..n]
+}
+
+/// Get the value of the header *name*.
+fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|h| h.0.eq_ignore_ascii_case(name))
+        .map(|h| h.1.as_str())
+}
+
+/// Get the boundary of a multipart part from its `Content-Type` header.
+fn boundary_of(headers: &[(String, String)]) -> Result<String, Error> {
+    let content_type = header_value(headers, "Content-Type").unwrap_or("");
+
+    for param in content_type.split(';').skip(1) {
+        let mut pieces = param.splitn(2, '=');
+
+        if pieces.next().map(|s| s.trim().to_lowercase()) == Some("boundary".to_owned()) {
+            if let Some(value) = pieces.next() {
+                return Ok(value.trim().trim_matches('"').to_owned());
+            }
+        }
+    }
+
+    Err(err_msg(format!(
+        "expected a multipart BDF part, but found one of type \"{}\"",
+        content_type
+    )))
+}
+
+/// Decode the body of an XML part.
+fn xml_text(body: &[u8], headers: &[(String, String)]) -> Result<String, Error> {
+    let content_type = header_value(headers, "Content-Type").unwrap_or("");
+
+    if !content_type.starts_with("text/xml") {
+        return Err(err_msg(format!(
+            "expected an XML part in the BDF document, but found one of type \"{}\"",
+            content_type
+        )));
+    }
+
+    // Some writers pad XML parts with NULs.
+    let n = body
+        .iter()
+        .rposition(|&b| b != 0)
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    Ok(String::from_utf8_lossy(&body[..n]).into_owned())
+}
+
+/// Decode binary values of the BDF type *element_type*.
+fn decode_values(bytes: &[u8], element_type: &str, big_endian: bool) -> Result<Vec<f64>, Error> {
+    macro_rules! decode {
+        ($ty:ty, $size:expr) => {
+            bytes
+                .chunks_exact($size)
+                .map(|c| {
+                    let mut buf = [0u8; $size];
+                    buf.copy_from_slice(c);
+
+                    if big_endian {
+                        <$ty>::from_be_bytes(buf) as f64
+                    } else {
+                        <$ty>::from_le_bytes(buf) as f64
+                    }
+                })
+                .collect()
+        };
+    }
+
+    Ok(match element_type {
+        "INT16_TYPE" => decode!(i16, 2),
+        "INT32_TYPE" => decode!(i32, 4),
+        "FLOAT32_TYPE" => decode!(f32, 4),
+        other => {
+            return Err(err_msg(format!(
+                "unsupported BDF crossData type \"{}\"",
+                other
+            )));
+        }
+    })
+}
+
+/// Decode the flag words.
+fn decode_u32s(bytes: &[u8], big_endian: bool) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|c| {
+            let buf = [c[0], c[1], c[2], c[3]];
+
+            if big_endian {
+                u32::from_be_bytes(buf)
+            } else {
+                u32::from_le_bytes(buf)
+            }
+        })
+        .collect()
+}
+
+/// Check that *axes* are some of *allowed*, in the same order.
+fn in_order(axes: &[String], allowed: &[&str]) -> bool {
+    let mut rest = allowed.iter();
+    axes.iter().all(|a| rest.any(|b| a == b))
+}
+
+fn parse_vispol(text: &str) -> Result<VisPol, Error> {
+    Ok(match text {
+        "XX" => VisPol::XX,
+        "XY" => VisPol::XY,
+        "YX" => VisPol::YX,
+        "YY" => VisPol::YY,
+        "RR" => VisPol::RR,
+        "RL" => VisPol::RL,
+        "LR" => VisPol::LR,
+        "LL" => VisPol::LL,
+        other => {
+            return Err(err_msg(format!(
+                "unsupported BDF polarization product \"{}\"",
+                other
+            )));
+        }
+    })
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Result<Node<'a, 'input>, Error> {
+    node.children()
+        .find(|n| n.has_tag_name(name))
+        .ok_or_else(|| {
+            err_msg(format!(
+                "BDF {} element has no {}",
+                node.tag_name().name(),
+                name
+            ))
+        })
+}
+
+fn child_text<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Result<&'a str, Error> {
+    Ok(child(node, name)?.text().unwrap_or("").trim())
+}
+
+fn parse_text<T: ::std::str::FromStr>(node: Node, name: &str) -> Result<T, Error> {
+    let t = child_text(node, name)?;
+    t.parse()
+        .map_err(|_| err_msg(format!("cannot parse \"{}\" as the BDF {}", t, name)))
+}
+
+fn parse_attribute<T: ::std::str::FromStr>(node: Node, name: &str) -> Result<T, Error> {
+    let t = node.attribute(name).ok_or_else(|| {
+        err_msg(format!(
+            "BDF {} element has no {} attribute",
+            node.tag_name().name(),
+            name
+        ))
+    })?;
+    t.parse()
+        .map_err(|_| err_msg(format!("cannot parse \"{}\" as the BDF {}", t, name)))
+}
+
+#[cfg(test)]
+#[test]
+fn decode_bdf() {
+    let mut cross = Vec::new();
+    let mut flags = Vec::new();
+
+    // Three antennas, so three baselines, each with 2 channels of XX and YY.
+    for bl in 0..3 {
+        for chan in 0..2 {
+            for pol in 0..2 {
+                cross.extend_from_slice(&((100 * bl + 10 * chan + pol) as f32).to_le_bytes());
+                cross.extend_from_slice(&(-1f32).to_le_bytes());
+            }
+        }
+
+        for pol in 0..2u32 {
+            flags.extend_from_slice(&(if bl == 1 && pol == 1 { 1u32 } else { 0 }).to_le_bytes());
+        }
+    }
+
+    let mut doc = b"MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"MIME_boundary-1\"; type=\"text/xml\"\r
+\r
+--MIME_boundary-1\r
+Content-Type: text/xml; charset=\"UTF-8\"\r
+Content-Location: sdmDataHeader.xml\r
+\r
+<sdmDataHeader xmlns=\"http://Alma/XASDM/sdmbin\" byteOrder=\"Little_Endian\">
+<startTime>4832118720000000000</startTime>
+<numAntenna>3</numAntenna>
+<correlationMode>CROSS_ONLY</correlationMode>
+<dataStruct apc=\"AP_UNCORRECTED\">
+<baseband name=\"BB_1\">
+<spectralWindow crossPolProducts=\"XX YY\" numSpectralPoint=\"2\" numBin=\"1\"/>
+</baseband>
+<flags size=\"6\" axes=\"BAL BAB POL\"/>
+<crossData size=\"24\" axes=\"BAL BAB SPW SPP POL\"/>
+</dataStruct>
+</sdmDataHeader>\r
+--MIME_boundary-1\r
+Content-Type: multipart/related; boundary=\"MIME_boundary-2\"\r
+\r
+--MIME_boundary-2\r
+Content-Type: text/xml; charset=\"UTF-8\"\r
+\r
+<sdmDataSubsetHeader xmlns=\"http://Alma/XASDM/sdmbin\">
+<schedulePeriodTime><time>4832118721000000000</time><interval>2000000000</interval></schedulePeriodTime>
+<crossData type=\"FLOAT32_TYPE\"/>
+</sdmDataSubsetHeader>\r
+--MIME_boundary-2\r
+Content-Type: binary/octet-stream\r
+Content-Location: 1/1/1/1/flags.bin\r
+\r
+"
+    .to_vec();
+    doc.extend_from_slice(&flags);
+    doc.extend_from_slice(
+        b"\r
+--MIME_boundary-2\r
+Content-Type: binary/octet-stream\r
+Content-Location: 1/1/1/1/crossData.bin\r
+\r
+",
+    );
+    doc.extend_from_slice(&cross);
+    doc.extend_from_slice(b"\r\n--MIME_boundary-2--\r\n\r\n--MIME_boundary-1--\r\n");
+
+    let mut reader = BdfReader::new(&doc[..]).unwrap();
+    assert_eq!(reader.header().num_baselines(), 3);
+    assert!((reader.header().start_time.mjd_days() - 55927.3).abs() < 1e-9);
+
+    let chunk = reader.next_chunk().unwrap().unwrap();
+    assert!((chunk.time.0 - 4832118721.).abs() < 1e-6);
+    assert_eq!(chunk.int_time, Seconds(2.));
+    assert_eq!(chunk.basepols.len(), 6);
+    assert_eq!(chunk.basepols[2], BasePol::new(0, 2, VisPol::XX));
+    assert_eq!(chunk.basepols[5], BasePol::new(1, 2, VisPol::YY));
+    assert_eq!(
+        chunk.data_for(3),
+        &[Complex::new(101., -1.), Complex::new(111., -1.)]
+    );
+    assert_eq!(chunk.flags_for(3), &[true, true]);
+    assert_eq!(chunk.flags_for(2), &[false, false]);
+    assert!(reader.next_chunk().unwrap().is_none());
+}
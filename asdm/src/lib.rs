@@ -0,0 +1,95 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Reading data sets in the ALMA Science Data Model (ASDM) format.
+
+The ASDM is the format in which ALMA and the VLA deliver their raw data. An
+ASDM is a directory of metadata tables, each stored as an XML file such as
+`Antenna.xml`, together with the visibilities themselves, which are stored
+separately in the Binary Data Format (BDF). This crate reads the metadata
+tables that are needed to inspect a delivery — `Scan`, `Main`, `Antenna`,
+and `SpectralWindow` — with the `tables` module, and decodes the
+visibilities of BDF files into `VisChunk`s with the `bdf` module. Large
+tables are sometimes stored in a binary form instead of as XML; those are
+not yet supported.
+
+*/
+
+extern crate failure;
+extern crate roxmltree;
+extern crate rubbl_core;
+extern crate rubbl_visdata;
+
+use failure::Error;
+use std::path::{Path, PathBuf};
+
+pub mod bdf;
+pub mod tables;
+
+use tables::{Antenna, MainRow, Scan, SpectralWindow};
+
+/// The metadata of an ASDM.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Asdm {
+    /// The path of the ASDM directory.
+    pub path: PathBuf,
+
+    /// The rows of the `Scan` table.
+    pub scans: Vec<Scan>,
+
+    /// The rows of the `Main` table, each of which refers to a block of
+    /// binary visibility data.
+    pub main: Vec<MainRow>,
+
+    /// The rows of the `Antenna` table.
+    pub antennas: Vec<Antenna>,
+
+    /// The rows of the `SpectralWindow` table.
+    pub spectral_windows: Vec<SpectralWindow>,
+}
+
+impl Asdm {
+    /// Read the metadata of the ASDM in the directory *path*.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        Ok(Asdm {
+            path: path.to_owned(),
+            scans: tables::read_table(path, "Scan")?,
+            main: tables::read_table(path, "Main")?,
+            antennas: tables::read_table(path, "Antenna")?,
+            spectral_windows: tables::read_table(path, "SpectralWindow")?,
+        })
+    }
+
+    /// Get the antenna with the identifier *antenna_id*, such as
+    /// `Antenna_0`.
+    pub fn antenna(&self, antenna_id: &str) -> Option<&Antenna> {
+        self.antennas.iter().find(|a| a.antenna_id == antenna_id)
+    }
+
+    /// Get the spectral window with the identifier *spectral_window_id*,
+    /// such as `SpectralWindow_0`.
+    pub fn spectral_window(&self, spectral_window_id: &str) -> Option<&SpectralWindow> {
+        self.spectral_windows
+            .iter()
+            .find(|s| s.spectral_window_id == spectral_window_id)
+    }
+
+    /// Get the path of the BDF file holding the visibilities of *row*.
+    ///
+    /// The files are named after the UIDs of the data, with the characters
+    /// `:` and `/` replaced by underscores.
+    pub fn bdf_path(&self, row: &MainRow) -> PathBuf {
+        self.path
+            .join("ASDMBinary")
+            .join(row.data_uid.replace([':', '/'], "_"))
+    }
+
+    /// Get the scan numbered *scan_number*.
+    pub fn scan(&self, scan_number: u32) -> Option<&Scan> {
+        self.scans.iter().find(|s| s.scan_number == scan_number)
+    }
+}
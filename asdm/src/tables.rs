@@ -0,0 +1,510 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+The metadata tables of an ASDM.
+
+Each table is stored in a file named after it, such as `Scan.xml`. The file
+holds an XML document whose root element contains one `row` element per row
+of the table, and each row contains one element per column, whose text is
+the column's value. Values are encoded as follows:
+
+- Times (`ArrayTime`) are integers counting nanoseconds since MJD 0, and
+  intervals are integers counting nanoseconds.
+- Identifiers of rows of other tables (`Tag`s) are strings such as
+  `Antenna_0`.
+- Arrays are lists of whitespace-separated tokens: the number of dimensions,
+  then the size of each dimension, then the values in row-major order.
+- References to other documents, such as the binary visibility data, are
+  `EntityRef` elements whose `entityId` attribute holds a UID.
+
+Optional columns are simply absent from rows that do not have them. Columns
+that this crate does not use are ignored.
+
+*/
+
+use failure::{err_msg, Error};
+use roxmltree::{Document, Node};
+use rubbl_core::units::{Hz, Meters, MjdSeconds};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A type that can be read from the rows of an ASDM table.
+pub trait AsdmRow: Sized {
+    /// Decode one row of the table.
+    fn from_row(row: &Row) -> Result<Self, Error>;
+}
+
+/// Read the table *name*, such as `Antenna`, of the ASDM in the directory
+/// *dir*.
+pub fn read_table<T: AsdmRow, P: AsRef<Path>>(dir: P, name: &str) -> Result<Vec<T>, Error> {
+    let path = dir.as_ref().join(format!("{}.xml", name));
+
+    let bytes = match fs::read(&path) {
+        Ok(b) => b,
+        Err(e) => {
+            if dir.as_ref().join(format!("{}.bin", name)).is_file() {
+                return Err(err_msg(format!(
+                    "the {} table is stored in binary form, which is not supported",
+                    name
+                )));
+            }
+
+            return Err(err_msg(format!(
+                "failed to read \"{}\": {}",
+                path.display(),
+                e
+            )));
+        }
+    };
+
+    // ASDM documents declare themselves to be ISO-8859-1, but in practice
+    // they are almost always ASCII. Decode them as UTF-8 if possible, and as
+    // Latin-1 otherwise.
+    let text = match String::from_utf8(bytes) {
+        Ok(t) => t,
+        Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+    };
+
+    parse_table(&text, name)
+}
+
+/// Parse the XML document *text*, holding the table *name*.
+pub fn parse_table<T: AsdmRow>(text: &str, name: &str) -> Result<Vec<T>, Error> {
+    let doc = Document::parse(text)?;
+    let mut rows = Vec::new();
+
+    for (index, node) in doc
+        .root_element()
+        .children()
+        .filter(|n| n.has_tag_name("row"))
+        .enumerate()
+    {
+        let row = Row {
+            node: node,
+            table: name,
+            index: index,
+        };
+        rows.push(T::from_row(&row)?);
+    }
+
+    Ok(rows)
+}
+
+/// One row of an ASDM table, as it is being decoded.
+pub struct Row<'a, 'input: 'a> {
+    node: Node<'a, 'input>,
+    table: &'a str,
+    index: usize,
+}
+
+impl<'a, 'input: 'a> Row<'a, 'input> {
+    /// Get the text of the column *name*, if the row has it.
+    pub fn opt_text(&self, name: &str) -> Option<&'a str> {
+        self.node
+            .children()
+            .find(|n| n.has_tag_name(name))
+            .map(|n| n.text().unwrap_or("").trim())
+    }
+
+    /// Get the text of the column *name*.
+    pub fn text(&self, name: &str) -> Result<&'a str, Error> {
+        self.opt_text(name).ok_or_else(|| {
+            err_msg(format!(
+                "row {} of the {} table has no {} column",
+                self.index, self.table, name
+            ))
+        })
+    }
+
+    /// Parse the value of the column *name*, if the row has it.
+    pub fn opt_parse<T: FromStr>(&self, name: &str) -> Result<Option<T>, Error> {
+        match self.opt_text(name) {
+            Some(text) => Ok(Some(self.parse_token(name, text)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Parse the value of the column *name*.
+    pub fn parse<T: FromStr>(&self, name: &str) -> Result<T, Error> {
+        let text = self.text(name)?;
+        self.parse_token(name, text)
+    }
+
+    /// Parse the value of the time column *name*.
+    pub fn time(&self, name: &str) -> Result<MjdSeconds, Error> {
+        Ok(MjdSeconds(self.parse::<i64>(name)? as f64 / 1e9))
+    }
+
+    /// Parse the value of the interval column *name*, in seconds.
+    pub fn interval(&self, name: &str) -> Result<f64, Error> {
+        Ok(self.parse::<i64>(name)? as f64 / 1e9)
+    }
+
+    /// Parse the array column *name*, if the row has it, returning its
+    /// values in row-major order.
+    pub fn opt_array<T: FromStr>(&self, name: &str) -> Result<Option<Vec<T>>, Error> {
+        let text = match self.opt_text(name) {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+
+        let mut tokens = text.split_whitespace();
+        let mut next_size = || -> Result<usize, Error> {
+            match tokens.next() {
+                Some(t) => self.parse_token(name, t),
+                None => Err(self.bad_value(name, text)),
+            }
+        };
+
+        let n_dims = next_size()?;
+        let mut n_values = 1usize;
+
+        for _ in 0..n_dims {
+            n_values = n_values.saturating_mul(next_size()?);
+        }
+
+        let values = tokens
+            .map(|t| self.parse_token(name, t))
+            .collect::<Result<Vec<T>, Error>>()?;
+
+        if values.len() != n_values {
+            return Err(self.bad_value(name, text));
+        }
+
+        Ok(Some(values))
+    }
+
+    /// Parse the array column *name*, returning its values in row-major
+    /// order.
+    pub fn array<T: FromStr>(&self, name: &str) -> Result<Vec<T>, Error> {
+        match self.opt_array(name)? {
+            Some(a) => Ok(a),
+            None => Err(err_msg(format!(
+                "row {} of the {} table has no {} column",
+                self.index, self.table, name
+            ))),
+        }
+    }
+
+    /// Parse the column *name*, which must be an array of three values.
+    pub fn vector(&self, name: &str) -> Result<[f64; 3], Error> {
+        let values = self.array::<f64>(name)?;
+
+        if values.len() != 3 {
+            return Err(self.bad_value(name, self.text(name)?));
+        }
+
+        Ok([values[0], values[1], values[2]])
+    }
+
+    /// Get the UID of the `EntityRef` in the column *name*.
+    pub fn entity_ref(&self, name: &str) -> Result<&'a str, Error> {
+        self.node
+            .children()
+            .find(|n| n.has_tag_name(name))
+            .and_then(|n| n.children().find(|c| c.has_tag_name("EntityRef")))
+            .and_then(|n| n.attribute("entityId"))
+            .ok_or_else(|| {
+                err_msg(format!(
+                    "row {} of the {} table has no entity reference in its {} column",
+                    self.index, self.table, name
+                ))
+            })
+    }
+
+    fn parse_token<T: FromStr>(&self, name: &str, text: &str) -> Result<T, Error> {
+        text.parse().map_err(|_| self.bad_value(name, text))
+    }
+
+    fn bad_value(&self, name: &str, text: &str) -> Error {
+        err_msg(format!(
+            "cannot parse \"{}\" in the {} column of row {} of the {} table",
+            text, name, self.index, self.table
+        ))
+    }
+}
+
+/// A row of the `Scan` table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scan {
+    /// The execution block to which the scan belongs, such as
+    /// `ExecBlock_0`.
+    pub exec_block_id: String,
+
+    /// The number of the scan, starting at 1.
+    pub scan_number: u32,
+
+    /// The start of the scan.
+    pub start_time: MjdSeconds,
+
+    /// The end of the scan.
+    pub end_time: MjdSeconds,
+
+    /// The number of subscans.
+    pub num_subscan: u32,
+
+    /// The purposes of the scan, such as `CALIBRATE_BANDPASS` or
+    /// `OBSERVE_TARGET`.
+    pub scan_intent: Vec<String>,
+
+    /// The name of the source observed, if recorded.
+    pub source_name: Option<String>,
+}
+
+impl AsdmRow for Scan {
+    fn from_row(row: &Row) -> Result<Self, Error> {
+        Ok(Scan {
+            exec_block_id: row.text("execBlockId")?.to_owned(),
+            scan_number: row.parse("scanNumber")?,
+            start_time: row.time("startTime")?,
+            end_time: row.time("endTime")?,
+            num_subscan: row.parse("numSubscan")?,
+            scan_intent: row.array("scanIntent")?,
+            source_name: row.opt_text("sourceName").map(|s| s.to_owned()),
+        })
+    }
+}
+
+/// A row of the `Main` table, which describes one block of binary
+/// visibility data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MainRow {
+    /// The midpoint of the data.
+    pub time: MjdSeconds,
+
+    /// The length of time covered by the data, in seconds.
+    pub interval: f64,
+
+    /// The configuration of the correlator, such as
+    /// `ConfigDescription_0`.
+    pub config_description_id: String,
+
+    /// The field observed, such as `Field_0`.
+    pub field_id: String,
+
+    /// The number of antennas.
+    pub num_antenna: u32,
+
+    /// Whether the data are whole integrations or subintegrations: either
+    /// `INTEGRATION` or `SUBINTEGRATION`.
+    pub time_sampling: String,
+
+    /// The number of integrations.
+    pub num_integration: u32,
+
+    /// The number of the scan.
+    pub scan_number: u32,
+
+    /// The number of the subscan within the scan.
+    pub subscan_number: u32,
+
+    /// The size of the binary data, in bytes.
+    pub data_size: u64,
+
+    /// The UID of the binary data, such as `uid://A002/X1234/X1`.
+    pub data_uid: String,
+
+    /// The state of each antenna, such as `State_0`.
+    pub state_id: Vec<String>,
+
+    /// The execution block to which the data belong.
+    pub exec_block_id: String,
+}
+
+impl AsdmRow for MainRow {
+    fn from_row(row: &Row) -> Result<Self, Error> {
+        Ok(MainRow {
+            time: row.time("time")?,
+            interval: row.interval("interval")?,
+            config_description_id: row.text("configDescriptionId")?.to_owned(),
+            field_id: row.text("fieldId")?.to_owned(),
+            num_antenna: row.parse("numAntenna")?,
+            time_sampling: row.text("timeSampling")?.to_owned(),
+            num_integration: row.parse("numIntegration")?,
+            scan_number: row.parse("scanNumber")?,
+            subscan_number: row.parse("subscanNumber")?,
+            data_size: row.parse("dataSize")?,
+            data_uid: row.entity_ref("dataUID")?.to_owned(),
+            state_id: row.array("stateId")?,
+            exec_block_id: row.text("execBlockId")?.to_owned(),
+        })
+    }
+}
+
+/// A row of the `Antenna` table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Antenna {
+    /// The identifier of the antenna, such as `Antenna_0`.
+    pub antenna_id: String,
+
+    /// The name of the antenna, such as `DA41`.
+    pub name: String,
+
+    /// The maker of the antenna, such as `AEM`.
+    pub antenna_make: String,
+
+    /// The type of the antenna, such as `GROUND_BASED`.
+    pub antenna_type: String,
+
+    /// The diameter of the dish.
+    pub dish_diameter: Meters,
+
+    /// The position of the antenna relative to its station, in meters.
+    pub position: [f64; 3],
+
+    /// The offset of the antenna's reference point from its position, in
+    /// meters.
+    pub offset: [f64; 3],
+
+    /// The time from which this description is valid.
+    pub time: MjdSeconds,
+
+    /// The station on which the antenna stands, such as `Station_0`.
+    pub station_id: String,
+}
+
+impl AsdmRow for Antenna {
+    fn from_row(row: &Row) -> Result<Self, Error> {
+        Ok(Antenna {
+            antenna_id: row.text("antennaId")?.to_owned(),
+            name: row.text("name")?.to_owned(),
+            antenna_make: row.text("antennaMake")?.to_owned(),
+            antenna_type: row.text("antennaType")?.to_owned(),
+            dish_diameter: Meters(row.parse("dishDiameter")?),
+            position: row.vector("position")?,
+            offset: row.vector("offset")?,
+            time: row.time("time")?,
+            station_id: row.text("stationId")?.to_owned(),
+        })
+    }
+}
+
+/// A row of the `SpectralWindow` table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpectralWindow {
+    /// The identifier of the window, such as `SpectralWindow_0`.
+    pub spectral_window_id: String,
+
+    /// The baseband of the window, such as `BB_1`.
+    pub baseband_name: String,
+
+    /// The net sideband of the window, such as `USB`.
+    pub net_sideband: String,
+
+    /// The number of channels.
+    pub num_chan: usize,
+
+    /// The reference frequency.
+    pub ref_freq: Hz,
+
+    /// The total bandwidth.
+    pub tot_bandwidth: Hz,
+
+    /// The frequency of each channel. This is empty if the table records
+    /// neither the frequencies nor the start and step of a regular grid.
+    pub chan_freqs: Vec<Hz>,
+
+    /// The width of each channel, which is empty if it is not recorded.
+    pub chan_widths: Vec<Hz>,
+
+    /// The name of the window, if recorded.
+    pub name: Option<String>,
+}
+
+impl AsdmRow for SpectralWindow {
+    fn from_row(row: &Row) -> Result<Self, Error> {
+        let num_chan: usize = row.parse("numChan")?;
+
+        let chan_freqs = match row.opt_array::<f64>("chanFreqArray")? {
+            Some(freqs) => freqs,
+            None => match (
+                row.opt_parse::<f64>("chanFreqStart")?,
+                row.opt_parse::<f64>("chanFreqStep")?,
+            ) {
+                (Some(start), Some(step)) => {
+                    (0..num_chan).map(|i| start + i as f64 * step).collect()
+                }
+                _ => Vec::new(),
+            },
+        };
+
+        let chan_widths = match row.opt_array::<f64>("chanWidthArray")? {
+            Some(widths) => widths,
+            None => match row.opt_parse::<f64>("chanWidth")? {
+                Some(width) => vec![width; num_chan],
+                None => Vec::new(),
+            },
+        };
+
+        Ok(SpectralWindow {
+            spectral_window_id: row.text("spectralWindowId")?.to_owned(),
+            baseband_name: row.text("basebandName")?.to_owned(),
+            net_sideband: row.text("netSideband")?.to_owned(),
+            num_chan: num_chan,
+            ref_freq: Hz(row.parse("refFreq")?),
+            tot_bandwidth: Hz(row.parse("totBandwidth")?),
+            chan_freqs: chan_freqs.into_iter().map(Hz).collect(),
+            chan_widths: chan_widths.into_iter().map(Hz).collect(),
+            name: row.opt_text("name").map(|s| s.to_owned()),
+        })
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn parse_tables() {
+    let antennas: Vec<Antenna> = parse_table(
+        r#"<?xml version="1.0" encoding="ISO-8859-1"?>
+<AntennaTable>
+<Entity entityId="uid://A002/X1/X2" entityTypeName="AntennaTable"/>
+<row>
+<antennaId>Antenna_0</antennaId>
+<name>DA41</name>
+<antennaMake>AEM</antennaMake>
+<antennaType>GROUND_BASED</antennaType>
+<dishDiameter>12.0</dishDiameter>
+<position>1 3 0.0 0.0 7.0</position>
+<offset>1 3 0.0 0.0 0.0</offset>
+<time>4832118720000000000</time>
+<stationId>Station_0</stationId>
+</row>
+</AntennaTable>"#,
+        "Antenna",
+    )
+    .unwrap();
+    assert_eq!(antennas.len(), 1);
+    assert_eq!(antennas[0].name, "DA41");
+    assert_eq!(antennas[0].position, [0., 0., 7.]);
+    assert!((antennas[0].time.mjd_days() - 55927.3).abs() < 1e-9);
+
+    let spws: Vec<SpectralWindow> = parse_table(
+        "<SpectralWindowTable><row>
+<spectralWindowId>SpectralWindow_0</spectralWindowId>
+<basebandName>BB_1</basebandName>
+<netSideband>USB</netSideband>
+<numChan>3</numChan>
+<refFreq>1.0E11</refFreq>
+<totBandwidth>3.0E6</totBandwidth>
+<chanFreqStart>1.0E11</chanFreqStart>
+<chanFreqStep>1.0E6</chanFreqStep>
+<chanWidth>1.0E6</chanWidth>
+</row></SpectralWindowTable>",
+        "SpectralWindow",
+    )
+    .unwrap();
+    assert_eq!(
+        spws[0].chan_freqs,
+        vec![Hz(1e11), Hz(1e11 + 1e6), Hz(1e11 + 2e6)]
+    );
+    assert_eq!(spws[0].chan_widths.len(), 3);
+    assert_eq!(spws[0].name, None);
+
+    let bad = parse_table::<Scan>(
+        "<ScanTable><row><scanNumber>1</scanNumber></row></ScanTable>",
+        "Scan",
+    );
+    assert!(bad.is_err());
+}
@@ -0,0 +1,109 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Solve for the antenna gains of a Measurement Set.
+
+The data are compared with the `MODEL_DATA` column, which must already be
+filled in, and the solutions are written to a new CASA gain table. See the
+`rubbl_cal::gaincal` module. With `--dry-run`, the gains are solved for but
+the table that would have been written is only reported.
+
+*/
+
+extern crate clap;
+#[macro_use]
+extern crate rubbl_core;
+extern crate rubbl_cal;
+
+use clap::{App, Arg};
+use rubbl_cal::gaincal::GainCal;
+use rubbl_core::dryrun::{dry_run_requested, ChangePlan, ClapDryRunArgsExt};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use std::io;
+use std::path::Path;
+use std::process;
+
+fn main() {
+    let matches = App::new("rubbl-gaincal")
+        .version("0.1.0")
+        .about("Solve for the antenna gains of a Measurement Set")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .rubbl_dry_run_args()
+        .arg(
+            Arg::with_name("solint")
+                .long("solint")
+                .value_name("SECONDS")
+                .default_value("inf")
+                .help("The length of the solution intervals"),
+        )
+        .arg(
+            Arg::with_name("refant")
+                .long("refant")
+                .value_name("NUMBER")
+                .help("The 0-based number of the reference antenna"),
+        )
+        .arg(
+            Arg::with_name("datacolumn")
+                .long("datacolumn")
+                .value_name("NAME")
+                .default_value("DATA")
+                .help("The column of visibilities to calibrate"),
+        )
+        .arg(
+            Arg::with_name("MS-PATH")
+                .help("The path of the Measurement Set")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("CALTABLE-PATH")
+                .help("The path of the gain table to create")
+                .required(true)
+                .index(2),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, nbe| -> Result<i32, Error> {
+            let solint = ctry!(matches.value_of("solint").unwrap().parse::<f64>();
+                               "bad --solint value");
+            let refant = match matches.value_of("refant") {
+                Some(v) => Some(ctry!(v.parse::<usize>(); "bad --refant value \"{}\"", v)),
+                None => None,
+            };
+
+            let ms_path = Path::new(matches.value_of_os("MS-PATH").unwrap());
+            let caltable_path = Path::new(matches.value_of_os("CALTABLE-PATH").unwrap());
+
+            let mut gaincal = GainCal::new();
+            gaincal
+                .set_solint(solint)
+                .set_refant(refant)
+                .set_data_column(matches.value_of("datacolumn").unwrap());
+
+            if dry_run_requested(&matches) {
+                let (n_intervals, n_rows) = ctry!(gaincal.solve_dry_run(ms_path);
+                                                  "failed to solve for the gains of \"{}\"",
+                                                  ms_path.display());
+                let mut plan = ChangePlan::new();
+                plan.record(
+                    caltable_path.display().to_string(),
+                    format!("create a gain table of {} solution intervals", n_intervals),
+                    n_rows,
+                    None,
+                );
+                plan.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+                return Ok(0);
+            }
+
+            let n_intervals = ctry!(gaincal.solve(ms_path, caltable_path);
+                                    "failed to solve for the gains of \"{}\"", ms_path.display());
+            rn_note!(nbe, "wrote solutions for {} intervals", n_intervals);
+            Ok(0)
+        },
+    ));
+}
@@ -0,0 +1,174 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Writing gain solutions as CASA calibration tables.
+
+The tables follow the layout that CASA's `gaincal` task produces for "G
+Jones" solutions, so that CASA's `applycal` and `plotms` can use them. Each
+row holds the solutions for one antenna in one solution interval, with one
+parameter per polarization in the `CPARAM` column. The `OBSERVATION`,
+`ANTENNA`, `FIELD`, `SPECTRAL_WINDOW`, and `HISTORY` subtables are copied
+from the Measurement Set that was calibrated.
+
+*/
+
+use failure::{err_msg, Error};
+use rubbl_casatables::ms::tag_standard_measures;
+use rubbl_casatables::{CasaScalarData, DeepCopyOptions, GlueDataType, Table, TableOpenMode};
+use rubbl_core::units::{MjdSeconds, Seconds};
+use rubbl_core::{Array, Complex};
+use std::path::Path;
+
+use super::solver::GainSolution;
+
+/// The subtables copied from the Measurement Set.
+const SUBTABLES: &[&str] = &[
+    "OBSERVATION",
+    "ANTENNA",
+    "FIELD",
+    "SPECTRAL_WINDOW",
+    "HISTORY",
+];
+
+/// The solutions for one antenna in one solution interval.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GainTableRow {
+    /// The midpoint of the solution interval.
+    pub time: MjdSeconds,
+
+    /// The length of the solution interval.
+    pub interval: Seconds,
+
+    /// The field of the data used in the solution.
+    pub field_id: i32,
+
+    /// The spectral window of the data used in the solution.
+    pub spw_id: i32,
+
+    /// The antenna whose gains these are.
+    pub antenna: i32,
+
+    /// The reference antenna, or -1 if there is none.
+    pub refant: i32,
+
+    /// The scan number of the data used in the solution.
+    pub scan_number: i32,
+
+    /// The observation of the data used in the solution.
+    pub observation_id: i32,
+
+    /// The solution for each polarization, or `None` where the gain could
+    /// not be determined.
+    pub solutions: Vec<Option<GainSolution>>,
+}
+
+/// Write *rows* to a new gain calibration table at *path* for the
+/// Measurement Set at *ms_path*.
+///
+/// Every row must have the same number of polarizations. It is an error if
+/// something already exists at *path*.
+pub fn write_gain_table<P: AsRef<Path>, Q: AsRef<Path>>(
+    path: P,
+    ms_path: Q,
+    rows: &[GainTableRow],
+) -> Result<(), Error> {
+    let path = path.as_ref();
+    let ms_path = ms_path.as_ref();
+    let n_pols = rows.first().map(|r| r.solutions.len()).unwrap_or(1);
+
+    if rows.iter().any(|r| r.solutions.len() != n_pols) {
+        return Err(err_msg(
+            "all gain solutions must have the same number of polarizations",
+        ));
+    }
+
+    let mut table = Table::create_with_scalar_columns(
+        path,
+        &[
+            ("TIME", GlueDataType::TpDouble),
+            ("FIELD_ID", GlueDataType::TpInt),
+            ("SPECTRAL_WINDOW_ID", GlueDataType::TpInt),
+            ("ANTENNA1", GlueDataType::TpInt),
+            ("ANTENNA2", GlueDataType::TpInt),
+            ("INTERVAL", GlueDataType::TpDouble),
+            ("SCAN_NUMBER", GlueDataType::TpInt),
+            ("OBSERVATION_ID", GlueDataType::TpInt),
+        ],
+        rows.len() as u64,
+    )?;
+
+    // One channel and `n_pols` parameters, in C order.
+    let shape = [1, n_pols as u64];
+    table.add_array_column("CPARAM", GlueDataType::TpComplex, Some(&shape))?;
+    table.add_array_column("PARAMERR", GlueDataType::TpFloat, Some(&shape))?;
+    table.add_array_column("FLAG", GlueDataType::TpBool, Some(&shape))?;
+    table.add_array_column("SNR", GlueDataType::TpFloat, Some(&shape))?;
+    table.add_array_column("WEIGHT", GlueDataType::TpFloat, Some(&shape))?;
+
+    table.put_col_from_iter("TIME", rows.iter().map(|r| r.time.0))?;
+    table.put_col_from_iter("FIELD_ID", rows.iter().map(|r| r.field_id))?;
+    table.put_col_from_iter("SPECTRAL_WINDOW_ID", rows.iter().map(|r| r.spw_id))?;
+    table.put_col_from_iter("ANTENNA1", rows.iter().map(|r| r.antenna))?;
+    table.put_col_from_iter("ANTENNA2", rows.iter().map(|r| r.refant))?;
+    table.put_col_from_iter("INTERVAL", rows.iter().map(|r| r.interval.0))?;
+    table.put_col_from_iter("SCAN_NUMBER", rows.iter().map(|r| r.scan_number))?;
+    table.put_col_from_iter("OBSERVATION_ID", rows.iter().map(|r| r.observation_id))?;
+
+    for (i, row) in rows.iter().enumerate() {
+        let i = i as u64;
+        let gain = |s: &GainSolution| Complex::new(s.gain.re as f32, s.gain.im as f32);
+        let snr = |s: &GainSolution| (s.gain.norm() / s.error) as f32;
+
+        put_pars(&mut table, "CPARAM", i, row, gain, Complex::new(1., 0.))?;
+        put_pars(&mut table, "PARAMERR", i, row, |s| s.error as f32, -1.)?;
+        put_pars(&mut table, "FLAG", i, row, |_| false, true)?;
+        put_pars(&mut table, "SNR", i, row, snr, 0.)?;
+        put_pars(&mut table, "WEIGHT", i, row, |_| 1., 0.)?;
+    }
+
+    table.put_keyword_string("ParType", "Complex")?;
+    table.put_keyword_string("VisCal", "G Jones")?;
+    table.put_keyword_string("PolBasis", "unknown")?;
+    table.put_keyword_string("MSName", &ms_path.display().to_string())?;
+    tag_standard_measures(&mut table)?;
+
+    for name in SUBTABLES {
+        let source = ms_path.join(name);
+
+        if !source.join("table.dat").is_file() {
+            continue;
+        }
+
+        let dest = path.join(name);
+        Table::open(&source, TableOpenMode::Read)?.deep_copy(&dest, &DeepCopyOptions::default())?;
+        table.put_keyword_subtable(name, &dest)?;
+    }
+
+    Ok(())
+}
+
+/// Write one cell of a per-polarization column, using *value* for the
+/// polarizations that have solutions and *missing* for those that do not.
+fn put_pars<T, F>(
+    table: &mut Table,
+    col_name: &str,
+    row: u64,
+    gain_row: &GainTableRow,
+    value: F,
+    missing: T,
+) -> Result<(), Error>
+where
+    T: CasaScalarData + Copy,
+    F: Fn(&GainSolution) -> T,
+{
+    let values: Vec<_> = gain_row
+        .solutions
+        .iter()
+        .map(|s| s.as_ref().map(&value).unwrap_or(missing))
+        .collect();
+    let dims = (1, values.len());
+    table.put_cell(col_name, row, &Array::from_shape_vec(dims, values)?)?;
+    Ok(())
+}
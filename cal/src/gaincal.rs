@@ -0,0 +1,261 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Gain calibration of Measurement Sets.
+
+`GainCal` solves for antenna-based complex gains by comparing the data of a
+Measurement Set with its `MODEL_DATA` column, and writes the solutions to a
+CASA gain table. The rows of the main table are grouped into solution
+intervals by field, data description, and time; within each interval every
+unflagged channel of the parallel-hand correlations is used as a separate
+measurement, weighted by the `WEIGHT` of its correlation. The gains of each
+polarization are solved for separately with a `GainSolver`.
+
+*/
+
+use failure::{err_msg, Error};
+use rubbl_casatables::{Table, TableOpenMode};
+use rubbl_core::units::MjdSeconds;
+use rubbl_core::Complex;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::caltable::{write_gain_table, GainTableRow};
+use super::solver::{GainSolver, Sample};
+
+/// Solves for the antenna gains of a Measurement Set.
+#[derive(Clone, Debug)]
+pub struct GainCal {
+    solint: f64,
+    refant: Option<usize>,
+    data_column: String,
+    solver: GainSolver,
+}
+
+impl Default for GainCal {
+    fn default() -> Self {
+        GainCal {
+            solint: std::f64::INFINITY,
+            refant: None,
+            data_column: "DATA".to_owned(),
+            solver: GainSolver::default(),
+        }
+    }
+}
+
+impl GainCal {
+    /// Set up a gain calibration with the default settings: one solution
+    /// interval for each field and data description, no reference antenna,
+    /// and solving using the `DATA` column.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the length of the solution intervals, in seconds. Infinity means
+    /// that all of the data of each field and data description are combined.
+    pub fn set_solint(&mut self, solint: f64) -> &mut Self {
+        self.solint = solint;
+        self
+    }
+
+    /// Set the reference antenna, whose gains are given zero phase.
+    pub fn set_refant(&mut self, refant: Option<usize>) -> &mut Self {
+        self.refant = refant;
+        self
+    }
+
+    /// Set the column of visibilities to calibrate, usually `DATA` or
+    /// `CORRECTED_DATA`.
+    pub fn set_data_column(&mut self, data_column: &str) -> &mut Self {
+        self.data_column = data_column.to_owned();
+        self
+    }
+
+    /// Get the solver, to change its settings.
+    pub fn solver_mut(&mut self) -> &mut GainSolver {
+        &mut self.solver
+    }
+
+    /// Solve for the gains of the Measurement Set at *ms_path* and write
+    /// them to a new gain table at *caltable_path*.
+    ///
+    /// Returns the number of solution intervals that had usable data.
+    pub fn solve<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        ms_path: P,
+        caltable_path: Q,
+    ) -> Result<usize, Error> {
+        let ms_path = ms_path.as_ref();
+        let (table_rows, n_intervals) = self.solve_rows(ms_path)?;
+        write_gain_table(caltable_path, ms_path, &table_rows)?;
+        Ok(n_intervals)
+    }
+
+    /// Solve for the gains of the Measurement Set at *ms_path* as `solve`
+    /// does, but do not write them out.
+    ///
+    /// Returns the number of solution intervals that had usable data and
+    /// the number of rows that the gain table would have.
+    pub fn solve_dry_run<P: AsRef<Path>>(&self, ms_path: P) -> Result<(usize, u64), Error> {
+        let (table_rows, n_intervals) = self.solve_rows(ms_path.as_ref())?;
+        Ok((n_intervals, table_rows.len() as u64))
+    }
+
+    /// Solve for the gains, returning the rows of the gain table and the
+    /// number of solution intervals that had usable data.
+    fn solve_rows(&self, ms_path: &Path) -> Result<(Vec<GainTableRow>, usize), Error> {
+        if self.solint.is_nan() || self.solint <= 0. {
+            return Err(err_msg(format!(
+                "the solution interval must be positive; got {}",
+                self.solint
+            )));
+        }
+
+        let n_ants = Table::open(ms_path.join("ANTENNA"), TableOpenMode::Read)?.n_rows() as usize;
+        let spw_ids = Table::open(ms_path.join("DATA_DESCRIPTION"), TableOpenMode::Read)?
+            .get_col_as_vec::<i32>("SPECTRAL_WINDOW_ID")?;
+
+        let mut ms = Table::open(ms_path, TableOpenMode::Read)?;
+        let times = ms.get_col_as_vec::<f64>("TIME")?;
+        let ant1 = ms.get_col_as_vec::<i32>("ANTENNA1")?;
+        let ant2 = ms.get_col_as_vec::<i32>("ANTENNA2")?;
+        let field_ids = ms.get_col_as_vec::<i32>("FIELD_ID")?;
+        let ddids = ms.get_col_as_vec::<i32>("DATA_DESC_ID")?;
+        let scans = ms.get_col_as_vec::<i32>("SCAN_NUMBER")?;
+        let obs_ids = ms.get_col_as_vec::<i32>("OBSERVATION_ID")?;
+        let row_flags = ms.get_col_as_vec::<bool>("FLAG_ROW")?;
+
+        let t0 = times.iter().cloned().fold(std::f64::INFINITY, f64::min);
+        let mut intervals = BTreeMap::new();
+
+        for row in 0..times.len() {
+            let bin = if self.solint.is_finite() {
+                ((times[row] - t0) / self.solint).floor() as i64
+            } else {
+                0
+            };
+
+            intervals
+                .entry((field_ids[row], ddids[row], bin))
+                .or_insert_with(Vec::new)
+                .push(row);
+        }
+
+        let mut table_rows = Vec::new();
+        let mut n_pols = None;
+        let mut n_intervals = 0;
+
+        for (&(field_id, ddid, _), rows) in &intervals {
+            let spw_id = match spw_ids.get(ddid as usize) {
+                Some(&s) => s,
+                None => {
+                    return Err(err_msg(format!(
+                        "DATA_DESC_ID {} has no DATA_DESCRIPTION entry",
+                        ddid
+                    )));
+                }
+            };
+
+            let mut samples = Vec::new();
+
+            for &row in rows {
+                if row_flags[row] || ant1[row] < 0 || ant2[row] < 0 {
+                    continue;
+                }
+
+                let r = row as u64;
+                let shape = ms.get_cell_shape(&self.data_column, r)?;
+                let data = ms.get_cell_as_vec::<Complex<f32>>(&self.data_column, r)?;
+                let model = ms.get_cell_as_vec::<Complex<f32>>("MODEL_DATA", r)?;
+                let flags = ms.get_cell_as_vec::<bool>("FLAG", r)?;
+                let weights = ms.get_cell_as_vec::<f32>("WEIGHT", r)?;
+                let n_corr = shape.last().cloned().unwrap_or(0) as usize;
+
+                if n_corr == 0
+                    || model.len() != data.len()
+                    || flags.len() != data.len()
+                    || weights.len() != n_corr
+                {
+                    return Err(err_msg(format!(
+                        "the data, model, flag, and weight cells of row {} do not match",
+                        row
+                    )));
+                }
+
+                let corrs = parallel_hands(n_corr)?;
+
+                if *n_pols.get_or_insert(corrs.len()) != corrs.len() {
+                    return Err(err_msg(
+                        "cannot solve for gains of data with varying numbers of polarizations",
+                    ));
+                }
+
+                samples.resize(corrs.len(), Vec::new());
+
+                for (pol, &corr) in corrs.iter().enumerate() {
+                    for i in (corr..data.len()).step_by(n_corr) {
+                        if flags[i] {
+                            continue;
+                        }
+
+                        samples[pol].push(Sample {
+                            ant1: ant1[row] as usize,
+                            ant2: ant2[row] as usize,
+                            vis: Complex::new(data[i].re as f64, data[i].im as f64),
+                            model: Complex::new(model[i].re as f64, model[i].im as f64),
+                            weight: weights[corr] as f64,
+                        });
+                    }
+                }
+            }
+
+            if samples.is_empty() {
+                continue;
+            }
+
+            n_intervals += 1;
+
+            let solutions: Vec<_> = samples
+                .iter()
+                .map(|s| self.solver.solve(n_ants, s, self.refant))
+                .collect();
+
+            let (t_min, t_max) = rows.iter().fold(
+                (std::f64::INFINITY, std::f64::NEG_INFINITY),
+                |(lo, hi), &r| (lo.min(times[r]), hi.max(times[r])),
+            );
+
+            for ant in 0..n_ants {
+                table_rows.push(GainTableRow {
+                    time: MjdSeconds(0.5 * (t_min + t_max)),
+                    interval: MjdSeconds(t_max).seconds_since(MjdSeconds(t_min)),
+                    field_id: field_id,
+                    spw_id: spw_id,
+                    antenna: ant as i32,
+                    refant: self.refant.map(|r| r as i32).unwrap_or(-1),
+                    scan_number: scans[rows[0]],
+                    observation_id: obs_ids[rows[0]],
+                    solutions: solutions.iter().map(|s| s[ant]).collect(),
+                });
+            }
+        }
+
+        Ok((table_rows, n_intervals))
+    }
+}
+
+/// Get the indices of the parallel-hand correlations (XX and YY, or RR and
+/// LL) for data with *n_corr* correlations in the standard orders.
+fn parallel_hands(n_corr: usize) -> Result<Vec<usize>, Error> {
+    match n_corr {
+        1 => Ok(vec![0]),
+        2 => Ok(vec![0, 1]),
+        4 => Ok(vec![0, 3]),
+        n => Err(err_msg(format!(
+            "cannot identify the parallel hands of data with {} correlations",
+            n
+        ))),
+    }
+}
@@ -0,0 +1,22 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Calibration of interferometric visibility data.
+
+This crate solves for antenna-based complex gains by comparing the
+visibilities of a Measurement Set with a model of the sky, and writes the
+solutions as CASA calibration tables. Together with
+`rubbl_casatables::ms::divide_by_model` and a way to apply the solutions,
+this is enough for a minimal self-calibration loop.
+
+*/
+
+extern crate failure;
+extern crate rubbl_casatables;
+extern crate rubbl_core;
+
+pub mod caltable;
+pub mod gaincal;
+pub mod solver;
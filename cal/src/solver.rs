@@ -0,0 +1,282 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Solving for antenna-based complex gains.
+
+Given visibilities *V*ᵢⱼ and model visibilities *M*ᵢⱼ on the baselines
+between antennas *i* and *j*, the gains *g* minimize
+
+  Σ *w*ᵢⱼ |*V*ᵢⱼ − *g*ᵢ *g*ⱼ\* *M*ᵢⱼ|²
+
+where *w*ᵢⱼ are the visibility weights. Holding all gains but *g*ᵢ fixed,
+this is a linear least-squares problem in *g*ᵢ with the solution
+
+  *g*ᵢ = Σⱼ *w*ᵢⱼ *V*ᵢⱼ (*g*ⱼ\* *M*ᵢⱼ)\* / Σⱼ *w*ᵢⱼ |*g*ⱼ\* *M*ᵢⱼ|²,
+
+so the solver updates all of the gains this way at once and repeats until
+they stop changing, averaging successive iterates every other step to damp
+oscillations (the "StEFCal" algorithm of Salvini & Wijnholds 2014). Each
+polarization is solved for separately.
+
+*/
+
+use rubbl_core::Complex;
+
+/// One visibility measurement to be used in a gain solution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    /// The first antenna of the baseline.
+    pub ant1: usize,
+
+    /// The second antenna of the baseline.
+    pub ant2: usize,
+
+    /// The measured visibility.
+    pub vis: Complex<f64>,
+
+    /// The model visibility.
+    pub model: Complex<f64>,
+
+    /// The weight of the measurement: its inverse variance.
+    pub weight: f64,
+}
+
+/// The gain solved for one antenna.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GainSolution {
+    /// The complex gain.
+    pub gain: Complex<f64>,
+
+    /// The uncertainty of the gain, from the weights of the samples.
+    pub error: f64,
+}
+
+/// Solves for antenna gains.
+#[derive(Clone, Debug)]
+pub struct GainSolver {
+    max_iterations: usize,
+    tolerance: f64,
+    min_baselines: usize,
+}
+
+impl Default for GainSolver {
+    fn default() -> Self {
+        GainSolver {
+            max_iterations: 100,
+            tolerance: 1e-8,
+            min_baselines: 2,
+        }
+    }
+}
+
+impl GainSolver {
+    /// Create a solver with the default settings: at most 100 iterations, a
+    /// convergence tolerance of 10⁻⁸, and at least two baselines per
+    /// antenna.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of iterations.
+    pub fn set_max_iterations(&mut self, max_iterations: usize) -> &mut Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+
+    /// Set the convergence tolerance: iteration stops once no gain changes
+    /// by more than this fraction of its amplitude.
+    pub fn set_tolerance(&mut self, tolerance: f64) -> &mut Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Set the number of distinct baselines with usable data that an
+    /// antenna must have for its gain to be solved for.
+    pub fn set_min_baselines(&mut self, min_baselines: usize) -> &mut Self {
+        self.min_baselines = min_baselines.max(1);
+        self
+    }
+
+    /// Solve for the gains of *n_ants* antennas from *samples*.
+    ///
+    /// Autocorrelations and samples with non-positive or non-finite weights
+    /// are ignored. If *refant* is given and has a solution, the gains are
+    /// rotated so that its phase is zero. Antennas without enough data get
+    /// no solution.
+    pub fn solve(
+        &self,
+        n_ants: usize,
+        samples: &[Sample],
+        refant: Option<usize>,
+    ) -> Vec<Option<GainSolution>> {
+        let samples: Vec<_> = samples
+            .iter()
+            .filter(|s| {
+                s.ant1 != s.ant2
+                    && s.ant1 < n_ants
+                    && s.ant2 < n_ants
+                    && s.weight.is_finite()
+                    && s.weight > 0.
+                    && s.vis.norm_sqr().is_finite()
+                    && s.model.norm_sqr().is_finite()
+            })
+            .collect();
+
+        let usable = self.usable_antennas(n_ants, &samples);
+        let samples: Vec<_> = samples
+            .into_iter()
+            .filter(|s| usable[s.ant1] && usable[s.ant2])
+            .collect();
+
+        let one = Complex::new(1., 0.);
+        let zero = Complex::new(0., 0.);
+        let mut gains = vec![one; n_ants];
+        let mut denoms = vec![0.; n_ants];
+
+        for iteration in 0..self.max_iterations {
+            let mut numers = vec![zero; n_ants];
+            denoms = vec![0.; n_ants];
+
+            for s in &samples {
+                // V_ij ≈ g_i (g_j* M_ij), and conj(V_ij) ≈ g_j (g_i* M_ij)*.
+                let z = gains[s.ant2].conj() * s.model;
+                numers[s.ant1] += s.vis * z.conj() * s.weight;
+                denoms[s.ant1] += z.norm_sqr() * s.weight;
+
+                let z = gains[s.ant1].conj() * s.model.conj();
+                numers[s.ant2] += s.vis.conj() * z.conj() * s.weight;
+                denoms[s.ant2] += z.norm_sqr() * s.weight;
+            }
+
+            let mut max_change = 0f64;
+
+            for i in 0..n_ants {
+                if !usable[i] || denoms[i] <= 0. {
+                    continue;
+                }
+
+                let mut new = numers[i] / denoms[i];
+
+                if iteration % 2 == 1 {
+                    new = (new + gains[i]) * 0.5;
+                }
+
+                let change = (new - gains[i]).norm() / new.norm().max(std::f64::MIN_POSITIVE);
+                max_change = max_change.max(change);
+                gains[i] = new;
+            }
+
+            if max_change < self.tolerance {
+                break;
+            }
+        }
+
+        let rotation = match refant {
+            Some(r) if r < n_ants && usable[r] && gains[r].norm() > 0. => {
+                gains[r].conj() / gains[r].norm()
+            }
+            _ => one,
+        };
+
+        (0..n_ants)
+            .map(|i| {
+                if usable[i] && denoms[i] > 0. {
+                    Some(GainSolution {
+                        gain: gains[i] * rotation,
+                        error: denoms[i].sqrt().recip(),
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Find the antennas with data on at least `min_baselines` baselines to
+    /// other such antennas.
+    fn usable_antennas(&self, n_ants: usize, samples: &[&Sample]) -> Vec<bool> {
+        let mut baselines = vec![false; n_ants * n_ants];
+
+        for s in samples {
+            baselines[s.ant1 * n_ants + s.ant2] = true;
+            baselines[s.ant2 * n_ants + s.ant1] = true;
+        }
+
+        let mut usable = vec![true; n_ants];
+
+        // Dropping an antenna can leave its partners short of baselines, so
+        // repeat until nothing changes.
+        loop {
+            let mut changed = false;
+
+            for i in 0..n_ants {
+                if !usable[i] {
+                    continue;
+                }
+
+                let n = (0..n_ants)
+                    .filter(|&j| usable[j] && baselines[i * n_ants + j])
+                    .count();
+
+                if n < self.min_baselines {
+                    usable[i] = false;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                return usable;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn gain_solution() {
+    let true_gains = [
+        Complex::from_polar(1.0, 0.3),
+        Complex::from_polar(0.8, -1.0),
+        Complex::from_polar(1.2, 2.0),
+        Complex::from_polar(0.9, 0.5),
+    ];
+    let mut samples = Vec::new();
+
+    for i in 0..4 {
+        for j in (i + 1)..4 {
+            let model = Complex::new(1. + i as f64, 0.5 * j as f64);
+            samples.push(Sample {
+                ant1: i,
+                ant2: j,
+                vis: true_gains[i] * true_gains[j].conj() * model,
+                model: model,
+                weight: 1.,
+            });
+        }
+    }
+
+    // Antenna 4 has one baseline, which is not enough, and antenna 5 has
+    // none.
+    samples.push(Sample {
+        ant1: 0,
+        ant2: 4,
+        vis: Complex::new(1., 0.),
+        model: Complex::new(1., 0.),
+        weight: 1.,
+    });
+
+    let solutions = GainSolver::new().solve(6, &samples, Some(0));
+    let rotation = true_gains[0].conj() / true_gains[0].norm();
+
+    for i in 0..4 {
+        let s = solutions[i].unwrap();
+        assert!((s.gain - true_gains[i] * rotation).norm() < 1e-6);
+        assert!(s.error > 0.);
+    }
+
+    assert!(solutions[0].unwrap().gain.im.abs() < 1e-9);
+    assert!(solutions[4].is_none());
+    assert!(solutions[5].is_none());
+}
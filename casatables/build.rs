@@ -17,9 +17,9 @@ extern crate cc;
 const FILES: &[&str] = &["src/glue.cc"];
 
 fn main() {
-    let mut builder = cc::Build::new();
-
-    builder
+    // Like the casacore code in `rubbl_casatables_impl`, the glue is only
+    // built for Linux and macOS.
+    cc::Build::new()
         .cpp(true)
         .warnings(true)
         .flag_if_supported("-std=c++11")
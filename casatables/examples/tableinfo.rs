@@ -7,19 +7,85 @@ extern crate rubbl_casatables;
 #[macro_use]
 extern crate rubbl_core;
 extern crate clap;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 
 use clap::{App, Arg};
 use rubbl_casatables::{Table, TableOpenMode};
 use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
 use rubbl_core::Error;
 use std::cmp::max;
+use std::io::{self, Write};
 use std::path::Path;
 use std::process;
 
+#[derive(Debug, Serialize)]
+struct ColumnInfo {
+    name: String,
+    data_type: String,
+    shape: Option<Vec<u64>>,
+    is_scalar: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct TableInfo {
+    path: String,
+    n_rows: u64,
+    columns: Vec<ColumnInfo>,
+    subtables: Vec<String>,
+}
+
+impl Report for TableInfo {
+    fn write_text(&self, dest: &mut Write) -> Result<(), Error> {
+        writeln!(dest, "Table \"{}\":", self.path)?;
+        writeln!(dest, "Number of rows: {}", self.n_rows)?;
+        writeln!(dest, "Number of columns: {}", self.columns.len())?;
+        writeln!(dest, "")?;
+
+        let mut max_name_len = 0;
+        let mut max_type_len = 0;
+
+        for col in &self.columns {
+            max_name_len = max(max_name_len, col.name.len());
+            max_type_len = max(max_type_len, col.data_type.len());
+        }
+
+        for col in &self.columns {
+            let multiplicity_text = if col.is_scalar {
+                "scalar".to_owned()
+            } else if let Some(ref shape) = col.shape {
+                format!("vector of shape {:?}", shape)
+            } else {
+                "variable-shape vector".to_owned()
+            };
+
+            writeln!(
+                dest,
+                "{0:<1$}  {2:<3$}  {4}",
+                col.name, max_name_len, col.data_type, max_type_len, multiplicity_text
+            )?;
+        }
+
+        if self.subtables.len() != 0 {
+            writeln!(dest)?;
+            writeln!(dest, "Sub-tables (table-type \"keywords\"):")?;
+
+            for n in &self.subtables {
+                writeln!(dest, "  {}", n)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 fn main() {
     let matches = App::new("tableinfo")
         .version("0.1.0")
         .rubbl_notify_args()
+        .rubbl_report_args()
         .arg(
             Arg::with_name("IN-TABLE")
                 .help("The path of the input data set")
@@ -36,57 +102,38 @@ fn main() {
             let mut t = ctry!(Table::open(&inpath, TableOpenMode::Read);
                           "failed to open input table \"{}\"", inpath.display());
 
-            println!("Table \"{}\":", inpath.display());
-            println!("Number of rows: {}", t.n_rows());
-            println!("Number of columns: {}", t.n_columns());
-            println!("");
-
             let col_names = ctry!(t.column_names();
                               "failed to get names of columns in \"{}\"", inpath.display());
 
-            let mut max_name_len = 0;
-            let mut max_type_len = 0;
-            let mut info: Vec<(&str, String, String)> = Vec::new();
+            let mut columns = Vec::new();
 
-            for n in &col_names {
+            for n in col_names {
                 let desc = ctry!(t.get_col_desc(&n);
                              "failed to query column \"{}\" in \"{}\"", n, inpath.display());
 
-                let type_text = format!("{}", desc.data_type());
-
-                let multiplicity_text = if desc.is_scalar() {
-                    "scalar".to_owned()
-                } else if desc.is_fixed_shape() {
-                    format!("vector of shape {:?}", desc.shape().unwrap())
-                } else {
-                    "variable-shape vector".to_owned()
-                };
-
-                max_name_len = max(max_name_len, n.len());
-                max_type_len = max(max_type_len, type_text.len());
-
-                info.push((&n, type_text, multiplicity_text));
+                columns.push(ColumnInfo {
+                    data_type: format!("{}", desc.data_type()),
+                    shape: if desc.is_fixed_shape() {
+                        desc.shape().map(|s| s.to_vec())
+                    } else {
+                        None
+                    },
+                    is_scalar: desc.is_scalar(),
+                    name: n,
+                });
             }
 
-            for i in info {
-                println!(
-                    "{0:<1$}  {2:<3$}  {4}",
-                    i.0, max_name_len, i.1, max_type_len, i.2
-                );
-            }
-
-            let table_kw_names = ctry!(t.table_keyword_names();
+            let subtables = ctry!(t.table_keyword_names();
                                    "failed to get keyword info in \"{}\"", inpath.display());
 
-            if table_kw_names.len() != 0 {
-                println!();
-                println!("Sub-tables (table-type \"keywords\"):");
-
-                for n in &table_kw_names {
-                    println!("  {}", n);
-                }
-            }
+            let info = TableInfo {
+                path: inpath.display().to_string(),
+                n_rows: t.n_rows(),
+                columns: columns,
+                subtables: subtables,
+            };
 
+            info.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
             Ok(0)
         },
     ));
@@ -9,7 +9,7 @@ cat >src/glue.rs <<'EOF'
 EOF
 
 exec bindgen \
-     --rust-target=1.19 \
+     --rust-target=1.51 \
      src/glue.h \
      -- \
      -x c++ >>src/glue.rs
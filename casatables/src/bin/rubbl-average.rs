@@ -0,0 +1,211 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Average the data of a Measurement Set in time and frequency.
+
+`rubbl average IN.ms -o OUT.ms --timebin 6 --chanbin 4` averages the time
+samples of IN.ms in groups of six and its channels in groups of four,
+scaling the weights to match, and writes the result to OUT.ms. With
+`--in-place`, the result replaces IN.ms instead. The rows of each group of
+times are combined baseline by baseline, within each data description,
+field, and scan. This is the averaging half of `rubbl decimate`; see the
+`rubbl_casatables::ms::decimate` module for details.
+
+With `--workers N`, the data of each data description are averaged in a
+separate process, with up to N running at once.
+
+With `--dry-run`, the input is checked and the rows that would be written
+are counted, but nothing is written.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+extern crate rubbl_visdata;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+use clap::{App, Arg};
+use rubbl_casatables::ms::decimate::{
+    decimate, decimate_dry_run, decimate_with_workers, run_decimate_worker, DecimateOptions,
+    DecimateSummary,
+};
+use rubbl_core::dryrun::{dry_run_requested, ChangePlan, ClapDryRunArgsExt};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::output::{ClapOutputArgsExt, OutputPolicy};
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use rubbl_visdata::average::FlagPolicy;
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+#[derive(Debug, Serialize)]
+struct AverageReport {
+    input: String,
+    output: String,
+    summary: DecimateSummary,
+}
+
+impl Report for AverageReport {
+    fn write_text(&self, dest: &mut Write) -> Result<(), Error> {
+        let s = &self.summary;
+
+        writeln!(dest, "\"{}\" -> \"{}\"", self.input, self.output)?;
+        writeln!(
+            dest,
+            "averaged {} times into {} and {} rows into {}",
+            s.n_times_in, s.n_times_out, s.n_rows_in, s.n_rows_out
+        )?;
+        Ok(())
+    }
+}
+
+fn parse_count(matches: &clap::ArgMatches, name: &str) -> Result<usize, Error> {
+    let text = matches.value_of(name).unwrap();
+
+    match text.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(failure::err_msg(format!(
+            "the value of --{} must be a positive integer; got \"{}\"",
+            name, text
+        ))),
+    }
+}
+
+fn main() {
+    let matches = App::new("rubbl-average")
+        .version("0.1.0")
+        .about("Average the data of a Measurement Set in time and frequency")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .rubbl_dry_run_args()
+        .rubbl_output_args()
+        .arg(
+            Arg::with_name("timebin")
+                .long("timebin")
+                .value_name("N")
+                .help("Average time samples in groups of N")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("chanbin")
+                .long("chanbin")
+                .value_name("M")
+                .help("Average channels in groups of M")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("column")
+                .long("column")
+                .value_name("NAME")
+                .help("The column of the input from which to take the visibilities")
+                .default_value("DATA"),
+        )
+        .arg(
+            Arg::with_name("flag-policy")
+                .long("flag-policy")
+                .value_name("POLICY")
+                .help(
+                    "How to average partly flagged samples: \"drop\", \"renormalize\", \
+                     or \"threshold=FRACTION\"",
+                )
+                .default_value("renormalize"),
+        )
+        .arg(
+            Arg::with_name("workers")
+                .long("workers")
+                .value_name("N")
+                .help("Average each data description in a separate process, N at a time")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("IN-MS")
+                .help("The path of the input Measurement Set")
+                .required(true)
+                .index(1),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let inpath = Path::new(matches.value_of_os("IN-MS").unwrap());
+            let output = OutputPolicy::from_clap(&matches)?;
+            let outpath = match output {
+                OutputPolicy::InPlace => inpath,
+                OutputPolicy::NewTable(ref p) | OutputPolicy::Overwrite(ref p) => p.as_path(),
+            };
+
+            let options = DecimateOptions {
+                time_step: 1,
+                time_width: parse_count(&matches, "timebin")?,
+                chan_width: parse_count(&matches, "chanbin")?,
+                data_column: matches.value_of("column").unwrap().to_owned(),
+                flag_policy: FlagPolicy::parse(matches.value_of("flag-policy").unwrap())?,
+            };
+
+            if options.time_width == 1 && options.chan_width == 1 {
+                return Err(failure::err_msg(
+                    "nothing to average: give --timebin or --chanbin",
+                ));
+            }
+
+            if run_decimate_worker(inpath, &options)? {
+                return Ok(0);
+            }
+
+            let n_workers = parse_count(&matches, "workers")?;
+
+            if dry_run_requested(&matches) {
+                let summary = ctry!(decimate_dry_run(inpath, &options);
+                                    "failed to plan the averaging of \"{}\"", inpath.display());
+                let mut plan = ChangePlan::new();
+                plan.record(
+                    outpath.display().to_string(),
+                    format!(
+                        "write the averaged data of \"{}\" at {} times",
+                        inpath.display(),
+                        summary.n_times_out
+                    ),
+                    summary.n_rows_out,
+                    None,
+                );
+                plan.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+                return Ok(0);
+            }
+
+            let result = if n_workers > 1 {
+                let args: Vec<OsString> = env::args_os().skip(1).collect();
+                decimate_with_workers(
+                    inpath,
+                    &output,
+                    &options,
+                    n_workers,
+                    &env::current_exe()?,
+                    &args,
+                )
+            } else {
+                decimate(inpath, &output, &options)
+            };
+            let summary = ctry!(result;
+                                "failed to average \"{}\" into \"{}\"",
+                                inpath.display(), outpath.display());
+
+            let report = AverageReport {
+                input: inpath.display().to_string(),
+                output: outpath.display().to_string(),
+                summary: summary,
+            };
+
+            report.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+            Ok(0)
+        },
+    ));
+}
@@ -0,0 +1,382 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Measure how quickly CASA table data can be read.
+
+`rubbl bench read TABLE COLUMN` reads a column in chunks of several sizes,
+both sequentially and in a strided pattern that reads one chunk out of every
+few, optionally under several tile-cache limits. It then reports the
+bandwidth of each combination and recommends the fastest settings, which can
+be used to choose a `--memory-budget` for other tools.
+
+Before the timed trials, the column is read once in full so that every trial
+sees the same operating-system file cache. The results therefore describe
+reading from memory-cached files, unless the column is larger than the
+available memory.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use failure::err_msg;
+use rubbl_casatables::{CasaScalarData, GlueDataType, Table, TableOpenMode};
+use rubbl_core::budget::MemoryBudget;
+use rubbl_core::notify::{ClapNotificationArgsExt, NotificationBackend};
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::{Complex, Error};
+use std::io::{self, Write};
+use std::mem;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+const DEFAULT_CHUNK_SIZES: &str = "1M,16M,128M";
+const DEFAULT_STRIDE: &str = "4";
+
+/// How the rows of a column are visited during a trial.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Access {
+    /// Every chunk is read, in order.
+    Sequential,
+
+    /// One chunk out of every `stride` is read, in order.
+    Strided,
+}
+
+#[derive(Debug, Serialize)]
+struct Trial {
+    access: Access,
+    chunk_bytes: u64,
+    rows_per_chunk: u64,
+    cache_mib: Option<u32>,
+    bytes_read: u64,
+    seconds: f64,
+    mib_per_second: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadBenchmark {
+    path: String,
+    column: String,
+    n_rows: u64,
+    stride: u64,
+    trials: Vec<Trial>,
+}
+
+impl ReadBenchmark {
+    /// Get the fastest trial with the given access pattern.
+    fn best(&self, access: Access) -> Option<&Trial> {
+        self.trials
+            .iter()
+            .filter(|t| t.access == access)
+            .max_by(|a, b| a.mib_per_second.partial_cmp(&b.mib_per_second).unwrap())
+    }
+}
+
+fn describe_cache(cache_mib: Option<u32>) -> String {
+    match cache_mib {
+        None => "default".to_owned(),
+        Some(0) => "unlimited".to_owned(),
+        Some(n) => format!("{} MiB", n),
+    }
+}
+
+impl Report for ReadBenchmark {
+    fn write_text(&self, dest: &mut Write) -> Result<(), Error> {
+        writeln!(
+            dest,
+            "Reading column \"{}\" of \"{}\" ({} rows; strided reads use one chunk in {}):",
+            self.column, self.path, self.n_rows, self.stride
+        )?;
+        writeln!(dest)?;
+        writeln!(
+            dest,
+            "{:<10}  {:>12}  {:>10}  {:>10}  {:>10}",
+            "access", "chunk bytes", "chunk rows", "tile cache", "MiB/s"
+        )?;
+
+        for t in &self.trials {
+            writeln!(
+                dest,
+                "{:<10}  {:>12}  {:>10}  {:>10}  {:>10.1}",
+                match t.access {
+                    Access::Sequential => "sequential",
+                    Access::Strided => "strided",
+                },
+                t.chunk_bytes,
+                t.rows_per_chunk,
+                describe_cache(t.cache_mib),
+                t.mib_per_second
+            )?;
+        }
+
+        writeln!(dest)?;
+
+        for &(access, desc) in &[
+            (Access::Sequential, "sequential"),
+            (Access::Strided, "strided"),
+        ] {
+            if let Some(t) = self.best(access) {
+                writeln!(
+                    dest,
+                    "Recommendation for {} reads: chunks of {} bytes ({} rows) with a {} tile cache",
+                    desc,
+                    t.chunk_bytes,
+                    t.rows_per_chunk,
+                    describe_cache(t.cache_mib)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Read rows from a column, returning the number of bytes of data read.
+fn read_rows_typed<T: CasaScalarData>(
+    table: &mut Table,
+    col_name: &str,
+    start_row: u64,
+    n_rows: u64,
+) -> Result<u64, Error> {
+    let data = table.get_col_range_as_vec::<T>(col_name, start_row, n_rows)?;
+    Ok((data.len() * mem::size_of::<T>()) as u64)
+}
+
+fn read_rows(
+    table: &mut Table,
+    col_name: &str,
+    data_type: GlueDataType,
+    start_row: u64,
+    n_rows: u64,
+) -> Result<u64, Error> {
+    match data_type {
+        GlueDataType::TpBool => read_rows_typed::<bool>(table, col_name, start_row, n_rows),
+        GlueDataType::TpChar => read_rows_typed::<i8>(table, col_name, start_row, n_rows),
+        GlueDataType::TpUChar => read_rows_typed::<u8>(table, col_name, start_row, n_rows),
+        GlueDataType::TpShort => read_rows_typed::<i16>(table, col_name, start_row, n_rows),
+        GlueDataType::TpUShort => read_rows_typed::<u16>(table, col_name, start_row, n_rows),
+        GlueDataType::TpInt => read_rows_typed::<i32>(table, col_name, start_row, n_rows),
+        GlueDataType::TpUInt => read_rows_typed::<u32>(table, col_name, start_row, n_rows),
+        GlueDataType::TpInt64 => read_rows_typed::<i64>(table, col_name, start_row, n_rows),
+        GlueDataType::TpFloat => read_rows_typed::<f32>(table, col_name, start_row, n_rows),
+        GlueDataType::TpDouble => read_rows_typed::<f64>(table, col_name, start_row, n_rows),
+        GlueDataType::TpComplex => {
+            read_rows_typed::<Complex<f32>>(table, col_name, start_row, n_rows)
+        }
+        GlueDataType::TpDComplex => {
+            read_rows_typed::<Complex<f64>>(table, col_name, start_row, n_rows)
+        }
+        other => Err(err_msg(format!(
+            "cannot benchmark column \"{}\" of type {}",
+            col_name, other
+        ))),
+    }
+}
+
+/// Read every *step*'th chunk of *rows_per_chunk* rows, returning the number
+/// of bytes read.
+fn read_chunks(
+    table: &mut Table,
+    col_name: &str,
+    data_type: GlueDataType,
+    rows_per_chunk: u64,
+    step: u64,
+) -> Result<u64, Error> {
+    let n_rows = table.n_rows();
+    let mut start_row = 0;
+    let mut bytes_read = 0;
+
+    while start_row < n_rows {
+        let n = std::cmp::min(rows_per_chunk, n_rows - start_row);
+        bytes_read += read_rows(table, col_name, data_type, start_row, n)?;
+        start_row += rows_per_chunk * step;
+    }
+
+    Ok(bytes_read)
+}
+
+fn parse_list<T, F: Fn(&str) -> Result<T, Error>>(
+    matches: &ArgMatches,
+    name: &str,
+    default: &str,
+    parse: F,
+) -> Result<Vec<T>, Error> {
+    matches
+        .value_of(name)
+        .unwrap_or(default)
+        .split(',')
+        .map(|s| parse(s.trim()))
+        .collect()
+}
+
+fn do_read(matches: &ArgMatches, nbe: &mut NotificationBackend) -> Result<i32, Error> {
+    let inpath = Path::new(matches.value_of_os("IN-TABLE").unwrap());
+    let col_name = matches.value_of("COLUMN").unwrap();
+
+    let chunk_sizes = parse_list(matches, "chunk_sizes", DEFAULT_CHUNK_SIZES, |s| {
+        MemoryBudget::parse(s)
+    })?;
+    let mut cache_sizes: Vec<Option<u32>> = parse_list(
+        matches,
+        "cache_sizes",
+        "",
+        |s| -> Result<Option<u32>, Error> {
+            if s.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(
+                    ctry!(s.parse::<u32>(); "cannot parse \"{}\" as a cache size in MiB", s),
+                ))
+            }
+        },
+    )?;
+    let stride: u64 = {
+        let text = matches.value_of("stride").unwrap_or(DEFAULT_STRIDE);
+        ctry!(text.parse::<u64>(); "cannot parse \"{}\" as a stride", text)
+    };
+
+    if stride < 1 {
+        return Err(err_msg("the stride must be at least 1"));
+    }
+
+    let mut t = ctry!(Table::open(inpath, TableOpenMode::Read);
+                      "failed to open input table \"{}\"", inpath.display());
+    let desc = ctry!(t.get_col_desc(col_name);
+                     "failed to query column \"{}\" in \"{}\"", col_name, inpath.display());
+    let data_type = desc.data_type();
+    let width = t.column_width(col_name)?;
+    let n_rows = t.n_rows();
+
+    if cache_sizes.iter().any(|c| c.is_some()) {
+        if let Err(e) = t.set_tile_cache_size(col_name, 0) {
+            rn_warning!(
+                nbe,
+                "cannot set the tile cache size of column \"{}\", so only the default will be \
+                 tested: {}",
+                col_name,
+                e
+            );
+            cache_sizes = vec![None];
+        }
+    }
+
+    let warmup_budget = chunk_sizes.iter().max().cloned().unwrap_or_default();
+    let warmup_rows = warmup_budget.rows_per_chunk(width, n_rows);
+    read_chunks(&mut t, col_name, data_type, warmup_rows, 1)?;
+
+    let mut trials = Vec::new();
+
+    for &access in &[Access::Sequential, Access::Strided] {
+        let step = match access {
+            Access::Sequential => 1,
+            Access::Strided => stride,
+        };
+
+        for budget in &chunk_sizes {
+            let rows_per_chunk = budget.rows_per_chunk(width, n_rows);
+
+            for &cache_mib in &cache_sizes {
+                // Reopen the table so that each trial starts with empty
+                // casacore caches.
+                let mut t = Table::open(inpath, TableOpenMode::Read)?;
+
+                if let Some(n) = cache_mib {
+                    t.set_tile_cache_size(col_name, n)?;
+                }
+
+                let start = Instant::now();
+                let bytes_read = read_chunks(&mut t, col_name, data_type, rows_per_chunk, step)?;
+                let elapsed = start.elapsed();
+                let seconds = elapsed.as_secs() as f64 + 1e-9 * elapsed.subsec_nanos() as f64;
+
+                trials.push(Trial {
+                    access: access,
+                    chunk_bytes: budget.bytes(),
+                    rows_per_chunk: rows_per_chunk,
+                    cache_mib: cache_mib,
+                    bytes_read: bytes_read,
+                    seconds: seconds,
+                    mib_per_second: bytes_read as f64 / 1048576. / seconds,
+                });
+            }
+        }
+    }
+
+    let report = ReadBenchmark {
+        path: inpath.display().to_string(),
+        column: col_name.to_owned(),
+        n_rows: n_rows,
+        stride: stride,
+        trials: trials,
+    };
+
+    report.emit(OutputFormat::from_clap(matches), &mut io::stdout())?;
+    Ok(0)
+}
+
+fn main() {
+    let matches = App::new("rubbl-bench")
+        .version("0.1.0")
+        .about("Measure how quickly CASA table data can be read")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .rubbl_notify_args()
+        .subcommand(
+            SubCommand::with_name("read")
+                .about("Measure the bandwidth of reading a column with different settings")
+                .rubbl_report_args()
+                .arg(
+                    Arg::with_name("chunk_sizes")
+                        .long("chunk-sizes")
+                        .value_name("SIZES")
+                        .help("Comma-separated memory sizes of the chunks to read (default: 1M,16M,128M)"),
+                )
+                .arg(
+                    Arg::with_name("cache_sizes")
+                        .long("cache-sizes")
+                        .value_name("MIBS")
+                        .help(
+                            "Comma-separated tile-cache limits to test, in MiB; 0 means \
+                             unlimited (default: casacore's own setting)",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("stride")
+                        .long("stride")
+                        .value_name("N")
+                        .help("Strided trials read one chunk out of every N (default: 4)"),
+                )
+                .arg(
+                    Arg::with_name("IN-TABLE")
+                        .help("The path of the input table")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("COLUMN")
+                        .help("The name of the column to read")
+                        .required(true)
+                        .index(2),
+                ),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, nbe| -> Result<i32, Error> {
+            match matches.subcommand() {
+                ("read", Some(m)) => do_read(m, nbe),
+                _ => unreachable!(),
+            }
+        },
+    ));
+}
@@ -0,0 +1,533 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Browse a CASA table interactively in the terminal.
+
+The rows of the table are shown a screenful at a time. Scalar cells show
+their values and array cells their shapes; only the rows and columns on
+screen are read, so even huge tables open instantly, which makes this handy
+for poking at data sets on remote machines over ssh.
+
+Keys:
+
+- arrows, Page Up/Down, Home/End: move around the table
+- Enter: summarize the selected cell (for arrays, the range and mean of
+  their values)
+- k: show the keywords of the table and of the selected column
+- Esc: close a summary
+- q: quit
+
+This command needs the `browse` feature of the crate.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate ratatui;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+
+use clap::{App, Arg};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Wrap};
+use ratatui::Frame;
+use rubbl_casatables::{GlueDataType, Table, TableOpenMode};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::{Complex, Error};
+use std::path::Path;
+use std::process;
+
+/// The width of each column of the display, in characters.
+const COLUMN_WIDTH: u16 = 18;
+
+/// The number of values of an array cell listed in its summary.
+const SUMMARY_VALUES: usize = 8;
+
+/// A column of the table being browsed.
+struct ColumnInfo {
+    name: String,
+    data_type: GlueDataType,
+    is_scalar: bool,
+}
+
+/// What is shown on top of the rows of the table.
+enum Popup {
+    None,
+    Text(String, String),
+}
+
+struct Browser {
+    table: Table,
+    path: String,
+    n_rows: u64,
+    columns: Vec<ColumnInfo>,
+
+    /// The selected row and column.
+    row: u64,
+    col: usize,
+
+    /// The first row and column on screen, and how many fit.
+    top: u64,
+    first_col: usize,
+    page_rows: u64,
+    page_cols: usize,
+
+    /// The text of the cells on screen, column by column, and the first row,
+    /// number of rows, and first column that they were read for.
+    page: Vec<Vec<String>>,
+    page_key: Option<(u64, u64, usize)>,
+
+    popup: Popup,
+}
+
+macro_rules! scalar_texts {
+    ($table:expr, $name:expr, $start:expr, $n:expr, $ty:ty) => {
+        $table
+            .get_col_range_as_vec::<$ty>($name, $start, $n)?
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    };
+}
+
+macro_rules! real_summary {
+    ($table:expr, $name:expr, $row:expr, $ty:ty) => {
+        summarize_reals(
+            &$table
+                .get_cell_as_vec::<$ty>($name, $row)?
+                .iter()
+                .map(|&v| v as f64)
+                .collect::<Vec<_>>(),
+        )
+    };
+}
+
+macro_rules! complex_summary {
+    ($table:expr, $name:expr, $row:expr, $ty:ty) => {{
+        let values = $table.get_cell_as_vec::<Complex<$ty>>($name, $row)?;
+        let amps: Vec<f64> = values.iter().map(|v| v.norm() as f64).collect();
+        format!(
+            "amplitudes: {}\nfirst values: {}",
+            summarize_reals(&amps),
+            list_values(&values)
+        )
+    }};
+}
+
+impl Browser {
+    fn new(mut table: Table, path: String) -> Result<Self, Error> {
+        let mut columns = Vec::new();
+
+        for name in table.column_names()? {
+            let desc = table.get_col_desc(&name)?;
+
+            columns.push(ColumnInfo {
+                name: name,
+                data_type: desc.data_type(),
+                is_scalar: desc.is_scalar(),
+            });
+        }
+
+        Ok(Browser {
+            n_rows: table.n_rows(),
+            table: table,
+            path: path,
+            columns: columns,
+            row: 0,
+            col: 0,
+            top: 0,
+            first_col: 0,
+            page_rows: 1,
+            page_cols: 1,
+            page: Vec::new(),
+            page_key: None,
+            popup: Popup::None,
+        })
+    }
+
+    /// Work out how much of the table fits in an area of the given size,
+    /// scroll so that the selected cell is visible, and read the cells on
+    /// screen if they have changed.
+    fn update_page(&mut self, width: u16, height: u16) -> Result<(), Error> {
+        // The title and key lines, the borders, and the column names.
+        self.page_rows = height.saturating_sub(5).max(1) as u64;
+        self.page_cols =
+            (width.saturating_sub(2 + self.row_label_width()) / (COLUMN_WIDTH + 1)).max(1) as usize;
+
+        if self.row < self.top {
+            self.top = self.row;
+        } else if self.row >= self.top + self.page_rows {
+            self.top = self.row + 1 - self.page_rows;
+        }
+
+        if self.col < self.first_col {
+            self.first_col = self.col;
+        } else if self.col >= self.first_col + self.page_cols {
+            self.first_col = self.col + 1 - self.page_cols;
+        }
+
+        let n = self.page_rows.min(self.n_rows - self.top.min(self.n_rows));
+        let key = (self.top, n, self.first_col);
+
+        if self.page_key == Some(key) && self.page.len() == self.visible_columns().len() {
+            return Ok(());
+        }
+
+        self.page.clear();
+
+        for col in self.visible_columns() {
+            let texts = self.read_texts(col, self.top, n)?;
+            self.page.push(texts);
+        }
+
+        self.page_key = Some(key);
+        Ok(())
+    }
+
+    fn visible_columns(&self) -> std::ops::Range<usize> {
+        self.first_col..(self.first_col + self.page_cols).min(self.columns.len())
+    }
+
+    fn row_label_width(&self) -> u16 {
+        self.n_rows.to_string().len() as u16 + 1
+    }
+
+    /// Get the text shown for *n* rows of column *col* starting at *start*.
+    fn read_texts(&mut self, col: usize, start: u64, n: u64) -> Result<Vec<String>, Error> {
+        use GlueDataType::*;
+
+        let name = &self.columns[col].name;
+
+        if !self.columns[col].is_scalar {
+            let mut texts = Vec::with_capacity(n as usize);
+
+            for row in start..start + n {
+                texts.push(if self.table.cell_is_defined(name, row)? {
+                    let shape = self.table.get_cell_shape(name, row)?;
+                    let dims: Vec<String> = shape.iter().map(|d| d.to_string()).collect();
+                    format!("[{}]", dims.join("×"))
+                } else {
+                    "-".to_owned()
+                });
+            }
+
+            return Ok(texts);
+        }
+
+        Ok(match self.columns[col].data_type {
+            TpBool => scalar_texts!(self.table, name, start, n, bool),
+            TpChar => scalar_texts!(self.table, name, start, n, i8),
+            TpUChar => scalar_texts!(self.table, name, start, n, u8),
+            TpShort => scalar_texts!(self.table, name, start, n, i16),
+            TpUShort => scalar_texts!(self.table, name, start, n, u16),
+            TpInt => scalar_texts!(self.table, name, start, n, i32),
+            TpUInt => scalar_texts!(self.table, name, start, n, u32),
+            TpInt64 => scalar_texts!(self.table, name, start, n, i64),
+            TpFloat => scalar_texts!(self.table, name, start, n, f32),
+            TpDouble => scalar_texts!(self.table, name, start, n, f64),
+            TpComplex => scalar_texts!(self.table, name, start, n, Complex<f32>),
+            TpDComplex => scalar_texts!(self.table, name, start, n, Complex<f64>),
+            TpString => scalar_texts!(self.table, name, start, n, String),
+            other => vec![format!("({})", other); n as usize],
+        })
+    }
+
+    /// Describe the selected cell in full.
+    fn summarize_cell(&mut self) -> Result<String, Error> {
+        use GlueDataType::*;
+
+        let name = self.columns[self.col].name.clone();
+        let data_type = self.columns[self.col].data_type;
+        let row = self.row;
+
+        if self.columns[self.col].is_scalar {
+            let text = self.read_texts(self.col, row, 1)?.pop().unwrap_or_default();
+            return Ok(format!("type: {}\nvalue: {}", data_type, text));
+        }
+
+        if !self.table.cell_is_defined(&name, row)? {
+            return Ok(format!("type: {}\nthe cell is undefined", data_type));
+        }
+
+        let shape = self.table.get_cell_shape(&name, row)?;
+        let details = match data_type {
+            TpBool => {
+                let values = self.table.get_cell_as_vec::<bool>(&name, row)?;
+                let n_true = values.iter().filter(|&&b| b).count();
+                format!("{} of {} values true", n_true, values.len())
+            }
+            TpChar => real_summary!(self.table, &name, row, i8),
+            TpUChar => real_summary!(self.table, &name, row, u8),
+            TpShort => real_summary!(self.table, &name, row, i16),
+            TpUShort => real_summary!(self.table, &name, row, u16),
+            TpInt => real_summary!(self.table, &name, row, i32),
+            TpUInt => real_summary!(self.table, &name, row, u32),
+            TpInt64 => real_summary!(self.table, &name, row, i64),
+            TpFloat => real_summary!(self.table, &name, row, f32),
+            TpDouble => real_summary!(self.table, &name, row, f64),
+            TpComplex => complex_summary!(self.table, &name, row, f32),
+            TpDComplex => complex_summary!(self.table, &name, row, f64),
+            TpString => {
+                let values = self.table.get_cell_as_vec::<String>(&name, row)?;
+                format!("first values: {}", list_values(&values))
+            }
+            _ => String::new(),
+        };
+
+        Ok(format!(
+            "type: {}\nshape: {:?}\n{}",
+            data_type, shape, details
+        ))
+    }
+
+    /// List the keywords of the table and of the selected column.
+    fn describe_keywords(&mut self) -> Result<String, Error> {
+        let mut text = String::from("Table keywords:\n");
+
+        for kw in self.table.keyword_values(None)? {
+            text.push_str(&format!(
+                "  {} ({}): {}\n",
+                kw.name(),
+                kw.data_type(),
+                kw.value()
+            ));
+        }
+
+        if let Some(col) = self.columns.get(self.col) {
+            text.push_str(&format!("\nKeywords of column {}:\n", col.name));
+
+            for kw in self.table.keyword_values(Some(&col.name))? {
+                text.push_str(&format!(
+                    "  {} ({}): {}\n",
+                    kw.name(),
+                    kw.data_type(),
+                    kw.value()
+                ));
+            }
+        }
+
+        Ok(text)
+    }
+
+    /// Act on a key press, returning false if the program should exit.
+    fn handle_key(&mut self, code: KeyCode) -> Result<bool, Error> {
+        let last_row = self.n_rows.saturating_sub(1);
+        let last_col = self.columns.len().saturating_sub(1);
+
+        if let Popup::Text(..) = self.popup {
+            match code {
+                KeyCode::Char('q') => return Ok(false),
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('k') => self.popup = Popup::None,
+                _ => {}
+            }
+
+            return Ok(true);
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Up => self.row = self.row.saturating_sub(1),
+            KeyCode::Down => self.row = (self.row + 1).min(last_row),
+            KeyCode::PageUp => self.row = self.row.saturating_sub(self.page_rows),
+            KeyCode::PageDown => self.row = (self.row + self.page_rows).min(last_row),
+            KeyCode::Home => self.row = 0,
+            KeyCode::End => self.row = last_row,
+            KeyCode::Left => self.col = self.col.saturating_sub(1),
+            KeyCode::Right => self.col = (self.col + 1).min(last_col),
+
+            KeyCode::Enter if self.n_rows > 0 && !self.columns.is_empty() => {
+                let title = format!("{}, row {}", self.columns[self.col].name, self.row);
+                self.popup = Popup::Text(title, self.summarize_cell()?);
+            }
+
+            KeyCode::Char('k') => {
+                self.popup = Popup::Text("Keywords".to_owned(), self.describe_keywords()?);
+            }
+
+            _ => {}
+        }
+
+        Ok(true)
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let areas = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .split(frame.area());
+
+        frame.render_widget(
+            Paragraph::new(format!(
+                "{}: {} rows, {} columns",
+                self.path,
+                self.n_rows,
+                self.columns.len()
+            )),
+            areas[0],
+        );
+
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let selected = Style::default().add_modifier(Modifier::REVERSED);
+        let cols = self.visible_columns();
+
+        let header = Row::new(
+            std::iter::once(Cell::from("")).chain(
+                cols.clone()
+                    .map(|c| Cell::from(self.columns[c].name.clone())),
+            ),
+        )
+        .style(bold);
+
+        let rows = (0..self.page.first().map(|p| p.len()).unwrap_or(0)).map(|i| {
+            let row = self.top + i as u64;
+            let label = Cell::from(row.to_string()).style(bold);
+
+            Row::new(
+                std::iter::once(label).chain(cols.clone().enumerate().map(|(j, c)| {
+                    let cell = Cell::from(self.page[j][i].clone());
+
+                    if row == self.row && c == self.col {
+                        cell.style(selected)
+                    } else {
+                        cell
+                    }
+                })),
+            )
+        });
+
+        let widths = std::iter::once(Constraint::Length(self.row_label_width())).chain(
+            self.visible_columns()
+                .map(|_| Constraint::Length(COLUMN_WIDTH)),
+        );
+
+        frame.render_widget(
+            ratatui::widgets::Table::new(rows, widths)
+                .header(header)
+                .block(Block::default().borders(Borders::ALL)),
+            areas[1],
+        );
+
+        frame.render_widget(
+            Paragraph::new(
+                "arrows/PgUp/PgDn/Home/End: move  Enter: cell summary  k: keywords  q: quit",
+            ),
+            areas[2],
+        );
+
+        if let Popup::Text(ref title, ref text) = self.popup {
+            let area = centered(areas[1], 80, 80);
+            frame.render_widget(Clear, area);
+            frame.render_widget(
+                Paragraph::new(text.as_str())
+                    .wrap(Wrap { trim: false })
+                    .block(Block::default().borders(Borders::ALL).title(title.as_str())),
+                area,
+            );
+        }
+    }
+}
+
+/// Get a rectangle covering the given percentages of the width and height
+/// of *area*, centered in it.
+fn centered(area: Rect, width_pct: u16, height_pct: u16) -> Rect {
+    let width = area.width * width_pct / 100;
+    let height = area.height * height_pct / 100;
+
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width: width,
+        height: height,
+    }
+}
+
+fn summarize_reals(values: &[f64]) -> String {
+    let finite: Vec<f64> = values.iter().cloned().filter(|v| v.is_finite()).collect();
+
+    if finite.is_empty() {
+        return format!("{} values, none finite", values.len());
+    }
+
+    let min = finite.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = finite.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let mean = finite.iter().sum::<f64>() / finite.len() as f64;
+
+    format!(
+        "{} values ({} finite), min {}, max {}, mean {}",
+        values.len(),
+        finite.len(),
+        min,
+        max,
+        mean
+    )
+}
+
+fn list_values<T: ToString>(values: &[T]) -> String {
+    let mut items: Vec<String> = values
+        .iter()
+        .take(SUMMARY_VALUES)
+        .map(|v| v.to_string())
+        .collect();
+
+    if values.len() > SUMMARY_VALUES {
+        items.push("…".to_owned());
+    }
+
+    items.join(", ")
+}
+
+/// Restores the terminal when dropped, however the browser exits.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+fn main() {
+    let matches = App::new("rubbl-browse")
+        .version("0.1.0")
+        .about("Browse a CASA table interactively in the terminal")
+        .rubbl_notify_args()
+        .arg(
+            Arg::with_name("IN-TABLE")
+                .help("The path of the table to browse")
+                .required(true)
+                .index(1),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let inpath = Path::new(matches.value_of_os("IN-TABLE").unwrap()).to_owned();
+
+            let table = ctry!(Table::open(&inpath, TableOpenMode::Read);
+                              "failed to open input table \"{}\"", inpath.display());
+            let mut browser = Browser::new(table, inpath.display().to_string())?;
+
+            let mut terminal = ratatui::try_init()?;
+            let _guard = TerminalGuard;
+
+            loop {
+                let size = terminal.size()?;
+                browser.update_page(size.width, size.height)?;
+                terminal.draw(|frame| browser.draw(frame))?;
+
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && !browser.handle_key(key.code)? {
+                        break;
+                    }
+                }
+            }
+
+            Ok(0)
+        },
+    ));
+}
@@ -0,0 +1,324 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Flag the data of a Measurement Set.
+
+`rubbl flag IN.ms -o OUT.ms --antenna '3&*' --spw 0:0~7` flags the data
+picked out by the standard `--antenna`, `--spw`, and `--timerange` options,
+including just the selected channels if `--spw` selects some, and writes the
+result to OUT.ms. With `--in-place`, IN.ms is modified instead.
+
+`rubbl flag IN.ms --in-place --auto-channels` instead finds the band edges
+and persistently bad channels of each data description from its
+autocorrelations and flags them in every row; see the
+`rubbl_casatables::chanflag` module. It cannot be combined with a selection.
+
+With `--workers N`, the rows of each data description are flagged in a
+separate process, with up to N running at once. This applies to flagging a
+selection only.
+
+With `--dry-run`, the data that would be flagged are reported, but nothing
+is written.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate ndarray;
+extern crate rubbl_casatables;
+extern crate rubbl_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+use clap::{App, Arg};
+use failure::err_msg;
+use ndarray::Array2;
+use rubbl_casatables::chanflag::{find_channel_flags, flag_channels, ChannelFlagger};
+use rubbl_casatables::partition::{for_each_ddid, run_ddid_worker};
+use rubbl_casatables::planner::SelectionPlan;
+use rubbl_casatables::{Table, TableOpenMode};
+use rubbl_core::dryrun::{dry_run_requested, ChangePlan, ClapDryRunArgsExt};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::output::{ClapOutputArgsExt, OutputPolicy};
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::select::{ClapSelectionArgsExt, Selection};
+use rubbl_core::Error;
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+#[derive(Debug, Serialize)]
+struct FlagReport {
+    input: String,
+    output: String,
+    n_rows_flagged: u64,
+    channels: Vec<String>,
+}
+
+impl Report for FlagReport {
+    fn write_text(&self, dest: &mut Write) -> Result<(), Error> {
+        writeln!(dest, "\"{}\" -> \"{}\"", self.input, self.output)?;
+        writeln!(dest, "flagged data in {} rows", self.n_rows_flagged)?;
+
+        for c in &self.channels {
+            writeln!(dest, "flagged channels {}", c)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_count(matches: &clap::ArgMatches, name: &str) -> Result<usize, Error> {
+    let text = matches.value_of(name).unwrap();
+
+    match text.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(err_msg(format!(
+            "the value of --{} must be a positive integer; got \"{}\"",
+            name, text
+        ))),
+    }
+}
+
+fn parse_number(matches: &clap::ArgMatches, name: &str) -> Result<Option<f64>, Error> {
+    match matches.value_of(name) {
+        None => Ok(None),
+        Some(text) => match text.parse::<f64>() {
+            Ok(v) => Ok(Some(v)),
+            Err(_) => Err(err_msg(format!(
+                "the value of --{} must be a number; got \"{}\"",
+                name, text
+            ))),
+        },
+    }
+}
+
+/// Work out which rows of *ms* are selected and which of their channels,
+/// as a map from `DATA_DESC_ID` to channel mask, or None if whole rows are
+/// selected. The subtables are read from the Measurement Set at *ms_path*.
+fn selected_data(
+    ms: &mut Table,
+    ms_path: &Path,
+    selection: &Selection,
+) -> Result<(Vec<bool>, Option<Vec<Option<Vec<bool>>>>), Error> {
+    let rows = SelectionPlan::new(selection, ms)?.row_mask(ms)?;
+
+    if selection.spws.iter().all(|t| t.channels.is_empty()) {
+        return Ok((rows, None));
+    }
+
+    let spws = Table::open(ms_path.join("DATA_DESCRIPTION"), TableOpenMode::Read)?
+        .get_col_as_vec::<i32>("SPECTRAL_WINDOW_ID")?;
+    let n_chans = Table::open(ms_path.join("SPECTRAL_WINDOW"), TableOpenMode::Read)?
+        .get_col_as_vec::<i32>("NUM_CHAN")?;
+    let masks = spws
+        .iter()
+        .map(|&spw| {
+            n_chans
+                .get(spw as usize)
+                .and_then(|&n| selection.channel_mask(spw as usize, n as usize))
+        })
+        .collect();
+
+    Ok((rows, Some(masks)))
+}
+
+/// Flag the selected data of *ms*, returning the number of rows changed.
+fn flag_selection(ms: &mut Table, ms_path: &Path, selection: &Selection) -> Result<u64, Error> {
+    let (rows, chan_masks) = selected_data(ms, ms_path, selection)?;
+    let ddids = match chan_masks {
+        Some(_) => ms.get_col_as_vec::<i32>("DATA_DESC_ID")?,
+        None => Vec::new(),
+    };
+    let mut n_flagged = 0;
+
+    for (row, _) in rows.iter().enumerate().filter(|&(_, &r)| r) {
+        let mut flags: Array2<bool> = ms.get_cell("FLAG", row as u64)?;
+
+        let mask = match chan_masks {
+            Some(ref m) => match m.get(ddids[row] as usize) {
+                Some(&Some(ref mask)) => Some(mask),
+                _ => continue,
+            },
+            None => None,
+        };
+
+        match mask {
+            Some(mask) => {
+                if flags.shape()[0] != mask.len() {
+                    return Err(err_msg(format!(
+                        "row {} has {} channels, but its spectral window has {}",
+                        row,
+                        flags.shape()[0],
+                        mask.len()
+                    )));
+                }
+
+                for (mut chan_flags, &sel) in flags.outer_iter_mut().zip(mask) {
+                    if sel {
+                        chan_flags.fill(true);
+                    }
+                }
+            }
+
+            None => flags.fill(true),
+        }
+
+        ms.put_cell("FLAG", row as u64, &flags)?;
+        ms.put_cell("FLAG_ROW", row as u64, &flags.iter().all(|&f| f))?;
+        n_flagged += 1;
+    }
+
+    Ok(n_flagged)
+}
+
+fn main() {
+    let matches = App::new("rubbl-flag")
+        .version("0.1.0")
+        .about("Flag the data of a Measurement Set")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .rubbl_dry_run_args()
+        .rubbl_output_args()
+        .rubbl_selection_args()
+        .arg(
+            Arg::with_name("auto_channels")
+                .long("auto-channels")
+                .help("Flag band edges and bad channels found in the autocorrelations"),
+        )
+        .arg(
+            Arg::with_name("threshold")
+                .long("threshold")
+                .value_name("SIGMAS")
+                .help("With --auto-channels, how far a bad channel deviates (default: 5)"),
+        )
+        .arg(
+            Arg::with_name("edge_level")
+                .long("edge-level")
+                .value_name("FRACTION")
+                .help("With --auto-channels, the level of the band edges (default: 0.5)"),
+        )
+        .arg(
+            Arg::with_name("workers")
+                .long("workers")
+                .value_name("N")
+                .help("Flag each data description in a separate process, N at a time")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("IN-MS")
+                .help("The path of the input Measurement Set")
+                .required(true)
+                .index(1),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let inpath = Path::new(matches.value_of_os("IN-MS").unwrap());
+            let output = OutputPolicy::from_clap(&matches)?;
+            let outpath = match output {
+                OutputPolicy::InPlace => inpath,
+                OutputPolicy::NewTable(ref p) | OutputPolicy::Overwrite(ref p) => p.as_path(),
+            };
+            let selection = Selection::from_clap(&matches)?;
+            let auto = matches.is_present("auto_channels");
+            let dry_run = dry_run_requested(&matches);
+            let n_workers = parse_count(&matches, "workers")?;
+
+            if auto && selection != Selection::new() {
+                return Err(err_msg(
+                    "--auto-channels cannot be combined with a selection",
+                ));
+            } else if !auto && selection == Selection::new() {
+                return Err(err_msg(
+                    "nothing to flag: give a selection or use --auto-channels",
+                ));
+            } else if auto && n_workers > 1 {
+                return Err(err_msg("--workers cannot be combined with --auto-channels"));
+            }
+
+            if run_ddid_worker(|_, ms| flag_selection(ms, inpath, &selection))? {
+                return Ok(0);
+            }
+
+            let mut report = FlagReport {
+                input: inpath.display().to_string(),
+                output: outpath.display().to_string(),
+                n_rows_flagged: 0,
+                channels: Vec::new(),
+            };
+            let mut plan = ChangePlan::new();
+
+            if auto {
+                let mut flagger = ChannelFlagger::new();
+
+                if let Some(v) = parse_number(&matches, "threshold")? {
+                    flagger.set_threshold(v);
+                }
+
+                if let Some(v) = parse_number(&matches, "edge_level")? {
+                    flagger.set_edge_level(v);
+                }
+
+                let ddids = Table::open(inpath, TableOpenMode::Read)?
+                    .get_col_as_vec::<i32>("DATA_DESC_ID")?;
+                let found = if dry_run {
+                    find_channel_flags(inpath, &flagger)?
+                } else {
+                    flag_channels(inpath, &output, &flagger)?
+                };
+
+                for c in found {
+                    let n_rows = ddids.iter().filter(|&&d| d == c.ddid).count() as u64;
+                    plan.record(
+                        outpath.display().to_string(),
+                        format!("flag channels {}", c),
+                        n_rows,
+                        None,
+                    );
+                    report.n_rows_flagged += n_rows;
+                    report.channels.push(c.to_string());
+                }
+            } else if dry_run {
+                let mut ms = Table::open(inpath, TableOpenMode::Read)?;
+                let (rows, _) = selected_data(&mut ms, inpath, &selection)?;
+                plan.record(
+                    outpath.display().to_string(),
+                    "flag the selected data",
+                    rows.iter().filter(|&&r| r).count() as u64,
+                    None,
+                );
+            } else if n_workers > 1 {
+                let prepared = output.prepare(inpath)?;
+                let args: Vec<OsString> = env::args_os().skip(1).collect();
+                let counts: Vec<(i32, u64)> = for_each_ddid(
+                    prepared.path(),
+                    TableOpenMode::ReadWrite,
+                    n_workers,
+                    &env::current_exe()?,
+                    &args,
+                )?;
+                prepared.commit()?;
+                report.n_rows_flagged = counts.iter().map(|&(_, n)| n).sum();
+            } else {
+                report.n_rows_flagged =
+                    Table::modify(inpath, &output, |ms| flag_selection(ms, inpath, &selection))?;
+            }
+
+            let format = OutputFormat::from_clap(&matches);
+
+            if dry_run {
+                plan.emit(format, &mut io::stdout())?;
+            } else {
+                report.emit(format, &mut io::stdout())?;
+            }
+
+            Ok(0)
+        },
+    ));
+}
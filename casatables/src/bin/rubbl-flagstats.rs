@@ -0,0 +1,118 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Report how much of a Measurement Set is flagged.
+
+`rubbl flagstats IN.ms` prints the fraction of the visibility samples of a
+Measurement Set that are flagged, in total and broken down by antenna, data
+description, and correlation. See the `rubbl_casatables::msstats` module
+for how the samples are counted.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+use clap::{App, Arg};
+use rubbl_casatables::msstats::{FlagCount, MsStats};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+#[derive(Debug, Serialize)]
+struct FlagStatsReport {
+    path: String,
+    total: FlagCount,
+    by_antenna: BTreeMap<i32, FlagCount>,
+    by_data_desc: BTreeMap<i32, FlagCount>,
+    by_correlation: Vec<FlagCount>,
+}
+
+fn write_count(dest: &mut Write, label: &str, count: &FlagCount) -> Result<(), Error> {
+    writeln!(
+        dest,
+        "    {:<16} {:>6.2}% ({} of {})",
+        label,
+        100. * count.fraction(),
+        count.n_flagged,
+        count.n_total
+    )?;
+    Ok(())
+}
+
+impl Report for FlagStatsReport {
+    fn write_text(&self, dest: &mut Write) -> Result<(), Error> {
+        writeln!(dest, "{}:", self.path)?;
+        write_count(dest, "total", &self.total)?;
+
+        writeln!(dest, "  by antenna:")?;
+        for (ant, count) in &self.by_antenna {
+            write_count(dest, &format!("antenna {}", ant), count)?;
+        }
+
+        writeln!(dest, "  by data description:")?;
+        for (ddid, count) in &self.by_data_desc {
+            write_count(dest, &format!("ddid {}", ddid), count)?;
+        }
+
+        writeln!(dest, "  by correlation:")?;
+        for (corr, count) in self.by_correlation.iter().enumerate() {
+            write_count(dest, &format!("corr {}", corr), count)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn main() {
+    let matches = App::new("rubbl-flagstats")
+        .version("0.1.0")
+        .about("Report how much of a Measurement Set is flagged")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .arg(
+            Arg::with_name("column")
+                .long("column")
+                .value_name("NAME")
+                .help("The column holding the visibilities that the flags apply to")
+                .default_value("DATA"),
+        )
+        .arg(
+            Arg::with_name("IN-MS")
+                .help("The path of the Measurement Set")
+                .required(true)
+                .index(1),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let inpath = Path::new(matches.value_of_os("IN-MS").unwrap());
+
+            let stats = ctry!(MsStats::gather(inpath, matches.value_of("column").unwrap());
+                              "failed to gather the flags of \"{}\"", inpath.display());
+
+            let report = FlagStatsReport {
+                path: inpath.display().to_string(),
+                total: stats.flags,
+                by_antenna: stats.flags_by_antenna,
+                by_data_desc: stats.flags_by_data_desc,
+                by_correlation: stats.flags_by_correlation,
+            };
+
+            report.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+            Ok(0)
+        },
+    ));
+}
@@ -0,0 +1,203 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Record and check the contents of datasets.
+
+`rubbl manifest create DATASET` prints a JSON manifest of a dataset: the
+size of each of its files and, for each CASA table in it, the number of
+rows and a hash of every column. `rubbl manifest verify MANIFEST DATASET`
+checks a dataset against a manifest saved earlier, reporting any
+differences and exiting with a nonzero status if there are some. See the
+`rubbl_casatables::manifest` module for details.
+
+With `--dry-run`, `create -o PATH` reports the manifest file that it would
+write rather than writing it.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use failure::err_msg;
+use rubbl_casatables::manifest::Manifest;
+use rubbl_casatables::HashAlgo;
+use rubbl_core::dryrun::{dry_run_requested, ChangePlan, ClapDryRunArgsExt};
+use rubbl_core::notify::{ClapNotificationArgsExt, NotificationBackend};
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+/// The outcome of checking a dataset against a manifest.
+#[derive(Debug, Serialize)]
+struct Verification {
+    dataset: String,
+    manifest: String,
+    matches: bool,
+    differences: Vec<String>,
+}
+
+impl Report for Verification {
+    fn write_text(&self, dest: &mut Write) -> Result<(), Error> {
+        if self.matches {
+            writeln!(
+                dest,
+                "\"{}\" matches the manifest \"{}\"",
+                self.dataset, self.manifest
+            )?;
+        } else {
+            writeln!(
+                dest,
+                "\"{}\" does not match the manifest \"{}\":",
+                self.dataset, self.manifest
+            )?;
+
+            for diff in &self.differences {
+                writeln!(dest, "    {}", diff)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn do_create(matches: &ArgMatches, _nbe: &mut NotificationBackend) -> Result<i32, Error> {
+    let inpath = Path::new(matches.value_of_os("DATASET").unwrap());
+    let algo_name = matches.value_of("hash").unwrap_or("crc32");
+    let algo = match HashAlgo::from_name(algo_name) {
+        Some(a) => a,
+        None => {
+            return Err(err_msg(format!(
+                "unsupported hash algorithm \"{}\"",
+                algo_name
+            )));
+        }
+    };
+
+    let manifest = ctry!(Manifest::generate(inpath, algo);
+                         "failed to generate a manifest of \"{}\"", inpath.display());
+
+    match matches.value_of_os("output") {
+        Some(outpath) if dry_run_requested(matches) => {
+            let mut text = serde_json::to_vec_pretty(&manifest)?;
+            text.push(b'\n');
+            let mut plan = ChangePlan::new();
+            plan.record(
+                Path::new(outpath).display().to_string(),
+                format!("write a manifest of \"{}\"", inpath.display()),
+                0,
+                Some(text.len() as u64),
+            );
+            plan.emit(OutputFormat::from_clap(matches), &mut io::stdout())?;
+        }
+
+        Some(outpath) => {
+            let mut dest = BufWriter::new(ctry!(File::create(outpath);
+                "failed to create \"{}\"", Path::new(outpath).display()));
+            serde_json::to_writer_pretty(&mut dest, &manifest)?;
+            writeln!(dest)?;
+        }
+
+        None => {
+            let stdout = io::stdout();
+            let mut dest = stdout.lock();
+            serde_json::to_writer_pretty(&mut dest, &manifest)?;
+            writeln!(dest)?;
+        }
+    }
+
+    Ok(0)
+}
+
+fn do_verify(matches: &ArgMatches, _nbe: &mut NotificationBackend) -> Result<i32, Error> {
+    let manpath = Path::new(matches.value_of_os("MANIFEST").unwrap());
+    let inpath = Path::new(matches.value_of_os("DATASET").unwrap());
+
+    let file = ctry!(File::open(manpath); "failed to open \"{}\"", manpath.display());
+    let manifest: Manifest = ctry!(serde_json::from_reader(BufReader::new(file));
+                                   "failed to parse the manifest \"{}\"", manpath.display());
+    let differences = ctry!(manifest.verify(inpath);
+                            "failed to check \"{}\"", inpath.display());
+
+    let report = Verification {
+        dataset: inpath.display().to_string(),
+        manifest: manpath.display().to_string(),
+        matches: differences.is_empty(),
+        differences: differences,
+    };
+
+    report.emit(OutputFormat::from_clap(matches), &mut io::stdout())?;
+    Ok(if report.matches { 0 } else { 1 })
+}
+
+fn main() {
+    let matches = App::new("rubbl-manifest")
+        .version("0.1.0")
+        .about("Record and check the contents of datasets")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .rubbl_notify_args()
+        .subcommand(
+            SubCommand::with_name("create")
+                .about("Print a JSON manifest of a dataset")
+                .rubbl_report_args()
+                .rubbl_dry_run_args()
+                .arg(
+                    Arg::with_name("hash")
+                        .long("hash")
+                        .value_name("ALGORITHM")
+                        .help("The algorithm used to hash columns (default: crc32)"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .value_name("PATH")
+                        .help("Write the manifest to PATH rather than standard output"),
+                )
+                .arg(
+                    Arg::with_name("DATASET")
+                        .help("The path of the dataset")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Check a dataset against a manifest")
+                .rubbl_report_args()
+                .arg(
+                    Arg::with_name("MANIFEST")
+                        .help("The path of the manifest")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("DATASET")
+                        .help("The path of the dataset")
+                        .required(true)
+                        .index(2),
+                ),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, nbe| -> Result<i32, Error> {
+            match matches.subcommand() {
+                ("create", Some(m)) => do_create(m, nbe),
+                ("verify", Some(m)) => do_verify(m, nbe),
+                _ => unreachable!(),
+            }
+        },
+    ));
+}
@@ -0,0 +1,93 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+//! Export the metadata subtables of a Measurement Set into an SQLite
+//! database.
+//!
+//! With `--dry-run`, the subtables are exported into a database in memory,
+//! so that any problems with them are found, and the tables that would have
+//! been created are reported rather than created.
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+extern crate rusqlite;
+
+use clap::{App, Arg};
+use rubbl_casatables::sqlite::{export_subtables, export_subtables_into};
+use rubbl_core::dryrun::{dry_run_requested, ChangePlan, ClapDryRunArgsExt};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use rusqlite::Connection;
+use std::io;
+use std::path::Path;
+use std::process;
+
+fn main() {
+    let matches = App::new("rubbl-mssqlite")
+        .version("0.1.0")
+        .about("Export the metadata subtables of a Measurement Set into an SQLite database")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .rubbl_dry_run_args()
+        .arg(
+            Arg::with_name("IN-TABLE")
+                .help("The path of the input Measurement Set")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("OUT-DB")
+                .help("The path of the SQLite database to create")
+                .required(true)
+                .index(2),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, nbe| -> Result<i32, Error> {
+            let inpath = Path::new(matches.value_of_os("IN-TABLE").unwrap());
+            let outpath = Path::new(matches.value_of_os("OUT-DB").unwrap());
+
+            if outpath.exists() {
+                return Err(failure::err_msg(format!(
+                    "refusing to overwrite existing file \"{}\"",
+                    outpath.display()
+                )));
+            }
+
+            if dry_run_requested(&matches) {
+                let mut conn = Connection::open_in_memory()?;
+                let exported = ctry!(export_subtables_into(inpath, &mut conn);
+                                     "failed to export subtables of \"{}\"", inpath.display());
+                let mut plan = ChangePlan::new();
+
+                for (name, n_rows) in exported {
+                    plan.record(
+                        outpath.display().to_string(),
+                        format!("create table {}", name),
+                        n_rows,
+                        None,
+                    );
+                }
+
+                plan.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+                return Ok(0);
+            }
+
+            let names = ctry!(export_subtables(inpath, outpath);
+                              "failed to export subtables of \"{}\" to \"{}\"",
+                              inpath.display(), outpath.display());
+
+            for name in &names {
+                rn_note!(nbe, "exported subtable {}", name);
+            }
+
+            Ok(0)
+        },
+    ));
+}
@@ -0,0 +1,132 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Summarize the contents of a Measurement Set.
+
+`rubbl msstat IN.ms` prints the number of rows, times, antennas, baselines,
+and data descriptions of a Measurement Set, the time span that it covers,
+and the statistics of the amplitudes of its unflagged visibilities. See the
+`rubbl_casatables::msstats` module for details.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+use clap::{App, Arg};
+use rubbl_casatables::msstats::MsStats;
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+#[derive(Debug, Serialize)]
+struct MsStatReport {
+    path: String,
+    column: String,
+    stats: MsStats,
+}
+
+impl Report for MsStatReport {
+    fn write_text(&self, dest: &mut Write) -> Result<(), Error> {
+        let s = &self.stats;
+
+        writeln!(dest, "{}:", self.path)?;
+        writeln!(dest, "    rows: {}", s.n_rows)?;
+        writeln!(dest, "    times: {}", s.n_times)?;
+
+        if let Some((start, end)) = s.time_range {
+            writeln!(
+                dest,
+                "    time span: {:.3} s ({:.3} to {:.3} MJD s)",
+                end - start,
+                start,
+                end
+            )?;
+        }
+
+        writeln!(dest, "    antennas: {}", s.antennas.len())?;
+        writeln!(dest, "    baselines: {}", s.n_baselines)?;
+        writeln!(dest, "    data descriptions: {}", s.data_desc_ids.len())?;
+        writeln!(
+            dest,
+            "    flagged: {:.1}% of {} samples",
+            100. * s.flags.fraction(),
+            s.flags.n_total
+        )?;
+
+        let a = &s.amplitudes;
+        writeln!(
+            dest,
+            "    {} amplitudes of {} unflagged samples:",
+            self.column, a.n_samples
+        )?;
+
+        if a.n_samples > 0 {
+            writeln!(dest, "        min: {:.6e}", a.min)?;
+            writeln!(dest, "        max: {:.6e}", a.max)?;
+            writeln!(dest, "        mean: {:.6e}", a.mean)?;
+            writeln!(dest, "        rms: {:.6e}", a.rms)?;
+        }
+
+        if s.n_nonfinite > 0 {
+            writeln!(
+                dest,
+                "    unflagged samples that are not finite: {}",
+                s.n_nonfinite
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn main() {
+    let matches = App::new("rubbl-msstat")
+        .version("0.1.0")
+        .about("Summarize the contents of a Measurement Set")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .arg(
+            Arg::with_name("column")
+                .long("column")
+                .value_name("NAME")
+                .help("The column from which to take the visibilities")
+                .default_value("DATA"),
+        )
+        .arg(
+            Arg::with_name("IN-MS")
+                .help("The path of the Measurement Set")
+                .required(true)
+                .index(1),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let inpath = Path::new(matches.value_of_os("IN-MS").unwrap());
+            let column = matches.value_of("column").unwrap();
+
+            let stats = ctry!(MsStats::gather(inpath, column);
+                              "failed to gather the statistics of \"{}\"", inpath.display());
+
+            let report = MsStatReport {
+                path: inpath.display().to_string(),
+                column: column.to_owned(),
+                stats: stats,
+            };
+
+            report.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+            Ok(0)
+        },
+    ));
+}
@@ -0,0 +1,121 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Run quality checks on a Measurement Set.
+
+`rubbl qa IN.ms` checks a Measurement Set for signs of trouble: no usable
+data, unflagged values that are not finite or have weights that are not
+positive, too much of the data flagged, or antennas that are entirely
+flagged. It prints the outcome of each check and exits with a nonzero
+status if any of them fail, so that it can gate a script or pipeline. See
+`MsStats::check` in the `rubbl_casatables::msstats` module for details.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+use clap::{App, Arg};
+use rubbl_casatables::msstats::{MsStats, QaCheck};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+#[derive(Debug, Serialize)]
+struct QaReport {
+    path: String,
+    passed: bool,
+    checks: Vec<QaCheck>,
+}
+
+impl Report for QaReport {
+    fn write_text(&self, dest: &mut Write) -> Result<(), Error> {
+        writeln!(
+            dest,
+            "{}: {}",
+            self.path,
+            if self.passed { "passed" } else { "FAILED" }
+        )?;
+
+        for check in &self.checks {
+            writeln!(
+                dest,
+                "    {:<4} {:<9} {}",
+                if check.passed { "ok" } else { "FAIL" },
+                check.name,
+                check.detail
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn main() {
+    let matches = App::new("rubbl-qa")
+        .version("0.1.0")
+        .about("Run quality checks on a Measurement Set")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .arg(
+            Arg::with_name("column")
+                .long("column")
+                .value_name("NAME")
+                .help("The column from which to take the visibilities")
+                .default_value("DATA"),
+        )
+        .arg(
+            Arg::with_name("max-flagged")
+                .long("max-flagged")
+                .value_name("FRACTION")
+                .help("The largest fraction of the data that may be flagged")
+                .default_value("0.5"),
+        )
+        .arg(
+            Arg::with_name("IN-MS")
+                .help("The path of the Measurement Set")
+                .required(true)
+                .index(1),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let inpath = Path::new(matches.value_of_os("IN-MS").unwrap());
+            let text = matches.value_of("max-flagged").unwrap();
+
+            let max_flagged = match text.parse::<f64>() {
+                Ok(f) if f >= 0. && f <= 1. => f,
+                _ => {
+                    return Err(failure::err_msg(format!(
+                        "the value of --max-flagged must be between 0 and 1; got \"{}\"",
+                        text
+                    )));
+                }
+            };
+
+            let stats = ctry!(MsStats::gather(inpath, matches.value_of("column").unwrap());
+                              "failed to gather the statistics of \"{}\"", inpath.display());
+            let checks = stats.check(max_flagged);
+
+            let report = QaReport {
+                path: inpath.display().to_string(),
+                passed: checks.iter().all(|c| c.passed),
+                checks: checks,
+            };
+
+            report.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+            Ok(if report.passed { 0 } else { 1 })
+        },
+    ));
+}
@@ -0,0 +1,127 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Copy the selected rows of a Measurement Set.
+
+`rubbl select IN.ms -o OUT.ms --antenna '!1&&&' --timerange T1~T2` writes a
+Measurement Set containing only the rows of IN.ms picked out by the
+standard `--antenna`, `--spw`, and `--timerange` options, with all of their
+columns and all of the subtables. With `--in-place`, the result replaces
+IN.ms instead. The rows are found with a
+`rubbl_casatables::planner::SelectionPlan`, so the bulky columns of rows
+that are not selected are never read.
+
+Only whole rows are selected: a `--spw` expression that picks out channels
+is rejected, since the channels of a Measurement Set cannot be trimmed
+without rewriting all of its per-channel columns.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+use clap::App;
+use rubbl_casatables::planner::SelectionPlan;
+use rubbl_casatables::{DeepCopyOptions, Table, TableOpenMode};
+use rubbl_core::dryrun::{dry_run_requested, ChangePlan, ClapDryRunArgsExt};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::output::{ClapOutputArgsExt, OutputPolicy};
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::select::{ClapSelectionArgsExt, Selection};
+use rubbl_core::Error;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+#[derive(Debug, Serialize)]
+struct SelectReport {
+    input: String,
+    output: String,
+    n_rows_in: u64,
+    n_rows_out: u64,
+}
+
+impl Report for SelectReport {
+    fn write_text(&self, dest: &mut Write) -> Result<(), Error> {
+        writeln!(dest, "\"{}\" -> \"{}\"", self.input, self.output)?;
+        writeln!(dest, "kept {} of {} rows", self.n_rows_out, self.n_rows_in)?;
+        Ok(())
+    }
+}
+
+fn main() {
+    let matches = App::new("rubbl-select")
+        .version("0.1.0")
+        .about("Copy the selected rows of a Measurement Set")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .rubbl_dry_run_args()
+        .rubbl_output_args()
+        .rubbl_selection_args()
+        .arg(
+            clap::Arg::with_name("IN-MS")
+                .help("The path of the input Measurement Set")
+                .required(true)
+                .index(1),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let inpath = Path::new(matches.value_of_os("IN-MS").unwrap());
+            let output = OutputPolicy::from_clap(&matches)?;
+            let outpath = match output {
+                OutputPolicy::InPlace => inpath,
+                OutputPolicy::NewTable(ref p) | OutputPolicy::Overwrite(ref p) => p.as_path(),
+            };
+            let selection = Selection::from_clap(&matches)?;
+
+            if selection.spws.iter().any(|t| !t.channels.is_empty()) {
+                return Err(failure::err_msg(
+                    "select keeps or drops whole rows, so --spw cannot select channels",
+                ));
+            }
+
+            let mut ms = ctry!(Table::open(inpath, TableOpenMode::Read);
+                               "failed to open \"{}\"", inpath.display());
+            let plan = SelectionPlan::new(&selection, &mut ms)?;
+            let mask = plan.row_mask(&mut ms)?;
+            let n_rows_out = mask.iter().filter(|&&m| m).count() as u64;
+
+            if dry_run_requested(&matches) {
+                let mut changes = ChangePlan::new();
+                changes.record(
+                    outpath.display().to_string(),
+                    format!("write the selected rows of \"{}\"", inpath.display()),
+                    n_rows_out,
+                    None,
+                );
+                changes.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+                return Ok(0);
+            }
+
+            let prepared = output.prepare_derived(inpath)?;
+            ctry!(ms.select_rows(&mask)?.deep_copy(prepared.path(), &DeepCopyOptions::default());
+                  "failed to write the selected rows to \"{}\"", outpath.display());
+            let report = SelectReport {
+                input: inpath.display().to_string(),
+                output: outpath.display().to_string(),
+                n_rows_in: ms.n_rows(),
+                n_rows_out: n_rows_out,
+            };
+
+            // The input must be closed before it can be replaced.
+            drop(ms);
+            prepared.commit()?;
+            report.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+            Ok(0)
+        },
+    ));
+}
@@ -0,0 +1,377 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Explore CASA tables interactively with a command prompt.
+
+Each invocation of a one-shot command like `rubbl tabledump` has to open
+its table anew, which for a large Measurement Set can take a while. This
+shell keeps one table open across commands, so that selections can be
+refined and their results previewed and exported quickly.
+
+Commands are read one per line from standard input; the prompt is written
+to standard error, so that the output of a script of commands piped in can
+be captured cleanly:
+
+- `open PATH`: open a table, closing any that is open
+- `info`: summarize the table and the current selection
+- `columns`: list the columns of the table
+- `select antenna|spw|time EXPR`: restrict the rows, using the same syntax
+  as the `--antenna`, `--spw`, and `--timerange` options of other commands;
+  each kind of selection replaces the previous one of that kind
+- `select clear`: remove the selection
+- `show [N] [COLUMNS]`: preview the first N selected rows (default 10)
+- `export PATH [COLUMNS]`: write the selected rows to a file, as TSV if its
+  name ends in `.tsv` and as CSV otherwise
+- `help`, `quit`
+
+COLUMNS is a comma-separated list of names; by default all scalar columns
+are used. Selections by spectral window only work on Measurement Sets.
+
+With `--dry-run`, `export` reports the file that it would write rather
+than writing it.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+
+use clap::{App, Arg};
+use failure::err_msg;
+use rubbl_casatables::planner::SelectionPlan;
+use rubbl_casatables::{DelimitedExportOptions, Delimiter, Table, TableOpenMode};
+use rubbl_core::dryrun::{dry_run_requested, ClapDryRunArgsExt};
+use rubbl_core::notify::{ClapNotificationArgsExt, NotificationBackend};
+use rubbl_core::select::Selection;
+use rubbl_core::Error;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Columns that are formatted as times, as in `rubbl tabledump`.
+const DEFAULT_TIME_COLUMNS: &[&str] = &["TIME", "TIME_CENTROID"];
+
+/// The number of rows shown by `show` if no number is given.
+const DEFAULT_SHOW_ROWS: u64 = 10;
+
+const HELP: &str = "\
+open PATH                    open a table
+info                         summarize the table and selection
+columns                      list the columns of the table
+select antenna|spw|time EXPR restrict the rows
+select clear                 remove the selection
+show [N] [COLUMNS]           preview the first N selected rows
+export PATH [COLUMNS]        write the selected rows as CSV or TSV
+help                         show this help
+quit                         leave the shell";
+
+/// A table open in the shell, along with the current selection of its rows.
+struct OpenTable {
+    path: PathBuf,
+    table: Table,
+    selection: Selection,
+
+    /// A reference table containing the selected rows, or `None` if the
+    /// selection is empty.
+    selected: Option<Table>,
+}
+
+impl OpenTable {
+    fn open(path: &Path) -> Result<Self, Error> {
+        let table = ctry!(Table::open(path, TableOpenMode::Read);
+                          "failed to open table \"{}\"", path.display());
+
+        Ok(OpenTable {
+            path: path.to_owned(),
+            table: table,
+            selection: Selection::new(),
+            selected: None,
+        })
+    }
+
+    /// Get the table holding the selected rows.
+    fn current(&mut self) -> &mut Table {
+        match self.selected {
+            Some(ref mut t) => t,
+            None => &mut self.table,
+        }
+    }
+
+    /// Recompute the selected rows after a change to the selection.
+    fn apply_selection(&mut self) -> Result<(), Error> {
+        self.selected = if self.selection == Selection::new() {
+            None
+        } else {
+            let plan = SelectionPlan::new(&self.selection, &mut self.table)?;
+            Some(plan.apply(&mut self.table)?)
+        };
+
+        Ok(())
+    }
+
+    /// Resolve a comma-separated list of column names, or all of the scalar
+    /// columns if there is none.
+    fn columns(&mut self, names: Option<&str>) -> Result<Vec<String>, Error> {
+        if let Some(names) = names {
+            return Ok(names.split(',').map(|s| s.trim().to_owned()).collect());
+        }
+
+        let mut scalars = Vec::new();
+
+        for n in self.table.column_names()? {
+            if self.table.get_col_desc(&n)?.is_scalar() {
+                scalars.push(n);
+            }
+        }
+
+        Ok(scalars)
+    }
+
+    fn export(
+        &mut self,
+        dest: &mut Write,
+        col_names: &[String],
+        n_rows: u64,
+        delimiter: Delimiter,
+    ) -> Result<u64, Error> {
+        let options = DelimitedExportOptions {
+            delimiter: delimiter,
+            header: true,
+            time_columns: col_names
+                .iter()
+                .filter(|n| DEFAULT_TIME_COLUMNS.contains(&n.as_str()))
+                .cloned()
+                .collect(),
+        };
+        let col_refs: Vec<&str> = col_names.iter().map(|s| s.as_str()).collect();
+        let t = self.current();
+        let n_rows = ::std::cmp::min(n_rows, t.n_rows());
+        t.export_delimited(dest, &col_refs, 0..n_rows, &options)
+    }
+}
+
+struct Shell {
+    table: Option<OpenTable>,
+    dry_run: bool,
+}
+
+impl Shell {
+    fn table(&mut self) -> Result<&mut OpenTable, Error> {
+        self.table
+            .as_mut()
+            .ok_or_else(|| err_msg("no table is open; use \"open PATH\""))
+    }
+
+    /// Run one command, returning false if the shell should exit.
+    fn run(&mut self, line: &str, out: &mut Write) -> Result<bool, Error> {
+        let line = line.trim();
+        let (command, rest) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        let args: Vec<&str> = rest.split_whitespace().collect();
+
+        match command {
+            "" => {}
+
+            "quit" | "exit" => return Ok(false),
+
+            "help" => writeln!(out, "{}", HELP)?,
+
+            "open" => {
+                if rest.is_empty() {
+                    return Err(err_msg("usage: open PATH"));
+                }
+
+                // Close the old table first, in case it is the same one.
+                self.table = None;
+                let t = OpenTable::open(Path::new(rest))?;
+                writeln!(
+                    out,
+                    "{}: {} rows, {} columns",
+                    t.path.display(),
+                    t.table.n_rows(),
+                    t.table.n_columns()
+                )?;
+                self.table = Some(t);
+            }
+
+            "info" => {
+                let t = self.table()?;
+                let info = t.table.info()?;
+                writeln!(out, "path: {}", t.path.display())?;
+
+                if !info.table_type.is_empty() {
+                    writeln!(out, "type: {}", info.table_type)?;
+                }
+
+                writeln!(out, "rows: {}", t.table.n_rows())?;
+                writeln!(out, "columns: {}", t.table.n_columns())?;
+
+                match t.selected {
+                    Some(ref s) => writeln!(out, "selected rows: {}", s.n_rows())?,
+                    None => writeln!(out, "selected rows: all")?,
+                }
+            }
+
+            "columns" => {
+                let t = self.table()?;
+
+                for n in t.table.column_names()? {
+                    let desc = t.table.get_col_desc(&n)?;
+
+                    match (desc.is_scalar(), desc.shape()) {
+                        (true, _) => writeln!(out, "{:24} {}", n, desc.data_type())?,
+                        (false, Some(shape)) => {
+                            writeln!(out, "{:24} {} {:?}", n, desc.data_type(), shape)?
+                        }
+                        (false, None) => writeln!(out, "{:24} {} [variable]", n, desc.data_type())?,
+                    }
+                }
+            }
+
+            "select" => {
+                let t = self.table()?;
+                let kind = args.first().cloned().unwrap_or("");
+                let expr = rest[kind.len()..].trim();
+
+                match kind {
+                    "clear" => t.selection = Selection::new(),
+                    "antenna" => {
+                        t.selection.set_antenna(expr)?;
+                    }
+                    "spw" => {
+                        t.selection.set_spw(expr)?;
+                    }
+                    "time" | "timerange" => {
+                        t.selection.set_timerange(expr)?;
+                    }
+                    _ => {
+                        return Err(err_msg(
+                            "usage: select antenna|spw|time EXPR, or select clear",
+                        ))
+                    }
+                }
+
+                if let Err(e) = t.apply_selection() {
+                    t.selection = Selection::new();
+                    t.selected = None;
+                    return Err(e);
+                }
+
+                writeln!(out, "{} rows selected", t.current().n_rows())?;
+            }
+
+            "show" => {
+                let t = self.table()?;
+                let (n_rows, cols) = match args.first().map(|a| a.parse::<u64>()) {
+                    Some(Ok(n)) => (n, args.get(1).cloned()),
+                    _ => (DEFAULT_SHOW_ROWS, args.first().cloned()),
+                };
+                let cols = t.columns(cols)?;
+                t.export(out, &cols, n_rows, Delimiter::Tab)?;
+            }
+
+            "export" => {
+                let dry_run = self.dry_run;
+                let t = self.table()?;
+                let path = match args.first() {
+                    Some(p) => Path::new(p),
+                    None => return Err(err_msg("usage: export PATH [COLUMNS]")),
+                };
+                let cols = t.columns(args.get(1).cloned())?;
+                let delimiter = if path.extension().and_then(|e| e.to_str()) == Some("tsv") {
+                    Delimiter::Tab
+                } else {
+                    Delimiter::Comma
+                };
+
+                if dry_run {
+                    let n = t.export(&mut io::sink(), &cols, u64::MAX, delimiter)?;
+                    writeln!(out, "dry run: would write {} rows to {}", n, path.display())?;
+                    return Ok(true);
+                }
+
+                let mut dest = io::BufWriter::new(ctry!(File::create(path);
+                                                        "failed to create \"{}\"", path.display()));
+                let n = t.export(&mut dest, &cols, u64::MAX, delimiter)?;
+                dest.flush()?;
+                writeln!(out, "wrote {} rows to {}", n, path.display())?;
+            }
+
+            other => {
+                return Err(err_msg(format!(
+                    "unknown command \"{}\"; type \"help\" for a list",
+                    other
+                )))
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+fn main() {
+    let matches = App::new("rubbl-shell")
+        .version("0.1.0")
+        .about("Explore CASA tables interactively with a command prompt")
+        .rubbl_notify_args()
+        .rubbl_dry_run_args()
+        .arg(
+            Arg::with_name("IN-TABLE")
+                .help("The path of a table to open at startup")
+                .index(1),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, nbe| -> Result<i32, Error> {
+            let mut shell = Shell {
+                table: None,
+                dry_run: dry_run_requested(&matches),
+            };
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            let stdin = io::stdin();
+            let mut lines = stdin.lock().lines();
+
+            if let Some(path) = matches.value_of("IN-TABLE") {
+                shell.run(&format!("open {}", path), &mut out)?;
+            }
+
+            loop {
+                eprint!("rubbl> ");
+
+                let line = match lines.next() {
+                    Some(l) => l?,
+                    None => {
+                        eprintln!();
+                        break;
+                    }
+                };
+
+                match shell.run(&line, &mut out) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => report(nbe, &e),
+                }
+
+                out.flush()?;
+            }
+
+            Ok(0)
+        },
+    ));
+}
+
+fn report(nbe: &mut NotificationBackend, e: &Error) {
+    rn_warning!(nbe, "{}", e);
+
+    for cause in e.iter_causes() {
+        rn_warning!(nbe, "  caused by: {}", cause);
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Show how the disk space of a CASA table is divided between its columns.
+
+`rubbl tabledu TABLE` lists the columns of a table with the number of bytes
+that each occupies on disk, largest first, followed by the totals for each
+data manager and for each subtable. Figures for columns that share a data
+manager with others are estimates, and are marked with `~`. See the
+`rubbl_casatables::diskusage` module for details.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+
+use clap::{App, Arg};
+use rubbl_casatables::diskusage::DiskUsage;
+use rubbl_casatables::{Table, TableOpenMode};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+#[derive(Debug, Serialize)]
+struct TableDiskUsage {
+    path: String,
+    usage: DiskUsage,
+}
+
+/// Format a number of bytes in binary units, as `du -h` does.
+fn format_bytes(n: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if n < 1024 {
+        return format!("{} B", n);
+    }
+
+    let mut value = n as f64 / 1024.;
+    let mut unit = 0;
+
+    while value >= 1024. && unit + 1 < UNITS.len() {
+        value /= 1024.;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.
+    } else {
+        100. * part as f64 / total as f64
+    }
+}
+
+impl Report for TableDiskUsage {
+    fn write_text(&self, dest: &mut Write) -> Result<(), Error> {
+        let u = &self.usage;
+
+        writeln!(
+            dest,
+            "\"{}\": {} in the table's own files",
+            self.path,
+            format_bytes(u.total_bytes)
+        )?;
+        writeln!(dest)?;
+        writeln!(
+            dest,
+            "{:<24}  {:>11}  {:>6}  data manager",
+            "column", "size", "share"
+        )?;
+
+        for c in &u.columns {
+            writeln!(
+                dest,
+                "{:<24}  {:>1}{:>10}  {:>5.1}%  {}",
+                c.name,
+                if c.estimated { "~" } else { "" },
+                format_bytes(c.bytes),
+                percent(c.bytes, u.total_bytes),
+                c.data_manager
+            )?;
+        }
+
+        writeln!(dest)?;
+        writeln!(
+            dest,
+            "{:<24}  {:>11}  {:>6}  type",
+            "data manager", "size", "share"
+        )?;
+
+        for m in &u.data_managers {
+            writeln!(
+                dest,
+                "{:<24}  {:>11}  {:>5.1}%  {}",
+                m.name,
+                format_bytes(m.bytes),
+                percent(m.bytes, u.total_bytes),
+                m.dm_type
+            )?;
+        }
+
+        writeln!(
+            dest,
+            "{:<24}  {:>11}  {:>5.1}%",
+            "(other files)",
+            format_bytes(u.overhead_bytes),
+            percent(u.overhead_bytes, u.total_bytes)
+        )?;
+
+        if !u.subtables.is_empty() {
+            writeln!(dest)?;
+            writeln!(dest, "{:<24}  {:>11}", "subtable", "size")?;
+
+            for s in &u.subtables {
+                writeln!(dest, "{:<24}  {:>11}", s.name, format_bytes(s.bytes))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn main() {
+    let matches = App::new("rubbl-tabledu")
+        .version("0.1.0")
+        .about("Show how the disk space of a CASA table is divided between its columns")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .arg(
+            Arg::with_name("IN-TABLE")
+                .help("The path of the input table")
+                .required(true)
+                .index(1),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let inpath = Path::new(matches.value_of_os("IN-TABLE").unwrap());
+            let mut t = ctry!(Table::open(inpath, TableOpenMode::Read);
+                              "failed to open input table \"{}\"", inpath.display());
+            let usage = ctry!(t.disk_usage();
+                              "failed to measure the disk usage of \"{}\"", inpath.display());
+
+            let report = TableDiskUsage {
+                path: inpath.display().to_string(),
+                usage: usage,
+            };
+
+            report.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+            Ok(0)
+        },
+    ));
+}
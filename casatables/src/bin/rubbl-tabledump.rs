@@ -0,0 +1,157 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+//! Export scalar columns of a CASA table as CSV or TSV text.
+
+extern crate clap;
+extern crate failure;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+
+use clap::{App, Arg};
+use failure::err_msg;
+use rubbl_casatables::{DelimitedExportOptions, Delimiter, Table, TableOpenMode};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::Error;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::process;
+
+/// Columns that are formatted as times if the user does not say otherwise.
+/// These are the Measurement Set conventions.
+const DEFAULT_TIME_COLUMNS: &[&str] = &["TIME", "TIME_CENTROID"];
+
+/// Parse a row range of the form `START:END`, where either bound may be
+/// omitted, or a single row number.
+fn parse_rows(text: &str, n_rows: u64) -> Result<Range<u64>, Error> {
+    let bad = || err_msg(format!("cannot parse \"{}\" as a row range", text));
+    let parse_bound = |s: &str, default: u64| -> Result<u64, Error> {
+        let s = s.trim();
+
+        if s.is_empty() {
+            Ok(default)
+        } else {
+            s.parse().map_err(|_| bad())
+        }
+    };
+
+    match text.find(':') {
+        Some(idx) => Ok(parse_bound(&text[..idx], 0)?..parse_bound(&text[idx + 1..], n_rows)?),
+        None => {
+            let row = parse_bound(text, 0)?;
+            Ok(row..row + 1)
+        }
+    }
+}
+
+fn main() {
+    let matches = App::new("rubbl-tabledump")
+        .version("0.1.0")
+        .about("Export scalar columns of a CASA table as CSV or TSV text")
+        .rubbl_notify_args()
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .value_name("NAMES")
+                .help(
+                    "Comma-separated names of the columns to export (default: all scalar columns)",
+                )
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::with_name("rows")
+                .long("rows")
+                .value_name("START:END")
+                .help("The range of rows to export; either bound may be omitted"),
+        )
+        .arg(
+            Arg::with_name("time")
+                .long("time")
+                .value_name("NAME")
+                .help(
+                    "A column of MJD seconds to format as ISO 8601 times; may be repeated \
+                     (default: TIME and TIME_CENTROID)",
+                )
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("tsv")
+                .long("tsv")
+                .help("Separate fields with tabs rather than commas"),
+        )
+        .arg(
+            Arg::with_name("no_header")
+                .long("no-header")
+                .help("Do not print a line of column names"),
+        )
+        .arg(
+            Arg::with_name("IN-TABLE")
+                .help("The path of the input table")
+                .required(true)
+                .index(1),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let inpath = Path::new(matches.value_of_os("IN-TABLE").unwrap()).to_owned();
+
+            let mut t = ctry!(Table::open(&inpath, TableOpenMode::Read);
+                              "failed to open input table \"{}\"", inpath.display());
+
+            let col_names = match matches.values_of("columns") {
+                Some(names) => names.map(|s| s.to_owned()).collect(),
+                None => {
+                    let mut names = Vec::new();
+
+                    for n in ctry!(t.column_names();
+                                   "failed to get names of columns in \"{}\"", inpath.display())
+                    {
+                        if t.get_col_desc(&n)?.is_scalar() {
+                            names.push(n);
+                        }
+                    }
+
+                    names
+                }
+            };
+
+            let time_columns = match matches.values_of("time") {
+                Some(names) => names.map(|s| s.to_owned()).collect(),
+                None => col_names
+                    .iter()
+                    .filter(|n| DEFAULT_TIME_COLUMNS.contains(&n.as_str()))
+                    .cloned()
+                    .collect(),
+            };
+
+            let rows = match matches.value_of("rows") {
+                Some(text) => parse_rows(text, t.n_rows())?,
+                None => 0..t.n_rows(),
+            };
+
+            let options = DelimitedExportOptions {
+                delimiter: if matches.is_present("tsv") {
+                    Delimiter::Tab
+                } else {
+                    Delimiter::Comma
+                },
+                header: !matches.is_present("no_header"),
+                time_columns: time_columns,
+            };
+
+            let col_refs: Vec<&str> = col_names.iter().map(|s| s.as_str()).collect();
+            let stdout = io::stdout();
+            let mut dest = io::BufWriter::new(stdout.lock());
+
+            ctry!(t.export_delimited(&mut dest, &col_refs, rows, &options);
+                  "failed to export data from \"{}\"", inpath.display());
+            dest.flush()?;
+            Ok(0)
+        },
+    ));
+}
@@ -0,0 +1,237 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Create a CASA table from a schema and CSV or TSV data.
+
+The schema is a TOML file listing the columns of the new table, in order:
+
+```toml
+[[column]]
+name = "NAME"
+type = "string"
+
+[[column]]
+name = "POSITION_X"
+type = "f64"
+units = "m"
+
+[[column]]
+name = "TIME"
+type = "f64"
+time = true  # values are ISO 8601 times, stored as MJD seconds
+```
+
+Time columns are tagged as UTC epochs in seconds, so that casacore's
+measures system understands them, and columns with `units` get the
+corresponding `QuantumUnits` keyword.
+
+Only scalar column types are supported. The type names are the ones printed
+by the `tableinfo` example: `bool`, `i8`, `u8`, `i16`, `u16`, `i32`, `u32`,
+`i64`, `f32`, `f64`, `c32`, `c64`, and `string`.
+
+With `--dry-run`, the data are imported into a scratch table in the
+temporary directory, so that any problems with them are found, and the
+table that would have been created is reported rather than created.
+
+*/
+
+extern crate clap;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+
+use clap::{App, Arg};
+use rubbl_casatables::{
+    ColumnMeasure, CreateOptions, DelimitedImportOptions, Delimiter, GlueDataType, Table,
+    TableStorage,
+};
+use rubbl_core::dryrun::{dry_run_requested, ChangePlan, ClapDryRunArgsExt};
+use rubbl_core::io::{ClapIoPolicyArgsExt, IoPolicy};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::process;
+
+#[derive(Debug, Deserialize)]
+struct Schema {
+    column: Vec<ColumnSchema>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ColumnSchema {
+    name: String,
+
+    #[serde(rename = "type")]
+    data_type: String,
+
+    #[serde(default)]
+    time: bool,
+
+    #[serde(default)]
+    units: Option<String>,
+}
+
+/// Create the table at *outpath* and fill it in, returning the number of
+/// rows imported.
+fn import(
+    outpath: &Path,
+    schema: &Schema,
+    columns: &[(&str, GlueDataType)],
+    data: &str,
+    options: &DelimitedImportOptions,
+    create_options: &CreateOptions,
+) -> Result<u64, Error> {
+    let mut t = ctry!(Table::create_with_scalar_columns_and_options(
+                          outpath, columns, 0, create_options);
+                      "failed to create table \"{}\"", outpath.display());
+    let n_rows = ctry!(t.import_delimited(data, options);
+                       "failed to import data into \"{}\"", outpath.display());
+
+    for col in &schema.column {
+        let measure = if col.time {
+            ColumnMeasure::epoch("UTC")
+        } else if let Some(ref units) = col.units {
+            ColumnMeasure::quantity(&[units])
+        } else {
+            continue;
+        };
+
+        ctry!(t.set_column_measure(&col.name, &measure);
+              "failed to set the units of column \"{}\"", col.name);
+    }
+
+    Ok(n_rows)
+}
+
+fn main() {
+    let matches = App::new("rubbl-tableimport")
+        .version("0.1.0")
+        .about("Create a CASA table from a schema and CSV or TSV data")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .rubbl_dry_run_args()
+        .rubbl_io_policy_args()
+        .arg(
+            Arg::with_name("tsv")
+                .long("tsv")
+                .help("The data are separated by tabs rather than commas"),
+        )
+        .arg(
+            Arg::with_name("no_header")
+                .long("no-header")
+                .help("The data do not start with a line of column names"),
+        )
+        .arg(
+            Arg::with_name("multifile")
+                .long("multifile")
+                .help("Store the table's data in a single MultiFile container"),
+        )
+        .arg(
+            Arg::with_name("SCHEMA")
+                .help("The path of the TOML file describing the table columns")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("DATA")
+                .help("The path of the CSV or TSV data, or \"-\" for standard input")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("OUT-TABLE")
+                .help("The path of the table to create")
+                .required(true)
+                .index(3),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let schema_path = Path::new(matches.value_of_os("SCHEMA").unwrap());
+            let data_path = Path::new(matches.value_of_os("DATA").unwrap());
+            let outpath = Path::new(matches.value_of_os("OUT-TABLE").unwrap());
+            let io_policy = IoPolicy::from_clap(&matches)?;
+
+            let schema_text = ctry!(fs::read_to_string(schema_path);
+                                    "failed to read schema file \"{}\"", schema_path.display());
+            let schema: Schema = ctry!(toml::from_str(&schema_text);
+                                       "failed to parse schema file \"{}\"", schema_path.display());
+
+            let mut columns = Vec::new();
+            let mut time_columns = Vec::new();
+
+            for col in &schema.column {
+                let data_type = ctry!(col.data_type.parse::<GlueDataType>();
+                                      "bad type for column \"{}\"", col.name);
+                columns.push((col.name.as_str(), data_type));
+
+                if col.time {
+                    time_columns.push(col.name.clone());
+                }
+            }
+
+            let mut data = String::new();
+
+            if data_path == Path::new("-") {
+                ctry!(io::stdin().read_to_string(&mut data); "failed to read standard input");
+            } else {
+                let file = ctry!(File::open(data_path);
+                                 "failed to open data file \"{}\"", data_path.display());
+                ctry!(io_policy.reader(file).read_to_string(&mut data);
+                      "failed to read data file \"{}\"", data_path.display());
+            }
+
+            let options = DelimitedImportOptions {
+                delimiter: if matches.is_present("tsv") {
+                    Delimiter::Tab
+                } else {
+                    Delimiter::Comma
+                },
+                header: !matches.is_present("no_header"),
+                time_columns: time_columns,
+            };
+
+            let create_options = if matches.is_present("multifile") {
+                CreateOptions::multifile(TableStorage::DEFAULT_BLOCK_SIZE)
+            } else {
+                CreateOptions::default()
+            };
+
+            if !dry_run_requested(&matches) {
+                import(outpath, &schema, &columns, &data, &options, &create_options)?;
+                return Ok(0);
+            }
+
+            let scratch = env::temp_dir().join(format!("rubbl-tableimport-{}", process::id()));
+            let result = import(
+                &scratch,
+                &schema,
+                &columns,
+                &data,
+                &options,
+                &create_options,
+            );
+            let _ = fs::remove_dir_all(&scratch);
+
+            let mut plan = ChangePlan::new();
+            plan.record(
+                outpath.display().to_string(),
+                format!("create a table with {} columns", columns.len()),
+                result?,
+                None,
+            );
+            plan.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+            Ok(0)
+        },
+    ));
+}
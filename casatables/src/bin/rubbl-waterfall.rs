@@ -0,0 +1,453 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Render quick-look waterfall plots of visibility data.
+
+`rubbl waterfall DATASET OUTDIR` reads the visibilities of a Measurement Set
+(or, with the `miriad` feature, a MIRIAD UV data set) and writes a PNG image
+for each baseline, polarization, and spectral window to OUTDIR, showing
+the amplitude or phase of the data as a function of channel (across) and
+time (down). Flagged data are drawn in white. The standard `--antenna`,
+`--spw`, and `--timerange` options select which data are plotted.
+
+All of the selected data are held in memory, so large data sets should be
+plotted a piece at a time.
+
+With `--dry-run`, the data are read as usual, but the images that would be
+written are reported rather than written.
+
+*/
+
+extern crate clap;
+extern crate failure;
+extern crate ndarray;
+extern crate rubbl_casatables;
+#[macro_use]
+extern crate rubbl_core;
+#[cfg(feature = "miriad")]
+extern crate rubbl_miriad;
+extern crate rubbl_visdata;
+
+use clap::{App, Arg, ArgMatches};
+use failure::err_msg;
+use ndarray::Array2;
+use rubbl_casatables::{mms, Table, TableOpenMode};
+use rubbl_core::colormap::Colormap;
+use rubbl_core::dryrun::{dry_run_requested, ChangePlan, ClapDryRunArgsExt};
+use rubbl_core::notify::{ClapNotificationArgsExt, NotificationBackend};
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::select::{ClapSelectionArgsExt, Selection};
+use rubbl_core::{Complex, Error};
+use rubbl_visdata::waterfall::{Waterfall, WaterfallQuantity};
+use rubbl_visdata::{BasePol, VisPol};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::process;
+
+const ALL_VISPOLS: &[VisPol] = &[
+    VisPol::XX,
+    VisPol::XY,
+    VisPol::YX,
+    VisPol::YY,
+    VisPol::RR,
+    VisPol::RL,
+    VisPol::LR,
+    VisPol::LL,
+    VisPol::I,
+    VisPol::Q,
+    VisPol::U,
+    VisPol::V,
+];
+
+/// The waterfalls being accumulated, keyed by spectral window and basepol.
+type Waterfalls = BTreeMap<(usize, BasePol), Waterfall>;
+
+/// The settings that decide which data go into the waterfalls.
+struct Filter {
+    selection: Selection,
+    pols: Option<Vec<VisPol>>,
+}
+
+impl Filter {
+    fn from_clap(matches: &ArgMatches) -> Result<Self, Error> {
+        let pols = match matches.value_of("pol") {
+            None => None,
+            Some(text) => {
+                let mut pols = Vec::new();
+
+                for name in text.split(',') {
+                    let name = name.trim().to_uppercase();
+
+                    match ALL_VISPOLS.iter().find(|p| format!("{:?}", p) == name) {
+                        Some(p) => pols.push(*p),
+                        None => {
+                            return Err(err_msg(format!("unrecognized polarization \"{}\"", name)))
+                        }
+                    }
+                }
+
+                Some(pols)
+            }
+        };
+
+        Ok(Filter {
+            selection: Selection::from_clap(matches)?,
+            pols: pols,
+        })
+    }
+
+    fn matches_pol(&self, pol: VisPol) -> bool {
+        match self.pols {
+            None => true,
+            Some(ref pols) => pols.contains(&pol),
+        }
+    }
+
+    /// Add one spectrum to the appropriate waterfall, dropping the channels
+    /// that are not selected.
+    fn accumulate(
+        &self,
+        waterfalls: &mut Waterfalls,
+        spw: usize,
+        basepol: BasePol,
+        time: f64,
+        data: &[Complex<f32>],
+        flags: &[bool],
+    ) -> Result<(), Error> {
+        let mask = match self.selection.channel_mask(spw, data.len()) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+
+        let data: Vec<Complex<f32>> = data
+            .iter()
+            .zip(&mask)
+            .filter(|&(_, &m)| m)
+            .map(|(d, _)| *d)
+            .collect();
+        let flags: Vec<bool> = flags
+            .iter()
+            .zip(&mask)
+            .filter(|&(_, &m)| m)
+            .map(|(f, _)| *f)
+            .collect();
+
+        waterfalls
+            .entry((spw, basepol))
+            .or_insert_with(|| Waterfall::new(data.len()))
+            .push(time, &data, &flags)
+    }
+}
+
+/// Read the selected data of the Measurement Set at *path*.
+fn read_ms(path: &Path, filter: &Filter, waterfalls: &mut Waterfalls) -> Result<(), Error> {
+    let mut dd_table = Table::open(path.join("DATA_DESCRIPTION"), TableOpenMode::Read)?;
+    let dd_spws = dd_table.get_col_as_vec::<i32>("SPECTRAL_WINDOW_ID")?;
+    let dd_pol_ids = dd_table.get_col_as_vec::<i32>("POLARIZATION_ID")?;
+
+    let mut pol_table = Table::open(path.join("POLARIZATION"), TableOpenMode::Read)?;
+    let mut pol_setups = Vec::new();
+
+    for row in 0..pol_table.n_rows() {
+        let mut pols = Vec::new();
+
+        for code in pol_table.get_cell_as_vec::<i32>("CORR_TYPE", row)? {
+            pols.push(mms::stokes_to_vispol(code)?);
+        }
+
+        pol_setups.push(pols);
+    }
+
+    let mut ms = Table::open(path, TableOpenMode::Read)?;
+    let ant1 = ms.get_col_as_vec::<i32>("ANTENNA1")?;
+    let ant2 = ms.get_col_as_vec::<i32>("ANTENNA2")?;
+    let times = ms.get_col_as_vec::<f64>("TIME")?;
+    let ddids = ms.get_col_as_vec::<i32>("DATA_DESC_ID")?;
+    let row_flags = ms.get_col_as_vec::<bool>("FLAG_ROW")?;
+
+    for row in 0..ms.n_rows() {
+        let i = row as usize;
+        let ddid = ddids[i] as usize;
+
+        if ddid >= dd_spws.len() || dd_pol_ids[ddid] as usize >= pol_setups.len() {
+            return Err(err_msg(format!(
+                "row {} has an invalid DATA_DESC_ID {}",
+                row, ddid
+            )));
+        }
+
+        let spw = dd_spws[ddid] as usize;
+
+        if !filter
+            .selection
+            .matches_baseline(ant1[i] as usize, ant2[i] as usize)
+            || !filter.selection.matches_time(times[i])
+            || !filter.selection.matches_spw(spw)
+        {
+            continue;
+        }
+
+        let data: Array2<Complex<f32>> = ms.get_cell("DATA", row)?;
+        let mut flags: Array2<bool> = ms.get_cell("FLAG", row)?;
+
+        if row_flags[i] {
+            flags.fill(true);
+        }
+
+        for (corr, &pol) in pol_setups[dd_pol_ids[ddid] as usize].iter().enumerate() {
+            if !filter.matches_pol(pol) {
+                continue;
+            }
+
+            let spectrum: Vec<Complex<f32>> = data.column(corr).to_vec();
+            let spec_flags: Vec<bool> = flags.column(corr).to_vec();
+            let basepol = BasePol::new(ant1[i] as u16, ant2[i] as u16, pol);
+            filter.accumulate(waterfalls, spw, basepol, times[i], &spectrum, &spec_flags)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert a MIRIAD polarization code to a `VisPol`.
+#[cfg(feature = "miriad")]
+fn miriad_pol_to_vispol(code: i32) -> Result<VisPol, Error> {
+    Ok(match code {
+        1 => VisPol::I,
+        2 => VisPol::Q,
+        3 => VisPol::U,
+        4 => VisPol::V,
+        -1 => VisPol::RR,
+        -2 => VisPol::LL,
+        -3 => VisPol::RL,
+        -4 => VisPol::LR,
+        -5 => VisPol::XX,
+        -6 => VisPol::YY,
+        -7 => VisPol::XY,
+        -8 => VisPol::YX,
+        other => {
+            return Err(err_msg(format!(
+                "unsupported MIRIAD polarization code {}",
+                other
+            )));
+        }
+    })
+}
+
+/// Read the selected data of the MIRIAD UV data set at *path*. MIRIAD has
+/// no notion of separate spectral windows here, so all of the channels are
+/// treated as window 0.
+#[cfg(feature = "miriad")]
+fn read_miriad(path: &Path, filter: &Filter, waterfalls: &mut Waterfalls) -> Result<(), Error> {
+    use rubbl_miriad::mask::MaskDecoder;
+    use rubbl_miriad::visdata::decode_baseline;
+    use rubbl_miriad::DataSet;
+
+    let mut ds = DataSet::open(path)?;
+    let mut uv = ds.open_uv()?;
+    let mut mask = MaskDecoder::new(
+        ds.get("flags")?
+            .ok_or_else(|| err_msg("no \"flags\" item in the data set"))?
+            .into_byte_stream()?,
+    );
+
+    let mut corr = Vec::new();
+    let mut good = Vec::new();
+
+    while uv.next()? {
+        let lookup = |name: &str| {
+            uv.lookup_variable(name)
+                .ok_or_else(|| err_msg(format!("no \"{}\" UV variable", name)))
+        };
+        let time_var = lookup("time")?;
+        let baseline_var = lookup("baseline")?;
+        let pol_var = lookup("pol")?;
+        let corr_var = lookup("corr")?;
+
+        uv.get_data(corr_var, &mut corr);
+        good.resize(corr.len(), false);
+        mask.expand(&mut good)?;
+
+        // JD to MJD seconds.
+        let time = (uv.get_scalar::<f64>(time_var) - 2_400_000.5) * 86400.;
+        let (ant1, ant2) = decode_baseline(uv.get_scalar::<f32>(baseline_var))?;
+        let pol = miriad_pol_to_vispol(uv.get_scalar::<i32>(pol_var))?;
+
+        if !filter.selection.matches_baseline(ant1, ant2)
+            || !filter.selection.matches_time(time)
+            || !filter.selection.matches_spw(0)
+            || !filter.matches_pol(pol)
+        {
+            continue;
+        }
+
+        // In MIRIAD masks, set bits mark good data.
+        let flags: Vec<bool> = good.iter().map(|g| !g).collect();
+        let basepol = BasePol::new(ant1 as u16, ant2 as u16, pol);
+        filter.accumulate(waterfalls, 0, basepol, time, &corr, &flags)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "miriad"))]
+fn read_miriad(path: &Path, _filter: &Filter, _waterfalls: &mut Waterfalls) -> Result<(), Error> {
+    Err(err_msg(format!(
+        "\"{}\" looks like a MIRIAD data set, but this program was built without MIRIAD support",
+        path.display()
+    )))
+}
+
+fn do_waterfall(matches: &ArgMatches, nbe: &mut NotificationBackend) -> Result<i32, Error> {
+    let inpath = Path::new(matches.value_of_os("DATASET").unwrap());
+    let outdir = Path::new(matches.value_of_os("OUTDIR").unwrap());
+    let filter = Filter::from_clap(matches)?;
+    let dry_run = dry_run_requested(matches);
+
+    let quantities = match matches.value_of("quantity").unwrap_or("amp") {
+        "amp" => vec![WaterfallQuantity::Amplitude],
+        "phase" => vec![WaterfallQuantity::Phase],
+        _ => vec![WaterfallQuantity::Amplitude, WaterfallQuantity::Phase],
+    };
+
+    let colormap = match matches.value_of("colormap") {
+        None => None,
+        Some(name) => Some(
+            Colormap::from_name(name)
+                .ok_or_else(|| err_msg(format!("unrecognized colormap \"{}\"", name)))?,
+        ),
+    };
+
+    let zoom = match matches.value_of("zoom") {
+        None => 1,
+        Some(text) => match text.parse::<usize>() {
+            Ok(z) if z > 0 => z,
+            _ => return Err(err_msg(format!("invalid zoom factor \"{}\"", text))),
+        },
+    };
+
+    let mut waterfalls = Waterfalls::new();
+
+    if inpath.join("visdata").exists() {
+        ctry!(read_miriad(inpath, &filter, &mut waterfalls);
+              "failed to read \"{}\"", inpath.display());
+    } else {
+        ctry!(read_ms(inpath, &filter, &mut waterfalls);
+              "failed to read \"{}\"", inpath.display());
+    }
+
+    if waterfalls.is_empty() {
+        return Err(err_msg("no data were selected"));
+    }
+
+    let mut plan = ChangePlan::new();
+
+    if !dry_run {
+        ctry!(fs::create_dir_all(outdir); "failed to create \"{}\"", outdir.display());
+    }
+
+    for (&(spw, basepol), waterfall) in &waterfalls {
+        for &quantity in &quantities {
+            let cmap = colormap.unwrap_or(match quantity {
+                WaterfallQuantity::Amplitude => Colormap::Viridis,
+                WaterfallQuantity::Phase => Colormap::Twilight,
+            });
+
+            let path = outdir.join(format!(
+                "{}-{}_{:?}_spw{}_{}.png",
+                basepol.ant1,
+                basepol.ant2,
+                basepol.pol,
+                spw,
+                quantity.name()
+            ));
+
+            if dry_run {
+                plan.record(
+                    path.display().to_string(),
+                    match quantity {
+                        WaterfallQuantity::Amplitude => "write a waterfall of the amplitudes",
+                        WaterfallQuantity::Phase => "write a waterfall of the phases",
+                    },
+                    waterfall.n_times() as u64,
+                    None,
+                );
+                continue;
+            }
+
+            let image = waterfall.render(quantity, cmap).scaled(zoom);
+            let file = ctry!(File::create(&path); "failed to create \"{}\"", path.display());
+            ctry!(image.write_png(BufWriter::new(file));
+                  "failed to write \"{}\"", path.display());
+        }
+    }
+
+    if dry_run {
+        plan.emit(OutputFormat::from_clap(matches), &mut io::stdout())?;
+        return Ok(0);
+    }
+
+    rn_note!(
+        nbe,
+        "wrote {} images to \"{}\"",
+        waterfalls.len() * quantities.len(),
+        outdir.display()
+    );
+    Ok(0)
+}
+
+fn main() {
+    let matches = App::new("rubbl-waterfall")
+        .version("0.1.0")
+        .about("Render quick-look waterfall plots of visibility data")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .rubbl_dry_run_args()
+        .rubbl_selection_args()
+        .arg(
+            Arg::with_name("pol")
+                .long("pol")
+                .value_name("POLS")
+                .help("Comma-separated polarizations to plot, e.g. \"XX,YY\" (default: all)"),
+        )
+        .arg(
+            Arg::with_name("quantity")
+                .long("quantity")
+                .value_name("QUANTITY")
+                .possible_values(&["amp", "phase", "both"])
+                .help("What to plot (default: amp)"),
+        )
+        .arg(
+            Arg::with_name("colormap")
+                .long("colormap")
+                .value_name("NAME")
+                .possible_values(&["gray", "viridis", "twilight"])
+                .help("The colormap (default: viridis for amplitudes, twilight for phases)"),
+        )
+        .arg(
+            Arg::with_name("zoom")
+                .long("zoom")
+                .value_name("FACTOR")
+                .help("Draw each sample as a FACTOR×FACTOR block of pixels (default: 1)"),
+        )
+        .arg(
+            Arg::with_name("DATASET")
+                .help("The path of the Measurement Set or MIRIAD data set")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("OUTDIR")
+                .help("The directory in which to write the images")
+                .required(true)
+                .index(2),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, nbe| do_waterfall(&matches, nbe),
+    ));
+}
@@ -0,0 +1,261 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Reading and writing boolean columns as packed bitmaps.
+
+Flags make up a large fraction of the I/O of flagging programs: a
+Measurement Set's `FLAG` column has a boolean for every visibility, and
+casacore stores each one in a byte in memory. `Table::get_col_range_as_bitmap`
+and `Table::put_col_range_from_bitmap` move ranges of boolean columns in and
+out of a `Bitmap`, which uses a bit per value. The packing is done by the C++
+glue a byte at a time, so the values never pass through an intermediate
+vector of `bool`s on the Rust side.
+
+*/
+
+use failure::{err_msg, Error};
+
+use super::{glue, GlueDataType, Table, UnexpectedDataTypeError};
+
+/// A packed sequence of booleans.
+///
+/// Value *i* is stored in bit `i % 8` of byte `i / 8`, so that the layout
+/// matches the usual least-significant-bit-first convention. Unused bits in
+/// the last byte are always zero.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Bitmap {
+    len: usize,
+    bytes: Vec<u8>,
+}
+
+impl Bitmap {
+    /// Create a bitmap of *len* values, all false.
+    pub fn new(len: usize) -> Self {
+        Bitmap {
+            len: len,
+            bytes: vec![0; len.div_ceil(8)],
+        }
+    }
+
+    /// Create a bitmap holding the values of *values*.
+    pub fn from_bools(values: &[bool]) -> Self {
+        let mut bytes = Vec::with_capacity(values.len().div_ceil(8));
+
+        for chunk in values.chunks(8) {
+            bytes.push(
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |b, (j, &v)| b | ((v as u8) << j)),
+            );
+        }
+
+        Bitmap {
+            len: values.len(),
+            bytes: bytes,
+        }
+    }
+
+    /// Get the number of values in the bitmap.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Get whether the bitmap holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get value *i*. Panics if *i* is out of bounds.
+    pub fn get(&self, i: usize) -> bool {
+        assert!(i < self.len, "bitmap index {} out of bounds", i);
+        self.bytes[i / 8] & (1 << (i % 8)) != 0
+    }
+
+    /// Set value *i*. Panics if *i* is out of bounds.
+    pub fn set(&mut self, i: usize, value: bool) {
+        assert!(i < self.len, "bitmap index {} out of bounds", i);
+
+        if value {
+            self.bytes[i / 8] |= 1 << (i % 8);
+        } else {
+            self.bytes[i / 8] &= !(1 << (i % 8));
+        }
+    }
+
+    /// Count the values that are true.
+    pub fn count_ones(&self) -> usize {
+        self.bytes.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    /// Get the packed bytes of the bitmap.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Unpack the bitmap into a vector of booleans.
+    pub fn to_bools(&self) -> Vec<bool> {
+        (0..self.len).map(|i| self.get(i)).collect()
+    }
+}
+
+impl Table {
+    /// Read the cells of a range of rows of a boolean column into a bitmap.
+    ///
+    /// This is like `get_col_range_as_vec::<bool>`, and has the same
+    /// restrictions, but uses an eighth of the memory.
+    pub fn get_col_range_as_bitmap(
+        &mut self,
+        col_name: &str,
+        start_row: u64,
+        n_rows: u64,
+    ) -> Result<Bitmap, Error> {
+        self.flush_writes()?;
+        let cell_items = self.bool_cell_items(col_name)?;
+
+        if start_row + n_rows > self.n_rows() {
+            return Err(err_msg(format!(
+                "cannot read rows {}-{} of a table with only {} rows",
+                start_row,
+                start_row + n_rows,
+                self.n_rows()
+            )));
+        }
+
+        let mut bitmap = Bitmap::new(cell_items * n_rows as usize);
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+
+        let rv = unsafe {
+            glue_call!(table_get_bool_range_bits(
+                self.handle,
+                &ccol_name,
+                start_row,
+                n_rows,
+                bitmap.bytes.as_mut_ptr(),
+                &mut self.exc_info,
+            ); table = self.path, column = col_name, rows = start_row..start_row + n_rows)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(bitmap)
+    }
+
+    /// Write a bitmap into the cells of consecutive rows of a boolean
+    /// column, starting at row *start_row*.
+    ///
+    /// The column must be a scalar column or an array column with a fixed
+    /// shape, and the length of *bitmap* must be a multiple of the number of
+    /// values in each cell. The rows must already exist.
+    pub fn put_col_range_from_bitmap(
+        &mut self,
+        col_name: &str,
+        start_row: u64,
+        bitmap: &Bitmap,
+    ) -> Result<(), Error> {
+        self.flush_writes()?;
+        let cell_items = self.bool_cell_items(col_name)?;
+
+        if cell_items == 0 || !bitmap.len().is_multiple_of(cell_items) {
+            return Err(err_msg(format!(
+                "a bitmap of {} values does not fill whole cells of the column \"{}\", \
+                 which have {} values each",
+                bitmap.len(),
+                col_name,
+                cell_items
+            )));
+        }
+
+        let n_rows = (bitmap.len() / cell_items) as u64;
+
+        if start_row + n_rows > self.n_rows() {
+            return Err(err_msg(format!(
+                "cannot write rows {}-{} of a table with only {} rows",
+                start_row,
+                start_row + n_rows,
+                self.n_rows()
+            )));
+        }
+
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                self.path.display().to_string(),
+                format!(
+                    "write column \"{}\" in rows {}-{}",
+                    col_name,
+                    start_row,
+                    start_row + n_rows
+                ),
+                n_rows,
+                Some(bitmap.len() as u64),
+            );
+            return Ok(());
+        }
+
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+
+        let rv = unsafe {
+            glue_call!(table_put_bool_range_bits(
+                self.handle,
+                &ccol_name,
+                start_row,
+                n_rows,
+                bitmap.bytes.as_ptr(),
+                &mut self.exc_info,
+            ); table = self.path, column = col_name, rows = start_row..start_row + n_rows)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(())
+    }
+
+    /// Get the number of values in each cell of the boolean column
+    /// *col_name*, checking that it can be read and written in ranges.
+    fn bool_cell_items(&mut self, col_name: &str) -> Result<usize, Error> {
+        let desc = self.get_col_desc(col_name)?;
+
+        if desc.data_type() != GlueDataType::TpBool {
+            return Err(UnexpectedDataTypeError(GlueDataType::TpBool, desc.data_type()).into());
+        }
+
+        if desc.is_scalar() {
+            return Ok(1);
+        }
+
+        match desc.shape() {
+            Some(shape) if desc.is_fixed_shape() => {
+                Ok(shape.iter().fold(1usize, |p, n| p * (*n as usize)))
+            }
+            _ => Err(err_msg(format!(
+                "cannot access a range of rows of the variable-shape column \"{}\"",
+                col_name
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn packing() {
+    let values: Vec<bool> = (0..19).map(|i| i % 3 == 0).collect();
+    let mut bitmap = Bitmap::from_bools(&values);
+    assert_eq!(bitmap.len(), 19);
+    assert_eq!(bitmap.as_bytes(), &[0b0100_1001, 0b1001_0010, 0b0000_0100]);
+    assert_eq!(bitmap.count_ones(), 7);
+    assert_eq!(bitmap.to_bools(), values);
+
+    bitmap.set(17, true);
+    bitmap.set(0, false);
+    assert!(bitmap.get(17) && !bitmap.get(0));
+    assert_eq!(bitmap.count_ones(), 7);
+
+    assert_eq!(Bitmap::new(9).as_bytes(), &[0, 0]);
+    assert!(Bitmap::from_bools(&[]).is_empty());
+}
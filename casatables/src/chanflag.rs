@@ -0,0 +1,374 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Automatic flagging of band edges and persistently bad channels.
+
+The autocorrelation spectrum of an antenna, averaged over time, traces the
+bandpass of its receiver. Channels where the bandpass rolls off at the edges
+of a spectral window, and channels affected by persistent interference or
+correlator artifacts, stand out from it clearly, so they can be found
+cheaply before any calibration is done. `flag_channels` averages the
+autocorrelations of a Measurement Set for each data description, finds the
+bad channels with a `ChannelFlagger`, and flags them in every row.
+
+A channel is considered bad if:
+
+- its value is not finite (for instance, because it has no unflagged data);
+- it deviates from a running median of the spectrum by more than a
+  threshold number of robust standard deviations (1.4826 times the median
+  absolute deviation of the whole spectrum from the running median); or
+- it is part of a run of channels at either end of the spectrum that are
+  bad or fall below a fraction of the median level of the spectrum.
+
+*/
+
+use failure::{err_msg, Error};
+use ndarray::Array2;
+use rubbl_core::output::OutputPolicy;
+use rubbl_core::Complex;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+
+use super::{Table, TableOpenMode};
+
+/// Finds the bad channels of a spectrum.
+#[derive(Clone, Debug)]
+pub struct ChannelFlagger {
+    threshold: f64,
+    window: usize,
+    edge_level: f64,
+}
+
+impl Default for ChannelFlagger {
+    fn default() -> Self {
+        ChannelFlagger {
+            threshold: 5.,
+            window: 15,
+            edge_level: 0.5,
+        }
+    }
+}
+
+impl ChannelFlagger {
+    /// Create a flagger with the default settings: a threshold of 5 robust
+    /// standard deviations, a running median over 15 channels, and an edge
+    /// level of half the median level of the spectrum.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of robust standard deviations from the running median
+    /// beyond which a channel is bad.
+    pub fn set_threshold(&mut self, threshold: f64) -> &mut Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the number of channels in the running median. Even numbers are
+    /// rounded up to the next odd one.
+    pub fn set_window(&mut self, window: usize) -> &mut Self {
+        self.window = window.max(1) | 1;
+        self
+    }
+
+    /// Set the fraction of the median level of the spectrum below which
+    /// channels at its ends are band edges.
+    pub fn set_edge_level(&mut self, edge_level: f64) -> &mut Self {
+        self.edge_level = edge_level;
+        self
+    }
+
+    /// Find the bad channels of *spectrum*, returning a mask that is true
+    /// for each bad channel.
+    pub fn find_bad_channels(&self, spectrum: &[f64]) -> Vec<bool> {
+        let n = spectrum.len();
+        let half = self.window / 2;
+        let mut window = Vec::with_capacity(self.window);
+
+        let smooth: Vec<f64> = (0..n)
+            .map(|i| {
+                window.clear();
+                window.extend(
+                    spectrum[i.saturating_sub(half)..(i + half + 1).min(n)]
+                        .iter()
+                        .filter(|v| v.is_finite()),
+                );
+                median(&mut window)
+            })
+            .collect();
+
+        let mut deviations: Vec<f64> = spectrum
+            .iter()
+            .zip(&smooth)
+            .map(|(v, s)| (v - s).abs())
+            .filter(|d| d.is_finite())
+            .collect();
+        let sigma = 1.4826 * median(&mut deviations);
+
+        let mut levels: Vec<f64> = smooth.iter().cloned().filter(|s| s.is_finite()).collect();
+        let level = median(&mut levels);
+
+        let mut bad: Vec<bool> = spectrum
+            .iter()
+            .zip(&smooth)
+            .map(|(v, s)| !(v.is_finite() && (v - s).abs() <= self.threshold * sigma))
+            .collect();
+
+        let is_edge = |i: usize| bad[i] || spectrum[i] < self.edge_level * level;
+        let first_good = (0..n).find(|&i| !is_edge(i)).unwrap_or(n);
+        let last_good = (0..n)
+            .rev()
+            .find(|&i| !is_edge(i))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+
+        for (i, b) in bad.iter_mut().enumerate() {
+            if i < first_good || i >= last_good {
+                *b = true;
+            }
+        }
+
+        bad
+    }
+}
+
+/// The channels flagged in one data description.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChannelFlags {
+    /// The data description ID.
+    pub ddid: i32,
+
+    /// The ranges of flagged channels, in increasing order.
+    pub ranges: Vec<Range<usize>>,
+}
+
+impl ChannelFlags {
+    /// Describe the channels flagged by *mask* in data description *ddid*.
+    pub fn from_mask(ddid: i32, mask: &[bool]) -> Self {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+
+        for (i, &bad) in mask.iter().enumerate() {
+            if !bad {
+                continue;
+            }
+
+            match ranges.last_mut() {
+                Some(ref mut r) if r.end == i => r.end = i + 1,
+                _ => ranges.push(i..i + 1),
+            }
+        }
+
+        ChannelFlags {
+            ddid: ddid,
+            ranges: ranges,
+        }
+    }
+}
+
+impl fmt::Display for ChannelFlags {
+    /// Formats the flags in the style of a CASA channel selection, e.g.
+    /// `0:0~7;60~63`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.ddid)?;
+
+        for (i, r) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                write!(f, ";")?;
+            }
+
+            write!(f, "{}~{}", r.start, r.end - 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Find and flag the bad channels of the Measurement Set at *input*, with
+/// the result going where *output* says.
+///
+/// The time-averaged spectrum of each data description is the mean of the
+/// real parts of the unflagged parallel-hand autocorrelations in the `DATA`
+/// column. Data descriptions without autocorrelations are left alone. The
+/// channels that *flagger* finds to be bad are flagged for every
+/// correlation of every row with that data description; the flags are
+/// returned for data descriptions with any bad channels.
+pub fn flag_channels<P: AsRef<Path>>(
+    input: P,
+    output: &OutputPolicy,
+    flagger: &ChannelFlagger,
+) -> Result<Vec<ChannelFlags>, Error> {
+    Table::modify(input, output, |ms| {
+        let (result, masks) = find_table_channel_flags(ms, flagger)?;
+        apply_channel_flags(ms, &masks)?;
+        Ok(result)
+    })
+}
+
+/// Find the bad channels of the Measurement Set at *input* as
+/// `flag_channels` does, without flagging them.
+pub fn find_channel_flags<P: AsRef<Path>>(
+    input: P,
+    flagger: &ChannelFlagger,
+) -> Result<Vec<ChannelFlags>, Error> {
+    let mut ms = Table::open(input, TableOpenMode::Read)?;
+    let (result, _) = find_table_channel_flags(&mut ms, flagger)?;
+    Ok(result)
+}
+
+/// Find the bad channels of each data description, returning their
+/// descriptions and their masks.
+fn find_table_channel_flags(
+    ms: &mut Table,
+    flagger: &ChannelFlagger,
+) -> Result<(Vec<ChannelFlags>, BTreeMap<i32, Vec<bool>>), Error> {
+    let ant1 = ms.get_col_as_vec::<i32>("ANTENNA1")?;
+    let ant2 = ms.get_col_as_vec::<i32>("ANTENNA2")?;
+    let ddids = ms.get_col_as_vec::<i32>("DATA_DESC_ID")?;
+    let row_flags = ms.get_col_as_vec::<bool>("FLAG_ROW")?;
+
+    // For each data description, the sum and count of the samples in each
+    // channel.
+    let mut sums: BTreeMap<i32, (Vec<f64>, Vec<usize>)> = BTreeMap::new();
+
+    for row in 0..ddids.len() {
+        if ant1[row] != ant2[row] || row_flags[row] {
+            continue;
+        }
+
+        let shape = ms.get_cell_shape("DATA", row as u64)?;
+        let data = ms.get_cell_as_vec::<Complex<f32>>("DATA", row as u64)?;
+        let flags = ms.get_cell_as_vec::<bool>("FLAG", row as u64)?;
+        let n_chan = shape[0] as usize;
+
+        if data.is_empty() {
+            continue;
+        }
+
+        let n_corr = data.len() / n_chan;
+        let corrs = if n_corr > 1 {
+            vec![0, n_corr - 1]
+        } else {
+            vec![0]
+        };
+
+        let entry = sums
+            .entry(ddids[row])
+            .or_insert_with(|| (vec![0.; n_chan], vec![0; n_chan]));
+
+        if entry.0.len() != n_chan {
+            return Err(err_msg(format!(
+                "autocorrelations with DATA_DESC_ID {} have varying numbers of channels",
+                ddids[row]
+            )));
+        }
+
+        for chan in 0..n_chan {
+            for &corr in &corrs {
+                let i = chan * n_corr + corr;
+
+                if !flags[i] {
+                    entry.0[chan] += data[i].re as f64;
+                    entry.1[chan] += 1;
+                }
+            }
+        }
+    }
+
+    let mut masks = BTreeMap::new();
+    let mut result = Vec::new();
+
+    for (ddid, (sum, count)) in sums {
+        let spectrum: Vec<f64> = sum
+            .iter()
+            .zip(&count)
+            .map(|(&s, &c)| if c > 0 { s / c as f64 } else { std::f64::NAN })
+            .collect();
+        let mask = flagger.find_bad_channels(&spectrum);
+
+        if mask.iter().any(|&b| b) {
+            result.push(ChannelFlags::from_mask(ddid, &mask));
+            masks.insert(ddid, mask);
+        }
+    }
+
+    Ok((result, masks))
+}
+
+/// Flag the channels given by *masks*, indexed by data description, in
+/// every row.
+fn apply_channel_flags(ms: &mut Table, masks: &BTreeMap<i32, Vec<bool>>) -> Result<(), Error> {
+    let ddids = ms.get_col_as_vec::<i32>("DATA_DESC_ID")?;
+
+    for (row, ddid) in ddids.iter().enumerate() {
+        let mask = match masks.get(ddid) {
+            Some(m) => m,
+            None => continue,
+        };
+
+        let mut flags: Array2<bool> = ms.get_cell("FLAG", row as u64)?;
+
+        if flags.shape()[0] != mask.len() {
+            return Err(err_msg(format!(
+                "row {} has {} channels, but the autocorrelations of its data description have {}",
+                row,
+                flags.shape()[0],
+                mask.len()
+            )));
+        }
+
+        for (mut chan_flags, &bad) in flags.outer_iter_mut().zip(mask) {
+            if bad {
+                chan_flags.fill(true);
+            }
+        }
+
+        ms.put_cell("FLAG", row as u64, &flags)?;
+    }
+
+    Ok(())
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    if values.is_empty() {
+        return std::f64::NAN;
+    }
+
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = values.len();
+
+    if n % 2 == 1 {
+        values[n / 2]
+    } else {
+        0.5 * (values[n / 2 - 1] + values[n / 2])
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn channel_flagging() {
+    // A bandpass that rolls off over the first and last four channels, with
+    // a little ripple, a spike in channel 20, and a dead channel 30.
+    let mut spectrum: Vec<f64> = (0..64)
+        .map(|i| {
+            let edge = (i.min(63 - i) as f64 / 4.).min(1.);
+            100. * edge * edge + (i % 3) as f64 * 0.1
+        })
+        .collect();
+    spectrum[20] = 150.;
+    spectrum[30] = std::f64::NAN;
+
+    let mask = ChannelFlagger::new().find_bad_channels(&spectrum);
+    let flags = ChannelFlags::from_mask(2, &mask);
+    assert_eq!(flags.ranges, vec![0..4, 20..21, 30..31, 60..64]);
+    assert_eq!(flags.to_string(), "2:0~3;20~20;30~30;60~63");
+
+    assert!(ChannelFlagger::new().find_bad_channels(&[]).is_empty());
+    assert_eq!(
+        ChannelFlagger::new().find_bad_channels(&[std::f64::NAN; 3]),
+        vec![true; 3]
+    );
+}
@@ -0,0 +1,339 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Breaking down the disk space used by a table.
+
+The bulk of a large table is usually in a few columns: in a Measurement Set,
+`DATA`, `FLAG`, and their relatives typically dwarf everything else. Knowing
+which columns dominate helps to decide what to compress, re-tile, or drop.
+`Table::disk_usage` works this out from the table's files.
+
+casacore keeps the data of each data manager in files named after its
+sequence number: `table.f0`, `table.f0i`, `table.f1_TSM0`, and so on. The
+sizes of these files are added up for each data manager. A data manager
+that stores a single column, as tiled storage managers usually do, gives the
+space used by that column exactly. The space of one that stores several
+columns is shared between them in proportion to the sizes of their cells
+(see `Table::column_width`), and the resulting figures are marked as
+estimates. Files that belong to no data manager, such as `table.dat`, are
+counted as overhead, and subtables are reported as a whole.
+
+The same attribution underlies `Table::data_file_paths`, which lists the
+files that hold a column, so that tools outside casacore, such as parallel
+copiers or staging systems that bring files back from tape, can work on
+just those files.
+
+*/
+
+use failure::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{NoSuchColumnError, Table};
+
+/// The disk space used by a table, as returned by `Table::disk_usage`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct DiskUsage {
+    /// The total size of the table's own files, in bytes. This does not
+    /// include its subtables.
+    pub total_bytes: u64,
+
+    /// The size of the files that belong to no data manager, such as
+    /// `table.dat` and `table.lock`.
+    pub overhead_bytes: u64,
+
+    /// The space used by each data manager, largest first.
+    pub data_managers: Vec<DataManagerUsage>,
+
+    /// The space used by each column, largest first.
+    pub columns: Vec<ColumnUsage>,
+
+    /// The subtables found in the table's directory, with the total size of
+    /// each, largest first.
+    pub subtables: Vec<SubtableUsage>,
+}
+
+/// The disk space used by one data manager.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct DataManagerUsage {
+    /// The type of the data manager, such as `StandardStMan`.
+    pub dm_type: String,
+
+    /// The name of the data manager.
+    pub name: String,
+
+    /// The sequence number of the data manager.
+    pub seq_nr: u32,
+
+    /// The names of the columns that it stores.
+    pub columns: Vec<String>,
+
+    /// The total size of its files, in bytes.
+    pub bytes: u64,
+
+    /// The names of its files, in order.
+    pub files: Vec<String>,
+}
+
+/// The disk space used by one column.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ColumnUsage {
+    /// The name of the column.
+    pub name: String,
+
+    /// The name of the data manager that stores it.
+    pub data_manager: String,
+
+    /// The number of bytes used by the column.
+    pub bytes: u64,
+
+    /// Whether *bytes* is an estimate, because the column shares its data
+    /// manager with others.
+    pub estimated: bool,
+}
+
+/// The disk space used by a subtable.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct SubtableUsage {
+    /// The name of the subtable's directory.
+    pub name: String,
+
+    /// The total size of the files in the directory, in bytes.
+    pub bytes: u64,
+}
+
+/// A file that holds the data of a column, as returned by
+/// `Table::data_file_paths`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct DataFile {
+    /// The path of the file.
+    pub path: PathBuf,
+
+    /// The size of the file, in bytes.
+    pub size: u64,
+
+    /// The range of bytes of the file that hold the column's data, as a
+    /// start offset and a length, if this can be determined. It can when
+    /// the column has its data manager to itself, in which case it is the
+    /// whole file. When the data manager stores several columns, their data
+    /// are interleaved in ways that only the data manager knows, so this is
+    /// `None`, and the whole file must be treated as needed.
+    pub extent: Option<(u64, u64)>,
+}
+
+impl Table {
+    /// List the files in the table's directory that hold the data of the
+    /// column *col_name*, sorted by name.
+    ///
+    /// This is an escape hatch for tools that move or stage the files
+    /// themselves. The files are those of the data manager that stores the
+    /// column, found by name as described in the `diskusage` module. The
+    /// list is empty for columns whose data manager has no files of its
+    /// own, such as virtual columns. casacore may rewrite the files at any
+    /// time while the table is open for writing, so they should only be
+    /// copied while no process has it open for writing.
+    pub fn data_file_paths(&mut self, col_name: &str) -> Result<Vec<DataFile>, Error> {
+        self.flush_writes()?;
+
+        let info = self
+            .data_manager_info()?
+            .into_iter()
+            .find(|info| info.columns().iter().any(|c| c == col_name))
+            .ok_or_else(|| NoSuchColumnError(col_name.to_owned()))?;
+        let shared = info.columns().len() > 1;
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if data_manager_file_seq(&name) != Some(info.seq_nr()) {
+                continue;
+            }
+
+            let meta = entry.metadata()?;
+
+            if meta.is_dir() {
+                continue;
+            }
+
+            files.push(DataFile {
+                path: entry.path(),
+                size: meta.len(),
+                extent: if shared { None } else { Some((0, meta.len())) },
+            });
+        }
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+
+    /// Work out how the disk space used by this table is divided between
+    /// its columns and data managers. See the `diskusage` module for how
+    /// this is done.
+    pub fn disk_usage(&mut self) -> Result<DiskUsage, Error> {
+        self.flush_writes()?;
+
+        let mut managers: Vec<DataManagerUsage> = self
+            .data_manager_info()?
+            .into_iter()
+            .map(|info| DataManagerUsage {
+                dm_type: info.dm_type().to_owned(),
+                name: info.name().to_owned(),
+                seq_nr: info.seq_nr(),
+                columns: info.columns().to_vec(),
+                bytes: 0,
+                files: Vec::new(),
+            })
+            .collect();
+
+        let mut usage = DiskUsage {
+            total_bytes: 0,
+            overhead_bytes: 0,
+            data_managers: Vec::new(),
+            columns: Vec::new(),
+            subtables: Vec::new(),
+        };
+
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if meta.is_dir() {
+                usage.subtables.push(SubtableUsage {
+                    bytes: tree_size(&entry.path())?,
+                    name: name,
+                });
+                continue;
+            }
+
+            usage.total_bytes += meta.len();
+
+            let manager = data_manager_file_seq(&name)
+                .and_then(|seq| managers.iter_mut().find(|m| m.seq_nr == seq));
+
+            match manager {
+                Some(m) => {
+                    m.bytes += meta.len();
+                    m.files.push(name);
+                }
+                None => usage.overhead_bytes += meta.len(),
+            }
+        }
+
+        for m in &mut managers {
+            m.files.sort();
+            let estimated = m.columns.len() > 1;
+            let mut weights = Vec::with_capacity(m.columns.len());
+
+            for c in &m.columns {
+                weights.push(if estimated { self.column_width(c)? } else { 1 });
+            }
+
+            for (c, bytes) in m.columns.iter().zip(apportion(m.bytes, &weights)) {
+                usage.columns.push(ColumnUsage {
+                    name: c.clone(),
+                    data_manager: m.name.clone(),
+                    bytes: bytes,
+                    estimated: estimated,
+                });
+            }
+        }
+
+        managers.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.seq_nr.cmp(&b.seq_nr)));
+        usage.data_managers = managers;
+        usage
+            .columns
+            .sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+        usage
+            .subtables
+            .sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+        Ok(usage)
+    }
+}
+
+/// Get the sequence number of the data manager that a file in a table
+/// directory belongs to, going by its name, or `None` if it does not belong
+/// to one.
+fn data_manager_file_seq(name: &str) -> Option<u32> {
+    if !name.starts_with("table.f") {
+        return None;
+    }
+
+    let rest = &name[7..];
+    let n_digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+
+    if n_digits == 0 {
+        return None;
+    }
+
+    match &rest[n_digits..] {
+        "" | "i" => {}
+        s if s.starts_with('_') => {}
+        _ => return None,
+    }
+
+    rest[..n_digits].parse().ok()
+}
+
+/// Divide *total* into parts proportional to *weights*, so that the parts
+/// add up to *total*. If the weights are all zero, the parts are equal.
+fn apportion(total: u64, weights: &[u64]) -> Vec<u64> {
+    if weights.is_empty() {
+        return Vec::new();
+    }
+
+    let sum: u64 = weights.iter().sum();
+    let mut parts: Vec<u64> = if sum == 0 {
+        vec![total / weights.len() as u64; weights.len()]
+    } else {
+        weights
+            .iter()
+            .map(|&w| (u128::from(total) * u128::from(w) / u128::from(sum)) as u64)
+            .collect()
+    };
+
+    let assigned: u64 = parts.iter().sum();
+    *parts.last_mut().unwrap() += total - assigned;
+    parts
+}
+
+/// Get the total size of the files in the directory tree at *path*.
+fn tree_size(path: &Path) -> Result<u64, Error> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+
+        if meta.is_dir() {
+            size += tree_size(&entry.path())?;
+        } else {
+            size += meta.len();
+        }
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+#[test]
+fn file_attribution() {
+    assert_eq!(data_manager_file_seq("table.f0"), Some(0));
+    assert_eq!(data_manager_file_seq("table.f12i"), Some(12));
+    assert_eq!(data_manager_file_seq("table.f3_TSM1"), Some(3));
+    assert_eq!(data_manager_file_seq("table.dat"), None);
+    assert_eq!(data_manager_file_seq("table.f"), None);
+    assert_eq!(data_manager_file_seq("table.f1x"), None);
+    assert_eq!(data_manager_file_seq("table.lock"), None);
+
+    assert_eq!(apportion(10, &[1, 1, 1]), vec![3, 3, 4]);
+    assert_eq!(apportion(100, &[8, 0, 2]), vec![80, 0, 20]);
+    assert_eq!(apportion(7, &[0, 0]), vec![3, 4]);
+    assert!(apportion(5, &[]).is_empty());
+}
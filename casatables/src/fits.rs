@@ -0,0 +1,279 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Exporting table columns into FITS binary tables.
+
+`Table::export_bintable` writes a selection of columns of a table to a new
+FITS file, as a `BINTABLE` extension with one row per table row, so that
+they can be read by tools that know nothing about CASA tables. The column
+types are mapped as follows:
+
+- Booleans become logical (`L`) columns.
+- `UChar`, `Short`, `Int`, and `Int64` columns become `B`, `I`, `J`, and
+  `K` columns. FITS has no signed bytes or unsigned wider integers, so
+  `Char`, `UShort`, and `UInt` are widened to `I`, `J`, and `K`
+  respectively.
+- `Float` and `Double` become `E` and `D`; `Complex` and `DComplex` become
+  `C` and `M`.
+- Scalar strings become character (`A`) columns as wide as the longest
+  value, padded with spaces.
+- Array columns must have a fixed shape. Each cell becomes a vector of its
+  elements with a `TDIMn` keyword giving the shape in FITS order, first axis
+  varying fastest, which is the same order as casacore's.
+
+If the units of a column are recorded in its `QuantumUnits` keyword, and
+they are the same for all of its components, they become its `TUNITn`
+keyword. Scaling keywords and null values are not written.
+
+This module is only available if the `fits` feature of this crate is
+enabled.
+
+*/
+
+use failure::{err_msg, Error};
+use rubbl_core::Complex;
+use rubbl_fits::bintable::{BinaryTableWriter, ColumnFormat};
+use std::cmp;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::{glue, CasaScalarData, Table, EXPORT_BATCH_ROWS};
+
+/// A column being exported, with its FITS format.
+struct ExportColumn {
+    name: String,
+    data_type: glue::GlueDataType,
+    format: ColumnFormat,
+    offset: usize,
+    width: usize,
+}
+
+impl Table {
+    /// Write the columns *col_names* of this table to a new FITS file at
+    /// *path*, as a binary table.
+    ///
+    /// All of the rows of the table are exported; to export only some of
+    /// them, select them into a reference table first. See the `fits` module
+    /// for how column types are mapped. Returns the number of rows written.
+    pub fn export_bintable<P: AsRef<Path>>(
+        &mut self,
+        col_names: &[&str],
+        path: P,
+    ) -> Result<u64, Error> {
+        self.flush_writes()?;
+        let path = path.as_ref();
+        let n_rows = self.n_rows();
+        let mut columns = Vec::with_capacity(col_names.len());
+        let mut offset = 0;
+
+        for name in col_names {
+            let format = self.bintable_format(name)?;
+            let width = format.width()?;
+
+            columns.push(ExportColumn {
+                name: (*name).to_owned(),
+                data_type: self.get_col_desc(name)?.data_type(),
+                format: format,
+                offset: offset,
+                width: width,
+            });
+
+            offset += width;
+        }
+
+        let formats: Vec<ColumnFormat> = columns.iter().map(|c| c.format.clone()).collect();
+        let dest = BufWriter::new(File::create(path)?);
+        let mut writer = BinaryTableWriter::new(dest, &formats, n_rows as usize, &[])?;
+        let row_size = writer.row_size();
+        let mut row = 0;
+
+        while row < n_rows {
+            let n = cmp::min(EXPORT_BATCH_ROWS, n_rows - row);
+            let mut data = vec![0u8; n as usize * row_size];
+
+            for col in &columns {
+                let cells = self.read_bintable_cells(col, row, n)?;
+
+                for (i, cell) in cells.chunks(col.width).enumerate() {
+                    let start = i * row_size + col.offset;
+                    data[start..start + col.width].copy_from_slice(cell);
+                }
+            }
+
+            writer.write_rows(&data)?;
+            row += n;
+        }
+
+        writer.finish()?.flush()?;
+        Ok(n_rows)
+    }
+
+    /// Work out the FITS format of the column *col_name*.
+    fn bintable_format(&mut self, col_name: &str) -> Result<ColumnFormat, Error> {
+        use glue::GlueDataType::*;
+
+        let desc = self.get_col_desc(col_name)?;
+        let data_type = desc.data_type();
+
+        let code = match data_type {
+            TpBool => 'L',
+            TpUChar => 'B',
+            TpChar | TpShort => 'I',
+            TpUShort | TpInt => 'J',
+            TpUInt | TpInt64 => 'K',
+            TpFloat => 'E',
+            TpDouble => 'D',
+            TpComplex => 'C',
+            TpDComplex => 'M',
+            TpString if desc.is_scalar() => 'A',
+            other => {
+                return Err(err_msg(format!(
+                    "cannot export column \"{}\" of type {} to FITS",
+                    col_name, other
+                )));
+            }
+        };
+
+        let mut format = ColumnFormat::new(col_name, code, 1);
+
+        if code == 'A' {
+            format.repeat = cmp::max(self.max_string_len(col_name)?, 1);
+        } else if !desc.is_scalar() {
+            let shape = match desc.shape() {
+                Some(s) => s,
+                None => {
+                    return Err(err_msg(format!(
+                        "cannot export the variable-shape column \"{}\" to FITS",
+                        col_name
+                    )));
+                }
+            };
+
+            format.dims = shape.iter().rev().map(|&d| d as usize).collect();
+            format.repeat = format.dims.iter().product();
+        }
+
+        if let Some(measure) = self.column_measure(col_name)? {
+            if let Some(first) = measure.units.first() {
+                if measure.units.iter().all(|u| u == first) {
+                    format.unit = first.clone();
+                }
+            }
+        }
+
+        Ok(format)
+    }
+
+    /// Get the length of the longest value of the string column *col_name*,
+    /// in bytes.
+    fn max_string_len(&mut self, col_name: &str) -> Result<usize, Error> {
+        let n_rows = self.n_rows();
+        let mut max_len = 0;
+        let mut row = 0;
+
+        while row < n_rows {
+            let n = cmp::min(EXPORT_BATCH_ROWS, n_rows - row);
+
+            for s in self.get_col_range_as_vec::<String>(col_name, row, n)? {
+                max_len = cmp::max(max_len, s.len());
+            }
+
+            row += n;
+        }
+
+        Ok(max_len)
+    }
+
+    /// Read *n* cells of the column *col*, starting at row *start*, as the
+    /// big-endian bytes of their FITS representation.
+    fn read_bintable_cells(
+        &mut self,
+        col: &ExportColumn,
+        start: u64,
+        n: u64,
+    ) -> Result<Vec<u8>, Error> {
+        use glue::GlueDataType::*;
+
+        let name = &col.name[..];
+
+        Ok(match col.data_type {
+            TpBool => cells_as_bytes(self, name, start, n, |v: &bool, b| {
+                b.push(if *v { b'T' } else { b'F' })
+            })?,
+            TpUChar => cells_as_bytes(self, name, start, n, |v: &u8, b| b.push(*v))?,
+            TpChar => cells_as_bytes(self, name, start, n, |v: &i8, b| {
+                b.extend_from_slice(&i16::from(*v).to_be_bytes())
+            })?,
+            TpShort => cells_as_bytes(self, name, start, n, |v: &i16, b| {
+                b.extend_from_slice(&v.to_be_bytes())
+            })?,
+            TpUShort => cells_as_bytes(self, name, start, n, |v: &u16, b| {
+                b.extend_from_slice(&i32::from(*v).to_be_bytes())
+            })?,
+            TpInt => cells_as_bytes(self, name, start, n, |v: &i32, b| {
+                b.extend_from_slice(&v.to_be_bytes())
+            })?,
+            TpUInt => cells_as_bytes(self, name, start, n, |v: &u32, b| {
+                b.extend_from_slice(&i64::from(*v).to_be_bytes())
+            })?,
+            TpInt64 => cells_as_bytes(self, name, start, n, |v: &i64, b| {
+                b.extend_from_slice(&v.to_be_bytes())
+            })?,
+            TpFloat => cells_as_bytes(self, name, start, n, |v: &f32, b| {
+                b.extend_from_slice(&v.to_bits().to_be_bytes())
+            })?,
+            TpDouble => cells_as_bytes(self, name, start, n, |v: &f64, b| {
+                b.extend_from_slice(&v.to_bits().to_be_bytes())
+            })?,
+            TpComplex => cells_as_bytes(self, name, start, n, |v: &Complex<f32>, b| {
+                b.extend_from_slice(&v.re.to_bits().to_be_bytes());
+                b.extend_from_slice(&v.im.to_bits().to_be_bytes());
+            })?,
+            TpDComplex => cells_as_bytes(self, name, start, n, |v: &Complex<f64>, b| {
+                b.extend_from_slice(&v.re.to_bits().to_be_bytes());
+                b.extend_from_slice(&v.im.to_bits().to_be_bytes());
+            })?,
+            TpString => {
+                let width = col.format.repeat;
+
+                cells_as_bytes(self, name, start, n, |v: &String, b| {
+                    let bytes = v.as_bytes();
+                    b.extend_from_slice(bytes);
+                    b.resize(b.len() + width - bytes.len(), b' ');
+                })?
+            }
+            other => {
+                return Err(err_msg(format!(
+                    "cannot export column \"{}\" of type {} to FITS",
+                    name, other
+                )));
+            }
+        })
+    }
+}
+
+/// Read a range of cells of a column and convert each of their elements to
+/// bytes with *put*.
+fn cells_as_bytes<T, F>(
+    table: &mut Table,
+    col_name: &str,
+    start: u64,
+    n: u64,
+    put: F,
+) -> Result<Vec<u8>, Error>
+where
+    T: CasaScalarData,
+    F: Fn(&T, &mut Vec<u8>),
+{
+    let values = table.get_col_range_as_vec::<T>(col_name, start, n)?;
+    let mut bytes = Vec::new();
+
+    for v in &values {
+        put(v, &mut bytes);
+    }
+
+    Ok(bytes)
+}
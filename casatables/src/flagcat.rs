@@ -0,0 +1,229 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Flag categories of Measurement Sets.
+
+The `FLAG_CATEGORY` column of a Measurement Set main table keeps several
+sets of flags side by side, one per named category, so that the flags set
+by different steps of a flagging workflow can be told apart and undone.
+Each cell has one more axis than the corresponding `FLAG` cell: in C order,
+its shape is (category, channel, correlation). The names of the categories
+are stored in the `CATEGORY` keyword of the column, in the same order.
+
+`create_flag_categories` creates the categories, `save_flags` copies the
+current contents of `FLAG` into one of them (“unfolding” it), and
+`fold_flag_categories` sets `FLAG` to the logical OR of a selection of
+them. For instance, a workflow might save the flags from the observatory
+into an `ONLINE` category and those from its own flagging into a `RFI` one;
+folding just `ONLINE` back into `FLAG` then undoes the second step. Each of
+these takes an `OutputPolicy` saying whether to modify the Measurement Set
+in place or to write a modified copy.
+
+Individual cells can be read and written as three-dimensional arrays with
+`Table::get_cell` and `Table::put_cell` like any other array column.
+
+*/
+
+use failure::{err_msg, Error};
+use ndarray::{Array2, Array3, Axis};
+use rubbl_core::output::OutputPolicy;
+use std::path::Path;
+
+use super::{GlueDataType, Table};
+
+/// The name of the keyword holding the names of the flag categories.
+const CATEGORY_KEYWORD: &str = "CATEGORY";
+
+/// Get the names of the flag categories of the main table *ms*, in the
+/// order of the first axis of its `FLAG_CATEGORY` cells. The result is
+/// empty if the table has no categories.
+pub fn flag_categories(ms: &mut Table) -> Result<Vec<String>, Error> {
+    if !ms.has_column("FLAG_CATEGORY")? {
+        return Ok(Vec::new());
+    }
+
+    Ok(ms.column_keyword_strings("FLAG_CATEGORY", CATEGORY_KEYWORD)?)
+}
+
+/// Set up the flag categories *names* of the Measurement Set at *input*,
+/// with the result going where *output* says, adding the `FLAG_CATEGORY`
+/// column if it does not exist yet. Every cell of the column is filled in,
+/// with nothing flagged in any category.
+///
+/// It is an error if the table already has flag categories, since their
+/// flags would be lost.
+pub fn create_flag_categories<P: AsRef<Path>>(
+    input: P,
+    output: &OutputPolicy,
+    names: &[&str],
+) -> Result<(), Error> {
+    Table::modify(input, output, |ms| create_table_categories(ms, names))
+}
+
+fn create_table_categories(ms: &mut Table, names: &[&str]) -> Result<(), Error> {
+    let existing = flag_categories(ms)?;
+
+    if !existing.is_empty() {
+        return Err(err_msg(format!(
+            "the table already has the flag categories {}",
+            existing.join(", ")
+        )));
+    }
+
+    for (i, name) in names.iter().enumerate() {
+        if name.is_empty() || names[..i].contains(name) {
+            return Err(err_msg(format!(
+                "invalid or duplicated flag category name \"{}\"",
+                name
+            )));
+        }
+    }
+
+    if !ms.has_column("FLAG_CATEGORY")? {
+        ms.add_array_column("FLAG_CATEGORY", GlueDataType::TpBool, None)?;
+    }
+
+    ms.put_column_keyword_strings("FLAG_CATEGORY", CATEGORY_KEYWORD, names)?;
+
+    for row in 0..ms.n_rows() {
+        let shape = ms.get_cell_shape("FLAG", row)?;
+
+        if shape.len() != 2 {
+            return Err(err_msg(format!(
+                "the FLAG cell in row {} is not two-dimensional",
+                row
+            )));
+        }
+
+        let cell = Array3::from_elem((names.len(), shape[0] as usize, shape[1] as usize), false);
+        ms.put_cell("FLAG_CATEGORY", row, &cell)?;
+    }
+
+    Ok(())
+}
+
+/// Copy the `FLAG` column of the Measurement Set at *input* into its flag
+/// category *name*, replacing the flags that were saved there before, with
+/// the result going where *output* says.
+pub fn save_flags<P: AsRef<Path>>(
+    input: P,
+    output: &OutputPolicy,
+    name: &str,
+) -> Result<(), Error> {
+    Table::modify(input, output, |ms| save_table_flags(ms, name))
+}
+
+fn save_table_flags(ms: &mut Table, name: &str) -> Result<(), Error> {
+    let index = category_index(ms, name)?;
+
+    for row in 0..ms.n_rows() {
+        let flags: Array2<bool> = ms.get_cell("FLAG", row)?;
+        let mut categories: Array3<bool> = ms.get_cell("FLAG_CATEGORY", row)?;
+        unfold(&mut categories, index, &flags).map_err(|e| in_row(e, row))?;
+        ms.put_cell("FLAG_CATEGORY", row, &categories)?;
+    }
+
+    Ok(())
+}
+
+/// Set the `FLAG` column of the Measurement Set at *input* to the logical
+/// OR of its flag categories *names*, with the result going where *output*
+/// says. If *names* is empty, `FLAG` is cleared. The `FLAG_ROW` column is
+/// left alone.
+pub fn fold_flag_categories<P: AsRef<Path>>(
+    input: P,
+    output: &OutputPolicy,
+    names: &[&str],
+) -> Result<(), Error> {
+    Table::modify(input, output, |ms| fold_table_categories(ms, names))
+}
+
+fn fold_table_categories(ms: &mut Table, names: &[&str]) -> Result<(), Error> {
+    let mut indices = Vec::with_capacity(names.len());
+
+    for name in names {
+        indices.push(category_index(ms, name)?);
+    }
+
+    for row in 0..ms.n_rows() {
+        let categories: Array3<bool> = ms.get_cell("FLAG_CATEGORY", row)?;
+        let flags = fold(&categories, &indices).map_err(|e| in_row(e, row))?;
+        ms.put_cell("FLAG", row, &flags)?;
+    }
+
+    Ok(())
+}
+
+/// Find the index of the flag category *name* of *ms*.
+fn category_index(ms: &mut Table, name: &str) -> Result<usize, Error> {
+    flag_categories(ms)?
+        .iter()
+        .position(|c| c == name)
+        .ok_or_else(|| err_msg(format!("the table has no flag category \"{}\"", name)))
+}
+
+fn in_row(err: Error, row: u64) -> Error {
+    err_msg(format!("{} in row {}", err, row))
+}
+
+/// Compute the OR of the categories *indices* of one `FLAG_CATEGORY` cell.
+fn fold(categories: &Array3<bool>, indices: &[usize]) -> Result<Array2<bool>, Error> {
+    let shape = categories.shape();
+    let mut flags = Array2::from_elem((shape[1], shape[2]), false);
+
+    for &index in indices {
+        if index >= shape[0] {
+            return Err(err_msg(format!(
+                "the FLAG_CATEGORY cell has no category {}",
+                index
+            )));
+        }
+
+        flags.zip_mut_with(&categories.index_axis(Axis(0), index), |f, &c| *f |= c);
+    }
+
+    Ok(flags)
+}
+
+/// Copy one `FLAG` cell into the category *index* of one `FLAG_CATEGORY`
+/// cell.
+fn unfold(categories: &mut Array3<bool>, index: usize, flags: &Array2<bool>) -> Result<(), Error> {
+    if index >= categories.shape()[0] || categories.shape()[1..] != *flags.shape() {
+        return Err(err_msg(format!(
+            "the FLAG_CATEGORY cell of shape {:?} does not match the FLAG cell of shape {:?}",
+            categories.shape(),
+            flags.shape()
+        )));
+    }
+
+    categories.index_axis_mut(Axis(0), index).assign(flags);
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn category_folding() {
+    use ndarray::arr2;
+
+    // Two channels of two correlations, in three categories.
+    let mut categories = Array3::from_elem((3, 2, 2), false);
+    unfold(&mut categories, 0, &arr2(&[[true, false], [false, false]])).unwrap();
+    unfold(&mut categories, 2, &arr2(&[[false, false], [true, true]])).unwrap();
+
+    assert_eq!(
+        fold(&categories, &[0, 2]).unwrap(),
+        arr2(&[[true, false], [true, true]])
+    );
+    assert_eq!(
+        fold(&categories, &[0]).unwrap(),
+        arr2(&[[true, false], [false, false]])
+    );
+    assert_eq!(
+        fold(&categories, &[]).unwrap(),
+        Array2::from_elem((2, 2), false)
+    );
+    assert!(fold(&categories, &[3]).is_err());
+    assert!(unfold(&mut categories, 1, &Array2::from_elem((3, 2), false)).is_err());
+}
@@ -6,30 +6,118 @@
 // C ordering instead. So we must take care to reverse array shapes when
 // translating from C++-land to Rust-land.
 
+#include <sstream>
 #include <stdexcept>
+#include <vector>
 #include <casacore/casa/BasicSL.h>
+#include <casacore/casa/Containers/ValueHolder.h>
 #include <casacore/tables/Tables.h>
+#include <casacore/tables/Tables/TableAttr.h>
+#include <casacore/casa/HDF5/HDF5Object.h>
+#include <casacore/casa/IO/LockFile.h>
+#include <casacore/casa/OS/File.h>
+#include <casacore/casa/System/Aipsrc.h>
+#include <casacore/tables/DataMan/TiledStManAccessor.h>
 
 #define CASA_TYPES_ALREADY_DECLARED
 #define GlueTable casacore::Table
 #define GlueTableRow casacore::ROTableRow
+#define GlueTableColumn casacore::TableColumn
 #define GlueDataType casacore::DataType
 
 #include "glue.h"
 
+#include <stdlib.h>
 #include <string.h>
 
+// Heap-allocated arrays whose storage is lent out to Rust. The base class
+// lets us free them without knowing their element type.
+struct ArrayHolderBase {
+    virtual ~ArrayHolderBase() {}
+};
+
+template <typename T>
+struct ArrayHolder : public ArrayHolderBase {
+    casacore::Array<T> array;
+};
+
 extern "C" {
+    static void
+    set_exception(ExcInfo &exc, const char *message, const GlueExceptionCategory category)
+    {
+        strncpy(exc.message, message, sizeof(exc.message) - 1);
+        exc.message[sizeof(exc.message) - 1] = '\0';
+        exc.category = (int) category;
+    }
+
+    // casacore has no dedicated exception class for references to columns
+    // that do not exist, so we have to recognize its message.
+    static bool
+    is_unknown_column_error(const casacore::TableError &e)
+    {
+        const std::string &msg = e.getMesg();
+        const std::string prefix = "Table column ", suffix = " is unknown";
+
+        return msg.size() > prefix.size() + suffix.size() &&
+            msg.compare(0, prefix.size(), prefix) == 0 &&
+            msg.compare(msg.size() - suffix.size(), suffix.size(), suffix) == 0;
+    }
+
+    static GlueExceptionCategory
+    aips_error_category(const casacore::AipsError &e)
+    {
+        switch (e.getCategory()) {
+        case casacore::AipsError::BOUNDARY:
+            return GEC_OUT_OF_BOUNDS;
+        case casacore::AipsError::CONFORMANCE:
+            return GEC_CONFORMANCE;
+        case casacore::AipsError::SYSTEM:
+        case casacore::AipsError::PERMISSION:
+            return GEC_IO;
+        default:
+            return GEC_GENERAL;
+        }
+    }
+
+    // Must be called from inside a `catch` block. More-derived exception
+    // classes must be caught before their base classes.
     void
     handle_exception(ExcInfo &exc)
     {
         try {
             throw;
+        } catch (const casacore::TableNoFile &e) {
+            set_exception(exc, e.what(), GEC_TABLE_NOT_FOUND);
+        } catch (const casacore::TableNoDir &e) {
+            set_exception(exc, e.what(), GEC_TABLE_NOT_FOUND);
+        } catch (const casacore::TableNoDatFile &e) {
+            set_exception(exc, e.what(), GEC_TABLE_NOT_FOUND);
+        } catch (const casacore::TableDuplFile &e) {
+            set_exception(exc, e.what(), GEC_TABLE_EXISTS);
+        } catch (const casacore::TableInvDT &e) {
+            set_exception(exc, e.what(), GEC_DATA_TYPE);
+        } catch (const casacore::TableArrayConformanceError &e) {
+            set_exception(exc, e.what(), GEC_CONFORMANCE);
+        } catch (const casacore::TableConformanceError &e) {
+            set_exception(exc, e.what(), GEC_CONFORMANCE);
+        } catch (const casacore::TableError &e) {
+            set_exception(exc, e.what(), is_unknown_column_error(e) ? GEC_NO_SUCH_COLUMN : GEC_TABLE);
+        } catch (const casacore::ArrayConformanceError &e) {
+            set_exception(exc, e.what(), GEC_CONFORMANCE);
+        } catch (const casacore::IndexError &e) {
+            set_exception(exc, e.what(), GEC_OUT_OF_BOUNDS);
+        } catch (const casacore::AllocError &e) {
+            set_exception(exc, e.what(), GEC_ALLOC);
+        } catch (const casacore::SystemCallError &e) {
+            set_exception(exc, e.what(), GEC_IO);
+        } catch (const casacore::AipsError &e) {
+            set_exception(exc, e.what(), aips_error_category(e));
+        } catch (const std::bad_alloc &e) {
+            set_exception(exc, e.what(), GEC_ALLOC);
         } catch (const std::exception &e) {
-            strncpy(exc.message, e.what(), sizeof(exc.message) - 1);
-            exc.message[sizeof(exc.message) - 1] = '\0';
+            set_exception(exc, e.what(), GEC_GENERAL);
         } catch (...) {
-            strcpy(exc.message, "unidentifiable C++ exception occurred");
+            set_exception(exc, "unidentifiable C++ exception occurred", GEC_GENERAL);
         }
     }
 
@@ -42,6 +130,8 @@ extern "C" {
         return result;
     }
 
+    // The result borrows the data of `input`, so it is only valid as long as
+    // `input` is. This is appropriate for callbacks, not for return values.
     void
     unbridge_string(const casacore::String &input, StringBridge &dest)
     {
@@ -49,6 +139,46 @@ extern "C" {
         dest.n_bytes = input.length();
     }
 
+    // The result owns a copy of the data of `input`, which must be released
+    // with string_bridge_free(). We must copy because strings are often
+    // temporaries, and short strings keep their data inside the String
+    // object itself, so that a pointer into them goes stale as soon as they
+    // are destroyed.
+    void
+    unbridge_string_owned(const casacore::String &input, StringBridge &dest)
+    {
+        size_t n = input.length();
+        char *buf = (char *) malloc(n > 0 ? n : 1);
+
+        if (buf == NULL)
+            throw std::bad_alloc();
+
+        memcpy(buf, input.data(), n);
+        dest.data = buf;
+        dest.n_bytes = n;
+    }
+
+    void
+    string_bridge_free(StringBridge *s)
+    {
+        free((void *) s->data);
+        s->data = NULL;
+        s->n_bytes = 0;
+    }
+
+    int
+    string_bridge_copy(const StringBridge &source, StringBridge *dest, ExcInfo &exc)
+    {
+        try {
+            unbridge_string_owned(bridge_string(source), *dest);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
     casacore::Array<casacore::String>
     bridge_string_array(const StringBridge *source, const casacore::IPosition &shape)
     {
@@ -62,17 +192,16 @@ extern "C" {
         return array;
     }
 
-    // We have to assume that destination has enough space.
+    // We have to assume that destination has enough space. As with
+    // unbridge_string_owned(), each element receives its own copy of the data.
     void
     unbridge_string_array(const casacore::Array<casacore::String> &input, StringBridge *dest)
     {
         unsigned int n = 0;
         casacore::Array<casacore::String>::const_iterator end = input.end();
 
-        for (casacore::Array<casacore::String>::const_iterator i = input.begin(); i != end; i++, n++) {
-            dest[n].data = (*i).data();
-            dest[n].n_bytes = (*i).length();
-        }
+        for (casacore::Array<casacore::String>::const_iterator i = input.begin(); i != end; i++, n++)
+            unbridge_string_owned(*i, dest[n]);
     }
 
     // Data Types
@@ -120,18 +249,200 @@ extern "C" {
 
     // Tables
 
+    casacore::StorageOption
+    make_storage_option(const GlueStorageOption storage, const uint32_t block_size)
+    {
+        switch (storage) {
+        case GSO_SEPARATE_FILES:
+            return casacore::StorageOption(casacore::StorageOption::SepFile);
+        case GSO_MULTI_FILE:
+            return casacore::StorageOption(casacore::StorageOption::MultiFile, (casacore::Int) block_size);
+        case GSO_MULTI_HDF5:
+            return casacore::StorageOption(casacore::StorageOption::MultiHDF5, (casacore::Int) block_size);
+        default:
+            return casacore::StorageOption();
+        }
+    }
+
+    int
+    storage_hdf5_is_supported(void)
+    {
+        return casacore::HDF5Object::hasHDF5Support() ? 1 : 0;
+    }
+
+    // Aipsrc sets itself up on first use, consulting HOME and CASAPATH or
+    // AIPSPATH, and throws if HOME is not set. Asking for the root and home
+    // directories forces that to happen now, where errors can be reported.
+    int
+    casacore_initialize(const StringBridge &aips_path, StringBridge *aips_root,
+                        StringBridge *aips_home, ExcInfo &exc)
+    {
+        try {
+            if (aips_path.n_bytes > 0)
+                casacore::Aipsrc::setAipsPath(bridge_string(aips_path));
+
+            unbridge_string_owned(casacore::Aipsrc::aipsRoot(), *aips_root);
+            unbridge_string_owned(casacore::Aipsrc::aipsHome(), *aips_home);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
     GlueTable *
     table_alloc_and_open(const StringBridge &path, const TableOpenMode mode, ExcInfo &exc)
     {
-        GlueTable::TableOption option = GlueTable::Old;
+        try {
+            // Opening a table by name only works for tables that exist, so a
+            // new one is set up with no columns, to be added afterwards.
+            if (mode == TOM_CREATE || mode == TOM_CREATE_NO_OVERWRITE) {
+                GlueTable::TableOption option =
+                    mode == TOM_CREATE ? GlueTable::New : GlueTable::NewNoReplace;
+                casacore::SetupNewTable setup(bridge_string(path), casacore::TableDesc(), option);
+                return new GlueTable(setup, 0);
+            }
+
+            GlueTable::TableOption option =
+                mode == TOM_OPEN_RW ? GlueTable::Update : GlueTable::Old;
+            return new GlueTable(bridge_string(path), option, casacore::TSMOption());
+        } catch (...) {
+            handle_exception(exc);
+            return NULL;
+        }
+    }
+
+    // Report whether another process holds a lock on the table at `path`
+    // that would make opening it in `mode` wait: 1 if so, 0 if not, -1 on
+    // error. The ID of the process holding the lock is stored in `pid`.
+    int
+    table_check_lock(const StringBridge &path, const TableOpenMode mode, uint32_t *pid,
+                     ExcInfo &exc)
+    {
+        *pid = 0;
+
+        try {
+            casacore::String name = bridge_string(path);
+            casacore::String lock_name = name + "/table.lock";
+
+            // A table that this process already has open shares its locks.
+            // It must not be probed, since closing the probe's descriptor
+            // would drop this process's fcntl locks on the lock file.
+            if (mode == TOM_CREATE || mode == TOM_CREATE_NO_OVERWRITE ||
+                GlueTable::isOpened(name) ||
+                !casacore::File(lock_name).exists())
+                return 0;
+
+            casacore::uInt lock_pid;
+            casacore::Bool perm_locked;
+
+            // 3 means write-locked elsewhere, and 2 read-locked elsewhere.
+            // Opening for reading only takes a read lock, which read locks
+            // held elsewhere do not block; opening for writing is blocked by
+            // both.
+            int status = casacore::LockFile::showLock(lock_pid, perm_locked, lock_name);
+
+            if (status == 3 || (status == 2 && mode == TOM_OPEN_RW)) {
+                *pid = lock_pid;
+                return 1;
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return -1;
+        }
 
-        if (mode == TOM_OPEN_RW)
-            option = GlueTable::Update;
-        else if (mode == TOM_CREATE)
-            option = GlueTable::NewNoReplace;
+        return 0;
+    }
 
+    // Column `i` is a scalar if `col_ndims[i]` is negative, and otherwise an
+    // array with that many dimensions, or any number if it is zero. If
+    // `col_fixed[i]` is nonzero, the array has a fixed shape, whose
+    // `col_ndims[i]` dimensions are the next ones in `shapes`.
+    GlueTable *
+    table_create(const StringBridge &path, const uint64_t n_cols,
+                 const StringBridge *col_names, const StringBridge *col_comments,
+                 const GlueDataType *col_types, const int32_t *col_ndims,
+                 const uint8_t *col_fixed, const uint64_t *shapes, const uint64_t n_rows,
+                 const GlueStorageOption storage, const uint32_t block_size, ExcInfo &exc)
+    {
         try {
-            return new GlueTable(bridge_string(path), option, casacore::TSMOption());
+            casacore::TableDesc desc;
+
+            for (uint64_t i = 0; i < n_cols; i++) {
+                casacore::String name = bridge_string(col_names[i]);
+                casacore::String comment = bridge_string(col_comments[i]);
+                int32_t n_dims = col_ndims[i];
+                casacore::IPosition shape;
+
+                if (col_fixed[i]) {
+                    shape.resize(n_dims);
+
+                    for (int32_t j = 0; j < n_dims; j++)
+                        shape[j] = shapes[n_dims - 1 - j];
+
+                    shapes += n_dims;
+                }
+
+                switch (col_types[i]) {
+
+#define CASE(DTYPE, CPPTYPE) \
+                case casacore::DTYPE: \
+                    if (n_dims < 0) \
+                        desc.addColumn(casacore::ScalarColumnDesc<CPPTYPE>(name, comment)); \
+                    else if (col_fixed[i]) \
+                        desc.addColumn(casacore::ArrayColumnDesc<CPPTYPE>(name, comment, shape, \
+                                                                          casacore::ColumnDesc::FixedShape)); \
+                    else \
+                        desc.addColumn(casacore::ArrayColumnDesc<CPPTYPE>(name, comment, \
+                                                                          n_dims == 0 ? -1 : n_dims)); \
+                    break;
+
+                CASE(TpBool, casacore::Bool)
+                CASE(TpChar, casacore::Char)
+                CASE(TpUChar, casacore::uChar)
+                CASE(TpShort, casacore::Short)
+                CASE(TpUShort, casacore::uShort)
+                CASE(TpInt, casacore::Int)
+                CASE(TpUInt, casacore::uInt)
+                CASE(TpInt64, casacore::Int64)
+                CASE(TpFloat, float)
+                CASE(TpDouble, double)
+                CASE(TpComplex, casacore::Complex)
+                CASE(TpDComplex, casacore::DComplex)
+                CASE(TpString, casacore::String)
+
+#undef CASE
+
+                default:
+                    throw std::runtime_error("unhandled column data type");
+                }
+            }
+
+            casacore::SetupNewTable setup(bridge_string(path), desc, casacore::Table::NewNoReplace,
+                                          make_storage_option(storage, block_size));
+            return new GlueTable(setup, n_rows);
+        } catch (...) {
+            handle_exception(exc);
+            return NULL;
+        }
+    }
+
+    // The result is a reference table that shares its data with `table`.
+    GlueTable *
+    table_select_rows(const GlueTable &table, const uint8_t *mask, const uint64_t n_rows,
+                      ExcInfo &exc)
+    {
+        try {
+            if (n_rows != table.nrow())
+                throw std::runtime_error("row mask length does not match number of table rows");
+
+            casacore::Block<casacore::Bool> bmask(n_rows);
+
+            for (uint64_t i = 0; i < n_rows; i++)
+                bmask[i] = mask[i] ? casacore::True : casacore::False;
+
+            return new GlueTable(table(bmask));
         } catch (...) {
             handle_exception(exc);
             return NULL;
@@ -148,20 +459,85 @@ extern "C" {
         }
     }
 
-    unsigned long
+    int
+    table_delete(const StringBridge &path, ExcInfo &exc)
+    {
+        try {
+            GlueTable::deleteTable(bridge_string(path), casacore::True /* checkSubTables */);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_rename(const StringBridge &old_path, const StringBridge &new_path, ExcInfo &exc)
+    {
+        try {
+            GlueTable table(bridge_string(old_path), GlueTable::Update);
+            table.rename(bridge_string(new_path), GlueTable::NewNoReplace);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Write a table that concatenates the tables at `part_paths`. casacore
+    // moves the parts into the subdirectory `sub_dir` of the new table, which
+    // is how CASA's multi-MS layout is made.
+    int
+    table_create_concat(const uint64_t n_parts, const StringBridge *part_paths,
+                        const StringBridge &sub_dir, const StringBridge &dest_path,
+                        ExcInfo &exc)
+    {
+        try {
+            casacore::Block<casacore::String> names(n_parts);
+
+            for (uint64_t i = 0; i < n_parts; i++)
+                names[i] = bridge_string(part_paths[i]);
+
+            GlueTable concat(
+                names,
+                casacore::Block<casacore::String>(), // no concatenated subtables
+                GlueTable::Old,
+                casacore::TSMOption(),
+                bridge_string(sub_dir)
+            );
+            concat.rename(bridge_string(dest_path), GlueTable::NewNoReplace);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    uint64_t
     table_n_rows(const GlueTable &table)
     {
         // I *think* we can safely say that this code should never trigger an exception.
         return table.nrow();
     }
 
-    unsigned long
+    uint64_t
     table_n_columns(const GlueTable &table)
     {
         // I *think* we can safely say that this code should never trigger an exception.
         return table.actualTableDesc().columnDescSet().ncolumn();
     }
 
+    int
+    table_is_reference(const GlueTable &table)
+    {
+        // A reference table (the result of a selection, sort or projection)
+        // is the only kind whose root is a different table.
+        return table.isRootTable() ? 0 : 1;
+    }
+
     // We assume the caller has allocated col_names of sufficient size.
     int
     table_get_column_names(const GlueTable &table, StringBridgeCallback callback,
@@ -196,27 +572,50 @@ extern "C" {
         return 0;
     }
 
-    unsigned long
-    table_n_keywords(const GlueTable &table)
-    {
-        return table.keywordSet().nfields();
-    }
-
+    // Add an array column to a table whose elements have the scalar type
+    // `data_type`. If `n_dims` is zero, the cells may have any shape;
+    // otherwise they all have the shape `dims`, given in C order.
     int
-    table_get_keyword_info(const GlueTable &table, KeywordInfoCallback callback, void *ctxt, ExcInfo &exc)
+    table_add_array_column(GlueTable &table, const StringBridge &col_name,
+                           const GlueDataType data_type, const uint64_t n_dims,
+                           const uint64_t *dims, ExcInfo &exc)
     {
         try {
-            StringBridge name;
-            const casacore::TableRecord &rec = table.keywordSet();
-            casacore::uInt n_kws = rec.nfields();
+            casacore::String name = bridge_string(col_name);
+            casacore::IPosition shape(n_dims);
 
-            for (casacore::uInt i = 0; i < n_kws; i++) {
-                // Note: must preserve string variable as a local until after
-                // the callback is called; otherwise it can be deleted before
-                // we copy its data.
-                const casacore::String n = rec.name(i);
-                unbridge_string(n, name);
-                callback(&name, rec.type(i), ctxt);
+            for (uint64_t i = 0; i < n_dims; i++)
+                shape[i] = dims[n_dims - 1 - i];
+
+            switch (data_type) {
+
+#define CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: \
+                if (n_dims == 0) \
+                    table.addColumn(casacore::ArrayColumnDesc<CPPTYPE>(name)); \
+                else \
+                    table.addColumn(casacore::ArrayColumnDesc<CPPTYPE>(name, shape, \
+                                                                       casacore::ColumnDesc::FixedShape)); \
+                break;
+
+            CASE(TpBool, casacore::Bool)
+            CASE(TpChar, casacore::Char)
+            CASE(TpUChar, casacore::uChar)
+            CASE(TpShort, casacore::Short)
+            CASE(TpUShort, casacore::uShort)
+            CASE(TpInt, casacore::Int)
+            CASE(TpUInt, casacore::uInt)
+            CASE(TpInt64, casacore::Int64)
+            CASE(TpFloat, float)
+            CASE(TpDouble, double)
+            CASE(TpComplex, casacore::Complex)
+            CASE(TpDComplex, casacore::DComplex)
+            CASE(TpString, casacore::String)
+
+#undef CASE
+
+            default:
+                throw std::runtime_error("unhandled array column element type");
             }
         } catch (...) {
             handle_exception(exc);
@@ -227,10 +626,11 @@ extern "C" {
     }
 
     int
-    table_copy_rows(const GlueTable &source, GlueTable &dest, ExcInfo &exc)
+    table_put_keyword_string(GlueTable &table, const StringBridge &kw_name,
+                             const StringBridge &value, ExcInfo &exc)
     {
         try {
-            casacore::TableCopy::copyRows(dest, source);
+            table.rwKeywordSet().define(bridge_string(kw_name), bridge_string(value));
         } catch (...) {
             handle_exception(exc);
             return 1;
@@ -239,17 +639,15 @@ extern "C" {
         return 0;
     }
 
+    // Make the keyword `kw_name` of a table refer to the existing table at
+    // `subtable_path`, as the subtables of a Measurement Set are.
     int
-    table_deep_copy_no_rows(const GlueTable &table, const StringBridge &dest_path, ExcInfo &exc)
+    table_put_keyword_subtable(GlueTable &table, const StringBridge &kw_name,
+                               const StringBridge &subtable_path, ExcInfo &exc)
     {
         try {
-            table.deepCopy(
-                bridge_string(dest_path),
-                GlueTable::NewNoReplace,
-                casacore::True, // "valueCopy"
-                GlueTable::LocalEndian,
-                casacore::True // "noRows"
-            );
+            casacore::Table subtable(bridge_string(subtable_path));
+            table.rwKeywordSet().defineTable(bridge_string(kw_name), subtable);
         } catch (...) {
             handle_exception(exc);
             return 1;
@@ -259,27 +657,14 @@ extern "C" {
     }
 
     int
-    table_get_column_info(const GlueTable &table, const StringBridge &col_name,
-                          unsigned long *n_rows, GlueDataType *data_type,
-                          int *is_scalar, int *is_fixed_shape, int *n_dim,
-                          unsigned long dims[8], ExcInfo &exc)
+    table_get_info(const GlueTable &table, StringBridge *type, StringBridge *sub_type,
+                   StringBridge *readme, ExcInfo &exc)
     {
         try {
-            casacore::TableColumn col(table, bridge_string(col_name));
-            const casacore::ColumnDesc &desc = col.columnDesc();
-            const casacore::IPosition &shape = desc.shape();
-
-            if (shape.size() > 8)
-                throw std::runtime_error("cannot handle columns with data of dimensionality greater than 8");
-
-            *n_rows = table.nrow();
-            *data_type = desc.dataType();
-            *is_scalar = (int) desc.isScalar();
-            *is_fixed_shape = (int) desc.isFixedShape();
-            *n_dim = (int) desc.ndim();
-
-            for (int i = 0; i < *n_dim; i++) // note: for empty cols, n_dim = -1; this is OK
-                dims[*n_dim - 1 - i] = (unsigned long) shape[i];
+            const casacore::TableInfo &info = table.tableInfo();
+            unbridge_string_owned(info.type(), *type);
+            unbridge_string_owned(info.subType(), *sub_type);
+            unbridge_string_owned(info.readme(), *readme);
         } catch (...) {
             handle_exception(exc);
             return 1;
@@ -288,51 +673,29 @@ extern "C" {
         return 0;
     }
 
-    // This function assumes that the caller has already vetted the types and
-    // has figured how big `data` needs to be.
+    // Replace the type, subtype, and readme text of a table and write them
+    // out to its `table.info` file. The readme is stored as given, so it
+    // should end with a newline if it is not empty.
     int
-    table_get_scalar_column_data(const GlueTable &table, const StringBridge &col_name,
-                                 void *data, ExcInfo &exc)
+    table_set_info(GlueTable &table, const StringBridge &type, const StringBridge &sub_type,
+                   const StringBridge &readme, ExcInfo &exc)
     {
         try {
-            const casacore::ColumnDesc &desc = casacore::TableColumn(table, bridge_string(col_name)).columnDesc();
-            casacore::IPosition shape(1, table.nrow());
-
-            switch (desc.dataType()) {
-
-#define CASE(DTYPE, CPPTYPE) \
-            case casacore::DTYPE: { \
-                casacore::ScalarColumn<CPPTYPE> col(table, bridge_string(col_name)); \
-                casacore::Vector<CPPTYPE> vec(shape, (CPPTYPE *) data, casacore::SHARE); \
-                col.getColumn(vec); \
-                break; \
-            }
-
-            CASE(TpBool, casacore::Bool)
-            CASE(TpChar, casacore::Char)
-            CASE(TpUChar, casacore::uChar)
-            CASE(TpShort, casacore::Short)
-            CASE(TpUShort, casacore::uShort)
-            CASE(TpInt, casacore::Int)
-            CASE(TpUInt, casacore::uInt)
-            CASE(TpFloat, float)
-            CASE(TpDouble, double)
-            CASE(TpComplex, casacore::Complex)
-            CASE(TpDComplex, casacore::DComplex)
-
-#undef CASE
-
-            case casacore::TpString: {                                         \
-                casacore::ScalarColumn<casacore::String> col(table, bridge_string(col_name));
-                casacore::Vector<casacore::String> vec(shape);
-                col.getColumn(vec);
-                unbridge_string_array(vec, (StringBridge *) data);
-                break;
+            casacore::TableInfo &info = table.tableInfo();
+            info.setType(bridge_string(type));
+            info.setSubType(bridge_string(sub_type));
+            info.readmeClear();
+
+            casacore::String text = bridge_string(readme);
+
+            if (!text.empty()) {
+                // readmeAddLine appends its own newline.
+                if (text[text.size() - 1] == '\n')
+                    text = text.substr(0, text.size() - 1);
+                info.readmeAddLine(text);
             }
 
-            default:
-                throw std::runtime_error("unhandled scalar column data type");
-            }
+            table.flushTableInfo();
         } catch (...) {
             handle_exception(exc);
             return 1;
@@ -341,29 +704,33 @@ extern "C" {
         return 0;
     }
 
+    // Attach measures metadata to a column: the `QuantumUnits` keyword, if
+    // `n_units` is nonzero, and the `MEASINFO` record, if `meas_type` is
+    // nonempty.
     int
-    table_get_cell_info(const GlueTable &table, const StringBridge &col_name,
-                        unsigned long row_number, GlueDataType *data_type,
-                        int *n_dim, unsigned long dims[8], ExcInfo &exc)
+    table_put_column_measure(GlueTable &table, const StringBridge &col_name,
+                             const uint64_t n_units, const StringBridge *units,
+                             const StringBridge &meas_type, const StringBridge &meas_ref,
+                             ExcInfo &exc)
     {
         try {
             casacore::TableColumn col(table, bridge_string(col_name));
-            const casacore::ColumnDesc &desc = col.columnDesc();
+            casacore::TableRecord &kws = col.rwKeywordSet();
 
-            *data_type = desc.dataType();
-
-            if (desc.isScalar())
-                *n_dim = 0;
-            else {
-                *n_dim = (int) col.ndim(row_number);
+            if (n_units > 0) {
+                casacore::Vector<casacore::String> v(n_units);
 
-                if (*n_dim > 8)
-                    throw std::runtime_error("cannot handle cells with data of dimensionality greater than 8");
+                for (uint64_t i = 0; i < n_units; i++)
+                    v[i] = bridge_string(units[i]);
 
-                const casacore::IPosition shape = col.shape(row_number);
+                kws.define("QuantumUnits", v);
+            }
 
-                for (int i = 0; i < *n_dim; i++)
-                    dims[*n_dim - 1 - i] = (unsigned long) shape[i];
+            if (meas_type.n_bytes > 0) {
+                casacore::TableRecord info;
+                info.define("type", bridge_string(meas_type));
+                info.define("Ref", bridge_string(meas_ref));
+                kws.defineRecord("MEASINFO", info);
             }
         } catch (...) {
             handle_exception(exc);
@@ -373,34 +740,1068 @@ extern "C" {
         return 0;
     }
 
-    // This function assumes that the caller has already vetted the types and
+    // Get the measures metadata of a column. The units are joined with
+    // commas. Each output is empty if the keyword that it comes from is
+    // absent, and must be released with string_bridge_free().
+    int
+    table_get_column_measure(const GlueTable &table, const StringBridge &col_name,
+                             StringBridge *units, StringBridge *meas_type,
+                             StringBridge *meas_ref, ExcInfo &exc)
+    {
+        try {
+            casacore::TableColumn col(table, bridge_string(col_name));
+            const casacore::TableRecord &kws = col.keywordSet();
+            casacore::String u, t, r;
+
+            if (kws.isDefined("QuantumUnits")) {
+                if (kws.dataType("QuantumUnits") == casacore::TpString) {
+                    u = kws.asString("QuantumUnits");
+                } else {
+                    const casacore::Array<casacore::String> &v = kws.asArrayString("QuantumUnits");
+                    casacore::Array<casacore::String>::const_iterator end = v.end();
+
+                    for (casacore::Array<casacore::String>::const_iterator i = v.begin(); i != end; i++) {
+                        if (!u.empty())
+                            u += ",";
+                        u += *i;
+                    }
+                }
+            }
+
+            if (kws.isDefined("MEASINFO")) {
+                const casacore::TableRecord &info = kws.subRecord("MEASINFO");
+
+                if (info.isDefined("type"))
+                    t = info.asString("type");
+                if (info.isDefined("Ref"))
+                    r = info.asString("Ref");
+            }
+
+            unbridge_string_owned(u, *units);
+            unbridge_string_owned(t, *meas_type);
+            unbridge_string_owned(r, *meas_ref);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Copy all of the keywords of the column `col_name` of `source` to the
+    // column of the same name of `dest`, replacing any that are already
+    // defined there.
+    int
+    table_copy_column_keywords(GlueTable &dest, const GlueTable &source,
+                               const StringBridge &col_name, ExcInfo &exc)
+    {
+        try {
+            casacore::String name = bridge_string(col_name);
+            casacore::TableColumn scol(source, name);
+            casacore::TableColumn dcol(dest, name);
+            dcol.rwKeywordSet().merge(scol.keywordSet(),
+                                      casacore::RecordInterface::OverwriteDuplicates);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Describe the keywords of a table, or of its column `col_name` if that
+    // is nonempty. Subtables are described by their paths and subrecords by
+    // their numbers of fields; other values are printed by casacore.
+    int
+    table_get_keyword_values(const GlueTable &table, const StringBridge &col_name,
+                             KeywordValueCallback callback, void *ctxt, ExcInfo &exc)
+    {
+        try {
+            StringBridge name, value;
+            casacore::String cname = bridge_string(col_name);
+            casacore::TableColumn col;
+
+            if (!cname.empty())
+                col.reference(casacore::TableColumn(table, cname));
+
+            const casacore::TableRecord &rec = cname.empty() ? table.keywordSet() : col.keywordSet();
+            casacore::uInt n_kws = rec.nfields();
+
+            for (casacore::uInt i = 0; i < n_kws; i++) {
+                const casacore::String n = rec.name(i);
+                casacore::DataType dtype = rec.type(i);
+                std::ostringstream os;
+
+                if (dtype == casacore::TpTable)
+                    os << rec.tableAttributes(i).name();
+                else if (dtype == casacore::TpRecord)
+                    os << rec.subRecord(i).nfields() << " fields";
+                else
+                    os << rec.asValueHolder(i);
+
+                // As in table_get_keyword_info, the strings must outlive the
+                // callback.
+                const casacore::String v = os.str();
+                unbridge_string(n, name);
+                unbridge_string(v, value);
+                callback(&name, dtype, &value, ctxt);
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Set the keyword `kw_name` of a column to a vector of strings.
+    int
+    table_put_column_keyword_strings(GlueTable &table, const StringBridge &col_name,
+                                     const StringBridge &kw_name, const uint64_t n_values,
+                                     const StringBridge *values, ExcInfo &exc)
+    {
+        try {
+            casacore::TableColumn col(table, bridge_string(col_name));
+            casacore::Vector<casacore::String> v(n_values);
+
+            for (uint64_t i = 0; i < n_values; i++)
+                v[i] = bridge_string(values[i]);
+
+            col.rwKeywordSet().define(bridge_string(kw_name), v);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Get the keyword `kw_name` of a column, which must be a string or an
+    // array of strings, calling `callback` with each of its values. The
+    // callback is not called at all if the keyword is absent.
+    int
+    table_get_column_keyword_strings(const GlueTable &table, const StringBridge &col_name,
+                                     const StringBridge &kw_name, StringBridgeCallback callback,
+                                     void *ctxt, ExcInfo &exc)
+    {
+        try {
+            StringBridge value;
+            casacore::TableColumn col(table, bridge_string(col_name));
+            const casacore::TableRecord &kws = col.keywordSet();
+            casacore::String name = bridge_string(kw_name);
+
+            if (!kws.isDefined(name))
+                return 0;
+
+            if (kws.dataType(name) == casacore::TpString) {
+                const casacore::String s = kws.asString(name);
+                unbridge_string(s, value);
+                callback(&value, ctxt);
+                return 0;
+            }
+
+            const casacore::Array<casacore::String> &v = kws.asArrayString(name);
+            casacore::Array<casacore::String>::const_iterator end = v.end();
+
+            for (casacore::Array<casacore::String>::const_iterator i = v.begin(); i != end; i++) {
+                unbridge_string(*i, value);
+                callback(&value, ctxt);
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    uint64_t
+    table_n_keywords(const GlueTable &table)
+    {
+        return table.keywordSet().nfields();
+    }
+
+    int
+    table_get_keyword_info(const GlueTable &table, KeywordInfoCallback callback, void *ctxt, ExcInfo &exc)
+    {
+        try {
+            StringBridge name;
+            const casacore::TableRecord &rec = table.keywordSet();
+            casacore::uInt n_kws = rec.nfields();
+
+            for (casacore::uInt i = 0; i < n_kws; i++) {
+                // Note: must preserve string variable as a local until after
+                // the callback is called; otherwise it can be deleted before
+                // we copy its data.
+                const casacore::String n = rec.name(i);
+                unbridge_string(n, name);
+                callback(&name, rec.type(i), ctxt);
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_get_data_manager_info(const GlueTable &table, DataManagerInfoCallback callback,
+                                void *ctxt, ExcInfo &exc)
+    {
+        try {
+            StringBridge dm_type, dm_name, col_name;
+            const casacore::Record info = table.dataManagerInfo();
+
+            for (casacore::uInt i = 0; i < info.nfields(); i++) {
+                const casacore::Record &dm = info.subRecord(i);
+                const casacore::String t = dm.asString("TYPE");
+                const casacore::String n = dm.asString("NAME");
+                const casacore::Vector<casacore::String> cols = dm.asArrayString("COLUMNS");
+                uint32_t seq_nr = (uint32_t) dm.asInt("SEQNR");
+                int64_t bucket_size = -1;
+                int n_tile_dim = 0;
+                uint64_t tile_shape[8];
+
+                if (dm.isDefined("SPEC")) {
+                    const casacore::Record &spec = dm.subRecord("SPEC");
+
+                    if (spec.isDefined("BUCKETSIZE"))
+                        bucket_size = (int64_t) spec.asInt("BUCKETSIZE");
+
+                    if (spec.isDefined("DEFAULTTILESHAPE")) {
+                        const casacore::Array<casacore::Int> shape = spec.toArrayInt("DEFAULTTILESHAPE");
+                        n_tile_dim = (int) shape.nelements();
+
+                        if (n_tile_dim > 8)
+                            throw std::runtime_error("unsupported number of tile dimensions");
+
+                        // Reverse to C order, as with all of our shapes.
+                        casacore::uInt j = 0;
+
+                        for (casacore::Array<casacore::Int>::const_iterator it = shape.begin();
+                             it != shape.end(); ++it, ++j)
+                            tile_shape[n_tile_dim - 1 - j] = (uint64_t) *it;
+                    }
+                }
+
+                unbridge_string(t, dm_type);
+                unbridge_string(n, dm_name);
+
+                for (casacore::uInt j = 0; j < cols.nelements(); j++) {
+                    unbridge_string(cols[j], col_name);
+                    callback(&dm_type, &dm_name, seq_nr, &col_name, bucket_size,
+                             n_tile_dim, tile_shape, ctxt);
+                }
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_copy_rows(const GlueTable &source, GlueTable &dest, ExcInfo &exc)
+    {
+        try {
+            casacore::TableCopy::copyRows(dest, source);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_get_storage_option(const GlueTable &table, GlueStorageOption *storage,
+                             uint32_t *block_size, ExcInfo &exc)
+    {
+        try {
+            const casacore::StorageOption &stopt = table.storageOption();
+
+            switch (stopt.option()) {
+            case casacore::StorageOption::MultiFile:
+                *storage = GSO_MULTI_FILE;
+                break;
+            case casacore::StorageOption::MultiHDF5:
+                *storage = GSO_MULTI_HDF5;
+                break;
+            default:
+                *storage = GSO_SEPARATE_FILES;
+                break;
+            }
+
+            *block_size = (uint32_t) stopt.blockSize();
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // If `col_names` is NULL, all columns are copied. Otherwise only the
+    // named columns are, by way of a projection of the table; the table
+    // keywords and subtables are copied either way.
+    int
+    table_deep_copy(const GlueTable &table, const StringBridge &dest_path,
+                    const uint64_t n_cols, const StringBridge *col_names,
+                    const int no_rows, const GlueStorageOption storage,
+                    const uint32_t block_size, ExcInfo &exc)
+    {
+        try {
+            GlueTable source = table;
+            casacore::StorageOption stopt = make_storage_option(storage, block_size);
+
+            if (col_names != NULL) {
+                casacore::Block<casacore::String> names(n_cols);
+
+                for (uint64_t i = 0; i < n_cols; i++)
+                    names[i] = bridge_string(col_names[i]);
+
+                source = table.project(names);
+            }
+
+            source.deepCopy(
+                bridge_string(dest_path),
+                casacore::Record(), // "dataManagerInfo": keep the existing ones
+                stopt,
+                GlueTable::NewNoReplace,
+                casacore::True, // "valueCopy"
+                GlueTable::LocalEndian,
+                no_rows ? casacore::True : casacore::False
+            );
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_get_column_info(const GlueTable &table, const StringBridge &col_name,
+                          uint64_t *n_rows, GlueDataType *data_type,
+                          int *is_scalar, int *is_fixed_shape, int *n_dim,
+                          uint64_t dims[8], ExcInfo &exc)
+    {
+        try {
+            casacore::TableColumn col(table, bridge_string(col_name));
+            const casacore::ColumnDesc &desc = col.columnDesc();
+            const casacore::IPosition &shape = desc.shape();
+
+            if (shape.size() > 8)
+                throw std::runtime_error("cannot handle columns with data of dimensionality greater than 8");
+
+            *n_rows = table.nrow();
+            *data_type = desc.dataType();
+            *is_scalar = (int) desc.isScalar();
+            *is_fixed_shape = (int) desc.isFixedShape();
+            *n_dim = (int) desc.ndim();
+
+            for (int i = 0; i < *n_dim; i++) // note: for empty cols, n_dim = -1; this is OK
+                dims[*n_dim - 1 - i] = (uint64_t) shape[i];
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // This function assumes that the caller has already vetted the types and
+    // has figured how big `data` needs to be.
+    int
+    table_get_scalar_column_data(const GlueTable &table, const StringBridge &col_name,
+                                 void *data, ExcInfo &exc)
+    {
+        try {
+            const casacore::ColumnDesc &desc = casacore::TableColumn(table, bridge_string(col_name)).columnDesc();
+            casacore::IPosition shape(1, table.nrow());
+
+            switch (desc.dataType()) {
+
+#define CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: { \
+                casacore::ScalarColumn<CPPTYPE> col(table, bridge_string(col_name)); \
+                casacore::Vector<CPPTYPE> vec(shape, (CPPTYPE *) data, casacore::SHARE); \
+                col.getColumn(vec); \
+                break; \
+            }
+
+            CASE(TpBool, casacore::Bool)
+            CASE(TpChar, casacore::Char)
+            CASE(TpUChar, casacore::uChar)
+            CASE(TpShort, casacore::Short)
+            CASE(TpUShort, casacore::uShort)
+            CASE(TpInt, casacore::Int)
+            CASE(TpUInt, casacore::uInt)
+            CASE(TpInt64, casacore::Int64)
+            CASE(TpFloat, float)
+            CASE(TpDouble, double)
+            CASE(TpComplex, casacore::Complex)
+            CASE(TpDComplex, casacore::DComplex)
+
+#undef CASE
+
+            case casacore::TpString: {                                         \
+                casacore::ScalarColumn<casacore::String> col(table, bridge_string(col_name));
+                casacore::Vector<casacore::String> vec(shape);
+                col.getColumn(vec);
+                unbridge_string_array(vec, (StringBridge *) data);
+                break;
+            }
+
+            default:
+                throw std::runtime_error("unhandled scalar column data type");
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Like the above, but only reads `n_rows` rows starting at `start_row`,
+    // and also handles fixed-shape array columns. The caller must make sure
+    // that `data` is big enough to hold all of the cells in the range.
+    int
+    table_get_column_range_data(const GlueTable &table, const StringBridge &col_name,
+                                const uint64_t start_row, const uint64_t n_rows,
+                                void *data, ExcInfo &exc)
+    {
+        try {
+            const casacore::ColumnDesc &desc = casacore::TableColumn(table, bridge_string(col_name)).columnDesc();
+            casacore::Slicer rows(casacore::IPosition(1, start_row), casacore::IPosition(1, n_rows));
+
+            if (desc.isScalar()) {
+                casacore::IPosition shape(1, n_rows);
+
+                switch (desc.dataType()) {
+
+#define CASE(DTYPE, CPPTYPE) \
+                case casacore::DTYPE: { \
+                    casacore::ScalarColumn<CPPTYPE> col(table, bridge_string(col_name)); \
+                    casacore::Vector<CPPTYPE> vec(shape, (CPPTYPE *) data, casacore::SHARE); \
+                    col.getColumnRange(rows, vec); \
+                    break; \
+                }
+
+                CASE(TpBool, casacore::Bool)
+                CASE(TpChar, casacore::Char)
+                CASE(TpUChar, casacore::uChar)
+                CASE(TpShort, casacore::Short)
+                CASE(TpUShort, casacore::uShort)
+                CASE(TpInt, casacore::Int)
+                CASE(TpUInt, casacore::uInt)
+                CASE(TpInt64, casacore::Int64)
+                CASE(TpFloat, float)
+                CASE(TpDouble, double)
+                CASE(TpComplex, casacore::Complex)
+                CASE(TpDComplex, casacore::DComplex)
+
+#undef CASE
+
+                case casacore::TpString: {
+                    casacore::ScalarColumn<casacore::String> col(table, bridge_string(col_name));
+                    casacore::Vector<casacore::String> vec(shape);
+                    col.getColumnRange(rows, vec);
+                    unbridge_string_array(vec, (StringBridge *) data);
+                    break;
+                }
+
+                default:
+                    throw std::runtime_error("unhandled scalar column data type");
+                }
+            } else {
+                if (!desc.isFixedShape())
+                    throw std::runtime_error("cannot read ranges of variable-shape array columns");
+
+                casacore::IPosition shape(desc.shape());
+                shape.append(casacore::IPosition(1, n_rows));
+
+                switch (desc.dataType()) {
+
+#define CASE(DTYPE, CPPTYPE) \
+                case casacore::DTYPE: { \
+                    casacore::ArrayColumn<CPPTYPE> col(table, bridge_string(col_name)); \
+                    casacore::Array<CPPTYPE> array(shape, (CPPTYPE *) data, casacore::SHARE); \
+                    col.getColumnRange(rows, array); \
+                    break; \
+                }
+
+                CASE(TpBool, casacore::Bool)
+                CASE(TpChar, casacore::Char)
+                CASE(TpUChar, casacore::uChar)
+                CASE(TpShort, casacore::Short)
+                CASE(TpUShort, casacore::uShort)
+                CASE(TpInt, casacore::Int)
+                CASE(TpUInt, casacore::uInt)
+                CASE(TpInt64, casacore::Int64)
+                CASE(TpFloat, float)
+                CASE(TpDouble, double)
+                CASE(TpComplex, casacore::Complex)
+                CASE(TpDComplex, casacore::DComplex)
+
+#undef CASE
+
+                default:
+                    throw std::runtime_error("unhandled array column data type");
+                }
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Packing of booleans into bitmaps, least significant bit first. FLAG
+    // columns are often the largest in a table after DATA, so it is worth
+    // doing this a byte at a time rather than a bit at a time.
+    static void
+    pack_bools(const casacore::Bool *values, const uint64_t n, uint8_t *bits)
+    {
+        const uint64_t n_full = n / 8;
+
+        for (uint64_t i = 0; i < n_full; i++) {
+            const casacore::Bool *v = values + 8 * i;
+            bits[i] = (v[0] ? 0x01 : 0) | (v[1] ? 0x02 : 0) | (v[2] ? 0x04 : 0) | (v[3] ? 0x08 : 0) |
+                (v[4] ? 0x10 : 0) | (v[5] ? 0x20 : 0) | (v[6] ? 0x40 : 0) | (v[7] ? 0x80 : 0);
+        }
+
+        if (n % 8) {
+            uint8_t last = 0;
+
+            for (uint64_t j = 0; j < n % 8; j++) {
+                if (values[8 * n_full + j])
+                    last |= 1 << j;
+            }
+
+            bits[n_full] = last;
+        }
+    }
+
+    static void
+    unpack_bools(const uint8_t *bits, const uint64_t n, casacore::Bool *values)
+    {
+        for (uint64_t i = 0; i < n / 8; i++) {
+            const uint8_t b = bits[i];
+            casacore::Bool *v = values + 8 * i;
+
+            for (int j = 0; j < 8; j++)
+                v[j] = (b >> j) & 1;
+        }
+
+        for (uint64_t i = n - n % 8; i < n; i++)
+            values[i] = (bits[i / 8] >> (i % 8)) & 1;
+    }
+
+    // Like table_get_column_range_data, but only for boolean columns, with
+    // the values packed into the bitmap *bits*, which must have room for all
+    // of them. Scalar and fixed-shape array columns are supported.
+    int
+    table_get_bool_range_bits(const GlueTable &table, const StringBridge &col_name,
+                              const uint64_t start_row, const uint64_t n_rows,
+                              uint8_t *bits, ExcInfo &exc)
+    {
+        try {
+            const casacore::ColumnDesc &desc = casacore::TableColumn(table, bridge_string(col_name)).columnDesc();
+            casacore::Slicer rows(casacore::IPosition(1, start_row), casacore::IPosition(1, n_rows));
+
+            if (desc.dataType() != casacore::TpBool)
+                throw std::runtime_error("can only read boolean columns as bitmaps");
+
+            casacore::IPosition shape(1, n_rows);
+
+            if (!desc.isScalar()) {
+                if (!desc.isFixedShape())
+                    throw std::runtime_error("cannot read ranges of variable-shape array columns");
+
+                shape = desc.shape();
+                shape.append(casacore::IPosition(1, n_rows));
+            }
+
+            casacore::Array<casacore::Bool> values(shape);
+
+            if (desc.isScalar()) {
+                casacore::ScalarColumn<casacore::Bool> col(table, bridge_string(col_name));
+                casacore::Vector<casacore::Bool> vec(values);
+                col.getColumnRange(rows, vec);
+            } else {
+                casacore::ArrayColumn<casacore::Bool> col(table, bridge_string(col_name));
+                col.getColumnRange(rows, values);
+            }
+
+            pack_bools(values.data(), values.nelements(), bits);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Conversions between single- and double-precision complex values, so
+    // that code standardized on one precision can use columns stored in the
+    // other. Widening is done in place, in a buffer big enough to hold the
+    // output, so that visibility data never need a second full-size buffer.
+    // Values are moved with memcpy to stay clear of aliasing rules.
+    static void
+    widen_complex_in_place(void *data, const uint64_t n)
+    {
+        char *bytes = (char *) data;
+
+        // Work backwards: output value i overlaps input values 2i and 2i + 1,
+        // which have been converted by the time that it is written.
+        for (uint64_t i = n; i > 0; i--) {
+            float in[2];
+            memcpy(in, bytes + 8 * (i - 1), sizeof(in));
+            const double out[2] = { in[0], in[1] };
+            memcpy(bytes + 16 * (i - 1), out, sizeof(out));
+        }
+    }
+
+    static void
+    narrow_complex(const casacore::DComplex *values, const uint64_t n, void *dest)
+    {
+        casacore::Complex *out = (casacore::Complex *) dest;
+
+        for (uint64_t i = 0; i < n; i++)
+            out[i] = casacore::Complex(values[i].real(), values[i].imag());
+    }
+
+    // Like table_get_column_range_data, but converting complex values to the
+    // precision *as_type*, which is TpComplex or TpDComplex.
+    int
+    table_get_column_range_data_as(const GlueTable &table, const StringBridge &col_name,
+                                   const uint64_t start_row, const uint64_t n_rows,
+                                   const GlueDataType as_type, void *data, ExcInfo &exc)
+    {
+        try {
+            const casacore::ColumnDesc &desc = casacore::TableColumn(table, bridge_string(col_name)).columnDesc();
+            uint64_t n_items = n_rows;
+
+            if (!desc.isScalar()) {
+                if (!desc.isFixedShape())
+                    throw std::runtime_error("cannot read ranges of variable-shape array columns");
+
+                n_items *= desc.shape().product();
+            }
+
+            if (desc.dataType() == casacore::TpComplex && as_type == casacore::TpDComplex) {
+                if (table_get_column_range_data(table, col_name, start_row, n_rows, data, exc))
+                    return 1;
+
+                widen_complex_in_place(data, n_items);
+            } else if (desc.dataType() == casacore::TpDComplex && as_type == casacore::TpComplex) {
+                std::vector<casacore::DComplex> values(n_items);
+
+                if (table_get_column_range_data(table, col_name, start_row, n_rows, values.data(), exc))
+                    return 1;
+
+                narrow_complex(values.data(), n_items, data);
+            } else {
+                throw std::runtime_error("unsupported conversion between column data types");
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_get_cell_info(const GlueTable &table, const StringBridge &col_name,
+                        uint64_t row_number, GlueDataType *data_type,
+                        int *n_dim, uint64_t dims[8], ExcInfo &exc)
+    {
+        try {
+            casacore::TableColumn col(table, bridge_string(col_name));
+            const casacore::ColumnDesc &desc = col.columnDesc();
+
+            *data_type = desc.dataType();
+
+            if (desc.isScalar())
+                *n_dim = 0;
+            else {
+                *n_dim = (int) col.ndim(row_number);
+
+                if (*n_dim > 8)
+                    throw std::runtime_error("cannot handle cells with data of dimensionality greater than 8");
+
+                const casacore::IPosition shape = col.shape(row_number);
+
+                for (int i = 0; i < *n_dim; i++)
+                    dims[*n_dim - 1 - i] = (uint64_t) shape[i];
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_cell_is_defined(const GlueTable &table, const StringBridge &col_name,
+                          const uint64_t row_number, int *is_defined, ExcInfo &exc)
+    {
+        try {
+            casacore::TableColumn col(table, bridge_string(col_name));
+            *is_defined = col.isDefined(row_number) ? 1 : 0;
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_set_tile_cache_size(const GlueTable &table, const StringBridge &col_name,
+                              const uint32_t n_mib, ExcInfo &exc)
+    {
+        try {
+            casacore::ROTiledStManAccessor accessor(table, bridge_string(col_name), true);
+            accessor.setMaximumCacheSize(n_mib);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // This function assumes that the caller has already vetted the types and
     // has figured how big `data` needs to be.
     int
-    table_get_cell(const GlueTable &table, const StringBridge &col_name,
-                   const unsigned long row_number, void *data, ExcInfo &exc)
+    table_get_cell(const GlueTable &table, const StringBridge &col_name,
+                   const uint64_t row_number, void *data, ExcInfo &exc)
+    {
+        try {
+            casacore::TableColumn col(table, bridge_string(col_name));
+            const casacore::ColumnDesc &desc = col.columnDesc();
+            casacore::IPosition shape;
+
+            if (!desc.isScalar())
+                shape = col.shape(row_number);
+
+            switch (desc.trueDataType()) {
+
+#define SCALAR_CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: { \
+                casacore::ScalarColumn<CPPTYPE> col(table, bridge_string(col_name)); \
+                *((CPPTYPE *) data) = col.get(row_number); \
+                break; \
+            }
+
+#define VECTOR_CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: { \
+                casacore::ArrayColumn<CPPTYPE> col(table, bridge_string(col_name)); \
+                casacore::Array<CPPTYPE> array(shape, (CPPTYPE *) data, casacore::SHARE); \
+                col.get(row_number, array, casacore::False); \
+                break; \
+            }
+
+            SCALAR_CASE(TpBool, casacore::Bool)
+            SCALAR_CASE(TpChar, casacore::Char)
+            SCALAR_CASE(TpUChar, casacore::uChar)
+            SCALAR_CASE(TpShort, casacore::Short)
+            SCALAR_CASE(TpUShort, casacore::uShort)
+            SCALAR_CASE(TpInt, casacore::Int)
+            SCALAR_CASE(TpUInt, casacore::uInt)
+            SCALAR_CASE(TpFloat, float)
+            SCALAR_CASE(TpDouble, double)
+            SCALAR_CASE(TpComplex, casacore::Complex)
+            SCALAR_CASE(TpDComplex, casacore::DComplex)
+
+            VECTOR_CASE(TpArrayBool, casacore::Bool)
+            VECTOR_CASE(TpArrayChar, casacore::Char)
+            VECTOR_CASE(TpArrayUChar, casacore::uChar)
+            VECTOR_CASE(TpArrayShort, casacore::Short)
+            VECTOR_CASE(TpArrayUShort, casacore::uShort)
+            VECTOR_CASE(TpArrayInt, casacore::Int)
+            VECTOR_CASE(TpArrayUInt, casacore::uInt)
+            VECTOR_CASE(TpArrayFloat, float)
+            VECTOR_CASE(TpArrayDouble, double)
+            VECTOR_CASE(TpArrayComplex, casacore::Complex)
+            VECTOR_CASE(TpArrayDComplex, casacore::DComplex)
+
+#undef SCALAR_CASE
+#undef VECTOR_CASE
+
+            case casacore::TpString: {                                         \
+                casacore::ScalarColumn<casacore::String> col(table, bridge_string(col_name));
+                unbridge_string_owned(col.get(row_number), *((StringBridge *) data));
+                break;
+            }
+
+            case casacore::TpArrayString: {
+                casacore::ArrayColumn<casacore::String> col(table, bridge_string(col_name));
+                casacore::Array<casacore::String> array(shape);
+                col.get(row_number, array, casacore::False);
+                unbridge_string_array(array, (StringBridge *) data);
+                break;
+            }
+
+            default:
+                throw std::runtime_error("unhandled cell data type");
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Read an array cell into a newly allocated array and lend its storage
+    // to the caller, who must release it with `array_holder_free`. Unlike
+    // table_get_cell, this does not copy the data into a caller-provided
+    // buffer.
+    int
+    table_get_cell_borrowed(const GlueTable &table, const StringBridge &col_name,
+                            const uint64_t row_number, void **holder,
+                            const void **data, ExcInfo &exc)
+    {
+        ArrayHolderBase *result = NULL;
+
+        try {
+            casacore::TableColumn col(table, bridge_string(col_name));
+
+            switch (col.columnDesc().trueDataType()) {
+
+#define CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: { \
+                ArrayHolder<CPPTYPE> *typed = new ArrayHolder<CPPTYPE>(); \
+                result = typed; \
+                casacore::ArrayColumn<CPPTYPE> col(table, bridge_string(col_name)); \
+                col.get(row_number, typed->array, casacore::True); \
+                if (!typed->array.contiguousStorage()) \
+                    throw std::runtime_error("cell data are not stored contiguously"); \
+                *data = typed->array.data(); \
+                break; \
+            }
+
+            CASE(TpArrayBool, casacore::Bool)
+            CASE(TpArrayChar, casacore::Char)
+            CASE(TpArrayUChar, casacore::uChar)
+            CASE(TpArrayShort, casacore::Short)
+            CASE(TpArrayUShort, casacore::uShort)
+            CASE(TpArrayInt, casacore::Int)
+            CASE(TpArrayUInt, casacore::uInt)
+            CASE(TpArrayInt64, casacore::Int64)
+            CASE(TpArrayFloat, float)
+            CASE(TpArrayDouble, double)
+            CASE(TpArrayComplex, casacore::Complex)
+            CASE(TpArrayDComplex, casacore::DComplex)
+
+#undef CASE
+
+            default:
+                throw std::runtime_error("cannot borrow cell data of this type");
+            }
+        } catch (...) {
+            delete result;
+            handle_exception(exc);
+            return 1;
+        }
+
+        *holder = result;
+        return 0;
+    }
+
+    void
+    array_holder_free(void *holder)
+    {
+        delete (ArrayHolderBase *) holder;
+    }
+
+    // The inverse of table_get_column_range_data, for scalar columns only.
+    int
+    table_put_column_range_data(GlueTable &table, const StringBridge &col_name,
+                                const uint64_t start_row, const uint64_t n_rows,
+                                const void *data, ExcInfo &exc)
+    {
+        try {
+            const casacore::ColumnDesc &desc = casacore::TableColumn(table, bridge_string(col_name)).columnDesc();
+            casacore::Slicer rows(casacore::IPosition(1, start_row), casacore::IPosition(1, n_rows));
+            casacore::IPosition shape(1, n_rows);
+
+            if (!desc.isScalar())
+                throw std::runtime_error("can only put ranges of rows into scalar columns");
+
+            switch (desc.dataType()) {
+
+#define CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: { \
+                casacore::ScalarColumn<CPPTYPE> col(table, bridge_string(col_name)); \
+                const casacore::Vector<CPPTYPE> vec(shape, (CPPTYPE *) data, casacore::SHARE); \
+                col.putColumnRange(rows, vec); \
+                break; \
+            }
+
+            CASE(TpBool, casacore::Bool)
+            CASE(TpChar, casacore::Char)
+            CASE(TpUChar, casacore::uChar)
+            CASE(TpShort, casacore::Short)
+            CASE(TpUShort, casacore::uShort)
+            CASE(TpInt, casacore::Int)
+            CASE(TpUInt, casacore::uInt)
+            CASE(TpInt64, casacore::Int64)
+            CASE(TpFloat, float)
+            CASE(TpDouble, double)
+            CASE(TpComplex, casacore::Complex)
+            CASE(TpDComplex, casacore::DComplex)
+
+#undef CASE
+
+            case casacore::TpString: {
+                casacore::ScalarColumn<casacore::String> col(table, bridge_string(col_name));
+                const casacore::Vector<casacore::String> vec(bridge_string_array((const StringBridge *) data, shape));
+                col.putColumnRange(rows, vec);
+                break;
+            }
+
+            default:
+                throw std::runtime_error("unhandled scalar column data type");
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Like table_put_column_range_data, but for array columns: the cells
+    // all have the shape *dims* (in C order), and the rows are stored one
+    // after another in *data*.
+    int
+    table_put_column_range_cells(GlueTable &table, const StringBridge &col_name,
+                                 const uint64_t start_row, const uint64_t n_rows,
+                                 const GlueDataType data_type,
+                                 const uint64_t n_dims, const uint64_t *dims,
+                                 const void *data, ExcInfo &exc)
+    {
+        try {
+            casacore::Slicer rows(casacore::IPosition(1, start_row), casacore::IPosition(1, n_rows));
+            casacore::IPosition shape(n_dims + 1);
+
+            for (casacore::uInt i = 0; i < n_dims; i++)
+                shape[i] = dims[n_dims - 1 - i];
+
+            shape[n_dims] = n_rows;
+
+            switch (data_type) {
+
+#define CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: { \
+                casacore::ArrayColumn<CPPTYPE> col(table, bridge_string(col_name)); \
+                const casacore::Array<CPPTYPE> array(shape, (CPPTYPE *) data, casacore::SHARE); \
+                col.putColumnRange(rows, array); \
+                break; \
+            }
+
+            CASE(TpArrayBool, casacore::Bool)
+            CASE(TpArrayChar, casacore::Char)
+            CASE(TpArrayUChar, casacore::uChar)
+            CASE(TpArrayShort, casacore::Short)
+            CASE(TpArrayUShort, casacore::uShort)
+            CASE(TpArrayInt, casacore::Int)
+            CASE(TpArrayUInt, casacore::uInt)
+            CASE(TpArrayInt64, casacore::Int64)
+            CASE(TpArrayFloat, float)
+            CASE(TpArrayDouble, double)
+            CASE(TpArrayComplex, casacore::Complex)
+            CASE(TpArrayDComplex, casacore::DComplex)
+
+#undef CASE
+
+            default:
+                throw std::runtime_error("unhandled array column data type");
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // The inverse of table_get_bool_range_bits.
+    int
+    table_put_bool_range_bits(GlueTable &table, const StringBridge &col_name,
+                              const uint64_t start_row, const uint64_t n_rows,
+                              const uint8_t *bits, ExcInfo &exc)
     {
         try {
-            casacore::TableColumn col(table, bridge_string(col_name));
-            const casacore::ColumnDesc &desc = col.columnDesc();
-            casacore::IPosition shape;
+            const casacore::ColumnDesc &desc = casacore::TableColumn(table, bridge_string(col_name)).columnDesc();
+            casacore::Slicer rows(casacore::IPosition(1, start_row), casacore::IPosition(1, n_rows));
 
-            if (!desc.isScalar())
-                shape = col.shape(row_number);
+            if (desc.dataType() != casacore::TpBool)
+                throw std::runtime_error("can only write bitmaps into boolean columns");
 
-            switch (desc.trueDataType()) {
+            casacore::IPosition shape(1, n_rows);
+
+            if (!desc.isScalar()) {
+                if (!desc.isFixedShape())
+                    throw std::runtime_error("cannot write ranges of variable-shape array columns");
+
+                shape = desc.shape();
+                shape.append(casacore::IPosition(1, n_rows));
+            }
+
+            casacore::Array<casacore::Bool> values(shape);
+            unpack_bools(bits, values.nelements(), values.data());
+
+            if (desc.isScalar()) {
+                casacore::ScalarColumn<casacore::Bool> col(table, bridge_string(col_name));
+                const casacore::Vector<casacore::Bool> vec(values);
+                col.putColumnRange(rows, vec);
+            } else {
+                casacore::ArrayColumn<casacore::Bool> col(table, bridge_string(col_name));
+                col.putColumnRange(rows, values);
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_put_cell(GlueTable &table, const StringBridge &col_name,
+                   const uint64_t row_number, const GlueDataType data_type,
+                   const uint64_t n_dims, const uint64_t *dims,
+                   void *data, ExcInfo &exc)
+    {
+        try {
+            switch (data_type) {
 
 #define SCALAR_CASE(DTYPE, CPPTYPE) \
             case casacore::DTYPE: { \
                 casacore::ScalarColumn<CPPTYPE> col(table, bridge_string(col_name)); \
-                *((CPPTYPE *) data) = col.get(row_number); \
+                col.put(row_number, *(CPPTYPE *) data); \
                 break; \
             }
 
 #define VECTOR_CASE(DTYPE, CPPTYPE) \
             case casacore::DTYPE: { \
                 casacore::ArrayColumn<CPPTYPE> col(table, bridge_string(col_name)); \
+                casacore::IPosition shape(n_dims); \
+                for (casacore::uInt i = 0; i < n_dims; i++) \
+                    shape[i] = dims[n_dims - 1 - i]; \
                 casacore::Array<CPPTYPE> array(shape, (CPPTYPE *) data, casacore::SHARE); \
-                col.get(row_number, array, casacore::False); \
+                col.put(row_number, array); \
                 break; \
             }
 
@@ -431,16 +1832,230 @@ extern "C" {
 #undef SCALAR_CASE
 #undef VECTOR_CASE
 
-            case casacore::TpString: {                                         \
+            case casacore::TpString: {
                 casacore::ScalarColumn<casacore::String> col(table, bridge_string(col_name));
-                unbridge_string(col.get(row_number), *((StringBridge *) data));
+                col.put(row_number, bridge_string(*((StringBridge *) data)));
                 break;
             }
 
             case casacore::TpArrayString: {
                 casacore::ArrayColumn<casacore::String> col(table, bridge_string(col_name));
+                casacore::IPosition shape(n_dims);
+                for (casacore::uInt i = 0; i < n_dims; i++)
+                    shape[i] = dims[n_dims - 1 - i];
+                col.put(row_number, bridge_string_array((const StringBridge *) data, shape));
+                break;
+            }
+
+            default:
+                throw std::runtime_error("unhandled cell data type");
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_add_rows(GlueTable &table, const uint64_t n_rows, ExcInfo &exc)
+    {
+        try {
+            table.addRow(n_rows);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Write all pending changes to a table and its subtables to disk. If
+    // `fsync` is nonzero, also wait for the operating system to commit them
+    // to storage.
+    int
+    table_flush(GlueTable &table, const int fsync, ExcInfo &exc)
+    {
+        try {
+            table.flush(fsync ? casacore::True : casacore::False, casacore::True);
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Rows
+
+    GlueTableRow *
+    table_row_alloc(const GlueTable &table, const unsigned char is_read_only, ExcInfo &exc)
+    {
+        try {
+            if (is_read_only)
+                return new casacore::ROTableRow(table);
+            else
+                return new casacore::TableRow(table);
+        } catch (...) {
+            handle_exception(exc);
+            return NULL;
+        }
+    }
+
+    int
+    table_row_free(GlueTableRow *row, ExcInfo &exc)
+    {
+        try {
+            delete row;
+            return 0;
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+    }
+
+    int
+    table_row_read(GlueTableRow &row, const uint64_t row_number, ExcInfo &exc)
+    {
+        try {
+            row.get(row_number);
+            return 0;
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+    }
+
+    int
+    table_row_copy_and_put(GlueTableRow &src_row, const uint64_t dest_row_number,
+                           GlueTableRow &wrap_dest_row, ExcInfo &exc)
+    {
+        casacore::TableRow &dest_row = (casacore::TableRow &) wrap_dest_row;
+
+        try {
+            dest_row.put(dest_row_number, src_row.record(), src_row.getDefined());
+            return 0;
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+    }
+
+    int
+    table_row_get_cell_info(const GlueTableRow &row, const StringBridge &col_name,
+                            GlueDataType *data_type, int *n_dim,
+                            uint64_t dims[8], ExcInfo &exc)
+    {
+        try {
+            const casacore::TableRecord &rec = row.record();
+            const casacore::RecordDesc &desc = rec.description();
+            casacore::Int field_num = rec.fieldNumber(bridge_string(col_name));
+
+            if (field_num < 0)
+                throw std::runtime_error("unrecognized column name");
+
+            *data_type = rec.type(field_num);
+
+            if (desc.isScalar(field_num))
+                *n_dim = 0;
+            else {
+                // desc.shape() is generic, not specific to the row we're
+                // looking at, so we have to create a TableColumn to get the
+                // cell's shape.
+                casacore::TableColumn col(row.table(), bridge_string(col_name));
+                *n_dim = (int) col.ndim(row.rowNumber());
+
+                if (*n_dim > 8)
+                    throw std::runtime_error("cannot handle cells with data of dimensionality greater than 8");
+
+                const casacore::IPosition shape = col.shape(row.rowNumber());
+
+                for (int i = 0; i < *n_dim; i++)
+                    dims[*n_dim - 1 - i] = (uint64_t) shape[i];
+            }
+
+            return 0;
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+    }
+
+    // This function assumes that the caller has already vetted the types and
+    // has figured how big `data` needs to be.
+    int
+    table_row_get_cell(const GlueTableRow &row, const StringBridge &col_name,
+                       void *data, ExcInfo &exc)
+    {
+        try {
+            const casacore::TableRecord &rec = row.record();
+            const casacore::RecordDesc &desc = rec.description();
+            casacore::Int field_num = rec.fieldNumber(bridge_string(col_name));
+            casacore::IPosition shape;
+
+            if (field_num < 0)
+                throw std::runtime_error("unrecognized column name");
+
+            if (!desc.isScalar(field_num)) {
+                casacore::TableColumn col(row.table(), bridge_string(col_name));
+                shape = col.shape(row.rowNumber());
+            }
+
+            switch (rec.type(field_num)) {
+
+#define SCALAR_CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: { \
+                CPPTYPE datum; \
+                rec.get(field_num, datum); \
+                *((CPPTYPE *) data) = datum; \
+                break; \
+            }
+
+#define VECTOR_CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: { \
+                casacore::Array<CPPTYPE> array(shape, (CPPTYPE *) data, casacore::SHARE); \
+                rec.get(field_num, array); \
+                break; \
+            }
+
+            SCALAR_CASE(TpBool, casacore::Bool)
+            //SCALAR_CASE(TpChar, casacore::Char)
+            SCALAR_CASE(TpUChar, casacore::uChar)
+            SCALAR_CASE(TpShort, casacore::Short)
+            //SCALAR_CASE(TpUShort, casacore::uShort)
+            SCALAR_CASE(TpInt, casacore::Int)
+            SCALAR_CASE(TpUInt, casacore::uInt)
+            SCALAR_CASE(TpFloat, float)
+            SCALAR_CASE(TpDouble, double)
+            SCALAR_CASE(TpComplex, casacore::Complex)
+            SCALAR_CASE(TpDComplex, casacore::DComplex)
+
+            VECTOR_CASE(TpArrayBool, casacore::Bool)
+            //VECTOR_CASE(TpArrayChar, casacore::Char)
+            VECTOR_CASE(TpArrayUChar, casacore::uChar)
+            VECTOR_CASE(TpArrayShort, casacore::Short)
+            //VECTOR_CASE(TpArrayUShort, casacore::uShort)
+            VECTOR_CASE(TpArrayInt, casacore::Int)
+            VECTOR_CASE(TpArrayUInt, casacore::uInt)
+            VECTOR_CASE(TpArrayFloat, float)
+            VECTOR_CASE(TpArrayDouble, double)
+            VECTOR_CASE(TpArrayComplex, casacore::Complex)
+            VECTOR_CASE(TpArrayDComplex, casacore::DComplex)
+
+#undef SCALAR_CASE
+#undef VECTOR_CASE
+
+            case casacore::TpString: {
+                casacore::String datum;
+                rec.get(field_num, datum);
+                unbridge_string_owned(datum, *(StringBridge *) data);
+                break;
+            }
+
+            case casacore::TpArrayString: {
                 casacore::Array<casacore::String> array(shape);
-                col.get(row_number, array, casacore::False);
+                rec.get(field_num, array);
                 unbridge_string_array(array, (StringBridge *) data);
                 break;
             }
@@ -457,37 +2072,39 @@ extern "C" {
     }
 
     int
-    table_put_cell(GlueTable &table, const StringBridge &col_name,
-                   const unsigned long row_number, const GlueDataType data_type,
-                   const unsigned long n_dims, const unsigned long *dims,
-                   void *data, ExcInfo &exc)
+    table_row_put_cell(GlueTableRow &wrap_row, const StringBridge &col_name,
+                       const GlueDataType data_type, const uint64_t n_dims,
+                       const uint64_t *dims, void *data, ExcInfo &exc)
     {
+        casacore::TableRow &row = (casacore::TableRow &) wrap_row;
+
         try {
+            casacore::TableRecord &rec = row.record();
+            casacore::Int field_num = rec.fieldNumber(bridge_string(col_name));
+
             switch (data_type) {
 
 #define SCALAR_CASE(DTYPE, CPPTYPE) \
             case casacore::DTYPE: { \
-                casacore::ScalarColumn<CPPTYPE> col(table, bridge_string(col_name)); \
-                col.put(row_number, *(CPPTYPE *) data); \
+                rec.define(field_num, *(CPPTYPE *) data); \
                 break; \
             }
 
 #define VECTOR_CASE(DTYPE, CPPTYPE) \
             case casacore::DTYPE: { \
-                casacore::ArrayColumn<CPPTYPE> col(table, bridge_string(col_name)); \
                 casacore::IPosition shape(n_dims); \
                 for (casacore::uInt i = 0; i < n_dims; i++) \
                     shape[i] = dims[n_dims - 1 - i]; \
                 casacore::Array<CPPTYPE> array(shape, (CPPTYPE *) data, casacore::SHARE); \
-                col.put(row_number, array); \
+                rec.define(field_num, array); \
                 break; \
             }
 
             SCALAR_CASE(TpBool, casacore::Bool)
-            SCALAR_CASE(TpChar, casacore::Char)
+            //SCALAR_CASE(TpChar, casacore::Char)
             SCALAR_CASE(TpUChar, casacore::uChar)
             SCALAR_CASE(TpShort, casacore::Short)
-            SCALAR_CASE(TpUShort, casacore::uShort)
+            //SCALAR_CASE(TpUShort, casacore::uShort)
             SCALAR_CASE(TpInt, casacore::Int)
             SCALAR_CASE(TpUInt, casacore::uInt)
             SCALAR_CASE(TpFloat, float)
@@ -496,10 +2113,10 @@ extern "C" {
             SCALAR_CASE(TpDComplex, casacore::DComplex)
 
             VECTOR_CASE(TpArrayBool, casacore::Bool)
-            VECTOR_CASE(TpArrayChar, casacore::Char)
+            //VECTOR_CASE(TpArrayChar, casacore::Char)
             VECTOR_CASE(TpArrayUChar, casacore::uChar)
             VECTOR_CASE(TpArrayShort, casacore::Short)
-            VECTOR_CASE(TpArrayUShort, casacore::uShort)
+            //VECTOR_CASE(TpArrayUShort, casacore::uShort)
             VECTOR_CASE(TpArrayInt, casacore::Int)
             VECTOR_CASE(TpArrayUInt, casacore::uInt)
             VECTOR_CASE(TpArrayFloat, float)
@@ -511,17 +2128,15 @@ extern "C" {
 #undef VECTOR_CASE
 
             case casacore::TpString: {
-                casacore::ScalarColumn<casacore::String> col(table, bridge_string(col_name));
-                col.put(row_number, bridge_string(*((StringBridge *) data)));
+                rec.define(field_num, bridge_string(*(StringBridge *) data));
                 break;
             }
 
             case casacore::TpArrayString: {
-                casacore::ArrayColumn<casacore::String> col(table, bridge_string(col_name));
                 casacore::IPosition shape(n_dims);
                 for (casacore::uInt i = 0; i < n_dims; i++)
                     shape[i] = dims[n_dims - 1 - i];
-                col.put(row_number, bridge_string_array((const StringBridge *) data, shape));
+                rec.define(field_num, bridge_string_array((const StringBridge *) data, shape));
                 break;
             }
 
@@ -537,10 +2152,12 @@ extern "C" {
     }
 
     int
-    table_add_rows(GlueTable &table, const unsigned long n_rows, ExcInfo &exc)
+    table_row_write(GlueTableRow &wrap_row, const uint64_t dest_row_number, ExcInfo &exc)
     {
+        casacore::TableRow &row = (casacore::TableRow &) wrap_row;
+
         try {
-            table.addRow(n_rows);
+            row.put(dest_row_number);
         } catch (...) {
             handle_exception(exc);
             return 1;
@@ -549,54 +2166,90 @@ extern "C" {
         return 0;
     }
 
-    // Rows
-
-    GlueTableRow *
-    table_row_alloc(const GlueTable &table, const unsigned char is_read_only, ExcInfo &exc)
-    {
-        try {
-            if (is_read_only)
-                return new casacore::ROTableRow(table);
-            else
-                return new casacore::TableRow(table);
-        } catch (...) {
-            handle_exception(exc);
-            return NULL;
-        }
-    }
-
     int
-    table_row_free(GlueTableRow *row, ExcInfo &exc)
+    table_has_column(const GlueTable &table, const StringBridge &col_name,
+                     int *has_column, ExcInfo &exc)
     {
         try {
-            delete row;
-            return 0;
+            *has_column = (int) table.tableDesc().isColumn(bridge_string(col_name));
         } catch (...) {
             handle_exception(exc);
             return 1;
         }
+
+        return 0;
     }
 
-    int
-    table_row_read(GlueTableRow &row, const unsigned long row_number, ExcInfo &exc)
+    // Column handles. The column object is created with the C++ type
+    // matching the column's data type, so that later accesses can downcast
+    // it without looking the column up again by name. The handle refers to
+    // the table without keeping it alive, so it must be freed before the
+    // table is closed.
+    GlueTableColumn *
+    table_column_alloc(const GlueTable &table, const StringBridge &col_name,
+                       GlueDataType *data_type, int *is_scalar, ExcInfo &exc)
     {
         try {
-            row.get(row_number);
-            return 0;
+            const casacore::ColumnDesc &desc = casacore::TableColumn(table, bridge_string(col_name)).columnDesc();
+
+            *data_type = desc.dataType();
+            *is_scalar = (int) desc.isScalar();
+
+            switch (desc.trueDataType()) {
+
+#define SCALAR_CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: \
+                return new casacore::ScalarColumn<CPPTYPE>(table, bridge_string(col_name));
+
+#define VECTOR_CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: \
+                return new casacore::ArrayColumn<CPPTYPE>(table, bridge_string(col_name));
+
+            SCALAR_CASE(TpBool, casacore::Bool)
+            SCALAR_CASE(TpChar, casacore::Char)
+            SCALAR_CASE(TpUChar, casacore::uChar)
+            SCALAR_CASE(TpShort, casacore::Short)
+            SCALAR_CASE(TpUShort, casacore::uShort)
+            SCALAR_CASE(TpInt, casacore::Int)
+            SCALAR_CASE(TpUInt, casacore::uInt)
+            SCALAR_CASE(TpInt64, casacore::Int64)
+            SCALAR_CASE(TpFloat, float)
+            SCALAR_CASE(TpDouble, double)
+            SCALAR_CASE(TpComplex, casacore::Complex)
+            SCALAR_CASE(TpDComplex, casacore::DComplex)
+            SCALAR_CASE(TpString, casacore::String)
+
+            VECTOR_CASE(TpArrayBool, casacore::Bool)
+            VECTOR_CASE(TpArrayChar, casacore::Char)
+            VECTOR_CASE(TpArrayUChar, casacore::uChar)
+            VECTOR_CASE(TpArrayShort, casacore::Short)
+            VECTOR_CASE(TpArrayUShort, casacore::uShort)
+            VECTOR_CASE(TpArrayInt, casacore::Int)
+            VECTOR_CASE(TpArrayUInt, casacore::uInt)
+            VECTOR_CASE(TpArrayInt64, casacore::Int64)
+            VECTOR_CASE(TpArrayFloat, float)
+            VECTOR_CASE(TpArrayDouble, double)
+            VECTOR_CASE(TpArrayComplex, casacore::Complex)
+            VECTOR_CASE(TpArrayDComplex, casacore::DComplex)
+            VECTOR_CASE(TpArrayString, casacore::String)
+
+#undef SCALAR_CASE
+#undef VECTOR_CASE
+
+            default:
+                throw std::runtime_error("unhandled column data type");
+            }
         } catch (...) {
             handle_exception(exc);
-            return 1;
+            return NULL;
         }
     }
 
     int
-    table_row_copy_and_put(GlueTableRow &src_row, const unsigned long dest_row_number,
-                           GlueTableRow &wrap_dest_row, ExcInfo &exc)
+    table_column_free(GlueTableColumn *column, ExcInfo &exc)
     {
-        casacore::TableRow &dest_row = (casacore::TableRow &) wrap_dest_row;
-
         try {
-            dest_row.put(dest_row_number, src_row.record(), src_row.getDefined());
+            delete column;
             return 0;
         } catch (...) {
             handle_exception(exc);
@@ -605,101 +2258,76 @@ extern "C" {
     }
 
     int
-    table_row_get_cell_info(const GlueTableRow &row, const StringBridge &col_name,
-                            GlueDataType *data_type, int *n_dim,
-                            unsigned long dims[8], ExcInfo &exc)
+    table_column_get_cell_shape(const GlueTableColumn &column, const uint64_t row_number,
+                                int *n_dim, uint64_t dims[8], ExcInfo &exc)
     {
         try {
-            const casacore::TableRecord &rec = row.record();
-            const casacore::RecordDesc &desc = rec.description();
-            casacore::Int field_num = rec.fieldNumber(bridge_string(col_name));
-
-            if (field_num < 0)
-                throw std::runtime_error("unrecognized column name");
-
-            *data_type = rec.type(field_num);
-
-            if (desc.isScalar(field_num))
+            if (column.columnDesc().isScalar()) {
                 *n_dim = 0;
-            else {
-                // desc.shape() is generic, not specific to the row we're
-                // looking at, so we have to create a TableColumn to get the
-                // cell's shape.
-                casacore::TableColumn col(row.table(), bridge_string(col_name));
-                *n_dim = (int) col.ndim(row.rowNumber());
-
-                if (*n_dim > 8)
-                    throw std::runtime_error("cannot handle cells with data of dimensionality greater than 8");
+                return 0;
+            }
 
-                const casacore::IPosition shape = col.shape(row.rowNumber());
+            const casacore::IPosition shape = column.shape(row_number);
+            *n_dim = (int) shape.size();
 
-                for (int i = 0; i < *n_dim; i++)
-                    dims[*n_dim - 1 - i] = (unsigned long) shape[i];
-            }
+            if (*n_dim > 8)
+                throw std::runtime_error("cannot handle cells with data of dimensionality greater than 8");
 
-            return 0;
+            for (int i = 0; i < *n_dim; i++)
+                dims[*n_dim - 1 - i] = (uint64_t) shape[i];
         } catch (...) {
             handle_exception(exc);
             return 1;
         }
+
+        return 0;
     }
 
-    // This function assumes that the caller has already vetted the types and
-    // has figured how big `data` needs to be.
+    // As with table_get_cell, the caller must make sure that `data` is big
+    // enough to hold the cell.
     int
-    table_row_get_cell(const GlueTableRow &row, const StringBridge &col_name,
-                       void *data, ExcInfo &exc)
+    table_column_get_cell(const GlueTableColumn &column, const uint64_t row_number,
+                          void *data, ExcInfo &exc)
     {
         try {
-            const casacore::TableRecord &rec = row.record();
-            const casacore::RecordDesc &desc = rec.description();
-            casacore::Int field_num = rec.fieldNumber(bridge_string(col_name));
-            casacore::IPosition shape;
-
-            if (field_num < 0)
-                throw std::runtime_error("unrecognized column name");
-
-            if (!desc.isScalar(field_num)) {
-                casacore::TableColumn col(row.table(), bridge_string(col_name));
-                shape = col.shape(row.rowNumber());
-            }
-
-            switch (rec.type(field_num)) {
+            switch (column.columnDesc().trueDataType()) {
 
 #define SCALAR_CASE(DTYPE, CPPTYPE) \
             case casacore::DTYPE: { \
-                CPPTYPE datum; \
-                rec.get(field_num, datum); \
-                *((CPPTYPE *) data) = datum; \
+                const casacore::ScalarColumn<CPPTYPE> &col = static_cast<const casacore::ScalarColumn<CPPTYPE> &>(column); \
+                *((CPPTYPE *) data) = col.get(row_number); \
                 break; \
             }
 
 #define VECTOR_CASE(DTYPE, CPPTYPE) \
             case casacore::DTYPE: { \
-                casacore::Array<CPPTYPE> array(shape, (CPPTYPE *) data, casacore::SHARE); \
-                rec.get(field_num, array); \
+                const casacore::ArrayColumn<CPPTYPE> &col = static_cast<const casacore::ArrayColumn<CPPTYPE> &>(column); \
+                casacore::Array<CPPTYPE> array(col.shape(row_number), (CPPTYPE *) data, casacore::SHARE); \
+                col.get(row_number, array, casacore::False); \
                 break; \
             }
 
             SCALAR_CASE(TpBool, casacore::Bool)
-            //SCALAR_CASE(TpChar, casacore::Char)
+            SCALAR_CASE(TpChar, casacore::Char)
             SCALAR_CASE(TpUChar, casacore::uChar)
             SCALAR_CASE(TpShort, casacore::Short)
-            //SCALAR_CASE(TpUShort, casacore::uShort)
+            SCALAR_CASE(TpUShort, casacore::uShort)
             SCALAR_CASE(TpInt, casacore::Int)
             SCALAR_CASE(TpUInt, casacore::uInt)
+            SCALAR_CASE(TpInt64, casacore::Int64)
             SCALAR_CASE(TpFloat, float)
             SCALAR_CASE(TpDouble, double)
             SCALAR_CASE(TpComplex, casacore::Complex)
             SCALAR_CASE(TpDComplex, casacore::DComplex)
 
             VECTOR_CASE(TpArrayBool, casacore::Bool)
-            //VECTOR_CASE(TpArrayChar, casacore::Char)
+            VECTOR_CASE(TpArrayChar, casacore::Char)
             VECTOR_CASE(TpArrayUChar, casacore::uChar)
             VECTOR_CASE(TpArrayShort, casacore::Short)
-            //VECTOR_CASE(TpArrayUShort, casacore::uShort)
+            VECTOR_CASE(TpArrayUShort, casacore::uShort)
             VECTOR_CASE(TpArrayInt, casacore::Int)
             VECTOR_CASE(TpArrayUInt, casacore::uInt)
+            VECTOR_CASE(TpArrayInt64, casacore::Int64)
             VECTOR_CASE(TpArrayFloat, float)
             VECTOR_CASE(TpArrayDouble, double)
             VECTOR_CASE(TpArrayComplex, casacore::Complex)
@@ -709,15 +2337,17 @@ extern "C" {
 #undef VECTOR_CASE
 
             case casacore::TpString: {
-                casacore::String datum;
-                rec.get(field_num, datum);
-                unbridge_string(datum, *(StringBridge *) data);
+                const casacore::ScalarColumn<casacore::String> &col =
+                    static_cast<const casacore::ScalarColumn<casacore::String> &>(column);
+                unbridge_string_owned(col.get(row_number), *((StringBridge *) data));
                 break;
             }
 
             case casacore::TpArrayString: {
-                casacore::Array<casacore::String> array(shape);
-                rec.get(field_num, array);
+                const casacore::ArrayColumn<casacore::String> &col =
+                    static_cast<const casacore::ArrayColumn<casacore::String> &>(column);
+                casacore::Array<casacore::String> array(col.shape(row_number));
+                col.get(row_number, array, casacore::False);
                 unbridge_string_array(array, (StringBridge *) data);
                 break;
             }
@@ -733,54 +2363,163 @@ extern "C" {
         return 0;
     }
 
+    // Read a scalar cell, converting it to `as_type`. casacore only allows
+    // conversions that promote values to a wider type.
     int
-    table_row_put_cell(GlueTableRow &wrap_row, const StringBridge &col_name,
-                       const GlueDataType data_type, const unsigned long n_dims,
-                       const unsigned long *dims, void *data, ExcInfo &exc)
+    table_column_get_scalar_as(const GlueTableColumn &column, const uint64_t row_number,
+                               const GlueDataType as_type, void *data, ExcInfo &exc)
     {
-        casacore::TableRow &row = (casacore::TableRow &) wrap_row;
+        try {
+            switch (as_type) {
+
+#define CASE(DTYPE, CPPTYPE) \
+            case casacore::DTYPE: \
+                column.getScalar(row_number, *((CPPTYPE *) data)); \
+                break;
+
+            CASE(TpUChar, casacore::uChar)
+            CASE(TpShort, casacore::Short)
+            CASE(TpUShort, casacore::uShort)
+            CASE(TpInt, casacore::Int)
+            CASE(TpUInt, casacore::uInt)
+            CASE(TpInt64, casacore::Int64)
+            CASE(TpFloat, float)
+            CASE(TpDouble, double)
+            CASE(TpComplex, casacore::Complex)
+            CASE(TpDComplex, casacore::DComplex)
+
+#undef CASE
+
+            default:
+                throw std::runtime_error("unhandled scalar conversion type");
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
 
+    // Read an array cell of complex values, converting them to the precision
+    // *as_type*, which is TpComplex or TpDComplex. When widening, *data* is
+    // used as the buffer into which the cell is read.
+    int
+    table_column_get_cell_as(const GlueTableColumn &column, const uint64_t row_number,
+                             const GlueDataType as_type, void *data, ExcInfo &exc)
+    {
         try {
-            casacore::TableRecord &rec = row.record();
-            casacore::Int field_num = rec.fieldNumber(bridge_string(col_name));
+            const casacore::DataType stored = column.columnDesc().trueDataType();
 
-            switch (data_type) {
+            if (stored == casacore::TpArrayComplex && as_type == casacore::TpDComplex) {
+                const casacore::ArrayColumn<casacore::Complex> &col =
+                    static_cast<const casacore::ArrayColumn<casacore::Complex> &>(column);
+                casacore::Array<casacore::Complex> array(col.shape(row_number), (casacore::Complex *) data, casacore::SHARE);
+                col.get(row_number, array, casacore::False);
+                widen_complex_in_place(data, array.nelements());
+            } else if (stored == casacore::TpArrayDComplex && as_type == casacore::TpComplex) {
+                const casacore::ArrayColumn<casacore::DComplex> &col =
+                    static_cast<const casacore::ArrayColumn<casacore::DComplex> &>(column);
+                const casacore::Array<casacore::DComplex> array(col.get(row_number));
+                casacore::Bool delete_it;
+                const casacore::DComplex *values = array.getStorage(delete_it);
+                narrow_complex(values, array.nelements(), data);
+                array.freeStorage(values, delete_it);
+            } else {
+                throw std::runtime_error("unsupported conversion between column data types");
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    // Write an array cell of complex values of the precision *as_type* into
+    // a column stored with the other precision.
+    int
+    table_column_put_cell_as(GlueTableColumn &column, const uint64_t row_number,
+                             const GlueDataType as_type, const uint64_t n_dims,
+                             const uint64_t *dims, const void *data, ExcInfo &exc)
+    {
+        try {
+            casacore::IPosition shape(n_dims);
+
+            for (casacore::uInt i = 0; i < n_dims; i++)
+                shape[i] = dims[n_dims - 1 - i];
+
+            const casacore::DataType stored = column.columnDesc().trueDataType();
+
+            if (stored == casacore::TpArrayComplex && as_type == casacore::TpDComplex) {
+                casacore::Array<casacore::Complex> array(shape);
+                narrow_complex((const casacore::DComplex *) data, array.nelements(), array.data());
+                static_cast<casacore::ArrayColumn<casacore::Complex> &>(column).put(row_number, array);
+            } else if (stored == casacore::TpArrayDComplex && as_type == casacore::TpComplex) {
+                casacore::Array<casacore::DComplex> array(shape);
+                memcpy(array.data(), data, array.nelements() * sizeof(casacore::Complex));
+                widen_complex_in_place(array.data(), array.nelements());
+                static_cast<casacore::ArrayColumn<casacore::DComplex> &>(column).put(row_number, array);
+            } else {
+                throw std::runtime_error("unsupported conversion between column data types");
+            }
+        } catch (...) {
+            handle_exception(exc);
+            return 1;
+        }
+
+        return 0;
+    }
+
+    int
+    table_column_put_cell(GlueTableColumn &column, const uint64_t row_number,
+                          const uint64_t n_dims, const uint64_t *dims,
+                          const void *data, ExcInfo &exc)
+    {
+        try {
+            casacore::IPosition shape(n_dims);
+
+            for (casacore::uInt i = 0; i < n_dims; i++)
+                shape[i] = dims[n_dims - 1 - i];
+
+            switch (column.columnDesc().trueDataType()) {
 
 #define SCALAR_CASE(DTYPE, CPPTYPE) \
             case casacore::DTYPE: { \
-                rec.define(field_num, *(CPPTYPE *) data); \
+                casacore::ScalarColumn<CPPTYPE> &col = static_cast<casacore::ScalarColumn<CPPTYPE> &>(column); \
+                col.put(row_number, *(const CPPTYPE *) data); \
                 break; \
             }
 
 #define VECTOR_CASE(DTYPE, CPPTYPE) \
             case casacore::DTYPE: { \
-                casacore::IPosition shape(n_dims); \
-                for (casacore::uInt i = 0; i < n_dims; i++) \
-                    shape[i] = dims[n_dims - 1 - i]; \
-                casacore::Array<CPPTYPE> array(shape, (CPPTYPE *) data, casacore::SHARE); \
-                rec.define(field_num, array); \
+                casacore::ArrayColumn<CPPTYPE> &col = static_cast<casacore::ArrayColumn<CPPTYPE> &>(column); \
+                const casacore::Array<CPPTYPE> array(shape, (CPPTYPE *) data, casacore::SHARE); \
+                col.put(row_number, array); \
                 break; \
             }
 
             SCALAR_CASE(TpBool, casacore::Bool)
-            //SCALAR_CASE(TpChar, casacore::Char)
+            SCALAR_CASE(TpChar, casacore::Char)
             SCALAR_CASE(TpUChar, casacore::uChar)
             SCALAR_CASE(TpShort, casacore::Short)
-            //SCALAR_CASE(TpUShort, casacore::uShort)
+            SCALAR_CASE(TpUShort, casacore::uShort)
             SCALAR_CASE(TpInt, casacore::Int)
             SCALAR_CASE(TpUInt, casacore::uInt)
+            SCALAR_CASE(TpInt64, casacore::Int64)
             SCALAR_CASE(TpFloat, float)
             SCALAR_CASE(TpDouble, double)
             SCALAR_CASE(TpComplex, casacore::Complex)
             SCALAR_CASE(TpDComplex, casacore::DComplex)
 
             VECTOR_CASE(TpArrayBool, casacore::Bool)
-            //VECTOR_CASE(TpArrayChar, casacore::Char)
+            VECTOR_CASE(TpArrayChar, casacore::Char)
             VECTOR_CASE(TpArrayUChar, casacore::uChar)
             VECTOR_CASE(TpArrayShort, casacore::Short)
-            //VECTOR_CASE(TpArrayUShort, casacore::uShort)
+            VECTOR_CASE(TpArrayUShort, casacore::uShort)
             VECTOR_CASE(TpArrayInt, casacore::Int)
             VECTOR_CASE(TpArrayUInt, casacore::uInt)
+            VECTOR_CASE(TpArrayInt64, casacore::Int64)
             VECTOR_CASE(TpArrayFloat, float)
             VECTOR_CASE(TpArrayDouble, double)
             VECTOR_CASE(TpArrayComplex, casacore::Complex)
@@ -790,15 +2529,16 @@ extern "C" {
 #undef VECTOR_CASE
 
             case casacore::TpString: {
-                rec.define(field_num, bridge_string(*(StringBridge *) data));
+                casacore::ScalarColumn<casacore::String> &col =
+                    static_cast<casacore::ScalarColumn<casacore::String> &>(column);
+                col.put(row_number, bridge_string(*((const StringBridge *) data)));
                 break;
             }
 
             case casacore::TpArrayString: {
-                casacore::IPosition shape(n_dims);
-                for (casacore::uInt i = 0; i < n_dims; i++)
-                    shape[i] = dims[n_dims - 1 - i];
-                rec.define(field_num, bridge_string_array((const StringBridge *) data, shape));
+                casacore::ArrayColumn<casacore::String> &col =
+                    static_cast<casacore::ArrayColumn<casacore::String> &>(column);
+                col.put(row_number, bridge_string_array((const StringBridge *) data, shape));
                 break;
             }
 
@@ -812,19 +2552,4 @@ extern "C" {
 
         return 0;
     }
-
-    int
-    table_row_write(GlueTableRow &wrap_row, const unsigned long dest_row_number, ExcInfo &exc)
-    {
-        casacore::TableRow &row = (casacore::TableRow &) wrap_row;
-
-        try {
-            row.put(dest_row_number);
-        } catch (...) {
-            handle_exception(exc);
-            return 1;
-        }
-
-        return 0;
-    }
 }
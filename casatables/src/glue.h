@@ -11,8 +11,13 @@
  *
  * We use some silly preprocessor futzing so that the same prototypes can
  * either use opaque struct pointers or the actual C++ types known to glue.cc.
+ *
+ * Integers that cross the boundary use fixed-width types, since `long` is 32
+ * bits on Windows but 64 bits on Linux and macOS.
  */
 
+#include <stdint.h>
+
 #ifndef CASA_TYPES_ALREADY_DECLARED
 
 // copied from casa/Utilities/DataType.h:
@@ -52,6 +57,7 @@ typedef enum GlueDataType {
 
 typedef struct GlueTable GlueTable;
 typedef struct GlueTableRow GlueTableRow;
+typedef struct GlueTableColumn GlueTableColumn;
 #endif
 
 // OMG, strings. First of all: casacore::String is a subclass of std::string,
@@ -64,15 +70,34 @@ typedef struct GlueTableRow GlueTableRow;
 // use a "small string optimization" that means that for short strings there
 // *is no* underyling buffer anyway.) So we have to copy data, and often need
 // to use C++->Rust callbacks to be able to copy string contents before they
-// are deallocated at the C++ layer.
+// are deallocated at the C++ layer. Strings that are returned rather than
+// passed to callbacks are copied into buffers allocated with malloc(), which
+// the Rust layer takes ownership of and must release with
+// string_bridge_free().
 
 typedef struct StringBridge {
     const void *data;
-    unsigned long n_bytes;
+    uint64_t n_bytes;
 } StringBridge;
 
+// The kinds of exception that the glue distinguishes, so that callers can
+// react to particular failures without parsing messages.
+typedef enum GlueExceptionCategory {
+    GEC_GENERAL = 0,
+    GEC_TABLE = 1,
+    GEC_TABLE_NOT_FOUND = 2,
+    GEC_TABLE_EXISTS = 3,
+    GEC_NO_SUCH_COLUMN = 4,
+    GEC_DATA_TYPE = 5,
+    GEC_CONFORMANCE = 6,
+    GEC_OUT_OF_BOUNDS = 7,
+    GEC_IO = 8,
+    GEC_ALLOC = 9,
+} GlueExceptionCategory;
+
 typedef struct ExcInfo {
     char message[512];
+    int category; // a GlueExceptionCategory
 } ExcInfo;
 
 // Generic callback prototype when handing off owned strings from C++ to Rust.
@@ -83,56 +108,186 @@ typedef void (*StringBridgeCallback)(const StringBridge *name, void *ctxt);
 // additional information we'd like to to transfer.
 typedef void (*KeywordInfoCallback)(const StringBridge *name, GlueDataType dtype, void *ctxt);
 
+// The callback type for table_get_data_manager_info, which is called once for
+// each column served by each data manager. Data managers without a bucket
+// size have `bucket_size` = -1, and those without a default tile shape have
+// `n_tile_dim` = 0.
+typedef void (*DataManagerInfoCallback)(const StringBridge *dm_type, const StringBridge *dm_name,
+                                        uint32_t seq_nr, const StringBridge *col_name,
+                                        int64_t bucket_size, int n_tile_dim,
+                                        const uint64_t *tile_shape, void *ctxt);
+
+// The callback type for table_get_keyword_values, which is called with the
+// name, type, and a printed representation of the value of each keyword.
+typedef void (*KeywordValueCallback)(const StringBridge *name, GlueDataType dtype,
+                                     const StringBridge *value, void *ctxt);
+
 typedef enum TableOpenMode {
     TOM_OPEN_READONLY = 1,
     TOM_OPEN_RW = 2,
     TOM_CREATE = 3,
+    TOM_CREATE_NO_OVERWRITE = 4,
 } TableOpenMode;
 
+// How the files of a table are organized; see casacore::StorageOption.
+// GSO_DEFAULT means whatever casacore chooses by default.
+typedef enum GlueStorageOption {
+    GSO_DEFAULT = 0,
+    GSO_SEPARATE_FILES = 1,
+    GSO_MULTI_FILE = 2,
+    GSO_MULTI_HDF5 = 3,
+} GlueStorageOption;
+
 extern "C" {
+    void string_bridge_free(StringBridge *s);
+    int string_bridge_copy(const StringBridge &source, StringBridge *dest, ExcInfo &exc);
+
     int data_type_get_element_size(const GlueDataType ty);
 
+    int storage_hdf5_is_supported(void);
+    int casacore_initialize(const StringBridge &aips_path, StringBridge *aips_root,
+                            StringBridge *aips_home, ExcInfo &exc);
     GlueTable *table_alloc_and_open(const StringBridge &path, const TableOpenMode mode, ExcInfo &exc);
+    int table_check_lock(const StringBridge &path, const TableOpenMode mode, uint32_t *pid,
+                         ExcInfo &exc);
+    GlueTable *table_create(const StringBridge &path, const uint64_t n_cols,
+                            const StringBridge *col_names, const StringBridge *col_comments,
+                            const GlueDataType *col_types, const int32_t *col_ndims,
+                            const uint8_t *col_fixed, const uint64_t *shapes,
+                            const uint64_t n_rows, const GlueStorageOption storage,
+                            const uint32_t block_size, ExcInfo &exc);
     void table_close_and_free(GlueTable *table, ExcInfo &exc);
-    unsigned long table_n_rows(const GlueTable &table);
-    unsigned long table_n_columns(const GlueTable &table);
+    GlueTable *table_select_rows(const GlueTable &table, const uint8_t *mask, const uint64_t n_rows,
+                                 ExcInfo &exc);
+    int table_delete(const StringBridge &path, ExcInfo &exc);
+    int table_rename(const StringBridge &old_path, const StringBridge &new_path, ExcInfo &exc);
+    int table_create_concat(const uint64_t n_parts, const StringBridge *part_paths,
+                            const StringBridge &sub_dir, const StringBridge &dest_path,
+                            ExcInfo &exc);
+    uint64_t table_n_rows(const GlueTable &table);
+    uint64_t table_n_columns(const GlueTable &table);
+    int table_is_reference(const GlueTable &table);
     int table_get_column_names(const GlueTable &table, StringBridgeCallback callback,
                                void *ctxt, ExcInfo &exc);
-    unsigned long table_n_keywords(const GlueTable &table);
+    uint64_t table_n_keywords(const GlueTable &table);
     int table_get_keyword_info(const GlueTable &table, KeywordInfoCallback callback,
                                void *ctxt, ExcInfo &exc);
+    int table_get_data_manager_info(const GlueTable &table, DataManagerInfoCallback callback,
+                                    void *ctxt, ExcInfo &exc);
     int table_copy_rows(const GlueTable &source, GlueTable &dest, ExcInfo &exc);
-    int table_deep_copy_no_rows(const GlueTable &table, const StringBridge &dest_path, ExcInfo &exc);
+    int table_deep_copy(const GlueTable &table, const StringBridge &dest_path,
+                        const uint64_t n_cols, const StringBridge *col_names,
+                        const int no_rows, const GlueStorageOption storage,
+                        const uint32_t block_size, ExcInfo &exc);
+    int table_get_storage_option(const GlueTable &table, GlueStorageOption *storage,
+                                 uint32_t *block_size, ExcInfo &exc);
     int table_get_column_info(const GlueTable &table, const StringBridge &col_name,
-                              unsigned long *n_rows, GlueDataType *data_type,
+                              uint64_t *n_rows, GlueDataType *data_type,
                               int *is_scalar, int *is_fixed_shape, int *n_dim,
-                              unsigned long dims[8], ExcInfo &exc);
+                              uint64_t dims[8], ExcInfo &exc);
     int table_remove_column(GlueTable &table, const StringBridge &col_name, ExcInfo &exc);
+    int table_add_array_column(GlueTable &table, const StringBridge &col_name,
+                               const GlueDataType data_type, const uint64_t n_dims,
+                               const uint64_t *dims, ExcInfo &exc);
+    int table_put_keyword_string(GlueTable &table, const StringBridge &kw_name,
+                                 const StringBridge &value, ExcInfo &exc);
+    int table_put_keyword_subtable(GlueTable &table, const StringBridge &kw_name,
+                                   const StringBridge &subtable_path, ExcInfo &exc);
+    int table_get_info(const GlueTable &table, StringBridge *type, StringBridge *sub_type,
+                       StringBridge *readme, ExcInfo &exc);
+    int table_set_info(GlueTable &table, const StringBridge &type, const StringBridge &sub_type,
+                       const StringBridge &readme, ExcInfo &exc);
+    int table_put_column_measure(GlueTable &table, const StringBridge &col_name,
+                                 const uint64_t n_units, const StringBridge *units,
+                                 const StringBridge &meas_type, const StringBridge &meas_ref,
+                                 ExcInfo &exc);
+    int table_get_column_measure(const GlueTable &table, const StringBridge &col_name,
+                                 StringBridge *units, StringBridge *meas_type,
+                                 StringBridge *meas_ref, ExcInfo &exc);
+    int table_copy_column_keywords(GlueTable &dest, const GlueTable &source,
+                                   const StringBridge &col_name, ExcInfo &exc);
+    int table_get_keyword_values(const GlueTable &table, const StringBridge &col_name,
+                                 KeywordValueCallback callback, void *ctxt, ExcInfo &exc);
+    int table_put_column_keyword_strings(GlueTable &table, const StringBridge &col_name,
+                                         const StringBridge &kw_name, const uint64_t n_values,
+                                         const StringBridge *values, ExcInfo &exc);
+    int table_get_column_keyword_strings(const GlueTable &table, const StringBridge &col_name,
+                                         const StringBridge &kw_name, StringBridgeCallback callback,
+                                         void *ctxt, ExcInfo &exc);
     int table_get_scalar_column_data(const GlueTable &table, const StringBridge &col_name,
                                      void *data, ExcInfo &exc);
+    int table_get_column_range_data(const GlueTable &table, const StringBridge &col_name,
+                                    const uint64_t start_row, const uint64_t n_rows,
+                                    void *data, ExcInfo &exc);
+    int table_get_bool_range_bits(const GlueTable &table, const StringBridge &col_name,
+                                  const uint64_t start_row, const uint64_t n_rows,
+                                  uint8_t *bits, ExcInfo &exc);
+    int table_get_column_range_data_as(const GlueTable &table, const StringBridge &col_name,
+                                       const uint64_t start_row, const uint64_t n_rows,
+                                       const GlueDataType as_type, void *data, ExcInfo &exc);
     int table_get_cell_info(const GlueTable &table, const StringBridge &col_name,
-                            unsigned long row_number, GlueDataType *data_type,
-                            int *n_dim, unsigned long dims[8], ExcInfo &exc);
+                            uint64_t row_number, GlueDataType *data_type,
+                            int *n_dim, uint64_t dims[8], ExcInfo &exc);
+    int table_cell_is_defined(const GlueTable &table, const StringBridge &col_name,
+                              const uint64_t row_number, int *is_defined, ExcInfo &exc);
+    int table_set_tile_cache_size(const GlueTable &table, const StringBridge &col_name,
+                                  const uint32_t n_mib, ExcInfo &exc);
     int table_get_cell(const GlueTable &table, const StringBridge &col_name,
-                       const unsigned long row_number, void *data, ExcInfo &exc);
+                       const uint64_t row_number, void *data, ExcInfo &exc);
+    int table_get_cell_borrowed(const GlueTable &table, const StringBridge &col_name,
+                                const uint64_t row_number, void **holder,
+                                const void **data, ExcInfo &exc);
+    void array_holder_free(void *holder);
+    int table_put_column_range_data(GlueTable &table, const StringBridge &col_name,
+                                    const uint64_t start_row, const uint64_t n_rows,
+                                    const void *data, ExcInfo &exc);
+    int table_put_column_range_cells(GlueTable &table, const StringBridge &col_name,
+                                     const uint64_t start_row, const uint64_t n_rows,
+                                     const GlueDataType data_type,
+                                     const uint64_t n_dims, const uint64_t *dims,
+                                     const void *data, ExcInfo &exc);
+    int table_put_bool_range_bits(GlueTable &table, const StringBridge &col_name,
+                                  const uint64_t start_row, const uint64_t n_rows,
+                                  const uint8_t *bits, ExcInfo &exc);
     int table_put_cell(GlueTable &table, const StringBridge &col_name,
-                       const unsigned long row_number, const GlueDataType data_type,
-                       const unsigned long n_dims, const unsigned long *dims,
+                       const uint64_t row_number, const GlueDataType data_type,
+                       const uint64_t n_dims, const uint64_t *dims,
                        void *data, ExcInfo &exc);
-    int table_add_rows(GlueTable &table, const unsigned long n_rows, ExcInfo &exc);
+    int table_add_rows(GlueTable &table, const uint64_t n_rows, ExcInfo &exc);
+    int table_flush(GlueTable &table, const int fsync, ExcInfo &exc);
 
     GlueTableRow *table_row_alloc(const GlueTable &table, const unsigned char is_read_only, ExcInfo &exc);
     int table_row_free(GlueTableRow *row, ExcInfo &exc);
-    int table_row_read(GlueTableRow &row, const unsigned long row_number, ExcInfo &exc);
-    int table_row_copy_and_put(GlueTableRow &src_row, const unsigned long dest_row_number,
+    int table_row_read(GlueTableRow &row, const uint64_t row_number, ExcInfo &exc);
+    int table_row_copy_and_put(GlueTableRow &src_row, const uint64_t dest_row_number,
                                GlueTableRow &dest_row, ExcInfo &exc);
     int table_row_get_cell_info(const GlueTableRow &row, const StringBridge &col_name,
                                 GlueDataType *data_type, int *n_dim,
-                                unsigned long dims[8], ExcInfo &exc);
+                                uint64_t dims[8], ExcInfo &exc);
     int table_row_get_cell(const GlueTableRow &row, const StringBridge &col_name,
                            void *data, ExcInfo &exc);
     int table_row_put_cell(GlueTableRow &row, const StringBridge &col_name,
-                           const GlueDataType data_type, const unsigned long n_dims,
-                           const unsigned long *dims, void *data, ExcInfo &exc);
-    int table_row_write(GlueTableRow &row, const unsigned long dest_row_number, ExcInfo &exc);
+                           const GlueDataType data_type, const uint64_t n_dims,
+                           const uint64_t *dims, void *data, ExcInfo &exc);
+    int table_row_write(GlueTableRow &row, const uint64_t dest_row_number, ExcInfo &exc);
+
+    int table_has_column(const GlueTable &table, const StringBridge &col_name,
+                         int *has_column, ExcInfo &exc);
+    GlueTableColumn *table_column_alloc(const GlueTable &table, const StringBridge &col_name,
+                                        GlueDataType *data_type, int *is_scalar, ExcInfo &exc);
+    int table_column_free(GlueTableColumn *column, ExcInfo &exc);
+    int table_column_get_cell_shape(const GlueTableColumn &column, const uint64_t row_number,
+                                    int *n_dim, uint64_t dims[8], ExcInfo &exc);
+    int table_column_get_cell(const GlueTableColumn &column, const uint64_t row_number,
+                              void *data, ExcInfo &exc);
+    int table_column_get_scalar_as(const GlueTableColumn &column, const uint64_t row_number,
+                                   const GlueDataType as_type, void *data, ExcInfo &exc);
+    int table_column_get_cell_as(const GlueTableColumn &column, const uint64_t row_number,
+                                 const GlueDataType as_type, void *data, ExcInfo &exc);
+    int table_column_put_cell_as(GlueTableColumn &column, const uint64_t row_number,
+                                 const GlueDataType as_type, const uint64_t n_dims,
+                                 const uint64_t *dims, const void *data, ExcInfo &exc);
+    int table_column_put_cell(GlueTableColumn &column, const uint64_t row_number,
+                              const uint64_t n_dims, const uint64_t *dims,
+                              const void *data, ExcInfo &exc);
 }
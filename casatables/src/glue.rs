@@ -47,13 +47,20 @@ pub struct GlueTableRow {
     _unused: [u8; 0],
 }
 #[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct GlueTableColumn {
+    _unused: [u8; 0],
+}
+#[repr(C)]
 #[derive(Debug, Copy)]
 pub struct StringBridge {
     pub data: *const ::std::os::raw::c_void,
-    pub n_bytes: ::std::os::raw::c_ulong,
+    pub n_bytes: u64,
 }
 #[test]
 fn bindgen_test_layout_StringBridge() {
+    const UNINIT: ::std::mem::MaybeUninit<StringBridge> = ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
     assert_eq!(
         ::std::mem::size_of::<StringBridge>(),
         16usize,
@@ -65,20 +72,20 @@ fn bindgen_test_layout_StringBridge() {
         concat!("Alignment of ", stringify!(StringBridge))
     );
     assert_eq!(
-        unsafe { &(*(0 as *const StringBridge)).data as *const _ as usize },
+        unsafe { ::std::ptr::addr_of!((*ptr).data) as usize - ptr as usize },
         0usize,
         concat!(
-            "Alignment of field: ",
+            "Offset of field: ",
             stringify!(StringBridge),
             "::",
             stringify!(data)
         )
     );
     assert_eq!(
-        unsafe { &(*(0 as *const StringBridge)).n_bytes as *const _ as usize },
+        unsafe { ::std::ptr::addr_of!((*ptr).n_bytes) as usize - ptr as usize },
         8usize,
         concat!(
-            "Alignment of field: ",
+            "Offset of field: ",
             stringify!(StringBridge),
             "::",
             stringify!(n_bytes)
@@ -90,33 +97,60 @@ impl Clone for StringBridge {
         *self
     }
 }
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GlueExceptionCategory {
+    GEC_GENERAL = 0,
+    GEC_TABLE = 1,
+    GEC_TABLE_NOT_FOUND = 2,
+    GEC_TABLE_EXISTS = 3,
+    GEC_NO_SUCH_COLUMN = 4,
+    GEC_DATA_TYPE = 5,
+    GEC_CONFORMANCE = 6,
+    GEC_OUT_OF_BOUNDS = 7,
+    GEC_IO = 8,
+    GEC_ALLOC = 9,
+}
 #[repr(C)]
 #[derive(Copy)]
 pub struct ExcInfo {
     pub message: [::std::os::raw::c_char; 512usize],
+    pub category: ::std::os::raw::c_int,
 }
 #[test]
 fn bindgen_test_layout_ExcInfo() {
+    const UNINIT: ::std::mem::MaybeUninit<ExcInfo> = ::std::mem::MaybeUninit::uninit();
+    let ptr = UNINIT.as_ptr();
     assert_eq!(
         ::std::mem::size_of::<ExcInfo>(),
-        512usize,
+        516usize,
         concat!("Size of: ", stringify!(ExcInfo))
     );
     assert_eq!(
         ::std::mem::align_of::<ExcInfo>(),
-        1usize,
+        4usize,
         concat!("Alignment of ", stringify!(ExcInfo))
     );
     assert_eq!(
-        unsafe { &(*(0 as *const ExcInfo)).message as *const _ as usize },
+        unsafe { ::std::ptr::addr_of!((*ptr).message) as usize - ptr as usize },
         0usize,
         concat!(
-            "Alignment of field: ",
+            "Offset of field: ",
             stringify!(ExcInfo),
             "::",
             stringify!(message)
         )
     );
+    assert_eq!(
+        unsafe { ::std::ptr::addr_of!((*ptr).category) as usize - ptr as usize },
+        512usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(ExcInfo),
+            "::",
+            stringify!(category)
+        )
+    );
 }
 impl Clone for ExcInfo {
     fn clone(&self) -> Self {
@@ -133,16 +167,66 @@ pub type KeywordInfoCallback = ::std::option::Option<
         ctxt: *mut ::std::os::raw::c_void,
     ),
 >;
+pub type DataManagerInfoCallback = ::std::option::Option<
+    unsafe extern "C" fn(
+        dm_type: *const StringBridge,
+        dm_name: *const StringBridge,
+        seq_nr: u32,
+        col_name: *const StringBridge,
+        bucket_size: i64,
+        n_tile_dim: ::std::os::raw::c_int,
+        tile_shape: *const u64,
+        ctxt: *mut ::std::os::raw::c_void,
+    ),
+>;
+pub type KeywordValueCallback = ::std::option::Option<
+    unsafe extern "C" fn(
+        name: *const StringBridge,
+        dtype: GlueDataType,
+        value: *const StringBridge,
+        ctxt: *mut ::std::os::raw::c_void,
+    ),
+>;
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum TableOpenMode {
     TOM_OPEN_READONLY = 1,
     TOM_OPEN_RW = 2,
     TOM_CREATE = 3,
+    TOM_CREATE_NO_OVERWRITE = 4,
+}
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GlueStorageOption {
+    GSO_DEFAULT = 0,
+    GSO_SEPARATE_FILES = 1,
+    GSO_MULTI_FILE = 2,
+    GSO_MULTI_HDF5 = 3,
+}
+extern "C" {
+    pub fn string_bridge_free(s: *mut StringBridge);
+}
+extern "C" {
+    pub fn string_bridge_copy(
+        source: *const StringBridge,
+        dest: *mut StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
 }
 extern "C" {
     pub fn data_type_get_element_size(ty: GlueDataType) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn storage_hdf5_is_supported() -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn casacore_initialize(
+        aips_path: *const StringBridge,
+        aips_root: *mut StringBridge,
+        aips_home: *mut StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn table_alloc_and_open(
         path: *const StringBridge,
@@ -150,14 +234,68 @@ extern "C" {
         exc: *mut ExcInfo,
     ) -> *mut GlueTable;
 }
+extern "C" {
+    pub fn table_check_lock(
+        path: *const StringBridge,
+        mode: TableOpenMode,
+        pid: *mut u32,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_create(
+        path: *const StringBridge,
+        n_cols: u64,
+        col_names: *const StringBridge,
+        col_comments: *const StringBridge,
+        col_types: *const GlueDataType,
+        col_ndims: *const i32,
+        col_fixed: *const u8,
+        shapes: *const u64,
+        n_rows: u64,
+        storage: GlueStorageOption,
+        block_size: u32,
+        exc: *mut ExcInfo,
+    ) -> *mut GlueTable;
+}
+extern "C" {
+    pub fn table_select_rows(
+        table: *const GlueTable,
+        mask: *const u8,
+        n_rows: u64,
+        exc: *mut ExcInfo,
+    ) -> *mut GlueTable;
+}
 extern "C" {
     pub fn table_close_and_free(table: *mut GlueTable, exc: *mut ExcInfo);
 }
 extern "C" {
-    pub fn table_n_rows(table: *const GlueTable) -> ::std::os::raw::c_ulong;
+    pub fn table_delete(path: *const StringBridge, exc: *mut ExcInfo) -> ::std::os::raw::c_int;
 }
 extern "C" {
-    pub fn table_n_columns(table: *const GlueTable) -> ::std::os::raw::c_ulong;
+    pub fn table_rename(
+        old_path: *const StringBridge,
+        new_path: *const StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_create_concat(
+        n_parts: u64,
+        part_paths: *const StringBridge,
+        sub_dir: *const StringBridge,
+        dest_path: *const StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_n_rows(table: *const GlueTable) -> u64;
+}
+extern "C" {
+    pub fn table_n_columns(table: *const GlueTable) -> u64;
+}
+extern "C" {
+    pub fn table_is_reference(table: *const GlueTable) -> ::std::os::raw::c_int;
 }
 extern "C" {
     pub fn table_get_column_names(
@@ -168,7 +306,7 @@ extern "C" {
     ) -> ::std::os::raw::c_int;
 }
 extern "C" {
-    pub fn table_n_keywords(table: *const GlueTable) -> ::std::os::raw::c_ulong;
+    pub fn table_n_keywords(table: *const GlueTable) -> u64;
 }
 extern "C" {
     pub fn table_get_keyword_info(
@@ -178,6 +316,14 @@ extern "C" {
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn table_get_data_manager_info(
+        table: *const GlueTable,
+        callback: DataManagerInfoCallback,
+        ctxt: *mut ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn table_copy_rows(
         source: *const GlueTable,
@@ -186,9 +332,22 @@ extern "C" {
     ) -> ::std::os::raw::c_int;
 }
 extern "C" {
-    pub fn table_deep_copy_no_rows(
+    pub fn table_deep_copy(
         table: *const GlueTable,
         dest_path: *const StringBridge,
+        n_cols: u64,
+        col_names: *const StringBridge,
+        no_rows: ::std::os::raw::c_int,
+        storage: GlueStorageOption,
+        block_size: u32,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_get_storage_option(
+        table: *const GlueTable,
+        storage: *mut GlueStorageOption,
+        block_size: *mut u32,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
@@ -196,12 +355,12 @@ extern "C" {
     pub fn table_get_column_info(
         table: *const GlueTable,
         col_name: *const StringBridge,
-        n_rows: *mut ::std::os::raw::c_ulong,
+        n_rows: *mut u64,
         data_type: *mut GlueDataType,
         is_scalar: *mut ::std::os::raw::c_int,
         is_fixed_shape: *mut ::std::os::raw::c_int,
         n_dim: *mut ::std::os::raw::c_int,
-        dims: *mut ::std::os::raw::c_ulong,
+        dims: *mut u64,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
@@ -212,6 +371,108 @@ extern "C" {
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn table_add_array_column(
+        table: *mut GlueTable,
+        col_name: *const StringBridge,
+        data_type: GlueDataType,
+        n_dims: u64,
+        dims: *const u64,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_put_keyword_string(
+        table: *mut GlueTable,
+        kw_name: *const StringBridge,
+        value: *const StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_put_keyword_subtable(
+        table: *mut GlueTable,
+        kw_name: *const StringBridge,
+        subtable_path: *const StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_get_info(
+        table: *const GlueTable,
+        type_: *mut StringBridge,
+        sub_type: *mut StringBridge,
+        readme: *mut StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_set_info(
+        table: *mut GlueTable,
+        type_: *const StringBridge,
+        sub_type: *const StringBridge,
+        readme: *const StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_put_column_measure(
+        table: *mut GlueTable,
+        col_name: *const StringBridge,
+        n_units: u64,
+        units: *const StringBridge,
+        meas_type: *const StringBridge,
+        meas_ref: *const StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_get_column_measure(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        units: *mut StringBridge,
+        meas_type: *mut StringBridge,
+        meas_ref: *mut StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_copy_column_keywords(
+        dest: *mut GlueTable,
+        source: *const GlueTable,
+        col_name: *const StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_get_keyword_values(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        callback: KeywordValueCallback,
+        ctxt: *mut ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_put_column_keyword_strings(
+        table: *mut GlueTable,
+        col_name: *const StringBridge,
+        kw_name: *const StringBridge,
+        n_values: u64,
+        values: *const StringBridge,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_get_column_keyword_strings(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        kw_name: *const StringBridge,
+        callback: StringBridgeCallback,
+        ctxt: *mut ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn table_get_scalar_column_data(
         table: *const GlueTable,
@@ -220,14 +481,62 @@ extern "C" {
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn table_get_column_range_data(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        start_row: u64,
+        n_rows: u64,
+        data: *mut ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_get_bool_range_bits(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        start_row: u64,
+        n_rows: u64,
+        bits: *mut u8,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_get_column_range_data_as(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        start_row: u64,
+        n_rows: u64,
+        as_type: GlueDataType,
+        data: *mut ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn table_get_cell_info(
         table: *const GlueTable,
         col_name: *const StringBridge,
-        row_number: ::std::os::raw::c_ulong,
+        row_number: u64,
         data_type: *mut GlueDataType,
         n_dim: *mut ::std::os::raw::c_int,
-        dims: *mut ::std::os::raw::c_ulong,
+        dims: *mut u64,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_set_tile_cache_size(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        n_mib: u32,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_cell_is_defined(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        row_number: u64,
+        is_defined: *mut ::std::os::raw::c_int,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
@@ -235,19 +544,65 @@ extern "C" {
     pub fn table_get_cell(
         table: *const GlueTable,
         col_name: *const StringBridge,
-        row_number: ::std::os::raw::c_ulong,
+        row_number: u64,
         data: *mut ::std::os::raw::c_void,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn table_get_cell_borrowed(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        row_number: u64,
+        holder: *mut *mut ::std::os::raw::c_void,
+        data: *mut *const ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn array_holder_free(holder: *mut ::std::os::raw::c_void);
+}
+extern "C" {
+    pub fn table_put_column_range_data(
+        table: *mut GlueTable,
+        col_name: *const StringBridge,
+        start_row: u64,
+        n_rows: u64,
+        data: *const ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_put_column_range_cells(
+        table: *mut GlueTable,
+        col_name: *const StringBridge,
+        start_row: u64,
+        n_rows: u64,
+        data_type: GlueDataType,
+        n_dims: u64,
+        dims: *const u64,
+        data: *const ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_put_bool_range_bits(
+        table: *mut GlueTable,
+        col_name: *const StringBridge,
+        start_row: u64,
+        n_rows: u64,
+        bits: *const u8,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn table_put_cell(
         table: *mut GlueTable,
         col_name: *const StringBridge,
-        row_number: ::std::os::raw::c_ulong,
+        row_number: u64,
         data_type: GlueDataType,
-        n_dims: ::std::os::raw::c_ulong,
-        dims: *const ::std::os::raw::c_ulong,
+        n_dims: u64,
+        dims: *const u64,
         data: *mut ::std::os::raw::c_void,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
@@ -255,7 +610,14 @@ extern "C" {
 extern "C" {
     pub fn table_add_rows(
         table: *mut GlueTable,
-        n_rows: ::std::os::raw::c_ulong,
+        n_rows: u64,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_flush(
+        table: *mut GlueTable,
+        fsync: ::std::os::raw::c_int,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
@@ -272,14 +634,14 @@ extern "C" {
 extern "C" {
     pub fn table_row_read(
         row: *mut GlueTableRow,
-        row_number: ::std::os::raw::c_ulong,
+        row_number: u64,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
 extern "C" {
     pub fn table_row_copy_and_put(
         src_row: *mut GlueTableRow,
-        dest_row_number: ::std::os::raw::c_ulong,
+        dest_row_number: u64,
         dest_row: *mut GlueTableRow,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
@@ -290,7 +652,7 @@ extern "C" {
         col_name: *const StringBridge,
         data_type: *mut GlueDataType,
         n_dim: *mut ::std::os::raw::c_int,
-        dims: *mut ::std::os::raw::c_ulong,
+        dims: *mut u64,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
@@ -307,8 +669,8 @@ extern "C" {
         row: *mut GlueTableRow,
         col_name: *const StringBridge,
         data_type: GlueDataType,
-        n_dims: ::std::os::raw::c_ulong,
-        dims: *const ::std::os::raw::c_ulong,
+        n_dims: u64,
+        dims: *const u64,
         data: *mut ::std::os::raw::c_void,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
@@ -316,7 +678,86 @@ extern "C" {
 extern "C" {
     pub fn table_row_write(
         row: *mut GlueTableRow,
-        dest_row_number: ::std::os::raw::c_ulong,
+        dest_row_number: u64,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_has_column(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        has_column: *mut ::std::os::raw::c_int,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_column_alloc(
+        table: *const GlueTable,
+        col_name: *const StringBridge,
+        data_type: *mut GlueDataType,
+        is_scalar: *mut ::std::os::raw::c_int,
+        exc: *mut ExcInfo,
+    ) -> *mut GlueTableColumn;
+}
+extern "C" {
+    pub fn table_column_free(
+        column: *mut GlueTableColumn,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_column_get_cell_shape(
+        column: *const GlueTableColumn,
+        row_number: u64,
+        n_dim: *mut ::std::os::raw::c_int,
+        dims: *mut u64,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_column_get_cell(
+        column: *const GlueTableColumn,
+        row_number: u64,
+        data: *mut ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_column_get_scalar_as(
+        column: *const GlueTableColumn,
+        row_number: u64,
+        as_type: GlueDataType,
+        data: *mut ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_column_get_cell_as(
+        column: *const GlueTableColumn,
+        row_number: u64,
+        as_type: GlueDataType,
+        data: *mut ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_column_put_cell_as(
+        column: *mut GlueTableColumn,
+        row_number: u64,
+        as_type: GlueDataType,
+        n_dims: u64,
+        dims: *const u64,
+        data: *const ::std::os::raw::c_void,
+        exc: *mut ExcInfo,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn table_column_put_cell(
+        column: *mut GlueTableColumn,
+        row_number: u64,
+        n_dims: u64,
+        dims: *const u64,
+        data: *const ::std::os::raw::c_void,
         exc: *mut ExcInfo,
     ) -> ::std::os::raw::c_int;
 }
@@ -0,0 +1,249 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Once-only initialization of casacore.
+
+casacore sets up some global state the first time that it needs it,
+consulting the environment as it does so: its resource-file machinery
+locates the casacore installation through `CASAPATH` or `AIPSPATH` and
+fails if `HOME` is not set. Left to itself, this happens in the middle of
+whatever operation first needs it, on whichever thread gets there first,
+and a failure is reported as an error from that operation.
+
+`initialize` does this setup explicitly, with the settings of a `Config`,
+and returns an `InitInfo` describing the result so that tools can log it.
+It may be called from any thread. Only the first call does any work: later
+calls with the same configuration return the same `InitInfo`, and calls
+with a different configuration are errors, since casacore cannot be
+reconfigured once it is running. If a table is opened or created before
+`initialize` is called, it is called implicitly with `Config::default()`.
+
+`Config` also records the location of the casacore *measures data*, the
+IERS, ephemeris, and observatory tables needed to convert between time and
+coordinate frames. The casacore bundled with this crate includes only the
+table system, so it never reads these tables itself, but code built on top
+of it that does conversions can find the directory with `measures_dir`
+rather than each looking for it in its own way.
+
+*/
+
+use failure::{err_msg, Error};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::{glue, CasaString};
+
+/// The environment variable read by `Config::from_env` to locate the
+/// measures data.
+pub const MEASURES_DIR_ENV_VAR: &str = "RUBBL_MEASURES_DIR";
+
+/// The subdirectories that a measures data directory must contain.
+const MEASURES_SUBDIRS: &[&str] = &["ephemerides", "geodetic"];
+
+/// Settings for initializing casacore.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Config {
+    /// The directory containing the casacore measures data, with
+    /// `ephemerides` and `geodetic` subdirectories, if known.
+    pub measures_dir: Option<PathBuf>,
+
+    /// A value to use in place of the `CASAPATH` or `AIPSPATH` environment
+    /// variables, giving the root directory of a casacore installation
+    /// followed by up to three further fields. If `None`, the environment
+    /// is consulted.
+    pub aips_path: Option<String>,
+}
+
+impl Config {
+    /// Create a configuration that takes the location of the measures data
+    /// from the `RUBBL_MEASURES_DIR` environment variable, if it is set.
+    pub fn from_env() -> Self {
+        Config {
+            measures_dir: env::var_os(MEASURES_DIR_ENV_VAR).map(PathBuf::from),
+            aips_path: None,
+        }
+    }
+}
+
+/// The outcome of initializing casacore.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InitInfo {
+    /// The configuration that was used.
+    pub config: Config,
+
+    /// Whether the initialization happened implicitly, when a table was
+    /// first opened or created, rather than through `initialize`.
+    pub implicit: bool,
+
+    /// The root directory of the casacore installation, as determined by
+    /// casacore. This is empty if there is none.
+    pub aips_root: String,
+
+    /// casacore's idea of the user's personal data directory.
+    pub aips_home: String,
+}
+
+impl InitInfo {
+    /// Get lines of text describing the initialization, for logging.
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        lines.push(format!(
+            "casacore initialized {}",
+            if self.implicit {
+                "implicitly with the default configuration"
+            } else {
+                "explicitly"
+            }
+        ));
+        lines.push(format!("casacore root: {:?}", self.aips_root));
+        lines.push(format!("casacore home: {:?}", self.aips_home));
+
+        match self.config.measures_dir {
+            Some(ref d) => lines.push(format!("measures data: {}", d.display())),
+            None => lines.push("measures data: not configured".to_owned()),
+        }
+
+        lines
+    }
+}
+
+/// The result of the first initialization, if there has been one.
+static STATE: Mutex<Option<InitInfo>> = Mutex::new(None);
+
+/// Initialize casacore with the settings of *config*, if that has not
+/// already been done.
+///
+/// Returns an error if casacore's setup fails, if the measures data
+/// directory does not look like one, or if casacore has already been
+/// initialized with a different configuration.
+pub fn initialize(config: Config) -> Result<InitInfo, Error> {
+    let mut state = STATE
+        .lock()
+        .map_err(|_| err_msg("an earlier casacore initialization panicked"))?;
+
+    if let Some(ref info) = *state {
+        if info.config != config {
+            return Err(err_msg(format!(
+                "casacore has already been initialized{} with a different configuration ({:?})",
+                if info.implicit {
+                    " implicitly, by opening a table,"
+                } else {
+                    ""
+                },
+                info.config
+            )));
+        }
+
+        return Ok(info.clone());
+    }
+
+    let info = do_initialize(config, false)?;
+    *state = Some(info.clone());
+    Ok(info)
+}
+
+/// Get the outcome of the initialization of casacore, or `None` if it has
+/// not happened yet.
+pub fn init_info() -> Option<InitInfo> {
+    STATE.lock().ok().and_then(|s| s.clone())
+}
+
+/// Get the configured location of the casacore measures data, or `None` if
+/// casacore has not been initialized or the location was not configured.
+pub fn measures_dir() -> Option<PathBuf> {
+    init_info().and_then(|i| i.config.measures_dir)
+}
+
+/// Initialize casacore with the default configuration if it has not been
+/// initialized yet. This is done automatically when a table is first opened
+/// or created.
+pub fn ensure_initialized() -> Result<(), Error> {
+    let mut state = STATE
+        .lock()
+        .map_err(|_| err_msg("an earlier casacore initialization panicked"))?;
+
+    if state.is_none() {
+        *state = Some(do_initialize(Config::default(), true)?);
+    }
+
+    Ok(())
+}
+
+fn do_initialize(config: Config, implicit: bool) -> Result<InitInfo, Error> {
+    if let Some(ref dir) = config.measures_dir {
+        check_measures_dir(dir)?;
+    }
+
+    let aips_path = glue::StringBridge::from_rust(config.aips_path.as_ref().map_or("", |s| &s[..]));
+    let mut aips_root = CasaString::new();
+    let mut aips_home = CasaString::new();
+    let mut exc_info = unsafe { ::std::mem::zeroed::<glue::ExcInfo>() };
+
+    let rv = unsafe {
+        glue_call!(casacore_initialize(
+            &aips_path,
+            aips_root.as_mut_ptr(),
+            aips_home.as_mut_ptr(),
+            &mut exc_info,
+        ))
+    };
+
+    if rv != 0 {
+        return Err(err_msg(format!(
+            "failed to initialize casacore: {}",
+            exc_info.as_error()
+        )));
+    }
+
+    Ok(InitInfo {
+        config: config,
+        implicit: implicit,
+        aips_root: aips_root.into(),
+        aips_home: aips_home.into(),
+    })
+}
+
+fn check_measures_dir(dir: &Path) -> Result<(), Error> {
+    if !dir.is_dir() {
+        return Err(err_msg(format!(
+            "the measures data directory \"{}\" does not exist",
+            dir.display()
+        )));
+    }
+
+    let missing: Vec<&str> = MEASURES_SUBDIRS
+        .iter()
+        .cloned()
+        .filter(|s| !dir.join(s).is_dir())
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(err_msg(format!(
+            "\"{}\" does not look like a casacore measures data directory: it has no {} subdirectory",
+            dir.display(),
+            missing.join(" or ")
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn measures_dir_check() {
+    let base = env::temp_dir().join(format!("rubbl-measures-test-{}", ::std::process::id()));
+    let _ = ::std::fs::remove_dir_all(&base);
+    assert!(check_measures_dir(&base).is_err());
+
+    ::std::fs::create_dir_all(base.join("geodetic")).unwrap();
+    let e = check_measures_dir(&base).unwrap_err().to_string();
+    assert!(e.contains("no ephemerides subdirectory"));
+
+    ::std::fs::create_dir_all(base.join("ephemerides")).unwrap();
+    assert!(check_measures_dir(&base).is_ok());
+    ::std::fs::remove_dir_all(&base).unwrap();
+}
@@ -5,36 +5,264 @@ extern crate failure;
 #[macro_use]
 extern crate failure_derive;
 extern crate ndarray;
+#[cfg(feature = "derive")]
+extern crate rubbl_casatables_derive;
 extern crate rubbl_casatables_impl;
 extern crate rubbl_core;
+#[cfg(feature = "fits")]
+extern crate rubbl_fits;
+extern crate rubbl_visdata;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 
 use failure::{err_msg, Error};
-use ndarray::Dimension;
+use ndarray::{ArrayD, ArrayViewD, Dimension, IxDyn};
+use rubbl_core::budget::MemoryBudget;
+use rubbl_core::decode::{Adler32, Checksum, Crc32};
+use rubbl_core::dryrun::{ChangePlan, DryRun};
+use rubbl_core::io::RetryPolicy;
 use rubbl_core::num::{DimFromShapeSlice, DimensionMismatchError};
+use rubbl_core::output::OutputPolicy;
+use rubbl_core::time;
 use rubbl_core::{Array, Complex};
+use std::cell::RefCell;
 use std::fmt;
-use std::path::Path;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{mpsc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 mod glue;
 
+pub use glue::GlueDataType;
+pub use init::{initialize, Config};
+pub use rows::{CasaTableRow, RowColumn};
+#[cfg(feature = "derive")]
+pub use rubbl_casatables_derive::CasaTableRow;
+
+// Instrumentation of glue calls
+
+/// Call a function of the C++ glue layer.
+///
+/// If the `tracing` feature is enabled, the call is made inside a
+/// TRACE-level span named `glue` that records the name of the function and
+/// any further fields given after the semicolon (typically the table path,
+/// column name, and row or row range), and an event recording how long the
+/// call took is emitted when it returns. Otherwise this is just a plain
+/// call. Like the functions it calls, the macro must be used inside an
+/// `unsafe` block.
+///
+/// Every call holds the process-wide `GLUE_LOCK` while it runs; see there
+/// for why.
+macro_rules! glue_call {
+    ($func:ident($($arg:expr),* $(,)*) $(; $($field:ident = $value:expr),*)*) => {{
+        let _glue_lock = $crate::lock_glue();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(
+            "glue",
+            call = stringify!($func)
+            $($(, $field = ?$value)*)*
+        )
+        .entered();
+        #[cfg(feature = "tracing")]
+        let _timer = $crate::GlueCallTimer::start();
+        $crate::glue::$func($($arg),*)
+    }};
+}
+
+/// Serializes calls into casacore.
+///
+/// casacore is built without `USE_THREADS`, so its mutexes do nothing, and
+/// handles are not independent: opening a table that is already open
+/// returns the same `PlainTable` from the process-wide table cache, with the
+/// same storage managers and buffers. Two threads calling into casacore at
+/// once, even through different handles and even only to read, can
+/// therefore race on its internal state. Holding this lock for the duration
+/// of every glue call rules that out, at the cost of any parallelism inside
+/// casacore itself. The lock is not reentrant, so glue calls must not be
+/// made from the callbacks that some glue functions invoke.
+///
+/// A lock per table would not be enough: the table cache, the registry of
+/// storage managers and the `Aipsrc` settings are shared by all tables, and
+/// opening or closing any table touches them. When uncontended the lock
+/// costs a few nanoseconds, small next to even the cheapest glue call. What
+/// it does cost is concurrency: threads reading the same or different
+/// tables take turns, and `prefetched_column_chunks` can only overlap a
+/// read with the consumer's own processing. Work that needs casacore to run
+/// in parallel should use separate processes, as `partition::for_each_ddid`
+/// does.
+static GLUE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Acquire `GLUE_LOCK`. A panic cannot unwind out of a glue call, so a
+/// poisoned lock guards nothing half-done and is simply taken over.
+fn lock_glue() -> MutexGuard<'static, ()> {
+    GLUE_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Emits the duration of a glue call when dropped.
+#[cfg(feature = "tracing")]
+struct GlueCallTimer(std::time::Instant);
+
+#[cfg(feature = "tracing")]
+impl GlueCallTimer {
+    fn start() -> Self {
+        GlueCallTimer(std::time::Instant::now())
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for GlueCallTimer {
+    fn drop(&mut self) {
+        let elapsed = self.0.elapsed();
+        tracing::trace!(
+            elapsed_us = elapsed.as_secs() * 1_000_000 + u64::from(elapsed.subsec_micros()),
+            "glue call returned"
+        );
+    }
+}
+
+// Submodules are declared after `glue_call!` so that they can use it.
+
+pub mod bitmap;
+pub mod chanflag;
+pub mod diskusage;
+#[cfg(feature = "fits")]
+pub mod fits;
+pub mod flagcat;
+pub mod init;
+pub mod manifest;
+pub mod mms;
+pub mod ms;
+pub mod msstats;
+pub mod mswriter;
+pub mod partition;
+pub mod planner;
+pub mod rows;
+pub mod rowserde;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod tabledesc;
+
 // Exceptions
 
 /// An error type used when the wrapped "casacore" C++ code raises an
 /// exception.
 #[derive(Fail, Debug)]
-#[fail(display = "{}", _0)]
-pub struct CasacoreError(String);
+#[fail(display = "{}", message)]
+pub struct CasacoreError {
+    kind: CasacoreErrorKind,
+    message: String,
+}
+
+impl CasacoreError {
+    fn general<S: Into<String>>(message: S) -> Self {
+        CasacoreError {
+            kind: CasacoreErrorKind::General,
+            message: message.into(),
+        }
+    }
+
+    /// Get the kind of problem that caused this error.
+    pub fn kind(&self) -> CasacoreErrorKind {
+        self.kind
+    }
+
+    /// Get the error message reported by casacore.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// The kinds of problem that a `CasacoreError` can describe.
+///
+/// These are derived from the class of the exception that casacore raised.
+/// New kinds may be added in the future.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CasacoreErrorKind {
+    /// A problem not covered by any of the other kinds.
+    General,
+
+    /// A table-related problem not covered by any of the other kinds.
+    Table,
+
+    /// The table does not exist, or is not a valid table.
+    TableNotFound,
+
+    /// A new table could not be created because one already exists.
+    TableExists,
+
+    /// A column named in the operation does not exist.
+    NoSuchColumn,
+
+    /// A value had an invalid data type for its column or keyword.
+    DataType,
+
+    /// The shapes of arrays involved in the operation did not conform.
+    Conformance,
+
+    /// An index, such as a row number, was out of bounds.
+    OutOfBounds,
+
+    /// An operating-system call, such as one performing I/O, failed.
+    Io,
+
+    /// Memory could not be allocated.
+    Alloc,
+
+    /// The table is locked by another process. This is only reported by
+    /// `Table::open_with_options`, when it gives up waiting for the lock.
+    Locked,
+}
 
 impl glue::ExcInfo {
     fn as_error(&self) -> CasacoreError {
-        let c_str = unsafe { std::ffi::CStr::from_ptr(self.message.as_ptr()) };
-
-        let msg = match c_str.to_str() {
-            Ok(s) => s,
-            Err(_) => "[un-translatable C++ exception]",
+        // Don't trust the C++ code to have NUL-terminated the message.
+        let bytes: Vec<u8> = self
+            .message
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as u8)
+            .collect();
+
+        let kind = match self.category {
+            c if c == glue::GlueExceptionCategory::GEC_TABLE as i32 => CasacoreErrorKind::Table,
+            c if c == glue::GlueExceptionCategory::GEC_TABLE_NOT_FOUND as i32 => {
+                CasacoreErrorKind::TableNotFound
+            }
+            c if c == glue::GlueExceptionCategory::GEC_TABLE_EXISTS as i32 => {
+                CasacoreErrorKind::TableExists
+            }
+            c if c == glue::GlueExceptionCategory::GEC_NO_SUCH_COLUMN as i32 => {
+                CasacoreErrorKind::NoSuchColumn
+            }
+            c if c == glue::GlueExceptionCategory::GEC_DATA_TYPE as i32 => {
+                CasacoreErrorKind::DataType
+            }
+            c if c == glue::GlueExceptionCategory::GEC_CONFORMANCE as i32 => {
+                CasacoreErrorKind::Conformance
+            }
+            c if c == glue::GlueExceptionCategory::GEC_OUT_OF_BOUNDS as i32 => {
+                CasacoreErrorKind::OutOfBounds
+            }
+            c if c == glue::GlueExceptionCategory::GEC_IO as i32 => CasacoreErrorKind::Io,
+            c if c == glue::GlueExceptionCategory::GEC_ALLOC as i32 => CasacoreErrorKind::Alloc,
+            _ => CasacoreErrorKind::General,
         };
 
-        CasacoreError(msg.to_owned())
+        CasacoreError {
+            kind: kind,
+            message: String::from_utf8_lossy(&bytes).into_owned(),
+        }
     }
 
     fn as_err<T, E>(&self) -> Result<T, E>
@@ -45,6 +273,35 @@ impl glue::ExcInfo {
     }
 }
 
+#[cfg(test)]
+#[test]
+fn exc_info_as_error() {
+    let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+    let e = exc_info.as_error();
+    assert_eq!(e.kind(), CasacoreErrorKind::General);
+    assert_eq!(e.message(), "");
+
+    for (i, b) in b"Table column FOO is unknown".iter().enumerate() {
+        exc_info.message[i] = *b as _;
+    }
+
+    exc_info.category = glue::GlueExceptionCategory::GEC_NO_SUCH_COLUMN as _;
+    let e = exc_info.as_error();
+    assert_eq!(e.kind(), CasacoreErrorKind::NoSuchColumn);
+    assert_eq!(e.to_string(), "Table column FOO is unknown");
+
+    // Unknown categories and unterminated messages must be tolerated.
+    exc_info.category = 12345;
+
+    for c in exc_info.message.iter_mut() {
+        *c = b'x' as _;
+    }
+
+    let e = exc_info.as_error();
+    assert_eq!(e.kind(), CasacoreErrorKind::General);
+    assert_eq!(e.message().len(), 512);
+}
+
 // Data types
 
 impl glue::GlueDataType {
@@ -55,6 +312,32 @@ impl glue::GlueDataType {
     pub fn element_size(&self) -> i32 {
         unsafe { glue::data_type_get_element_size(*self) as i32 }
     }
+
+    /// Return the array data type whose elements have this data type.
+    ///
+    /// Array types, and types that cannot be the elements of arrays, are
+    /// returned unchanged.
+    pub fn as_array(&self) -> glue::GlueDataType {
+        use self::glue::GlueDataType::*;
+
+        match *self {
+            TpBool => TpArrayBool,
+            TpChar => TpArrayChar,
+            TpUChar => TpArrayUChar,
+            TpShort => TpArrayShort,
+            TpUShort => TpArrayUShort,
+            TpInt => TpArrayInt,
+            TpUInt => TpArrayUInt,
+            TpInt64 => TpArrayInt64,
+            TpFloat => TpArrayFloat,
+            TpDouble => TpArrayDouble,
+            TpComplex => TpArrayComplex,
+            TpDComplex => TpArrayDComplex,
+            TpString => TpArrayString,
+            TpQuantity => TpArrayQuantity,
+            other => other,
+        }
+    }
 }
 
 impl fmt::Display for glue::GlueDataType {
@@ -95,6 +378,54 @@ impl fmt::Display for glue::GlueDataType {
     }
 }
 
+/// An error type for when a data type name is not recognized.
+#[derive(Fail, Debug)]
+#[fail(display = "unrecognized data type name \"{}\"", _0)]
+pub struct UnknownDataTypeError(String);
+
+/// Data types are parsed from the names used by their `Display`
+/// implementation, such as `"i32"` or `"arr<f64>"`.
+impl FromStr for glue::GlueDataType {
+    type Err = UnknownDataTypeError;
+
+    fn from_str(s: &str) -> Result<Self, UnknownDataTypeError> {
+        Ok(match s {
+            "bool" => glue::GlueDataType::TpBool,
+            "i8" => glue::GlueDataType::TpChar,
+            "u8" => glue::GlueDataType::TpUChar,
+            "i16" => glue::GlueDataType::TpShort,
+            "u16" => glue::GlueDataType::TpUShort,
+            "i32" => glue::GlueDataType::TpInt,
+            "u32" => glue::GlueDataType::TpUInt,
+            "f32" => glue::GlueDataType::TpFloat,
+            "f64" => glue::GlueDataType::TpDouble,
+            "c32" => glue::GlueDataType::TpComplex,
+            "c64" => glue::GlueDataType::TpDComplex,
+            "string" => glue::GlueDataType::TpString,
+            "table" => glue::GlueDataType::TpTable,
+            "arr<bool>" => glue::GlueDataType::TpArrayBool,
+            "arr<i8>" => glue::GlueDataType::TpArrayChar,
+            "arr<u8>" => glue::GlueDataType::TpArrayUChar,
+            "arr<i16>" => glue::GlueDataType::TpArrayShort,
+            "arr<u16>" => glue::GlueDataType::TpArrayUShort,
+            "arr<i32>" => glue::GlueDataType::TpArrayInt,
+            "arr<u32>" => glue::GlueDataType::TpArrayUInt,
+            "arr<f32>" => glue::GlueDataType::TpArrayFloat,
+            "arr<f64>" => glue::GlueDataType::TpArrayDouble,
+            "arr<c32>" => glue::GlueDataType::TpArrayComplex,
+            "arr<c64>" => glue::GlueDataType::TpArrayDComplex,
+            "arr<string>" => glue::GlueDataType::TpArrayString,
+            "record" => glue::GlueDataType::TpRecord,
+            "other" => glue::GlueDataType::TpOther,
+            "quantity" => glue::GlueDataType::TpQuantity,
+            "arr<quantity>" => glue::GlueDataType::TpArrayQuantity,
+            "i64" => glue::GlueDataType::TpInt64,
+            "arr<i64>" => glue::GlueDataType::TpArrayInt64,
+            _ => return Err(UnknownDataTypeError(s.to_owned())),
+        })
+    }
+}
+
 /// A type that can be translated into a CASA table data type.
 pub trait CasaDataType: Clone + PartialEq + Sized {
     const DATA_TYPE: glue::GlueDataType;
@@ -344,31 +675,339 @@ mod data_type_tests {
 
 impl glue::StringBridge {
     fn from_rust(s: &str) -> Self {
+        Self::from_bytes(s.as_bytes())
+    }
+
+    fn from_bytes(b: &[u8]) -> Self {
         Self {
-            data: s.as_ptr() as _,
-            n_bytes: s.len() as std::os::raw::c_ulong,
+            data: b.as_ptr() as _,
+            n_bytes: b.len() as u64,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        // `from_raw_parts` requires a non-null pointer even for empty slices.
+        if self.n_bytes == 0 {
+            return &[];
         }
+
+        unsafe { std::slice::from_raw_parts(self.data as *const u8, self.n_bytes as usize) }
     }
 
     fn to_rust(&self) -> String {
-        let buf =
-            unsafe { std::slice::from_raw_parts(self.data as *const u8, self.n_bytes as usize) };
+        String::from_utf8_lossy(self.as_bytes()).into_owned()
+    }
+}
+
+/// A string whose data have been copied out of casacore by the glue layer.
+///
+/// Glue functions that return strings, rather than passing them to
+/// callbacks, fill in `StringBridge` structures pointing to buffers that
+/// they allocate and that we must free. This type owns such a buffer,
+/// releasing it when dropped. It has the same layout as a `StringBridge`, so
+/// that a slice of them can be passed to the glue as a destination.
+///
+/// The contents are arbitrary bytes: they may include NULs, and are only
+/// checked for UTF-8 validity when converted into a `String`, with invalid
+/// sequences replaced by U+FFFD.
+#[repr(transparent)]
+struct CasaString(glue::StringBridge);
+
+impl CasaString {
+    /// Create an empty string, ready to be filled in by the glue.
+    fn new() -> Self {
+        CasaString(glue::StringBridge {
+            data: std::ptr::null(),
+            n_bytes: 0,
+        })
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut glue::StringBridge {
+        &mut self.0
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl Drop for CasaString {
+    fn drop(&mut self) {
+        if !self.0.data.is_null() {
+            unsafe { glue::string_bridge_free(&mut self.0) }
+        }
+    }
+}
+
+impl From<CasaString> for String {
+    fn from(s: CasaString) -> String {
+        s.0.to_rust()
+    }
+}
+
+impl fmt::Debug for CasaString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CasaString({:?})",
+            String::from_utf8_lossy(self.as_bytes())
+        )
+    }
+}
+
+#[cfg(test)]
+fn casa_string_round_trip(b: &[u8]) -> CasaString {
+    let source = glue::StringBridge::from_bytes(b);
+    let mut dest = CasaString::new();
+    let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+
+    if unsafe { glue::string_bridge_copy(&source, dest.as_mut_ptr(), &mut exc_info) } != 0 {
+        panic!("{}", exc_info.as_error());
+    }
+
+    dest
+}
 
-        String::from_utf8_lossy(buf).into_owned()
+#[cfg(test)]
+#[test]
+fn casa_string_contents() {
+    // Lengths around the small-string limits of the common C++ standard
+    // libraries, so that both inline and heap-allocated strings are covered.
+    let mut cases: Vec<String> = (0..40).map(|n| "x".repeat(n)).collect();
+    cases.push("a\0b\0".to_owned());
+    cases.push("\0".repeat(20));
+    cases.push("Ωμέγα Centauri ✓ 東京".to_owned());
+    cases.push("\u{1F4E1}".repeat(10));
+    cases.push("é".repeat(5000));
+
+    for s in &cases {
+        let c = casa_string_round_trip(s.as_bytes());
+        assert_eq!(c.as_bytes(), s.as_bytes());
+        assert_eq!(&String::from(c), s);
     }
 }
 
+#[cfg(test)]
+#[test]
+fn casa_string_lossy() {
+    let c = casa_string_round_trip(b"ok\xff\xfe!");
+    assert_eq!(c.as_bytes(), b"ok\xff\xfe!");
+    assert_eq!(String::from(c), "ok\u{FFFD}\u{FFFD}!");
+}
+
+#[cfg(test)]
+#[test]
+fn casa_string_empty() {
+    let c = CasaString::new();
+    assert_eq!(c.as_bytes(), b"");
+    assert_eq!(String::from(c), "");
+
+    // Filled in by the glue, but still empty.
+    let c = casa_string_round_trip(b"");
+    assert_eq!(String::from(c), "");
+}
+
 // Tables
 
+/// An error type for when a path cannot be passed to casacore.
+///
+/// On Unix, casacore accepts arbitrary byte strings as paths, so this error
+/// never occurs. On other platforms, including Windows, paths are passed to
+/// casacore as UTF-8, so paths that are not valid Unicode cannot be used.
+#[derive(Fail, Debug)]
+#[fail(
+    display = "the path \"{}\" cannot be used because it is not valid Unicode",
+    _0
+)]
+pub struct NonUnicodePathError(String);
+
+#[cfg(unix)]
+fn path_as_bytes(path: &Path) -> Result<&[u8], NonUnicodePathError> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_as_bytes(path: &Path) -> Result<&[u8], NonUnicodePathError> {
+    match path.to_str() {
+        Some(s) => Ok(s.as_bytes()),
+        None => Err(NonUnicodePathError(path.display().to_string())),
+    }
+}
+
 pub struct Table {
     handle: *mut glue::GlueTable,
     exc_info: glue::ExcInfo,
+    path: PathBuf,
+    dry_run: Option<ChangePlan>,
+    /// This is a `RefCell` so that buffered cells can be written out by
+    /// methods such as `scalar_column` that only borrow the table.
+    write_behind: RefCell<Option<WriteBehind>>,
 }
 
+// A handle does not own its casacore objects outright: handles onto the same
+// table share one cached PlainTable, and casacore has no locking of its own.
+// Moving a handle to another thread is nonetheless sound because every call
+// into casacore holds `GLUE_LOCK`, so no two threads are ever inside it at
+// once, and none of its objects are tied to the thread that created them.
+// Handles are still not `Sync`.
+unsafe impl Send for Table {}
+
+/// How `Table::open` opens a table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum TableOpenMode {
+    /// Open an existing table for reading.
     Read = 1,
+
+    /// Open an existing table for reading and writing.
     ReadWrite = 2,
+
+    /// Create a new, empty table, replacing any table already at the path.
+    /// The table has no columns or rows; add them with `add_array_column`
+    /// and `add_rows`.
     Create = 3,
+
+    /// Like `Create`, but fail if a table already exists at the path.
+    CreateNoOverwrite = 4,
+}
+
+impl TableOpenMode {
+    fn to_glue(self) -> glue::TableOpenMode {
+        match self {
+            TableOpenMode::Read => glue::TableOpenMode::TOM_OPEN_READONLY,
+            TableOpenMode::ReadWrite => glue::TableOpenMode::TOM_OPEN_RW,
+            TableOpenMode::Create => glue::TableOpenMode::TOM_CREATE,
+            TableOpenMode::CreateNoOverwrite => glue::TableOpenMode::TOM_CREATE_NO_OVERWRITE,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn create_modes() {
+    let dir = std::env::temp_dir().join(format!("rubbl-open-modes-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("t.table");
+
+    {
+        let mut t = Table::open(&path, TableOpenMode::CreateNoOverwrite).unwrap();
+        t.add_rows(3).unwrap();
+    }
+
+    assert_eq!(Table::open(&path, TableOpenMode::Read).unwrap().n_rows(), 3);
+    assert!(Table::open(&path, TableOpenMode::CreateNoOverwrite).is_err());
+    assert_eq!(Table::open(&path, TableOpenMode::Read).unwrap().n_rows(), 3);
+
+    {
+        let mut t = Table::open(&path, TableOpenMode::Create).unwrap();
+        t.add_rows(1).unwrap();
+    }
+
+    assert_eq!(
+        Table::open(&path, TableOpenMode::ReadWrite)
+            .unwrap()
+            .n_rows(),
+        1
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn modify_policies() {
+    let dir = std::env::temp_dir().join(format!("rubbl-modify-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.table");
+    let output = dir.join("out.table");
+    Table::open(&input, TableOpenMode::Create)
+        .unwrap()
+        .add_rows(3)
+        .unwrap();
+
+    let n = Table::modify(&input, &OutputPolicy::NewTable(output.clone()), |t| {
+        t.add_rows(2)?;
+        Ok(t.n_rows())
+    })
+    .unwrap();
+    assert_eq!(n, 5);
+    assert_eq!(
+        Table::open(&input, TableOpenMode::Read).unwrap().n_rows(),
+        3
+    );
+    assert_eq!(
+        Table::open(&output, TableOpenMode::Read).unwrap().n_rows(),
+        5
+    );
+
+    let r: Result<(), Error> =
+        Table::modify(&input, &OutputPolicy::Overwrite(output.clone()), |t| {
+            t.add_rows(1)?;
+            Err(err_msg("failed"))
+        });
+    assert!(r.is_err());
+    assert_eq!(
+        Table::open(&output, TableOpenMode::Read).unwrap().n_rows(),
+        5
+    );
+
+    Table::modify(&input, &OutputPolicy::InPlace, |t| {
+        t.add_rows(1).map_err(|e| e.into())
+    })
+    .unwrap();
+    assert_eq!(
+        Table::open(&input, TableOpenMode::Read).unwrap().n_rows(),
+        4
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn array_cells() {
+    let dir = std::env::temp_dir().join(format!("rubbl-array-cells-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut t = Table::create_with_scalar_columns(dir.join("t.table"), &[], 2).unwrap();
+    t.add_array_column("FLAG", GlueDataType::TpBool, None)
+        .unwrap();
+
+    let flags = ndarray::Array2::from_shape_fn((3, 2), |(i, j)| i == j);
+    t.put_cell("FLAG", 1, &flags).unwrap();
+    assert_eq!(
+        t.get_cell::<ndarray::Array2<bool>>("FLAG", 1).unwrap(),
+        flags
+    );
+    assert!(t.get_cell::<bool>("FLAG", 1).is_err());
+
+    drop(t);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn dynamic_array_cells() {
+    let dir = std::env::temp_dir().join(format!("rubbl-array-dyn-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut t =
+        Table::create_with_scalar_columns(dir.join("t.table"), &[("S", GlueDataType::TpInt)], 1)
+            .unwrap();
+    t.add_array_column("DATA", GlueDataType::TpComplex, None)
+        .unwrap();
+
+    // Four channels by two correlations, as in a Measurement Set.
+    let data = ndarray::Array2::from_shape_fn((4, 2), |(chan, corr)| {
+        Complex::new(chan as f32, corr as f32)
+    });
+    t.put_cell("DATA", 0, &data).unwrap();
+
+    let cell = t.get_cell_array::<Complex<f32>>("DATA", 0).unwrap();
+    assert_eq!(cell.shape(), &[4, 2]);
+    assert_eq!(cell, data.into_dyn());
+
+    let err = t.get_cell_array::<i32>("S", 0).unwrap_err();
+    assert!(err.downcast_ref::<NotArrayColumnError>().is_some());
+
+    drop(t);
+    std::fs::remove_dir_all(&dir).unwrap();
 }
 
 #[derive(Fail, Debug)]
@@ -385,26 +1024,28 @@ pub struct NotScalarColumnError(glue::GlueDataType);
 )]
 pub struct UnexpectedDataTypeError(glue::GlueDataType, glue::GlueDataType);
 
+#[derive(Fail, Debug)]
+#[fail(
+    display = "Expected a column with a vector data type, but found a scalar {}",
+    _0
+)]
+pub struct NotArrayColumnError(glue::GlueDataType);
+
+#[derive(Fail, Debug)]
+#[fail(display = "no column named \"{}\" in the table", _0)]
+pub struct NoSuchColumnError(String);
+
 impl Table {
+    /// Open or create the table at *path*, as directed by *mode*.
     pub fn open<P: AsRef<Path>>(path: P, mode: TableOpenMode) -> Result<Self, Error> {
-        let spath = match path.as_ref().to_str() {
-            Some(s) => s,
-            None => {
-                return Err(err_msg(
-                    "table paths must be representable as UTF-8 strings",
-                ));
-            }
-        };
-        let cpath = glue::StringBridge::from_rust(spath);
+        init::ensure_initialized()?;
+        let path = path.as_ref();
+        let cpath = glue::StringBridge::from_bytes(path_as_bytes(path)?);
         let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
 
-        let cmode = match mode {
-            TableOpenMode::Read => glue::TableOpenMode::TOM_OPEN_READONLY,
-            TableOpenMode::ReadWrite => glue::TableOpenMode::TOM_OPEN_RW,
-            TableOpenMode::Create => glue::TableOpenMode::TOM_CREATE,
-        };
-
-        let handle = unsafe { glue::table_alloc_and_open(&cpath, cmode, &mut exc_info) };
+        let cmode = mode.to_glue();
+        let handle =
+            unsafe { glue_call!(table_alloc_and_open(&cpath, cmode, &mut exc_info); table = path) };
         if handle.is_null() {
             return exc_info.as_err();
         }
@@ -412,15 +1053,168 @@ impl Table {
         Ok(Table {
             handle: handle,
             exc_info: exc_info,
+            path: path.to_owned(),
+            dry_run: None,
+            write_behind: RefCell::new(None),
         })
     }
 
+    /// Open the table at *path* as directed by *options*.
+    ///
+    /// Unlike `open`, which waits for as long as it takes if another process
+    /// holds a lock on the table that conflicts with *options.mode*, this
+    /// checks for such a lock first, and retries after a delay as allowed by
+    /// `options.retry` and `options.timeout`. Opening for reading is held up
+    /// by write locks, and opening for writing by read locks as well. If the
+    /// lock is still held when the retries run out, it returns a
+    /// `CasacoreError` of kind `CasacoreErrorKind::Locked`. The lock might be
+    /// taken between the check and the opening, in which case this waits
+    /// like `open` does.
+    pub fn open_with_options<P: AsRef<Path>>(
+        path: P,
+        options: &OpenOptions,
+    ) -> Result<Self, Error> {
+        init::ensure_initialized()?;
+        let path = path.as_ref();
+        let cpath = glue::StringBridge::from_bytes(path_as_bytes(path)?);
+        let cmode = options.mode.to_glue();
+        let start = Instant::now();
+
+        wait_for_lock(
+            path,
+            options,
+            || {
+                let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+                let mut pid = 0;
+                let rv = unsafe {
+                    glue_call!(table_check_lock(&cpath, cmode, &mut pid, &mut exc_info); table = path)
+                };
+
+                match rv {
+                    0 => Ok(None),
+                    r if r > 0 => Ok(Some(pid)),
+                    _ => exc_info.as_err(),
+                }
+            },
+            || start.elapsed(),
+            thread::sleep,
+        )?;
+
+        Self::open(path, options.mode)
+    }
+
+    /// Create a new table at *path* whose columns are all scalars.
+    ///
+    /// Each entry of *columns* gives the name and data type of a column. The
+    /// table starts out with *n_rows* rows, which are filled with default
+    /// values. It is an error if something already exists at *path*. To
+    /// create tables with array columns, use `Table::create`.
+    pub fn create_with_scalar_columns<P: AsRef<Path>>(
+        path: P,
+        columns: &[(&str, GlueDataType)],
+        n_rows: u64,
+    ) -> Result<Self, Error> {
+        Self::create_with_scalar_columns_and_options(
+            path,
+            columns,
+            n_rows,
+            &CreateOptions::default(),
+        )
+    }
+
+    /// Like `create_with_scalar_columns`, but with control over how the
+    /// table is stored on disk. See `CreateOptions`.
+    pub fn create_with_scalar_columns_and_options<P: AsRef<Path>>(
+        path: P,
+        columns: &[(&str, GlueDataType)],
+        n_rows: u64,
+        options: &CreateOptions,
+    ) -> Result<Self, Error> {
+        let mut desc = tabledesc::TableDescription::new();
+
+        for &(name, data_type) in columns {
+            desc.add_scalar_column(name, data_type, "");
+        }
+
+        Self::create_with_options(path, &desc, n_rows, options)
+    }
+
+    /// Delete the table at *path*, including its subtables.
+    ///
+    /// This goes through casacore, which refuses to delete a table that is
+    /// open in this or another process and removes it properly. It is not
+    /// safe to delete a table by simply removing its directory, because
+    /// reference tables elsewhere may depend on it.
+    pub fn delete<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+        let cpath = glue::StringBridge::from_bytes(path_as_bytes(path.as_ref())?);
+        let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+
+        if unsafe { glue_call!(table_delete(&cpath, &mut exc_info); table = path.as_ref()) } != 0 {
+            return exc_info.as_err();
+        }
+
+        Ok(())
+    }
+
+    /// Rename the table at *old_path* to *new_path*.
+    ///
+    /// This goes through casacore so that the table's internal references,
+    /// such as those to its subtables, are updated correctly. It is an
+    /// error if something already exists at *new_path*.
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(old_path: P, new_path: Q) -> Result<(), Error> {
+        let cold_path = glue::StringBridge::from_bytes(path_as_bytes(old_path.as_ref())?);
+        let cnew_path = glue::StringBridge::from_bytes(path_as_bytes(new_path.as_ref())?);
+        let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+
+        if unsafe {
+            glue_call!(table_rename(&cold_path, &cnew_path, &mut exc_info);
+                table = old_path.as_ref())
+        } != 0
+        {
+            return exc_info.as_err();
+        }
+
+        Ok(())
+    }
+
+    /// Modify the table at *input*, sending the result where *output*
+    /// says.
+    ///
+    /// The table to be modified is opened for writing and passed to *op*. It
+    /// is closed before the output is committed, so *op* must not keep any
+    /// other handles onto it open. If *op* fails, the input is left as it
+    /// was unless the policy is to modify it in place.
+    pub fn modify<P, F, R>(input: P, output: &OutputPolicy, op: F) -> Result<R, Error>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Table) -> Result<R, Error>,
+    {
+        let prepared = output.prepare(input)?;
+
+        let result = {
+            let mut table = Table::open(prepared.path(), TableOpenMode::ReadWrite)?;
+            op(&mut table)?
+        };
+
+        prepared.commit()?;
+        Ok(result)
+    }
+
     pub fn n_rows(&self) -> u64 {
-        unsafe { glue::table_n_rows(self.handle) as u64 }
+        unsafe { glue_call!(table_n_rows(self.handle); table = self.path) as u64 }
     }
 
     pub fn n_columns(&self) -> usize {
-        unsafe { glue::table_n_columns(self.handle) as usize }
+        unsafe { glue_call!(table_n_columns(self.handle); table = self.path) as usize }
+    }
+
+    /// Return true if this is a reference table.
+    ///
+    /// A reference table holds no data of its own: it refers to a selection,
+    /// reordering, or projection of the rows and columns of another table.
+    /// Use `materialize` to turn one into a standalone table.
+    pub fn is_reference(&self) -> bool {
+        unsafe { glue_call!(table_is_reference(self.handle); table = self.path) != 0 }
     }
 
     pub fn column_names(&mut self) -> Result<Vec<String>, CasacoreError> {
@@ -453,12 +1247,12 @@ impl Table {
         where
             F: FnMut(String),
         {
-            glue::table_get_column_names(
+            glue_call!(table_get_column_names(
                 handle,
                 Some(casatables_cb_table_column_names::<F>),
                 &mut f as *mut _ as *mut std::os::raw::c_void,
                 exc_info,
-            )
+            ))
         }
 
         // Here's where we actually do stuff.
@@ -480,9 +1274,23 @@ impl Table {
     }
 
     pub fn remove_column(&mut self, col_name: &str) -> Result<(), CasacoreError> {
+        self.flush_writes()?;
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                self.path.display().to_string(),
+                format!("remove column \"{}\"", col_name),
+                0,
+                None,
+            );
+            return Ok(());
+        }
+
         let ccol_name = glue::StringBridge::from_rust(col_name);
 
-        let rv = unsafe { glue::table_remove_column(self.handle, &ccol_name, &mut self.exc_info) };
+        let rv = unsafe {
+            glue_call!(table_remove_column(self.handle, &ccol_name, &mut self.exc_info);
+                table = self.path, column = col_name)
+        };
 
         if rv != 0 {
             return self.exc_info.as_err();
@@ -491,44 +1299,221 @@ impl Table {
         Ok(())
     }
 
-    pub fn table_keyword_names(&mut self) -> Result<Vec<String>, CasacoreError> {
-        // Oh man. So, the C++ code behind this functionality reports back a
-        // sequence of casa::String (<=> std::string) objects, but they are
-        // only temporary, so for each item we need to make a copy of its
-        // contents right then and there. Therefore we need a callback. It
-        // took me a long time to get this right but I think it's working now.
-        // We need an "extern C fn" callback that the C code can call
-        // explicitly, which then hands off to the actual Rust closure of
-        // interest.
-
-        unsafe extern "C" fn casatables_cb_table_keyword_names<F>(
-            name: *const glue::StringBridge,
-            dtype: glue::GlueDataType,
-            ctxt: *mut std::os::raw::c_void,
-        ) where
-            F: FnMut(String, glue::GlueDataType),
-        {
-            let f: &mut F = &mut *(ctxt as *mut F);
-            f((&*name).to_rust(), dtype)
+    /// Add an array column whose elements have the scalar type *data_type*.
+    ///
+    /// If *shape* is given, every cell of the column has that shape, in C
+    /// order; otherwise the cells may have different shapes. The cells of
+    /// existing rows are left undefined.
+    pub fn add_array_column(
+        &mut self,
+        col_name: &str,
+        data_type: GlueDataType,
+        shape: Option<&[u64]>,
+    ) -> Result<(), CasacoreError> {
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                self.path.display().to_string(),
+                format!("add array column \"{}\"", col_name),
+                0,
+                None,
+            );
+            return Ok(());
         }
 
-        // I think we need this wrapper to give us a way to name the type `F`
-        // of our closure.
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let dims = shape.unwrap_or(&[]);
 
-        unsafe fn invoke<F>(
-            handle: *mut glue::GlueTable,
-            exc_info: &mut glue::ExcInfo,
-            mut f: F,
-        ) -> std::os::raw::c_int
-        where
-            F: FnMut(String, glue::GlueDataType),
-        {
-            glue::table_get_keyword_info(
-                handle,
-                Some(casatables_cb_table_keyword_names::<F>),
-                &mut f as *mut _ as *mut std::os::raw::c_void,
-                exc_info,
-            )
+        let rv = unsafe {
+            glue_call!(table_add_array_column(
+                self.handle,
+                &ccol_name,
+                data_type,
+                dims.len() as u64,
+                dims.as_ptr(),
+                &mut self.exc_info,
+            ); table = self.path, column = col_name)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(())
+    }
+
+    /// Set the table keyword *kw_name* to the string *value*.
+    pub fn put_keyword_string(&mut self, kw_name: &str, value: &str) -> Result<(), CasacoreError> {
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                self.path.display().to_string(),
+                format!("set keyword \"{}\"", kw_name),
+                0,
+                None,
+            );
+            return Ok(());
+        }
+
+        let ckw_name = glue::StringBridge::from_rust(kw_name);
+        let cvalue = glue::StringBridge::from_rust(value);
+
+        let rv = unsafe {
+            glue_call!(table_put_keyword_string(
+                self.handle,
+                &ckw_name,
+                &cvalue,
+                &mut self.exc_info,
+            ); table = self.path, keyword = kw_name)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(())
+    }
+
+    /// Make the table keyword *kw_name* refer to the existing table at
+    /// *subtable_path*, in the way that a Measurement Set refers to its
+    /// subtables.
+    pub fn put_keyword_subtable<P: AsRef<Path>>(
+        &mut self,
+        kw_name: &str,
+        subtable_path: P,
+    ) -> Result<(), Error> {
+        let subtable_path = subtable_path.as_ref();
+
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                self.path.display().to_string(),
+                format!(
+                    "set keyword \"{}\" to subtable {}",
+                    kw_name,
+                    subtable_path.display()
+                ),
+                0,
+                None,
+            );
+            return Ok(());
+        }
+
+        let ckw_name = glue::StringBridge::from_rust(kw_name);
+        let csubtable_path = glue::StringBridge::from_bytes(path_as_bytes(subtable_path)?);
+
+        let rv = unsafe {
+            glue_call!(table_put_keyword_subtable(
+                self.handle,
+                &ckw_name,
+                &csubtable_path,
+                &mut self.exc_info,
+            ); table = self.path, keyword = kw_name)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(())
+    }
+
+    /// Get the table's information: its type, subtype, and readme text.
+    pub fn info(&mut self) -> Result<TableInfo, CasacoreError> {
+        let mut table_type = CasaString::new();
+        let mut sub_type = CasaString::new();
+        let mut readme = CasaString::new();
+
+        let rv = unsafe {
+            glue_call!(table_get_info(
+                self.handle,
+                table_type.as_mut_ptr(),
+                sub_type.as_mut_ptr(),
+                readme.as_mut_ptr(),
+                &mut self.exc_info,
+            ); table = self.path)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(TableInfo {
+            table_type: table_type.into(),
+            sub_type: sub_type.into(),
+            readme: readme.into(),
+        })
+    }
+
+    /// Replace the table's information with *info*, writing it out to the
+    /// table's `table.info` file immediately.
+    pub fn set_info(&mut self, info: &TableInfo) -> Result<(), CasacoreError> {
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                self.path.display().to_string(),
+                format!("set table type to \"{}\"", info.table_type),
+                0,
+                None,
+            );
+            return Ok(());
+        }
+
+        let ctype = glue::StringBridge::from_rust(&info.table_type);
+        let csub_type = glue::StringBridge::from_rust(&info.sub_type);
+        let creadme = glue::StringBridge::from_rust(&info.readme);
+
+        let rv = unsafe {
+            glue_call!(table_set_info(
+                self.handle,
+                &ctype,
+                &csub_type,
+                &creadme,
+                &mut self.exc_info,
+            ); table = self.path)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(())
+    }
+
+    pub fn table_keyword_names(&mut self) -> Result<Vec<String>, CasacoreError> {
+        // Oh man. So, the C++ code behind this functionality reports back a
+        // sequence of casa::String (<=> std::string) objects, but they are
+        // only temporary, so for each item we need to make a copy of its
+        // contents right then and there. Therefore we need a callback. It
+        // took me a long time to get this right but I think it's working now.
+        // We need an "extern C fn" callback that the C code can call
+        // explicitly, which then hands off to the actual Rust closure of
+        // interest.
+
+        unsafe extern "C" fn casatables_cb_table_keyword_names<F>(
+            name: *const glue::StringBridge,
+            dtype: glue::GlueDataType,
+            ctxt: *mut std::os::raw::c_void,
+        ) where
+            F: FnMut(String, glue::GlueDataType),
+        {
+            let f: &mut F = &mut *(ctxt as *mut F);
+            f((&*name).to_rust(), dtype)
+        }
+
+        // I think we need this wrapper to give us a way to name the type `F`
+        // of our closure.
+
+        unsafe fn invoke<F>(
+            handle: *mut glue::GlueTable,
+            exc_info: &mut glue::ExcInfo,
+            mut f: F,
+        ) -> std::os::raw::c_int
+        where
+            F: FnMut(String, glue::GlueDataType),
+        {
+            glue_call!(table_get_keyword_info(
+                handle,
+                Some(casatables_cb_table_keyword_names::<F>),
+                &mut f as *mut _ as *mut std::os::raw::c_void,
+                exc_info,
+            ))
         }
 
         // Here's where we actually do stuff.
@@ -550,6 +1535,253 @@ impl Table {
         Ok(result)
     }
 
+    /// Set the keyword *kw_name* of the column *col_name* to the vector of
+    /// strings *values*, replacing it if it is already set.
+    pub fn put_column_keyword_strings(
+        &mut self,
+        col_name: &str,
+        kw_name: &str,
+        values: &[&str],
+    ) -> Result<(), CasacoreError> {
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                self.path.display().to_string(),
+                format!("set keyword \"{}\" of column \"{}\"", kw_name, col_name),
+                0,
+                None,
+            );
+            return Ok(());
+        }
+
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let ckw_name = glue::StringBridge::from_rust(kw_name);
+        let cvalues: Vec<_> = values
+            .iter()
+            .map(|v| glue::StringBridge::from_rust(v))
+            .collect();
+
+        let rv = unsafe {
+            glue_call!(table_put_column_keyword_strings(
+                self.handle,
+                &ccol_name,
+                &ckw_name,
+                cvalues.len() as u64,
+                cvalues.as_ptr(),
+                &mut self.exc_info,
+            ); table = self.path, column = col_name, keyword = kw_name)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(())
+    }
+
+    /// Get the values of the keyword *kw_name* of the column *col_name*,
+    /// which must be a string or a vector of strings. The result is empty if
+    /// the keyword is not set.
+    pub fn column_keyword_strings(
+        &mut self,
+        col_name: &str,
+        kw_name: &str,
+    ) -> Result<Vec<String>, CasacoreError> {
+        // See `column_names` for an explanation of this callback business.
+
+        unsafe extern "C" fn casatables_cb_column_keyword_strings<F>(
+            value: *const glue::StringBridge,
+            ctxt: *mut std::os::raw::c_void,
+        ) where
+            F: FnMut(String),
+        {
+            let f: &mut F = &mut *(ctxt as *mut F);
+            f((&*value).to_rust())
+        }
+
+        unsafe fn invoke<F>(
+            handle: *mut glue::GlueTable,
+            col_name: &glue::StringBridge,
+            kw_name: &glue::StringBridge,
+            exc_info: &mut glue::ExcInfo,
+            mut f: F,
+        ) -> std::os::raw::c_int
+        where
+            F: FnMut(String),
+        {
+            glue_call!(table_get_column_keyword_strings(
+                handle,
+                col_name,
+                kw_name,
+                Some(casatables_cb_column_keyword_strings::<F>),
+                &mut f as *mut _ as *mut std::os::raw::c_void,
+                exc_info,
+            ))
+        }
+
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let ckw_name = glue::StringBridge::from_rust(kw_name);
+        let mut result = Vec::new();
+
+        let rv = unsafe {
+            invoke(
+                self.handle,
+                &ccol_name,
+                &ckw_name,
+                &mut self.exc_info,
+                |value| {
+                    result.push(value);
+                },
+            )
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(result)
+    }
+
+    /// Get the names, types, and values of the keywords of this table, or
+    /// of its column *col_name* if that is given.
+    ///
+    /// The values are printed as text, for display: subtables appear as
+    /// their paths, subrecords as their numbers of fields, and everything
+    /// else as casacore prints it.
+    pub fn keyword_values(
+        &mut self,
+        col_name: Option<&str>,
+    ) -> Result<Vec<KeywordValue>, CasacoreError> {
+        // See `table_keyword_names` for an explanation of this callback
+        // business.
+
+        unsafe extern "C" fn casatables_cb_keyword_values<F>(
+            name: *const glue::StringBridge,
+            dtype: glue::GlueDataType,
+            value: *const glue::StringBridge,
+            ctxt: *mut std::os::raw::c_void,
+        ) where
+            F: FnMut(KeywordValue),
+        {
+            let f: &mut F = &mut *(ctxt as *mut F);
+            f(KeywordValue {
+                name: (&*name).to_rust(),
+                data_type: dtype,
+                value: (&*value).to_rust(),
+            })
+        }
+
+        unsafe fn invoke<F>(
+            handle: *mut glue::GlueTable,
+            col_name: &glue::StringBridge,
+            exc_info: &mut glue::ExcInfo,
+            mut f: F,
+        ) -> std::os::raw::c_int
+        where
+            F: FnMut(KeywordValue),
+        {
+            glue_call!(table_get_keyword_values(
+                handle,
+                col_name,
+                Some(casatables_cb_keyword_values::<F>),
+                &mut f as *mut _ as *mut std::os::raw::c_void,
+                exc_info,
+            ))
+        }
+
+        let ccol_name = glue::StringBridge::from_rust(col_name.unwrap_or(""));
+        let mut result = Vec::new();
+
+        let rv = unsafe {
+            invoke(self.handle, &ccol_name, &mut self.exc_info, |kw| {
+                result.push(kw);
+            })
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(result)
+    }
+
+    /// Get information about the data managers that store this table's
+    /// columns.
+    ///
+    /// Each column is served by exactly one data manager, so this tells you
+    /// how each column is laid out on disk.
+    pub fn data_manager_info(&mut self) -> Result<Vec<DataManagerInfo>, CasacoreError> {
+        // See `table_keyword_names` for an explanation of this callback
+        // dance. The C++ code calls us once per column, so we group the
+        // columns by data manager sequence number.
+
+        unsafe extern "C" fn casatables_cb_data_manager_info<F>(
+            dm_type: *const glue::StringBridge,
+            dm_name: *const glue::StringBridge,
+            seq_nr: u32,
+            col_name: *const glue::StringBridge,
+            bucket_size: i64,
+            n_tile_dim: std::os::raw::c_int,
+            tile_shape: *const u64,
+            ctxt: *mut std::os::raw::c_void,
+        ) where
+            F: FnMut(DataManagerInfo),
+        {
+            let f: &mut F = &mut *(ctxt as *mut F);
+
+            f(DataManagerInfo {
+                dm_type: (&*dm_type).to_rust(),
+                name: (&*dm_name).to_rust(),
+                seq_nr: seq_nr,
+                columns: vec![(&*col_name).to_rust()],
+                bucket_size: if bucket_size < 0 {
+                    None
+                } else {
+                    Some(bucket_size as u64)
+                },
+                tile_shape: if n_tile_dim == 0 {
+                    None
+                } else {
+                    Some(std::slice::from_raw_parts(tile_shape, n_tile_dim as usize).to_vec())
+                },
+            })
+        }
+
+        unsafe fn invoke<F>(
+            handle: *mut glue::GlueTable,
+            exc_info: &mut glue::ExcInfo,
+            mut f: F,
+        ) -> std::os::raw::c_int
+        where
+            F: FnMut(DataManagerInfo),
+        {
+            glue_call!(table_get_data_manager_info(
+                handle,
+                Some(casatables_cb_data_manager_info::<F>),
+                &mut f as *mut _ as *mut std::os::raw::c_void,
+                exc_info,
+            ))
+        }
+
+        let mut result: Vec<DataManagerInfo> = Vec::new();
+
+        let rv = unsafe {
+            invoke(self.handle, &mut self.exc_info, |mut info| {
+                if let Some(prev) = result.iter_mut().find(|p| p.seq_nr == info.seq_nr) {
+                    prev.columns.append(&mut info.columns);
+                    return;
+                }
+
+                result.push(info);
+            })
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(result)
+    }
+
     pub fn get_col_desc(&mut self, col_name: &str) -> Result<ColumnDescription, CasacoreError> {
         let ccol_name = glue::StringBridge::from_rust(col_name);
         let mut n_rows = 0;
@@ -560,7 +1792,7 @@ impl Table {
         let mut dims = [0; 8];
 
         let rv = unsafe {
-            glue::table_get_column_info(
+            glue_call!(table_get_column_info(
                 self.handle,
                 &ccol_name,
                 &mut n_rows,
@@ -570,7 +1802,7 @@ impl Table {
                 &mut n_dim,
                 dims.as_mut_ptr(),
                 &mut self.exc_info,
-            )
+            ); table = self.path, column = col_name)
         };
 
         if rv != 0 {
@@ -598,7 +1830,14 @@ impl Table {
         })
     }
 
+    /// Read every cell of the scalar column *col_name* into a vector, with
+    /// one bulk read on the C++ side.
+    ///
+    /// It is an error if *T* does not match the stored data type exactly.
+    /// `get_col_range_as_vec` reads only some of the rows, and also handles
+    /// fixed-shape array columns.
     pub fn get_col_as_vec<T: CasaScalarData>(&mut self, col_name: &str) -> Result<Vec<T>, Error> {
+        self.flush_writes()?;
         let ccol_name = glue::StringBridge::from_rust(col_name);
         let mut n_rows = 0;
         let mut data_type = glue::GlueDataType::TpOther;
@@ -608,7 +1847,7 @@ impl Table {
         let mut dims = [0; 8];
 
         let rv = unsafe {
-            glue::table_get_column_info(
+            glue_call!(table_get_column_info(
                 self.handle,
                 &ccol_name,
                 &mut n_rows,
@@ -618,7 +1857,7 @@ impl Table {
                 &mut n_dim,
                 dims.as_mut_ptr(),
                 &mut self.exc_info,
-            )
+            ); table = self.path, column = col_name)
         };
 
         if rv != 0 {
@@ -637,12 +1876,12 @@ impl Table {
 
         if data_type != glue::GlueDataType::TpString {
             let rv = unsafe {
-                glue::table_get_scalar_column_data(
+                glue_call!(table_get_scalar_column_data(
                     self.handle,
                     &ccol_name,
                     result.as_mut_ptr() as _,
                     &mut self.exc_info,
-                )
+                ); table = self.path, column = col_name)
             };
 
             if rv != 0 {
@@ -653,74 +1892,161 @@ impl Table {
                 result.set_len(n_rows as usize);
             }
         } else {
-            // We are not given ownership of the String objects that are
-            // returned, so we must std::mem::forget() them. Empirically, we
-            // have to initialize our string structures.
-            let mut glue_strings = Vec::<glue::StringBridge>::with_capacity(n_rows as usize);
-
-            for _ in 0..n_rows as usize {
-                glue_strings.push(glue::StringBridge::from_rust(""));
-            }
+            let mut glue_strings: Vec<_> =
+                (0..n_rows as usize).map(|_| CasaString::new()).collect();
 
             let rv = unsafe {
-                glue::table_get_scalar_column_data(
+                glue_call!(table_get_scalar_column_data(
                     self.handle,
                     &ccol_name,
                     glue_strings.as_mut_ptr() as _,
                     &mut self.exc_info,
-                )
+                ); table = self.path, column = col_name)
             };
 
             if rv != 0 {
                 return self.exc_info.as_err();
             }
 
-            for cstr in glue_strings.into_iter() {
-                result.push(T::casatables_string_pass_through(cstr.to_rust()));
-                std::mem::forget(cstr);
+            for cstr in glue_strings {
+                result.push(T::casatables_string_pass_through(cstr.into()));
             }
         };
 
         Ok(result)
     }
 
-    pub fn get_cell<T: CasaDataType>(&mut self, col_name: &str, row: u64) -> Result<T, Error> {
+    /// Return whether a cell contains a value.
+    ///
+    /// Cells of scalar columns always do, but cells of array columns may be
+    /// left undefined, in which case they cannot be read.
+    pub fn cell_is_defined(&mut self, col_name: &str, row: u64) -> Result<bool, CasacoreError> {
+        self.flush_writes()?;
         let ccol_name = glue::StringBridge::from_rust(col_name);
-        let mut data_type = glue::GlueDataType::TpOther;
-        let mut n_dim = 0;
-        let mut dims = [0; 8];
+        let mut is_defined = 0;
 
         let rv = unsafe {
-            glue::table_get_cell_info(
+            glue_call!(table_cell_is_defined(
                 self.handle,
                 &ccol_name,
                 row,
-                &mut data_type,
-                &mut n_dim,
-                dims.as_mut_ptr(),
+                &mut is_defined,
                 &mut self.exc_info,
-            )
+            ); table = self.path, column = col_name, row = row)
         };
 
         if rv != 0 {
             return self.exc_info.as_err();
         }
 
-        if data_type != T::DATA_TYPE {
-            return Err(UnexpectedDataTypeError(T::DATA_TYPE, data_type).into());
-        }
-
-        let result = if data_type != glue::GlueDataType::TpString {
-            let mut result = T::casatables_alloc(&dims[..n_dim as usize])?;
+        Ok(is_defined != 0)
+    }
+
+    /// Set the maximum size of the cache used to read and write a column,
+    /// in MiB. Zero means no limit.
+    ///
+    /// This only applies to columns stored with one of casacore's tiled
+    /// storage managers, which keep recently used tiles of data in memory.
+    /// The cache size can make a large difference to the speed of reading
+    /// tiled data in an order that does not match the tiling. An error is
+    /// returned for columns stored in other ways.
+    pub fn set_tile_cache_size(&mut self, col_name: &str, n_mib: u32) -> Result<(), CasacoreError> {
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+
+        if unsafe {
+            glue_call!(table_set_tile_cache_size(
+                self.handle,
+                &ccol_name,
+                n_mib,
+                &mut self.exc_info,
+            ); table = self.path, column = col_name)
+        } != 0
+        {
+            self.exc_info.as_err()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get the shape of the data in a cell, in C order. The shape of a
+    /// scalar cell is empty.
+    pub fn get_cell_shape(&mut self, col_name: &str, row: u64) -> Result<Vec<u64>, CasacoreError> {
+        self.flush_writes()?;
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let mut data_type = glue::GlueDataType::TpOther;
+        let mut n_dim = 0;
+        let mut dims = [0; 8];
+
+        let rv = unsafe {
+            glue_call!(table_get_cell_info(
+                self.handle,
+                &ccol_name,
+                row,
+                &mut data_type,
+                &mut n_dim,
+                dims.as_mut_ptr(),
+                &mut self.exc_info,
+            ); table = self.path, column = col_name, row = row)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(dims[..n_dim as usize].to_vec())
+    }
+
+    /// Read the cell of column *col_name* in row *row*.
+    ///
+    /// *T* may be a scalar type such as `f64` or `String`, for scalar
+    /// columns, or a vector or `ndarray` array of one, for array columns.
+    /// It is an error if *T* does not match the stored data type exactly;
+    /// see `ScalarColumn` for reading with type promotion.
+    pub fn get_cell<T: CasaDataType>(&mut self, col_name: &str, row: u64) -> Result<T, Error> {
+        self.flush_writes()?;
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let mut data_type = glue::GlueDataType::TpOther;
+        let mut n_dim = 0;
+        let mut dims = [0; 8];
+
+        let rv = unsafe {
+            glue_call!(table_get_cell_info(
+                self.handle,
+                &ccol_name,
+                row,
+                &mut data_type,
+                &mut n_dim,
+                dims.as_mut_ptr(),
+                &mut self.exc_info,
+            ); table = self.path, column = col_name, row = row)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        // casacore describes array columns by the type of their elements.
+        let cell_type = if n_dim > 0 {
+            data_type.as_array()
+        } else {
+            data_type
+        };
+
+        if cell_type != T::DATA_TYPE {
+            return Err(UnexpectedDataTypeError(T::DATA_TYPE, cell_type).into());
+        }
+
+        let result = if data_type != glue::GlueDataType::TpString {
+            let mut result = T::casatables_alloc(&dims[..n_dim as usize])?;
 
             let rv = unsafe {
-                glue::table_get_cell(
+                glue_call!(table_get_cell(
                     self.handle,
                     &ccol_name,
                     row,
                     result.casatables_as_mut_buf() as _,
                     &mut self.exc_info,
-                )
+                ); table = self.path, column = col_name, row = row)
             };
 
             if rv != 0 {
@@ -729,315 +2055,3994 @@ impl Table {
 
             result
         } else {
-            // We are not given ownership of the String object that is
-            // returned, so we must std::mem::forget() it.
-            let mut glue_string = glue::StringBridge::from_rust("");
+            let mut glue_string = CasaString::new();
 
             let rv = unsafe {
-                glue::table_get_cell(
+                glue_call!(table_get_cell(
                     self.handle,
                     &ccol_name,
                     row,
-                    &mut glue_string as *mut glue::StringBridge as _,
+                    glue_string.as_mut_ptr() as _,
                     &mut self.exc_info,
-                )
+                ); table = self.path, column = col_name, row = row)
             };
 
             if rv != 0 {
                 return self.exc_info.as_err();
             }
 
-            let result = T::casatables_string_pass_through(glue_string.to_rust());
-            std::mem::forget(glue_string);
-            result
+            T::casatables_string_pass_through(glue_string.into())
         };
 
         Ok(result)
     }
 
+    /// Read the cell of the array column *col_name* in row *row* into an
+    /// `ndarray` array of whatever dimensionality it has.
+    ///
+    /// The shape is that of `get_cell_shape`, in C order: the `DATA` cells
+    /// of a Measurement Set come out with shape `(n_chan, n_corr)`. When the
+    /// dimensionality is known in advance, `get_cell::<Array2<T>>` and the
+    /// like check it and avoid the dynamic indexing.
+    pub fn get_cell_array<T: CasaScalarData + Copy>(
+        &mut self,
+        col_name: &str,
+        row: u64,
+    ) -> Result<ArrayD<T>, Error> {
+        let desc = self.get_col_desc(col_name)?;
+
+        if desc.is_scalar {
+            return Err(NotArrayColumnError(desc.data_type).into());
+        }
+
+        self.get_cell(col_name, row)
+    }
+
     /// This function discards shape information but won't accept scalars.
     pub fn get_cell_as_vec<T: CasaScalarData>(
         &mut self,
         col_name: &str,
         row: u64,
     ) -> Result<Vec<T>, Error> {
+        self.flush_writes()?;
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let mut data_type = glue::GlueDataType::TpOther;
+        let mut n_dim = 0;
+        let mut dims = [0; 8];
+
+        let rv = unsafe {
+            glue_call!(table_get_cell_info(
+                self.handle,
+                &ccol_name,
+                row,
+                &mut data_type,
+                &mut n_dim,
+                dims.as_mut_ptr(),
+                &mut self.exc_info,
+            ); table = self.path, column = col_name, row = row)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        if data_type != T::DATA_TYPE {
+            return Err(UnexpectedDataTypeError(T::DATA_TYPE, data_type).into());
+        }
+
+        let n_items = dims[..n_dim as usize]
+            .iter()
+            .fold(1usize, |p, n| p * (*n as usize));
+
+        let mut result = Vec::<T>::with_capacity(n_items);
+
+        if data_type != glue::GlueDataType::TpString {
+            let rv = unsafe {
+                glue_call!(table_get_cell(
+                    self.handle,
+                    &ccol_name,
+                    row,
+                    result.as_mut_ptr() as _,
+                    &mut self.exc_info,
+                ); table = self.path, column = col_name, row = row)
+            };
+
+            if rv != 0 {
+                return self.exc_info.as_err();
+            }
+
+            unsafe {
+                result.set_len(n_items as usize);
+            }
+        } else {
+            let mut glue_strings: Vec<_> = (0..n_items).map(|_| CasaString::new()).collect();
+
+            let rv = unsafe {
+                glue_call!(table_get_cell(
+                    self.handle,
+                    &ccol_name,
+                    row,
+                    glue_strings.as_mut_ptr() as _,
+                    &mut self.exc_info,
+                ); table = self.path, column = col_name, row = row)
+            };
+
+            if rv != 0 {
+                return self.exc_info.as_err();
+            }
+
+            for cstr in glue_strings {
+                result.push(T::casatables_string_pass_through(cstr.into()));
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Read an array cell without copying its data into a Rust vector.
+    ///
+    /// The returned guard keeps the array allocated by casacore alive and
+    /// gives access to its storage directly, which avoids an extra copy
+    /// compared to `get_cell_as_vec` and friends. Scalar and string cells
+    /// cannot be read this way.
+    pub fn get_cell_borrowed<T: CasaScalarData>(
+        &mut self,
+        col_name: &str,
+        row: u64,
+    ) -> Result<CasaArrayGuard<T>, Error> {
+        self.flush_writes()?;
         let ccol_name = glue::StringBridge::from_rust(col_name);
         let mut data_type = glue::GlueDataType::TpOther;
         let mut n_dim = 0;
         let mut dims = [0; 8];
 
         let rv = unsafe {
-            glue::table_get_cell_info(
+            glue_call!(table_get_cell_info(
+                self.handle,
+                &ccol_name,
+                row,
+                &mut data_type,
+                &mut n_dim,
+                dims.as_mut_ptr(),
+                &mut self.exc_info,
+            ); table = self.path, column = col_name, row = row)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        if data_type != T::DATA_TYPE {
+            return Err(UnexpectedDataTypeError(T::DATA_TYPE, data_type).into());
+        }
+
+        if n_dim == 0 || data_type == glue::GlueDataType::TpString {
+            return Err(err_msg(format!(
+                "cannot borrow the data of a {} cell of column \"{}\"",
+                if n_dim == 0 { "scalar" } else { "string" },
+                col_name
+            )));
+        }
+
+        let shape: Vec<u64> = dims[..n_dim as usize].iter().map(|d| *d as u64).collect();
+        let len = shape.iter().fold(1usize, |p, n| p * (*n as usize));
+        let mut holder = std::ptr::null_mut();
+        let mut data = std::ptr::null();
+
+        let rv = unsafe {
+            glue_call!(table_get_cell_borrowed(
                 self.handle,
                 &ccol_name,
                 row,
+                &mut holder,
+                &mut data,
+                &mut self.exc_info,
+            ); table = self.path, column = col_name, row = row)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(CasaArrayGuard {
+            holder: holder,
+            data: data as *const T,
+            len: len,
+            shape: shape,
+        })
+    }
+
+    /// Get the number of bytes needed to hold one cell of a column in
+    /// memory.
+    ///
+    /// For variable-shape columns, the shape of the first cell is used as a
+    /// representative. Strings do not have a fixed size, so each one is
+    /// assumed to occupy `STRING_WIDTH_ESTIMATE` bytes.
+    pub fn column_width(&mut self, col_name: &str) -> Result<u64, Error> {
+        let desc = self.get_col_desc(col_name)?;
+
+        let elem_size = match desc.data_type.element_size() {
+            n if n < 0 => STRING_WIDTH_ESTIMATE,
+            n => n as u64,
+        };
+
+        if desc.is_scalar {
+            return Ok(elem_size);
+        }
+
+        if let Some(shape) = desc.shape {
+            return Ok(shape.iter().fold(elem_size, |p, n| p * n));
+        }
+
+        if self.n_rows() == 0 {
+            return Ok(0);
+        }
+
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let mut data_type = glue::GlueDataType::TpOther;
+        let mut n_dim = 0;
+        let mut dims = [0; 8];
+
+        let rv = unsafe {
+            glue_call!(table_get_cell_info(
+                self.handle,
+                &ccol_name,
+                0,
                 &mut data_type,
                 &mut n_dim,
                 dims.as_mut_ptr(),
                 &mut self.exc_info,
-            )
+            ); table = self.path, column = col_name)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(dims[..n_dim as usize]
+            .iter()
+            .fold(elem_size, |p, n| p * (*n as u64)))
+    }
+
+    /// Get the number of bytes needed to hold one row of the specified
+    /// columns in memory. See `column_width`.
+    pub fn row_width(&mut self, col_names: &[&str]) -> Result<u64, Error> {
+        let mut width = 0;
+
+        for col_name in col_names {
+            width += self.column_width(col_name)?;
+        }
+
+        Ok(width)
+    }
+
+    /// Read the cells of a range of rows of a column into a flat vector.
+    ///
+    /// The column must be a scalar column or an array column with a fixed
+    /// shape. Array data are returned in C order, with the row number being
+    /// the slowest-varying axis. Single- and double-precision complex columns
+    /// can each be read as the other type; see `complex_precision_converts`.
+    pub fn get_col_range_as_vec<T: CasaScalarData>(
+        &mut self,
+        col_name: &str,
+        start_row: u64,
+        n_rows: u64,
+    ) -> Result<Vec<T>, Error> {
+        self.flush_writes()?;
+        let desc = self.get_col_desc(col_name)?;
+
+        if desc.data_type != T::DATA_TYPE
+            && !complex_precision_converts(desc.data_type, T::DATA_TYPE)
+        {
+            return Err(UnexpectedDataTypeError(T::DATA_TYPE, desc.data_type).into());
+        }
+
+        let cell_items = match desc.shape {
+            Some(ref shape) if desc.is_fixed_shape => {
+                shape.iter().fold(1usize, |p, n| p * (*n as usize))
+            }
+            _ => {
+                return Err(err_msg(format!(
+                    "cannot read a range of rows of the variable-shape column \"{}\"",
+                    col_name
+                )));
+            }
+        };
+
+        if start_row + n_rows > self.n_rows() {
+            return Err(err_msg(format!(
+                "cannot read rows {}-{} of a table with only {} rows",
+                start_row,
+                start_row + n_rows,
+                self.n_rows()
+            )));
+        }
+
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let n_items = cell_items * n_rows as usize;
+        let mut result = Vec::<T>::with_capacity(n_items);
+
+        if desc.data_type != T::DATA_TYPE {
+            // The conversion is done by the glue, into our buffer.
+            let rv = unsafe {
+                glue_call!(table_get_column_range_data_as(
+                    self.handle,
+                    &ccol_name,
+                    start_row,
+                    n_rows,
+                    T::DATA_TYPE,
+                    result.as_mut_ptr() as _,
+                    &mut self.exc_info,
+                ); table = self.path, column = col_name, rows = start_row..start_row + n_rows)
+            };
+
+            if rv != 0 {
+                return self.exc_info.as_err();
+            }
+
+            unsafe {
+                result.set_len(n_items);
+            }
+        } else if desc.data_type != glue::GlueDataType::TpString {
+            let rv = unsafe {
+                glue_call!(table_get_column_range_data(
+                    self.handle,
+                    &ccol_name,
+                    start_row,
+                    n_rows,
+                    result.as_mut_ptr() as _,
+                    &mut self.exc_info,
+                ); table = self.path, column = col_name, rows = start_row..start_row + n_rows)
+            };
+
+            if rv != 0 {
+                return self.exc_info.as_err();
+            }
+
+            unsafe {
+                result.set_len(n_items);
+            }
+        } else {
+            let mut glue_strings: Vec<_> = (0..n_items).map(|_| CasaString::new()).collect();
+
+            let rv = unsafe {
+                glue_call!(table_get_column_range_data(
+                    self.handle,
+                    &ccol_name,
+                    start_row,
+                    n_rows,
+                    glue_strings.as_mut_ptr() as _,
+                    &mut self.exc_info,
+                ); table = self.path, column = col_name, rows = start_row..start_row + n_rows)
+            };
+
+            if rv != 0 {
+                return self.exc_info.as_err();
+            }
+
+            for cstr in glue_strings {
+                result.push(T::casatables_string_pass_through(cstr.into()));
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Read a column in chunks of rows, using no more than about *budget*
+    /// bytes of memory per chunk.
+    ///
+    /// The same restrictions as for `get_col_range_as_vec` apply. To read
+    /// several columns in lockstep, compute a common chunk size with
+    /// `row_width` and `MemoryBudget::rows_per_chunk` and use
+    /// `column_chunks_of_rows` on each column.
+    pub fn column_chunks<'a, T: CasaScalarData>(
+        &'a mut self,
+        col_name: &str,
+        budget: &MemoryBudget,
+    ) -> Result<ColumnChunks<'a, T>, Error> {
+        self.flush_writes()?;
+        let width = self.column_width(col_name)?;
+        let rows_per_chunk = budget.rows_per_chunk(width, self.n_rows());
+        self.column_chunks_of_rows(col_name, rows_per_chunk)
+    }
+
+    /// Read a column in chunks of rows like `column_chunks`, but fetch each
+    /// chunk in a background thread while the previous one is being
+    /// processed.
+    ///
+    /// The background thread reads the data through its own handle onto
+    /// the table, which casacore backs with the same in-memory table as
+    /// this one. The iterator therefore borrows this handle, so that nothing
+    /// else can be done with it until the iterator is dropped, and the
+    /// thread stops when it is; calls into casacore from the two threads
+    /// are serialized, so the prefetching overlaps with the consumer's own
+    /// processing rather than with other table access. Because two chunks
+    /// are in memory at once, each one gets half of *budget*.
+    pub fn prefetched_column_chunks<'a, T: CasaScalarData + Send + 'static>(
+        &'a mut self,
+        col_name: &str,
+        budget: &MemoryBudget,
+    ) -> Result<PrefetchedColumnChunks<'a, T>, Error> {
+        self.flush_writes()?;
+        // Validate the request here so that problems are reported
+        // immediately rather than from the first chunk.
+        let (cell_shape, rows_per_chunk) = {
+            let chunks = self.column_chunks::<T>(col_name, &budget.divide(2))?;
+            (chunks.cell_shape().to_vec(), chunks.rows_per_chunk())
+        };
+
+        let path = self.path.clone();
+
+        Ok(PrefetchedColumnChunks::spawn(
+            move || Table::open(&path, TableOpenMode::Read),
+            col_name,
+            cell_shape,
+            rows_per_chunk,
+        ))
+    }
+
+    /// Read a column in chunks of *rows_per_chunk* rows. See `column_chunks`.
+    pub fn column_chunks_of_rows<'a, T: CasaScalarData>(
+        &'a mut self,
+        col_name: &str,
+        rows_per_chunk: u64,
+    ) -> Result<ColumnChunks<'a, T>, Error> {
+        self.flush_writes()?;
+        let desc = self.get_col_desc(col_name)?;
+
+        if desc.data_type != T::DATA_TYPE {
+            return Err(UnexpectedDataTypeError(T::DATA_TYPE, desc.data_type).into());
+        }
+
+        let cell_shape = match desc.shape {
+            Some(ref shape) if desc.is_fixed_shape => shape.clone(),
+            _ => {
+                return Err(err_msg(format!(
+                    "cannot read the variable-shape column \"{}\" in chunks",
+                    col_name
+                )));
+            }
+        };
+
+        let n_rows = self.n_rows();
+
+        Ok(ColumnChunks {
+            table: self,
+            col_name: col_name.to_owned(),
+            cell_shape: cell_shape,
+            next_row: 0,
+            n_rows: n_rows,
+            rows_per_chunk: std::cmp::max(rows_per_chunk, 1),
+            _data_type: PhantomData,
+        })
+    }
+
+    /// Write *value* into the cell of column *col_name* in row *row*, which
+    /// must already exist.
+    ///
+    /// *T* may be a scalar type, for scalar columns, or a vector or
+    /// `ndarray` array of one, for array columns. A vector is written as a
+    /// one-dimensional array.
+    pub fn put_cell<T: CasaDataType>(
+        &mut self,
+        col_name: &str,
+        row: u64,
+        value: &T,
+    ) -> Result<(), CasacoreError> {
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let mut shape = Vec::new();
+
+        value.casatables_put_shape(&mut shape);
+
+        if let Some(ref mut plan) = self.dry_run {
+            let elsize = T::DATA_TYPE.element_size();
+            let n_elements: u64 = shape.iter().product();
+
+            plan.record(
+                self.path.display().to_string(),
+                format!("write cell in column \"{}\" of row {}", col_name, row),
+                1,
+                if elsize < 0 {
+                    None
+                } else {
+                    Some(n_elements * elsize as u64)
+                },
+            );
+            return Ok(());
+        }
+
+        if self.write_behind.get_mut().is_some()
+            && self.buffer_cell(col_name, row, &shape, value)?
+        {
+            return Ok(());
+        }
+
+        if T::DATA_TYPE == glue::GlueDataType::TpString {
+            let as_string = T::casatables_string_pass_through_out(value);
+            let glue_string = glue::StringBridge::from_rust(&as_string);
+
+            let rv = unsafe {
+                glue_call!(table_put_cell(
+                    self.handle,
+                    &ccol_name,
+                    row,
+                    T::DATA_TYPE,
+                    shape.len() as u64,
+                    shape.as_ptr(),
+                    &glue_string as *const glue::StringBridge as _,
+                    &mut self.exc_info,
+                ); table = self.path, column = col_name, row = row)
+            };
+
+            if rv != 0 {
+                return self.exc_info.as_err();
+            }
+        } else if T::DATA_TYPE == glue::GlueDataType::TpArrayString {
+            let glue_strings = T::casatables_stringvec_pass_through_out(value);
+
+            let rv = unsafe {
+                glue_call!(table_put_cell(
+                    self.handle,
+                    &ccol_name,
+                    row,
+                    T::DATA_TYPE,
+                    shape.len() as u64,
+                    shape.as_ptr(),
+                    glue_strings.as_ptr() as _,
+                    &mut self.exc_info,
+                ); table = self.path, column = col_name, row = row)
+            };
+
+            if rv != 0 {
+                return self.exc_info.as_err();
+            }
+        } else {
+            let rv = unsafe {
+                glue_call!(table_put_cell(
+                    self.handle,
+                    &ccol_name,
+                    row,
+                    T::DATA_TYPE,
+                    shape.len() as u64,
+                    shape.as_ptr(),
+                    value.casatables_as_buf() as _,
+                    &mut self.exc_info,
+                ); table = self.path, column = col_name, row = row)
+            };
+
+            if rv != 0 {
+                return self.exc_info.as_err();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fill a scalar column with the values produced by an iterator.
+    ///
+    /// The values are written to the column starting at row zero, in
+    /// batches of `COLUMN_PUT_BATCH_ROWS` rows, so that the full set of
+    /// values never needs to be held in memory. Rows are added to the table
+    /// if the iterator produces more values than the table has rows.
+    /// Returns the number of rows that were written.
+    pub fn put_col_from_iter<T, I>(&mut self, col_name: &str, values: I) -> Result<u64, Error>
+    where
+        T: CasaScalarData,
+        I: IntoIterator<Item = T>,
+    {
+        self.flush_writes()?;
+        let desc = self.get_col_desc(col_name)?;
+
+        if !desc.is_scalar {
+            return Err(NotScalarColumnError(desc.data_type).into());
+        }
+
+        if desc.data_type != T::DATA_TYPE {
+            return Err(UnexpectedDataTypeError(T::DATA_TYPE, desc.data_type).into());
+        }
+
+        let mut values = values.into_iter();
+        let mut batch = Vec::with_capacity(COLUMN_PUT_BATCH_ROWS);
+        let mut start_row = 0;
+        // In dry-run mode, add_rows() doesn't change the row count, so we
+        // need to track it ourselves.
+        let mut n_rows = self.n_rows();
+
+        loop {
+            batch.clear();
+            batch.extend(values.by_ref().take(COLUMN_PUT_BATCH_ROWS));
+
+            if batch.is_empty() {
+                break;
+            }
+
+            let end_row = start_row + batch.len() as u64;
+
+            if end_row > n_rows {
+                self.add_rows((end_row - n_rows) as usize)?;
+                n_rows = end_row;
+            }
+
+            self.put_col_range(col_name, start_row, &batch)?;
+            start_row = end_row;
+        }
+
+        Ok(start_row)
+    }
+
+    /// Write every cell of the scalar column *col_name* from a slice, with
+    /// one bulk write on the C++ side. This is the counterpart of
+    /// `get_col_as_vec`.
+    ///
+    /// *values* must have one element per row of the table, and *T* must
+    /// match the stored data type exactly. Use `put_col_from_iter` to fill
+    /// a column from values that are not all in memory at once.
+    pub fn put_col<T: CasaScalarData>(
+        &mut self,
+        col_name: &str,
+        values: &[T],
+    ) -> Result<(), Error> {
+        self.flush_writes()?;
+        let desc = self.get_col_desc(col_name)?;
+
+        if !desc.is_scalar {
+            return Err(NotScalarColumnError(desc.data_type).into());
+        }
+
+        if desc.data_type != T::DATA_TYPE {
+            return Err(UnexpectedDataTypeError(T::DATA_TYPE, desc.data_type).into());
+        }
+
+        let n_rows = self.n_rows();
+
+        if values.len() as u64 != n_rows {
+            return Err(err_msg(format!(
+                "cannot write {} values into the column \"{}\" of a table with {} rows",
+                values.len(),
+                col_name,
+                n_rows
+            )));
+        }
+
+        self.put_col_range(col_name, 0, values)?;
+        Ok(())
+    }
+
+    /// Write a slice of values into consecutive rows of a scalar column.
+    fn put_col_range<T: CasaScalarData>(
+        &mut self,
+        col_name: &str,
+        start_row: u64,
+        values: &[T],
+    ) -> Result<(), CasacoreError> {
+        if let Some(ref mut plan) = self.dry_run {
+            let elsize = T::DATA_TYPE.element_size();
+
+            plan.record(
+                self.path.display().to_string(),
+                format!(
+                    "write column \"{}\" in rows {}-{}",
+                    col_name,
+                    start_row,
+                    start_row + values.len() as u64
+                ),
+                values.len() as u64,
+                if elsize < 0 {
+                    None
+                } else {
+                    Some(values.len() as u64 * elsize as u64)
+                },
+            );
+            return Ok(());
+        }
+
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+
+        let rv = if T::DATA_TYPE == glue::GlueDataType::TpString {
+            let strings: Vec<String> = values
+                .iter()
+                .map(T::casatables_string_pass_through_out)
+                .collect();
+            let glue_strings: Vec<glue::StringBridge> = strings
+                .iter()
+                .map(|s| glue::StringBridge::from_rust(s))
+                .collect();
+
+            unsafe {
+                glue_call!(table_put_column_range_data(
+                    self.handle,
+                    &ccol_name,
+                    start_row,
+                    values.len() as u64,
+                    glue_strings.as_ptr() as _,
+                    &mut self.exc_info,
+                ); table = self.path, column = col_name,
+                   rows = start_row..start_row + values.len() as u64)
+            }
+        } else {
+            unsafe {
+                glue_call!(table_put_column_range_data(
+                    self.handle,
+                    &ccol_name,
+                    start_row,
+                    values.len() as u64,
+                    values.as_ptr() as _,
+                    &mut self.exc_info,
+                ); table = self.path, column = col_name,
+                   rows = start_row..start_row + values.len() as u64)
+            }
+        };
+
+        if rv != 0 {
+            self.exc_info.as_err()
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn add_rows(&mut self, n_rows: usize) -> Result<(), CasacoreError> {
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                self.path.display().to_string(),
+                "add rows",
+                n_rows as u64,
+                None,
+            );
+            return Ok(());
+        }
+
+        if unsafe {
+            glue_call!(table_add_rows(self.handle, n_rows as u64, &mut self.exc_info);
+                table = self.path)
+                != 0
+        } {
+            self.exc_info.as_err()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Write all pending changes to the table and its subtables to disk,
+    /// including any cells held by the write-behind buffer.
+    ///
+    /// casacore buffers changes in memory and writes them out when a table
+    /// is closed, so a process that dies while a table is open can leave it
+    /// inconsistent on disk. After this returns, the on-disk table matches
+    /// the in-memory one, and if *fsync* is true, the operating system has
+    /// been told to commit it to storage.
+    pub fn flush(&mut self, fsync: bool) -> Result<(), CasacoreError> {
+        if self.dry_run.is_some() {
+            return Ok(());
+        }
+
+        self.flush_writes()?;
+
+        if unsafe {
+            glue_call!(table_flush(self.handle, fsync as i32, &mut self.exc_info);
+                table = self.path)
+                != 0
+        } {
+            self.exc_info.as_err()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn get_row_handle(&mut self, is_read_only: bool) -> Result<TableRow, CasacoreError> {
+        self.flush_writes()?;
+        let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+        let ro_flag = if is_read_only { 1 } else { 0 };
+
+        let handle = unsafe {
+            glue_call!(table_row_alloc(self.handle, ro_flag, &mut exc_info); table = self.path)
+        };
+        if handle.is_null() {
+            return exc_info.as_err();
+        }
+
+        Ok(TableRow {
+            handle: handle,
+            exc_info: exc_info,
+        })
+    }
+
+    /// Get a handle for reading and writing cells of a scalar column.
+    ///
+    /// The column is looked up once, when the handle is created, so
+    /// accessing many cells through the handle is faster than calling
+    /// `get_cell` and `put_cell` repeatedly. The handle borrows the table,
+    /// so several handles can be used at once, but the table cannot be
+    /// modified in other ways while any of them exist.
+    ///
+    /// The column is checked when the handle is created: a
+    /// `NoSuchColumnError`, `NotScalarColumnError`, or
+    /// `UnexpectedDataTypeError` is returned if it does not exist, contains
+    /// arrays, or has a data type that cannot be read as `T`. Integer and
+    /// floating-point columns can be read as a wider type of the same kind,
+    /// since older tables sometimes store columns such as antenna numbers
+    /// with narrower types than are now conventional; handles that widen
+    /// values in this way cannot write them.
+    pub fn scalar_column<'a, T: CasaScalarData>(
+        &'a self,
+        col_name: &str,
+    ) -> Result<ScalarColumn<'a, T>, Error> {
+        self.flush_pending_columns()?;
+        let column = ColumnHandle::new(self, col_name)?;
+
+        if !column.is_scalar {
+            return Err(NotScalarColumnError(column.data_type).into());
+        }
+
+        if column.data_type != T::DATA_TYPE
+            && !scalar_type_promotes_to(column.data_type, T::DATA_TYPE)
+        {
+            return Err(UnexpectedDataTypeError(T::DATA_TYPE, column.data_type).into());
+        }
+
+        Ok(ScalarColumn {
+            column: column,
+            _data_type: PhantomData,
+        })
+    }
+
+    /// Get a handle for reading and writing cells of an array column.
+    ///
+    /// See `scalar_column` for more information. A `NotArrayColumnError` is
+    /// returned if the column contains scalars. The element type `T` must
+    /// match the data type of the column, except that single- and
+    /// double-precision complex columns can each be accessed as the other
+    /// type. In that case values are converted as they are read and
+    /// written, so that code standardized on `Complex<f64>` can read a
+    /// `DATA` column without allocating a second buffer of its own.
+    pub fn array_column<'a, T: CasaScalarData>(
+        &'a self,
+        col_name: &str,
+    ) -> Result<ArrayColumn<'a, T>, Error> {
+        self.flush_pending_columns()?;
+        let column = ColumnHandle::new(self, col_name)?;
+
+        if column.is_scalar {
+            return Err(NotArrayColumnError(column.data_type).into());
+        }
+
+        if column.data_type != T::DATA_TYPE
+            && !complex_precision_converts(column.data_type, T::DATA_TYPE)
+        {
+            return Err(UnexpectedDataTypeError(T::DATA_TYPE, column.data_type).into());
+        }
+
+        Ok(ArrayColumn {
+            column: column,
+            _data_type: PhantomData,
+        })
+    }
+
+    /// Return whether the table has a column with the specified name.
+    pub fn has_column(&mut self, col_name: &str) -> Result<bool, CasacoreError> {
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let mut has_column = 0;
+
+        if unsafe {
+            glue_call!(table_has_column(
+                self.handle,
+                &ccol_name,
+                &mut has_column,
+                &mut self.exc_info,
+            ); table = self.path, column = col_name)
+        } != 0
+        {
+            return self.exc_info.as_err();
+        }
+
+        Ok(has_column != 0)
+    }
+
+    pub fn get_row_reader(&mut self) -> Result<TableRow, CasacoreError> {
+        self.get_row_handle(true)
+    }
+
+    pub fn get_row_writer(&mut self) -> Result<TableRow, CasacoreError> {
+        if self.dry_run.is_some() {
+            return Err(CasacoreError::general(
+                "row writers are not available in dry-run mode",
+            ));
+        }
+
+        self.get_row_handle(false)
+    }
+
+    pub fn read_row(&mut self, row: &mut TableRow, row_number: u64) -> Result<(), Error> {
+        self.flush_writes()?;
+        if unsafe {
+            glue_call!(table_row_read(row.handle, row_number, &mut row.exc_info);
+                table = self.path, row = row_number)
+        } != 0
+        {
+            return row.exc_info.as_err();
+        }
+
+        Ok(())
+    }
+
+    pub fn for_each_row<F>(&mut self, mut func: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut TableRow) -> Result<(), Error>,
+    {
+        self.flush_writes()?;
+        let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+
+        let handle = unsafe {
+            glue_call!(table_row_alloc(self.handle, 1, &mut exc_info); table = self.path)
+        };
+        if handle.is_null() {
+            return exc_info.as_err();
+        }
+
+        let mut row = TableRow {
+            handle: handle,
+            exc_info: exc_info,
+        };
+
+        for row_number in 0..self.n_rows() {
+            if unsafe {
+                glue_call!(table_row_read(row.handle, row_number as u64, &mut row.exc_info);
+                    table = self.path, row = row_number)
+            } != 0
+            {
+                return row.exc_info.as_err();
+            }
+
+            func(&mut row)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn copy_rows_to(&mut self, dest: &mut Table) -> Result<(), CasacoreError> {
+        self.flush_writes()?;
+        dest.flush_writes()?;
+        let n_rows = self.n_rows();
+
+        if let Some(ref mut plan) = dest.dry_run {
+            plan.record(
+                dest.path.display().to_string(),
+                format!("copy rows from {}", self.path.display()),
+                n_rows,
+                None,
+            );
+            return Ok(());
+        }
+
+        if unsafe {
+            glue_call!(table_copy_rows(self.handle, dest.handle, &mut self.exc_info);
+                table = self.path)
+                != 0
+        } {
+            self.exc_info.as_err()
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn deep_copy_no_rows<P: AsRef<Path>>(&mut self, dest_path: P) -> Result<(), Error> {
+        let options = DeepCopyOptions {
+            no_rows: true,
+            ..DeepCopyOptions::default()
+        };
+
+        self.deep_copy(dest_path, &options)
+    }
+
+    /// Copy this table to a new, independent table at *dest_path*.
+    ///
+    /// The table keywords and the subtables that they refer to are always
+    /// copied, as are the keywords of each copied column. See
+    /// `DeepCopyOptions` for how to copy only some of the columns or rows.
+    /// It is an error if something already exists at *dest_path*.
+    pub fn deep_copy<P: AsRef<Path>>(
+        &mut self,
+        dest_path: P,
+        options: &DeepCopyOptions,
+    ) -> Result<(), Error> {
+        self.flush_writes()?;
+        let dest_path = dest_path.as_ref();
+        let n_rows = if options.no_rows { 0 } else { self.n_rows() };
+
+        if let Some(ref mut plan) = self.dry_run {
+            let what = match (&options.column_names, options.no_rows) {
+                (&None, false) => "a copy".to_owned(),
+                (&None, true) => "an empty copy".to_owned(),
+                (&Some(ref names), false) => format!("a copy of columns {}", names.join(", ")),
+                (&Some(ref names), true) => {
+                    format!("an empty copy of columns {}", names.join(", "))
+                }
+            };
+
+            plan.record(
+                dest_path.display().to_string(),
+                format!("create as {} of {}", what, self.path.display()),
+                n_rows,
+                None,
+            );
+            return Ok(());
+        }
+
+        let cdest_path = glue::StringBridge::from_bytes(path_as_bytes(dest_path)?);
+        let ccol_names: Option<Vec<_>> = options.column_names.as_ref().map(|names| {
+            names
+                .iter()
+                .map(|n| glue::StringBridge::from_rust(n))
+                .collect()
+        });
+
+        let (n_cols, col_names_ptr) = match ccol_names {
+            Some(ref v) => (v.len() as u64, v.as_ptr()),
+            None => (0, std::ptr::null()),
+        };
+
+        let (storage, block_size) = storage_for_glue(options.storage);
+
+        if unsafe {
+            glue_call!(table_deep_copy(
+                self.handle,
+                &cdest_path,
+                n_cols,
+                col_names_ptr,
+                if options.no_rows { 1 } else { 0 },
+                storage,
+                block_size,
+                &mut self.exc_info,
+            ); table = self.path)
+                != 0
+        } {
+            self.exc_info.as_err()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get how the files of this table are organized on disk.
+    ///
+    /// For a reference table, this is the layout of the table that it
+    /// refers to.
+    pub fn storage(&mut self) -> Result<TableStorage, CasacoreError> {
+        let mut storage = glue::GlueStorageOption::GSO_DEFAULT;
+        let mut block_size = 0;
+
+        let rv = unsafe {
+            glue_call!(table_get_storage_option(
+                self.handle,
+                &mut storage,
+                &mut block_size,
+                &mut self.exc_info,
+            ); table = self.path)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(match storage {
+            glue::GlueStorageOption::GSO_MULTI_FILE => TableStorage::MultiFile { block_size },
+            glue::GlueStorageOption::GSO_MULTI_HDF5 => TableStorage::MultiHdf5 { block_size },
+            _ => TableStorage::SeparateFiles,
+        })
+    }
+
+    /// Select the rows of this table for which *mask* is true.
+    ///
+    /// The result is a reference table: it shares its data with this one,
+    /// so that selecting rows is cheap and changes made through either table
+    /// are visible in both. The length of *mask* must equal the number of
+    /// rows in this table. If this table is in dry-run mode, so is the
+    /// result.
+    pub fn select_rows(&mut self, mask: &[bool]) -> Result<Table, Error> {
+        self.flush_writes()?;
+        let cmask: Vec<u8> = mask.iter().map(|&m| m as u8).collect();
+
+        let handle = unsafe {
+            glue_call!(table_select_rows(
+                self.handle,
+                cmask.as_ptr(),
+                cmask.len() as u64,
+                &mut self.exc_info,
+            ); table = self.path)
+        };
+
+        if handle.is_null() {
+            return self.exc_info.as_err();
+        }
+
+        Ok(Table {
+            handle: handle,
+            exc_info: unsafe { std::mem::zeroed::<glue::ExcInfo>() },
+            path: self.path.clone(),
+            dry_run: self.dry_run.as_ref().map(|_| ChangePlan::new()),
+            write_behind: RefCell::new(None),
+        })
+    }
+
+    /// Write this table as a standalone plain table where *output* says.
+    ///
+    /// If this is a reference table, the rows and columns that it refers to
+    /// are copied out of the underlying table, so that the result no longer
+    /// depends on it. Otherwise this is equivalent to a full deep copy. The
+    /// table cannot be materialized in place, since this handle still
+    /// refers to the data that would be replaced. Returns the path of the
+    /// new table.
+    pub fn materialize(&mut self, output: &OutputPolicy) -> Result<PathBuf, Error> {
+        if *output == OutputPolicy::InPlace {
+            return Err(err_msg(format!(
+                "cannot materialize \"{}\" in place; give an output path",
+                self.path.display()
+            )));
+        }
+
+        let prepared = output.prepare_derived(&self.path)?;
+        self.deep_copy(prepared.path(), &DeepCopyOptions::default())?;
+        prepared.commit()
+    }
+
+    /// Write the values of scalar columns in a range of rows as delimited
+    /// text, one line per row.
+    ///
+    /// Rows beyond the end of the table are ignored. See
+    /// `DelimitedExportOptions` for how values are formatted. Returns the
+    /// number of rows written.
+    pub fn export_delimited(
+        &mut self,
+        dest: &mut io::Write,
+        col_names: &[&str],
+        rows: Range<u64>,
+        options: &DelimitedExportOptions,
+    ) -> Result<u64, Error> {
+        self.flush_writes()?;
+        let end = std::cmp::min(rows.end, self.n_rows());
+        let start = std::cmp::min(rows.start, end);
+        let sep = options.delimiter.as_char();
+        let mut kinds = Vec::with_capacity(col_names.len());
+
+        for name in col_names {
+            let desc = self.get_col_desc(name)?;
+
+            if !desc.is_scalar {
+                return Err(NotScalarColumnError(desc.data_type).into());
+            }
+
+            let is_time = options.time_columns.iter().any(|c| c == name);
+
+            if is_time && desc.data_type != glue::GlueDataType::TpDouble {
+                return Err(
+                    UnexpectedDataTypeError(glue::GlueDataType::TpDouble, desc.data_type).into(),
+                );
+            }
+
+            kinds.push((desc.data_type, is_time));
+        }
+
+        if options.header {
+            for (i, name) in col_names.iter().enumerate() {
+                if i > 0 {
+                    write!(dest, "{}", sep)?;
+                }
+
+                write_delimited_text(dest, name, sep)?;
+            }
+
+            writeln!(dest)?;
+        }
+
+        let mut row = start;
+
+        while row < end {
+            let n = std::cmp::min(EXPORT_BATCH_ROWS, end - row);
+            let mut columns = Vec::with_capacity(col_names.len());
+
+            for (name, &(data_type, _)) in col_names.iter().zip(&kinds) {
+                columns.push(ExportValues::read(self, name, data_type, row, n)?);
+            }
+
+            for i in 0..n as usize {
+                for (j, values) in columns.iter().enumerate() {
+                    if j > 0 {
+                        write!(dest, "{}", sep)?;
+                    }
+
+                    values.write(dest, i, kinds[j].1, sep)?;
+                }
+
+                writeln!(dest)?;
+            }
+
+            row += n;
+        }
+
+        Ok(end - start)
+    }
+
+    /// Fill an empty table with data parsed from delimited text.
+    ///
+    /// If *options.header* is true, the first record of the text names the
+    /// columns that the fields belong to, and any columns that it does not
+    /// mention are left with default values. Otherwise, the fields are
+    /// matched to the table's columns in order. All of the columns must be
+    /// scalars. Values are parsed in the formats written by
+    /// `export_delimited`; booleans may also be given as `t`/`f`, `yes`/`no`,
+    /// or `1`/`0`. Returns the number of rows imported.
+    pub fn import_delimited(
+        &mut self,
+        text: &str,
+        options: &DelimitedImportOptions,
+    ) -> Result<u64, Error> {
+        if self.n_rows() != 0 {
+            return Err(err_msg(format!(
+                "cannot import data into \"{}\": it already contains rows",
+                self.path.display()
+            )));
+        }
+
+        let mut records = parse_delimited(text, options.delimiter.as_char())?.into_iter();
+
+        let col_names = if options.header {
+            match records.next() {
+                Some((_, names)) => names,
+                None => return Ok(0),
+            }
+        } else {
+            self.column_names()?
+        };
+
+        let records: Vec<_> = records.collect();
+
+        for &(line, ref fields) in &records {
+            if fields.len() != col_names.len() {
+                return Err(err_msg(format!(
+                    "expected {} fields on line {} but found {}",
+                    col_names.len(),
+                    line,
+                    fields.len()
+                )));
+            }
+        }
+
+        for (i, name) in col_names.iter().enumerate() {
+            use glue::GlueDataType::*;
+
+            let desc = self.get_col_desc(name)?;
+
+            if !desc.is_scalar {
+                return Err(NotScalarColumnError(desc.data_type).into());
+            }
+
+            let is_time = options.time_columns.iter().any(|c| c == name);
+
+            if is_time && desc.data_type != TpDouble {
+                return Err(UnexpectedDataTypeError(TpDouble, desc.data_type).into());
+            }
+
+            match desc.data_type {
+                TpBool => import_column(self, name, &records, i, parse_bool)?,
+                TpChar => import_column::<i8, _>(self, name, &records, i, parse_number)?,
+                TpUChar => import_column::<u8, _>(self, name, &records, i, parse_number)?,
+                TpShort => import_column::<i16, _>(self, name, &records, i, parse_number)?,
+                TpUShort => import_column::<u16, _>(self, name, &records, i, parse_number)?,
+                TpInt => import_column::<i32, _>(self, name, &records, i, parse_number)?,
+                TpUInt => import_column::<u32, _>(self, name, &records, i, parse_number)?,
+                TpInt64 => import_column::<i64, _>(self, name, &records, i, parse_number)?,
+                TpFloat => import_column::<f32, _>(self, name, &records, i, parse_number)?,
+                TpDouble if is_time => import_column(self, name, &records, i, |s| {
+                    time::iso8601_to_mjd_seconds(s).ok()
+                })?,
+                TpDouble => import_column::<f64, _>(self, name, &records, i, parse_number)?,
+                TpComplex => {
+                    import_column::<Complex<f32>, _>(self, name, &records, i, parse_complex)?
+                }
+                TpDComplex => {
+                    import_column::<Complex<f64>, _>(self, name, &records, i, parse_complex)?
+                }
+                TpString => import_column(self, name, &records, i, |s| Some(s.to_owned()))?,
+                other => {
+                    return Err(err_msg(format!(
+                        "cannot import column \"{}\" of type {}",
+                        name, other
+                    )));
+                }
+            }
+        }
+
+        Ok(records.len() as u64)
+    }
+}
+
+impl DryRun for Table {
+    fn set_dry_run(&mut self, enabled: bool) {
+        if !enabled {
+            self.dry_run = None;
+        } else if self.dry_run.is_none() {
+            self.dry_run = Some(ChangePlan::new());
+        }
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.dry_run.is_some()
+    }
+
+    fn take_change_plan(&mut self) -> ChangePlan {
+        match self.dry_run {
+            Some(ref mut plan) => std::mem::replace(plan, ChangePlan::new()),
+            None => ChangePlan::new(),
+        }
+    }
+}
+
+impl Drop for Table {
+    fn drop(&mut self) {
+        // Errors can't be reported from here; call `flush` or `flush_writes`
+        // first to see them.
+        let _ = self.flush_writes();
+
+        // FIXME: not sure if this function can actually produce useful
+        // exceptions anyway, but we can't do anything if it does!
+        unsafe {
+            glue_call!(table_close_and_free(self.handle, &mut self.exc_info); table = self.path)
+        }
+    }
+}
+
+pub struct ColumnDescription {
+    name: String,
+    data_type: glue::GlueDataType,
+    is_scalar: bool,
+    is_fixed_shape: bool,
+    shape: Option<Vec<u64>>,
+}
+
+impl ColumnDescription {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn data_type(&self) -> glue::GlueDataType {
+        self.data_type
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        self.is_scalar
+    }
+
+    pub fn is_fixed_shape(&self) -> bool {
+        self.is_fixed_shape
+    }
+
+    pub fn shape(&self) -> Option<&[u64]> {
+        self.shape.as_ref().map(|v| &v[..])
+    }
+}
+
+/// Information about one of the data managers that store a table's columns,
+/// as returned by `Table::data_manager_info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataManagerInfo {
+    dm_type: String,
+    name: String,
+    seq_nr: u32,
+    columns: Vec<String>,
+    bucket_size: Option<u64>,
+    tile_shape: Option<Vec<u64>>,
+}
+
+impl DataManagerInfo {
+    /// The type of the data manager, such as `StandardStMan` or
+    /// `TiledShapeStMan`.
+    pub fn dm_type(&self) -> &str {
+        &self.dm_type
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The sequence number of the data manager, which determines the names
+    /// of its files inside the table directory (`table.f<N>`).
+    pub fn seq_nr(&self) -> u32 {
+        self.seq_nr
+    }
+
+    /// The names of the columns stored by this data manager.
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// The bucket size in bytes, for bucket-based storage managers such as
+    /// `StandardStMan` and `IncrementalStMan`.
+    pub fn bucket_size(&self) -> Option<u64> {
+        self.bucket_size
+    }
+
+    /// The default tile shape, for tiled storage managers. As with other
+    /// shapes in this crate, this is in C order, so that the row axis comes
+    /// first.
+    pub fn tile_shape(&self) -> Option<&[u64]> {
+        self.tile_shape.as_ref().map(|v| &v[..])
+    }
+}
+
+/// A keyword of a table or column, as returned by `Table::keyword_values`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeywordValue {
+    name: String,
+    data_type: glue::GlueDataType,
+    value: String,
+}
+
+impl KeywordValue {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn data_type(&self) -> glue::GlueDataType {
+        self.data_type
+    }
+
+    /// The value of the keyword, printed as text.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+// Borrowed array data
+
+/// Array data owned by casacore, as returned by `Table::get_cell_borrowed`.
+///
+/// The guard dereferences to a slice of the array's elements, in C order.
+/// The underlying C++ array is freed when the guard is dropped.
+pub struct CasaArrayGuard<T> {
+    holder: *mut std::os::raw::c_void,
+    data: *const T,
+    len: usize,
+    shape: Vec<u64>,
+}
+
+impl<T> CasaArrayGuard<T> {
+    /// Get the shape of the array, in C order.
+    pub fn shape(&self) -> &[u64] {
+        &self.shape[..]
+    }
+
+    /// Get an `ndarray` view of the array data.
+    pub fn view<'a>(&'a self) -> Result<ArrayViewD<'a, T>, Error> {
+        let dims: Vec<usize> = self.shape.iter().map(|n| *n as usize).collect();
+        Ok(ArrayViewD::from_shape(IxDyn(&dims), &self[..])?)
+    }
+}
+
+impl<T> std::ops::Deref for CasaArrayGuard<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        unsafe { std::slice::from_raw_parts(self.data, self.len) }
+    }
+}
+
+impl<T> Drop for CasaArrayGuard<T> {
+    fn drop(&mut self) {
+        unsafe { glue_call!(array_holder_free(self.holder)) }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn borrowed_cells() {
+    use self::GlueDataType::*;
+
+    let dir = std::env::temp_dir().join(format!("rubbl-borrowed-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut t = Table::create_with_scalar_columns(dir.join("t.table"), &[("S", TpInt)], 1).unwrap();
+    t.add_array_column("F", TpFloat, Some(&[2, 3])).unwrap();
+    t.add_array_column("L", TpInt64, None).unwrap();
+    t.add_array_column("A", TpString, Some(&[2])).unwrap();
+
+    let floats = ndarray::Array2::from_shape_fn((2, 3), |(i, j)| (3 * i + j) as f32 * 0.5);
+    let longs = ndarray::Array1::from(vec![1i64 << 40, -7, 3]);
+    t.put_cell("F", 0, &floats).unwrap();
+    t.put_cell("L", 0, &longs).unwrap();
+    t.put_cell("S", 0, &5i32).unwrap();
+    t.put_cell("A", 0, &vec!["a".to_owned(), "b".to_owned()])
+        .unwrap();
+
+    let f = t.get_cell_borrowed::<f32>("F", 0).unwrap();
+    assert_eq!(f.shape(), &[2, 3]);
+    assert_eq!(f.view().unwrap(), floats.view().into_dyn());
+    assert_eq!(&f[..], &t.get_cell_as_vec::<f32>("F", 0).unwrap()[..]);
+    drop(f);
+
+    let l = t.get_cell_borrowed::<i64>("L", 0).unwrap();
+    assert_eq!(l.shape(), &[3]);
+    assert_eq!(l.view().unwrap(), longs.view().into_dyn());
+    assert_eq!(&l[..], &t.get_cell_as_vec::<i64>("L", 0).unwrap()[..]);
+    drop(l);
+
+    assert!(t.get_cell_borrowed::<f64>("F", 0).is_err());
+    assert!(t.get_cell_borrowed::<i32>("S", 0).is_err());
+    assert!(t.get_cell_borrowed::<String>("A", 0).is_err());
+
+    drop(t);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Chunked column reading and writing
+
+/// The number of rows written at a time by `Table::put_col_from_iter`.
+pub const COLUMN_PUT_BATCH_ROWS: usize = 4096;
+
+/// The assumed in-memory size of a string cell, used when estimating how
+/// many rows of a string column fit into a memory budget.
+pub const STRING_WIDTH_ESTIMATE: u64 = 64;
+
+/// A chunk of rows read from a column by `ColumnChunks`.
+#[derive(Clone, Debug)]
+pub struct ColumnChunk<T> {
+    /// The number of the first row in this chunk.
+    pub start_row: u64,
+
+    /// The number of rows in this chunk.
+    pub n_rows: u64,
+
+    /// The cell data, in C order with the row number being the
+    /// slowest-varying axis.
+    pub data: Vec<T>,
+}
+
+/// An iterator that reads a column of a table in chunks of rows.
+///
+/// Created by `Table::column_chunks`.
+pub struct ColumnChunks<'a, T> {
+    table: &'a mut Table,
+    col_name: String,
+    cell_shape: Vec<u64>,
+    next_row: u64,
+    n_rows: u64,
+    rows_per_chunk: u64,
+    _data_type: PhantomData<T>,
+}
+
+impl<'a, T: CasaScalarData> ColumnChunks<'a, T> {
+    /// Get the shape of each cell of the column; empty for scalar columns.
+    pub fn cell_shape(&self) -> &[u64] {
+        &self.cell_shape[..]
+    }
+
+    /// Get the maximum number of rows in each chunk.
+    pub fn rows_per_chunk(&self) -> u64 {
+        self.rows_per_chunk
+    }
+}
+
+impl<'a, T: CasaScalarData> Iterator for ColumnChunks<'a, T> {
+    type Item = Result<ColumnChunk<T>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_row >= self.n_rows {
+            return None;
+        }
+
+        let start_row = self.next_row;
+        let n_rows = std::cmp::min(self.rows_per_chunk, self.n_rows - start_row);
+        self.next_row += n_rows;
+
+        Some(
+            self.table
+                .get_col_range_as_vec(&self.col_name, start_row, n_rows)
+                .map(|data| ColumnChunk {
+                    start_row: start_row,
+                    n_rows: n_rows,
+                    data: data,
+                }),
+        )
+    }
+}
+
+/// An iterator that reads a column of a table in chunks of rows, fetching
+/// the next chunk in a background thread.
+///
+/// Created by `Table::prefetched_column_chunks`.
+pub struct PrefetchedColumnChunks<'a, T> {
+    receiver: Option<mpsc::Receiver<Result<ColumnChunk<T>, Error>>>,
+    thread: Option<thread::JoinHandle<()>>,
+    cell_shape: Vec<u64>,
+    rows_per_chunk: u64,
+    table: PhantomData<&'a mut Table>,
+}
+
+impl<'a, T: CasaScalarData + Send + 'static> PrefetchedColumnChunks<'a, T> {
+    /// Start a thread that reads the column *col_name* of the table opened
+    /// by *open* in chunks of *rows_per_chunk* rows.
+    fn spawn<F>(open: F, col_name: &str, cell_shape: Vec<u64>, rows_per_chunk: u64) -> Self
+    where
+        F: FnOnce() -> Result<Table, Error> + Send + 'static,
+    {
+        // A rendezvous channel: the reader thread can get one chunk ahead of
+        // the consumer, but no further.
+        let (sender, receiver) = mpsc::sync_channel(0);
+        let col_name = col_name.to_owned();
+
+        let thread = thread::spawn(move || {
+            let mut table = match open() {
+                Ok(t) => t,
+                Err(e) => {
+                    let _r = sender.send(Err(e));
+                    return;
+                }
+            };
+
+            let chunks = match table.column_chunks_of_rows::<T>(&col_name, rows_per_chunk) {
+                Ok(c) => c,
+                Err(e) => {
+                    let _r = sender.send(Err(e));
+                    return;
+                }
+            };
+
+            for chunk in chunks {
+                let failed = chunk.is_err();
+
+                // If the send fails, the consumer has gone away.
+                if sender.send(chunk).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        PrefetchedColumnChunks {
+            receiver: Some(receiver),
+            thread: Some(thread),
+            cell_shape: cell_shape,
+            rows_per_chunk: rows_per_chunk,
+            table: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> PrefetchedColumnChunks<'a, T> {
+    /// Get the shape of each cell of the column; empty for scalar columns.
+    pub fn cell_shape(&self) -> &[u64] {
+        &self.cell_shape[..]
+    }
+
+    /// Get the maximum number of rows in each chunk.
+    pub fn rows_per_chunk(&self) -> u64 {
+        self.rows_per_chunk
+    }
+}
+
+impl<'a, T> Iterator for PrefetchedColumnChunks<'a, T> {
+    type Item = Result<ColumnChunk<T>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.receiver {
+            Some(ref r) => {
+                if let Ok(item) = r.recv() {
+                    return Some(item);
+                }
+            }
+            None => return None,
+        }
+
+        // The reader thread has finished. If it panicked, pass that along.
+        self.receiver = None;
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                return Some(Err(err_msg("the column prefetching thread panicked")));
+            }
+        }
+
+        None
+    }
+}
+
+impl<'a, T> Drop for PrefetchedColumnChunks<'a, T> {
+    fn drop(&mut self) {
+        // Dropping the receiver makes the reader thread's next send fail,
+        // so that it exits promptly.
+        self.receiver = None;
+
+        if let Some(thread) = self.thread.take() {
+            let _r = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn prefetched_chunks() {
+    let dir = std::env::temp_dir().join(format!("rubbl-prefetch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("t.table");
+    let mut t =
+        Table::create_with_scalar_columns(&path, &[("X", GlueDataType::TpDouble)], 1000).unwrap();
+    let values: Vec<f64> = (0..1000).map(|i| i as f64 * 0.25).collect();
+    t.put_col_from_iter("X", values.iter().cloned()).unwrap();
+
+    // 100 rows per chunk for the prefetcher, which halves the budget.
+    let budget = MemoryBudget::new(1600);
+    let prefetched: Vec<_> = t
+        .prefetched_column_chunks::<f64>("X", &budget)
+        .unwrap()
+        .map(|c| c.unwrap())
+        .collect();
+    let direct: Vec<_> = t
+        .column_chunks::<f64>("X", &budget.divide(2))
+        .unwrap()
+        .map(|c| c.unwrap())
+        .collect();
+    assert_eq!(prefetched.len(), 10);
+    assert_eq!(prefetched.len(), direct.len());
+
+    for (p, d) in prefetched.iter().zip(direct.iter()) {
+        assert_eq!((p.start_row, p.n_rows), (d.start_row, d.n_rows));
+        assert_eq!(p.data, d.data);
+    }
+
+    let all: Vec<f64> = t
+        .column_chunks::<f64>("X", &budget)
+        .unwrap()
+        .flat_map(|c| c.unwrap().data)
+        .collect();
+    assert_eq!(all, values);
+    drop(t);
+
+    // Dropping the iterator part way through stops the reader thread, which
+    // is blocked sending the next chunk. Do it in another thread so that a
+    // deadlock fails the test rather than hanging it.
+    let reopen = path.clone();
+    let mut chunks = PrefetchedColumnChunks::<f64>::spawn(
+        move || Table::open(&reopen, TableOpenMode::Read),
+        "X",
+        Vec::new(),
+        10,
+    );
+    assert_eq!(chunks.next().unwrap().unwrap().data, &values[..10]);
+    let (done_tx, done_rx) = mpsc::channel();
+    thread::spawn(move || {
+        drop(chunks);
+        done_tx.send(()).unwrap();
+    });
+    done_rx.recv_timeout(Duration::from_secs(30)).unwrap();
+
+    // Failures to open the table in the background are passed along.
+    let mut chunks =
+        PrefetchedColumnChunks::<f64>::spawn(|| Err(err_msg("cannot open")), "X", Vec::new(), 10);
+    assert_eq!(
+        chunks.next().unwrap().unwrap_err().to_string(),
+        "cannot open"
+    );
+    assert!(chunks.next().is_none());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Table information
+
+/// The information that casacore keeps about a table in its `table.info`
+/// file.
+///
+/// Other applications use the type to recognize what a table holds: CASA,
+/// for instance, only treats a table as a Measurement Set if its type is
+/// `"Measurement Set"`. The readme is free text describing the table, one
+/// line per line of text.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TableInfo {
+    /// The type of the table, or an empty string if it has none.
+    pub table_type: String,
+
+    /// The subtype of the table, or an empty string if it has none.
+    pub sub_type: String,
+
+    /// The readme text of the table.
+    pub readme: String,
+}
+
+impl TableInfo {
+    /// The type of the main table of a Measurement Set.
+    pub const MEASUREMENT_SET: &'static str = "Measurement Set";
+
+    /// Create information with the type *table_type*, no subtype, and no
+    /// readme text.
+    pub fn new(table_type: &str) -> Self {
+        TableInfo {
+            table_type: table_type.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    /// Test whether this describes the main table of a Measurement Set.
+    pub fn is_measurement_set(&self) -> bool {
+        self.table_type == Self::MEASUREMENT_SET
+    }
+}
+
+// Measures metadata
+
+/// The measures metadata of a column.
+///
+/// casacore's measures system, and CASA tasks built on it, learn the units
+/// of a column from its `QuantumUnits` keyword, and what its values measure
+/// — an epoch, a direction, a position, and so on — and in which reference
+/// frame from its `MEASINFO` keyword. Columns without them are treated as
+/// plain numbers, which CASA's stricter tools reject for standard
+/// Measurement Set columns. `Table::deep_copy` keeps these keywords; use
+/// `Table::set_column_measure` to attach them to new columns, and
+/// `Table::copy_column_keywords` to carry them over to columns that are
+/// created to hold copies of others.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ColumnMeasure {
+    /// The units of the column's values, as stored in `QuantumUnits`. For
+    /// measures with several components, such as directions, there is one
+    /// unit per component. This may be empty if only `MEASINFO` is set.
+    pub units: Vec<String>,
+
+    /// The kind of measure, such as `epoch`, or `None` if the column holds
+    /// plain quantities and has no `MEASINFO`.
+    pub measure_type: Option<String>,
+
+    /// The reference frame of the measure, such as `UTC` or `J2000`. This is
+    /// empty for columns whose frame is given by another column.
+    pub reference: String,
+}
+
+impl ColumnMeasure {
+    /// Describe a column of quantities with the given *units*.
+    pub fn quantity(units: &[&str]) -> Self {
+        ColumnMeasure {
+            units: units.iter().map(|u| (*u).to_owned()).collect(),
+            measure_type: None,
+            reference: String::new(),
+        }
+    }
+
+    fn measure(measure_type: &str, reference: &str, units: &[&str]) -> Self {
+        ColumnMeasure {
+            measure_type: Some(measure_type.to_owned()),
+            reference: reference.to_owned(),
+            ..Self::quantity(units)
+        }
+    }
+
+    /// Describe a column of times in seconds, such as the `TIME` column of
+    /// a Measurement Set, whose reference is usually `UTC`.
+    pub fn epoch(reference: &str) -> Self {
+        Self::measure("epoch", reference, &["s"])
+    }
+
+    /// Describe a column of directions in radians, such as the `PHASE_DIR`
+    /// column of a `FIELD` table, whose reference is usually `J2000`.
+    pub fn direction(reference: &str) -> Self {
+        Self::measure("direction", reference, &["rad", "rad"])
+    }
+
+    /// Describe a column of positions in meters, such as the `POSITION`
+    /// column of an `ANTENNA` table, whose reference is usually `ITRF`.
+    pub fn position(reference: &str) -> Self {
+        Self::measure("position", reference, &["m", "m", "m"])
+    }
+
+    /// Describe a column of baseline coordinates in meters, such as the `UVW`
+    /// column of a Measurement Set, whose reference is usually `J2000`.
+    pub fn uvw(reference: &str) -> Self {
+        Self::measure("uvw", reference, &["m", "m", "m"])
+    }
+
+    /// Describe a column of frequencies in hertz.
+    pub fn frequency(reference: &str) -> Self {
+        Self::measure("frequency", reference, &["Hz"])
+    }
+}
+
+impl Table {
+    /// Attach *measure* to the column *col_name*, replacing the
+    /// `QuantumUnits` and `MEASINFO` keywords if they are already set.
+    ///
+    /// If `measure.units` is empty, `QuantumUnits` is left alone, and if
+    /// `measure.measure_type` is `None`, `MEASINFO` is.
+    pub fn set_column_measure(
+        &mut self,
+        col_name: &str,
+        measure: &ColumnMeasure,
+    ) -> Result<(), CasacoreError> {
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                self.path.display().to_string(),
+                format!("set the measures metadata of column \"{}\"", col_name),
+                0,
+                None,
+            );
+            return Ok(());
+        }
+
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let cunits: Vec<_> = measure
+            .units
+            .iter()
+            .map(|u| glue::StringBridge::from_rust(u))
+            .collect();
+        let cmeas_type = glue::StringBridge::from_rust(
+            measure
+                .measure_type
+                .as_ref()
+                .map(|t| t.as_str())
+                .unwrap_or(""),
+        );
+        let cmeas_ref = glue::StringBridge::from_rust(&measure.reference);
+
+        let rv = unsafe {
+            glue_call!(table_put_column_measure(
+                self.handle,
+                &ccol_name,
+                cunits.len() as u64,
+                cunits.as_ptr(),
+                &cmeas_type,
+                &cmeas_ref,
+                &mut self.exc_info,
+            ); table = self.path, column = col_name)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(())
+    }
+
+    /// Get the measures metadata of the column *col_name*, or `None` if it
+    /// has neither a `QuantumUnits` nor a `MEASINFO` keyword.
+    pub fn column_measure(
+        &mut self,
+        col_name: &str,
+    ) -> Result<Option<ColumnMeasure>, CasacoreError> {
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let mut units = CasaString::new();
+        let mut meas_type = CasaString::new();
+        let mut meas_ref = CasaString::new();
+
+        let rv = unsafe {
+            glue_call!(table_get_column_measure(
+                self.handle,
+                &ccol_name,
+                units.as_mut_ptr(),
+                meas_type.as_mut_ptr(),
+                meas_ref.as_mut_ptr(),
+                &mut self.exc_info,
+            ); table = self.path, column = col_name)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        let units = String::from(units);
+        let meas_type = String::from(meas_type);
+
+        if units.is_empty() && meas_type.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(ColumnMeasure {
+            units: if units.is_empty() {
+                Vec::new()
+            } else {
+                units.split(',').map(|u| u.to_owned()).collect()
+            },
+            measure_type: if meas_type.is_empty() {
+                None
+            } else {
+                Some(meas_type)
+            },
+            reference: meas_ref.into(),
+        }))
+    }
+
+    /// Copy all of the keywords of the column *col_name* of *source*,
+    /// including its measures metadata, to the column of the same name of
+    /// this table, replacing any that are already set.
+    pub fn copy_column_keywords(
+        &mut self,
+        source: &mut Table,
+        col_name: &str,
+    ) -> Result<(), CasacoreError> {
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                self.path.display().to_string(),
+                format!(
+                    "copy the keywords of column \"{}\" from {}",
+                    col_name,
+                    source.path.display()
+                ),
+                0,
+                None,
+            );
+            return Ok(());
+        }
+
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+
+        let rv = unsafe {
+            glue_call!(table_copy_column_keywords(
+                self.handle,
+                source.handle,
+                &ccol_name,
+                &mut self.exc_info,
+            ); table = self.path, column = col_name)
+        };
+
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
+
+        Ok(())
+    }
+}
+
+// Column hashing
+
+/// An algorithm used by `Table::hash_column`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum HashAlgo {
+    /// CRC-32.
+    Crc32,
+
+    /// Adler-32, which is faster but weaker than CRC-32.
+    Adler32,
+
+    /// 64-bit xxHash, which is fast and has good collision properties. This
+    /// is only available if the `xxhash` feature of this crate is enabled.
+    #[cfg(feature = "xxhash")]
+    XxHash64,
+}
+
+impl HashAlgo {
+    /// Get the name of the algorithm, as used on the command line and in
+    /// manifests.
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgo::Crc32 => "crc32",
+            HashAlgo::Adler32 => "adler32",
+            #[cfg(feature = "xxhash")]
+            HashAlgo::XxHash64 => "xxhash64",
+        }
+    }
+
+    /// Parse an algorithm name as returned by `name`. Returns `None` if the
+    /// name is not recognized, or names an algorithm that this build of the
+    /// crate does not support.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "crc32" => Some(HashAlgo::Crc32),
+            "adler32" => Some(HashAlgo::Adler32),
+            #[cfg(feature = "xxhash")]
+            "xxhash64" => Some(HashAlgo::XxHash64),
+            _ => None,
+        }
+    }
+
+    fn checksum(self) -> Box<Checksum> {
+        match self {
+            HashAlgo::Crc32 => Box::new(Crc32::new()),
+            HashAlgo::Adler32 => Box::new(Adler32::new()),
+            #[cfg(feature = "xxhash")]
+            HashAlgo::XxHash64 => Box::new(rubbl_core::io::XxHash64::new()),
+        }
+    }
+}
+
+/// A scalar type whose values can be fed to a checksum in a
+/// platform-independent byte representation.
+trait HashableData: CasaScalarData {
+    fn hash_values(values: &[Self], checksum: &mut Checksum);
+}
+
+macro_rules! impl_hashable_number {
+    ($($rust_type:ty),*) => {
+        $(
+            impl HashableData for $rust_type {
+                fn hash_values(values: &[Self], checksum: &mut Checksum) {
+                    for v in values {
+                        checksum.update(&v.to_le_bytes());
+                    }
+                }
+            }
+
+            impl HashableData for Complex<$rust_type> {
+                fn hash_values(values: &[Self], checksum: &mut Checksum) {
+                    for v in values {
+                        checksum.update(&v.re.to_le_bytes());
+                        checksum.update(&v.im.to_le_bytes());
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_hashable_number! { f32, f64 }
+
+macro_rules! impl_hashable_integer {
+    ($($rust_type:ty),*) => {
+        $(
+            impl HashableData for $rust_type {
+                fn hash_values(values: &[Self], checksum: &mut Checksum) {
+                    for v in values {
+                        checksum.update(&v.to_le_bytes());
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_hashable_integer! { i8, u8, i16, u16, i32, u32, i64 }
+
+impl HashableData for bool {
+    fn hash_values(values: &[Self], checksum: &mut Checksum) {
+        for &v in values {
+            checksum.update(&[v as u8]);
+        }
+    }
+}
+
+impl HashableData for String {
+    /// Each string is preceded by its length, so that the boundaries between
+    /// strings affect the hash.
+    fn hash_values(values: &[Self], checksum: &mut Checksum) {
+        for v in values {
+            checksum.update(&(v.len() as u64).to_le_bytes());
+            checksum.update(v.as_bytes());
+        }
+    }
+}
+
+impl Table {
+    /// Compute a hash of the contents of a column, as a quick way to check
+    /// whether two columns hold the same data, or whether a column has
+    /// changed.
+    ///
+    /// The values are hashed in row order as little-endian bytes, so the
+    /// result does not depend on the platform or on how the table is
+    /// stored. A fixed-shape column is read in chunks of rows sized to the
+    /// default memory budget; in a variable-shape column, the shape of each
+    /// cell is hashed along with its values, and undefined cells are hashed
+    /// as a single marker byte. Hashes of columns of different types are not
+    /// comparable.
+    pub fn hash_column(&mut self, col_name: &str, algo: HashAlgo) -> Result<u64, Error> {
+        use glue::GlueDataType::*;
+
+        self.flush_writes()?;
+        let desc = self.get_col_desc(col_name)?;
+        let mut checksum = algo.checksum();
+
+        match desc.data_type {
+            TpBool => self.hash_column_as::<bool>(&desc, &mut *checksum)?,
+            TpChar => self.hash_column_as::<i8>(&desc, &mut *checksum)?,
+            TpUChar => self.hash_column_as::<u8>(&desc, &mut *checksum)?,
+            TpShort => self.hash_column_as::<i16>(&desc, &mut *checksum)?,
+            TpUShort => self.hash_column_as::<u16>(&desc, &mut *checksum)?,
+            TpInt => self.hash_column_as::<i32>(&desc, &mut *checksum)?,
+            TpUInt => self.hash_column_as::<u32>(&desc, &mut *checksum)?,
+            TpInt64 => self.hash_column_as::<i64>(&desc, &mut *checksum)?,
+            TpFloat => self.hash_column_as::<f32>(&desc, &mut *checksum)?,
+            TpDouble => self.hash_column_as::<f64>(&desc, &mut *checksum)?,
+            TpComplex => self.hash_column_as::<Complex<f32>>(&desc, &mut *checksum)?,
+            TpDComplex => self.hash_column_as::<Complex<f64>>(&desc, &mut *checksum)?,
+            TpString => self.hash_column_as::<String>(&desc, &mut *checksum)?,
+            other => {
+                return Err(err_msg(format!(
+                    "cannot hash column \"{}\" of type {}",
+                    col_name, other
+                )));
+            }
+        }
+
+        Ok(checksum.value())
+    }
+
+    fn hash_column_as<T: HashableData>(
+        &mut self,
+        desc: &ColumnDescription,
+        checksum: &mut Checksum,
+    ) -> Result<(), Error> {
+        if desc.is_fixed_shape || desc.is_scalar {
+            for chunk in self.column_chunks::<T>(desc.name(), &MemoryBudget::default())? {
+                T::hash_values(&chunk?.data, checksum);
+            }
+
+            return Ok(());
+        }
+
+        for row in 0..self.n_rows() {
+            if !self.cell_is_defined(desc.name(), row)? {
+                checksum.update(&[0]);
+                continue;
+            }
+
+            let shape = self.get_cell_shape(desc.name(), row)?;
+            checksum.update(&[1, shape.len() as u8]);
+
+            for n in shape {
+                checksum.update(&n.to_le_bytes());
+            }
+
+            T::hash_values(&self.get_cell_as_vec::<T>(desc.name(), row)?, checksum);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn hashable_bytes() {
+    let mut a = Crc32::new();
+    String::hash_values(&["ab".to_owned(), "c".to_owned()], &mut a);
+    let mut b = Crc32::new();
+    String::hash_values(&["a".to_owned(), "bc".to_owned()], &mut b);
+    assert_ne!(a.value(), b.value());
+
+    let mut a = Crc32::new();
+    Complex::<f32>::hash_values(&[Complex::new(1., 2.)], &mut a);
+    let mut b = Crc32::new();
+    f32::hash_values(&[1., 2.], &mut b);
+    assert_eq!(a.value(), b.value());
+}
+
+// Storage layout
+
+/// How the files of a table are organized on disk.
+///
+/// Traditionally each storage manager of a table keeps its data in its own
+/// files inside the table directory. Tables can instead combine nearly all of
+/// these into a single container file, which is much friendlier to parallel
+/// filesystems and object stores that penalize large numbers of small files.
+/// Such tables are read exactly like any other, so only the creation of new
+/// tables needs to say which layout to use: see `CreateOptions` and
+/// `DeepCopyOptions`.
+///
+/// Note that casacore does all of its own I/O using the regular filesystem
+/// APIs, so this crate cannot read tables directly out of an object store
+/// such as S3. Container-format tables are, however, well suited to access
+/// through a filesystem that mounts the object store, since they involve only
+/// a handful of large files that can be fetched in big blocks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TableStorage {
+    /// Each storage manager uses its own files.
+    SeparateFiles,
+
+    /// The files are combined into a casacore MultiFile container with the
+    /// specified block size in bytes.
+    MultiFile { block_size: u32 },
+
+    /// The files are combined into an HDF5 container with the specified
+    /// block size in bytes. This requires a casacore built with HDF5
+    /// support.
+    MultiHdf5 { block_size: u32 },
+}
+
+impl TableStorage {
+    /// The block size used by casacore if none is specified, 4 MiB.
+    pub const DEFAULT_BLOCK_SIZE: u32 = 4 * 1024 * 1024;
+
+    /// Return true if the underlying casacore was built with HDF5 support,
+    /// which `TableStorage::MultiHdf5` requires.
+    pub fn hdf5_is_supported() -> bool {
+        unsafe { glue::storage_hdf5_is_supported() != 0 }
+    }
+}
+
+fn storage_for_glue(storage: Option<TableStorage>) -> (glue::GlueStorageOption, u32) {
+    match storage {
+        None => (glue::GlueStorageOption::GSO_DEFAULT, 0),
+        Some(TableStorage::SeparateFiles) => (glue::GlueStorageOption::GSO_SEPARATE_FILES, 0),
+        Some(TableStorage::MultiFile { block_size }) => {
+            (glue::GlueStorageOption::GSO_MULTI_FILE, block_size)
+        }
+        Some(TableStorage::MultiHdf5 { block_size }) => {
+            (glue::GlueStorageOption::GSO_MULTI_HDF5, block_size)
+        }
+    }
+}
+
+// Opening tables
+
+/// Options for opening tables with `Table::open_with_options`.
+///
+/// Nightly pipelines often collide with interactive CASA sessions that hold
+/// locks on the same data. Rather than waiting indefinitely, as `Table::open`
+/// does, opening with these options retries with increasing delays for a
+/// bounded time, and can report each wait through a callback so that the
+/// reason for the delay can be logged.
+#[derive(Clone, Copy)]
+pub struct OpenOptions<'a> {
+    /// How to open the table.
+    pub mode: TableOpenMode,
+
+    /// How many times to retry while the table is locked, and how long to
+    /// wait before each retry. The default allows 5 retries, waiting 0.1 s
+    /// at first and doubling the delay each time.
+    pub retry: RetryPolicy,
+
+    /// The longest time to keep retrying, if it is limited.
+    pub timeout: Option<Duration>,
+
+    /// A function called before each wait for a lock.
+    pub progress: Option<&'a Fn(&LockWait)>,
+}
+
+impl<'a> OpenOptions<'a> {
+    /// Create options for opening a table in mode *mode*, with the default
+    /// retries and no timeout or progress callback.
+    pub fn new(mode: TableOpenMode) -> Self {
+        OpenOptions {
+            mode: mode,
+            retry: RetryPolicy::default(),
+            timeout: None,
+            progress: None,
+        }
+    }
+}
+
+/// The circumstances of a wait for a lock, as passed to the progress
+/// callback of `OpenOptions`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LockWait {
+    /// The number of the retry about to be waited for, starting at 1.
+    pub attempt: u32,
+
+    /// The ID of the process holding the lock.
+    pub holder_pid: u32,
+
+    /// The time since the first attempt to open the table.
+    pub elapsed: Duration,
+
+    /// How long the next wait will be.
+    pub next_delay: Duration,
+}
+
+/// Wait until the table at *path* is not locked, as allowed by the retry
+/// policy, timeout, and progress callback of *options*.
+///
+/// *check* returns the ID of the process holding a conflicting lock, or
+/// `None` if there is none. The time since the first check is measured by
+/// *elapsed*, and *sleep* does the waiting, so that the timing can be
+/// simulated.
+fn wait_for_lock<C, E, S>(
+    path: &Path,
+    options: &OpenOptions,
+    mut check: C,
+    mut elapsed: E,
+    mut sleep: S,
+) -> Result<(), Error>
+where
+    C: FnMut() -> Result<Option<u32>, Error>,
+    E: FnMut() -> Duration,
+    S: FnMut(Duration),
+{
+    let mut delay = options.retry.initial_delay;
+    let mut attempt = 0;
+
+    loop {
+        let pid = match check()? {
+            Some(pid) => pid,
+            None => return Ok(()),
+        };
+
+        let elapsed = elapsed();
+        let remaining = options
+            .timeout
+            .map(|t| t.checked_sub(elapsed).unwrap_or_default());
+
+        if attempt >= options.retry.max_retries || remaining == Some(Duration::default()) {
+            return Err(CasacoreError {
+                kind: CasacoreErrorKind::Locked,
+                message: format!(
+                    "table {} is locked by process {}; gave up after {} attempts over {:.1} s",
+                    path.display(),
+                    pid,
+                    attempt + 1,
+                    elapsed.as_secs() as f64 + f64::from(elapsed.subsec_millis()) / 1000.
+                ),
+            }
+            .into());
+        }
+
+        attempt += 1;
+        let wait = remaining.map_or(delay, |r| std::cmp::min(r, delay));
+
+        if let Some(progress) = options.progress {
+            progress(&LockWait {
+                attempt: attempt,
+                holder_pid: pid,
+                elapsed: elapsed,
+                next_delay: wait,
+            });
+        }
+
+        sleep(wait);
+        delay = std::cmp::min(delay * 2, options.retry.max_delay);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn lock_waits() {
+    use std::cell::Cell;
+
+    let path = Path::new("t.table");
+    let now = Cell::new(Duration::default());
+    let waits = RefCell::new(Vec::new());
+    let progress = |w: &LockWait| waits.borrow_mut().push(*w);
+    let mut options = OpenOptions::new(TableOpenMode::ReadWrite);
+    options.progress = Some(&progress);
+    options.retry.max_delay = Duration::from_millis(300);
+
+    let ms = Duration::from_millis;
+    let sleep = |d: Duration| now.set(now.get() + d);
+
+    // Released after three checks.
+    let mut n_checks = 0;
+    wait_for_lock(
+        path,
+        &options,
+        || {
+            n_checks += 1;
+            Ok(if n_checks < 3 { Some(42) } else { None })
+        },
+        || now.get(),
+        sleep,
+    )
+    .unwrap();
+    assert_eq!(
+        *waits.borrow(),
+        vec![
+            LockWait {
+                attempt: 1,
+                holder_pid: 42,
+                elapsed: ms(0),
+                next_delay: ms(100),
+            },
+            LockWait {
+                attempt: 2,
+                holder_pid: 42,
+                elapsed: ms(100),
+                next_delay: ms(200),
+            },
+        ]
+    );
+
+    // Never released: the retries run out, with the delay capped.
+    now.set(Duration::default());
+    waits.borrow_mut().clear();
+    let err = wait_for_lock(path, &options, || Ok(Some(7)), || now.get(), sleep).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<CasacoreError>().unwrap().kind,
+        CasacoreErrorKind::Locked
+    );
+    let delays: Vec<_> = waits.borrow().iter().map(|w| w.next_delay).collect();
+    assert_eq!(delays, vec![ms(100), ms(200), ms(300), ms(300), ms(300)]);
+
+    // The timeout cuts the last wait short and then ends the retries.
+    now.set(Duration::default());
+    waits.borrow_mut().clear();
+    options.timeout = Some(ms(250));
+    assert!(wait_for_lock(path, &options, || Ok(Some(7)), || now.get(), sleep).is_err());
+    let delays: Vec<_> = waits.borrow().iter().map(|w| w.next_delay).collect();
+    assert_eq!(delays, vec![ms(100), ms(150)]);
+    assert_eq!(now.get(), ms(250));
+
+    // Errors from the check are passed through.
+    assert!(wait_for_lock(
+        path,
+        &options,
+        || Err(err_msg("no such table")),
+        || now.get(),
+        sleep
+    )
+    .is_err());
+}
+
+/// Options for creating new tables.
+#[derive(Clone, Debug, Default)]
+pub struct CreateOptions {
+    /// How to organize the files of the new table, or `None` to use
+    /// casacore's default, which is normally `TableStorage::SeparateFiles`.
+    pub storage: Option<TableStorage>,
+}
+
+impl CreateOptions {
+    /// Create options for storing the new table in a MultiFile container
+    /// with the specified block size in bytes.
+    ///
+    /// The block size should match the preferred I/O size of the
+    /// filesystem; `TableStorage::DEFAULT_BLOCK_SIZE` is a reasonable choice
+    /// for most parallel filesystems.
+    pub fn multifile(block_size: u32) -> Self {
+        CreateOptions {
+            storage: Some(TableStorage::MultiFile { block_size }),
+        }
+    }
+
+    /// Create options for storing the new table in an HDF5 container with the
+    /// specified block size in bytes. See `TableStorage::hdf5_is_supported`.
+    pub fn multihdf5(block_size: u32) -> Self {
+        CreateOptions {
+            storage: Some(TableStorage::MultiHdf5 { block_size }),
+        }
+    }
+}
+
+// Write-behind buffering
+
+/// Options for buffering the cells written by `Table::put_cell`; see
+/// `Table::set_write_behind`.
+#[derive(Clone, Copy, Debug)]
+pub struct WriteBehindOptions {
+    /// The buffered cells of a column are written out once they cover this
+    /// many rows.
+    pub max_rows: u64,
+
+    /// All buffered cells are written out once they take up this many
+    /// bytes.
+    pub max_bytes: u64,
+}
+
+impl Default for WriteBehindOptions {
+    fn default() -> Self {
+        WriteBehindOptions {
+            max_rows: COLUMN_PUT_BATCH_ROWS as u64,
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Buffered cells of one column, for consecutive rows.
+struct PendingColumn {
+    col_name: String,
+    data_type: GlueDataType,
+    cell_shape: Vec<u64>,
+    start_row: u64,
+    n_rows: u64,
+    /// Raw cell data. This is stored as `u64`s so that it is suitably
+    /// aligned for every data type.
+    words: Vec<u64>,
+    n_bytes: usize,
+    strings: Vec<String>,
+}
+
+/// The state of a table's write-behind buffer.
+struct WriteBehind {
+    options: WriteBehindOptions,
+    columns: Vec<PendingColumn>,
+    n_bytes: u64,
+}
+
+/// Get the array data type whose elements have the scalar data type
+/// *scalar*, for the types that can be written in batches.
+fn batch_array_type(scalar: GlueDataType) -> Option<GlueDataType> {
+    use self::GlueDataType::*;
+
+    Some(match scalar {
+        TpBool => TpArrayBool,
+        TpChar => TpArrayChar,
+        TpUChar => TpArrayUChar,
+        TpShort => TpArrayShort,
+        TpUShort => TpArrayUShort,
+        TpInt => TpArrayInt,
+        TpUInt => TpArrayUInt,
+        TpInt64 => TpArrayInt64,
+        TpFloat => TpArrayFloat,
+        TpDouble => TpArrayDouble,
+        TpComplex => TpArrayComplex,
+        TpDComplex => TpArrayDComplex,
+        _ => return None,
+    })
+}
+
+impl Table {
+    /// Turn buffering of the cells written by `put_cell` on or off.
+    ///
+    /// Writing a table one cell at a time is slow, because each call goes
+    /// through casacore's column machinery separately. With write-behind
+    /// enabled, `put_cell` instead collects the cells written to each column
+    /// in consecutive rows, and writes them out together as a range of rows
+    /// once there are `max_rows` of them or the buffer holds `max_bytes`,
+    /// so that loops that fill in a table row by row speed up without being
+    /// restructured. Writes to rows out of order, string arrays, and cells
+    /// whose type does not match their column are not buffered, but do
+    /// first write out any buffered cells of the same column.
+    ///
+    /// Buffered cells are written out before the table is read through
+    /// `get_cell` and the other methods of `Table` that read cells, when a
+    /// column or row handle is created, and by `flush` and `flush_writes`.
+    /// They are also written out when the table is dropped, but errors are
+    /// lost then. Passing `None` writes out the buffer and turns buffering
+    /// off.
+    pub fn set_write_behind(
+        &mut self,
+        options: Option<WriteBehindOptions>,
+    ) -> Result<(), CasacoreError> {
+        self.flush_writes()?;
+
+        *self.write_behind.get_mut() = options.map(|options| WriteBehind {
+            options: options,
+            columns: Vec::new(),
+            n_bytes: 0,
+        });
+        Ok(())
+    }
+
+    /// Write out all cells held by the write-behind buffer.
+    pub fn flush_writes(&mut self) -> Result<(), CasacoreError> {
+        self.flush_pending_columns()
+    }
+
+    /// Write out all cells held by the write-behind buffer, through a shared
+    /// borrow of the table.
+    fn flush_pending_columns(&self) -> Result<(), CasacoreError> {
+        while self.has_pending_columns() {
+            self.write_pending_column(0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Return whether the write-behind buffer holds any cells.
+    fn has_pending_columns(&self) -> bool {
+        self.write_behind
+            .borrow()
+            .as_ref()
+            .map(|wb| !wb.columns.is_empty())
+            .unwrap_or(false)
+    }
+
+    /// Add a cell to the write-behind buffer if possible. Returns false if
+    /// the cell must be written directly instead.
+    fn buffer_cell<T: CasaDataType>(
+        &mut self,
+        col_name: &str,
+        row: u64,
+        shape: &[u64],
+        value: &T,
+    ) -> Result<bool, CasacoreError> {
+        if T::DATA_TYPE == GlueDataType::TpArrayString {
+            self.write_pending_column_named(col_name)?;
+            return Ok(false);
+        }
+
+        let extends = match *self.write_behind.get_mut() {
+            Some(ref wb) => wb.columns.iter().position(|c| {
+                c.col_name == col_name
+                    && c.data_type == T::DATA_TYPE
+                    && c.cell_shape == shape
+                    && c.start_row + c.n_rows == row
+            }),
+            None => return Ok(false),
+        };
+
+        let index = match extends {
+            Some(i) => i,
+            None => {
+                self.write_pending_column_named(col_name)?;
+
+                // Batched writes interpret the data according to the type of
+                // the column, so they are only safe if it is the type of the
+                // value.
+                let desc = self.get_col_desc(col_name)?;
+                let matches = if desc.is_scalar {
+                    desc.data_type == T::DATA_TYPE
+                } else {
+                    batch_array_type(desc.data_type) == Some(T::DATA_TYPE)
+                };
+
+                if !matches {
+                    return Ok(false);
+                }
+
+                let wb = self.write_behind.get_mut().as_mut().unwrap();
+                wb.columns.push(PendingColumn {
+                    col_name: col_name.to_owned(),
+                    data_type: T::DATA_TYPE,
+                    cell_shape: shape.to_owned(),
+                    start_row: row,
+                    n_rows: 0,
+                    words: Vec::new(),
+                    n_bytes: 0,
+                    strings: Vec::new(),
+                });
+                wb.columns.len() - 1
+            }
+        };
+
+        let wb = self.write_behind.get_mut().as_mut().unwrap();
+        let column = &mut wb.columns[index];
+
+        let n_bytes = if T::DATA_TYPE == GlueDataType::TpString {
+            let s = T::casatables_string_pass_through_out(value);
+            let n = s.len();
+            column.strings.push(s);
+            n
+        } else {
+            let n_elements: u64 = shape.iter().product();
+            let n = n_elements as usize * T::DATA_TYPE.element_size() as usize;
+            let start = column.n_bytes;
+            column.words.resize((start + n).div_ceil(8), 0);
+
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    value.casatables_as_buf() as *const u8,
+                    (column.words.as_mut_ptr() as *mut u8).add(start),
+                    n,
+                );
+            }
+
+            column.n_bytes += n;
+            n
+        };
+
+        column.n_rows += 1;
+        wb.n_bytes += n_bytes as u64;
+        let column_full = column.n_rows >= wb.options.max_rows;
+        let buffer_full = wb.n_bytes >= wb.options.max_bytes;
+
+        if buffer_full {
+            self.flush_writes()?;
+        } else if column_full {
+            self.write_pending_column(index)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Write out the buffered cells of the column *col_name*, if there are
+    /// any.
+    fn write_pending_column_named(&mut self, col_name: &str) -> Result<(), CasacoreError> {
+        let index = self
+            .write_behind
+            .get_mut()
+            .as_ref()
+            .and_then(|wb| wb.columns.iter().position(|c| c.col_name == col_name));
+
+        match index {
+            Some(i) => self.write_pending_column(i),
+            None => Ok(()),
+        }
+    }
+
+    /// Write out the buffered cells of the column with index *index* in the
+    /// write-behind buffer, removing them from it. If the write fails, the
+    /// cells stay in the buffer.
+    fn write_pending_column(&self, index: usize) -> Result<(), CasacoreError> {
+        let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+        let wb = self.write_behind.borrow();
+        let column = &wb.as_ref().unwrap().columns[index];
+        let ccol_name = glue::StringBridge::from_rust(&column.col_name);
+
+        let rv = if column.data_type == GlueDataType::TpString {
+            let glue_strings: Vec<glue::StringBridge> = column
+                .strings
+                .iter()
+                .map(|s| glue::StringBridge::from_rust(s))
+                .collect();
+
+            unsafe {
+                glue_call!(table_put_column_range_data(
+                    self.handle,
+                    &ccol_name,
+                    column.start_row,
+                    column.n_rows,
+                    glue_strings.as_ptr() as _,
+                    &mut exc_info,
+                ); table = self.path, column = column.col_name, rows = column.start_row..column.start_row + column.n_rows)
+            }
+        } else if column.cell_shape.is_empty() {
+            unsafe {
+                glue_call!(table_put_column_range_data(
+                    self.handle,
+                    &ccol_name,
+                    column.start_row,
+                    column.n_rows,
+                    column.words.as_ptr() as _,
+                    &mut exc_info,
+                ); table = self.path, column = column.col_name, rows = column.start_row..column.start_row + column.n_rows)
+            }
+        } else {
+            unsafe {
+                glue_call!(table_put_column_range_cells(
+                    self.handle,
+                    &ccol_name,
+                    column.start_row,
+                    column.n_rows,
+                    column.data_type,
+                    column.cell_shape.len() as u64,
+                    column.cell_shape.as_ptr(),
+                    column.words.as_ptr() as _,
+                    &mut exc_info,
+                ); table = self.path, column = column.col_name, rows = column.start_row..column.start_row + column.n_rows)
+            }
+        };
+
+        drop(wb);
+
+        if rv != 0 {
+            return exc_info.as_err();
+        }
+
+        let mut wb = self.write_behind.borrow_mut();
+        let wb = wb.as_mut().unwrap();
+        let column = wb.columns.remove(index);
+        wb.n_bytes -= if column.data_type == GlueDataType::TpString {
+            column.strings.iter().map(|s| s.len() as u64).sum()
+        } else {
+            column.n_bytes as u64
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn write_behind_buffering() {
+    use self::GlueDataType::*;
+
+    let dir = std::env::temp_dir().join(format!("rubbl-write-behind-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut t =
+        Table::create_with_scalar_columns(dir.join("t.table"), &[("N", TpInt), ("S", TpString)], 5)
+            .unwrap();
+    t.add_array_column("A", TpDouble, Some(&[2])).unwrap();
+    t.set_write_behind(Some(WriteBehindOptions {
+        max_rows: 3,
+        max_bytes: 1 << 20,
+    }))
+    .unwrap();
+
+    for row in 0..5u64 {
+        t.put_cell("N", row, &(10 * row as i32)).unwrap();
+        t.put_cell("S", row, &format!("s{}", row)).unwrap();
+        t.put_cell("A", row, &vec![row as f64, -(row as f64)])
+            .unwrap();
+    }
+
+    // Rows 3 and 4 are still buffered; this out-of-order write makes them
+    // go out first.
+    t.put_cell("N", 1, &-1i32).unwrap();
+
+    // Creating handles writes out whatever is left.
+    {
+        let mut n = t.scalar_column::<i32>("N").unwrap();
+        let mut a = t.array_column::<f64>("A").unwrap();
+        assert_eq!(n.get(1).unwrap(), -1);
+        assert_eq!(n.get(4).unwrap(), 40);
+        assert_eq!(a.get_as_vec(4).unwrap(), vec![4., -4.]);
+    }
+
+    t.put_cell("S", 0, &"again".to_owned()).unwrap();
+    let mut reader = t.get_row_reader().unwrap();
+    t.read_row(&mut reader, 0).unwrap();
+    assert_eq!(reader.get_cell::<String>("S").unwrap(), "again");
+
+    t.put_cell("N", 2, &7i32).unwrap();
+    t.set_write_behind(None).unwrap();
+    assert_eq!(t.get_cell::<i32>("N", 2).unwrap(), 7);
+    assert_eq!(t.get_cell::<String>("S", 4).unwrap(), "s4");
+    assert_eq!(t.get_cell_as_vec::<f64>("A", 2).unwrap(), vec![2., -2.]);
+
+    drop(reader);
+    drop(t);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Deep copies
+
+/// Options for `Table::deep_copy`.
+#[derive(Clone, Debug, Default)]
+pub struct DeepCopyOptions {
+    /// The names of the columns to copy, or `None` to copy all of them.
+    pub column_names: Option<Vec<String>>,
+
+    /// If true, the copy has the same structure as the original table but
+    /// no rows.
+    pub no_rows: bool,
+
+    /// How to organize the files of the copy, or `None` to use casacore's
+    /// default, which is normally `TableStorage::SeparateFiles`.
+    pub storage: Option<TableStorage>,
+}
+
+impl DeepCopyOptions {
+    /// Create options for copying only the specified columns.
+    ///
+    /// For instance, copying every column of a Measurement Set except
+    /// `DATA` gives a lightweight copy of its metadata: the subtables are
+    /// copied in full regardless.
+    pub fn columns(names: &[&str]) -> Self {
+        DeepCopyOptions {
+            column_names: Some(names.iter().map(|s| (*s).to_owned()).collect()),
+            ..DeepCopyOptions::default()
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn materialize_policies() {
+    let dir = std::env::temp_dir().join(format!("rubbl-materialize-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.table");
+    let output = dir.join("out.table");
+    let mut t =
+        Table::create_with_scalar_columns(&input, &[("X", GlueDataType::TpInt)], 3).unwrap();
+    t.put_col("X", &[1, 2, 3]).unwrap();
+
+    assert!(t.materialize(&OutputPolicy::InPlace).is_err());
+    assert_eq!(
+        t.materialize(&OutputPolicy::NewTable(output.clone()))
+            .unwrap(),
+        output
+    );
+    assert!(t
+        .materialize(&OutputPolicy::NewTable(output.clone()))
+        .is_err());
+    t.materialize(&OutputPolicy::Overwrite(output.clone()))
+        .unwrap();
+
+    let mut copy = Table::open(&output, TableOpenMode::Read).unwrap();
+    assert!(!copy.is_reference());
+    assert_eq!(copy.get_col_as_vec::<i32>("X").unwrap(), vec![1, 2, 3]);
+    drop(t);
+    drop(copy);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Handle pools
+
+/// A pool of read-only handles onto one table.
+///
+/// Opening a table is relatively expensive, so a server that answers many
+/// requests from the same table should not open it anew for each one. A pool
+/// opens a fixed number of handles up front and lends them out with `with`.
+/// Each handle is lent to one caller at a time; if all of them are in use,
+/// `with` blocks until one is returned. The pool can be shared between
+/// threads, for instance by wrapping it in an `Arc`.
+///
+/// Up to `n_handles` callers can hold handles at once, but their calls into
+/// casacore still take turns, since every such call holds the process-wide
+/// lock described at `GLUE_LOCK`. What runs concurrently is everything
+/// else: decoding, computing on, and serializing the values read. Pools
+/// therefore help servers whose requests spend much of their time outside
+/// casacore, and do not speed up reading itself.
+pub struct TablePool {
+    path: PathBuf,
+    n_handles: usize,
+    idle: Mutex<Vec<Table>>,
+    returned: Condvar,
+}
+
+impl TablePool {
+    /// Open *n_handles* read-only handles onto the table at *path*.
+    pub fn open<P: AsRef<Path>>(path: P, n_handles: usize) -> Result<Self, Error> {
+        if n_handles == 0 {
+            return Err(err_msg("a table pool needs at least one handle"));
+        }
+
+        let path = path.as_ref();
+        let mut handles = Vec::with_capacity(n_handles);
+
+        for _ in 0..n_handles {
+            handles.push(Table::open(path, TableOpenMode::Read)?);
+        }
+
+        Ok(TablePool {
+            path: path.to_owned(),
+            n_handles: n_handles,
+            idle: Mutex::new(handles),
+            returned: Condvar::new(),
+        })
+    }
+
+    /// Get the path of the pooled table.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the number of handles in the pool.
+    pub fn n_handles(&self) -> usize {
+        self.n_handles
+    }
+
+    /// Call *func* with one of the pool's handles, waiting for one to be
+    /// returned if all of them are in use.
+    ///
+    /// The handle goes back into the pool when *func* returns, or if it
+    /// panics. Dry-run mode is switched off for the next borrower.
+    pub fn with<F, R>(&self, func: F) -> R
+    where
+        F: FnOnce(&mut Table) -> R,
+    {
+        let mut lent = LentTable {
+            pool: self,
+            table: Some(self.take()),
+        };
+        func(lent.table.as_mut().unwrap())
+    }
+
+    fn take(&self) -> Table {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+
+        loop {
+            if let Some(table) = idle.pop() {
+                return table;
+            }
+
+            idle = self
+                .returned
+                .wait(idle)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+/// A handle lent out by a `TablePool`, which is given back when this is
+/// dropped.
+struct LentTable<'a> {
+    pool: &'a TablePool,
+    table: Option<Table>,
+}
+
+impl<'a> Drop for LentTable<'a> {
+    fn drop(&mut self) {
+        if let Some(mut table) = self.table.take() {
+            table.set_dry_run(false);
+            self.pool
+                .idle
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(table);
+            self.pool.returned.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn pooled_reads() {
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join(format!("rubbl-table-pool-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("t.table");
+
+    {
+        let mut desc = tabledesc::TableDescription::new();
+        desc.add_scalar_column("X", GlueDataType::TpInt, "");
+        let mut t = Table::create(&path, &desc, 100).unwrap();
+        t.put_col("X", &(0..100).collect::<Vec<i32>>()).unwrap();
+    }
+
+    assert!(TablePool::open(&path, 0).is_err());
+    let pool = Arc::new(TablePool::open(&path, 2).unwrap());
+    assert_eq!(pool.n_handles(), 2);
+
+    let workers: Vec<_> = (0..4)
+        .map(|i| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                (0..25)
+                    .map(|j| {
+                        let row = (25 * i + j) as u64;
+                        pool.with(|t| t.get_cell::<i32>("X", row).unwrap()) as i64
+                    })
+                    .sum::<i64>()
+            })
+        })
+        .collect();
+
+    let total: i64 = workers.into_iter().map(|w| w.join().unwrap()).sum();
+    assert_eq!(total, 99 * 100 / 2);
+
+    // A panicking borrower still returns its handle.
+    let p = pool.clone();
+    assert!(thread::spawn(move || p.with(|_| panic!("oops")))
+        .join()
+        .is_err());
+    assert_eq!(pool.idle.lock().unwrap().len(), 2);
+
+    drop(pool);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Delimited text export
+
+/// The number of rows read at a time by `Table::export_delimited`.
+pub const EXPORT_BATCH_ROWS: u64 = 4096;
+
+/// The character separating fields in delimited text.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Delimiter {
+    /// Comma-separated values.
+    Comma,
+
+    /// Tab-separated values.
+    Tab,
+}
+
+impl Delimiter {
+    /// Get the character used for this delimiter.
+    pub fn as_char(&self) -> char {
+        match *self {
+            Delimiter::Comma => ',',
+            Delimiter::Tab => '\t',
+        }
+    }
+}
+
+/// Options controlling `Table::export_delimited`.
+///
+/// Values are formatted as follows:
+///
+/// - Booleans are written as `true` or `false`.
+/// - Complex numbers are written like `1.5-2j`, a form that Python's
+///   `complex()` and many other tools accept.
+/// - Floating-point numbers are written with as many digits as needed to
+///   reproduce them exactly.
+/// - Columns listed in *time_columns* must contain MJD seconds, the CASA
+///   convention, and are written as ISO 8601 dates and times.
+/// - Fields containing the delimiter, a double quote, or a line break are
+///   enclosed in double quotes, with embedded double quotes doubled.
+#[derive(Clone, Debug)]
+pub struct DelimitedExportOptions {
+    /// The field delimiter.
+    pub delimiter: Delimiter,
+
+    /// Whether to start with a line giving the column names.
+    pub header: bool,
+
+    /// Columns whose values should be formatted as times.
+    pub time_columns: Vec<String>,
+}
+
+impl Default for DelimitedExportOptions {
+    fn default() -> Self {
+        DelimitedExportOptions {
+            delimiter: Delimiter::Comma,
+            header: true,
+            time_columns: Vec::new(),
+        }
+    }
+}
+
+/// Write text as a delimited-text field, quoting it if necessary.
+fn write_delimited_text(dest: &mut io::Write, text: &str, sep: char) -> Result<(), Error> {
+    if text.contains(|c| c == sep || c == '"' || c == '\n' || c == '\r') {
+        write!(dest, "\"{}\"", text.replace('"', "\"\""))?;
+    } else {
+        write!(dest, "{}", text)?;
+    }
+
+    Ok(())
+}
+
+/// A batch of values read from one column by `Table::export_delimited`.
+/// Integer types are widened to `i64` since they are all formatted the same
+/// way.
+enum ExportValues {
+    Bool(Vec<bool>),
+    Int(Vec<i64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    Complex(Vec<Complex<f32>>),
+    DComplex(Vec<Complex<f64>>),
+    String(Vec<String>),
+}
+
+macro_rules! read_widened {
+    ($table:expr, $name:expr, $start:expr, $n:expr, $ty:ty) => {
+        ExportValues::Int(
+            $table
+                .get_col_range_as_vec::<$ty>($name, $start, $n)?
+                .into_iter()
+                .map(|v| v as i64)
+                .collect(),
+        )
+    };
+}
+
+impl ExportValues {
+    fn read(
+        table: &mut Table,
+        name: &str,
+        data_type: glue::GlueDataType,
+        start: u64,
+        n: u64,
+    ) -> Result<Self, Error> {
+        use glue::GlueDataType::*;
+
+        Ok(match data_type {
+            TpBool => ExportValues::Bool(table.get_col_range_as_vec(name, start, n)?),
+            TpChar => read_widened!(table, name, start, n, i8),
+            TpUChar => read_widened!(table, name, start, n, u8),
+            TpShort => read_widened!(table, name, start, n, i16),
+            TpUShort => read_widened!(table, name, start, n, u16),
+            TpInt => read_widened!(table, name, start, n, i32),
+            TpUInt => read_widened!(table, name, start, n, u32),
+            TpInt64 => ExportValues::Int(table.get_col_range_as_vec(name, start, n)?),
+            TpFloat => ExportValues::Float(table.get_col_range_as_vec(name, start, n)?),
+            TpDouble => ExportValues::Double(table.get_col_range_as_vec(name, start, n)?),
+            TpComplex => ExportValues::Complex(table.get_col_range_as_vec(name, start, n)?),
+            TpDComplex => ExportValues::DComplex(table.get_col_range_as_vec(name, start, n)?),
+            TpString => ExportValues::String(table.get_col_range_as_vec(name, start, n)?),
+            other => {
+                return Err(err_msg(format!(
+                    "cannot export column \"{}\" of type {}",
+                    name, other
+                )));
+            }
+        })
+    }
+
+    fn write(&self, dest: &mut io::Write, i: usize, is_time: bool, sep: char) -> Result<(), Error> {
+        match *self {
+            ExportValues::Bool(ref v) => write!(dest, "{}", v[i])?,
+            ExportValues::Int(ref v) => write!(dest, "{}", v[i])?,
+            ExportValues::Float(ref v) => write!(dest, "{}", v[i])?,
+            ExportValues::Double(ref v) if is_time => {
+                write!(dest, "{}", time::mjd_seconds_to_iso8601(v[i]))?
+            }
+            ExportValues::Double(ref v) => write!(dest, "{}", v[i])?,
+            ExportValues::Complex(ref v) => write_complex(dest, v[i].re, v[i].im)?,
+            ExportValues::DComplex(ref v) => write_complex(dest, v[i].re, v[i].im)?,
+            ExportValues::String(ref v) => write_delimited_text(dest, &v[i], sep)?,
+        }
+
+        Ok(())
+    }
+}
+
+fn write_complex<T: fmt::Display>(dest: &mut io::Write, re: T, im: T) -> Result<(), Error> {
+    let im = im.to_string();
+
+    if im.starts_with('-') {
+        write!(dest, "{}{}j", re, im)?;
+    } else {
+        write!(dest, "{}+{}j", re, im)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn delimited_formatting() {
+    let mut buf = Vec::new();
+    write_delimited_text(&mut buf, "plain", ',').unwrap();
+    write_delimited_text(&mut buf, "a,b", ',').unwrap();
+    write_delimited_text(&mut buf, "a,b", '\t').unwrap();
+    write_delimited_text(&mut buf, "say \"hi\"", '\t').unwrap();
+    write_complex(&mut buf, 1.5f32, -2.).unwrap();
+    write_complex(&mut buf, 0.1f32, 0.).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        "plain\"a,b\"a,b\"say \"\"hi\"\"\"1.5-2j0.1+0j"
+    );
+}
+
+// Delimited text import
+
+/// Options controlling `Table::import_delimited`.
+#[derive(Clone, Debug)]
+pub struct DelimitedImportOptions {
+    /// The field delimiter.
+    pub delimiter: Delimiter,
+
+    /// Whether the first line gives the names of the columns.
+    pub header: bool,
+
+    /// Columns whose values are given as ISO 8601 times, to be stored as
+    /// MJD seconds.
+    pub time_columns: Vec<String>,
+}
+
+impl Default for DelimitedImportOptions {
+    fn default() -> Self {
+        DelimitedImportOptions {
+            delimiter: Delimiter::Comma,
+            header: true,
+            time_columns: Vec::new(),
+        }
+    }
+}
+
+/// Split delimited text into records of fields, following the quoting rules
+/// used by `Table::export_delimited`. Each record is returned along with the
+/// number of the line on which it starts. Blank lines are skipped.
+fn parse_delimited(text: &str, sep: char) -> Result<Vec<(usize, Vec<String>)>, Error> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                if c == '\n' {
+                    line += 1;
+                }
+
+                field.push(c);
+            }
+        } else if c == '"' && field.is_empty() {
+            in_quotes = true;
+        } else if c == sep {
+            fields.push(std::mem::replace(&mut field, String::new()));
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+
+            fields.push(std::mem::replace(&mut field, String::new()));
+
+            if fields.len() > 1 || !fields[0].is_empty() {
+                records.push((record_line, std::mem::replace(&mut fields, Vec::new())));
+            } else {
+                fields.clear();
+            }
+
+            line += 1;
+            record_line = line;
+        } else {
+            field.push(c);
+        }
+    }
+
+    if in_quotes {
+        return Err(err_msg(format!(
+            "unterminated quoted field in record starting on line {}",
+            record_line
+        )));
+    }
+
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push((record_line, fields));
+    }
+
+    Ok(records)
+}
+
+/// Parse field *index* of each record with *parse* and write the results
+/// into the named column.
+fn import_column<T, F>(
+    table: &mut Table,
+    col_name: &str,
+    records: &[(usize, Vec<String>)],
+    index: usize,
+    parse: F,
+) -> Result<(), Error>
+where
+    T: CasaScalarData,
+    F: Fn(&str) -> Option<T>,
+{
+    let mut values = Vec::with_capacity(records.len());
+
+    for &(line, ref fields) in records {
+        match parse(&fields[index]) {
+            Some(v) => values.push(v),
+            None => {
+                return Err(err_msg(format!(
+                    "cannot parse \"{}\" on line {} as a value of type {} for column \"{}\"",
+                    fields[index],
+                    line,
+                    T::DATA_TYPE,
+                    col_name
+                )));
+            }
+        }
+    }
+
+    table.put_col_from_iter(col_name, values)?;
+    Ok(())
+}
+
+fn parse_number<T: FromStr>(text: &str) -> Option<T> {
+    text.trim().parse().ok()
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_lowercase().as_str() {
+        "true" | "t" | "yes" | "y" | "1" => Some(true),
+        "false" | "f" | "no" | "n" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a complex number written like `1.5-2j`, `(1.5-2j)`, `-2j`, or
+/// `1.5`. An `i` may be used in place of the `j`.
+fn parse_complex<T: FromStr + Default>(text: &str) -> Option<Complex<T>> {
+    let text = text.trim();
+    let text = text.trim_start_matches('(').trim_end_matches(')').trim();
+
+    if !(text.ends_with('j') || text.ends_with('i')) {
+        return Some(Complex::new(text.parse().ok()?, T::default()));
+    }
+
+    let body = &text[..text.len() - 1];
+
+    // The imaginary part starts at the last sign that is neither the first
+    // character nor part of an exponent.
+    let split = body
+        .char_indices()
+        .filter(|&(i, c)| {
+            (c == '+' || c == '-') && i > 0 && !body[..i].ends_with(|p| p == 'e' || p == 'E')
+        })
+        .map(|(i, _)| i)
+        .last();
+
+    match split {
+        Some(i) => Some(Complex::new(
+            body[..i].trim().parse().ok()?,
+            body[i..].trim_start_matches('+').trim().parse().ok()?,
+        )),
+        None => Some(Complex::new(T::default(), body.parse().ok()?)),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn delimited_parsing() {
+    let records =
+        parse_delimited("a,\"b,c\"\r\n\n\"say \"\"hi\"\"\",\"x\ny\"\nlast,", ',').unwrap();
+    assert_eq!(
+        records,
+        vec![
+            (1, vec!["a".to_owned(), "b,c".to_owned()]),
+            (3, vec!["say \"hi\"".to_owned(), "x\ny".to_owned()]),
+            (5, vec!["last".to_owned(), "".to_owned()]),
+        ]
+    );
+    assert!(parse_delimited("\"open", ',').is_err());
+
+    assert_eq!(parse_complex::<f32>("1.5-2j"), Some(Complex::new(1.5, -2.)));
+    assert_eq!(
+        parse_complex::<f64>("(1e-3+2e+1j)"),
+        Some(Complex::new(1e-3, 20.))
+    );
+    assert_eq!(parse_complex::<f64>("-2i"), Some(Complex::new(0., -2.)));
+    assert_eq!(parse_complex::<f64>("3"), Some(Complex::new(3., 0.)));
+    assert_eq!(parse_complex::<f64>("3+xj"), None);
+    assert_eq!(parse_bool(" Yes"), Some(true));
+    assert_eq!(parse_bool("maybe"), None);
+}
+
+// Column handles
+
+/// Return whether scalar values stored with the data type *stored* can be
+/// read losslessly as values of the data type *requested*.
+///
+/// These are the conversions that casacore performs when reading scalars,
+/// minus those that can lose information.
+fn scalar_type_promotes_to(stored: GlueDataType, requested: GlueDataType) -> bool {
+    use self::GlueDataType::*;
+
+    match (stored, requested) {
+        (TpUChar, TpShort) | (TpUChar, TpUShort) | (TpUChar, TpInt) | (TpUChar, TpUInt) => true,
+        (TpShort, TpInt) | (TpUShort, TpInt) | (TpUShort, TpUInt) => true,
+        (TpUChar, TpInt64) | (TpShort, TpInt64) | (TpUShort, TpInt64) => true,
+        (TpInt, TpInt64) | (TpUInt, TpInt64) => true,
+        (TpFloat, TpDouble) | (TpComplex, TpDComplex) => true,
+        _ => false,
+    }
+}
+
+/// Return whether array or range data stored with the data type *stored*
+/// can be read and written as values of the data type *requested*, with
+/// conversion done by the glue: that is, whether they are complex values of
+/// different precisions.
+fn complex_precision_converts(stored: GlueDataType, requested: GlueDataType) -> bool {
+    use self::GlueDataType::*;
+
+    matches!(
+        (stored, requested),
+        (TpComplex, TpDComplex) | (TpDComplex, TpComplex)
+    )
+}
+
+#[cfg(test)]
+#[test]
+fn scalar_type_promotion() {
+    use self::GlueDataType::*;
+
+    assert!(scalar_type_promotes_to(TpShort, TpInt));
+    assert!(scalar_type_promotes_to(TpInt, TpInt64));
+    assert!(scalar_type_promotes_to(TpFloat, TpDouble));
+    assert!(!scalar_type_promotes_to(TpInt, TpShort));
+    assert!(!scalar_type_promotes_to(TpUInt, TpInt));
+    assert!(!scalar_type_promotes_to(TpInt, TpUInt));
+    assert!(!scalar_type_promotes_to(TpInt, TpDouble));
+    assert!(!scalar_type_promotes_to(TpInt, TpInt));
+    assert!(complex_precision_converts(TpComplex, TpDComplex));
+    assert!(complex_precision_converts(TpDComplex, TpComplex));
+    assert!(!complex_precision_converts(TpFloat, TpDouble));
+    assert!(!complex_precision_converts(TpComplex, TpComplex));
+}
+
+#[cfg(test)]
+#[test]
+fn complex_precision_round_trip() {
+    use self::GlueDataType::*;
+
+    let dir = std::env::temp_dir().join(format!("rubbl-complex-as-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut t = Table::open(dir.join("t.table"), TableOpenMode::Create).unwrap();
+    t.add_array_column("C", TpComplex, Some(&[2])).unwrap();
+    t.add_array_column("D", TpDComplex, Some(&[2])).unwrap();
+    t.add_rows(2).unwrap();
+
+    // All of these values are exactly representable in single precision.
+    let narrow = vec![Complex::new(1.5f32, -2.25), Complex::new(0.125, 4.)];
+    let wide: Vec<Complex<f64>> = narrow
+        .iter()
+        .map(|c| Complex::new(c.re as f64, c.im as f64))
+        .collect();
+
+    for row in 0..2 {
+        // Double-precision values into a single-precision column and vice
+        // versa, through table_column_put_cell_as.
+        t.array_column::<Complex<f64>>("C")
+            .unwrap()
+            .put(row, &Array::from(wide.clone()))
+            .unwrap();
+        t.array_column::<Complex<f32>>("D")
+            .unwrap()
+            .put(row, &Array::from(narrow.clone()))
+            .unwrap();
+    }
+
+    // Cells read back through table_column_get_cell_as ...
+    assert_eq!(
+        t.array_column::<Complex<f64>>("C")
+            .unwrap()
+            .get_as_vec(1)
+            .unwrap(),
+        wide
+    );
+    assert_eq!(
+        t.array_column::<Complex<f32>>("D")
+            .unwrap()
+            .get_as_vec(1)
+            .unwrap(),
+        narrow
+    );
+
+    // ... and ranges through table_get_column_range_data_as.
+    let wide2: Vec<_> = wide.iter().chain(wide.iter()).cloned().collect();
+    let narrow2: Vec<_> = narrow.iter().chain(narrow.iter()).cloned().collect();
+    assert_eq!(
+        t.get_col_range_as_vec::<Complex<f64>>("C", 0, 2).unwrap(),
+        wide2
+    );
+    assert_eq!(
+        t.get_col_range_as_vec::<Complex<f32>>("D", 0, 2).unwrap(),
+        narrow2
+    );
+
+    // The stored types are unchanged.
+    assert_eq!(
+        t.get_col_range_as_vec::<Complex<f32>>("C", 0, 2).unwrap(),
+        narrow2
+    );
+    assert_eq!(
+        t.get_col_range_as_vec::<Complex<f64>>("D", 0, 2).unwrap(),
+        wide2
+    );
+
+    drop(t);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The type-independent part of a `ScalarColumn` or `ArrayColumn`.
+struct ColumnHandle<'a> {
+    handle: *mut glue::GlueTableColumn,
+    exc_info: glue::ExcInfo,
+    name: String,
+    data_type: glue::GlueDataType,
+    is_scalar: bool,
+    dry_run: bool,
+    _table: PhantomData<&'a Table>,
+}
+
+impl<'a> ColumnHandle<'a> {
+    fn new(table: &'a Table, col_name: &str) -> Result<Self, Error> {
+        let ccol_name = glue::StringBridge::from_rust(col_name);
+        let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+        let mut has_column = 0;
+        let mut data_type = glue::GlueDataType::TpOther;
+        let mut is_scalar = 0;
+
+        if unsafe {
+            glue_call!(table_has_column(table.handle, &ccol_name, &mut has_column, &mut exc_info);
+                table = table.path, column = col_name)
+        } != 0
+        {
+            return exc_info.as_err();
+        }
+
+        if has_column == 0 {
+            return Err(NoSuchColumnError(col_name.to_owned()).into());
+        }
+
+        let handle = unsafe {
+            glue_call!(table_column_alloc(
+                table.handle,
+                &ccol_name,
+                &mut data_type,
+                &mut is_scalar,
+                &mut exc_info,
+            ); table = table.path, column = col_name)
+        };
+
+        if handle.is_null() {
+            return exc_info.as_err();
+        }
+
+        Ok(ColumnHandle {
+            handle: handle,
+            exc_info: exc_info,
+            name: col_name.to_owned(),
+            data_type: data_type,
+            is_scalar: is_scalar != 0,
+            dry_run: table.dry_run.is_some(),
+            _table: PhantomData,
+        })
+    }
+
+    fn check_writable(&self) -> Result<(), CasacoreError> {
+        if self.dry_run {
+            return Err(CasacoreError::general(
+                "column handles cannot write data in dry-run mode",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn cell_shape(&mut self, row: u64) -> Result<Vec<u64>, CasacoreError> {
+        let mut n_dim = 0;
+        let mut dims = [0; 8];
+
+        let rv = unsafe {
+            glue_call!(table_column_get_cell_shape(
+                self.handle,
+                row,
+                &mut n_dim,
+                dims.as_mut_ptr(),
+                &mut self.exc_info,
+            ); column = self.name, row = row)
         };
 
         if rv != 0 {
             return self.exc_info.as_err();
         }
 
-        if data_type != T::DATA_TYPE {
-            return Err(UnexpectedDataTypeError(T::DATA_TYPE, data_type).into());
-        }
-
-        let n_items = dims[..n_dim as usize]
-            .iter()
-            .fold(1usize, |p, n| p * (*n as usize));
-
-        let mut result = Vec::<T>::with_capacity(n_items);
-
-        if data_type != glue::GlueDataType::TpString {
-            let rv = unsafe {
-                glue::table_get_cell(
-                    self.handle,
-                    &ccol_name,
-                    row,
-                    result.as_mut_ptr() as _,
-                    &mut self.exc_info,
-                )
-            };
-
-            if rv != 0 {
-                return self.exc_info.as_err();
-            }
-
-            unsafe {
-                result.set_len(n_items as usize);
-            }
-        } else {
-            // We are not given ownership of the String objects that are
-            // returned, so we must std::mem::forget() them.
-            let mut glue_strings = Vec::<glue::StringBridge>::with_capacity(n_items as usize);
-
-            for _ in 0..n_items as usize {
-                glue_strings.push(glue::StringBridge::from_rust(""));
-            }
+        Ok(dims[..n_dim as usize].to_vec())
+    }
 
-            let rv = unsafe {
-                glue::table_get_cell(
-                    self.handle,
-                    &ccol_name,
-                    row,
-                    glue_strings.as_mut_ptr() as _,
-                    &mut self.exc_info,
-                )
-            };
+    /// Read a cell into *data*, which must be big enough to hold it.
+    unsafe fn get_cell(&mut self, row: u64, data: *mut ()) -> Result<(), CasacoreError> {
+        if glue_call!(table_column_get_cell(self.handle, row, data as _, &mut self.exc_info);
+            column = self.name, row = row)
+            != 0
+        {
+            return self.exc_info.as_err();
+        }
 
-            if rv != 0 {
-                return self.exc_info.as_err();
-            }
+        Ok(())
+    }
 
-            for cstr in glue_strings.into_iter() {
-                result.push(T::casatables_string_pass_through(cstr.to_rust()));
-                std::mem::forget(cstr);
-            }
+    /// Read a scalar cell into *data*, converting it to *as_type*.
+    unsafe fn get_scalar_as(
+        &mut self,
+        row: u64,
+        as_type: GlueDataType,
+        data: *mut (),
+    ) -> Result<(), CasacoreError> {
+        if glue_call!(table_column_get_scalar_as(self.handle, row, as_type, data as _, &mut self.exc_info);
+            column = self.name, row = row)
+            != 0
+        {
+            return self.exc_info.as_err();
         }
 
-        Ok(result)
+        Ok(())
     }
 
-    pub fn put_cell<T: CasaDataType>(
+    /// Read an array cell into *data*, converting its elements to
+    /// *as_type*. *data* must be big enough to hold the converted cell.
+    unsafe fn get_cell_as(
         &mut self,
-        col_name: &str,
         row: u64,
-        value: &T,
+        as_type: GlueDataType,
+        data: *mut (),
     ) -> Result<(), CasacoreError> {
-        let ccol_name = glue::StringBridge::from_rust(col_name);
-        let mut shape = Vec::new();
+        if glue_call!(table_column_get_cell_as(self.handle, row, as_type, data as _, &mut self.exc_info);
+            column = self.name, row = row)
+            != 0
+        {
+            return self.exc_info.as_err();
+        }
 
-        value.casatables_put_shape(&mut shape);
+        Ok(())
+    }
 
-        if T::DATA_TYPE == glue::GlueDataType::TpString {
-            let as_string = T::casatables_string_pass_through_out(value);
-            let glue_string = glue::StringBridge::from_rust(&as_string);
+    /// Write a cell of the specified shape from *data*.
+    unsafe fn put_cell(
+        &mut self,
+        row: u64,
+        shape: &[u64],
+        data: *const (),
+    ) -> Result<(), CasacoreError> {
+        self.check_writable()?;
 
-            let rv = unsafe {
-                glue::table_put_cell(
-                    self.handle,
-                    &ccol_name,
-                    row,
-                    T::DATA_TYPE,
-                    shape.len() as u64,
-                    shape.as_ptr(),
-                    &glue_string as *const glue::StringBridge as _,
-                    &mut self.exc_info,
-                )
-            };
+        let rv = glue_call!(table_column_put_cell(
+            self.handle,
+            row,
+            shape.len() as u64,
+            shape.as_ptr(),
+            data as _,
+            &mut self.exc_info,
+        ); column = self.name, row = row);
 
-            if rv != 0 {
-                return self.exc_info.as_err();
-            }
-        } else if T::DATA_TYPE == glue::GlueDataType::TpArrayString {
-            let glue_strings = T::casatables_stringvec_pass_through_out(value);
+        if rv != 0 {
+            return self.exc_info.as_err();
+        }
 
-            let rv = unsafe {
-                glue::table_put_cell(
-                    self.handle,
-                    &ccol_name,
-                    row,
-                    T::DATA_TYPE,
-                    shape.len() as u64,
-                    shape.as_ptr(),
-                    glue_strings.as_ptr() as _,
-                    &mut self.exc_info,
-                )
-            };
+        Ok(())
+    }
 
-            if rv != 0 {
-                return self.exc_info.as_err();
-            }
-        } else {
-            let rv = unsafe {
-                glue::table_put_cell(
-                    self.handle,
-                    &ccol_name,
-                    row,
-                    T::DATA_TYPE,
-                    shape.len() as u64,
-                    shape.as_ptr(),
-                    value.casatables_as_buf() as _,
-                    &mut self.exc_info,
-                )
-            };
+    /// Write an array cell of the specified shape from *data*, whose
+    /// elements have the type *as_type*, converting them to the type of the
+    /// column.
+    unsafe fn put_cell_as(
+        &mut self,
+        row: u64,
+        as_type: GlueDataType,
+        shape: &[u64],
+        data: *const (),
+    ) -> Result<(), CasacoreError> {
+        self.check_writable()?;
+
+        let rv = glue_call!(table_column_put_cell_as(
+            self.handle,
+            row,
+            as_type,
+            shape.len() as u64,
+            shape.as_ptr(),
+            data as _,
+            &mut self.exc_info,
+        ); column = self.name, row = row);
 
-            if rv != 0 {
-                return self.exc_info.as_err();
-            }
+        if rv != 0 {
+            return self.exc_info.as_err();
         }
 
         Ok(())
     }
 
-    pub fn add_rows(&mut self, n_rows: usize) -> Result<(), CasacoreError> {
-        if unsafe { glue::table_add_rows(self.handle, n_rows as u64, &mut self.exc_info) != 0 } {
-            self.exc_info.as_err()
-        } else {
-            Ok(())
+    /// Read a string cell.
+    fn get_string(&mut self, row: u64) -> Result<String, CasacoreError> {
+        let mut glue_string = CasaString::new();
+        unsafe {
+            self.get_cell(row, glue_string.as_mut_ptr() as _)?;
         }
+        Ok(glue_string.into())
     }
 
-    fn get_row_handle(&mut self, is_read_only: bool) -> Result<TableRow, CasacoreError> {
-        let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
-        let ro_flag = if is_read_only { 1 } else { 0 };
+    /// Read a cell of strings with *n_items* elements.
+    fn get_strings(&mut self, row: u64, n_items: usize) -> Result<Vec<String>, CasacoreError> {
+        let mut glue_strings: Vec<_> = (0..n_items).map(|_| CasaString::new()).collect();
 
-        let handle = unsafe { glue::table_row_alloc(self.handle, ro_flag, &mut exc_info) };
-        if handle.is_null() {
-            return exc_info.as_err();
+        unsafe {
+            self.get_cell(row, glue_strings.as_mut_ptr() as _)?;
         }
 
-        Ok(TableRow {
-            handle: handle,
-            exc_info: exc_info,
-        })
-    }
-
-    pub fn get_row_reader(&mut self) -> Result<TableRow, CasacoreError> {
-        self.get_row_handle(true)
+        Ok(glue_strings.into_iter().map(String::from).collect())
     }
+}
 
-    pub fn get_row_writer(&mut self) -> Result<TableRow, CasacoreError> {
-        self.get_row_handle(false)
+impl<'a> Drop for ColumnHandle<'a> {
+    fn drop(&mut self) {
+        // As with TableRow, there is nothing we can do about an error here.
+        unsafe {
+            glue_call!(table_column_free(self.handle, &mut self.exc_info); column = self.name);
+        }
     }
+}
 
-    pub fn read_row(&mut self, row: &mut TableRow, row_number: u64) -> Result<(), Error> {
-        if unsafe { glue::table_row_read(row.handle, row_number, &mut row.exc_info) } != 0 {
-            return row.exc_info.as_err();
-        }
+/// A handle for accessing the cells of a scalar column.
+///
+/// Created by `Table::scalar_column`.
+pub struct ScalarColumn<'a, T> {
+    column: ColumnHandle<'a>,
+    _data_type: PhantomData<T>,
+}
 
-        Ok(())
+impl<'a, T: CasaScalarData> ScalarColumn<'a, T> {
+    /// Get the name of the column.
+    pub fn name(&self) -> &str {
+        &self.column.name
     }
 
-    pub fn for_each_row<F>(&mut self, mut func: F) -> Result<(), Error>
-    where
-        F: FnMut(&mut TableRow) -> Result<(), Error>,
-    {
-        let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+    /// Get the data type with which the column's values are stored. This
+    /// may be narrower than `T`, in which case the handle cannot write
+    /// values.
+    pub fn stored_data_type(&self) -> GlueDataType {
+        self.column.data_type
+    }
 
-        let handle = unsafe { glue::table_row_alloc(self.handle, 1, &mut exc_info) };
-        if handle.is_null() {
-            return exc_info.as_err();
+    /// Read the value of a cell.
+    pub fn get(&mut self, row: u64) -> Result<T, Error> {
+        if T::DATA_TYPE == glue::GlueDataType::TpString {
+            return Ok(T::casatables_string_pass_through(
+                self.column.get_string(row)?,
+            ));
         }
 
-        let mut row = TableRow {
-            handle: handle,
-            exc_info: exc_info,
-        };
-
-        for row_number in 0..self.n_rows() {
-            if unsafe { glue::table_row_read(row.handle, row_number as u64, &mut row.exc_info) }
-                != 0
-            {
-                return row.exc_info.as_err();
+        let mut result = T::casatables_alloc(&[])?;
+        unsafe {
+            if self.column.data_type == T::DATA_TYPE {
+                self.column.get_cell(row, result.casatables_as_mut_buf())?;
+            } else {
+                self.column
+                    .get_scalar_as(row, T::DATA_TYPE, result.casatables_as_mut_buf())?;
             }
-
-            func(&mut row)?;
         }
-
-        Ok(())
+        Ok(result)
     }
 
-    pub fn copy_rows_to(&mut self, dest: &mut Table) -> Result<(), CasacoreError> {
-        if unsafe { glue::table_copy_rows(self.handle, dest.handle, &mut self.exc_info) != 0 } {
-            self.exc_info.as_err()
-        } else {
-            Ok(())
+    /// Write the value of a cell.
+    pub fn put(&mut self, row: u64, value: &T) -> Result<(), Error> {
+        if self.column.data_type != T::DATA_TYPE {
+            return Err(err_msg(format!(
+                "cannot write {} values into the column \"{}\", which stores {}",
+                T::DATA_TYPE,
+                self.column.name,
+                self.column.data_type
+            )));
         }
-    }
 
-    pub fn deep_copy_no_rows(&mut self, dest_path: &str) -> Result<(), CasacoreError> {
-        let cdest_path = glue::StringBridge::from_rust(dest_path);
-
-        if unsafe {
-            glue::table_deep_copy_no_rows(self.handle, &cdest_path, &mut self.exc_info) != 0
-        } {
-            self.exc_info.as_err()
+        if T::DATA_TYPE == glue::GlueDataType::TpString {
+            let as_string = T::casatables_string_pass_through_out(value);
+            let glue_string = glue::StringBridge::from_rust(&as_string);
+            unsafe {
+                self.column
+                    .put_cell(row, &[], &glue_string as *const glue::StringBridge as _)?;
+            }
         } else {
-            Ok(())
+            unsafe {
+                self.column.put_cell(row, &[], value.casatables_as_buf())?;
+            }
         }
-    }
-}
 
-impl Drop for Table {
-    fn drop(&mut self) {
-        // FIXME: not sure if this function can actually produce useful
-        // exceptions anyway, but we can't do anything if it does!
-        unsafe { glue::table_close_and_free(self.handle, &mut self.exc_info) }
+        Ok(())
     }
 }
 
-pub struct ColumnDescription {
-    name: String,
-    data_type: glue::GlueDataType,
-    is_scalar: bool,
-    is_fixed_shape: bool,
-    shape: Option<Vec<u64>>,
+/// A handle for accessing the cells of an array column.
+///
+/// Created by `Table::array_column`. The type parameter is the type of the
+/// array elements.
+pub struct ArrayColumn<'a, T> {
+    column: ColumnHandle<'a>,
+    _data_type: PhantomData<T>,
 }
 
-impl ColumnDescription {
+impl<'a, T: CasaScalarData> ArrayColumn<'a, T> {
+    /// Get the name of the column.
     pub fn name(&self) -> &str {
-        &self.name
+        &self.column.name
     }
 
-    pub fn data_type(&self) -> glue::GlueDataType {
-        self.data_type
+    /// Get the data type with which the column's elements are stored. If
+    /// this differs from `T`, values are converted as they are read and
+    /// written.
+    pub fn stored_data_type(&self) -> GlueDataType {
+        self.column.data_type
     }
 
-    pub fn is_scalar(&self) -> bool {
-        self.is_scalar
+    /// Get the shape of the array in a cell, in C order.
+    pub fn shape(&mut self, row: u64) -> Result<Vec<u64>, CasacoreError> {
+        self.column.cell_shape(row)
     }
 
-    pub fn is_fixed_shape(&self) -> bool {
-        self.is_fixed_shape
+    /// Read a cell into *data*, which must be big enough to hold it,
+    /// converting it if necessary.
+    unsafe fn read_cell(&mut self, row: u64, data: *mut ()) -> Result<(), CasacoreError> {
+        if self.column.data_type == T::DATA_TYPE {
+            self.column.get_cell(row, data)
+        } else {
+            self.column.get_cell_as(row, T::DATA_TYPE, data)
+        }
     }
 
-    pub fn shape(&self) -> Option<&[u64]> {
-        self.shape.as_ref().map(|v| &v[..])
+    /// Read a cell into a flat vector, discarding its shape.
+    pub fn get_as_vec(&mut self, row: u64) -> Result<Vec<T>, Error> {
+        let n_items = self.column.cell_shape(row)?.iter().product::<u64>() as usize;
+
+        if T::DATA_TYPE == glue::GlueDataType::TpString {
+            return Ok(self
+                .column
+                .get_strings(row, n_items)?
+                .into_iter()
+                .map(T::casatables_string_pass_through)
+                .collect());
+        }
+
+        let mut result = Vec::<T>::with_capacity(n_items);
+        unsafe {
+            self.read_cell(row, result.as_mut_ptr() as _)?;
+            result.set_len(n_items);
+        }
+        Ok(result)
+    }
+}
+
+impl<'a, T: CasaScalarData + Copy> ArrayColumn<'a, T> {
+    /// Read a cell into an array.
+    ///
+    /// It is an error if the dimensionality of the cell does not match that
+    /// of the array type.
+    pub fn get<D: Dimension + DimFromShapeSlice<u64>>(
+        &mut self,
+        row: u64,
+    ) -> Result<Array<T, D>, Error> {
+        let shape = self.column.cell_shape(row)?;
+        let mut result = Array::<T, D>::casatables_alloc(&shape)?;
+        unsafe {
+            self.read_cell(row, result.casatables_as_mut_buf())?;
+        }
+        Ok(result)
+    }
+
+    /// Write an array into a cell.
+    pub fn put<D: Dimension + DimFromShapeSlice<u64>>(
+        &mut self,
+        row: u64,
+        value: &Array<T, D>,
+    ) -> Result<(), Error> {
+        if !value.is_standard_layout() {
+            return Err(err_msg(
+                "arrays written to tables must be in standard (C) order",
+            ));
+        }
+
+        let mut shape = Vec::new();
+        value.casatables_put_shape(&mut shape);
+        unsafe {
+            if self.column.data_type == T::DATA_TYPE {
+                self.column
+                    .put_cell(row, &shape, value.casatables_as_buf())?;
+            } else {
+                self.column
+                    .put_cell_as(row, T::DATA_TYPE, &shape, value.casatables_as_buf())?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -1056,14 +6061,14 @@ impl TableRow {
         let mut dims = [0; 8];
 
         let rv = unsafe {
-            glue::table_row_get_cell_info(
+            glue_call!(table_row_get_cell_info(
                 self.handle,
                 &ccol_name,
                 &mut data_type,
                 &mut n_dim,
                 dims.as_mut_ptr(),
                 &mut self.exc_info,
-            )
+            ); column = col_name)
         };
 
         if rv != 0 {
@@ -1078,12 +6083,12 @@ impl TableRow {
             let mut result = T::casatables_alloc(&dims[..n_dim as usize])?;
 
             let rv = unsafe {
-                glue::table_row_get_cell(
+                glue_call!(table_row_get_cell(
                     self.handle,
                     &ccol_name,
                     result.casatables_as_mut_buf() as _,
                     &mut self.exc_info,
-                )
+                ); column = col_name)
             };
 
             if rv != 0 {
@@ -1092,26 +6097,22 @@ impl TableRow {
 
             result
         } else {
-            // We are not given ownership of the String object that is
-            // returned, so we must std::mem::forget() it.
-            let mut glue_string = glue::StringBridge::from_rust("");
+            let mut glue_string = CasaString::new();
 
             let rv = unsafe {
-                glue::table_row_get_cell(
+                glue_call!(table_row_get_cell(
                     self.handle,
                     &ccol_name,
-                    &mut glue_string as *mut glue::StringBridge as _,
+                    glue_string.as_mut_ptr() as _,
                     &mut self.exc_info,
-                )
+                ); column = col_name)
             };
 
             if rv != 0 {
                 return self.exc_info.as_err();
             }
 
-            let result = T::casatables_string_pass_through(glue_string.to_rust());
-            std::mem::forget(glue_string);
-            result
+            T::casatables_string_pass_through(glue_string.into())
         };
 
         Ok(result)
@@ -1134,7 +6135,7 @@ impl TableRow {
             let glue_string = glue::StringBridge::from_rust(&as_string);
 
             let rv = unsafe {
-                glue::table_row_put_cell(
+                glue_call!(table_row_put_cell(
                     self.handle,
                     &ccol_name,
                     T::DATA_TYPE,
@@ -1142,7 +6143,7 @@ impl TableRow {
                     shape.as_ptr(),
                     &glue_string as *const glue::StringBridge as _,
                     &mut self.exc_info,
-                )
+                ); column = col_name)
             };
 
             if rv != 0 {
@@ -1152,7 +6153,7 @@ impl TableRow {
             let glue_strings = T::casatables_stringvec_pass_through_out(value);
 
             let rv = unsafe {
-                glue::table_row_put_cell(
+                glue_call!(table_row_put_cell(
                     self.handle,
                     &ccol_name,
                     T::DATA_TYPE,
@@ -1160,7 +6161,7 @@ impl TableRow {
                     shape.as_ptr(),
                     glue_strings.as_ptr() as _,
                     &mut self.exc_info,
-                )
+                ); column = col_name)
             };
 
             if rv != 0 {
@@ -1168,7 +6169,7 @@ impl TableRow {
             }
         } else {
             let rv = unsafe {
-                glue::table_row_put_cell(
+                glue_call!(table_row_put_cell(
                     self.handle,
                     &ccol_name,
                     T::DATA_TYPE,
@@ -1176,7 +6177,7 @@ impl TableRow {
                     shape.as_ptr(),
                     value.casatables_as_buf() as _,
                     &mut self.exc_info,
-                )
+                ); column = col_name)
             };
 
             if rv != 0 {
@@ -1193,7 +6194,12 @@ impl TableRow {
         row_number: u64,
     ) -> Result<(), CasacoreError> {
         let rv = unsafe {
-            glue::table_row_copy_and_put(self.handle, row_number, dest.handle, &mut self.exc_info)
+            glue_call!(table_row_copy_and_put(
+                self.handle,
+                row_number,
+                dest.handle,
+                &mut self.exc_info,
+            ); row = row_number)
         };
 
         if rv != 0 {
@@ -1204,7 +6210,10 @@ impl TableRow {
     }
 
     pub fn put(&mut self, row_number: u64) -> Result<(), CasacoreError> {
-        let rv = unsafe { glue::table_row_write(self.handle, row_number, &mut self.exc_info) };
+        let rv = unsafe {
+            glue_call!(table_row_write(self.handle, row_number, &mut self.exc_info);
+                row = row_number)
+        };
 
         if rv != 0 {
             return self.exc_info.as_err();
@@ -1219,7 +6228,7 @@ impl Drop for TableRow {
         // FIXME: not sure if this function can actually produce useful
         // exceptions anyway, but we can't do anything if it does!
         unsafe {
-            glue::table_row_free(self.handle, &mut self.exc_info);
+            glue_call!(table_row_free(self.handle, &mut self.exc_info));
         }
     }
 }
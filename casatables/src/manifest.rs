@@ -0,0 +1,377 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Manifests of datasets, for provenance tracking.
+
+A `Manifest` records the state of a dataset at some moment: the size of
+every file in it and, for every CASA table in it, the number of rows and a
+hash of the contents of each column, as computed by `Table::hash_column`.
+Saved alongside a published result, it makes it possible to check later
+that the data used are exactly the ones that were used then.
+
+The dataset may be a single file or a directory, such as a Measurement Set.
+The tables in a directory are found by looking for `table.dat` files, so
+the subtables of a Measurement Set are included. casacore's `table.lock`
+files, which change whenever a table is opened, are ignored.
+
+Files are compared by size rather than content, since they are large and
+the column hashes already cover the data that matter. Comparing the hashes
+of columns rather than of files means that a table that has been copied
+with a different storage layout still matches.
+
+*/
+
+use failure::{err_msg, Error};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use super::{HashAlgo, Table, TableOpenMode};
+
+/// The version of the manifest format written by this module.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// A record of the contents of a dataset.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Manifest {
+    /// The version of the manifest format.
+    pub version: u32,
+
+    /// The name of the algorithm used to hash the columns, as given by
+    /// `HashAlgo::name`.
+    pub hash_algo: String,
+
+    /// The files of the dataset, in order of path.
+    pub files: Vec<FileEntry>,
+
+    /// The tables of the dataset, in order of path.
+    pub tables: Vec<TableEntry>,
+
+    /// Totals over the whole dataset.
+    pub summary: ManifestSummary,
+}
+
+/// One file of a dataset.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FileEntry {
+    /// The path of the file relative to the dataset, with `/` separators.
+    /// For a dataset that is a single file, this is `.`.
+    pub path: String,
+
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// One table of a dataset.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TableEntry {
+    /// The path of the table relative to the dataset, with `/` separators.
+    /// For the table at the top of the dataset, this is `.`.
+    pub path: String,
+
+    /// The number of rows of the table.
+    pub n_rows: u64,
+
+    /// The columns of the table, in the table's order.
+    pub columns: Vec<ColumnEntry>,
+}
+
+/// One column of a table.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ColumnEntry {
+    /// The name of the column.
+    pub name: String,
+
+    /// The data type of the column's values, such as `c32`.
+    pub data_type: String,
+
+    /// The hash of the column's contents, as 16 hexadecimal digits, or
+    /// `None` if columns of this type cannot be hashed.
+    pub hash: Option<String>,
+}
+
+/// Totals over a whole dataset.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ManifestSummary {
+    /// The number of files.
+    pub n_files: u64,
+
+    /// The total size of the files in bytes.
+    pub total_bytes: u64,
+
+    /// The number of tables.
+    pub n_tables: u64,
+
+    /// The total number of rows of the tables.
+    pub total_rows: u64,
+}
+
+impl Manifest {
+    /// Generate a manifest of the dataset at *path*, hashing its columns
+    /// with *algo*.
+    pub fn generate<P: AsRef<Path>>(path: P, algo: HashAlgo) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut files = Vec::new();
+        let mut table_paths = Vec::new();
+        walk(path, ".", &mut files, &mut table_paths)?;
+
+        let mut tables = Vec::with_capacity(table_paths.len());
+
+        for rel_path in table_paths {
+            tables.push(describe_table(&path.join(&rel_path), rel_path, algo)?);
+        }
+
+        let summary = ManifestSummary {
+            n_files: files.len() as u64,
+            total_bytes: files.iter().map(|f| f.size).sum(),
+            n_tables: tables.len() as u64,
+            total_rows: tables.iter().map(|t| t.n_rows).sum(),
+        };
+
+        Ok(Manifest {
+            version: MANIFEST_VERSION,
+            hash_algo: algo.name().to_owned(),
+            files: files,
+            tables: tables,
+            summary: summary,
+        })
+    }
+
+    /// Check the dataset at *path* against this manifest, returning a
+    /// description of each difference found. An empty result means that the
+    /// dataset matches.
+    ///
+    /// The dataset's columns are hashed with the algorithm recorded in the
+    /// manifest; it is an error if this build does not support it.
+    pub fn verify<P: AsRef<Path>>(&self, path: P) -> Result<Vec<String>, Error> {
+        if self.version != MANIFEST_VERSION {
+            return Err(err_msg(format!(
+                "unsupported manifest version {} (expected {})",
+                self.version, MANIFEST_VERSION
+            )));
+        }
+
+        let algo = match HashAlgo::from_name(&self.hash_algo) {
+            Some(a) => a,
+            None => {
+                return Err(err_msg(format!(
+                    "the manifest uses the unsupported hash algorithm \"{}\"",
+                    self.hash_algo
+                )));
+            }
+        };
+
+        Ok(self.differences(&Manifest::generate(path, algo)?))
+    }
+
+    /// Describe each way in which *actual* differs from this manifest.
+    pub fn differences(&self, actual: &Manifest) -> Vec<String> {
+        let mut diffs = Vec::new();
+
+        let expected_files: BTreeMap<_, _> = self.files.iter().map(|f| (&f.path, f.size)).collect();
+        let actual_files: BTreeMap<_, _> = actual.files.iter().map(|f| (&f.path, f.size)).collect();
+
+        for (path, &size) in &expected_files {
+            match actual_files.get(path) {
+                None => diffs.push(format!("file \"{}\" is missing", path)),
+                Some(&s) if s != size => diffs.push(format!(
+                    "file \"{}\" has size {} (expected {})",
+                    path, s, size
+                )),
+                _ => {}
+            }
+        }
+
+        for path in actual_files.keys() {
+            if !expected_files.contains_key(path) {
+                diffs.push(format!("file \"{}\" is not in the manifest", path));
+            }
+        }
+
+        let actual_tables: BTreeMap<_, _> = actual.tables.iter().map(|t| (&t.path, t)).collect();
+
+        for expected in &self.tables {
+            let table = match actual_tables.get(&expected.path) {
+                Some(t) => t,
+                None => {
+                    diffs.push(format!("table \"{}\" is missing", expected.path));
+                    continue;
+                }
+            };
+
+            if table.n_rows != expected.n_rows {
+                diffs.push(format!(
+                    "table \"{}\" has {} rows (expected {})",
+                    expected.path, table.n_rows, expected.n_rows
+                ));
+            }
+
+            let columns: BTreeMap<_, _> = table.columns.iter().map(|c| (&c.name, c)).collect();
+
+            for col in &expected.columns {
+                match columns.get(&col.name) {
+                    None => diffs.push(format!(
+                        "column \"{}\" of table \"{}\" is missing",
+                        col.name, expected.path
+                    )),
+                    Some(c) if c.data_type != col.data_type => diffs.push(format!(
+                        "column \"{}\" of table \"{}\" has type {} (expected {})",
+                        col.name, expected.path, c.data_type, col.data_type
+                    )),
+                    Some(c) if c.hash != col.hash => diffs.push(format!(
+                        "the contents of column \"{}\" of table \"{}\" have changed",
+                        col.name, expected.path
+                    )),
+                    _ => {}
+                }
+            }
+
+            for col in &table.columns {
+                if !expected.columns.iter().any(|c| c.name == col.name) {
+                    diffs.push(format!(
+                        "column \"{}\" of table \"{}\" is not in the manifest",
+                        col.name, expected.path
+                    ));
+                }
+            }
+        }
+
+        for table in &actual.tables {
+            if !self.tables.iter().any(|t| t.path == table.path) {
+                diffs.push(format!("table \"{}\" is not in the manifest", table.path));
+            }
+        }
+
+        diffs
+    }
+}
+
+/// Join a relative path and a name with a `/`.
+fn join_rel(rel_path: &str, name: &str) -> String {
+    if rel_path == "." {
+        name.to_owned()
+    } else {
+        format!("{}/{}", rel_path, name)
+    }
+}
+
+/// Record the files under *path*, whose path relative to the dataset is
+/// *rel_path*, and the relative paths of the tables among them.
+fn walk(
+    path: &Path,
+    rel_path: &str,
+    files: &mut Vec<FileEntry>,
+    tables: &mut Vec<String>,
+) -> Result<(), Error> {
+    let metadata = fs::metadata(path)?;
+
+    if !metadata.is_dir() {
+        files.push(FileEntry {
+            path: rel_path.to_owned(),
+            size: metadata.len(),
+        });
+        return Ok(());
+    }
+
+    if path.join("table.dat").is_file() {
+        tables.push(rel_path.to_owned());
+    }
+
+    let mut names = Vec::new();
+
+    for entry in fs::read_dir(path)? {
+        names.push(entry?.file_name().to_string_lossy().into_owned());
+    }
+
+    names.sort();
+
+    for name in names {
+        if name == "table.lock" {
+            continue;
+        }
+
+        walk(&path.join(&name), &join_rel(rel_path, &name), files, tables)?;
+    }
+
+    Ok(())
+}
+
+/// Describe the table at *path*, whose path relative to the dataset is
+/// *rel_path*.
+fn describe_table(path: &Path, rel_path: String, algo: HashAlgo) -> Result<TableEntry, Error> {
+    use super::GlueDataType::*;
+
+    let mut table = Table::open(path, TableOpenMode::Read)?;
+    let mut columns = Vec::new();
+
+    for name in table.column_names()? {
+        let data_type = table.get_col_desc(&name)?.data_type();
+
+        // Only the types that `hash_column` understands are hashed; others,
+        // such as table-valued columns, are only listed.
+        let hash = match data_type {
+            TpBool | TpChar | TpUChar | TpShort | TpUShort | TpInt | TpUInt | TpInt64 | TpFloat
+            | TpDouble | TpComplex | TpDComplex | TpString => {
+                Some(format!("{:016x}", table.hash_column(&name, algo)?))
+            }
+            _ => None,
+        };
+
+        columns.push(ColumnEntry {
+            name: name,
+            data_type: data_type.to_string(),
+            hash: hash,
+        });
+    }
+
+    Ok(TableEntry {
+        path: rel_path,
+        n_rows: table.n_rows(),
+        columns: columns,
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn manifest_differences() {
+    let entry = |path: &str, size| FileEntry {
+        path: path.to_owned(),
+        size: size,
+    };
+    let column = |hash: &str| ColumnEntry {
+        name: "DATA".to_owned(),
+        data_type: "c32".to_owned(),
+        hash: Some(hash.to_owned()),
+    };
+    let manifest = |files, hash| Manifest {
+        version: MANIFEST_VERSION,
+        hash_algo: "crc32".to_owned(),
+        files: files,
+        tables: vec![TableEntry {
+            path: ".".to_owned(),
+            n_rows: 10,
+            columns: vec![column(hash)],
+        }],
+        summary: ManifestSummary {
+            n_files: 0,
+            total_bytes: 0,
+            n_tables: 1,
+            total_rows: 10,
+        },
+    };
+
+    let expected = manifest(vec![entry("table.dat", 100), entry("table.f0", 800)], "01");
+    assert!(expected.differences(&expected).is_empty());
+
+    let actual = manifest(vec![entry("table.dat", 100), entry("table.f1", 800)], "02");
+    assert_eq!(
+        expected.differences(&actual),
+        vec![
+            "file \"table.f0\" is missing".to_owned(),
+            "file \"table.f1\" is not in the manifest".to_owned(),
+            "the contents of column \"DATA\" of table \".\" have changed".to_owned(),
+        ]
+    );
+}
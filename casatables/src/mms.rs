@@ -0,0 +1,338 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Partitioned Measurement Sets ("multi-MSs").
+
+CASA's `partition` task, which the ALMA pipeline uses routinely, splits a
+Measurement Set into several ordinary MSs, the *members*, stored in the
+`SUBMSS` subdirectory of an outer MS. The main table of the outer MS is a
+casacore concatenation of the members' main tables. A `MultiMs` reads the
+members directly, so that each can be processed as the plain MS that it
+is, and presents them together as one stream of visibilities with
+`vis_stream`. `MultiMs::create` goes the other way, assembling ordinary MSs
+into a new multi-MS.
+
+*/
+
+use failure::{err_msg, Error};
+use rubbl_visdata::{BasePol, VisPol, VisStream};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::ms::consistency::{self, Tolerances};
+use super::{glue, path_as_bytes, Table, TableOpenMode};
+
+/// The name of the subdirectory of a multi-MS that holds its members.
+pub const SUBMSS_DIR: &str = "SUBMSS";
+
+/// Return true if *path* looks like a multi-MS: a table with a `SUBMSS`
+/// subdirectory containing at least one table.
+pub fn is_multi_ms<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    path.join("table.dat").is_file() && find_members(path).map(|m| !m.is_empty()).unwrap_or(false)
+}
+
+/// Find the tables in the `SUBMSS` subdirectory of *path*, sorted by name.
+fn find_members(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut members = Vec::new();
+
+    for entry in fs::read_dir(path.join(SUBMSS_DIR))? {
+        let member = entry?.path();
+
+        if member.join("table.dat").is_file() {
+            members.push(member);
+        }
+    }
+
+    members.sort();
+    Ok(members)
+}
+
+/// A partitioned Measurement Set.
+#[derive(Clone, Debug)]
+pub struct MultiMs {
+    path: PathBuf,
+    members: Vec<PathBuf>,
+}
+
+impl MultiMs {
+    /// Open the multi-MS at *path*.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        if !is_multi_ms(path) {
+            return Err(err_msg(format!(
+                "\"{}\" is not a multi-MS: it has no tables in a {} subdirectory",
+                path.display(),
+                SUBMSS_DIR
+            )));
+        }
+
+        Ok(MultiMs {
+            path: path.to_owned(),
+            members: find_members(path)?,
+        })
+    }
+
+    /// Create a multi-MS at *path* whose members are the MSs at *parts*.
+    ///
+    /// The parts must all have the same columns. They are moved into the
+    /// `SUBMSS` subdirectory of the new multi-MS, in the given order; the
+    /// outer MS takes its subtables from the first part. It is an error if
+    /// something already exists at *path*.
+    ///
+    /// Since the rows of every part are interpreted with the subtables of
+    /// the first, the parts are checked with `ms::consistency::check` first,
+    /// using the default tolerances. If any part's spectral windows,
+    /// polarization setups, or antennas differ from those of the first
+    /// part, nothing is moved and the error lists all the differences.
+    pub fn create<P: AsRef<Path>, Q: AsRef<Path>>(path: P, parts: &[Q]) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        if parts.is_empty() {
+            return Err(err_msg("a multi-MS needs at least one member"));
+        }
+
+        let tol = Tolerances::default();
+        let mut problems = Vec::new();
+
+        for part in &parts[1..] {
+            let report = consistency::check(parts[0].as_ref(), part.as_ref(), &tol)?;
+
+            if !report.is_consistent() {
+                problems.push(format!(
+                    "\"{}\" differs from \"{}\":\n{}",
+                    part.as_ref().display(),
+                    parts[0].as_ref().display(),
+                    report
+                ));
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(err_msg(format!(
+                "cannot combine inconsistent Measurement Sets into a multi-MS: {}",
+                problems.join("\n")
+            )));
+        }
+
+        let cparts = parts
+            .iter()
+            .map(|p| Ok(glue::StringBridge::from_bytes(path_as_bytes(p.as_ref())?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let csub_dir = glue::StringBridge::from_rust(SUBMSS_DIR);
+        let cpath = glue::StringBridge::from_bytes(path_as_bytes(path)?);
+        let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+
+        if unsafe {
+            glue_call!(table_create_concat(
+                cparts.len() as u64,
+                cparts.as_ptr(),
+                &csub_dir,
+                &cpath,
+                &mut exc_info,
+            ); table = path)
+        } != 0
+        {
+            return exc_info.as_err();
+        }
+
+        MultiMs::open(path)
+    }
+
+    /// Get the path of the multi-MS.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the paths of the members, in order.
+    pub fn members(&self) -> &[PathBuf] {
+        &self.members
+    }
+
+    /// Open the main table of member number *index*.
+    pub fn open_member(&self, index: usize, mode: TableOpenMode) -> Result<Table, Error> {
+        match self.members.get(index) {
+            Some(p) => Table::open(p, mode),
+            None => Err(err_msg(format!(
+                "no member #{} in multi-MS \"{}\"",
+                index,
+                self.path.display()
+            ))),
+        }
+    }
+
+    /// Get the total number of rows in the main tables of the members.
+    pub fn n_rows(&self) -> Result<u64, Error> {
+        let mut n_rows = 0;
+
+        for member in &self.members {
+            n_rows += Table::open(member, TableOpenMode::Read)?.n_rows();
+        }
+
+        Ok(n_rows)
+    }
+
+    /// Read the visibilities of all of the members, in order, as one stream.
+    pub fn vis_stream(&self) -> MultiMsVisStream {
+        MultiMsVisStream {
+            members: self.members.clone(),
+            next_member: 0,
+            ant1: Vec::new(),
+            ant2: Vec::new(),
+            ddids: Vec::new(),
+            ddid_pols: Vec::new(),
+            current: None,
+        }
+    }
+}
+
+/// A `VisStream` over the visibilities of a multi-MS.
+///
+/// Each record is one correlation of one row of a member's main table. Only
+/// the small index columns of each member are read into memory, one member
+/// at a time.
+#[derive(Clone, Debug)]
+pub struct MultiMsVisStream {
+    members: Vec<PathBuf>,
+    next_member: usize,
+    ant1: Vec<i32>,
+    ant2: Vec<i32>,
+    ddids: Vec<i32>,
+
+    /// The correlation types of each data description of the current
+    /// member.
+    ddid_pols: Vec<Vec<VisPol>>,
+
+    /// The row and correlation of the current record.
+    current: Option<(usize, usize)>,
+}
+
+impl MultiMsVisStream {
+    /// Load the index columns of the next member. Returns false if there are
+    /// no more members.
+    fn load_next_member(&mut self) -> Result<bool, Error> {
+        let path = match self.members.get(self.next_member) {
+            Some(p) => p.clone(),
+            None => return Ok(false),
+        };
+
+        self.next_member += 1;
+
+        let mut table = Table::open(&path, TableOpenMode::Read)?;
+        self.ant1 = table.get_col_as_vec("ANTENNA1")?;
+        self.ant2 = table.get_col_as_vec("ANTENNA2")?;
+        self.ddids = table.get_col_as_vec("DATA_DESC_ID")?;
+
+        let mut dd = Table::open(path.join("DATA_DESCRIPTION"), TableOpenMode::Read)?;
+        let pol_ids = dd.get_col_as_vec::<i32>("POLARIZATION_ID")?;
+        let mut pol = Table::open(path.join("POLARIZATION"), TableOpenMode::Read)?;
+        self.ddid_pols.clear();
+
+        for pol_id in pol_ids {
+            let codes = pol.get_cell_as_vec::<i32>("CORR_TYPE", pol_id as u64)?;
+            let pols = codes
+                .into_iter()
+                .map(stokes_to_vispol)
+                .collect::<Result<Vec<_>, _>>()?;
+            self.ddid_pols.push(pols);
+        }
+
+        Ok(true)
+    }
+
+    fn n_corrs(&self, row: usize) -> Result<usize, Error> {
+        match self.ddid_pols.get(self.ddids[row] as usize) {
+            Some(pols) => Ok(pols.len()),
+            None => Err(err_msg(format!(
+                "DATA_DESC_ID {} in row {} has no DATA_DESCRIPTION entry",
+                self.ddids[row], row
+            ))),
+        }
+    }
+}
+
+impl VisStream for MultiMsVisStream {
+    fn next(&mut self) -> Result<bool, Error> {
+        let (mut row, mut corr) = match self.current {
+            Some((row, corr)) => (row, corr + 1),
+            None => (0, 0),
+        };
+
+        loop {
+            if row >= self.ant1.len() {
+                if !self.load_next_member()? {
+                    self.current = None;
+                    return Ok(false);
+                }
+
+                row = 0;
+                corr = 0;
+                continue;
+            }
+
+            if corr < self.n_corrs(row)? {
+                self.current = Some((row, corr));
+                return Ok(true);
+            }
+
+            row += 1;
+            corr = 0;
+        }
+    }
+
+    /// Get the basepol of the current record. This panics unless the last
+    /// call to `next` returned true.
+    fn basepol(&self) -> BasePol {
+        let (row, corr) = self.current.expect("no current record in VisStream");
+        BasePol::new(
+            self.ant1[row] as u16,
+            self.ant2[row] as u16,
+            self.ddid_pols[self.ddids[row] as usize][corr],
+        )
+    }
+}
+
+/// Convert a `CORR_TYPE` code, from casacore's `Stokes::StokesTypes`, to a
+/// `VisPol`.
+pub fn stokes_to_vispol(code: i32) -> Result<VisPol, Error> {
+    Ok(match code {
+        1 => VisPol::I,
+        2 => VisPol::Q,
+        3 => VisPol::U,
+        4 => VisPol::V,
+        5 => VisPol::RR,
+        6 => VisPol::RL,
+        7 => VisPol::LR,
+        8 => VisPol::LL,
+        9 => VisPol::XX,
+        10 => VisPol::XY,
+        11 => VisPol::YX,
+        12 => VisPol::YY,
+        other => {
+            return Err(err_msg(format!("unsupported CORR_TYPE code {}", other)));
+        }
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn multi_ms_detection() {
+    let dir = std::env::temp_dir().join(format!("rubbl-mms-test-{}", std::process::id()));
+    let member = dir.join(SUBMSS_DIR).join("part.0000.ms");
+    fs::create_dir_all(&member).unwrap();
+    assert!(!is_multi_ms(&dir));
+
+    fs::write(dir.join("table.dat"), b"").unwrap();
+    assert!(!is_multi_ms(&dir));
+
+    fs::write(member.join("table.dat"), b"").unwrap();
+    assert!(is_multi_ms(&dir));
+    assert_eq!(MultiMs::open(&dir).unwrap().members(), &[member]);
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(stokes_to_vispol(9).unwrap(), VisPol::XX);
+    assert!(stokes_to_vispol(0).is_err());
+}
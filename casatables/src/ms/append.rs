@@ -0,0 +1,255 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Appending new epochs of observations to a Measurement Set.
+
+Monitoring programs often accumulate a season of observations in one
+growing Measurement Set rather than one per epoch. `append` adds the
+visibilities of a `LiveVisSource` to the main table of an existing MS, using
+an `MsWriter` opened with `MsWriter::open_append`; an `OutputPolicy` says
+whether the MS is extended in place or copied first. The new rows need
+indices into the subtables, and working these out is the job of
+*reconciliation*:
+
+- The data description is the one given in the `Epoch`, or else the first
+  one whose spectral window has the right number of channels and whose
+  polarization setup includes every correlation of the first chunk. Spectral
+  windows cannot be added, since the visibilities do not describe their
+  frequencies, so it is an error if there is no such data description.
+- The field is looked up in the `FIELD` subtable by name and phase center,
+  and added if it is not there, according to the `ReconcilePolicy`.
+- The scan number is the one given in the `Epoch`, or else one more than the
+  largest in the MS, so that each epoch is a new scan.
+
+*/
+
+use failure::{err_msg, Error};
+use ndarray::Array2;
+use rubbl_core::budget::MemoryBudget;
+use rubbl_core::output::OutputPolicy;
+use rubbl_core::units::{MjdSeconds, Radians};
+use rubbl_visdata::streaming::LiveVisSource;
+use std::f64::consts::PI;
+use std::path::Path;
+
+use super::super::mswriter::MsWriter;
+use super::super::{Table, TableOpenMode};
+
+/// Phase centers closer together than this, in radians, are taken to be
+/// the same. This is about 0.2 arcseconds.
+const DIRECTION_TOLERANCE: f64 = 1e-6;
+
+/// How the fields of new data are reconciled with the `FIELD` subtable.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ReconcilePolicy {
+    /// Use an existing field with the same name and phase center if there
+    /// is one, and add a new field otherwise.
+    #[default]
+    MatchOrAdd,
+
+    /// Use an existing field with the same name and phase center, and fail
+    /// if there is none. This suits programs whose targets are fixed in
+    /// advance, where a new field indicates a mistake.
+    MatchOnly,
+
+    /// Always add a new field, so that each epoch has its own.
+    AlwaysAdd,
+}
+
+/// A description of the observations being appended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Epoch {
+    /// The name of the field observed.
+    pub field_name: String,
+
+    /// The phase center of the field, as a longitude and latitude in the
+    /// reference frame of the `FIELD` subtable (usually right ascension and
+    /// declination).
+    pub phase_center: (Radians, Radians),
+
+    /// The data description of the visibilities, or `None` to find one that
+    /// matches them.
+    pub ddid: Option<i32>,
+
+    /// The scan number of the new rows, or `None` to use one more than the
+    /// largest in the Measurement Set.
+    pub scan_number: Option<i32>,
+}
+
+/// What `append` did.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AppendSummary {
+    /// The ID of the field of the new rows.
+    pub field_id: i32,
+
+    /// Whether the field was added to the `FIELD` subtable.
+    pub field_added: bool,
+
+    /// The data description of the new rows.
+    pub ddid: i32,
+
+    /// The scan number of the new rows.
+    pub scan_number: i32,
+
+    /// The number of rows added to the main table.
+    pub n_rows: u64,
+}
+
+/// Append the visibilities delivered by *source*, which were obtained as
+/// described by *epoch*, to the Measurement Set at *input*, with the result
+/// going where *output* says. Rows are buffered and written in batches of
+/// up to about *budget* bytes.
+///
+/// The subtables are only modified once the first chunk has arrived, so if
+/// *source* delivers nothing, the output is the same as the input.
+pub fn append<P: AsRef<Path>, S: LiveVisSource>(
+    input: P,
+    output: &OutputPolicy,
+    source: &mut S,
+    epoch: &Epoch,
+    policy: ReconcilePolicy,
+    budget: &MemoryBudget,
+) -> Result<AppendSummary, Error> {
+    let prepared = output.prepare(input)?;
+    let summary = append_to(prepared.path(), source, epoch, policy, budget)?;
+    prepared.commit()?;
+    Ok(summary)
+}
+
+fn append_to<S: LiveVisSource>(
+    path: &Path,
+    source: &mut S,
+    epoch: &Epoch,
+    policy: ReconcilePolicy,
+    budget: &MemoryBudget,
+) -> Result<AppendSummary, Error> {
+    let mut writer = MsWriter::open_append(path, budget)?;
+
+    if let Some(scan_number) = epoch.scan_number {
+        writer.set_scan_number(scan_number);
+    }
+
+    let mut summary = AppendSummary {
+        field_id: -1,
+        field_added: false,
+        ddid: -1,
+        scan_number: writer.scan_number(),
+        n_rows: 0,
+    };
+
+    let first = match source.next_chunk()? {
+        Some(c) => c,
+        None => return Ok(summary),
+    };
+
+    summary.ddid = match epoch.ddid {
+        Some(ddid) => ddid,
+        None => writer.data_description_for(&first).ok_or_else(|| {
+            err_msg(format!(
+                "the Measurement Set has no data description with {} channels and \
+                 the correlations of the new data",
+                first.n_chan
+            ))
+        })?,
+    };
+
+    let (field_id, field_added) = reconcile_field(path, epoch, policy, first.time)?;
+    summary.field_id = field_id;
+    summary.field_added = field_added;
+    writer.set_field_id(field_id);
+
+    writer.write_chunk(&first, summary.ddid)?;
+    writer.write_source(source, summary.ddid)?;
+    summary.n_rows = writer.finalize()?;
+    Ok(summary)
+}
+
+/// Find or add the field described by *epoch* in the `FIELD` subtable of
+/// the MS at *ms_path*, returning its ID and whether it was added. *time*
+/// is used as the time origin of a new field's direction polynomials.
+fn reconcile_field(
+    ms_path: &Path,
+    epoch: &Epoch,
+    policy: ReconcilePolicy,
+    time: MjdSeconds,
+) -> Result<(i32, bool), Error> {
+    let mut field = Table::open(ms_path.join("FIELD"), TableOpenMode::ReadWrite)?;
+
+    if policy != ReconcilePolicy::AlwaysAdd {
+        let names = field.get_col_as_vec::<String>("NAME")?;
+
+        for (i, name) in names.iter().enumerate() {
+            if *name != epoch.field_name {
+                continue;
+            }
+
+            let dir = field.get_cell_as_vec::<f64>("PHASE_DIR", i as u64)?;
+
+            if dir.len() >= 2 && same_direction((dir[0], dir[1]), epoch.phase_center) {
+                return Ok((i as i32, false));
+            }
+        }
+
+        if policy == ReconcilePolicy::MatchOnly {
+            return Err(err_msg(format!(
+                "the Measurement Set has no field named \"{}\" at the phase center of \
+                 the new data",
+                epoch.field_name
+            )));
+        }
+    }
+
+    let row = field.n_rows();
+    field.add_rows(1)?;
+
+    let (lon, lat) = epoch.phase_center;
+    let dir = Array2::from_shape_vec((1, 2), vec![lon.0, lat.0])?;
+
+    field.put_cell("NAME", row, &epoch.field_name)?;
+    field.put_cell("CODE", row, &String::new())?;
+    field.put_cell("TIME", row, &time.0)?;
+    field.put_cell("NUM_POLY", row, &0i32)?;
+    field.put_cell("DELAY_DIR", row, &dir)?;
+    field.put_cell("PHASE_DIR", row, &dir)?;
+    field.put_cell("REFERENCE_DIR", row, &dir)?;
+    field.put_cell("SOURCE_ID", row, &-1i32)?;
+    field.put_cell("FLAG_ROW", row, &false)?;
+    field.flush(true)?;
+    Ok((row as i32, true))
+}
+
+/// Decide whether the direction *a*, in radians, is the same as *b*, to
+/// within `DIRECTION_TOLERANCE`.
+fn same_direction(a: (f64, f64), b: (Radians, Radians)) -> bool {
+    let mut dlon = (a.0 - (b.0).0) % (2. * PI);
+
+    if dlon > PI {
+        dlon -= 2. * PI;
+    } else if dlon < -PI {
+        dlon += 2. * PI;
+    }
+
+    let dlat = a.1 - (b.1).0;
+    let dx = dlon * (0.5 * (a.1 + (b.1).0)).cos();
+    dx.hypot(dlat) < DIRECTION_TOLERANCE
+}
+
+#[cfg(test)]
+#[test]
+fn direction_matching() {
+    let center = (Radians(1.0), Radians(0.5));
+    assert!(same_direction((1.0, 0.5), center));
+    assert!(same_direction((1.0 + 1e-7, 0.5 - 1e-7), center));
+    assert!(!same_direction((1.0, 0.5 + 1e-5), center));
+    assert!(same_direction(
+        (2. * PI - 1e-8, 0.),
+        (Radians(1e-8), Radians(0.))
+    ));
+    // Near the pole, large differences in longitude are small on the sky.
+    assert!(same_direction(
+        (0., 0.5 * PI),
+        (Radians(3.), Radians(0.5 * PI))
+    ));
+}
@@ -0,0 +1,421 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Checking that Measurement Sets can be combined.
+
+The rows of a Measurement Set refer to its spectral windows, polarization
+setups, and antennas by index. When the main tables of two MSs are combined,
+as in a multi-MS, the combined rows are interpreted with one set of
+subtables, so the indices must mean the same things in both: spectral window
+3 must have the same channels, and antenna 12 must be the same antenna in
+the same place. If they do not, the combined data set is silently wrong.
+
+`check` compares the `SPECTRAL_WINDOW`, `POLARIZATION`, and `ANTENNA`
+subtables of two MSs, to within the `Tolerances` given, and returns a
+`ConsistencyReport` listing every difference that it finds, rather than
+stopping at the first one. `MultiMs::create` runs it on each of its parts.
+
+*/
+
+use failure::Error;
+use rubbl_core::units::{Hz, Meters};
+use std::fmt;
+use std::path::Path;
+
+use super::super::{Table, TableOpenMode};
+
+/// How different the subtables of two Measurement Sets may be while still
+/// being considered consistent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerances {
+    /// The largest allowed difference between the frequencies, or the
+    /// widths, of corresponding channels.
+    pub frequency: Hz,
+
+    /// The largest allowed distance between the positions of corresponding
+    /// antennas.
+    pub position: Meters,
+}
+
+impl Default for Tolerances {
+    /// Allow channel frequencies to differ by 1 Hz and antenna positions by
+    /// 1 mm, which is enough to absorb rounding in conversions between
+    /// formats but far smaller than any real difference in setup.
+    fn default() -> Self {
+        Tolerances {
+            frequency: Hz(1.),
+            position: Meters(1e-3),
+        }
+    }
+}
+
+/// A difference between the subtables of two Measurement Sets.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Conflict {
+    /// The MSs have different numbers of spectral windows.
+    SpwCount(usize, usize),
+
+    /// A spectral window has different numbers of channels.
+    ChannelCount {
+        spw: usize,
+        first: usize,
+        second: usize,
+    },
+
+    /// The frequencies of a spectral window differ by more than the
+    /// tolerance. Only the channel with the largest difference is reported.
+    ChannelFrequency {
+        spw: usize,
+        channel: usize,
+        first: Hz,
+        second: Hz,
+    },
+
+    /// The channel widths of a spectral window differ by more than the
+    /// tolerance. Only the channel with the largest difference is reported.
+    ChannelWidth {
+        spw: usize,
+        channel: usize,
+        first: Hz,
+        second: Hz,
+    },
+
+    /// The MSs have different numbers of polarization setups.
+    PolarizationCount(usize, usize),
+
+    /// A polarization setup has different correlations, given as Stokes
+    /// codes.
+    Correlations {
+        pol: usize,
+        first: Vec<i32>,
+        second: Vec<i32>,
+    },
+
+    /// The MSs have different numbers of antennas.
+    AntennaCount(usize, usize),
+
+    /// An antenna has different names or stations. Each is given as
+    /// `NAME@STATION`.
+    AntennaIdentity {
+        ant: usize,
+        first: String,
+        second: String,
+    },
+
+    /// The positions of an antenna differ by more than the tolerance.
+    AntennaPosition { ant: usize, offset: Meters },
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Conflict::SpwCount(a, b) => {
+                write!(f, "{} spectral windows versus {}", a, b)
+            }
+            Conflict::ChannelCount { spw, first, second } => write!(
+                f,
+                "spectral window {} has {} channels versus {}",
+                spw, first, second
+            ),
+            Conflict::ChannelFrequency {
+                spw,
+                channel,
+                first,
+                second,
+            } => write!(
+                f,
+                "channel {} of spectral window {} is at {} versus {}",
+                channel, spw, first, second
+            ),
+            Conflict::ChannelWidth {
+                spw,
+                channel,
+                first,
+                second,
+            } => write!(
+                f,
+                "channel {} of spectral window {} is {} wide versus {}",
+                channel, spw, first, second
+            ),
+            Conflict::PolarizationCount(a, b) => {
+                write!(f, "{} polarization setups versus {}", a, b)
+            }
+            Conflict::Correlations {
+                pol,
+                ref first,
+                ref second,
+            } => write!(
+                f,
+                "polarization setup {} has correlations {:?} versus {:?}",
+                pol, first, second
+            ),
+            Conflict::AntennaCount(a, b) => write!(f, "{} antennas versus {}", a, b),
+            Conflict::AntennaIdentity {
+                ant,
+                ref first,
+                ref second,
+            } => write!(f, "antenna {} is {} versus {}", ant, first, second),
+            Conflict::AntennaPosition { ant, offset } => {
+                write!(f, "antenna {} has moved by {}", ant, offset)
+            }
+        }
+    }
+}
+
+/// The differences found by `check`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConsistencyReport {
+    /// Every difference found, in the order spectral windows, polarization
+    /// setups, antennas.
+    pub conflicts: Vec<Conflict>,
+}
+
+impl ConsistencyReport {
+    /// Get whether no differences were found.
+    pub fn is_consistent(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl fmt::Display for ConsistencyReport {
+    /// Formats the report with one conflict per line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.conflicts.is_empty() {
+            return write!(f, "no conflicts");
+        }
+
+        for (i, c) in self.conflicts.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{}", c)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The parts of a Measurement Set's subtables that `check` compares.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Setup {
+    /// The channel frequencies and widths of each spectral window.
+    spws: Vec<(Vec<f64>, Vec<f64>)>,
+
+    /// The correlation types of each polarization setup.
+    pols: Vec<Vec<i32>>,
+
+    /// The `NAME@STATION` and position of each antenna.
+    antennas: Vec<(String, Vec<f64>)>,
+}
+
+impl Setup {
+    fn read(ms_path: &Path) -> Result<Self, Error> {
+        let mut setup = Setup::default();
+
+        let mut spw = Table::open(ms_path.join("SPECTRAL_WINDOW"), TableOpenMode::Read)?;
+
+        for i in 0..spw.n_rows() {
+            setup.spws.push((
+                spw.get_cell_as_vec::<f64>("CHAN_FREQ", i)?,
+                spw.get_cell_as_vec::<f64>("CHAN_WIDTH", i)?,
+            ));
+        }
+
+        let mut pol = Table::open(ms_path.join("POLARIZATION"), TableOpenMode::Read)?;
+
+        for i in 0..pol.n_rows() {
+            setup.pols.push(pol.get_cell_as_vec::<i32>("CORR_TYPE", i)?);
+        }
+
+        let mut ant = Table::open(ms_path.join("ANTENNA"), TableOpenMode::Read)?;
+        let names = ant.get_col_as_vec::<String>("NAME")?;
+        let stations = ant.get_col_as_vec::<String>("STATION")?;
+
+        for (i, (name, station)) in names.iter().zip(stations.iter()).enumerate() {
+            setup.antennas.push((
+                format!("{}@{}", name, station),
+                ant.get_cell_as_vec::<f64>("POSITION", i as u64)?,
+            ));
+        }
+
+        Ok(setup)
+    }
+
+    /// Compare this setup with *other*, appending the differences to
+    /// *conflicts*.
+    fn compare(&self, other: &Setup, tol: &Tolerances, conflicts: &mut Vec<Conflict>) {
+        if self.spws.len() != other.spws.len() {
+            conflicts.push(Conflict::SpwCount(self.spws.len(), other.spws.len()));
+        }
+
+        for (spw, (a, b)) in self.spws.iter().zip(other.spws.iter()).enumerate() {
+            if a.0.len() != b.0.len() {
+                conflicts.push(Conflict::ChannelCount {
+                    spw: spw,
+                    first: a.0.len(),
+                    second: b.0.len(),
+                });
+                continue;
+            }
+
+            if let Some((channel, first, second)) = worst_mismatch(&a.0, &b.0, tol.frequency.0) {
+                conflicts.push(Conflict::ChannelFrequency {
+                    spw: spw,
+                    channel: channel,
+                    first: Hz(first),
+                    second: Hz(second),
+                });
+            }
+
+            if let Some((channel, first, second)) = worst_mismatch(&a.1, &b.1, tol.frequency.0) {
+                conflicts.push(Conflict::ChannelWidth {
+                    spw: spw,
+                    channel: channel,
+                    first: Hz(first),
+                    second: Hz(second),
+                });
+            }
+        }
+
+        if self.pols.len() != other.pols.len() {
+            conflicts.push(Conflict::PolarizationCount(
+                self.pols.len(),
+                other.pols.len(),
+            ));
+        }
+
+        for (pol, (a, b)) in self.pols.iter().zip(other.pols.iter()).enumerate() {
+            if a != b {
+                conflicts.push(Conflict::Correlations {
+                    pol: pol,
+                    first: a.clone(),
+                    second: b.clone(),
+                });
+            }
+        }
+
+        if self.antennas.len() != other.antennas.len() {
+            conflicts.push(Conflict::AntennaCount(
+                self.antennas.len(),
+                other.antennas.len(),
+            ));
+        }
+
+        for (ant, (a, b)) in self.antennas.iter().zip(other.antennas.iter()).enumerate() {
+            if a.0 != b.0 {
+                conflicts.push(Conflict::AntennaIdentity {
+                    ant: ant,
+                    first: a.0.clone(),
+                    second: b.0.clone(),
+                });
+                continue;
+            }
+
+            let offset =
+                a.1.iter()
+                    .zip(b.1.iter())
+                    .map(|(x, y)| (x - y) * (x - y))
+                    .sum::<f64>()
+                    .sqrt();
+
+            if offset.is_nan() || offset > tol.position.0 {
+                conflicts.push(Conflict::AntennaPosition {
+                    ant: ant,
+                    offset: Meters(offset),
+                });
+            }
+        }
+    }
+}
+
+/// Find the element where *a* and *b*, which have the same length, differ
+/// the most, if that is by more than *tol*. NaNs count as mismatches.
+fn worst_mismatch(a: &[f64], b: &[f64], tol: f64) -> Option<(usize, f64, f64)> {
+    let mut worst = None;
+    let mut worst_diff = tol;
+
+    for (i, (&x, &y)) in a.iter().zip(b.iter()).enumerate() {
+        let diff = (x - y).abs();
+
+        if diff.is_nan() {
+            return Some((i, x, y));
+        }
+
+        if diff > worst_diff {
+            worst = Some((i, x, y));
+            worst_diff = diff;
+        }
+    }
+
+    worst
+}
+
+/// Compare the spectral windows, polarization setups, and antennas of the
+/// Measurement Sets at *first* and *second*.
+///
+/// Differences are not errors: they are listed in the report, which should
+/// be checked with `ConsistencyReport::is_consistent`. An error is returned
+/// only if the subtables cannot be read.
+pub fn check<P: AsRef<Path>, Q: AsRef<Path>>(
+    first: P,
+    second: Q,
+    tol: &Tolerances,
+) -> Result<ConsistencyReport, Error> {
+    let a = Setup::read(first.as_ref())?;
+    let b = Setup::read(second.as_ref())?;
+    let mut report = ConsistencyReport::default();
+    a.compare(&b, tol, &mut report.conflicts);
+    Ok(report)
+}
+
+#[cfg(test)]
+#[test]
+fn setup_comparison() {
+    let a = Setup {
+        spws: vec![
+            (vec![1e9, 1.1e9], vec![1e8, 1e8]),
+            (vec![2e9, 2.1e9], vec![1e8, 1e8]),
+        ],
+        pols: vec![vec![9, 10, 11, 12]],
+        antennas: vec![
+            ("A0@P1".to_owned(), vec![0., 0., 0.]),
+            ("A1@P2".to_owned(), vec![10., 0., 0.]),
+        ],
+    };
+    let tol = Tolerances::default();
+    let mut conflicts = Vec::new();
+    a.compare(&a.clone(), &tol, &mut conflicts);
+    assert!(conflicts.is_empty());
+
+    let mut b = a.clone();
+    (b.spws[0].0)[1] += 0.5;
+    (b.spws[1].0)[1] += 5.;
+    b.pols[0] = vec![9, 12];
+    b.antennas[1].1[2] = 0.25;
+    b.antennas.push(("A2@P3".to_owned(), vec![20., 0., 0.]));
+    a.compare(&b, &tol, &mut conflicts);
+    assert_eq!(
+        conflicts,
+        vec![
+            Conflict::ChannelFrequency {
+                spw: 1,
+                channel: 1,
+                first: Hz(2.1e9),
+                second: Hz(2.1e9 + 5.),
+            },
+            Conflict::Correlations {
+                pol: 0,
+                first: vec![9, 10, 11, 12],
+                second: vec![9, 12],
+            },
+            Conflict::AntennaCount(2, 3),
+            Conflict::AntennaPosition {
+                ant: 1,
+                offset: Meters(0.25),
+            },
+        ]
+    );
+}
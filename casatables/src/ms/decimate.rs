@@ -0,0 +1,683 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Making small quick-look copies of Measurement Sets.
+
+Archives like to offer a preview of each data set that is small enough to
+download and inspect casually. `decimate` makes one by keeping every Nth
+time sample of a Measurement Set and averaging its channels in groups. It
+can also average the time samples in groups, which makes it the basis of
+the `rubbl average` tool. The result is an ordinary MS that any tool can
+read:
+
+- Rows are kept whole: a row is kept if its time is one of the kept time
+  samples, so every baseline of a kept integration is present. Thinning in
+  time does not combine data, so it leaves the weights alone.
+- If the kept time samples are averaged in groups, the rows of each group
+  that share a baseline, data description, field, and scan are combined
+  into one. Their visibilities are averaged together with the channels, as
+  below; `TIME`, `TIME_CENTROID`, and `UVW` become the means of those of
+  the rows, and `INTERVAL` and `EXPOSURE` their sums. Rows with `FLAG_ROW`
+  set count as flagged throughout.
+- The visibilities of each group of channels are averaged, weighted by
+  `WEIGHT_SPECTRUM` if the input has it and by `WEIGHT` otherwise. Partially
+  flagged groups are handled according to a `FlagPolicy` (see
+  `rubbl_visdata::average`). The weight of each averaged channel is the sum
+  of the weights of its unflagged inputs, `WEIGHT` becomes the mean of these
+  over the unflagged channels of each correlation, and `SIGMA` is set to
+  match, so that statistics computed from the preview have the right noise
+  levels.
+- The `SPECTRAL_WINDOW` subtable is rebinned to match: the frequency of each
+  new channel is the mean of those of its inputs, and its width, effective
+  bandwidth, and resolution are their sums.
+
+The output has a single visibility column, `DATA`, which can be filled from
+any of the input's data columns. Other per-channel columns, such as
+`MODEL_DATA` and `WEIGHT_SPECTRUM`, are left out. What was done, including
+the flag policy, is recorded in the output's `HISTORY` subtable.
+
+*/
+
+use failure::{err_msg, Error};
+use ndarray::Array2;
+use rubbl_core::output::OutputPolicy;
+use rubbl_core::Complex;
+use rubbl_visdata::average::{BinAverager, FlagPolicy};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::Path;
+
+use super::super::partition::{for_each_ddid, run_ddid_worker, DdidPartition};
+use super::super::{DeepCopyOptions, GlueDataType, Table, TableOpenMode, WriteBehindOptions};
+use super::add_history;
+
+/// Main-table columns with a value per channel, which are not copied.
+const CHANNEL_COLUMNS: &[&str] = &[
+    "DATA",
+    "FLOAT_DATA",
+    "MODEL_DATA",
+    "CORRECTED_DATA",
+    "FLAG",
+    "FLAG_CATEGORY",
+    "WEIGHT_SPECTRUM",
+    "SIGMA_SPECTRUM",
+];
+
+/// The name recorded as the application in the `HISTORY` subtable.
+const HISTORY_APPLICATION: &str = "rubbl decimate";
+
+/// How to decimate a Measurement Set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecimateOptions {
+    /// Keep every this many time samples, starting with the first.
+    pub time_step: usize,
+
+    /// Average the kept time samples in groups of this many.
+    pub time_width: usize,
+
+    /// Average this many adjacent channels into one. It must divide the
+    /// number of channels of every spectral window.
+    pub chan_width: usize,
+
+    /// The column of the input from which to take the visibilities.
+    pub data_column: String,
+
+    /// How to average groups of channels that are partly flagged.
+    pub flag_policy: FlagPolicy,
+}
+
+impl Default for DecimateOptions {
+    fn default() -> Self {
+        DecimateOptions {
+            time_step: 1,
+            time_width: 1,
+            chan_width: 1,
+            data_column: "DATA".to_owned(),
+            flag_policy: FlagPolicy::default(),
+        }
+    }
+}
+
+/// What `decimate` did.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub struct DecimateSummary {
+    /// The number of distinct times in the input.
+    pub n_times_in: usize,
+
+    /// The number of distinct times in the output.
+    pub n_times_out: usize,
+
+    /// The number of rows in the input.
+    pub n_rows_in: u64,
+
+    /// The number of rows in the output.
+    pub n_rows_out: u64,
+}
+
+/// Make a decimated copy of the Measurement Set at *input*, as directed by
+/// *options*, and put it where *output* says. See the module documentation
+/// for what is done.
+///
+/// The copy is written from scratch, so with the in-place policy it
+/// replaces the input once it is complete.
+pub fn decimate<P: AsRef<Path>>(
+    input: P,
+    output: &OutputPolicy,
+    options: &DecimateOptions,
+) -> Result<DecimateSummary, Error> {
+    let input = input.as_ref();
+    let prepared = output.prepare_derived(input)?;
+    let summary = decimate_into(input, prepared.path(), options)?;
+    prepared.commit()?;
+    Ok(summary)
+}
+
+/// Work out what `decimate` would do with the Measurement Set at *input*,
+/// checking that it can be done, but do not write anything.
+pub fn decimate_dry_run<P: AsRef<Path>>(
+    input: P,
+    options: &DecimateOptions,
+) -> Result<DecimateSummary, Error> {
+    let input = input.as_ref();
+    let mut ms = Table::open(input, TableOpenMode::Read)?;
+    Ok(plan_decimation(&mut ms, input, options)?.summary)
+}
+
+/// Which rows of the input are combined into each row of the output.
+struct DecimationPlan {
+    /// The input rows of each output row, in order.
+    groups: Vec<Vec<u64>>,
+
+    summary: DecimateSummary,
+}
+
+fn plan_decimation(
+    ms: &mut Table,
+    input: &Path,
+    options: &DecimateOptions,
+) -> Result<DecimationPlan, Error> {
+    if options.time_step == 0 || options.time_width == 0 || options.chan_width == 0 {
+        return Err(err_msg(
+            "the time step and averaging widths of a decimation must be at least 1",
+        ));
+    }
+
+    for col_name in &[options.data_column.as_str(), "FLAG", "WEIGHT", "SIGMA"] {
+        if !ms.has_column(col_name)? {
+            return Err(err_msg(format!(
+                "the Measurement Set has no {} column",
+                col_name
+            )));
+        }
+    }
+
+    let n_chans = Table::open(input.join("SPECTRAL_WINDOW"), TableOpenMode::Read)?
+        .get_col_as_vec::<i32>("NUM_CHAN")?;
+
+    for (spw, &n_chan) in n_chans.iter().enumerate() {
+        if n_chan as usize % options.chan_width != 0 {
+            return Err(err_msg(format!(
+                "the {} channels of spectral window {} cannot be averaged in groups of {}",
+                n_chan, spw, options.chan_width
+            )));
+        }
+    }
+
+    let times = ms.get_col_as_vec::<f64>("TIME")?;
+    let mut keys = Vec::new();
+
+    if options.time_width > 1 {
+        let columns = [
+            ms.get_col_as_vec::<i32>("ANTENNA1")?,
+            ms.get_col_as_vec::<i32>("ANTENNA2")?,
+            ms.get_col_as_vec::<i32>("DATA_DESC_ID")?,
+            ms.get_col_as_vec::<i32>("FIELD_ID")?,
+            ms.get_col_as_vec::<i32>("SCAN_NUMBER")?,
+        ];
+
+        keys = (0..times.len())
+            .map(|r| {
+                [
+                    columns[0][r],
+                    columns[1][r],
+                    columns[2][r],
+                    columns[3][r],
+                    columns[4][r],
+                ]
+            })
+            .collect();
+    }
+
+    let (groups, n_times_in, n_times_out) =
+        time_groups(&times, &keys, options.time_step, options.time_width);
+
+    Ok(DecimationPlan {
+        summary: DecimateSummary {
+            n_times_in: n_times_in,
+            n_times_out: n_times_out,
+            n_rows_in: times.len() as u64,
+            n_rows_out: groups.len() as u64,
+        },
+        groups: groups,
+    })
+}
+
+fn decimate_into(
+    input: &Path,
+    output: &Path,
+    options: &DecimateOptions,
+) -> Result<DecimateSummary, Error> {
+    let mut ms = Table::open(input, TableOpenMode::Read)?;
+    let plan = plan_decimation(&mut ms, input, options)?;
+    copy_structure(&mut ms, &plan, output, options)?;
+
+    let mut out = Table::open(output, TableOpenMode::ReadWrite)?;
+    out.set_write_behind(Some(WriteBehindOptions::default()))?;
+    average_groups(
+        &mut ms,
+        &mut out,
+        plan.groups
+            .iter()
+            .enumerate()
+            .map(|(i, g)| (i as u64, g.as_slice())),
+        options,
+    )?;
+    out.flush(true)?;
+    write_history(input, output, options, &plan.summary)?;
+    Ok(plan.summary)
+}
+
+/// Make a decimated copy of the Measurement Set at *input* like `decimate`,
+/// but average the data of each data description in a separate process,
+/// with up to *n_workers* at once.
+///
+/// The workers are started by running *program* with the arguments *args*
+/// (see `partition::for_each_ddid`), and must call `run_decimate_worker`
+/// with the same input and options.
+pub fn decimate_with_workers<P: AsRef<Path>>(
+    input: P,
+    output: &OutputPolicy,
+    options: &DecimateOptions,
+    n_workers: usize,
+    program: &Path,
+    args: &[OsString],
+) -> Result<DecimateSummary, Error> {
+    let input = input.as_ref();
+    let prepared = output.prepare_derived(input)?;
+
+    let plan = {
+        let mut ms = Table::open(input, TableOpenMode::Read)?;
+        let plan = plan_decimation(&mut ms, input, options)?;
+        copy_structure(&mut ms, &plan, prepared.path(), options)?;
+        plan
+    };
+
+    for_each_ddid::<_, u64>(
+        prepared.path(),
+        TableOpenMode::ReadWrite,
+        n_workers,
+        program,
+        args,
+    )?;
+    write_history(input, prepared.path(), options, &plan.summary)?;
+    prepared.commit()?;
+    Ok(plan.summary)
+}
+
+/// If this process was started as a worker by `decimate_with_workers`,
+/// average the data of its data description and return true. Otherwise,
+/// return false without doing anything.
+///
+/// The partition given to the worker is of the output, whose rows follow
+/// the groups of the decimation plan, so the plan is worked out again here
+/// to find the input rows of each of them.
+pub fn run_decimate_worker<P: AsRef<Path>>(
+    input: P,
+    options: &DecimateOptions,
+) -> Result<bool, Error> {
+    let input = input.as_ref();
+
+    run_ddid_worker(|partition: &DdidPartition, out: &mut Table| {
+        let mut ms = Table::open(input, TableOpenMode::Read)?;
+        let plan = plan_decimation(&mut ms, input, options)?;
+        average_groups(
+            &mut ms,
+            out,
+            partition
+                .rows
+                .iter()
+                .enumerate()
+                .map(|(i, &r)| (i as u64, plan.groups[r].as_slice())),
+            options,
+        )?;
+        Ok(partition.rows.len() as u64)
+    })
+}
+
+/// Create the output *output* of the decimation of *ms* described by
+/// *plan*: a copy of the first input row of each group, without the
+/// per-channel columns, with rebinned spectral windows and empty `DATA` and
+/// `FLAG` columns.
+fn copy_structure(
+    ms: &mut Table,
+    plan: &DecimationPlan,
+    output: &Path,
+    options: &DecimateOptions,
+) -> Result<(), Error> {
+    let mut mask = vec![false; plan.summary.n_rows_in as usize];
+
+    for group in &plan.groups {
+        mask[group[0] as usize] = true;
+    }
+
+    let kept_columns: Vec<String> = ms
+        .column_names()?
+        .into_iter()
+        .filter(|c| !CHANNEL_COLUMNS.contains(&c.as_str()))
+        .collect();
+    let kept_columns: Vec<&str> = kept_columns.iter().map(|c| c.as_str()).collect();
+    ms.select_rows(&mask)?
+        .deep_copy(output, &DeepCopyOptions::columns(&kept_columns))?;
+
+    rebin_spectral_windows(&output.join("SPECTRAL_WINDOW"), options.chan_width)?;
+
+    let mut out = Table::open(output, TableOpenMode::ReadWrite)?;
+    out.add_array_column("DATA", GlueDataType::TpComplex, None)?;
+    out.add_array_column("FLAG", GlueDataType::TpBool, None)?;
+    Ok(())
+}
+
+/// Average the rows of *ms* in *groups*, each given with the row of *out*
+/// to write it to, as directed by *options*.
+fn average_groups<'g, I>(
+    ms: &mut Table,
+    out: &mut Table,
+    groups: I,
+    options: &DecimateOptions,
+) -> Result<(), Error>
+where
+    I: IntoIterator<Item = (u64, &'g [u64])>,
+{
+    let has_weight_spectrum = ms.has_column("WEIGHT_SPECTRUM")?;
+    let row_flags = ms.get_col_as_vec::<bool>("FLAG_ROW")?;
+
+    let time_columns = if options.time_width > 1 {
+        Some((
+            ms.get_col_as_vec::<f64>("TIME")?,
+            ms.get_col_as_vec::<f64>("TIME_CENTROID")?,
+            ms.get_col_as_vec::<f64>("INTERVAL")?,
+            ms.get_col_as_vec::<f64>("EXPOSURE")?,
+        ))
+    } else {
+        None
+    };
+
+    for (out_row, group) in groups {
+        let mut samples = Vec::with_capacity(group.len());
+
+        for &row in group {
+            let data: Array2<Complex<f32>> = ms.get_cell(&options.data_column, row)?;
+            let mut flags: Array2<bool> = ms.get_cell("FLAG", row)?;
+            let row_weights: Vec<f32> = ms.get_cell("WEIGHT", row)?;
+
+            let weights: Array2<f32> = if has_weight_spectrum {
+                ms.get_cell("WEIGHT_SPECTRUM", row)?
+            } else {
+                Array2::from_shape_fn(data.dim(), |(_, corr)| row_weights[corr])
+            };
+
+            if row_flags[row as usize] {
+                flags.fill(true);
+            }
+
+            if data.dim()
+                != samples
+                    .first()
+                    .map_or(data.dim(), |s: &RowData| s.data.dim())
+            {
+                return Err(err_msg(format!(
+                    "the data of rows {} and {} have different shapes, so they cannot be averaged",
+                    group[0], row
+                )));
+            }
+
+            samples.push(RowData {
+                data: data,
+                flags: flags,
+                weights: weights,
+            });
+        }
+
+        let (avg_data, avg_flags, avg_weights) =
+            average_rows(&samples, options.chan_width, options.flag_policy);
+
+        let sigmas: Vec<f32> = avg_weights
+            .iter()
+            .map(|&w| if w > 0. { 1. / w.sqrt() } else { 0. })
+            .collect();
+
+        out.put_cell("DATA", out_row, &avg_data)?;
+        out.put_cell("FLAG", out_row, &avg_flags)?;
+        out.put_cell("FLAG_ROW", out_row, &avg_flags.iter().all(|&f| f))?;
+        out.put_cell("WEIGHT", out_row, &avg_weights)?;
+        out.put_cell("SIGMA", out_row, &sigmas)?;
+
+        if let Some((ref times, ref centroids, ref intervals, ref exposures)) = time_columns {
+            let n = group.len() as f64;
+            let mean = |values: &[f64]| group.iter().map(|&r| values[r as usize]).sum::<f64>() / n;
+            let sum = |values: &[f64]| group.iter().map(|&r| values[r as usize]).sum::<f64>();
+            let mut uvw = vec![0.; 3];
+
+            for &row in group {
+                for (u, v) in uvw.iter_mut().zip(ms.get_cell_as_vec::<f64>("UVW", row)?) {
+                    *u += v / n;
+                }
+            }
+
+            out.put_cell("TIME", out_row, &mean(times))?;
+            out.put_cell("TIME_CENTROID", out_row, &mean(centroids))?;
+            out.put_cell("INTERVAL", out_row, &sum(intervals))?;
+            out.put_cell("EXPOSURE", out_row, &sum(exposures))?;
+            out.put_cell("UVW", out_row, &uvw)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Record the decimation of *input* into *output* in the `HISTORY`
+/// subtable of *output*, if it has one.
+fn write_history(
+    input: &Path,
+    output: &Path,
+    options: &DecimateOptions,
+    summary: &DecimateSummary,
+) -> Result<(), Error> {
+    if output.join("HISTORY").join("table.dat").is_file() {
+        let time_message = if options.time_width > 1 {
+            format!(" averaged them in groups of {}, and", options.time_width)
+        } else {
+            String::new()
+        };
+
+        add_history(
+            output,
+            HISTORY_APPLICATION,
+            &format!(
+                "Decimated from {}: kept every {} of {} time samples,{} averaged the {} \
+                 column in groups of {} channels. {}",
+                input.display(),
+                options.time_step,
+                summary.n_times_in,
+                time_message,
+                options.data_column,
+                options.chan_width,
+                options.flag_policy.history_message()
+            ),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Work out how to keep every *step*th of the distinct values of *times*
+/// and average the kept ones in groups of *width*, returning the rows that
+/// make up each output row and the numbers of distinct times before and
+/// after. When *width* is above 1, rows are only combined if they have the
+/// same entry of *keys*.
+fn time_groups<K: Copy + Eq + std::hash::Hash>(
+    times: &[f64],
+    keys: &[K],
+    step: usize,
+    width: usize,
+) -> (Vec<Vec<u64>>, usize, usize) {
+    let mut distinct = times.to_vec();
+    distinct.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    distinct.dedup();
+
+    let kept: Vec<f64> = distinct.iter().step_by(step).cloned().collect();
+    let mut groups: Vec<Vec<u64>> = Vec::new();
+    let mut group_index = HashMap::new();
+
+    for (row, t) in times.iter().enumerate() {
+        let bin = match kept
+            .binary_search_by(|k| k.partial_cmp(t).unwrap_or(std::cmp::Ordering::Equal))
+        {
+            Ok(i) => i / width,
+            Err(_) => continue,
+        };
+
+        if width == 1 {
+            groups.push(vec![row as u64]);
+            continue;
+        }
+
+        let n_groups = groups.len();
+        let index = *group_index.entry((bin, keys[row])).or_insert(n_groups);
+
+        if index == n_groups {
+            groups.push(Vec::new());
+        }
+
+        groups[index].push(row as u64);
+    }
+
+    (groups, distinct.len(), (kept.len() + width - 1) / width)
+}
+
+/// The visibilities, flags, and weights of one row, each of shape
+/// `(n_chan, n_corr)`.
+struct RowData {
+    data: Array2<Complex<f32>>,
+    flags: Array2<bool>,
+    weights: Array2<f32>,
+}
+
+/// Average the channels of some rows of the same shape in groups of
+/// *width*, and the rows together, returning the averaged data and flags
+/// and the new `WEIGHT` of each correlation.
+fn average_rows(
+    rows: &[RowData],
+    width: usize,
+    policy: FlagPolicy,
+) -> (Array2<Complex<f32>>, Array2<bool>, Vec<f32>) {
+    let (n_chan, n_corr) = rows[0].data.dim();
+    let n_out = n_chan / width;
+    let n_bins = n_out * n_corr;
+    let mut averager = BinAverager::new(n_bins);
+    let mut d = vec![Complex::new(0., 0.); n_bins];
+    let mut w = vec![0.; n_bins];
+    let mut f = vec![false; n_bins];
+
+    for row in rows {
+        for k in 0..width {
+            for out_chan in 0..n_out {
+                let chan = out_chan * width + k;
+
+                for corr in 0..n_corr {
+                    let i = out_chan * n_corr + corr;
+                    d[i] = row.data[[chan, corr]];
+                    w[i] = row.weights[[chan, corr]];
+                    f[i] = row.flags[[chan, corr]];
+                }
+            }
+
+            averager.add(&d, &w, &f);
+        }
+    }
+
+    let bins = averager.finish(policy);
+    let mut row_weights = vec![0.; n_corr];
+    let mut n_good = vec![0; n_corr];
+
+    for (i, (&wt, &flag)) in bins.weights.iter().zip(&bins.flags).enumerate() {
+        if !flag {
+            row_weights[i % n_corr] += wt;
+            n_good[i % n_corr] += 1;
+        }
+    }
+
+    for (wt, n) in row_weights.iter_mut().zip(n_good) {
+        if n > 0 {
+            *wt /= n as f32;
+        }
+    }
+
+    (
+        Array2::from_shape_vec((n_out, n_corr), bins.data).unwrap(),
+        Array2::from_shape_vec((n_out, n_corr), bins.flags).unwrap(),
+        row_weights,
+    )
+}
+
+/// Rebin the channels of every spectral window of the `SPECTRAL_WINDOW`
+/// table at *path* in groups of *width*.
+fn rebin_spectral_windows(path: &Path, width: usize) -> Result<(), Error> {
+    let mut spw = Table::open(path, TableOpenMode::ReadWrite)?;
+
+    for row in 0..spw.n_rows() {
+        let freqs = spw.get_cell_as_vec::<f64>("CHAN_FREQ", row)?;
+        let mean_freqs: Vec<f64> = freqs
+            .chunks(width)
+            .map(|c| c.iter().sum::<f64>() / c.len() as f64)
+            .collect();
+        spw.put_cell("CHAN_FREQ", row, &mean_freqs)?;
+
+        for col_name in &["CHAN_WIDTH", "EFFECTIVE_BW", "RESOLUTION"] {
+            let values = spw.get_cell_as_vec::<f64>(col_name, row)?;
+            let sums: Vec<f64> = values.chunks(width).map(|c| c.iter().sum()).collect();
+            spw.put_cell(col_name, row, &sums)?;
+        }
+
+        spw.put_cell("NUM_CHAN", row, &(mean_freqs.len() as i32))?;
+    }
+
+    spw.flush(true)?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn decimation() {
+    let times = [10., 10., 20., 20., 30., 40., 40., 50.];
+    let no_keys: &[u8] = &[];
+    let (groups, n_in, n_out) = time_groups(&times, no_keys, 2, 1);
+    assert_eq!(groups, vec![vec![0], vec![1], vec![4], vec![7]]);
+    assert_eq!((n_in, n_out), (5, 3));
+
+    let keys = ['a', 'b', 'a', 'b', 'a', 'a', 'b', 'b'];
+    let (groups, n_in, n_out) = time_groups(&times, &keys, 1, 2);
+    assert_eq!(
+        groups,
+        vec![vec![0, 2], vec![1, 3], vec![4, 5], vec![6], vec![7]]
+    );
+    assert_eq!((n_in, n_out), (5, 3));
+
+    let c = |re: f32| Complex::new(re, 0.);
+    let row = RowData {
+        data: Array2::from_shape_vec(
+            (4, 2),
+            vec![c(1.), c(10.), c(3.), c(20.), c(5.), c(30.), c(7.), c(40.)],
+        )
+        .unwrap(),
+        flags: Array2::from_elem((4, 2), false),
+        weights: Array2::from_elem((4, 2), 2.),
+    };
+    let mut flagged = RowData {
+        data: row.data.clone(),
+        flags: row.flags.clone(),
+        weights: row.weights.clone(),
+    };
+    flagged.flags[[3, 1]] = true;
+
+    let (d, f, w) = average_rows(&[flagged], 2, FlagPolicy::Renormalize);
+    assert_eq!(d.into_raw_vec(), vec![c(2.), c(15.), c(6.), c(30.)]);
+    assert!(f.iter().all(|&f| !f));
+    assert_eq!(w, vec![4., 3.]);
+
+    let mut flagged = RowData {
+        data: row.data.clone(),
+        flags: row.flags.clone(),
+        weights: row.weights.clone(),
+    };
+    flagged.flags[[3, 1]] = true;
+    let (_, f, w) = average_rows(&[flagged], 2, FlagPolicy::Drop);
+    assert_eq!(f.into_raw_vec(), vec![false, false, false, true]);
+    assert_eq!(w, vec![4., 4.]);
+
+    let mut later = RowData {
+        data: row.data.mapv(|v| v * 3.),
+        flags: row.flags.clone(),
+        weights: row.weights.clone(),
+    };
+    later.flags[[0, 0]] = true;
+    let (d, f, w) = average_rows(&[row, later], 1, FlagPolicy::Renormalize);
+    assert_eq!(
+        d.into_raw_vec(),
+        vec![c(1.), c(20.), c(6.), c(40.), c(10.), c(60.), c(14.), c(80.)]
+    );
+    assert!(f.iter().all(|&f| !f));
+    assert_eq!(w, vec![3.5, 4.]);
+}
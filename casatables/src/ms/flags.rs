@@ -0,0 +1,243 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Saved versions of the flags of Measurement Sets.
+
+Like CASA's `flagmanager` task, this module saves copies of the `FLAG` and
+`FLAG_ROW` columns of a main table under a name, so that the effects of a
+flagging step can be undone by restoring the flags saved before it. This
+makes it safe to experiment with flaggers such as
+`chanflag::flag_channels`.
+
+The versions are kept next to the Measurement Set, in the same layout as
+CASA uses: for `foo.ms`, the directory `foo.ms.flagversions` holds a table
+named `flags.NAME` for each version, and a text file `FLAG_VERSION_LIST`
+listing the versions in the order that they were saved, one `NAME :
+COMMENT` per line. The versions are therefore interchangeable with those
+saved by CASA.
+
+*/
+
+use failure::{err_msg, Error};
+use ndarray::Array2;
+use rubbl_core::output::OutputPolicy;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use super::super::{GlueDataType, Table, TableOpenMode};
+
+/// The name of the file listing the saved versions.
+const VERSION_LIST: &str = "FLAG_VERSION_LIST";
+
+/// A saved version of the flags of a Measurement Set.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FlagVersion {
+    /// The name of the version.
+    pub name: String,
+
+    /// A description of the version, which may be empty.
+    pub comment: String,
+}
+
+/// List the saved flag versions of the main table *ms*, in the order that
+/// they were saved.
+pub fn list_versions(ms: &Table) -> Result<Vec<FlagVersion>, Error> {
+    read_version_list(&ms.path)
+}
+
+/// List the saved flag versions of the table at *ms_path*.
+fn read_version_list(ms_path: &Path) -> Result<Vec<FlagVersion>, Error> {
+    let path = versions_dir(ms_path).join(VERSION_LIST);
+
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(parse_version_list(&text)),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(err_msg(format!(
+            "failed to read \"{}\": {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+/// Save the `FLAG` and `FLAG_ROW` columns of the main table *ms* as the
+/// flag version *name*, with the description *comment*. A version that
+/// already has that name is replaced.
+pub fn save_version(ms: &mut Table, name: &str, comment: &str) -> Result<(), Error> {
+    check_name(name)?;
+
+    let dir = versions_dir(&ms.path);
+    fs::create_dir_all(&dir)?;
+
+    let mut versions = list_versions(ms)?;
+    let table_path = dir.join(format!("flags.{}", name));
+
+    if versions.iter().any(|v| v.name == name) || table_path.exists() {
+        Table::delete(&table_path)?;
+    }
+
+    let flag_desc = ms.get_col_desc("FLAG")?;
+    let mut backup =
+        Table::create_with_scalar_columns(&table_path, &[("FLAG_ROW", GlueDataType::TpBool)], 0)?;
+    backup.add_array_column(
+        "FLAG",
+        GlueDataType::TpBool,
+        if flag_desc.is_fixed_shape() {
+            flag_desc.shape()
+        } else {
+            None
+        },
+    )?;
+    copy_flags(ms, &mut backup)?;
+    backup.flush(true)?;
+
+    versions.retain(|v| v.name != name);
+    versions.push(FlagVersion {
+        name: name.to_owned(),
+        comment: comment.to_owned(),
+    });
+    write_version_list(&dir, &versions)
+}
+
+/// Replace the `FLAG` and `FLAG_ROW` columns of the Measurement Set at
+/// *input* with its flag version *name*, with the result going where
+/// *output* says. The version itself is kept, and stays with the input.
+pub fn restore_version<P: AsRef<Path>>(
+    input: P,
+    output: &OutputPolicy,
+    name: &str,
+) -> Result<(), Error> {
+    let input = input.as_ref();
+    let table_path = version_table(input, name)?;
+    let mut backup = Table::open(&table_path, TableOpenMode::Read)?;
+
+    Table::modify(input, output, |ms| {
+        if backup.n_rows() != ms.n_rows() {
+            return Err(err_msg(format!(
+                "the flag version \"{}\" has {} rows, but the table has {}",
+                name,
+                backup.n_rows(),
+                ms.n_rows()
+            )));
+        }
+
+        copy_flags(&mut backup, ms)
+    })
+}
+
+/// Delete the flag version *name* of the main table *ms*.
+pub fn delete_version(ms: &Table, name: &str) -> Result<(), Error> {
+    let table_path = version_table(&ms.path, name)?;
+    Table::delete(&table_path)?;
+
+    let mut versions = list_versions(ms)?;
+    versions.retain(|v| v.name != name);
+    write_version_list(&versions_dir(&ms.path), &versions)
+}
+
+/// Get the path of the directory holding the flag versions of the table at
+/// *ms_path*.
+fn versions_dir(ms_path: &Path) -> PathBuf {
+    // Going through the components drops any trailing slash.
+    let mut dir = ms_path.components().as_path().as_os_str().to_owned();
+    dir.push(".flagversions");
+    PathBuf::from(dir)
+}
+
+/// Get the path of the table holding the saved flag version *name* of the
+/// table at *ms_path*, checking that it exists.
+fn version_table(ms_path: &Path, name: &str) -> Result<PathBuf, Error> {
+    if !read_version_list(ms_path)?.iter().any(|v| v.name == name) {
+        return Err(err_msg(format!("there is no flag version \"{}\"", name)));
+    }
+
+    Ok(versions_dir(ms_path).join(format!("flags.{}", name)))
+}
+
+/// Check that *name* can be used as the name of a flag version: it becomes
+/// part of a file name, and the version list is delimited by colons.
+fn check_name(name: &str) -> Result<(), Error> {
+    if name.is_empty()
+        || name
+            .chars()
+            .any(|c| c == '/' || c == ':' || c.is_whitespace())
+    {
+        return Err(err_msg(format!("invalid flag version name \"{}\"", name)));
+    }
+
+    Ok(())
+}
+
+/// Copy the `FLAG` and `FLAG_ROW` columns of *source* to *dest*, starting
+/// at the first row, adding rows to *dest* if it has fewer.
+fn copy_flags(source: &mut Table, dest: &mut Table) -> Result<(), Error> {
+    let row_flags = source.get_col_as_vec::<bool>("FLAG_ROW")?;
+    dest.put_col_from_iter("FLAG_ROW", row_flags)?;
+
+    for row in 0..source.n_rows() {
+        let flags: Array2<bool> = source.get_cell("FLAG", row)?;
+        dest.put_cell("FLAG", row, &flags)?;
+    }
+
+    Ok(())
+}
+
+fn parse_version_list(text: &str) -> Vec<FlagVersion> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut pieces = line.splitn(2, ':');
+            FlagVersion {
+                name: pieces.next().unwrap_or("").trim().to_owned(),
+                comment: pieces.next().unwrap_or("").trim().to_owned(),
+            }
+        })
+        .collect()
+}
+
+fn write_version_list(dir: &Path, versions: &[FlagVersion]) -> Result<(), Error> {
+    let mut text = String::new();
+
+    for v in versions {
+        text.push_str(&format!("{} : {}\n", v.name, v.comment));
+    }
+
+    fs::write(dir.join(VERSION_LIST), text)?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn version_list() {
+    let versions =
+        parse_version_list("Original : Original flags at import\n\nrfi : after: aoflagger\nbare\n");
+    assert_eq!(
+        versions,
+        vec![
+            FlagVersion {
+                name: "Original".to_owned(),
+                comment: "Original flags at import".to_owned(),
+            },
+            FlagVersion {
+                name: "rfi".to_owned(),
+                comment: "after: aoflagger".to_owned(),
+            },
+            FlagVersion {
+                name: "bare".to_owned(),
+                comment: String::new(),
+            },
+        ]
+    );
+
+    assert_eq!(
+        versions_dir(Path::new("data/foo.ms/")),
+        Path::new("data/foo.ms.flagversions")
+    );
+    assert!(check_name("before_chanflag").is_ok());
+    assert!(check_name("").is_err());
+    assert!(check_name("a b").is_err());
+    assert!(check_name("../x").is_err());
+}
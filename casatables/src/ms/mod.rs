@@ -0,0 +1,463 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Operations on the visibilities of Measurement Sets.
+
+`subtract_model` and `divide_by_model` combine the `DATA` and `MODEL_DATA`
+columns of a main table into its `CORRECTED_DATA` column. They are the
+building blocks of peeling, where a model of a source is subtracted from
+the data, and of self-calibration, where the data are divided by the model
+so that what remains is the antenna gains. The table is processed a chunk
+of rows at a time, sized according to a `MemoryBudget`, and an
+`OutputPolicy` says whether to modify it in place or to write a modified
+copy.
+
+The `CORRECTED_DATA` column must already exist. `DATA` itself is never
+modified, but the operations take account of the flags:
+
+- Subtraction is done for every sample, and leaves the flags and weights
+  unchanged, since the model contributes no noise.
+- In division, samples that are flagged, or where the model is zero or not
+  finite, are set to zero and flagged. Dividing by the model scales the
+  noise of each sample by 1/|*M*|, so the weights are multiplied by |*M*|²:
+  each element of `WEIGHT_SPECTRUM`, if the table has that column, and each
+  element of `WEIGHT` by the mean of |*M*|² over the unflagged channels of
+  its correlation.
+
+The module also has helpers that read metadata out of a Measurement Set.
+They return the newtypes of `rubbl_core::units` rather than bare `f64`s, so
+that a time in MJD seconds cannot be mistaken for one in days, nor an angle
+in radians for one in degrees.
+
+`add_history` records what was done to a Measurement Set in its `HISTORY`
+subtable, where CASA's `listhistory` task shows it. Tools should use it for
+choices that change the science, such as the flag policy used when
+averaging (see `rubbl_visdata::average`).
+
+Finally, `tag_standard_measures` attaches the units and measures metadata
+that the Measurement Set definition prescribes to the standard columns of a
+table, so that tables written from scratch satisfy CASA's stricter tools.
+
+The `append` submodule adds new epochs of observations to an existing
+Measurement Set, the `consistency` submodule checks that two MSs describe
+their spectral windows and antennas in the same way before their data are
+combined, the `decimate` submodule makes small quick-look copies of an MS
+and averages its data in time and frequency, the `flags` submodule saves
+and restores versions of its flags, and the `timeindex` submodule indexes
+its rows by time and scan so that they can be found without reading the
+whole `TIME` column.
+
+*/
+
+pub mod append;
+pub mod consistency;
+pub mod decimate;
+pub mod flags;
+pub mod timeindex;
+
+use failure::{err_msg, Error};
+use ndarray::Array2;
+use rubbl_core::budget::MemoryBudget;
+use rubbl_core::output::OutputPolicy;
+use rubbl_core::time::unix_to_mjd_seconds;
+use rubbl_core::units::{Hz, MjdSeconds, Radians};
+use rubbl_core::Complex;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{CasaScalarData, ColumnMeasure, Table, TableOpenMode};
+
+/// How the model is combined with the data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ModelOp {
+    Subtract,
+    Divide,
+}
+
+/// Write `DATA − MODEL_DATA` into the `CORRECTED_DATA` column of the
+/// Measurement Set at *input*, with the result going where *output* says.
+pub fn subtract_model<P: AsRef<Path>>(
+    input: P,
+    output: &OutputPolicy,
+    budget: &MemoryBudget,
+) -> Result<(), Error> {
+    Table::modify(input, output, |ms| {
+        apply_model(ms, budget, ModelOp::Subtract)
+    })
+}
+
+/// Write `DATA / MODEL_DATA` into the `CORRECTED_DATA` column of the
+/// Measurement Set at *input*, with the result going where *output* says,
+/// updating its flags and weights as described in the module documentation.
+pub fn divide_by_model<P: AsRef<Path>>(
+    input: P,
+    output: &OutputPolicy,
+    budget: &MemoryBudget,
+) -> Result<(), Error> {
+    Table::modify(input, output, |ms| apply_model(ms, budget, ModelOp::Divide))
+}
+
+fn apply_model(ms: &mut Table, budget: &MemoryBudget, op: ModelOp) -> Result<(), Error> {
+    for col_name in &["DATA", "MODEL_DATA", "CORRECTED_DATA", "FLAG"] {
+        if !ms.has_column(col_name)? {
+            return Err(err_msg(format!(
+                "the Measurement Set has no {} column",
+                col_name
+            )));
+        }
+    }
+
+    let weight_spectrum = op == ModelOp::Divide && ms.has_column("WEIGHT_SPECTRUM")?;
+    let mut col_names = vec!["DATA", "MODEL_DATA", "CORRECTED_DATA", "FLAG"];
+
+    if op == ModelOp::Divide {
+        col_names.push("WEIGHT");
+
+        if weight_spectrum {
+            col_names.push("WEIGHT_SPECTRUM");
+        }
+    }
+
+    let n_rows = ms.n_rows();
+    let rows_per_chunk = budget.rows_per_chunk(ms.row_width(&col_names)?, n_rows);
+    let mut start_row = 0;
+
+    while start_row < n_rows {
+        let n = std::cmp::min(rows_per_chunk, n_rows - start_row);
+        let shapes = (start_row..start_row + n)
+            .map(|row| ms.get_cell_shape("DATA", row))
+            .collect::<Result<Vec<_>, _>>()?;
+        let sizes = shapes
+            .iter()
+            .map(|s| s.iter().product::<u64>() as usize)
+            .collect::<Vec<_>>();
+
+        let data = read_cells::<Complex<f32>>(ms, "DATA", start_row, &sizes)?;
+        let model = read_cells::<Complex<f32>>(ms, "MODEL_DATA", start_row, &sizes)?;
+        let mut flags = read_cells::<bool>(ms, "FLAG", start_row, &sizes)?;
+        let mut spectra = if weight_spectrum {
+            Some(read_cells::<f32>(ms, "WEIGHT_SPECTRUM", start_row, &sizes)?)
+        } else {
+            None
+        };
+
+        let mut offset = 0;
+
+        for (i, shape) in shapes.iter().enumerate() {
+            let row = start_row + i as u64;
+            let cell = offset..offset + sizes[i];
+            offset = cell.end;
+
+            if shape.len() != 2 {
+                return Err(err_msg(format!(
+                    "expected a two-dimensional DATA cell in row {}; got shape {:?}",
+                    row, shape
+                )));
+            }
+
+            let n_corr = shape[1] as usize;
+            let mut result = data[cell.clone()].to_vec();
+            let scales = combine(
+                op,
+                n_corr,
+                &mut result,
+                &model[cell.clone()],
+                &mut flags[cell.clone()],
+                spectra.as_mut().map(|s| &mut s[cell.clone()]),
+            );
+
+            let dims = (shape[0] as usize, n_corr);
+            ms.put_cell(
+                "CORRECTED_DATA",
+                row,
+                &Array2::from_shape_vec(dims, result)?,
+            )?;
+
+            if op == ModelOp::Divide {
+                ms.put_cell(
+                    "FLAG",
+                    row,
+                    &Array2::from_shape_vec(dims, flags[cell.clone()].to_vec())?,
+                )?;
+
+                if let Some(ref s) = spectra {
+                    ms.put_cell(
+                        "WEIGHT_SPECTRUM",
+                        row,
+                        &Array2::from_shape_vec(dims, s[cell.clone()].to_vec())?,
+                    )?;
+                }
+
+                let mut weight = ms.get_cell_as_vec::<f32>("WEIGHT", row)?;
+
+                if weight.len() != n_corr {
+                    return Err(err_msg(format!(
+                        "expected {} WEIGHT values in row {}; got {}",
+                        n_corr,
+                        row,
+                        weight.len()
+                    )));
+                }
+
+                for (w, scale) in weight.iter_mut().zip(scales) {
+                    if let Some(s) = scale {
+                        *w *= s;
+                    }
+                }
+
+                ms.put_cell("WEIGHT", row, &weight)?;
+            }
+        }
+
+        start_row += n;
+    }
+
+    Ok(())
+}
+
+/// Get the earliest and latest values of the `TIME` column of the main
+/// table *ms*, or `None` if it has no rows.
+pub fn time_range(ms: &mut Table) -> Result<Option<(MjdSeconds, MjdSeconds)>, Error> {
+    let times = ms.get_col_as_vec::<f64>("TIME")?;
+
+    Ok(times
+        .into_iter()
+        .fold(None, |range, t| {
+            Some(match range {
+                Some((t0, t1)) => (f64::min(t0, t), f64::max(t1, t)),
+                None => (t, t),
+            })
+        })
+        .map(|(t0, t1)| (MjdSeconds(t0), MjdSeconds(t1))))
+}
+
+/// Get the frequencies of the channels of spectral window *spw_id* of the
+/// Measurement Set at *path*.
+pub fn channel_frequencies<P: AsRef<Path>>(path: P, spw_id: u64) -> Result<Vec<Hz>, Error> {
+    let mut spw = Table::open(path.as_ref().join("SPECTRAL_WINDOW"), TableOpenMode::Read)?;
+
+    if spw_id >= spw.n_rows() {
+        return Err(err_msg(format!(
+            "the Measurement Set has no spectral window {}",
+            spw_id
+        )));
+    }
+
+    Ok(spw
+        .get_cell_as_vec::<f64>("CHAN_FREQ", spw_id)?
+        .into_iter()
+        .map(Hz)
+        .collect())
+}
+
+/// Get the phase center of field *field_id* of the Measurement Set at
+/// *path*, as a longitude and latitude in the field's reference frame
+/// (usually right ascension and declination). Only the zeroth-order term of
+/// the `PHASE_DIR` polynomial is used.
+pub fn phase_center<P: AsRef<Path>>(path: P, field_id: u64) -> Result<(Radians, Radians), Error> {
+    let mut field = Table::open(path.as_ref().join("FIELD"), TableOpenMode::Read)?;
+
+    if field_id >= field.n_rows() {
+        return Err(err_msg(format!(
+            "the Measurement Set has no field {}",
+            field_id
+        )));
+    }
+
+    let dir = field.get_cell_as_vec::<f64>("PHASE_DIR", field_id)?;
+
+    if dir.len() < 2 {
+        return Err(err_msg(format!(
+            "the PHASE_DIR cell of field {} is malformed",
+            field_id
+        )));
+    }
+
+    Ok((Radians(dir[0]), Radians(dir[1])))
+}
+
+/// Add a message to the `HISTORY` subtable of the Measurement Set at
+/// *path*, attributed to *application*, such as the name of the tool that
+/// processed it. The message is stamped with the current time.
+pub fn add_history<P: AsRef<Path>>(path: P, application: &str, message: &str) -> Result<(), Error> {
+    let mut history = Table::open(path.as_ref().join("HISTORY"), TableOpenMode::ReadWrite)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.);
+    let row = history.n_rows();
+    history.add_rows(1)?;
+
+    history.put_cell("TIME", row, &unix_to_mjd_seconds(now))?;
+    history.put_cell("OBSERVATION_ID", row, &-1i32)?;
+    history.put_cell("MESSAGE", row, &message.to_owned())?;
+    history.put_cell("PRIORITY", row, &"NORMAL".to_owned())?;
+    history.put_cell("ORIGIN", row, &application.to_owned())?;
+    history.put_cell("OBJECT_ID", row, &-1i32)?;
+    history.put_cell("APPLICATION", row, &application.to_owned())?;
+    history.flush(true)?;
+    Ok(())
+}
+
+/// Get the measures metadata that the Measurement Set definition prescribes
+/// for columns named *col_name*, in the main table or any of the standard
+/// subtables, or `None` if it is not a standard column with units.
+///
+/// Columns whose reference frame is given by another column, such as
+/// `CHAN_FREQ`, whose frame is given by `MEAS_FREQ_REF`, only get units.
+pub fn standard_column_measure(col_name: &str) -> Option<ColumnMeasure> {
+    Some(match col_name {
+        "TIME" | "TIME_CENTROID" => ColumnMeasure::epoch("UTC"),
+        "UVW" => ColumnMeasure::uvw("J2000"),
+        "POSITION" | "OFFSET" => ColumnMeasure::position("ITRF"),
+        "PHASE_DIR" | "DELAY_DIR" | "REFERENCE_DIR" | "DIRECTION" | "TARGET" => {
+            ColumnMeasure::direction("J2000")
+        }
+        "INTERVAL" | "EXPOSURE" => ColumnMeasure::quantity(&["s"]),
+        "DISH_DIAMETER" => ColumnMeasure::quantity(&["m"]),
+        "CHAN_FREQ" | "REF_FREQUENCY" | "CHAN_WIDTH" | "EFFECTIVE_BW" | "RESOLUTION"
+        | "TOTAL_BANDWIDTH" => ColumnMeasure::quantity(&["Hz"]),
+        _ => return None,
+    })
+}
+
+/// Attach the standard measures metadata, as given by
+/// `standard_column_measure`, to each column of *table* that should have it
+/// but has neither units nor a measure type. Returns the number of columns
+/// tagged.
+pub fn tag_standard_measures(table: &mut Table) -> Result<usize, Error> {
+    let mut n_tagged = 0;
+
+    for col_name in table.column_names()? {
+        if let Some(measure) = standard_column_measure(&col_name) {
+            if table.column_measure(&col_name)?.is_none() {
+                table.set_column_measure(&col_name, &measure)?;
+                n_tagged += 1;
+            }
+        }
+    }
+
+    Ok(n_tagged)
+}
+
+/// Read the cells of an array column starting at *start_row*, one per
+/// element of *sizes*, into one flat vector, checking that each cell has
+/// the given number of elements.
+fn read_cells<T: CasaScalarData>(
+    ms: &mut Table,
+    col_name: &str,
+    start_row: u64,
+    sizes: &[usize],
+) -> Result<Vec<T>, Error> {
+    let total = sizes.iter().sum::<usize>();
+
+    if ms.get_col_desc(col_name)?.is_fixed_shape() {
+        let values = ms.get_col_range_as_vec(col_name, start_row, sizes.len() as u64)?;
+
+        if values.len() == total {
+            return Ok(values);
+        }
+
+        return Err(err_msg(format!(
+            "the cells of the {} column do not match those of the DATA column",
+            col_name
+        )));
+    }
+
+    let mut values = Vec::with_capacity(total);
+
+    for (i, &size) in sizes.iter().enumerate() {
+        let row = start_row + i as u64;
+        let cell = ms.get_cell_as_vec(col_name, row)?;
+
+        if cell.len() != size {
+            return Err(err_msg(format!(
+                "the {} cell in row {} does not match the DATA cell",
+                col_name, row
+            )));
+        }
+
+        values.extend(cell);
+    }
+
+    Ok(values)
+}
+
+/// Combine one cell of the model with the data in place. The cells are in
+/// C order with *n_corr* correlations per channel.
+///
+/// For division, returns the factor by which to multiply the `WEIGHT` of
+/// each correlation, or `None` if all of its channels are flagged.
+fn combine(
+    op: ModelOp,
+    n_corr: usize,
+    data: &mut [Complex<f32>],
+    model: &[Complex<f32>],
+    flags: &mut [bool],
+    mut weight_spectrum: Option<&mut [f32]>,
+) -> Vec<Option<f32>> {
+    let mut sums = vec![(0f32, 0usize); n_corr];
+
+    for i in 0..data.len() {
+        match op {
+            ModelOp::Subtract => {
+                data[i] -= model[i];
+            }
+
+            ModelOp::Divide => {
+                let m2 = model[i].norm_sqr();
+
+                if flags[i] || !(m2.is_finite() && m2 > 0.) {
+                    data[i] = Complex::new(0., 0.);
+                    flags[i] = true;
+                } else {
+                    data[i] /= model[i];
+                    sums[i % n_corr].0 += m2;
+                    sums[i % n_corr].1 += 1;
+                }
+
+                if let Some(ref mut w) = weight_spectrum {
+                    w[i] = if flags[i] { 0. } else { w[i] * m2 };
+                }
+            }
+        }
+    }
+
+    sums.into_iter()
+        .map(|(sum, n)| if n > 0 { Some(sum / n as f32) } else { None })
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn model_combination() {
+    let c = |re, im| Complex::new(re, im);
+    let model = [c(2., 0.), c(0., 1.), c(0., 0.), c(1., 1.)];
+
+    let mut data = [c(3., 1.), c(1., 1.), c(1., 0.), c(2., 2.)];
+    let mut flags = [false, false, false, true];
+    let scales = combine(ModelOp::Subtract, 2, &mut data, &model, &mut flags, None);
+    assert_eq!(data, [c(1., 1.), c(1., 0.), c(1., 0.), c(1., 1.)]);
+    assert_eq!(flags, [false, false, false, true]);
+    assert_eq!(scales, vec![None, None]);
+
+    // Two channels of two correlations: the zero model value and the
+    // flagged sample both end up flagged.
+    let mut data = [c(4., 2.), c(1., 1.), c(1., 0.), c(2., 2.)];
+    let mut flags = [false, false, false, true];
+    let mut weights = [1., 1., 1., 1.];
+    let scales = combine(
+        ModelOp::Divide,
+        2,
+        &mut data,
+        &model,
+        &mut flags,
+        Some(&mut weights),
+    );
+    assert_eq!(data, [c(2., 1.), c(1., -1.), c(0., 0.), c(0., 0.)]);
+    assert_eq!(flags, [false, false, true, true]);
+    assert_eq!(weights, [4., 1., 0., 0.]);
+    assert_eq!(scales, vec![Some(4.), Some(1.)]);
+}
@@ -0,0 +1,292 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+An index of the rows of Measurement Sets by time and scan.
+
+Tools often need the rows of a particular scan, or those between two times.
+Finding them means reading the whole `TIME` or `SCAN_NUMBER` column, which
+for a large Measurement Set takes a noticeable time on every invocation. A
+`TimeIndex` records, once, how the rows of the main table are laid out in
+time: it divides them into runs of consecutive rows spanning no more than a
+fixed *bin width* in time, and it lists the row ranges of each scan.
+Queries then only need to read the `TIME` values of the runs at the edges
+of the requested interval.
+
+The index is saved in the sidecar of the Measurement Set (see
+`rubbl_core::sidecar`) under the key `casatables.time_index`, along with
+the number of rows and the modification time of the table's files. It is
+only used while these still match, so that an index cannot silently go
+stale when the table is modified: `TimeIndex::load_or_build` rebuilds it
+when needed.
+
+*/
+
+use failure::{err_msg, Error};
+use rubbl_core::sidecar::Sidecar;
+use rubbl_core::units::{MjdSeconds, Seconds};
+use std::collections::BTreeMap;
+use std::f64;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use super::super::Table;
+
+/// The sidecar key under which the index is stored.
+pub const SIDECAR_KEY: &str = "casatables.time_index";
+
+/// The default maximum span of the `TIME` values within one run.
+pub const DEFAULT_BIN_WIDTH: Seconds = Seconds(60.);
+
+/// A run of consecutive rows whose times all lie within one bin width.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct TimeBin {
+    start_row: u64,
+    end_row: u64,
+    t_min: f64,
+    t_max: f64,
+}
+
+/// An index of the rows of a Measurement Set main table by time and scan.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TimeIndex {
+    n_rows: u64,
+    stamp: (u64, u32),
+    bin_width: f64,
+    bins: Vec<TimeBin>,
+    scans: BTreeMap<i32, Vec<(u64, u64)>>,
+}
+
+impl TimeIndex {
+    /// Build the index of the main table *ms* from scratch, with runs
+    /// spanning at most *bin_width*. The index is not saved.
+    pub fn build(ms: &mut Table, bin_width: Seconds) -> Result<Self, Error> {
+        let bin_width = bin_width.0;
+
+        if bin_width.is_nan() || bin_width <= 0. {
+            return Err(err_msg(format!(
+                "invalid time index bin width {}",
+                bin_width
+            )));
+        }
+
+        let times = ms.get_col_as_vec::<f64>("TIME")?;
+        let scan_numbers = if ms.has_column("SCAN_NUMBER")? {
+            ms.get_col_as_vec::<i32>("SCAN_NUMBER")?
+        } else {
+            Vec::new()
+        };
+
+        let mut index = TimeIndex {
+            n_rows: ms.n_rows(),
+            stamp: table_stamp(&ms.path)?,
+            bin_width: bin_width,
+            bins: bin_times(&times, bin_width),
+            scans: BTreeMap::new(),
+        };
+
+        for (row, &scan) in scan_numbers.iter().enumerate() {
+            let row = row as u64;
+            let ranges = index.scans.entry(scan).or_default();
+
+            match ranges.last_mut() {
+                Some(r) if r.1 == row => r.1 = row + 1,
+                _ => ranges.push((row, row + 1)),
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Load the saved index of the main table *ms*, if there is one and it
+    /// is still up to date.
+    pub fn load_current(ms: &Table) -> Result<Option<Self>, Error> {
+        let index: Option<TimeIndex> = Sidecar::load(&ms.path)?.get(SIDECAR_KEY)?;
+
+        match index {
+            Some(index) if index.is_current(ms)? => Ok(Some(index)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Load the saved index of the main table *ms*, building and saving a
+    /// new one with the default bin width if there is none or it is out of
+    /// date.
+    pub fn load_or_build(ms: &mut Table) -> Result<Self, Error> {
+        if let Some(index) = Self::load_current(ms)? {
+            return Ok(index);
+        }
+
+        let index = Self::build(ms, DEFAULT_BIN_WIDTH)?;
+        index.save(ms)?;
+        Ok(index)
+    }
+
+    /// Save this index in the sidecar of the main table *ms*.
+    pub fn save(&self, ms: &Table) -> Result<(), Error> {
+        Sidecar::update(&ms.path, |sc| sc.set(SIDECAR_KEY, self))
+    }
+
+    /// Test whether this index still describes the main table *ms*.
+    pub fn is_current(&self, ms: &Table) -> Result<bool, Error> {
+        Ok(self.n_rows == ms.n_rows() && self.stamp == table_stamp(&ms.path)?)
+    }
+
+    /// Get the earliest and latest times in the table, or `None` if it has
+    /// no rows.
+    pub fn time_range(&self) -> Option<(MjdSeconds, MjdSeconds)> {
+        if self.bins.is_empty() {
+            return None;
+        }
+
+        let (t0, t1) = self
+            .bins
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |r, b| {
+                (r.0.min(b.t_min), r.1.max(b.t_max))
+            });
+        Some((MjdSeconds(t0), MjdSeconds(t1)))
+    }
+
+    /// Get the scan numbers in the table, in increasing order.
+    pub fn scans(&self) -> Vec<i32> {
+        self.scans.keys().cloned().collect()
+    }
+
+    /// Get the ranges of rows belonging to scan *scan*, in increasing order.
+    /// The result is empty if there is no such scan.
+    pub fn scan_rows(&self, scan: i32) -> Vec<Range<u64>> {
+        match self.scans.get(&scan) {
+            Some(ranges) => ranges.iter().map(|r| r.0..r.1).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Get the ranges of rows of the main table *ms* whose times are between
+    /// *t1* and *t2* (inclusive), in increasing order. Only the `TIME`
+    /// values of runs that straddle the ends of the interval are read.
+    pub fn rows_between(
+        &self,
+        ms: &mut Table,
+        t1: MjdSeconds,
+        t2: MjdSeconds,
+    ) -> Result<Vec<Range<u64>>, Error> {
+        let (t1, t2) = (t1.0, t2.0);
+        let mut ranges: Vec<Range<u64>> = Vec::new();
+
+        let mut add = |r: Range<u64>| match ranges.last_mut() {
+            Some(last) if last.end == r.start => last.end = r.end,
+            _ => ranges.push(r),
+        };
+
+        for bin in &self.bins {
+            if bin.t_max < t1 || bin.t_min > t2 {
+                continue;
+            }
+
+            if bin.t_min >= t1 && bin.t_max <= t2 {
+                add(bin.start_row..bin.end_row);
+                continue;
+            }
+
+            let times =
+                ms.get_col_range_as_vec::<f64>("TIME", bin.start_row, bin.end_row - bin.start_row)?;
+
+            for (i, &t) in times.iter().enumerate() {
+                if t >= t1 && t <= t2 {
+                    let row = bin.start_row + i as u64;
+                    add(row..row + 1);
+                }
+            }
+        }
+
+        Ok(ranges)
+    }
+}
+
+/// Divide *times* into runs of consecutive values spanning at most
+/// *bin_width*.
+fn bin_times(times: &[f64], bin_width: f64) -> Vec<TimeBin> {
+    let mut bins: Vec<TimeBin> = Vec::new();
+
+    for (row, &t) in times.iter().enumerate() {
+        let row = row as u64;
+
+        if let Some(bin) = bins.last_mut() {
+            let t_min = bin.t_min.min(t);
+            let t_max = bin.t_max.max(t);
+
+            if t_max - t_min <= bin_width {
+                bin.end_row = row + 1;
+                bin.t_min = t_min;
+                bin.t_max = t_max;
+                continue;
+            }
+        }
+
+        bins.push(TimeBin {
+            start_row: row,
+            end_row: row + 1,
+            t_min: t,
+            t_max: t,
+        });
+    }
+
+    bins
+}
+
+/// Get the latest modification time of the files of the table at *path*,
+/// not counting its subtables, as seconds and nanoseconds since the Unix
+/// epoch.
+fn table_stamp(path: &Path) -> Result<(u64, u32), Error> {
+    let mut stamp = (0, 0);
+
+    for entry in fs::read_dir(path)? {
+        let meta = entry?.metadata()?;
+
+        if meta.is_file() {
+            let t = meta.modified()?.duration_since(UNIX_EPOCH)?;
+            stamp = ::std::cmp::max(stamp, (t.as_secs(), t.subsec_nanos()));
+        }
+    }
+
+    Ok(stamp)
+}
+
+#[cfg(test)]
+#[test]
+fn time_binning() {
+    let times = [0., 10., 10., 70., 75., 200., 130., 131.];
+    let bins = bin_times(&times, 60.);
+    let spans: Vec<_> = bins
+        .iter()
+        .map(|b| (b.start_row, b.end_row, b.t_min, b.t_max))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            (0, 3, 0., 10.),
+            (3, 5, 70., 75.),
+            (5, 6, 200., 200.),
+            (6, 8, 130., 131.),
+        ]
+    );
+    assert!(bin_times(&[], 60.).is_empty());
+
+    let index = TimeIndex {
+        n_rows: 8,
+        stamp: (0, 0),
+        bin_width: 60.,
+        bins: bins,
+        scans: vec![(1, vec![(0, 3)]), (2, vec![(3, 5), (6, 8)])]
+            .into_iter()
+            .collect(),
+    };
+    assert_eq!(index.time_range(), Some((MjdSeconds(0.), MjdSeconds(200.))));
+    assert_eq!(index.scans(), vec![1, 2]);
+    assert_eq!(index.scan_rows(2), vec![3..5, 6..8]);
+    assert!(index.scan_rows(3).is_empty());
+}
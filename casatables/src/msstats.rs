@@ -0,0 +1,411 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Summary statistics and quality checks of Measurement Sets.
+
+`MsStats::gather` reads the main table of a Measurement Set once and
+collects the numbers reported by the `rubbl msstat` and `rubbl flagstats`
+tools: the extent of the data in time, antennas, and data descriptions, the
+statistics of the amplitudes of the unflagged visibilities, and the
+fractions of the visibilities that are flagged, broken down by antenna,
+data description, and correlation. Each element of the `FLAG` column counts
+as one sample, and every sample of a row with `FLAG_ROW` set counts as
+flagged. A flagged sample of a baseline counts against both of its
+antennas.
+
+`MsStats::check` turns the statistics into the pass/fail checks reported by
+`rubbl qa`.
+
+*/
+
+use failure::{err_msg, Error};
+use rubbl_core::Complex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use super::{Table, TableOpenMode};
+
+/// Counts of flagged visibility samples.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct FlagCount {
+    /// The number of samples that are flagged.
+    pub n_flagged: u64,
+
+    /// The total number of samples.
+    pub n_total: u64,
+}
+
+impl FlagCount {
+    fn add(&mut self, flagged: bool) {
+        self.n_total += 1;
+
+        if flagged {
+            self.n_flagged += 1;
+        }
+    }
+
+    /// Get the fraction of the samples that are flagged, or zero if there
+    /// are none.
+    pub fn fraction(&self) -> f64 {
+        if self.n_total == 0 {
+            0.
+        } else {
+            self.n_flagged as f64 / self.n_total as f64
+        }
+    }
+}
+
+/// Statistics of the amplitudes of the unflagged, finite visibilities.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct AmplitudeStats {
+    /// The number of samples. The other values are zero if there are none.
+    pub n_samples: u64,
+
+    /// The smallest amplitude.
+    pub min: f64,
+
+    /// The largest amplitude.
+    pub max: f64,
+
+    /// The mean amplitude.
+    pub mean: f64,
+
+    /// The root-mean-square amplitude.
+    pub rms: f64,
+}
+
+/// Summary statistics of the main table of a Measurement Set.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MsStats {
+    /// The number of rows.
+    pub n_rows: u64,
+
+    /// The number of distinct values of `TIME`.
+    pub n_times: usize,
+
+    /// The earliest and latest values of `TIME`, in MJD seconds, or None if
+    /// there are no rows.
+    pub time_range: Option<(f64, f64)>,
+
+    /// The antennas that appear in the data.
+    pub antennas: Vec<i32>,
+
+    /// The number of distinct baselines between different antennas.
+    pub n_baselines: usize,
+
+    /// The data descriptions that appear in the data.
+    pub data_desc_ids: Vec<i32>,
+
+    /// The amplitudes of the visibilities.
+    pub amplitudes: AmplitudeStats,
+
+    /// The number of unflagged samples whose values are not finite.
+    pub n_nonfinite: u64,
+
+    /// The number of unflagged samples whose weights are not positive.
+    pub n_bad_weights: u64,
+
+    /// The flags of all of the samples.
+    pub flags: FlagCount,
+
+    /// The flags of the samples of each antenna.
+    pub flags_by_antenna: BTreeMap<i32, FlagCount>,
+
+    /// The flags of the samples of each data description.
+    pub flags_by_data_desc: BTreeMap<i32, FlagCount>,
+
+    /// The flags of the samples of each correlation, by index.
+    pub flags_by_correlation: Vec<FlagCount>,
+}
+
+/// The outcome of one quality check.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct QaCheck {
+    /// A short name for the check.
+    pub name: &'static str,
+
+    /// Whether the data passed it.
+    pub passed: bool,
+
+    /// What was found.
+    pub detail: String,
+}
+
+impl MsStats {
+    /// Gather the statistics of the Measurement Set at *path*, taking the
+    /// visibilities from the column *data_column*.
+    pub fn gather<P: AsRef<Path>>(path: P, data_column: &str) -> Result<Self, Error> {
+        let mut ms = Table::open(path, TableOpenMode::Read)?;
+        let times = ms.get_col_as_vec::<f64>("TIME")?;
+        let ant1 = ms.get_col_as_vec::<i32>("ANTENNA1")?;
+        let ant2 = ms.get_col_as_vec::<i32>("ANTENNA2")?;
+        let ddids = ms.get_col_as_vec::<i32>("DATA_DESC_ID")?;
+        let row_flags = ms.get_col_as_vec::<bool>("FLAG_ROW")?;
+        let mut acc = Accumulator::default();
+
+        for row in 0..ms.n_rows() {
+            let i = row as usize;
+            let data = ms.get_cell_as_vec::<Complex<f32>>(data_column, row)?;
+            let mut flags = ms.get_cell_as_vec::<bool>("FLAG", row)?;
+            let weights = ms.get_cell_as_vec::<f32>("WEIGHT", row)?;
+
+            if row_flags[i] {
+                for f in &mut flags {
+                    *f = true;
+                }
+            }
+
+            if data.len() != flags.len() || weights.is_empty() || data.len() % weights.len() != 0 {
+                return Err(err_msg(format!(
+                    "the {}, FLAG, and WEIGHT cells of row {} do not match",
+                    data_column, row
+                )));
+            }
+
+            acc.add_row(
+                times[i],
+                (ant1[i], ant2[i]),
+                ddids[i],
+                &data,
+                &flags,
+                &weights,
+            );
+        }
+
+        Ok(acc.finish())
+    }
+
+    /// Check the statistics for signs of trouble: no usable data, values
+    /// that are not finite, weights that are not positive, more than the
+    /// fraction *max_flagged* of the data flagged, or antennas that are
+    /// entirely flagged.
+    pub fn check(&self, max_flagged: f64) -> Vec<QaCheck> {
+        let dead: Vec<String> = self
+            .flags_by_antenna
+            .iter()
+            .filter(|&(_, c)| c.n_flagged == c.n_total)
+            .map(|(a, _)| a.to_string())
+            .collect();
+
+        vec![
+            QaCheck {
+                name: "data",
+                passed: self.amplitudes.n_samples > 0,
+                detail: format!("{} unflagged samples", self.amplitudes.n_samples),
+            },
+            QaCheck {
+                name: "finite",
+                passed: self.n_nonfinite == 0,
+                detail: format!("{} unflagged samples are not finite", self.n_nonfinite),
+            },
+            QaCheck {
+                name: "weights",
+                passed: self.n_bad_weights == 0,
+                detail: format!(
+                    "{} unflagged samples have weights that are not positive",
+                    self.n_bad_weights
+                ),
+            },
+            QaCheck {
+                name: "flagged",
+                passed: self.flags.fraction() <= max_flagged,
+                detail: format!(
+                    "{:.1}% of the samples are flagged; the limit is {:.1}%",
+                    100. * self.flags.fraction(),
+                    100. * max_flagged
+                ),
+            },
+            QaCheck {
+                name: "antennas",
+                passed: dead.is_empty(),
+                detail: if dead.is_empty() {
+                    "no antennas are entirely flagged".to_owned()
+                } else {
+                    format!("entirely flagged antennas: {}", dead.join(", "))
+                },
+            },
+        ]
+    }
+}
+
+/// Accumulates `MsStats` a row at a time.
+#[derive(Default)]
+struct Accumulator {
+    stats: MsStats,
+    times: BTreeSet<u64>,
+    baselines: BTreeSet<(i32, i32)>,
+    antennas: BTreeSet<i32>,
+    data_desc_ids: BTreeSet<i32>,
+    sum: f64,
+    sum_sq: f64,
+}
+
+impl Accumulator {
+    /// Add a row. The visibilities are in order of channel and then
+    /// correlation, with one weight per correlation.
+    fn add_row(
+        &mut self,
+        time: f64,
+        baseline: (i32, i32),
+        ddid: i32,
+        data: &[Complex<f32>],
+        flags: &[bool],
+        weights: &[f32],
+    ) {
+        let s = &mut self.stats;
+        let n_corr = weights.len();
+
+        s.n_rows += 1;
+        s.time_range = Some(match s.time_range {
+            Some((lo, hi)) => (lo.min(time), hi.max(time)),
+            None => (time, time),
+        });
+        self.times.insert(time.to_bits());
+        self.antennas.insert(baseline.0);
+        self.antennas.insert(baseline.1);
+        self.data_desc_ids.insert(ddid);
+
+        if baseline.0 != baseline.1 {
+            self.baselines
+                .insert((baseline.0.min(baseline.1), baseline.0.max(baseline.1)));
+        }
+
+        if s.flags_by_correlation.len() < n_corr {
+            s.flags_by_correlation.resize(n_corr, FlagCount::default());
+        }
+
+        for (i, (vis, &flagged)) in data.iter().zip(flags).enumerate() {
+            let corr = i % n_corr;
+
+            s.flags.add(flagged);
+            s.flags_by_correlation[corr].add(flagged);
+            s.flags_by_data_desc
+                .entry(ddid)
+                .or_insert_with(FlagCount::default)
+                .add(flagged);
+            s.flags_by_antenna
+                .entry(baseline.0)
+                .or_insert_with(FlagCount::default)
+                .add(flagged);
+
+            if baseline.1 != baseline.0 {
+                s.flags_by_antenna
+                    .entry(baseline.1)
+                    .or_insert_with(FlagCount::default)
+                    .add(flagged);
+            }
+
+            if flagged {
+                continue;
+            }
+
+            if weights[corr].is_nan() || weights[corr] <= 0. {
+                s.n_bad_weights += 1;
+            }
+
+            let amp = vis.norm() as f64;
+
+            if !amp.is_finite() {
+                s.n_nonfinite += 1;
+                continue;
+            }
+
+            let a = &mut s.amplitudes;
+
+            if a.n_samples == 0 {
+                a.min = amp;
+                a.max = amp;
+            } else {
+                a.min = a.min.min(amp);
+                a.max = a.max.max(amp);
+            }
+
+            a.n_samples += 1;
+            self.sum += amp;
+            self.sum_sq += amp * amp;
+        }
+    }
+
+    fn finish(mut self) -> MsStats {
+        let n = self.stats.amplitudes.n_samples;
+
+        if n > 0 {
+            self.stats.amplitudes.mean = self.sum / n as f64;
+            self.stats.amplitudes.rms = (self.sum_sq / n as f64).sqrt();
+        }
+
+        self.stats.n_times = self.times.len();
+        self.stats.antennas = self.antennas.into_iter().collect();
+        self.stats.n_baselines = self.baselines.len();
+        self.stats.data_desc_ids = self.data_desc_ids.into_iter().collect();
+        self.stats
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn accumulation() {
+    let c = |re: f32, im: f32| Complex::new(re, im);
+    let mut acc = Accumulator::default();
+
+    acc.add_row(
+        10.,
+        (0, 1),
+        0,
+        &[c(3., 4.), c(1., 0.), c(0., 2.), c(std::f32::NAN, 0.)],
+        &[false, false, true, false],
+        &[1., 0.],
+    );
+    acc.add_row(
+        10.,
+        (1, 1),
+        1,
+        &[c(2., 0.), c(2., 0.)],
+        &[true, true],
+        &[1., 1.],
+    );
+    acc.add_row(
+        20.,
+        (1, 0),
+        0,
+        &[c(1., 0.), c(1., 0.)],
+        &[false, false],
+        &[1., 1.],
+    );
+    let stats = acc.finish();
+
+    assert_eq!(stats.n_rows, 3);
+    assert_eq!(stats.n_times, 2);
+    assert_eq!(stats.time_range, Some((10., 20.)));
+    assert_eq!(stats.antennas, vec![0, 1]);
+    assert_eq!(stats.n_baselines, 1);
+    assert_eq!(stats.data_desc_ids, vec![0, 1]);
+    assert_eq!(stats.n_nonfinite, 1);
+    assert_eq!(stats.n_bad_weights, 2);
+    assert_eq!(stats.amplitudes.n_samples, 4);
+    assert_eq!(stats.amplitudes.min, 1.);
+    assert_eq!(stats.amplitudes.max, 5.);
+    assert_eq!(stats.amplitudes.mean, 2.);
+    assert_eq!(stats.amplitudes.rms, 7f64.sqrt());
+    assert_eq!(
+        stats.flags,
+        FlagCount {
+            n_flagged: 3,
+            n_total: 8
+        }
+    );
+    assert_eq!(stats.flags_by_antenna[&0].n_flagged, 1);
+    assert_eq!(stats.flags_by_antenna[&1].n_flagged, 3);
+    assert_eq!(stats.flags_by_data_desc[&1].fraction(), 1.);
+    assert_eq!(stats.flags_by_correlation[0].n_flagged, 2);
+
+    let checks = stats.check(0.5);
+    let failed: Vec<&str> = checks
+        .iter()
+        .filter(|c| !c.passed)
+        .map(|c| c.name)
+        .collect();
+    assert_eq!(failed, vec!["finite", "weights"]);
+}
@@ -0,0 +1,659 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Writing Measurement Sets from streams of visibilities.
+
+An `MsWriter` creates a new Measurement Set modeled on a *template* MS,
+which supplies the array configuration, spectral windows, polarizations,
+fields, and so on. The main table and the `POINTING` and `FLAG_CMD`
+subtables start out empty; the other subtables are copied in full. The
+writer then turns each `VisChunk` it is given into main-table rows, one per
+baseline, with the correlations ordered as in the template's `POLARIZATION`
+table. The `UVW` column is left at zero, and the weights at one, for later
+steps of processing to fill in. If the template's antennas are not the right
+ones, `set_antennas` replaces them with an `ArrayLayout`. The new main table
+is given the type `Measurement Set` (see `TableInfo`), so that CASA and other
+applications recognize it even if the template lacked it.
+
+Rows are buffered in memory and written out in batches sized according to
+a `MemoryBudget`. Each batch is written out by `flush`, which adds the rows
+to the main table, fills them in (column by column, through the table's
+write-behind buffer), updates the time range of the
+`OBSERVATION` subtable, and only then syncs the table and its subtables to
+disk. casacore records the number of rows of a table when it is synced, so
+if the process dies between flushes, the MS on disk is the readable one
+left by the previous flush. `finalize` flushes the last rows and closes the
+MS; if a writer is dropped without being finalized, it makes a best-effort
+attempt to flush.
+
+`MsWriter::open_append` instead adds rows to an existing Measurement Set,
+such as one that a monitoring program extends with each new epoch of
+observations. Scan numbers continue from the largest one in the MS, and the
+time range of the `OBSERVATION` subtable is extended rather than replaced.
+`ms::append::append` builds on this to reconcile the fields of the new data
+with those already present.
+
+For live ingest, `MsWriter::spawn` moves the writer to a background thread
+that is fed through a bounded queue. If the disk cannot keep up with the
+correlator, `MsWriterHandle::send` blocks once the queue is full, while
+`MsWriterHandle::try_send` returns the chunk so that the caller can decide
+whether to drop it.
+
+Visibilities that are NaN or infinite are handled according to the writer's
+`NanPolicy`, set with `MsWriter::set_nan_policy`. By default they are written
+as they are; they can instead be flagged and zeroed, or rejected.
+
+*/
+
+use failure::{err_msg, Error};
+use ndarray::Array2;
+use rubbl_core::budget::MemoryBudget;
+use rubbl_core::nan::NanPolicy;
+use rubbl_core::telescopes::ArrayLayout;
+use rubbl_core::units::{MjdSeconds, Seconds};
+use rubbl_core::Complex;
+use rubbl_visdata::baseline::AntPair;
+use rubbl_visdata::streaming::{LiveVisSource, VisChunk};
+use rubbl_visdata::VisPol;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+
+use super::mms::stokes_to_vispol;
+use super::{DeepCopyOptions, Table, TableInfo, TableOpenMode, WriteBehindOptions};
+
+/// The columns of the main table that the writer fills in.
+const MAIN_COLUMNS: &[&str] = &[
+    "UVW",
+    "FLAG",
+    "FLAG_CATEGORY",
+    "WEIGHT",
+    "SIGMA",
+    "ANTENNA1",
+    "ANTENNA2",
+    "ARRAY_ID",
+    "DATA_DESC_ID",
+    "EXPOSURE",
+    "FEED1",
+    "FEED2",
+    "FIELD_ID",
+    "FLAG_ROW",
+    "INTERVAL",
+    "OBSERVATION_ID",
+    "PROCESSOR_ID",
+    "SCAN_NUMBER",
+    "STATE_ID",
+    "TIME",
+    "TIME_CENTROID",
+    "DATA",
+];
+
+/// The subtables that describe the template observation itself rather than
+/// the instrument, and so are left empty.
+const EMPTY_SUBTABLES: &[&str] = &["POINTING", "FLAG_CMD"];
+
+/// The shape of the data of one data description.
+#[derive(Clone, Debug)]
+struct DataLayout {
+    n_chan: usize,
+    corrs: Vec<VisPol>,
+}
+
+/// A row that has not been written yet.
+#[derive(Clone, Debug)]
+struct PendingRow {
+    time: MjdSeconds,
+    interval: Seconds,
+    ant1: i32,
+    ant2: i32,
+    ddid: i32,
+    field_id: i32,
+    scan_number: i32,
+    data: Array2<Complex<f32>>,
+    flags: Array2<bool>,
+}
+
+/// Writes visibilities into a new or existing Measurement Set.
+pub struct MsWriter {
+    ms: Table,
+    path: PathBuf,
+    layouts: Vec<DataLayout>,
+    field_id: i32,
+    scan_number: i32,
+    budget: MemoryBudget,
+    pending: Vec<PendingRow>,
+    pending_bytes: u64,
+    n_written: u64,
+    time_range: Option<(MjdSeconds, MjdSeconds)>,
+    prior_time_range: Option<(MjdSeconds, MjdSeconds)>,
+    nan_policy: NanPolicy,
+    n_non_finite: u64,
+}
+
+impl MsWriter {
+    /// Create a new Measurement Set at *path* modeled on the one at
+    /// *template*, buffering up to about *budget* bytes of rows between
+    /// flushes.
+    ///
+    /// It is an error if something already exists at *path*.
+    pub fn create<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        template: Q,
+        budget: &MemoryBudget,
+    ) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let template = template.as_ref();
+        let mut source = Table::open(template, TableOpenMode::Read)?;
+
+        for col_name in MAIN_COLUMNS {
+            if !source.has_column(col_name)? {
+                return Err(err_msg(format!(
+                    "the template Measurement Set has no {} column",
+                    col_name
+                )));
+            }
+        }
+
+        // casacore empties the subtables of a copy without rows too, so
+        // copy the ones that we want in full separately.
+        source.deep_copy(
+            path,
+            &DeepCopyOptions {
+                no_rows: true,
+                ..DeepCopyOptions::columns(MAIN_COLUMNS)
+            },
+        )?;
+
+        for kw_name in source.table_keyword_names()? {
+            let subtable = template.join(&kw_name);
+
+            if EMPTY_SUBTABLES.contains(&kw_name.as_str()) || !subtable.join("table.dat").is_file()
+            {
+                continue;
+            }
+
+            let dest = path.join(&kw_name);
+            Table::delete(&dest)?;
+            Table::open(&subtable, TableOpenMode::Read)?
+                .deep_copy(&dest, &DeepCopyOptions::default())?;
+        }
+
+        let layouts = read_layouts(path)?;
+        let mut ms = Table::open(path, TableOpenMode::ReadWrite)?;
+
+        // The template may have been written by a tool that did not set the
+        // table type, and other applications only recognize a Measurement
+        // Set by it.
+        let mut info = ms.info()?;
+
+        if !info.is_measurement_set() {
+            info.table_type = TableInfo::MEASUREMENT_SET.to_owned();
+            info.sub_type = String::new();
+            ms.set_info(&info)?;
+        }
+
+        ms.set_write_behind(Some(WriteBehindOptions::default()))?;
+
+        Ok(MsWriter {
+            ms: ms,
+            path: path.to_owned(),
+            layouts: layouts,
+            field_id: 0,
+            scan_number: 1,
+            budget: *budget,
+            pending: Vec::new(),
+            pending_bytes: 0,
+            n_written: 0,
+            time_range: None,
+            prior_time_range: None,
+            nan_policy: NanPolicy::default(),
+            n_non_finite: 0,
+        })
+    }
+
+    /// Open the existing Measurement Set at *path* to add rows to it,
+    /// buffering up to about *budget* bytes of rows between flushes.
+    ///
+    /// The scan number of the new rows defaults to one more than the largest
+    /// in the MS.
+    pub fn open_append<P: AsRef<Path>>(path: P, budget: &MemoryBudget) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut ms = Table::open(path, TableOpenMode::ReadWrite)?;
+
+        for col_name in MAIN_COLUMNS {
+            if !ms.has_column(col_name)? {
+                return Err(err_msg(format!(
+                    "the Measurement Set has no {} column",
+                    col_name
+                )));
+            }
+        }
+
+        let layouts = read_layouts(path)?;
+        let last_scan = ms
+            .get_col_as_vec::<i32>("SCAN_NUMBER")?
+            .into_iter()
+            .max()
+            .unwrap_or(0);
+
+        let mut obs = Table::open(path.join("OBSERVATION"), TableOpenMode::Read)?;
+        let prior_time_range = if obs.n_rows() > 0 {
+            match obs.get_cell_as_vec::<f64>("TIME_RANGE", 0)?[..] {
+                [t0, t1] if t1 > t0 => Some((MjdSeconds(t0), MjdSeconds(t1))),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        ms.set_write_behind(Some(WriteBehindOptions::default()))?;
+
+        Ok(MsWriter {
+            ms: ms,
+            path: path.to_owned(),
+            layouts: layouts,
+            field_id: 0,
+            scan_number: last_scan + 1,
+            budget: *budget,
+            pending: Vec::new(),
+            pending_bytes: 0,
+            n_written: 0,
+            time_range: None,
+            prior_time_range: prior_time_range,
+            nan_policy: NanPolicy::default(),
+            n_non_finite: 0,
+        })
+    }
+
+    /// Set the field ID of the rows written from now on. The default is 0.
+    pub fn set_field_id(&mut self, field_id: i32) -> &mut Self {
+        self.field_id = field_id;
+        self
+    }
+
+    /// Set the scan number of the rows written from now on. The default is
+    /// 1.
+    pub fn set_scan_number(&mut self, scan_number: i32) -> &mut Self {
+        self.scan_number = scan_number;
+        self
+    }
+
+    /// Set the policy applied to the visibilities written from now on. The
+    /// default is `NanPolicy::Propagate`. Under `NanPolicy::Error`, a chunk
+    /// containing a non-finite value is rejected as a whole.
+    pub fn set_nan_policy(&mut self, policy: NanPolicy) -> &mut Self {
+        self.nan_policy = policy;
+        self
+    }
+
+    /// Replace the contents of the `ANTENNA` subtable with the antennas of
+    /// *layout*, so that metadata from another source, such as an MWA
+    /// metafits file, can override those of the template. Antenna IDs are
+    /// the indices into `layout.antennas`. If the template has more
+    /// antennas than the layout, the extra rows are flagged.
+    pub fn set_antennas(&mut self, layout: &ArrayLayout) -> Result<(), Error> {
+        let mut ant = Table::open(self.path.join("ANTENNA"), TableOpenMode::ReadWrite)?;
+        let n_ants = layout.antennas.len() as u64;
+
+        if ant.n_rows() < n_ants {
+            ant.add_rows((n_ants - ant.n_rows()) as usize)?;
+        }
+
+        for (i, a) in layout.antennas.iter().enumerate() {
+            let r = i as u64;
+            ant.put_cell("NAME", r, &a.name)?;
+            ant.put_cell("STATION", r, &a.station)?;
+            ant.put_cell("POSITION", r, &a.position.to_vec())?;
+            ant.put_cell("OFFSET", r, &vec![0f64; 3])?;
+            ant.put_cell("DISH_DIAMETER", r, &a.diameter)?;
+            ant.put_cell("FLAG_ROW", r, &false)?;
+        }
+
+        for r in n_ants..ant.n_rows() {
+            ant.put_cell("FLAG_ROW", r, &true)?;
+        }
+
+        ant.flush(true)?;
+        Ok(())
+    }
+
+    /// Get the path of the Measurement Set.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the number of rows that have been written to disk.
+    pub fn n_rows_written(&self) -> u64 {
+        self.n_written
+    }
+
+    /// Get the number of non-finite visibilities seen so far.
+    pub fn n_non_finite(&self) -> u64 {
+        self.n_non_finite
+    }
+
+    /// Get the span of time covered by the visibilities given to the writer
+    /// so far, or `None` if there have been none.
+    pub fn time_range(&self) -> Option<(MjdSeconds, MjdSeconds)> {
+        self.time_range
+    }
+
+    /// Get the number of rows waiting to be written.
+    pub fn n_rows_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Get the scan number of the rows written from now on.
+    pub fn scan_number(&self) -> i32 {
+        self.scan_number
+    }
+
+    /// Find the first data description of the Measurement Set that can hold
+    /// the visibilities of *chunk*: one with the same number of channels and
+    /// a correlation for each of its polarizations.
+    pub fn data_description_for(&self, chunk: &VisChunk) -> Option<i32> {
+        self.layouts
+            .iter()
+            .position(|l| {
+                l.n_chan == chunk.n_chan
+                    && chunk.basepols.iter().all(|bp| l.corrs.contains(&bp.pol))
+            })
+            .map(|i| i as i32)
+    }
+
+    /// Add the visibilities of *chunk*, which belong to data description
+    /// *ddid*, flushing if the buffer is full.
+    ///
+    /// Each baseline in the chunk becomes one row. Correlations that the
+    /// chunk does not include are flagged.
+    pub fn write_chunk(&mut self, chunk: &VisChunk, ddid: i32) -> Result<(), Error> {
+        let layout = match self.layouts.get(ddid as usize) {
+            Some(l) if ddid >= 0 => l,
+            _ => {
+                return Err(err_msg(format!(
+                    "DATA_DESC_ID {} is not in the Measurement Set",
+                    ddid
+                )));
+            }
+        };
+
+        if chunk.n_chan != layout.n_chan {
+            return Err(err_msg(format!(
+                "visibility chunk has {} channels, but DATA_DESC_ID {} has {}",
+                chunk.n_chan, ddid, layout.n_chan
+            )));
+        }
+
+        let n_corr = layout.corrs.len();
+        let mut rows: BTreeMap<AntPair, PendingRow> = BTreeMap::new();
+
+        for (i, bp) in chunk.basepols.iter().enumerate() {
+            let corr = match layout.corrs.iter().position(|&p| p == bp.pol) {
+                Some(c) => c,
+                None => {
+                    return Err(err_msg(format!(
+                        "DATA_DESC_ID {} has no {:?} correlation",
+                        ddid, bp.pol
+                    )));
+                }
+            };
+
+            let row = rows
+                .entry(AntPair::from(*bp))
+                .or_insert_with(|| PendingRow {
+                    time: chunk.time,
+                    interval: chunk.int_time,
+                    ant1: bp.ant1 as i32,
+                    ant2: bp.ant2 as i32,
+                    ddid: ddid,
+                    field_id: self.field_id,
+                    scan_number: self.scan_number,
+                    data: Array2::zeros((chunk.n_chan, n_corr)),
+                    flags: Array2::from_elem((chunk.n_chan, n_corr), true),
+                });
+
+            for (chan, (&v, &f)) in chunk.data_for(i).iter().zip(chunk.flags_for(i)).enumerate() {
+                row.data[[chan, corr]] = v;
+                row.flags[[chan, corr]] = f;
+            }
+        }
+
+        let mut n_non_finite = 0;
+
+        for row in rows.values_mut() {
+            n_non_finite += self.nan_policy.apply(
+                row.data.as_slice_mut().unwrap(),
+                row.flags.as_slice_mut().unwrap(),
+            )?;
+        }
+
+        self.n_non_finite += n_non_finite as u64;
+
+        // Eight bytes per visibility and one per flag, plus the scalars.
+        let row_bytes = 9 * (chunk.n_chan * n_corr) as u64 + 64;
+        self.pending_bytes += row_bytes * rows.len() as u64;
+        self.pending.extend(rows.into_values());
+
+        let half_time = 0.5 * chunk.int_time.0;
+        let start = chunk.time.plus_seconds(Seconds(-half_time));
+        let end = chunk.time.plus_seconds(Seconds(half_time));
+        self.time_range = Some(match self.time_range {
+            Some((t0, t1)) => (
+                if start < t0 { start } else { t0 },
+                if end > t1 { end } else { t1 },
+            ),
+            None => (start, end),
+        });
+
+        if self.pending_bytes >= self.budget.bytes() {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Add every chunk delivered by *source*, all of which belong to data
+    /// description *ddid*, returning the number of chunks. This is the
+    /// usual way to convert raw correlator output into a Measurement Set.
+    pub fn write_source<S: LiveVisSource>(
+        &mut self,
+        source: &mut S,
+        ddid: i32,
+    ) -> Result<usize, Error> {
+        let mut n_chunks = 0;
+
+        while let Some(chunk) = source.next_chunk()? {
+            self.write_chunk(&chunk, ddid)?;
+            n_chunks += 1;
+        }
+
+        Ok(n_chunks)
+    }
+
+    /// Write the buffered rows to the Measurement Set and sync it to disk.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let first_row = self.ms.n_rows();
+        self.ms.add_rows(self.pending.len())?;
+
+        for (i, row) in self.pending.iter().enumerate() {
+            let r = first_row + i as u64;
+            let n_corr = row.data.shape()[1];
+            let unflagged = !row.flags.iter().all(|&f| f);
+
+            self.ms.put_cell("TIME", r, &row.time.0)?;
+            self.ms.put_cell("TIME_CENTROID", r, &row.time.0)?;
+            self.ms.put_cell("INTERVAL", r, &row.interval.0)?;
+            self.ms.put_cell("EXPOSURE", r, &row.interval.0)?;
+            self.ms.put_cell("ANTENNA1", r, &row.ant1)?;
+            self.ms.put_cell("ANTENNA2", r, &row.ant2)?;
+            self.ms.put_cell("FEED1", r, &0i32)?;
+            self.ms.put_cell("FEED2", r, &0i32)?;
+            self.ms.put_cell("DATA_DESC_ID", r, &row.ddid)?;
+            self.ms.put_cell("FIELD_ID", r, &row.field_id)?;
+            self.ms.put_cell("SCAN_NUMBER", r, &row.scan_number)?;
+            self.ms.put_cell("ARRAY_ID", r, &0i32)?;
+            self.ms.put_cell("OBSERVATION_ID", r, &0i32)?;
+            self.ms.put_cell("PROCESSOR_ID", r, &0i32)?;
+            self.ms.put_cell("STATE_ID", r, &-1i32)?;
+            self.ms.put_cell("FLAG_ROW", r, &!unflagged)?;
+            self.ms.put_cell("UVW", r, &vec![0f64; 3])?;
+            self.ms.put_cell("WEIGHT", r, &vec![1f32; n_corr])?;
+            self.ms.put_cell("SIGMA", r, &vec![1f32; n_corr])?;
+            self.ms.put_cell("DATA", r, &row.data)?;
+            self.ms.put_cell("FLAG", r, &row.flags)?;
+        }
+
+        if let Some((t0, t1)) = merge_time_ranges(self.prior_time_range, self.time_range) {
+            let mut obs = Table::open(self.path.join("OBSERVATION"), TableOpenMode::ReadWrite)?;
+
+            if obs.n_rows() > 0 {
+                obs.put_cell("TIME_RANGE", 0, &vec![t0.0, t1.0])?;
+                obs.flush(true)?;
+            }
+        }
+
+        self.ms.flush(true)?;
+        self.n_written += self.pending.len() as u64;
+        self.pending.clear();
+        self.pending_bytes = 0;
+        Ok(())
+    }
+
+    /// Flush the remaining rows and close the Measurement Set, returning the
+    /// total number of rows written.
+    pub fn finalize(mut self) -> Result<u64, Error> {
+        self.flush()?;
+        Ok(self.n_written)
+    }
+
+    /// Move the writer to a background thread that accepts up to
+    /// *queue_len* chunks ahead of what it has written.
+    pub fn spawn(self, queue_len: usize) -> MsWriterHandle {
+        let (sender, receiver) = mpsc::sync_channel(queue_len);
+
+        let thread = thread::spawn(move || {
+            let mut writer = self;
+            let receiver: Receiver<(VisChunk, i32)> = receiver;
+
+            // If this fails, the receiver is dropped and the handle's sends
+            // start failing.
+            for (chunk, ddid) in receiver {
+                writer.write_chunk(&chunk, ddid)?;
+            }
+
+            writer.finalize()
+        });
+
+        MsWriterHandle {
+            sender: sender,
+            thread: thread,
+        }
+    }
+}
+
+impl Drop for MsWriter {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            // We can't report errors from here.
+            let _r = self.flush();
+        }
+    }
+}
+
+/// A handle onto an `MsWriter` running in a background thread.
+#[derive(Debug)]
+pub struct MsWriterHandle {
+    sender: SyncSender<(VisChunk, i32)>,
+    thread: JoinHandle<Result<u64, Error>>,
+}
+
+impl MsWriterHandle {
+    /// Queue *chunk*, which belongs to data description *ddid*, to be
+    /// written, blocking while the queue is full.
+    ///
+    /// If the writer has failed, this returns an error; `finish` gives the
+    /// reason.
+    pub fn send(&self, chunk: VisChunk, ddid: i32) -> Result<(), Error> {
+        self.sender
+            .send((chunk, ddid))
+            .map_err(|_| err_msg("the Measurement Set writer has stopped"))
+    }
+
+    /// Queue *chunk* to be written if there is room, or give it back if the
+    /// queue is full.
+    pub fn try_send(&self, chunk: VisChunk, ddid: i32) -> Result<Option<VisChunk>, Error> {
+        match self.sender.try_send((chunk, ddid)) {
+            Ok(()) => Ok(None),
+            Err(TrySendError::Full((chunk, _))) => Ok(Some(chunk)),
+            Err(TrySendError::Disconnected(_)) => {
+                Err(err_msg("the Measurement Set writer has stopped"))
+            }
+        }
+    }
+
+    /// Wait for the writer to write everything that has been queued and
+    /// finalize the Measurement Set, returning the total number of rows
+    /// written.
+    pub fn finish(self) -> Result<u64, Error> {
+        drop(self.sender);
+
+        match self.thread.join() {
+            Ok(r) => r,
+            Err(_) => Err(err_msg("the Measurement Set writer thread panicked")),
+        }
+    }
+}
+
+/// Combine two optional time ranges into one that covers both.
+fn merge_time_ranges(
+    a: Option<(MjdSeconds, MjdSeconds)>,
+    b: Option<(MjdSeconds, MjdSeconds)>,
+) -> Option<(MjdSeconds, MjdSeconds)> {
+    match (a, b) {
+        (Some((a0, a1)), Some((b0, b1))) => {
+            Some((if b0 < a0 { b0 } else { a0 }, if b1 > a1 { b1 } else { a1 }))
+        }
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Read the number of channels and the correlation types of each data
+/// description of the MS at *path*.
+fn read_layouts(path: &Path) -> Result<Vec<DataLayout>, Error> {
+    let mut dd = Table::open(path.join("DATA_DESCRIPTION"), TableOpenMode::Read)?;
+    let spw_ids = dd.get_col_as_vec::<i32>("SPECTRAL_WINDOW_ID")?;
+    let pol_ids = dd.get_col_as_vec::<i32>("POLARIZATION_ID")?;
+    let n_chans = Table::open(path.join("SPECTRAL_WINDOW"), TableOpenMode::Read)?
+        .get_col_as_vec::<i32>("NUM_CHAN")?;
+    let mut pol = Table::open(path.join("POLARIZATION"), TableOpenMode::Read)?;
+    let mut layouts = Vec::with_capacity(spw_ids.len());
+
+    for (spw_id, pol_id) in spw_ids.into_iter().zip(pol_ids) {
+        let n_chan = match n_chans.get(spw_id as usize) {
+            Some(&n) if spw_id >= 0 => n as usize,
+            _ => {
+                return Err(err_msg(format!(
+                    "SPECTRAL_WINDOW_ID {} has no SPECTRAL_WINDOW entry",
+                    spw_id
+                )));
+            }
+        };
+
+        let corrs = pol
+            .get_cell_as_vec::<i32>("CORR_TYPE", pol_id as u64)?
+            .into_iter()
+            .map(stokes_to_vispol)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        layouts.push(DataLayout {
+            n_chan: n_chan,
+            corrs: corrs,
+        });
+    }
+
+    Ok(layouts)
+}
@@ -0,0 +1,512 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Processing Measurement Sets one data description at a time, in parallel.
+
+The rows of a Measurement Set main table with different `DATA_DESC_ID`
+values belong to different spectral windows (or polarization setups), and
+operations such as averaging, flagging, and applying calibration treat them
+completely independently. `for_each_ddid` takes advantage of this to run
+such an operation on several data descriptions at once:
+
+- The rows of the table are split into partitions by `DATA_DESC_ID`.
+- Each partition is processed by a worker process, with up to a given
+  number of them running at once.
+- For each partition, the operation is given the partition and a reference
+  table containing only its rows, so code written to process a whole table
+  works unchanged.
+- The results of the operation come back ordered by `DATA_DESC_ID`, ready to
+  be merged.
+
+The workers are processes rather than threads because casacore is not
+thread-safe: within one process every call into it holds a process-wide
+lock, so threads would take turns doing their I/O. Separate processes each
+have their own copy of casacore and really do run in parallel. Those that
+write to the table coordinate through casacore's usual table locks, so
+operations that mostly write gain less than those that mostly read.
+
+A worker is the calling program itself, run again with the same arguments
+and some environment variables that tell it which partition to process. Its
+rows and its result are passed through files in a private temporary
+directory. A program that uses `for_each_ddid` must therefore call
+`run_ddid_worker` with the same operation early in `main`, before it has
+side effects, and exit if it returns true:
+
+```rust,no_run
+# extern crate failure;
+# extern crate rubbl_casatables;
+# fn main() -> Result<(), failure::Error> {
+use rubbl_casatables::partition::{for_each_ddid, run_ddid_worker, DdidPartition};
+use rubbl_casatables::{Table, TableOpenMode};
+
+let count_rows = |_p: &DdidPartition, t: &mut Table| Ok(t.n_rows());
+
+if run_ddid_worker(count_rows)? {
+    return Ok(());
+}
+
+let exe = std::env::current_exe()?;
+let args: Vec<_> = std::env::args_os().skip(1).collect();
+let counts: Vec<(i32, u64)> =
+    for_each_ddid("data.ms", TableOpenMode::Read, 4, &exe, &args)?;
+# Ok(())
+# }
+```
+
+*/
+
+use failure::{err_msg, Error};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use super::{Table, TableOpenMode};
+
+/// The environment variable giving a worker the path of its table.
+const TABLE_VAR: &str = "RUBBL_DDID_WORKER_TABLE";
+
+/// The environment variable giving a worker the mode in which to open its
+/// table: "read" or "write".
+const MODE_VAR: &str = "RUBBL_DDID_WORKER_MODE";
+
+/// The environment variable giving a worker its `DATA_DESC_ID`.
+const DDID_VAR: &str = "RUBBL_DDID_WORKER_DDID";
+
+/// The environment variable giving a worker the file listing its rows.
+const ROWS_VAR: &str = "RUBBL_DDID_WORKER_ROWS";
+
+/// The environment variable giving a worker the file to write its result to.
+const OUTPUT_VAR: &str = "RUBBL_DDID_WORKER_OUTPUT";
+
+/// How often `for_each_ddid` checks whether its workers have finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The rows of a main table that have one `DATA_DESC_ID` value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DdidPartition {
+    /// The data description ID.
+    pub ddid: i32,
+
+    /// The numbers of the rows with this ID, in increasing order.
+    pub rows: Vec<usize>,
+}
+
+impl DdidPartition {
+    /// Get a row mask for a table with *n_rows* rows that selects the rows
+    /// of this partition, for use with `Table::select_rows`.
+    pub fn row_mask(&self, n_rows: usize) -> Vec<bool> {
+        let mut mask = vec![false; n_rows];
+
+        for &row in &self.rows {
+            mask[row] = true;
+        }
+
+        mask
+    }
+}
+
+/// Split the rows of the main table *table* into partitions by
+/// `DATA_DESC_ID`, ordered by ID.
+pub fn ddid_partitions(table: &mut Table) -> Result<Vec<DdidPartition>, Error> {
+    let ddids = table.get_col_as_vec::<i32>("DATA_DESC_ID")?;
+    Ok(partition_ddids(&ddids))
+}
+
+fn partition_ddids(ddids: &[i32]) -> Vec<DdidPartition> {
+    let mut rows = BTreeMap::new();
+
+    for (row, &ddid) in ddids.iter().enumerate() {
+        rows.entry(ddid).or_insert_with(Vec::new).push(row);
+    }
+
+    rows.into_iter()
+        .map(|(ddid, rows)| DdidPartition {
+            ddid: ddid,
+            rows: rows,
+        })
+        .collect()
+}
+
+/// A directory for the files exchanged with worker processes, which only
+/// the current user can access. It is deleted when dropped.
+struct WorkDir {
+    path: PathBuf,
+}
+
+impl WorkDir {
+    fn create() -> Result<Self, Error> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        loop {
+            let path = env::temp_dir().join(format!(
+                "rubbl-ddid-{}-{}",
+                process::id(),
+                COUNTER.fetch_add(1, Ordering::SeqCst)
+            ));
+            let mut builder = fs::DirBuilder::new();
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::DirBuilderExt;
+                builder.mode(0o700);
+            }
+
+            // Creation fails if anything, including a symbolic link, is
+            // already there.
+            match builder.create(&path) {
+                Ok(()) => return Ok(WorkDir { path: path }),
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn rows_path(&self, ddid: i32) -> PathBuf {
+        self.path.join(format!("{}.rows.json", ddid))
+    }
+
+    fn output_path(&self, ddid: i32) -> PathBuf {
+        self.path.join(format!("{}.result.json", ddid))
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        let _r = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Write *value* as JSON into a new file at *path*.
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), Error> {
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, value)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
+
+/// Run an operation on each `DATA_DESC_ID` partition of the main table at
+/// *path* in worker processes, with up to *n_workers* of them at once.
+///
+/// Each worker is started by running *program* with the arguments *args*,
+/// and must call `run_ddid_worker` to do its work; see the module
+/// documentation. It opens the table in *mode*, which must be `Read` or
+/// `ReadWrite`. The results are returned in order of ID. If a worker fails,
+/// no new ones are started, those still running are stopped, and an error
+/// is returned. The standard output of the workers is discarded, but their
+/// standard error is passed through, so that their error messages are seen.
+pub fn for_each_ddid<P, R>(
+    path: P,
+    mode: TableOpenMode,
+    n_workers: usize,
+    program: &Path,
+    args: &[OsString],
+) -> Result<Vec<(i32, R)>, Error>
+where
+    P: AsRef<Path>,
+    R: DeserializeOwned,
+{
+    let mode_name = match mode {
+        TableOpenMode::Read => "read",
+        TableOpenMode::ReadWrite => "write",
+        _ => {
+            return Err(err_msg("partition workers can only open existing tables"));
+        }
+    };
+
+    let path = path.as_ref();
+    let mut queue = ddid_partitions(&mut Table::open(path, TableOpenMode::Read)?)?;
+    queue.reverse();
+    let work_dir = WorkDir::create()?;
+    let n_workers = n_workers.max(1);
+    let mut running: Vec<(i32, Child)> = Vec::new();
+    let mut results = Vec::with_capacity(queue.len());
+
+    let outcome = (|| -> Result<(), Error> {
+        while !(queue.is_empty() && running.is_empty()) {
+            while running.len() < n_workers {
+                let partition = match queue.pop() {
+                    Some(p) => p,
+                    None => break,
+                };
+
+                let rows_path = work_dir.rows_path(partition.ddid);
+                write_json(&rows_path, &partition.rows)?;
+                let child = Command::new(program)
+                    .args(args)
+                    .env(TABLE_VAR, path)
+                    .env(MODE_VAR, mode_name)
+                    .env(DDID_VAR, partition.ddid.to_string())
+                    .env(ROWS_VAR, &rows_path)
+                    .env(OUTPUT_VAR, work_dir.output_path(partition.ddid))
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .spawn()?;
+                running.push((partition.ddid, child));
+            }
+
+            let mut i = 0;
+            let mut any_done = false;
+
+            while i < running.len() {
+                let status = match running[i].1.try_wait()? {
+                    Some(s) => s,
+                    None => {
+                        i += 1;
+                        continue;
+                    }
+                };
+
+                let (ddid, _) = running.swap_remove(i);
+                any_done = true;
+
+                if !status.success() {
+                    return Err(err_msg(format!(
+                        "the worker for DATA_DESC_ID {} failed ({})",
+                        ddid, status
+                    )));
+                }
+
+                match read_json(&work_dir.output_path(ddid)) {
+                    Ok(r) => results.push((ddid, r)),
+                    Err(e) => {
+                        return Err(err_msg(format!(
+                            "the worker for DATA_DESC_ID {} did not report a result: {}",
+                            ddid, e
+                        )));
+                    }
+                }
+            }
+
+            if !any_done {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+
+        Ok(())
+    })();
+
+    for (_, mut child) in running {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    outcome?;
+    results.sort_by_key(|&(ddid, _)| ddid);
+    Ok(results)
+}
+
+/// If this process was started as a worker by `for_each_ddid`, run *op* on
+/// its partition, report the result to the parent process, and return true.
+/// Otherwise, return false without doing anything.
+///
+/// *op* is called with the partition and a reference table containing its
+/// rows. It should be the same operation in every process. If the table was
+/// opened for writing, it is flushed after *op* returns.
+pub fn run_ddid_worker<F, R>(op: F) -> Result<bool, Error>
+where
+    F: FnOnce(&DdidPartition, &mut Table) -> Result<R, Error>,
+    R: Serialize,
+{
+    let path = match env::var_os(TABLE_VAR) {
+        Some(p) => PathBuf::from(p),
+        None => return Ok(false),
+    };
+
+    let var = |name| {
+        env::var(name).map_err(|_| {
+            err_msg(format!(
+                "partition worker environment variable {} is missing",
+                name
+            ))
+        })
+    };
+
+    let mode = match var(MODE_VAR)?.as_ref() {
+        "write" => TableOpenMode::ReadWrite,
+        _ => TableOpenMode::Read,
+    };
+    let ddid: i32 = var(DDID_VAR)?
+        .parse()
+        .map_err(|_| err_msg(format!("illegal value of {}", DDID_VAR)))?;
+    let rows: Vec<usize> = read_json(Path::new(&var(ROWS_VAR)?))?;
+    let output = PathBuf::from(var(OUTPUT_VAR)?);
+
+    let mut table = Table::open(&path, mode)?;
+    let n_rows = table.n_rows() as usize;
+
+    if let Some(row) = rows.iter().find(|&&r| r >= n_rows) {
+        return Err(err_msg(format!(
+            "partition worker was given row {}, but the table has only {} rows",
+            row, n_rows
+        )));
+    }
+
+    let partition = DdidPartition {
+        ddid: ddid,
+        rows: rows,
+    };
+    let result = {
+        let mut subset = table.select_rows(&partition.row_mask(n_rows))?;
+        op(&partition, &mut subset)?
+    };
+
+    if mode == TableOpenMode::ReadWrite {
+        table.flush(false)?;
+    }
+
+    write_json(&output, &result)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+#[test]
+fn ddid_partitioning() {
+    let partitions = partition_ddids(&[1, 0, 1, 3, 0]);
+    assert_eq!(
+        partitions,
+        vec![
+            DdidPartition {
+                ddid: 0,
+                rows: vec![1, 4],
+            },
+            DdidPartition {
+                ddid: 1,
+                rows: vec![0, 2],
+            },
+            DdidPartition {
+                ddid: 3,
+                rows: vec![3],
+            },
+        ]
+    );
+    assert_eq!(
+        partitions[1].row_mask(5),
+        vec![true, false, true, false, false]
+    );
+    assert!(partition_ddids(&[]).is_empty());
+}
+
+/// Create a main table with a `DATA_DESC_ID` column, and a `VALUE` column
+/// of zeros, in a new directory named after *name*.
+#[cfg(test)]
+fn worker_test_table(name: &str) -> (PathBuf, PathBuf) {
+    use super::tabledesc::TableDescription;
+    use super::GlueDataType;
+
+    let dir = env::temp_dir().join(format!("rubbl-{}-{}", name, process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.table");
+    let mut desc = TableDescription::new();
+    desc.add_scalar_column("DATA_DESC_ID", GlueDataType::TpInt, "");
+    desc.add_scalar_column("VALUE", GlueDataType::TpInt, "");
+    let mut t = Table::create(&path, &desc, 5).unwrap();
+    t.put_col("DATA_DESC_ID", &[1i32, 0, 1, 3, 0]).unwrap();
+    (dir, path)
+}
+
+/// The arguments that make the test binary run just the test *name*, so
+/// that it can act as the worker program.
+#[cfg(test)]
+fn worker_test_args(name: &str) -> Vec<OsString> {
+    vec![
+        format!("partition::{}", name).into(),
+        "--exact".into(),
+        "--test-threads=1".into(),
+    ]
+}
+
+#[cfg(test)]
+#[test]
+fn ddid_worker_processes() {
+    let op = |p: &DdidPartition, t: &mut Table| -> Result<(i32, u64, Vec<usize>), Error> {
+        Ok((p.ddid, t.n_rows(), p.rows.clone()))
+    };
+
+    if run_ddid_worker(op).unwrap() {
+        return;
+    }
+
+    let (dir, path) = worker_test_table("ddid-workers");
+    let exe = env::current_exe().unwrap();
+    let results: Vec<(i32, (i32, u64, Vec<usize>))> = for_each_ddid(
+        &path,
+        TableOpenMode::Read,
+        2,
+        &exe,
+        &worker_test_args("ddid_worker_processes"),
+    )
+    .unwrap();
+    assert_eq!(
+        results,
+        vec![
+            (0, (0, 2, vec![1, 4])),
+            (1, (1, 2, vec![0, 2])),
+            (3, (3, 1, vec![3])),
+        ]
+    );
+
+    // A worker that does not report a result is an error.
+    let failing: Result<Vec<(i32, u64)>, _> = for_each_ddid(
+        &path,
+        TableOpenMode::Read,
+        2,
+        &exe,
+        &worker_test_args("no_such_test"),
+    );
+    assert!(failing.is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn ddid_worker_writes() {
+    let op = |p: &DdidPartition, t: &mut Table| -> Result<u64, Error> {
+        for row in 0..t.n_rows() {
+            t.put_cell("VALUE", row, &(10 * p.ddid + 1))?;
+        }
+
+        Ok(t.n_rows())
+    };
+
+    if run_ddid_worker(op).unwrap() {
+        return;
+    }
+
+    let (dir, path) = worker_test_table("ddid-worker-writes");
+    let exe = env::current_exe().unwrap();
+    let results: Vec<(i32, u64)> = for_each_ddid(
+        &path,
+        TableOpenMode::ReadWrite,
+        3,
+        &exe,
+        &worker_test_args("ddid_worker_writes"),
+    )
+    .unwrap();
+    assert_eq!(results, vec![(0, 2), (1, 2), (3, 1)]);
+
+    let mut t = Table::open(&path, TableOpenMode::Read).unwrap();
+    assert_eq!(
+        t.get_col_as_vec::<i32>("VALUE").unwrap(),
+        vec![11, 1, 11, 31, 1]
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,173 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Planning row masks for data selections on Measurement Sets.
+
+A `rubbl_core::select::Selection` is backend-neutral: readers of any data
+format can apply it to their records one at a time. When the data live in a
+CASA table, though, it is much cheaper to work out which rows are selected
+up front and only ever read those. A `SelectionPlan` does this by reading
+only the small index columns that the selection involves (`ANTENNA1`,
+`ANTENNA2`, `DATA_DESC_ID`, `TIME`), evaluating the selection on them, and
+making a reference table of the selected rows with `Table::select_rows`.
+Bulky columns such as `DATA` are then never read for unselected rows.
+
+If the Measurement Set has an up-to-date `ms::timeindex::TimeIndex` saved in
+its sidecar, time ranges are resolved with it instead of reading the whole
+`TIME` column.
+
+This is a Rust-side planner: the row filter is evaluated in Rust with the
+very same `Selection` methods that other backends use, so that results
+cannot differ between backends, and casacore only sees the resulting row
+mask. The filter is not pushed down into casacore as a TaQL query. That
+would need the TaQL engine (`tables/TaQL`), which the casacore bundled in
+`rubbl_casatables_impl` does not include, and can only be done once it is
+vendored.
+
+Channel selections do not filter rows. They are applied downstream with
+`Selection::channel_mask`.
+
+*/
+
+use failure::Error;
+use rubbl_core::select::Selection;
+use rubbl_core::units::MjdSeconds;
+
+use super::ms::timeindex::TimeIndex;
+use super::{Table, TableOpenMode};
+
+/// A plan for applying a `Selection` to the main table of a Measurement Set.
+#[derive(Clone, Debug)]
+pub struct SelectionPlan {
+    selection: Selection,
+
+    /// The spectral window of each data description, indexed by
+    /// `DATA_DESC_ID`. Only loaded if the selection involves spectral
+    /// windows.
+    ddid_spws: Option<Vec<usize>>,
+}
+
+impl SelectionPlan {
+    /// Plan how to apply *selection* to the main table *table*.
+    ///
+    /// If the selection involves spectral windows, this reads the
+    /// `DATA_DESCRIPTION` subtable to learn how data description IDs map to
+    /// spectral windows.
+    pub fn new(selection: &Selection, table: &mut Table) -> Result<Self, Error> {
+        let ddid_spws = if selection.spws.is_empty() {
+            None
+        } else {
+            let mut dd = Table::open(table.path.join("DATA_DESCRIPTION"), TableOpenMode::Read)?;
+            let spws = dd.get_col_as_vec::<i32>("SPECTRAL_WINDOW_ID")?;
+            Some(spws.into_iter().map(|s| s as usize).collect())
+        };
+
+        Ok(SelectionPlan {
+            selection: selection.clone(),
+            ddid_spws: ddid_spws,
+        })
+    }
+
+    /// Compute which rows of *table* are selected.
+    pub fn row_mask(&self, table: &mut Table) -> Result<Vec<bool>, Error> {
+        let sel = &self.selection;
+        let mut mask = vec![true; table.n_rows() as usize];
+
+        if !sel.baselines.is_empty() {
+            let ant1 = table.get_col_as_vec::<i32>("ANTENNA1")?;
+            let ant2 = table.get_col_as_vec::<i32>("ANTENNA2")?;
+
+            for (i, m) in mask.iter_mut().enumerate() {
+                *m = *m && sel.matches_baseline(ant1[i] as usize, ant2[i] as usize);
+            }
+        }
+
+        if let Some(ref ddid_spws) = self.ddid_spws {
+            let ddids = table.get_col_as_vec::<i32>("DATA_DESC_ID")?;
+
+            for (i, m) in mask.iter_mut().enumerate() {
+                *m = *m
+                    && ddid_spws
+                        .get(ddids[i] as usize)
+                        .map(|&spw| sel.matches_spw(spw))
+                        .unwrap_or(false);
+            }
+        }
+
+        if !sel.timeranges.is_empty() {
+            if let Some(index) = TimeIndex::load_current(table)? {
+                let mut in_range = vec![false; mask.len()];
+
+                for r in &sel.timeranges {
+                    for rows in index.rows_between(table, MjdSeconds(r.start), MjdSeconds(r.end))? {
+                        for m in &mut in_range[rows.start as usize..rows.end as usize] {
+                            *m = true;
+                        }
+                    }
+                }
+
+                for (m, i) in mask.iter_mut().zip(in_range) {
+                    *m = *m && i;
+                }
+            } else {
+                let times = table.get_col_as_vec::<f64>("TIME")?;
+
+                for (i, m) in mask.iter_mut().enumerate() {
+                    *m = *m && sel.matches_time(times[i]);
+                }
+            }
+        }
+
+        Ok(mask)
+    }
+
+    /// Apply this plan to *table*, returning a reference table containing
+    /// only the selected rows.
+    pub fn apply(&self, table: &mut Table) -> Result<Table, Error> {
+        let mask = self.row_mask(table)?;
+        table.select_rows(&mask)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn row_selection() {
+    use super::tabledesc::TableDescription;
+    use super::GlueDataType;
+
+    let dir = std::env::temp_dir().join(format!("rubbl-planner-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut desc = TableDescription::new();
+    desc.add_scalar_column("ANTENNA1", GlueDataType::TpInt, "")
+        .add_scalar_column("ANTENNA2", GlueDataType::TpInt, "")
+        .add_scalar_column("TIME", GlueDataType::TpDouble, "");
+
+    let mut t = Table::create(dir.join("main.table"), &desc, 4).unwrap();
+    t.put_col("ANTENNA1", &[0i32, 0, 1, 0]).unwrap();
+    t.put_col("ANTENNA2", &[1i32, 0, 2, 2]).unwrap();
+    t.put_col("TIME", &[10f64, 10., 20., 30.]).unwrap();
+
+    let mut sel = Selection::new();
+    sel.set_antenna("0&*").unwrap();
+    let plan = SelectionPlan::new(&sel, &mut t).unwrap();
+    assert_eq!(
+        plan.row_mask(&mut t).unwrap(),
+        vec![true, false, false, true]
+    );
+
+    let mut selected = plan.apply(&mut t).unwrap();
+    assert_eq!(
+        selected.get_col_as_vec::<f64>("TIME").unwrap(),
+        vec![10., 30.]
+    );
+
+    let all = SelectionPlan::new(&Selection::new(), &mut t).unwrap();
+    assert_eq!(all.row_mask(&mut t).unwrap(), vec![true; 4]);
+
+    drop(selected);
+    drop(t);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,146 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Reading and writing table rows as Rust structs.
+
+The methods of `Table` work a column and a cell at a time, which gets
+tedious when a program deals in whole rows. A type implementing
+`CasaTableRow` describes the columns of a table, and can be read from and
+written to a row of one; `Table::create_for_rows`, `Table::read_rows`, and
+`Table::append_rows` build on this.
+
+With the `derive` feature of this crate, `CasaTableRow` can be derived for
+structs with named fields:
+
+```ignore
+use rubbl_casatables::CasaTableRow;
+
+#[derive(CasaTableRow)]
+struct Antenna {
+    name: String,
+    #[casa(name = "DISH_DIAMETER")]
+    diameter: f64,
+    #[casa(shape = "3")]
+    position: Vec<f64>,
+}
+```
+
+Each field becomes a column named after the field in upper case, unless a
+`#[casa(name = "...")]` attribute says otherwise. The column's type follows
+from the field's, through `CasaDataType`: scalar fields such as `f64` and
+`String` become scalar columns, while `Vec`s and `ndarray` arrays become
+array columns. Array columns have cells of varying shape unless a
+`#[casa(shape = "...")]` attribute gives a fixed shape, in C order.
+
+*/
+
+use failure::{err_msg, Error};
+use std::path::Path;
+
+use super::{CreateOptions, GlueDataType, Table};
+
+/// The result of reading or writing a row.
+pub type RowResult<T> = Result<T, Error>;
+
+/// A column of a table holding rows of a `CasaTableRow` type.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RowColumn {
+    /// The name of the column.
+    pub name: &'static str,
+
+    /// The type of the column's cells, such as `TpDouble` for scalar columns
+    /// or `TpArrayDouble` for array columns.
+    pub data_type: GlueDataType,
+
+    /// The fixed shape of the cells of an array column, in C order, or
+    /// `None` if they may vary.
+    pub shape: Option<&'static [u64]>,
+}
+
+/// A type that can be stored in a row of a table.
+///
+/// This is normally implemented with `#[derive(CasaTableRow)]`; see the
+/// module documentation.
+pub trait CasaTableRow: Sized {
+    /// Get the columns that a table must have to hold values of this type.
+    fn columns() -> Vec<RowColumn>;
+
+    /// Read a value from row *row* of *table*.
+    fn read_row(table: &mut Table, row: u64) -> RowResult<Self>;
+
+    /// Write this value to row *row* of *table*, which must already exist.
+    fn write_row(&self, table: &mut Table, row: u64) -> RowResult<()>;
+}
+
+impl Table {
+    /// Create a new table at *path* with the columns needed to hold values
+    /// of the type `R`, and no rows.
+    pub fn create_for_rows<R: CasaTableRow, P: AsRef<Path>>(
+        path: P,
+        options: &CreateOptions,
+    ) -> Result<Self, Error> {
+        let columns = R::columns();
+        let scalars: Vec<(&str, GlueDataType)> = columns
+            .iter()
+            .filter(|c| array_element_type(c.data_type).is_none())
+            .map(|c| (c.name, c.data_type))
+            .collect();
+
+        let mut table = Self::create_with_scalar_columns_and_options(path, &scalars, 0, options)?;
+
+        for c in &columns {
+            if let Some(elem_type) = array_element_type(c.data_type) {
+                table.add_array_column(c.name, elem_type, c.shape)?;
+            } else if c.shape.is_some() {
+                return Err(err_msg(format!(
+                    "a shape is given for the column \"{}\", but its type {} is not an array",
+                    c.name, c.data_type
+                )));
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Read every row of this table as a value of type `R`.
+    pub fn read_rows<R: CasaTableRow>(&mut self) -> Result<Vec<R>, Error> {
+        (0..self.n_rows()).map(|i| R::read_row(self, i)).collect()
+    }
+
+    /// Add *rows* to the end of this table.
+    pub fn append_rows<R: CasaTableRow>(&mut self, rows: &[R]) -> Result<(), Error> {
+        let start = self.n_rows();
+        self.add_rows(rows.len())?;
+
+        for (i, r) in rows.iter().enumerate() {
+            r.write_row(self, start + i as u64)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the type of the elements of the array type *data_type*, or `None` if
+/// it is not an array type.
+fn array_element_type(data_type: GlueDataType) -> Option<GlueDataType> {
+    use glue::GlueDataType::*;
+
+    Some(match data_type {
+        TpArrayBool => TpBool,
+        TpArrayChar => TpChar,
+        TpArrayUChar => TpUChar,
+        TpArrayShort => TpShort,
+        TpArrayUShort => TpUShort,
+        TpArrayInt => TpInt,
+        TpArrayUInt => TpUInt,
+        TpArrayInt64 => TpInt64,
+        TpArrayFloat => TpFloat,
+        TpArrayDouble => TpDouble,
+        TpArrayComplex => TpComplex,
+        TpArrayDComplex => TpDComplex,
+        TpArrayString => TpString,
+        _ => return None,
+    })
+}
@@ -0,0 +1,972 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Reading and writing table rows with serde.
+
+`Table::deserialize_row` fills in any type implementing `serde::Deserialize`
+from a row of a table, and `Table::serialize_row` writes any type
+implementing `serde::Serialize` to one. This gives quick typed access to
+tables without deriving `CasaTableRow` (see the `rows` module), at the cost
+of looking up each column by name every time.
+
+A row is presented to serde as a map from column names to cell values, so
+it can be deserialized into a struct, whose fields are matched to columns by
+name, or into a map. casacore's column names are usually in upper case, so
+`#[serde(rename_all = "SCREAMING_SNAKE_CASE")]` is often useful. Fields with
+no matching column are treated as missing, so that `Option` fields and
+fields with `#[serde(default)]` may be absent from a table. Cell values map
+as follows:
+
+- Booleans, integers, floating-point numbers, and strings map to the
+  corresponding serde types. When writing, values are converted to the type
+  of the column, so that for instance an `i64` can be stored in an `Int`
+  column as long as it fits.
+- Complex numbers are sequences of two floating-point numbers, the real and
+  imaginary parts, so that they can be read into `[f32; 2]` or `(f64,
+  f64)`.
+- Array cells are flat sequences of their elements in C order. When a
+  sequence is written to a fixed-shape array column, it is given the shape
+  of the column; otherwise it is written as a one-dimensional array.
+- Undefined array cells read as `None`. Writing `None` leaves a cell
+  unchanged.
+- Unit enum variants are written as their names, so that enums can be
+  stored in string columns.
+
+*/
+
+use failure::Error;
+use ndarray::{ArrayD, IxDyn};
+use rubbl_core::Complex;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use std::convert::TryFrom;
+use std::error::Error as StdError;
+use std::fmt;
+
+use super::{CasaDataType, CasaScalarData, Table};
+
+impl Table {
+    /// Read row *row* of this table into a value of type `T`. See the
+    /// `rowserde` module for how cells are mapped.
+    pub fn deserialize_row<T: DeserializeOwned>(&mut self, row: u64) -> Result<T, Error> {
+        self.flush_writes()?;
+        let mut columns = Vec::new();
+
+        for name in self.column_names()? {
+            let value = CellValue::read(self, &name, row)?;
+            columns.push((name, value));
+        }
+
+        Ok(T::deserialize(RowDeserializer { columns: columns })?)
+    }
+
+    /// Write *value* to row *row* of this table, which must already exist.
+    /// See the `rowserde` module for how cells are mapped.
+    pub fn serialize_row<T: Serialize>(&mut self, row: u64, value: &T) -> Result<(), Error> {
+        for (name, cell) in value.serialize(RowSerializer)? {
+            if !self.has_column(&name)? {
+                return Err(
+                    SerdeError(format!("no column named \"{}\" in the table", name)).into(),
+                );
+            }
+
+            cell.write(self, &name, row)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An error arising when mapping between a table row and a Rust value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SerdeError(String);
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StdError for SerdeError {}
+
+impl de::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError(msg.to_string())
+    }
+}
+
+impl ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError(msg.to_string())
+    }
+}
+
+/// The value of one cell, in a form that serde can work with.
+#[derive(Clone, Debug, PartialEq)]
+enum CellValue {
+    Undefined,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Complex(f64, f64),
+    String(String),
+    Seq(Vec<CellValue>),
+}
+
+macro_rules! read_array {
+    ($table:expr, $name:expr, $row:expr, $ty:ty, $map:expr) => {
+        CellValue::Seq(
+            $table
+                .array_column::<$ty>($name)?
+                .get_as_vec($row)?
+                .into_iter()
+                .map($map)
+                .collect(),
+        )
+    };
+}
+
+impl CellValue {
+    /// Read the cell of column *name* in row *row* of *table*.
+    fn read(table: &mut Table, name: &str, row: u64) -> Result<Self, Error> {
+        use glue::GlueDataType::*;
+
+        let desc = table.get_col_desc(name)?;
+
+        if desc.is_scalar() {
+            return Ok(match desc.data_type() {
+                TpBool => CellValue::Bool(table.get_cell(name, row)?),
+                TpChar => CellValue::Int(i64::from(table.get_cell::<i8>(name, row)?)),
+                TpUChar => CellValue::UInt(u64::from(table.get_cell::<u8>(name, row)?)),
+                TpShort => CellValue::Int(i64::from(table.get_cell::<i16>(name, row)?)),
+                TpUShort => CellValue::UInt(u64::from(table.get_cell::<u16>(name, row)?)),
+                TpInt => CellValue::Int(i64::from(table.get_cell::<i32>(name, row)?)),
+                TpUInt => CellValue::UInt(u64::from(table.get_cell::<u32>(name, row)?)),
+                TpInt64 => CellValue::Int(table.get_cell(name, row)?),
+                TpFloat => CellValue::Float(f64::from(table.get_cell::<f32>(name, row)?)),
+                TpDouble => CellValue::Float(table.get_cell(name, row)?),
+                TpComplex => {
+                    let c = table.get_cell::<Complex<f32>>(name, row)?;
+                    CellValue::Complex(f64::from(c.re), f64::from(c.im))
+                }
+                TpDComplex => {
+                    let c = table.get_cell::<Complex<f64>>(name, row)?;
+                    CellValue::Complex(c.re, c.im)
+                }
+                TpString => CellValue::String(table.get_cell(name, row)?),
+                other => {
+                    return Err(SerdeError(format!(
+                        "cannot read column \"{}\" of type {} with serde",
+                        name, other
+                    ))
+                    .into());
+                }
+            });
+        }
+
+        if !table.cell_is_defined(name, row)? {
+            return Ok(CellValue::Undefined);
+        }
+
+        Ok(match desc.data_type() {
+            TpBool => read_array!(table, name, row, bool, CellValue::Bool),
+            TpChar => read_array!(table, name, row, i8, |v| CellValue::Int(i64::from(v))),
+            TpUChar => read_array!(table, name, row, u8, |v| CellValue::UInt(u64::from(v))),
+            TpShort => read_array!(table, name, row, i16, |v| CellValue::Int(i64::from(v))),
+            TpUShort => read_array!(table, name, row, u16, |v| CellValue::UInt(u64::from(v))),
+            TpInt => read_array!(table, name, row, i32, |v| CellValue::Int(i64::from(v))),
+            TpUInt => read_array!(table, name, row, u32, |v| CellValue::UInt(u64::from(v))),
+            TpInt64 => read_array!(table, name, row, i64, CellValue::Int),
+            TpFloat => read_array!(table, name, row, f32, |v| CellValue::Float(f64::from(v))),
+            TpDouble => read_array!(table, name, row, f64, CellValue::Float),
+            TpComplex => read_array!(table, name, row, Complex<f32>, |v| {
+                CellValue::Complex(f64::from(v.re), f64::from(v.im))
+            }),
+            TpDComplex => read_array!(table, name, row, Complex<f64>, |v| {
+                CellValue::Complex(v.re, v.im)
+            }),
+            TpString => read_array!(table, name, row, String, CellValue::String),
+            other => {
+                return Err(SerdeError(format!(
+                    "cannot read column \"{}\" of type {} with serde",
+                    name, other
+                ))
+                .into());
+            }
+        })
+    }
+
+    /// Write this value to the cell of column *name* in row *row* of
+    /// *table*, converting it to the type of the column.
+    fn write(self, table: &mut Table, name: &str, row: u64) -> Result<(), Error> {
+        use glue::GlueDataType::*;
+
+        if self == CellValue::Undefined {
+            return Ok(());
+        }
+
+        let desc = table.get_col_desc(name)?;
+        let data_type = desc.data_type();
+        let context = |e: SerdeError| SerdeError(format!("column \"{}\": {}", name, e.0));
+
+        if desc.is_scalar() {
+            match data_type {
+                TpBool => table.put_cell(name, row, &self.to_bool().map_err(context)?)?,
+                TpChar => table.put_cell(name, row, &self.to_int::<i8>().map_err(context)?)?,
+                TpUChar => table.put_cell(name, row, &self.to_int::<u8>().map_err(context)?)?,
+                TpShort => table.put_cell(name, row, &self.to_int::<i16>().map_err(context)?)?,
+                TpUShort => table.put_cell(name, row, &self.to_int::<u16>().map_err(context)?)?,
+                TpInt => table.put_cell(name, row, &self.to_int::<i32>().map_err(context)?)?,
+                TpUInt => table.put_cell(name, row, &self.to_int::<u32>().map_err(context)?)?,
+                TpInt64 => table.put_cell(name, row, &self.to_int::<i64>().map_err(context)?)?,
+                TpFloat => table.put_cell(name, row, &(self.to_f64().map_err(context)? as f32))?,
+                TpDouble => table.put_cell(name, row, &self.to_f64().map_err(context)?)?,
+                TpComplex => {
+                    let (re, im) = self.to_complex().map_err(context)?;
+                    table.put_cell(name, row, &Complex::new(re as f32, im as f32))?
+                }
+                TpDComplex => {
+                    let (re, im) = self.to_complex().map_err(context)?;
+                    table.put_cell(name, row, &Complex::new(re, im))?
+                }
+                TpString => table.put_cell(name, row, &self.into_string().map_err(context)?)?,
+                other => {
+                    return Err(SerdeError(format!(
+                        "cannot write column \"{}\" of type {} with serde",
+                        name, other
+                    ))
+                    .into());
+                }
+            }
+
+            return Ok(());
+        }
+
+        let items = match self {
+            CellValue::Seq(items) => items,
+            other => {
+                return Err(context(SerdeError(format!(
+                    "expected a sequence for an array column, got {:?}",
+                    other
+                )))
+                .into());
+            }
+        };
+
+        let shape = desc.shape().map(|s| s.to_vec());
+
+        macro_rules! put_array {
+            ($conv:expr) => {{
+                let values = items
+                    .into_iter()
+                    .map($conv)
+                    .collect::<Result<Vec<_>, SerdeError>>()
+                    .map_err(context)?;
+                put_array(table, name, row, values, shape)
+            }};
+        }
+
+        match data_type {
+            TpBool => put_array!(|v: CellValue| v.to_bool()),
+            TpChar => put_array!(|v: CellValue| v.to_int::<i8>()),
+            TpUChar => put_array!(|v: CellValue| v.to_int::<u8>()),
+            TpShort => put_array!(|v: CellValue| v.to_int::<i16>()),
+            TpUShort => put_array!(|v: CellValue| v.to_int::<u16>()),
+            TpInt => put_array!(|v: CellValue| v.to_int::<i32>()),
+            TpUInt => put_array!(|v: CellValue| v.to_int::<u32>()),
+            TpInt64 => put_array!(|v: CellValue| v.to_int::<i64>()),
+            TpFloat => put_array!(|v: CellValue| v.to_f64().map(|f| f as f32)),
+            TpDouble => put_array!(|v: CellValue| v.to_f64()),
+            TpComplex => put_array!(|v: CellValue| v
+                .to_complex()
+                .map(|(re, im)| Complex::new(re as f32, im as f32))),
+            TpDComplex => {
+                put_array!(|v: CellValue| v.to_complex().map(|(re, im)| Complex::new(re, im)))
+            }
+            TpString => {
+                let values = items
+                    .into_iter()
+                    .map(|v| v.into_string())
+                    .collect::<Result<Vec<_>, SerdeError>>()
+                    .map_err(context)?;
+                Ok(table.put_cell(name, row, &values)?)
+            }
+            other => Err(SerdeError(format!(
+                "cannot write column \"{}\" of type {} with serde",
+                name, other
+            ))
+            .into()),
+        }
+    }
+
+    fn mismatch(&self, expected: &str) -> SerdeError {
+        SerdeError(format!("expected {}, got {:?}", expected, self))
+    }
+
+    fn to_bool(&self) -> Result<bool, SerdeError> {
+        match *self {
+            CellValue::Bool(b) => Ok(b),
+            _ => Err(self.mismatch("a boolean")),
+        }
+    }
+
+    fn to_int<T: TryFrom<i64> + TryFrom<u64>>(&self) -> Result<T, SerdeError> {
+        let result = match *self {
+            CellValue::Int(i) => T::try_from(i).ok(),
+            CellValue::UInt(u) => T::try_from(u).ok(),
+            CellValue::Float(f) if f.fract() == 0. && f.abs() < 9.2e18 => {
+                T::try_from(f as i64).ok()
+            }
+            _ => return Err(self.mismatch("an integer")),
+        };
+
+        result.ok_or_else(|| self.mismatch("an integer in the range of the column's type"))
+    }
+
+    fn to_f64(&self) -> Result<f64, SerdeError> {
+        match *self {
+            CellValue::Int(i) => Ok(i as f64),
+            CellValue::UInt(u) => Ok(u as f64),
+            CellValue::Float(f) => Ok(f),
+            _ => Err(self.mismatch("a number")),
+        }
+    }
+
+    fn to_complex(&self) -> Result<(f64, f64), SerdeError> {
+        match *self {
+            CellValue::Complex(re, im) => Ok((re, im)),
+            CellValue::Seq(ref parts) if parts.len() == 2 => {
+                Ok((parts[0].to_f64()?, parts[1].to_f64()?))
+            }
+            _ => Ok((
+                self.to_f64()
+                    .map_err(|_| self.mismatch("a complex number"))?,
+                0.,
+            )),
+        }
+    }
+
+    fn into_string(self) -> Result<String, SerdeError> {
+        match self {
+            CellValue::String(s) => Ok(s),
+            other => Err(other.mismatch("a string")),
+        }
+    }
+}
+
+/// Write an array cell, giving it the fixed shape of its column if it has
+/// one of more than one dimension.
+fn put_array<T>(
+    table: &mut Table,
+    name: &str,
+    row: u64,
+    values: Vec<T>,
+    shape: Option<Vec<u64>>,
+) -> Result<(), Error>
+where
+    T: CasaScalarData + Copy,
+    Vec<T>: CasaDataType,
+{
+    match shape {
+        Some(ref s) if s.len() != 1 => {
+            let dims: Vec<usize> = s.iter().map(|&n| n as usize).collect();
+            let array = ArrayD::from_shape_vec(IxDyn(&dims), values)?;
+            table.put_cell(name, row, &array)?;
+        }
+        _ => table.put_cell(name, row, &values)?,
+    }
+
+    Ok(())
+}
+
+// Deserialization
+
+/// A deserializer presenting a row as a map from column names to values.
+struct RowDeserializer {
+    columns: Vec<(String, CellValue)>,
+}
+
+impl<'de> de::Deserializer<'de> for RowDeserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_map(de::value::MapDeserializer::new(self.columns.into_iter()))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        // Only hand over the columns that the struct wants, so that structs
+        // that deny unknown fields can describe a subset of the columns.
+        let columns = self
+            .columns
+            .into_iter()
+            .filter(|c| fields.contains(&&c.0[..]));
+        visitor.visit_map(de::value::MapDeserializer::new(columns))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, SerdeError> for CellValue {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for CellValue {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            CellValue::Undefined => visitor.visit_none(),
+            CellValue::Bool(b) => visitor.visit_bool(b),
+            CellValue::Int(i) => visitor.visit_i64(i),
+            CellValue::UInt(u) => visitor.visit_u64(u),
+            CellValue::Float(f) => visitor.visit_f64(f),
+            CellValue::Complex(re, im) => visitor.visit_seq(de::value::SeqDeserializer::new(
+                vec![CellValue::Float(re), CellValue::Float(im)].into_iter(),
+            )),
+            CellValue::String(s) => visitor.visit_string(s),
+            CellValue::Seq(items) => {
+                visitor.visit_seq(de::value::SeqDeserializer::new(items.into_iter()))
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self {
+            CellValue::Undefined => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self {
+            CellValue::String(s) => visitor.visit_enum(s.into_deserializer()),
+            other => Err(other.mismatch("the name of an enum variant")),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+// Serialization
+
+/// A serializer turning a struct or map into a list of column names and
+/// cell values.
+struct RowSerializer;
+
+/// Collects the fields of a struct or the entries of a map.
+struct RowFields {
+    columns: Vec<(String, CellValue)>,
+    key: Option<String>,
+}
+
+fn not_a_row<T>(what: &str) -> Result<T, SerdeError> {
+    Err(SerdeError(format!(
+        "cannot store {} as a table row; only structs and maps can be",
+        what
+    )))
+}
+
+impl ser::Serializer for RowSerializer {
+    type Ok = Vec<(String, CellValue)>;
+    type Error = SerdeError;
+    type SerializeSeq = ser::Impossible<Self::Ok, SerdeError>;
+    type SerializeTuple = ser::Impossible<Self::Ok, SerdeError>;
+    type SerializeTupleStruct = ser::Impossible<Self::Ok, SerdeError>;
+    type SerializeTupleVariant = ser::Impossible<Self::Ok, SerdeError>;
+    type SerializeMap = RowFields;
+    type SerializeStruct = RowFields;
+    type SerializeStructVariant = ser::Impossible<Self::Ok, SerdeError>;
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a boolean")
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a number")
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a number")
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a number")
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a number")
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a number")
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a number")
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a number")
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a number")
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a number")
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a number")
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a character")
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a string")
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, SerdeError> {
+        not_a_row("bytes")
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, SerdeError> {
+        not_a_row("None")
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, SerdeError> {
+        not_a_row("()")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, SerdeError> {
+        not_a_row("a unit struct")
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok, SerdeError> {
+        not_a_row("an enum")
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, SerdeError> {
+        not_a_row("an enum")
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SerdeError> {
+        not_a_row("a sequence")
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, SerdeError> {
+        not_a_row("a tuple")
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SerdeError> {
+        not_a_row("a tuple struct")
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerdeError> {
+        not_a_row("an enum")
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerdeError> {
+        Ok(RowFields {
+            columns: Vec::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, SerdeError> {
+        self.serialize_map(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerdeError> {
+        not_a_row("an enum")
+    }
+}
+
+impl ser::SerializeStruct for RowFields {
+    type Ok = Vec<(String, CellValue)>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.columns
+            .push((key.to_owned(), value.serialize(CellSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        Ok(self.columns)
+    }
+}
+
+impl ser::SerializeMap for RowFields {
+    type Ok = Vec<(String, CellValue)>;
+    type Error = SerdeError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), SerdeError> {
+        self.key = Some(key.serialize(CellSerializer)?.into_string()?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| SerdeError("map value serialized without a key".to_owned()))?;
+        self.columns.push((key, value.serialize(CellSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        Ok(self.columns)
+    }
+}
+
+/// A serializer turning a value into a `CellValue`.
+struct CellSerializer;
+
+/// Collects the elements of a sequence.
+struct CellSeq(Vec<CellValue>);
+
+fn not_a_cell<T>(what: &str) -> Result<T, SerdeError> {
+    Err(SerdeError(format!("cannot store {} in a table cell", what)))
+}
+
+impl ser::Serializer for CellSerializer {
+    type Ok = CellValue;
+    type Error = SerdeError;
+    type SerializeSeq = CellSeq;
+    type SerializeTuple = CellSeq;
+    type SerializeTupleStruct = CellSeq;
+    type SerializeTupleVariant = ser::Impossible<CellValue, SerdeError>;
+    type SerializeMap = ser::Impossible<CellValue, SerdeError>;
+    type SerializeStruct = ser::Impossible<CellValue, SerdeError>;
+    type SerializeStructVariant = ser::Impossible<CellValue, SerdeError>;
+
+    fn serialize_bool(self, v: bool) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Int(i64::from(v)))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Int(i64::from(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Int(i64::from(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::UInt(u64::from(v)))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::UInt(u64::from(v)))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::UInt(u64::from(v)))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::UInt(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Float(f64::from(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Seq(
+            v.iter().map(|&b| CellValue::UInt(u64::from(b))).collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Undefined)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<CellValue, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Undefined)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Undefined)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::String(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<CellValue, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<CellValue, SerdeError> {
+        not_a_cell("an enum variant with data")
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<CellSeq, SerdeError> {
+        Ok(CellSeq(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<CellSeq, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<CellSeq, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SerdeError> {
+        not_a_cell("an enum variant with data")
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, SerdeError> {
+        not_a_cell("a map")
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, SerdeError> {
+        not_a_cell("a struct")
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, SerdeError> {
+        not_a_cell("an enum variant with data")
+    }
+}
+
+impl ser::SerializeSeq for CellSeq {
+    type Ok = CellValue;
+    type Error = SerdeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.0.push(value.serialize(CellSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<CellValue, SerdeError> {
+        Ok(CellValue::Seq(self.0))
+    }
+}
+
+impl ser::SerializeTuple for CellSeq {
+    type Ok = CellValue;
+    type Error = SerdeError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<CellValue, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for CellSeq {
+    type Ok = CellValue;
+    type Error = SerdeError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<CellValue, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn cell_values() {
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    struct Row {
+        antenna1: i32,
+        uvw: [f64; 3],
+        data: Vec<(f32, f32)>,
+        flag_row: Option<bool>,
+        name: String,
+    }
+
+    let row = Row {
+        antenna1: 3,
+        uvw: [1., 2., 3.],
+        data: vec![(1., -1.)],
+        flag_row: None,
+        name: "ea01".to_owned(),
+    };
+
+    let columns = row.serialize(RowSerializer).unwrap();
+    assert_eq!(columns[0], ("ANTENNA1".to_owned(), CellValue::Int(3)));
+    assert_eq!(columns[3], ("FLAG_ROW".to_owned(), CellValue::Undefined));
+
+    // Reading back what was written: complex cells come out as pairs.
+    let mut read: Vec<_> = columns.clone();
+    read[2].1 = CellValue::Seq(vec![CellValue::Complex(1., -1.)]);
+    read.push(("EXTRA".to_owned(), CellValue::Float(0.5)));
+    let back: Row = Row::deserialize(RowDeserializer {
+        columns: read.clone(),
+    })
+    .unwrap();
+    assert_eq!(back, row);
+
+    let map: BTreeMap<String, f64> = BTreeMap::deserialize(RowDeserializer {
+        columns: vec![("A".to_owned(), CellValue::UInt(2))],
+    })
+    .unwrap();
+    assert_eq!(map["A"], 2.);
+
+    assert_eq!(CellValue::Int(300).to_int::<i16>(), Ok(300));
+    assert!(CellValue::Int(300).to_int::<u8>().is_err());
+    assert!(CellValue::Int(-1).to_int::<u32>().is_err());
+    assert_eq!(CellValue::Float(4.).to_int::<i32>(), Ok(4));
+    assert!(CellValue::Float(4.5).to_int::<i32>().is_err());
+    assert!(CellValue::String("x".to_owned()).to_f64().is_err());
+    assert_eq!(
+        CellValue::Seq(vec![CellValue::Int(1), CellValue::Float(2.)]).to_complex(),
+        Ok((1., 2.))
+    );
+    assert!(5u32.serialize(RowSerializer).is_err());
+}
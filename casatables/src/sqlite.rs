@@ -0,0 +1,357 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Exporting CASA tables into SQLite databases.
+
+Data sets such as Measurement Sets keep their metadata in a collection of
+small subtables (ANTENNA, FIELD, SPECTRAL_WINDOW, …). Copying them into a
+single SQLite database makes them easy to explore with ad-hoc SQL joins.
+
+Each CASA table becomes an SQLite table with the same column names, plus a
+leading `ROWNR` column giving the zero-based CASA row number, which is how
+other tables refer to its rows. Values are mapped as follows:
+
+- Booleans and integers become `INTEGER`s, floating-point numbers become
+  `REAL`s, and strings become `TEXT`.
+- Each scalar complex column `X` becomes two `REAL` columns, `X_RE` and
+  `X_IM`.
+- Array cells become `TEXT` holding the array as nested JSON lists in C
+  order, with complex values written as `[re, im]` pairs, so that SQLite's
+  JSON functions can pick them apart. Undefined cells become `NULL`.
+- Columns of other types, such as records, are omitted.
+
+This module is only available if the `sqlite` feature of this crate is
+enabled.
+
+*/
+
+use failure::Error;
+use rubbl_core::Complex;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use std::path::Path;
+
+use super::{CasaScalarData, GlueDataType, Table, TableOpenMode};
+
+/// Export the subtables of the data set at *path*, such as a Measurement
+/// Set, into the SQLite database at *db_path*, creating it if needed.
+///
+/// Returns the names of the exported subtables. It is an error if the
+/// database already contains a table with the same name as one of them.
+pub fn export_subtables<P: AsRef<Path>, Q: AsRef<Path>>(
+    path: P,
+    db_path: Q,
+) -> Result<Vec<String>, Error> {
+    let mut conn = Connection::open(db_path)?;
+    let exported = export_subtables_into(path, &mut conn)?;
+    Ok(exported.into_iter().map(|(name, _)| name).collect())
+}
+
+/// Export the subtables of the data set at *path* into the open database
+/// *conn*, as `export_subtables` does.
+///
+/// Returns the name and number of rows of each exported subtable.
+pub fn export_subtables_into<P: AsRef<Path>>(
+    path: P,
+    conn: &mut Connection,
+) -> Result<Vec<(String, u64)>, Error> {
+    let path = path.as_ref();
+    let names = Table::open(path, TableOpenMode::Read)?.table_keyword_names()?;
+    let mut exported = Vec::with_capacity(names.len());
+
+    for name in names {
+        let mut subtable = Table::open(path.join(&name), TableOpenMode::Read)?;
+        let n_rows = export_table(&mut subtable, conn, &name)?;
+        exported.push((name, n_rows));
+    }
+
+    Ok(exported)
+}
+
+/// Export a table into a new SQLite table named *name*.
+///
+/// The rows are inserted in a single transaction. Returns the number of rows
+/// exported.
+pub fn export_table(table: &mut Table, conn: &mut Connection, name: &str) -> Result<u64, Error> {
+    let mut sql_columns = vec![format!("{} INTEGER PRIMARY KEY", quote("ROWNR"))];
+    let mut column_values = Vec::new();
+    let n_rows = table.n_rows();
+
+    for col_name in table.column_names()? {
+        let desc = table.get_col_desc(&col_name)?;
+        let data_type = desc.data_type();
+
+        let sql_type = match data_type {
+            GlueDataType::TpBool
+            | GlueDataType::TpChar
+            | GlueDataType::TpUChar
+            | GlueDataType::TpShort
+            | GlueDataType::TpUShort
+            | GlueDataType::TpInt
+            | GlueDataType::TpUInt
+            | GlueDataType::TpInt64 => "INTEGER",
+            GlueDataType::TpFloat | GlueDataType::TpDouble => "REAL",
+            GlueDataType::TpComplex | GlueDataType::TpDComplex => "REAL",
+            GlueDataType::TpString => "TEXT",
+            _ => continue,
+        };
+
+        if !desc.is_scalar() {
+            sql_columns.push(format!("{} TEXT", quote(&col_name)));
+            column_values.push(read_array_column(table, &col_name, data_type)?);
+            continue;
+        }
+
+        match data_type {
+            GlueDataType::TpComplex => {
+                let values = table.get_col_as_vec::<Complex<f32>>(&col_name)?;
+                push_complex_columns(&mut sql_columns, &mut column_values, &col_name, values);
+            }
+            GlueDataType::TpDComplex => {
+                let values = table.get_col_as_vec::<Complex<f64>>(&col_name)?;
+                push_complex_columns(&mut sql_columns, &mut column_values, &col_name, values);
+            }
+            _ => {
+                sql_columns.push(format!("{} {}", quote(&col_name), sql_type));
+                column_values.push(read_scalar_column(table, &col_name, data_type)?);
+            }
+        }
+    }
+
+    let txn = conn.transaction()?;
+    txn.execute_batch(&format!(
+        "CREATE TABLE {} ({});",
+        quote(name),
+        sql_columns.join(", ")
+    ))?;
+
+    {
+        let placeholders = vec!["?"; sql_columns.len()].join(", ");
+        let mut stmt = txn.prepare(&format!(
+            "INSERT INTO {} VALUES ({})",
+            quote(name),
+            placeholders
+        ))?;
+        let mut row_values = Vec::with_capacity(sql_columns.len());
+
+        for row in 0..n_rows as usize {
+            row_values.clear();
+            row_values.push(Value::Integer(row as i64));
+
+            for values in &column_values {
+                row_values.push(values[row].clone());
+            }
+
+            stmt.execute(&row_values)?;
+        }
+    }
+
+    txn.commit()?;
+    Ok(n_rows)
+}
+
+/// Quote an identifier for use in SQL.
+fn quote(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+fn push_complex_columns<T: Into<f64> + Copy>(
+    sql_columns: &mut Vec<String>,
+    column_values: &mut Vec<Vec<Value>>,
+    col_name: &str,
+    values: Vec<Complex<T>>,
+) {
+    sql_columns.push(format!("{} REAL", quote(&format!("{}_RE", col_name))));
+    sql_columns.push(format!("{} REAL", quote(&format!("{}_IM", col_name))));
+    column_values.push(values.iter().map(|c| Value::Real(c.re.into())).collect());
+    column_values.push(values.iter().map(|c| Value::Real(c.im.into())).collect());
+}
+
+macro_rules! map_col {
+    ($table:expr, $col_name:expr, $ty:ty, $variant:ident, $conv:expr) => {
+        $table
+            .get_col_as_vec::<$ty>($col_name)?
+            .into_iter()
+            .map(|v| Value::$variant($conv(v)))
+            .collect()
+    };
+}
+
+fn read_scalar_column(
+    table: &mut Table,
+    col_name: &str,
+    data_type: GlueDataType,
+) -> Result<Vec<Value>, Error> {
+    Ok(match data_type {
+        GlueDataType::TpBool => map_col!(table, col_name, bool, Integer, |v: bool| v as i64),
+        GlueDataType::TpChar => map_col!(table, col_name, i8, Integer, |v: i8| v as i64),
+        GlueDataType::TpUChar => map_col!(table, col_name, u8, Integer, |v: u8| v as i64),
+        GlueDataType::TpShort => map_col!(table, col_name, i16, Integer, |v: i16| v as i64),
+        GlueDataType::TpUShort => map_col!(table, col_name, u16, Integer, |v: u16| v as i64),
+        GlueDataType::TpInt => map_col!(table, col_name, i32, Integer, |v: i32| v as i64),
+        GlueDataType::TpUInt => map_col!(table, col_name, u32, Integer, |v: u32| v as i64),
+        GlueDataType::TpInt64 => map_col!(table, col_name, i64, Integer, |v: i64| v),
+        GlueDataType::TpFloat => map_col!(table, col_name, f32, Real, |v: f32| v as f64),
+        GlueDataType::TpDouble => map_col!(table, col_name, f64, Real, |v: f64| v),
+        GlueDataType::TpString => map_col!(table, col_name, String, Text, |v: String| v),
+        _ => unreachable!(),
+    })
+}
+
+/// A type whose values can be written as JSON.
+trait ToJson {
+    fn write_json(&self, out: &mut String);
+}
+
+macro_rules! impl_to_json_display {
+    ($($ty:ty),*) => {
+        $(
+            impl ToJson for $ty {
+                fn write_json(&self, out: &mut String) {
+                    out.push_str(&self.to_string());
+                }
+            }
+        )*
+    }
+}
+
+impl_to_json_display! { bool, i8, u8, i16, u16, i32, u32, i64 }
+
+macro_rules! impl_to_json_float {
+    ($($ty:ty),*) => {
+        $(
+            impl ToJson for $ty {
+                fn write_json(&self, out: &mut String) {
+                    // JSON has no representation of non-finite numbers.
+                    if self.is_finite() {
+                        out.push_str(&self.to_string());
+                    } else {
+                        out.push_str("null");
+                    }
+                }
+            }
+        )*
+    }
+}
+
+impl_to_json_float! { f32, f64 }
+
+impl<T: ToJson> ToJson for Complex<T> {
+    fn write_json(&self, out: &mut String) {
+        out.push('[');
+        self.re.write_json(out);
+        out.push(',');
+        self.im.write_json(out);
+        out.push(']');
+    }
+}
+
+impl ToJson for String {
+    fn write_json(&self, out: &mut String) {
+        out.push('"');
+
+        for c in self.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            }
+        }
+
+        out.push('"');
+    }
+}
+
+/// Write *values*, an array of the given *shape* in C order, as nested JSON
+/// lists.
+fn write_nested_json<T: ToJson>(values: &[T], shape: &[u64], out: &mut String) {
+    if shape.is_empty() {
+        values[0].write_json(out);
+        return;
+    }
+
+    let n = shape[0] as usize;
+    let stride = shape[1..].iter().product::<u64>() as usize;
+    out.push('[');
+
+    for i in 0..n {
+        if i > 0 {
+            out.push(',');
+        }
+
+        write_nested_json(&values[i * stride..(i + 1) * stride], &shape[1..], out);
+    }
+
+    out.push(']');
+}
+
+fn read_array_cells<T: CasaScalarData + ToJson>(
+    table: &mut Table,
+    col_name: &str,
+) -> Result<Vec<Value>, Error> {
+    let mut result = Vec::with_capacity(table.n_rows() as usize);
+
+    for row in 0..table.n_rows() {
+        if !table.cell_is_defined(col_name, row)? {
+            result.push(Value::Null);
+            continue;
+        }
+
+        let shape = table.get_cell_shape(col_name, row)?;
+        let values = table.get_cell_as_vec::<T>(col_name, row)?;
+        let mut json = String::new();
+        write_nested_json(&values, &shape, &mut json);
+        result.push(Value::Text(json));
+    }
+
+    Ok(result)
+}
+
+fn read_array_column(
+    table: &mut Table,
+    col_name: &str,
+    data_type: GlueDataType,
+) -> Result<Vec<Value>, Error> {
+    match data_type {
+        GlueDataType::TpBool => read_array_cells::<bool>(table, col_name),
+        GlueDataType::TpChar => read_array_cells::<i8>(table, col_name),
+        GlueDataType::TpUChar => read_array_cells::<u8>(table, col_name),
+        GlueDataType::TpShort => read_array_cells::<i16>(table, col_name),
+        GlueDataType::TpUShort => read_array_cells::<u16>(table, col_name),
+        GlueDataType::TpInt => read_array_cells::<i32>(table, col_name),
+        GlueDataType::TpUInt => read_array_cells::<u32>(table, col_name),
+        GlueDataType::TpInt64 => read_array_cells::<i64>(table, col_name),
+        GlueDataType::TpFloat => read_array_cells::<f32>(table, col_name),
+        GlueDataType::TpDouble => read_array_cells::<f64>(table, col_name),
+        GlueDataType::TpComplex => read_array_cells::<Complex<f32>>(table, col_name),
+        GlueDataType::TpDComplex => read_array_cells::<Complex<f64>>(table, col_name),
+        GlueDataType::TpString => read_array_cells::<String>(table, col_name),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn nested_json() {
+    let mut out = String::new();
+    write_nested_json(&[1i32, 2, 3, 4, 5, 6], &[2, 3], &mut out);
+    assert_eq!(out, "[[1,2,3],[4,5,6]]");
+
+    out.clear();
+    write_nested_json(&[Complex::new(0.5f64, -1.)], &[1], &mut out);
+    assert_eq!(out, "[[0.5,-1]]");
+
+    out.clear();
+    write_nested_json(&["a\"b".to_owned()], &[], &mut out);
+    assert_eq!(out, "\"a\\\"b\"");
+
+    out.clear();
+    write_nested_json::<f32>(&[], &[0], &mut out);
+    assert_eq!(out, "[]");
+}
@@ -0,0 +1,306 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Describing the columns of new tables.
+
+A `TableDescription` lists the columns of a table to be created, in the
+manner of a casacore `TableDesc`: each column has a name, a data type, a
+comment, and is either a scalar or an array. Arrays may have a fixed shape,
+which every cell shares, or a fixed or unconstrained number of dimensions,
+with shapes set cell by cell. `Table::create` makes a new table from a
+description, so that pipelines can write data products from scratch rather
+than by copying a template.
+
+```ignore
+let mut desc = TableDescription::new();
+desc.add_scalar_column("TIME", GlueDataType::TpDouble, "Time of the sample")
+    .add_fixed_array_column("UVW", GlueDataType::TpDouble, &[3], "Baseline vector")
+    .add_array_column("DATA", GlueDataType::TpComplex, Some(2), "");
+let table = Table::create("out.tbl", &desc, 0)?;
+```
+
+*/
+
+use failure::{err_msg, Error};
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::{glue, init, path_as_bytes, storage_for_glue, CreateOptions, GlueDataType, Table};
+
+/// The shape of the cells of a column.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum ColumnShape {
+    Scalar,
+    Array(Option<usize>),
+    Fixed(Vec<u64>),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ColumnSpec {
+    name: String,
+    comment: String,
+    data_type: GlueDataType,
+    shape: ColumnShape,
+}
+
+/// A description of the columns of a new table.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TableDescription {
+    columns: Vec<ColumnSpec>,
+}
+
+impl TableDescription {
+    /// Create a description with no columns.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a column whose cells are scalars of type *data_type*.
+    pub fn add_scalar_column(
+        &mut self,
+        name: &str,
+        data_type: GlueDataType,
+        comment: &str,
+    ) -> &mut Self {
+        self.add(name, data_type, ColumnShape::Scalar, comment)
+    }
+
+    /// Add a column whose cells are arrays of type *data_type* with shapes
+    /// that may differ from cell to cell. If *ndim* is given, every cell
+    /// has that many dimensions.
+    pub fn add_array_column(
+        &mut self,
+        name: &str,
+        data_type: GlueDataType,
+        ndim: Option<usize>,
+        comment: &str,
+    ) -> &mut Self {
+        self.add(name, data_type, ColumnShape::Array(ndim), comment)
+    }
+
+    /// Add a column whose cells are all arrays of type *data_type* and
+    /// shape *shape*, given in row-major order as for `ndarray`.
+    pub fn add_fixed_array_column(
+        &mut self,
+        name: &str,
+        data_type: GlueDataType,
+        shape: &[u64],
+        comment: &str,
+    ) -> &mut Self {
+        self.add(name, data_type, ColumnShape::Fixed(shape.to_vec()), comment)
+    }
+
+    fn add(
+        &mut self,
+        name: &str,
+        data_type: GlueDataType,
+        shape: ColumnShape,
+        comment: &str,
+    ) -> &mut Self {
+        self.columns.push(ColumnSpec {
+            name: name.to_owned(),
+            comment: comment.to_owned(),
+            data_type: data_type,
+            shape: shape,
+        });
+        self
+    }
+
+    /// Get the number of columns described.
+    pub fn n_columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Get the names of the columns, in the order that they were added.
+    pub fn column_names(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    /// Check that the description can be turned into a table.
+    fn validate(&self) -> Result<(), Error> {
+        use glue::GlueDataType::*;
+
+        let mut seen = HashSet::new();
+
+        for c in &self.columns {
+            if c.name.is_empty() {
+                return Err(err_msg("table columns must have names"));
+            }
+
+            if !seen.insert(&c.name) {
+                return Err(err_msg(format!(
+                    "the column \"{}\" is described more than once",
+                    c.name
+                )));
+            }
+
+            match c.data_type {
+                TpBool | TpChar | TpUChar | TpShort | TpUShort | TpInt | TpUInt | TpInt64
+                | TpFloat | TpDouble | TpComplex | TpDComplex | TpString => {}
+                other => {
+                    return Err(err_msg(format!(
+                        "the column \"{}\" cannot hold values of type {}; give the element \
+                         type of array columns",
+                        c.name, other
+                    )));
+                }
+            }
+
+            match c.shape {
+                ColumnShape::Array(Some(0)) => {
+                    return Err(err_msg(format!(
+                        "the array column \"{}\" must have at least one dimension",
+                        c.name
+                    )));
+                }
+                ColumnShape::Fixed(ref shape) if shape.is_empty() || shape.contains(&0) => {
+                    return Err(err_msg(format!(
+                        "the fixed shape {:?} of the column \"{}\" is empty",
+                        shape, c.name
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the dimensionalities, fixed-shape flags, and concatenated fixed
+    /// shapes of the columns, encoded as `table_create` expects them.
+    fn glue_shapes(&self) -> (Vec<i32>, Vec<u8>, Vec<u64>) {
+        let mut ndims = Vec::with_capacity(self.columns.len());
+        let mut fixed = Vec::with_capacity(self.columns.len());
+        let mut shapes = Vec::new();
+
+        for c in &self.columns {
+            match c.shape {
+                ColumnShape::Scalar => {
+                    ndims.push(-1);
+                    fixed.push(0);
+                }
+                ColumnShape::Array(ndim) => {
+                    ndims.push(ndim.unwrap_or(0) as i32);
+                    fixed.push(0);
+                }
+                ColumnShape::Fixed(ref shape) => {
+                    ndims.push(shape.len() as i32);
+                    fixed.push(1);
+                    shapes.extend_from_slice(shape);
+                }
+            }
+        }
+
+        (ndims, fixed, shapes)
+    }
+}
+
+impl Table {
+    /// Create a new table at *path* with the columns described by *desc*.
+    ///
+    /// The table starts out with *n_rows* rows. Their scalar cells are
+    /// filled with default values, as are the array cells of fixed-shape
+    /// columns; the cells of other array columns are undefined until they
+    /// are written. It is an error if something already exists at *path*.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        desc: &TableDescription,
+        n_rows: u64,
+    ) -> Result<Self, Error> {
+        Self::create_with_options(path, desc, n_rows, &CreateOptions::default())
+    }
+
+    /// Like `create`, but with control over how the table is stored on
+    /// disk. See `CreateOptions`.
+    pub fn create_with_options<P: AsRef<Path>>(
+        path: P,
+        desc: &TableDescription,
+        n_rows: u64,
+        options: &CreateOptions,
+    ) -> Result<Self, Error> {
+        desc.validate()?;
+        init::ensure_initialized()?;
+        let path = path.as_ref();
+        let cpath = glue::StringBridge::from_bytes(path_as_bytes(path)?);
+        let mut exc_info = unsafe { std::mem::zeroed::<glue::ExcInfo>() };
+
+        let cnames: Vec<_> = desc
+            .columns
+            .iter()
+            .map(|c| glue::StringBridge::from_rust(&c.name))
+            .collect();
+        let ccomments: Vec<_> = desc
+            .columns
+            .iter()
+            .map(|c| glue::StringBridge::from_rust(&c.comment))
+            .collect();
+        let ctypes: Vec<_> = desc.columns.iter().map(|c| c.data_type).collect();
+        let (ndims, fixed, shapes) = desc.glue_shapes();
+        let (storage, block_size) = storage_for_glue(options.storage);
+
+        let handle = unsafe {
+            glue_call!(table_create(
+                &cpath,
+                desc.columns.len() as u64,
+                cnames.as_ptr(),
+                ccomments.as_ptr(),
+                ctypes.as_ptr(),
+                ndims.as_ptr(),
+                fixed.as_ptr(),
+                shapes.as_ptr(),
+                n_rows,
+                storage,
+                block_size,
+                &mut exc_info,
+            ); table = path)
+        };
+
+        if handle.is_null() {
+            return exc_info.as_err();
+        }
+
+        Ok(Table {
+            handle: handle,
+            exc_info: exc_info,
+            path: path.to_owned(),
+            dry_run: None,
+            write_behind: None,
+        })
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn description_encoding() {
+    use glue::GlueDataType::*;
+
+    let mut desc = TableDescription::new();
+    desc.add_scalar_column("TIME", TpDouble, "")
+        .add_fixed_array_column("UVW", TpDouble, &[3], "")
+        .add_array_column("DATA", TpComplex, Some(2), "")
+        .add_array_column("ANY", TpString, None, "")
+        .add_fixed_array_column("FLAG", TpBool, &[4, 64], "");
+    assert!(desc.validate().is_ok());
+    assert_eq!(
+        desc.column_names(),
+        vec!["TIME", "UVW", "DATA", "ANY", "FLAG"]
+    );
+    assert_eq!(
+        desc.glue_shapes(),
+        (vec![-1, 1, 2, 0, 2], vec![0, 1, 0, 0, 1], vec![3, 4, 64])
+    );
+
+    let mut dup = desc.clone();
+    dup.add_scalar_column("TIME", TpFloat, "");
+    assert!(dup.validate().is_err());
+
+    let mut bad = TableDescription::new();
+    bad.add_scalar_column("X", TpArrayInt, "");
+    assert!(bad.validate().is_err());
+
+    let mut empty = TableDescription::new();
+    empty.add_fixed_array_column("X", TpInt, &[2, 0], "");
+    assert!(empty.validate().is_err());
+}
@@ -0,0 +1,256 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+The `CasaTableRow` derive macro.
+
+This crate implements `#[derive(CasaTableRow)]`, which maps a struct with
+named fields onto the rows of a CASA table: each field becomes a column
+whose type is determined by the field's type, through the
+`rubbl_casatables::CasaDataType` trait. It is not meant to be used
+directly; enable the `derive` feature of `rubbl_casatables` and use the
+macro re-exported from there. See the `rubbl_casatables::rows` module for
+documentation.
+
+*/
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use syn::{Data, DeriveInput, Fields, Lit, Meta, NestedMeta};
+
+/// One field of a struct, along with the column that it maps to.
+struct ColumnField {
+    ident: syn::Ident,
+    ty: syn::Type,
+    column: String,
+    shape: Option<Vec<u64>>,
+}
+
+#[proc_macro_derive(CasaTableRow, attributes(casa))]
+pub fn derive_casa_table_row(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = column_fields(input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let columns = fields.iter().map(|f| {
+        let column = &f.column;
+        let ty = &f.ty;
+        let shape = match f.shape {
+            Some(ref dims) => quote!(Some(&[#(#dims),*])),
+            None => quote!(None),
+        };
+
+        quote! {
+            ::rubbl_casatables::RowColumn {
+                name: #column,
+                data_type: <#ty as ::rubbl_casatables::CasaDataType>::DATA_TYPE,
+                shape: #shape,
+            }
+        }
+    });
+
+    let reads = fields.iter().map(|f| {
+        let ident = &f.ident;
+        let column = &f.column;
+        quote!(#ident: table.get_cell(#column, row)?)
+    });
+
+    let writes = fields.iter().map(|f| {
+        let ident = &f.ident;
+        let column = &f.column;
+        quote!(table.put_cell(#column, row, &self.#ident)?;)
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::rubbl_casatables::CasaTableRow for #name #ty_generics #where_clause {
+            fn columns() -> ::std::vec::Vec<::rubbl_casatables::RowColumn> {
+                vec![#(#columns),*]
+            }
+
+            fn read_row(
+                table: &mut ::rubbl_casatables::Table,
+                row: u64,
+            ) -> ::rubbl_casatables::rows::RowResult<Self> {
+                Ok(#name { #(#reads,)* })
+            }
+
+            fn write_row(
+                &self,
+                table: &mut ::rubbl_casatables::Table,
+                row: u64,
+            ) -> ::rubbl_casatables::rows::RowResult<()> {
+                #(#writes)*
+                Ok(())
+            }
+        }
+    })
+}
+
+/// Work out the columns that the fields of the struct *input* map to.
+fn column_fields(input: &DeriveInput) -> syn::Result<Vec<ColumnField>> {
+    let fields = match input.data {
+        Data::Struct(ref s) => match s.fields {
+            Fields::Named(ref f) => &f.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    input,
+                    "CasaTableRow can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                input,
+                "CasaTableRow can only be derived for structs",
+            ));
+        }
+    };
+
+    let mut result = Vec::with_capacity(fields.len());
+
+    for field in fields {
+        let ident = field.ident.clone().unwrap();
+        let mut column = ident.to_string().trim_start_matches("r#").to_uppercase();
+        let mut shape = None;
+
+        for attr in &field.attrs {
+            if !attr.path.is_ident("casa") {
+                continue;
+            }
+
+            let items = match attr.parse_meta()? {
+                Meta::List(list) => list.nested,
+                other => {
+                    return Err(syn::Error::new_spanned(
+                        other,
+                        "expected #[casa(name = \"...\")] or #[casa(shape = \"...\")]",
+                    ));
+                }
+            };
+
+            for item in items {
+                let nv = match item {
+                    NestedMeta::Meta(Meta::NameValue(ref nv)) => nv.clone(),
+                    ref other => {
+                        return Err(syn::Error::new_spanned(
+                            other,
+                            "unrecognized casa attribute",
+                        ));
+                    }
+                };
+
+                let value = match nv.lit {
+                    Lit::Str(ref s) => s.value(),
+                    ref other => {
+                        return Err(syn::Error::new_spanned(
+                            other,
+                            "the value of a casa attribute must be a string",
+                        ));
+                    }
+                };
+
+                if nv.path.is_ident("name") {
+                    column = value;
+                } else if nv.path.is_ident("shape") {
+                    shape = Some(parse_shape(&value).ok_or_else(|| {
+                        syn::Error::new_spanned(
+                            &nv.lit,
+                            "expected a shape of comma-separated positive integers",
+                        )
+                    })?);
+                } else {
+                    return Err(syn::Error::new_spanned(
+                        &nv.path,
+                        "unrecognized casa attribute",
+                    ));
+                }
+            }
+        }
+
+        result.push(ColumnField {
+            ident: ident,
+            ty: field.ty.clone(),
+            column: column,
+            shape: shape,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Parse a shape such as `"4, 2"`.
+fn parse_shape(text: &str) -> Option<Vec<u64>> {
+    let dims: Option<Vec<u64>> = text
+        .split(',')
+        .map(|s| s.trim().parse().ok().filter(|&n| n > 0))
+        .collect();
+
+    dims.filter(|d| !d.is_empty())
+}
+
+#[cfg(test)]
+#[test]
+fn field_mapping() {
+    let input: DeriveInput = syn::parse_quote! {
+        struct Row<T> {
+            antenna1: i32,
+            #[casa(name = "UVW")]
+            uvw_m: Vec<f64>,
+            #[casa(shape = "4, 2")]
+            flag: Vec<bool>,
+            r#type: T,
+        }
+    };
+
+    let fields = column_fields(&input).unwrap();
+    let columns: Vec<_> = fields
+        .iter()
+        .map(|f| (&f.column[..], f.shape.clone()))
+        .collect();
+    assert_eq!(
+        columns,
+        vec![
+            ("ANTENNA1", None),
+            ("UVW", None),
+            ("FLAG", Some(vec![4, 2])),
+            ("TYPE", None),
+        ]
+    );
+    assert!(expand(&input).is_ok());
+
+    assert!(column_fields(&syn::parse_quote!(
+        struct Tuple(i32);
+    ))
+    .is_err());
+    assert!(column_fields(&syn::parse_quote!(
+        struct S {
+            #[casa(shape = "0")]
+            x: Vec<i32>,
+        }
+    ))
+    .is_err());
+    assert!(column_fields(&syn::parse_quote!(
+        struct S {
+            #[casa(size = "1")]
+            x: i32,
+        }
+    ))
+    .is_err());
+    assert_eq!(parse_shape(" 3 "), Some(vec![3]));
+    assert_eq!(parse_shape(""), None);
+}
@@ -3,6 +3,8 @@
 
 extern crate cc;
 
+use std::env;
+
 const FILES: &[&str] = &[
     "casacore/casa/Arrays/Array2.cc",
     "casacore/casa/Arrays/Array2Math.cc",
@@ -63,7 +65,6 @@ const FILES: &[&str] = &[
     "casacore/casa/IO/BucketBase.cc",
     "casacore/casa/IO/BucketBuffered.cc",
     "casacore/casa/IO/BucketCache.cc",
-    "casacore/casa/IO/BucketFile.cc",
     "casacore/casa/IO/BucketMapped.cc",
     "casacore/casa/IO/ByteIO.cc",
     "casacore/casa/IO/ByteSink.cc",
@@ -71,22 +72,12 @@ const FILES: &[&str] = &[
     "casacore/casa/IO/ByteSource.cc",
     "casacore/casa/IO/CanonicalIO.cc",
     "casacore/casa/IO/ConversionIO.cc",
-    "casacore/casa/IO/FilebufIO.cc",
-    "casacore/casa/IO/FiledesIO.cc",
-    "casacore/casa/IO/FileLocker.cc",
     "casacore/casa/IO/LECanonicalIO.cc",
-    "casacore/casa/IO/LockFile.cc",
     "casacore/casa/IO/MemoryIO.cc",
     "casacore/casa/IO/MFFileIO.cc",
-    "casacore/casa/IO/MMapfdIO.cc",
     "casacore/casa/IO/MMapIO.cc",
-    "casacore/casa/IO/MultiFileBase.cc",
-    "casacore/casa/IO/MultiFile.cc",
     "casacore/casa/IO/MultiHDF5.cc",
     "casacore/casa/IO/RawIO.cc",
-    "casacore/casa/IO/RegularFileIO.cc",
-    "casacore/casa/IO/StreamIO.cc",
-    "casacore/casa/IO/TapeIO.cc",
     "casacore/casa/IO/TypeIO.cc",
     "casacore/casa/Logging/LogFilter.cc",
     "casacore/casa/Logging/LogFilterInterface.cc",
@@ -102,30 +93,17 @@ const FILES: &[&str] = &[
     "casacore/casa/OS/CanonicalDataConversion.cc",
     "casacore/casa/OS/Conversion.cc",
     "casacore/casa/OS/DataConversion.cc",
-    "casacore/casa/OS/Directory.cc",
-    "casacore/casa/OS/DirectoryIterator.cc",
     "casacore/casa/OS/DOos.cc",
-    "casacore/casa/OS/DynLib.cc",
     "casacore/casa/OS/EnvVar.cc",
-    "casacore/casa/OS/File.cc",
-    "casacore/casa/OS/HostInfo.cc",
     "casacore/casa/OS/IBMConversion.cc",
     "casacore/casa/OS/IBMDataConversion.cc",
     "casacore/casa/OS/LECanonicalConversion.cc",
     "casacore/casa/OS/LECanonicalDataConversion.cc",
     "casacore/casa/OS/LittleEndianConversion.cc",
-    "casacore/casa/OS/malloc.cc",
-    "casacore/casa/OS/Memory.cc",
-    "casacore/casa/OS/MemoryTrace.cc",
     "casacore/casa/OS/ModcompConversion.cc",
     "casacore/casa/OS/ModcompDataConversion.cc",
     "casacore/casa/OS/Mutex.cc",
-    "casacore/casa/OS/Path.cc",
-    "casacore/casa/OS/PrecTimer.cc",
     "casacore/casa/OS/RawDataConversion.cc",
-    "casacore/casa/OS/RegularFile.cc",
-    "casacore/casa/OS/SymLink.cc",
-    "casacore/casa/OS/Time.cc",
     "casacore/casa/OS/Timer.cc",
     "casacore/casa/OS/VAXConversion.cc",
     "casacore/casa/OS/VAXDataConversion.cc",
@@ -167,7 +145,6 @@ const FILES: &[&str] = &[
     "casacore/casa/System/AipsrcVBool.cc",
     "casacore/casa/System/AipsrcVString.cc",
     "casacore/casa/System/AppInfo.cc",
-    "casacore/casa/System/Casarc.cc",
     "casacore/casa/System/Choice.cc",
     "casacore/casa/System/ObjectID2.cc",
     "casacore/casa/System/ObjectID.cc",
@@ -295,26 +272,73 @@ const FILES: &[&str] = &[
     "casacore/tables/Tables/TableIter.cc",
     "casacore/tables/Tables/TableKeyword.cc",
     "casacore/tables/Tables/TableLock.cc",
-    "casacore/tables/Tables/TableLockData.cc",
     "casacore/tables/Tables/TableLocker.cc",
     "casacore/tables/Tables/TableRecord.cc",
     "casacore/tables/Tables/TableRecordRep.cc",
     "casacore/tables/Tables/TableRow.cc",
     "casacore/tables/Tables/TableSyncData.cc",
     "casacore/tables/Tables/TableTrace.cc",
+];
+
+/// Sources that use POSIX APIs directly: file descriptors, memory mapping,
+/// `fcntl` locks, directory iteration, and the like. They have no Win32
+/// implementation yet.
+const POSIX_FILES: &[&str] = &[
+    "casacore/casa/IO/BucketFile.cc",
+    "casacore/casa/IO/FilebufIO.cc",
+    "casacore/casa/IO/FiledesIO.cc",
+    "casacore/casa/IO/FileLocker.cc",
+    "casacore/casa/IO/LockFile.cc",
+    "casacore/casa/IO/MMapfdIO.cc",
+    "casacore/casa/IO/MultiFileBase.cc",
+    "casacore/casa/IO/MultiFile.cc",
+    "casacore/casa/IO/RegularFileIO.cc",
+    "casacore/casa/IO/StreamIO.cc",
+    "casacore/casa/IO/TapeIO.cc",
+    "casacore/casa/OS/Directory.cc",
+    "casacore/casa/OS/DirectoryIterator.cc",
+    "casacore/casa/OS/DynLib.cc",
+    "casacore/casa/OS/File.cc",
+    "casacore/casa/OS/HostInfo.cc",
+    "casacore/casa/OS/malloc.cc",
+    "casacore/casa/OS/Memory.cc",
+    "casacore/casa/OS/MemoryTrace.cc",
+    "casacore/casa/OS/Path.cc",
+    "casacore/casa/OS/PrecTimer.cc",
+    "casacore/casa/OS/RegularFile.cc",
+    "casacore/casa/OS/SymLink.cc",
+    "casacore/casa/OS/Time.cc",
+    "casacore/casa/System/Casarc.cc",
+    "casacore/tables/Tables/TableLockData.cc",
     "casacore/tables/Tables/TabPath.cc",
 ];
 
 fn main() {
+    // casacore's I/O layer is built on POSIX APIs, so it can't be built for
+    // Windows. Fail clearly instead of with hundreds of compiler errors. On
+    // Linux and macOS, casacore's `aipsenv.h` configures itself for the
+    // platform automatically.
+    let target_family = env::var("CARGO_CFG_TARGET_FAMILY").unwrap_or_default();
+
+    if target_family != "unix" {
+        panic!(
+            "the casacore table code bundled in rubbl_casatables_impl can only be built \
+             for Unix-like targets: {} of its sources, listed in POSIX_FILES in its \
+             build.rs, use POSIX APIs and have not been ported",
+            POSIX_FILES.len()
+        );
+    }
+
     cc::Build::new()
         .cpp(true)
         .warnings(true)
         .flag_if_supported("-std=c++11")
         .include(".")
         .files(FILES)
+        .files(POSIX_FILES)
         .compile("libcasatables_impl.a");
 
-    for file in FILES {
+    for file in FILES.iter().chain(POSIX_FILES) {
         println!("cargo:rerun-if-changed={}", file);
     }
 }
@@ -74,6 +74,12 @@ Bool Aipsrc::find(String &value,
   return find(value, keyword, 0);
 }
 
+Bool Aipsrc::findNoParse(String &value, const String &keyword,
+			 uInt start) {
+  // rubbl customization: as with find(), never use any config files
+  return find(value, keyword, start);
+}
+
 Bool Aipsrc::findNoHome(String &value,
 			const String &keyword) {
   return find(value, keyword, fileEnd);
@@ -16,18 +16,26 @@ extern crate clap;
 extern crate failure_derive;
 extern crate failure;
 extern crate rubbl_core;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
 
-use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
 use failure::Error;
 use rubbl_core::notify::{ClapNotificationArgsExt, NotificationBackend};
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report, FORMAT_ENV_VAR};
 use rubbl_core::Result;
 use std::collections::BTreeSet;
 use std::env;
 use std::fs;
+use std::io::{self, Write};
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process;
 
+mod pipeline;
+
 // Some error help.
 
 #[derive(Fail, Debug)]
@@ -40,9 +48,17 @@ fn main() {
     process::exit(rubbl_core::notify::run_with_notifications(
         matches,
         |matches, nbe| -> Result<i32> {
+            // Sub-commands are separate programs, so we pass the output format
+            // along through the environment.
+            if let Some(name) = matches.value_of("output_format") {
+                env::set_var(FORMAT_ENV_VAR, name);
+            }
+
             match matches.subcommand() {
                 ("help", Some(m)) => do_help(m, nbe),
+                ("completions", Some(m)) => do_completions(m, nbe),
                 ("list", Some(m)) => do_list(m, nbe),
+                ("pipeline", Some(m)) => pipeline::do_pipeline(m, nbe),
                 (external, Some(m)) => do_external(external, m, nbe),
                 (_, None) => {
                     // No sub-command provided; can't use do_help() since it wants sub-matches.
@@ -62,12 +78,24 @@ fn make_app<'a, 'b>() -> App<'a, 'b> {
         .setting(AppSettings::AllowExternalSubcommands)
         .setting(AppSettings::DisableHelpSubcommand)
         .rubbl_notify_args()
+        .rubbl_report_args()
         .subcommand(
             SubCommand::with_name("help")
                 .about("Get help information for sub-commands")
                 .arg(Arg::with_name("command").help("The name of a sub-command to get help for")),
         )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Generate a shell completion script")
+                .arg(
+                    Arg::with_name("SHELL")
+                        .help("The shell to generate completions for")
+                        .possible_values(&Shell::variants())
+                        .required(true),
+                ),
+        )
         .subcommand(SubCommand::with_name("list").about("List the available sub-commands"))
+        .subcommand(pipeline::make_subcommand())
         .help(
             r#"rubbl -- dispatcher for command-line access to Rubbl tools
 
@@ -75,16 +103,19 @@ USAGE:
     rubbl [GLOBAL-OPTIONS] [SUBCOMMAND] [SUBCOMMAND arguments ...]
 
 GLOBAL OPTIONS:
-    -h, --help     Print help information
-    -V, --version  Print version information
+    -h, --help        Print help information
+    -V, --version     Print version information
+    --format FORMAT   Print results as "text" (default) or "json"
 
 SUBCOMMANDS:
     Available sub-commands depend on which Rubbl tools you have installed.
     Use "rubbl list" to see what is available and "rubbl help" to get help
     on their usage. Built-in sub-commands are:
 
-    help    Get help on sub-command usage
-    list    List the available sub-commands
+    completions  Generate a shell completion script
+    help         Get help on sub-command usage
+    list         List the available sub-commands
+    pipeline     Run a sequence of sub-commands declared in a TOML file
 "#,
         )
 }
@@ -92,11 +123,16 @@ SUBCOMMANDS:
 /// Get help on a subcommand, or on the main program.
 fn do_help(matches: &ArgMatches, _nbe: &mut NotificationBackend) -> Result<i32> {
     match matches.value_of("command") {
-        None | Some("help") | Some("list") => {
+        None | Some("completions") | Some("help") | Some("list") => {
             make_app().print_long_help()?;
             Ok(0)
         }
 
+        Some("pipeline") => {
+            pipeline::make_subcommand().print_long_help()?;
+            Ok(0)
+        }
+
         Some(cmd) => {
             // If the function returns, something went wrong by definition.
             Err(try_exec_subcommand(cmd, &["--help"]))
@@ -104,14 +140,55 @@ fn do_help(matches: &ArgMatches, _nbe: &mut NotificationBackend) -> Result<i32>
     }
 }
 
-/// Print out a list of the available sub-commands.
-fn do_list(_matches: &ArgMatches, _nbe: &mut NotificationBackend) -> Result<i32> {
-    println!("Currently available \"rubbl\" sub-commands:");
+/// Print a shell completion script to standard output.
+///
+/// External sub-commands found on `$PATH` at the time the script is
+/// generated are included, so it should be regenerated when new Rubbl tools
+/// are installed.
+fn do_completions(matches: &ArgMatches, _nbe: &mut NotificationBackend) -> Result<i32> {
+    let shell = value_t!(matches, "SHELL", Shell).unwrap_or_else(|e| e.exit());
+    let mut app = make_app();
 
     for command in list_commands() {
-        println!("    {}", command);
+        if !BUILTIN_COMMANDS.contains(&command.as_str()) {
+            app = app.subcommand(
+                SubCommand::with_name(&command)
+                    .about("External sub-command")
+                    .setting(AppSettings::TrailingVarArg)
+                    .arg(Arg::with_name("args").multiple(true)),
+            );
+        }
+    }
+
+    app.gen_completions_to("rubbl", shell, &mut io::stdout());
+    Ok(0)
+}
+
+/// The list of available sub-commands.
+#[derive(Debug, Serialize)]
+struct CommandList {
+    commands: Vec<String>,
+}
+
+impl Report for CommandList {
+    fn write_text(&self, dest: &mut Write) -> Result<()> {
+        writeln!(dest, "Currently available \"rubbl\" sub-commands:")?;
+
+        for command in &self.commands {
+            writeln!(dest, "    {}", command)?;
+        }
+
+        Ok(())
     }
+}
 
+/// Print out a list of the available sub-commands.
+fn do_list(matches: &ArgMatches, _nbe: &mut NotificationBackend) -> Result<i32> {
+    let report = CommandList {
+        commands: list_commands().into_iter().collect(),
+    };
+
+    report.emit(OutputFormat::from_clap(matches), &mut io::stdout())?;
     Ok(0)
 }
 
@@ -129,13 +206,7 @@ fn do_external(cmd: &str, matches: &ArgMatches, _nbe: &mut NotificationBackend)
 /// Try to re-execute the process using the executable corresponding to the
 /// named sub-command. If this function returns, something went wrong.
 fn try_exec_subcommand(cmd: &str, args: &[&str]) -> Error {
-    let command_exe = format!("rubbl-{}{}", cmd, env::consts::EXE_SUFFIX);
-    let path = search_directories()
-        .iter()
-        .map(|dir| dir.join(&command_exe))
-        .find(|file| is_executable(file));
-
-    let command = match path {
+    let command = match find_subcommand(cmd) {
         Some(command) => command,
         None => {
             return NoSuchSubcommandError(cmd.to_owned()).into();
@@ -145,6 +216,18 @@ fn try_exec_subcommand(cmd: &str, args: &[&str]) -> Error {
     process::Command::new(command).args(args).exec().into()
 }
 
+/// Find the executable corresponding to the named external sub-command.
+fn find_subcommand(cmd: &str) -> Option<PathBuf> {
+    let command_exe = format!("rubbl-{}{}", cmd, env::consts::EXE_SUFFIX);
+    search_directories()
+        .iter()
+        .map(|dir| dir.join(&command_exe))
+        .find(|file| is_executable(file))
+}
+
+/// The sub-commands implemented by this program itself.
+const BUILTIN_COMMANDS: &[&str] = &["completions", "help", "list", "pipeline"];
+
 // Lots of copy/paste from cargo:
 
 fn list_commands() -> BTreeSet<String> {
@@ -173,8 +256,9 @@ fn list_commands() -> BTreeSet<String> {
         }
     }
 
-    commands.insert("help".to_owned());
-    commands.insert("list".to_owned());
+    for command in BUILTIN_COMMANDS {
+        commands.insert((*command).to_owned());
+    }
 
     commands
 }
@@ -0,0 +1,328 @@
+// Copyright 2017-2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! The "pipeline" built-in sub-command.
+
+This runs a sequence of Rubbl sub-commands declared in a TOML file, so that
+batch processing can be described declaratively rather than with long
+command lines. A pipeline file looks like:
+
+```toml
+# Retry I/O that fails transiently up to 10 times, and read and write each
+# file at no more than 200 MiB per second.
+[io]
+retries = 10
+rate_limit = "200M"
+
+[[step]]
+command = "select"
+args = ["in.ms", "-o", "subset.ms"]
+
+# Data selection for this step.
+[step.select]
+antenna = "!1&&&"
+spw = "0,2"
+
+[[step]]
+command = "average"
+args = ["--timebin", "6", "--chanbin", "8", "subset.ms", "-o", "averaged.ms"]
+
+[[step]]
+command = "flag"
+args = ["--auto-channels", "averaged.ms", "--in-place"]
+
+[[step]]
+command = "waterfall"
+args = ["averaged.ms", "plots/"]
+```
+
+Each step invokes the external `rubbl-<command>` program. A `[select]` table
+at the top level gives a data selection for every step that does not
+override it. Selection expressions are passed to a step as `--antenna`,
+`--spw`, and `--timerange` options, so only steps whose programs accept those
+options, such as `select`, `flag`, and `waterfall`, may have a selection.
+Before anything is run, the expressions are checked and each program's
+`--help` output is examined to make sure that it takes the options that the
+pipeline would pass it. The steps run in order and the pipeline stops at
+the first one that fails. With `--dry-run`, that option is passed along to
+every step, which must accept it and should then report what it would
+change without changing anything. Since nothing is written, a step that
+reads what an earlier step would have written fails in a dry run unless it
+already exists.
+The `[io]` settings are passed to every step through the `RUBBL_IO_RETRIES`
+and `RUBBL_IO_RATE_LIMIT` environment variables. The tools that do their own
+file I/O obey them: `imgcoadd` and `imgspindex` for their FITS inputs and
+outputs, and `tableimport` for its data file. CASA tables are read and
+written by casacore, which the settings do not affect.
+
+*/
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use failure::err_msg;
+use rubbl_core::budget::MemoryBudget;
+use rubbl_core::dryrun::{dry_run_requested, ClapDryRunArgsExt};
+use rubbl_core::io::{IO_RATE_LIMIT_ENV_VAR, IO_RETRIES_ENV_VAR};
+use rubbl_core::notify::{NotificationBackend, NotificationKind};
+use rubbl_core::select::Selection;
+use rubbl_core::{Result, ResultExt};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::process;
+use toml;
+
+use super::{find_subcommand, NoSuchSubcommandError};
+
+/// The top-level structure of a pipeline file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipelineConfig {
+    #[serde(default)]
+    select: SelectConfig,
+
+    #[serde(default)]
+    io: IoConfig,
+
+    #[serde(default, rename = "step")]
+    steps: Vec<StepConfig>,
+}
+
+/// Data-selection settings, at either the pipeline or step level.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SelectConfig {
+    antenna: Option<String>,
+    spw: Option<String>,
+    timerange: Option<String>,
+}
+
+impl SelectConfig {
+    /// Combine step-level settings with pipeline-level defaults.
+    fn overlay(&self, defaults: &SelectConfig) -> SelectConfig {
+        SelectConfig {
+            antenna: self.antenna.clone().or_else(|| defaults.antenna.clone()),
+            spw: self.spw.clone().or_else(|| defaults.spw.clone()),
+            timerange: self
+                .timerange
+                .clone()
+                .or_else(|| defaults.timerange.clone()),
+        }
+    }
+
+    /// Check that the expressions parse, and convert them into command-line
+    /// arguments.
+    fn to_args(&self) -> Result<Vec<String>> {
+        let mut sel = Selection::new();
+        let mut args = Vec::new();
+
+        if let Some(ref expr) = self.antenna {
+            sel.set_antenna(expr)?;
+            args.push("--antenna".to_owned());
+            args.push(expr.clone());
+        }
+
+        if let Some(ref expr) = self.spw {
+            sel.set_spw(expr)?;
+            args.push("--spw".to_owned());
+            args.push(expr.clone());
+        }
+
+        if let Some(ref expr) = self.timerange {
+            sel.set_timerange(expr)?;
+            args.push("--timerange".to_owned());
+            args.push(expr.clone());
+        }
+
+        Ok(args)
+    }
+}
+
+/// I/O settings for every step of a pipeline.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IoConfig {
+    retries: Option<u32>,
+    rate_limit: Option<String>,
+}
+
+impl IoConfig {
+    /// Check the settings, and convert them into environment variables.
+    fn to_env(&self) -> Result<Vec<(&'static str, String)>> {
+        let mut vars = Vec::new();
+
+        if let Some(n) = self.retries {
+            vars.push((IO_RETRIES_ENV_VAR, n.to_string()));
+        }
+
+        if let Some(ref text) = self.rate_limit {
+            MemoryBudget::parse(text)?;
+            vars.push((IO_RATE_LIMIT_ENV_VAR, text.clone()));
+        }
+
+        Ok(vars)
+    }
+}
+
+/// One step of a pipeline.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StepConfig {
+    command: String,
+
+    #[serde(default)]
+    args: Vec<String>,
+
+    #[serde(default)]
+    select: SelectConfig,
+}
+
+/// Check that the program *exe* accepts the options among *args* that the
+/// pipeline adds, by looking for them in its `--help` output.
+fn check_options(exe: &Path, args: &[String]) -> Result<()> {
+    let options: Vec<&String> = args.iter().filter(|a| a.starts_with("--")).collect();
+
+    if options.is_empty() {
+        return Ok(());
+    }
+
+    let output = process::Command::new(exe)
+        .arg("--help")
+        .output()
+        .with_context(|_| format!("failed to launch {}", exe.display()))?;
+    let help = String::from_utf8_lossy(&output.stdout);
+    let accepted = help_options(&help);
+
+    for option in options {
+        if !accepted.contains(option.as_str()) {
+            return Err(err_msg(format!(
+                "{} does not accept the {} option",
+                exe.display(),
+                option
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the long options mentioned in a program's `--help` output. The text
+/// is split into whole tokens so that, say, `--spw-map` does not count as
+/// `--spw`.
+fn help_options(help: &str) -> HashSet<&str> {
+    help.split(|c: char| c.is_whitespace() || c == ',' || c == '=')
+        .map(|t| t.trim_matches(|c| c == '[' || c == ']' || c == '(' || c == ')'))
+        .filter(|t| t.starts_with("--"))
+        .collect()
+}
+
+pub fn make_subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("pipeline")
+        .about("Run a sequence of sub-commands declared in a TOML file")
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run a pipeline")
+                .rubbl_dry_run_args()
+                .arg(
+                    Arg::with_name("CONFIG")
+                        .help("The path to the pipeline TOML file")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+}
+
+pub fn do_pipeline(matches: &ArgMatches, nbe: &mut NotificationBackend) -> Result<i32> {
+    match matches.subcommand() {
+        ("run", Some(m)) => do_run(m, nbe),
+        _ => {
+            make_subcommand().print_long_help()?;
+            Ok(0)
+        }
+    }
+}
+
+fn do_run(matches: &ArgMatches, nbe: &mut NotificationBackend) -> Result<i32> {
+    let path = matches.value_of_os("CONFIG").unwrap();
+    let dry_run = dry_run_requested(matches);
+    let text = fs::read_to_string(path)
+        .with_context(|_| format!("failed to read pipeline file {}", path.to_string_lossy()))?;
+    let config: PipelineConfig = toml::from_str(&text)
+        .with_context(|_| format!("failed to parse pipeline file {}", path.to_string_lossy()))?;
+
+    // Check everything up front so that a typo in step 5 doesn't bite us
+    // after steps 1 through 4 have already done a bunch of work.
+
+    let step_env = config
+        .io
+        .to_env()
+        .with_context(|_| "in the pipeline's [io] settings")?;
+
+    let mut plan = Vec::new();
+
+    for (num, step) in config.steps.iter().enumerate() {
+        let exe = find_subcommand(&step.command)
+            .ok_or_else(|| NoSuchSubcommandError(step.command.clone()))
+            .with_context(|_| format!("in pipeline step #{}", num + 1))?;
+
+        let mut args = step
+            .select
+            .overlay(&config.select)
+            .to_args()
+            .with_context(|_| format!("in pipeline step #{}", num + 1))?;
+        if dry_run {
+            args.push("--dry-run".to_owned());
+        }
+
+        check_options(&exe, &args).with_context(|_| format!("in pipeline step #{}", num + 1))?;
+        args.extend(step.args.iter().cloned());
+        plan.push((exe, args));
+    }
+
+    for (num, (exe, args)) in plan.into_iter().enumerate() {
+        let step = &config.steps[num];
+        nbe.notify(
+            NotificationKind::Note,
+            format_args!(
+                "pipeline step #{}: {} {}",
+                num + 1,
+                step.command,
+                args.join(" ")
+            ),
+            None,
+        );
+
+        let status = process::Command::new(&exe)
+            .args(&args)
+            .envs(step_env.iter().cloned())
+            .status()
+            .with_context(|_| format!("failed to launch {}", exe.display()))?;
+
+        if !status.success() {
+            return Err(err_msg(format!(
+                "pipeline step #{} (\"{}\") failed: {}",
+                num + 1,
+                step.command,
+                status
+            )));
+        }
+    }
+
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn help_options_match_whole_tokens() {
+        let help = "USAGE:\n    rubbl-x [FLAGS] [--dry-run] <IN>\n\n\
+                    OPTIONS:\n    -o, --output <PATH>    Output\n        \
+                    --spw-map=<MAP>    Remap windows\n";
+        let opts = help_options(help);
+        assert!(opts.contains("--output"));
+        assert!(opts.contains("--spw-map"));
+        assert!(opts.contains("--dry-run"));
+        assert!(!opts.contains("--spw"));
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Limiting the memory used by chunked operations.
+
+Operations that stream through large data sets (chunked column readers,
+averagers, …) process a certain number of rows at a time. Rather than asking
+users to guess a good number of rows, these operations take a `MemoryBudget`
+and derive their chunk sizes from it and the width of the rows that they are
+reading: the user says "use at most 8 GiB" and the operation figures out the
+rest.
+
+*/
+
+use clap;
+
+use super::Result;
+
+/// The budget used if the user does not specify one: 1 GiB.
+pub const DEFAULT_MEMORY_BUDGET: u64 = 1 << 30;
+
+/// An error type for when a memory size cannot be parsed.
+#[derive(Fail, Debug)]
+#[fail(
+    display = "cannot parse \"{}\" as a memory size (expected something like \"512M\" or \"8GB\")",
+    _0
+)]
+pub struct MemoryBudgetParseError(pub String);
+
+/// A limit on the amount of memory that an operation may use for its data
+/// buffers.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct MemoryBudget {
+    bytes: u64,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        MemoryBudget::new(DEFAULT_MEMORY_BUDGET)
+    }
+}
+
+impl MemoryBudget {
+    /// Create a budget of the specified number of bytes.
+    pub fn new(bytes: u64) -> Self {
+        MemoryBudget { bytes: bytes }
+    }
+
+    /// Parse a memory size as given by a user.
+    ///
+    /// The size is a number followed by an optional suffix `K`, `M`, `G`,
+    /// or `T`, which may be followed by `B` or `iB` and is not
+    /// case-sensitive. All suffixes are treated as powers of 1024, so both
+    /// "8G" and "8GB" mean 8 GiB. A bare number is a count of bytes.
+    pub fn parse(text: &str) -> Result<Self> {
+        let err = || MemoryBudgetParseError(text.to_owned());
+        let trimmed = text.trim();
+        let split = trimmed
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or_else(|| trimmed.len());
+        let (number, suffix) = trimmed.split_at(split);
+
+        let number: f64 = number.parse().map_err(|_| err())?;
+
+        let suffix = suffix.trim().to_lowercase();
+        let suffix = suffix.trim_end_matches("ib").trim_end_matches('b');
+
+        let scale = match suffix {
+            "" => 1u64,
+            "k" => 1 << 10,
+            "m" => 1 << 20,
+            "g" => 1 << 30,
+            "t" => 1 << 40,
+            _ => return Err(err().into()),
+        };
+
+        let bytes = number * scale as f64;
+
+        if !bytes.is_finite() || bytes < 1. || bytes > u64::max_value() as f64 {
+            return Err(err().into());
+        }
+
+        Ok(MemoryBudget::new(bytes as u64))
+    }
+
+    /// Get the number of bytes in this budget.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Split this budget into *n* equal parts, e.g. for operations that
+    /// need to hold several buffers at once.
+    pub fn divide(&self, n: u64) -> Self {
+        MemoryBudget::new(self.bytes / ::std::cmp::max(n, 1))
+    }
+
+    /// Compute how many rows to process at once, given the number of bytes
+    /// needed to hold one row and the total number of rows.
+    ///
+    /// At least one row is always processed, even if a single row exceeds
+    /// the budget. The result never exceeds *n_rows* unless *n_rows* is
+    /// zero.
+    pub fn rows_per_chunk(&self, row_width: u64, n_rows: u64) -> u64 {
+        let n = if row_width == 0 {
+            n_rows
+        } else {
+            self.bytes / row_width
+        };
+
+        ::std::cmp::max(::std::cmp::min(n, n_rows), 1)
+    }
+
+    /// Determine the budget from command-line arguments added with
+    /// `ClapMemoryBudgetArgsExt::rubbl_memory_budget_args`, falling back to
+    /// the default.
+    pub fn from_clap(matches: &clap::ArgMatches) -> Result<Self> {
+        match matches.value_of("memory_budget") {
+            Some(text) => MemoryBudget::parse(text),
+            None => Ok(MemoryBudget::default()),
+        }
+    }
+}
+
+/// Extend a `clap::App` with the standard memory-budget argument.
+pub trait ClapMemoryBudgetArgsExt {
+    /// Add the `--memory` argument to this App.
+    fn rubbl_memory_budget_args(self) -> Self;
+}
+
+impl<'a, 'b> ClapMemoryBudgetArgsExt for clap::App<'a, 'b> {
+    fn rubbl_memory_budget_args(self) -> Self {
+        self.arg(
+            clap::Arg::with_name("memory_budget")
+                .long("memory")
+                .value_name("SIZE")
+                .help("The maximum amount of memory to use for data buffers, e.g. \"8G\""),
+        )
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn memory_budget() {
+    assert_eq!(MemoryBudget::parse("4096").unwrap().bytes(), 4096);
+    assert_eq!(MemoryBudget::parse("8G").unwrap().bytes(), 8 << 30);
+    assert_eq!(MemoryBudget::parse("8 GB").unwrap().bytes(), 8 << 30);
+    assert_eq!(MemoryBudget::parse("1.5MiB").unwrap().bytes(), 3 << 19);
+    assert_eq!(MemoryBudget::parse("512k").unwrap().bytes(), 512 << 10);
+    assert!(MemoryBudget::parse("").is_err());
+    assert!(MemoryBudget::parse("8X").is_err());
+    assert!(MemoryBudget::parse("0").is_err());
+
+    let b = MemoryBudget::new(1000);
+    assert_eq!(b.rows_per_chunk(10, 1_000_000), 100);
+    assert_eq!(b.rows_per_chunk(10, 30), 30);
+    assert_eq!(b.rows_per_chunk(5000, 30), 1);
+    assert_eq!(b.rows_per_chunk(0, 30), 30);
+    assert_eq!(b.divide(2).bytes(), 500);
+}
@@ -0,0 +1,355 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Compression codecs for chunked outputs.
+
+Writers of chunked array formats compress each chunk independently with a
+`Codec`. Which codecs are available depends on the features with which this
+crate is built:
+
+- `Uncompressed` is always available.
+- `Zstd` requires the `zstd-codec` feature. It gives the best compression
+  ratios and is the usual choice for visibilities.
+- `Lz4` requires the `lz4-codec` feature. It compresses less but is much
+  faster, especially to decompress.
+- `BitshuffleLz4` requires the `bitshuffle-codec` feature. It transposes the
+  bits of each block of elements before compressing them with LZ4, which
+  gathers the slowly-varying high bits of floating-point and integer data
+  together and often compresses them as well as Zstandard, at LZ4 speeds.
+  This is the scheme used by the HDF5 bitshuffle filter.
+
+Users choose codecs with `CodecSpec` values, which can be parsed from text
+such as `zstd:5`, and `CodecOptions` selects a codec for each dataset of an
+output, so that, say, flags can be compressed differently than data.
+
+*/
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::Result;
+
+/// An error type for when a codec specification cannot be parsed.
+#[derive(Fail, Debug)]
+#[fail(
+    display = "cannot parse \"{}\" as a codec (expected \"none\", \"zstd[:LEVEL]\", \"lz4\", or \"bitshuffle-lz4\")",
+    _0
+)]
+pub struct CodecParseError(pub String);
+
+/// An error type for when a codec is requested that this build of the crate
+/// does not support.
+#[derive(Fail, Debug)]
+#[fail(
+    display = "the \"{}\" codec is not available; rebuild rubbl_core with the \"{}\" feature",
+    _0, _1
+)]
+pub struct CodecUnavailableError(pub String, pub &'static str);
+
+/// A compression scheme applied to each chunk of an output.
+pub trait Codec: Send + Sync {
+    /// Get the name of the codec, as recorded in output metadata.
+    fn name(&self) -> &str;
+
+    /// Compress *data*.
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>>;
+
+    /// Decompress *data*, which were compressed by `encode`.
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// A codec that stores data as they are.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Uncompressed;
+
+impl Codec for Uncompressed {
+    fn name(&self) -> &str {
+        "none"
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+/// The Zstandard codec.
+#[cfg(feature = "zstd-codec")]
+#[derive(Clone, Copy, Debug)]
+pub struct Zstd {
+    /// The compression level, from 1 (fastest) to 22 (smallest).
+    pub level: i32,
+}
+
+#[cfg(feature = "zstd-codec")]
+impl Codec for Zstd {
+    fn name(&self) -> &str {
+        "zstd"
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(::zstd::bulk::compress(data, self.level)?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(::zstd::stream::decode_all(data)?)
+    }
+}
+
+/// The LZ4 codec. The compressed data are preceded by their uncompressed
+/// size as a little-endian `u32`.
+#[cfg(feature = "lz4-codec")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lz4;
+
+#[cfg(feature = "lz4-codec")]
+impl Codec for Lz4 {
+    fn name(&self) -> &str {
+        "lz4"
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(::lz4_flex::compress_prepend_size(data))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(::lz4_flex::decompress_size_prepended(data)?)
+    }
+}
+
+/// Bit-shuffling followed by LZ4 compression.
+#[cfg(feature = "bitshuffle-codec")]
+#[derive(Clone, Copy, Debug)]
+pub struct BitshuffleLz4 {
+    /// The size of the elements of the data, in bytes.
+    pub element_size: usize,
+}
+
+#[cfg(feature = "bitshuffle-codec")]
+impl Codec for BitshuffleLz4 {
+    fn name(&self) -> &str {
+        "bitshuffle-lz4"
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Lz4.encode(&bitshuffle(data, self.element_size))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(bitunshuffle(&Lz4.decode(data)?, self.element_size))
+    }
+}
+
+/// Transpose the bits of *data*, viewed as elements of *element_size*
+/// bytes, so that bit *b* of every element is stored together.
+///
+/// Elements are processed in groups of eight, so that each bit plane
+/// occupies whole bytes; any elements (or bytes) left over at the end are
+/// copied unchanged.
+#[cfg(feature = "bitshuffle-codec")]
+pub fn bitshuffle(data: &[u8], element_size: usize) -> Vec<u8> {
+    let element_size = ::std::cmp::max(element_size, 1);
+    let n = data.len() / element_size / 8 * 8;
+    let mut out = vec![0u8; data.len()];
+
+    for bit in 0..8 * element_size {
+        let plane = &mut out[bit * n / 8..(bit + 1) * n / 8];
+
+        for i in 0..n {
+            let v = (data[i * element_size + bit / 8] >> (bit % 8)) & 1;
+            plane[i / 8] |= v << (i % 8);
+        }
+    }
+
+    out[n * element_size..].copy_from_slice(&data[n * element_size..]);
+    out
+}
+
+/// Undo `bitshuffle`.
+#[cfg(feature = "bitshuffle-codec")]
+pub fn bitunshuffle(data: &[u8], element_size: usize) -> Vec<u8> {
+    let element_size = ::std::cmp::max(element_size, 1);
+    let n = data.len() / element_size / 8 * 8;
+    let mut out = vec![0u8; data.len()];
+
+    for bit in 0..8 * element_size {
+        let plane = &data[bit * n / 8..(bit + 1) * n / 8];
+
+        for i in 0..n {
+            let v = (plane[i / 8] >> (i % 8)) & 1;
+            out[i * element_size + bit / 8] |= v << (bit % 8);
+        }
+    }
+
+    out[n * element_size..].copy_from_slice(&data[n * element_size..]);
+    out
+}
+
+/// A choice of codec, as made by a user.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum CodecSpec {
+    /// No compression.
+    #[default]
+    None,
+
+    /// Zstandard, at the given level.
+    Zstd(i32),
+
+    /// LZ4.
+    Lz4,
+
+    /// Bit-shuffling followed by LZ4.
+    BitshuffleLz4,
+}
+
+impl fmt::Display for CodecSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodecSpec::None => write!(f, "none"),
+            CodecSpec::Zstd(level) => write!(f, "zstd:{}", level),
+            CodecSpec::Lz4 => write!(f, "lz4"),
+            CodecSpec::BitshuffleLz4 => write!(f, "bitshuffle-lz4"),
+        }
+    }
+}
+
+impl CodecSpec {
+    /// The Zstandard level used if none is given.
+    pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+    /// Parse a codec as given by a user: `none`, `zstd` with an optional
+    /// level such as `zstd:9`, `lz4`, or `bitshuffle-lz4`.
+    ///
+    /// Codecs that this build of the crate does not support can still be
+    /// parsed; it is `build` that fails for them.
+    pub fn parse(text: &str) -> Result<Self> {
+        let err = || CodecParseError(text.to_owned());
+        let text = text.trim().to_lowercase();
+
+        let (name, arg) = match text.find(':') {
+            Some(idx) => (&text[..idx], Some(&text[idx + 1..])),
+            None => (&text[..], None),
+        };
+
+        Ok(match (name, arg) {
+            ("none", None) => CodecSpec::None,
+            ("zstd", None) => CodecSpec::Zstd(Self::DEFAULT_ZSTD_LEVEL),
+            ("zstd", Some(level)) => match level.parse() {
+                Ok(l) if (1..=22).contains(&l) => CodecSpec::Zstd(l),
+                _ => return Err(err().into()),
+            },
+            ("lz4", None) => CodecSpec::Lz4,
+            ("bitshuffle-lz4", None) => CodecSpec::BitshuffleLz4,
+            _ => return Err(err().into()),
+        })
+    }
+
+    /// Create the codec, for data whose elements are *element_size* bytes
+    /// wide. Fails if this build of the crate does not support it.
+    #[allow(unused_variables)]
+    pub fn build(&self, element_size: usize) -> Result<Box<Codec>> {
+        match *self {
+            CodecSpec::None => Ok(Box::new(Uncompressed)),
+
+            #[cfg(feature = "zstd-codec")]
+            CodecSpec::Zstd(level) => Ok(Box::new(Zstd { level: level })),
+            #[cfg(not(feature = "zstd-codec"))]
+            CodecSpec::Zstd(_) => Err(CodecUnavailableError(self.to_string(), "zstd-codec").into()),
+
+            #[cfg(feature = "lz4-codec")]
+            CodecSpec::Lz4 => Ok(Box::new(Lz4)),
+            #[cfg(not(feature = "lz4-codec"))]
+            CodecSpec::Lz4 => Err(CodecUnavailableError(self.to_string(), "lz4-codec").into()),
+
+            #[cfg(feature = "bitshuffle-codec")]
+            CodecSpec::BitshuffleLz4 => Ok(Box::new(BitshuffleLz4 {
+                element_size: element_size,
+            })),
+            #[cfg(not(feature = "bitshuffle-codec"))]
+            CodecSpec::BitshuffleLz4 => {
+                Err(CodecUnavailableError(self.to_string(), "bitshuffle-codec").into())
+            }
+        }
+    }
+}
+
+/// The codecs to use for the datasets of an output.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CodecOptions {
+    /// The codec for datasets that are not listed in *datasets*.
+    pub default: CodecSpec,
+
+    /// Codecs for particular datasets, by name.
+    pub datasets: BTreeMap<String, CodecSpec>,
+}
+
+impl CodecOptions {
+    /// Create options using *spec* for every dataset.
+    pub fn uniform(spec: CodecSpec) -> Self {
+        CodecOptions {
+            default: spec,
+            datasets: BTreeMap::new(),
+        }
+    }
+
+    /// Parse options as given by a user: a comma-separated list of codecs,
+    /// each either plain, to set the default, or prefixed with a dataset
+    /// name and `=`. For instance, `zstd:5,flags=lz4` compresses the
+    /// `flags` dataset with LZ4 and everything else with Zstandard.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut options = CodecOptions::default();
+
+        for item in text.split(',') {
+            match item.find('=') {
+                Some(idx) => {
+                    options.datasets.insert(
+                        item[..idx].trim().to_owned(),
+                        CodecSpec::parse(&item[idx + 1..])?,
+                    );
+                }
+                None => options.default = CodecSpec::parse(item)?,
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Get the codec choice for the dataset named *name*.
+    pub fn for_dataset(&self, name: &str) -> CodecSpec {
+        self.datasets.get(name).cloned().unwrap_or(self.default)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn codec_choices() {
+    let opts = CodecOptions::parse("zstd:5, flags=lz4").unwrap();
+    assert_eq!(opts.for_dataset("data"), CodecSpec::Zstd(5));
+    assert_eq!(opts.for_dataset("flags"), CodecSpec::Lz4);
+    assert_eq!(CodecSpec::parse("ZSTD").unwrap(), CodecSpec::Zstd(3));
+    assert!(CodecSpec::parse("zstd:30").is_err());
+    assert!(CodecSpec::parse("gzip").is_err());
+    assert_eq!(
+        CodecSpec::parse(&CodecSpec::BitshuffleLz4.to_string()).unwrap(),
+        CodecSpec::BitshuffleLz4
+    );
+
+    let data: Vec<u8> = (0..203).map(|i| (i * 7 % 256) as u8).collect();
+    let codec = CodecSpec::None.build(4).unwrap();
+    assert_eq!(codec.decode(&codec.encode(&data).unwrap()).unwrap(), data);
+
+    #[cfg(feature = "bitshuffle-codec")]
+    {
+        let shuffled = bitshuffle(&data, 4);
+        assert_ne!(shuffled, data);
+        assert_eq!(bitunshuffle(&shuffled, 4), data);
+
+        let codec = CodecSpec::BitshuffleLz4.build(4).unwrap();
+        assert_eq!(codec.decode(&codec.encode(&data).unwrap()).unwrap(), data);
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Colormaps and minimal PNG output for quick-look images.
+
+This module turns numbers into colors and writes the results as PNG files,
+without depending on an imaging library. It is meant for diagnostic plots
+such as waterfalls, not for publication graphics: the PNG encoder does not
+compress the image data at all, so the files are about as large as the raw
+pixels.
+
+```rust,ignore
+let mut image = RgbImage::new(n_chan, n_time);
+image.set_pixel(x, y, Colormap::Viridis.rgb(0.7));
+image.write_png(File::create("waterfall.png")?)?;
+```
+
+*/
+
+use failure::err_msg;
+use std::f64;
+use std::io::Write;
+
+use super::decode::{Adler32, Checksum, Crc32};
+use super::Result;
+
+/// The color used for values that cannot be mapped, such as NaNs.
+pub const BAD_COLOR: [u8; 3] = [255, 255, 255];
+
+/// The stops of the Viridis colormap, evenly spaced from 0 to 1.
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+/// The stops of an approximation to the Twilight colormap, evenly spaced
+/// from 0 to 1. The first and last are the same, so that it wraps around.
+const TWILIGHT: [[u8; 3]; 9] = [
+    [226, 217, 226],
+    [160, 178, 199],
+    [96, 125, 184],
+    [88, 63, 156],
+    [47, 20, 54],
+    [125, 40, 90],
+    [180, 82, 72],
+    [204, 158, 140],
+    [226, 217, 226],
+];
+
+/// A mapping from numbers between 0 and 1 to colors.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Colormap {
+    /// Black to white.
+    Gray,
+
+    /// The perceptually uniform dark blue to yellow map of matplotlib.
+    Viridis,
+
+    /// A cyclic map, suitable for phases: values of 0 and 1 have the same
+    /// color.
+    Twilight,
+}
+
+impl Colormap {
+    /// Look up a colormap by its name, as returned by `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "gray" => Some(Colormap::Gray),
+            "viridis" => Some(Colormap::Viridis),
+            "twilight" => Some(Colormap::Twilight),
+            _ => None,
+        }
+    }
+
+    /// Get the name of this colormap.
+    pub fn name(self) -> &'static str {
+        match self {
+            Colormap::Gray => "gray",
+            Colormap::Viridis => "viridis",
+            Colormap::Twilight => "twilight",
+        }
+    }
+
+    /// Test whether this colormap wraps around, so that values outside of
+    /// the range 0 to 1 are reduced modulo 1 rather than clipped.
+    pub fn is_cyclic(self) -> bool {
+        self == Colormap::Twilight
+    }
+
+    /// Get the color of the value *x*, which should be between 0 and 1. For
+    /// non-cyclic colormaps, values outside of that range get the color of
+    /// the nearest end. Non-finite values get `BAD_COLOR`.
+    pub fn rgb(self, x: f64) -> [u8; 3] {
+        if !x.is_finite() {
+            return BAD_COLOR;
+        }
+
+        let x = if self.is_cyclic() {
+            x - x.floor()
+        } else {
+            x.clamp(0., 1.)
+        };
+
+        match self {
+            Colormap::Gray => {
+                let v = (255. * x).round() as u8;
+                [v, v, v]
+            }
+            Colormap::Viridis => interpolate(&VIRIDIS, x),
+            Colormap::Twilight => interpolate(&TWILIGHT, x),
+        }
+    }
+}
+
+/// Linearly interpolate between evenly spaced color stops.
+fn interpolate(stops: &[[u8; 3]], x: f64) -> [u8; 3] {
+    let pos = x * (stops.len() - 1) as f64;
+    let i = (pos.floor() as usize).min(stops.len() - 2);
+    let frac = pos - i as f64;
+    let mut rgb = [0; 3];
+
+    for (c, v) in rgb.iter_mut().enumerate() {
+        let lo = stops[i][c] as f64;
+        let hi = stops[i + 1][c] as f64;
+        *v = (lo + frac * (hi - lo)).round() as u8;
+    }
+
+    rgb
+}
+
+/// An image with 8-bit red, green, and blue channels.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RgbImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl RgbImage {
+    /// Create a new image filled with black. Pixel (0, 0) is at the top
+    /// left.
+    pub fn new(width: usize, height: usize) -> Self {
+        RgbImage {
+            width: width,
+            height: height,
+            pixels: vec![[0; 3]; width * height],
+        }
+    }
+
+    /// Get the width of the image, in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Get the height of the image, in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Get the color of the pixel in column *x* and row *y*.
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Set the color of the pixel in column *x* and row *y*.
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        self.pixels[y * self.width + x] = rgb;
+    }
+
+    /// Make a copy of this image enlarged by an integer *factor*, with each
+    /// pixel becoming a square block. Small images are easier to look at
+    /// this way than when scaled up by image viewers, which usually blur
+    /// them.
+    pub fn scaled(&self, factor: usize) -> Self {
+        let mut big = RgbImage::new(self.width * factor, self.height * factor);
+
+        for y in 0..big.height {
+            for x in 0..big.width {
+                big.pixels[y * big.width + x] = self.pixel(x / factor, y / factor);
+            }
+        }
+
+        big
+    }
+
+    /// Write this image to *dest* in PNG format.
+    pub fn write_png<W: Write>(&self, mut dest: W) -> Result<()> {
+        if self.width == 0
+            || self.height == 0
+            || self.width > 0x7FFF_FFFF
+            || self.height > 0x7FFF_FFFF
+        {
+            return Err(err_msg(format!(
+                "cannot write a {}×{} image as a PNG",
+                self.width, self.height
+            )));
+        }
+
+        dest.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // Bit depth 8, color type 2 (RGB), default compression and
+        // filtering, no interlacing.
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_chunk(&mut dest, b"IHDR", &header)?;
+
+        // Each row of the image data is preceded by its filter type, which
+        // is always 0 (none) here.
+        let mut raw = Vec::with_capacity(self.height * (3 * self.width + 1));
+
+        for row in self.pixels.chunks(self.width) {
+            raw.push(0);
+
+            for rgb in row {
+                raw.extend_from_slice(rgb);
+            }
+        }
+
+        write_chunk(&mut dest, b"IDAT", &zlib_stored(&raw))?;
+        write_chunk(&mut dest, b"IEND", &[])?;
+        Ok(())
+    }
+}
+
+/// Write a PNG chunk, including its length and checksum.
+fn write_chunk<W: Write>(dest: &mut W, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    let mut crc = Crc32::new();
+    crc.update(kind);
+    crc.update(data);
+
+    dest.write_all(&(data.len() as u32).to_be_bytes())?;
+    dest.write_all(kind)?;
+    dest.write_all(data)?;
+    dest.write_all(&(crc.value() as u32).to_be_bytes())?;
+    Ok(())
+}
+
+/// Wrap *data* in a zlib stream without compressing it, using “stored”
+/// deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xFFFF;
+
+    let mut out = Vec::with_capacity(data.len() + 5 * (data.len() / MAX_BLOCK + 1) + 6);
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(if is_final { 1 } else { 0 });
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    let mut adler = Adler32::new();
+    adler.update(data);
+    out.extend_from_slice(&(adler.value() as u32).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+#[test]
+fn colormaps_and_png() {
+    assert_eq!(Colormap::Gray.rgb(0.5), [128, 128, 128]);
+    assert_eq!(Colormap::Viridis.rgb(-1.), VIRIDIS[0]);
+    assert_eq!(Colormap::Viridis.rgb(1.), VIRIDIS[8]);
+    assert_eq!(Colormap::Viridis.rgb(f64::NAN), BAD_COLOR);
+    assert_eq!(Colormap::Twilight.rgb(1.25), Colormap::Twilight.rgb(0.25));
+    assert_eq!(Colormap::from_name("twilight"), Some(Colormap::Twilight));
+    assert_eq!(Colormap::from_name("jet"), None);
+
+    let mut image = RgbImage::new(2, 1);
+    image.set_pixel(1, 0, [255, 0, 0]);
+    let big = image.scaled(2);
+    assert_eq!(big.width(), 4);
+    assert_eq!(big.pixel(3, 1), [255, 0, 0]);
+    assert_eq!(big.pixel(1, 1), [0, 0, 0]);
+
+    let mut png = Vec::new();
+    image.write_png(&mut png).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&png[12..16], b"IHDR");
+    // The raw data are the filter byte and six bytes of pixels, stored in
+    // one final block.
+    assert_eq!(&png[37..41], b"IDAT");
+    assert_eq!(&png[41..48], &[0x78, 0x01, 1, 7, 0, 0xF8, 0xFF]);
+    assert_eq!(&png[48..55], &[0, 0, 0, 0, 255, 0, 0]);
+    assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+}
@@ -0,0 +1,320 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Byte-level decoding primitives that do not need the standard library.
+
+The rest of this crate works in terms of `std::io` streams. This module
+provides the operations underneath them — reading fixed-size values of a
+given endianness out of byte buffers, splitting buffers into framed records,
+and computing checksums — using only `core` and `alloc`. If this crate is
+built with its default `std` feature disabled, it becomes `no_std` and this
+is the only module available, so that these primitives can be used in
+contexts such as correlator or monitoring firmware.
+
+*/
+
+use adler32::RollingAdler32;
+use alloc::fmt;
+use alloc::vec::Vec;
+use crc32fast;
+
+/// An error arising from decoding a byte buffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// The buffer ended before a complete value could be read.
+    UnexpectedEnd {
+        /// The offset into the buffer at which the value started.
+        offset: usize,
+
+        /// The number of bytes that the value needed.
+        needed: usize,
+    },
+
+    /// A record was too long to be described by its length prefix.
+    RecordTooLong {
+        /// The length of the record, in bytes.
+        length: usize,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnexpectedEnd { offset, needed } => write!(
+                f,
+                "data ended unexpectedly: needed {} bytes at offset {}",
+                needed, offset
+            ),
+            DecodeError::RecordTooLong { length } => {
+                write!(f, "record of {} bytes is too long to be framed", length)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for DecodeError {}
+
+/// A cursor for reading values out of a byte buffer.
+///
+/// This is the buffer-based analogue of `io::AligningReader`: it tracks how
+/// far into the buffer it has progressed so that the data can be aligned.
+#[derive(Clone, Debug)]
+pub struct ByteCursor<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+macro_rules! cursor_reads {
+    ($($name:ident, $ty:ident, $conv:ident, $n:expr, $doc:expr;)*) => {
+        $(
+            #[doc = $doc]
+            pub fn $name(&mut self) -> Result<$ty, DecodeError> {
+                let mut buf = [0u8; $n];
+                buf.copy_from_slice(self.take($n)?);
+                Ok($ty::$conv(buf))
+            }
+        )*
+    }
+}
+
+impl<'a> ByteCursor<'a> {
+    /// Create a new cursor positioned at the start of *data*.
+    pub fn new(data: &'a [u8]) -> Self {
+        ByteCursor {
+            data: data,
+            offset: 0,
+        }
+    }
+
+    /// Get how many bytes have been consumed from the buffer.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Get the bytes that have not yet been consumed.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.offset..]
+    }
+
+    /// Return true if all of the bytes in the buffer have been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.offset == self.data.len()
+    }
+
+    /// Consume and return the next *n* bytes.
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if n > self.data.len() - self.offset {
+            return Err(DecodeError::UnexpectedEnd {
+                offset: self.offset,
+                needed: n,
+            });
+        }
+
+        let result = &self.data[self.offset..self.offset + n];
+        self.offset += n;
+        Ok(result)
+    }
+
+    /// Skip bytes to ensure that the cursor is aligned as specified.
+    ///
+    /// Returns whether the cursor was already at the right alignment.
+    pub fn align_to(&mut self, alignment: usize) -> Result<bool, DecodeError> {
+        let excess = self.offset % alignment;
+
+        if excess == 0 {
+            Ok(true)
+        } else {
+            self.take(alignment - excess)?;
+            Ok(false)
+        }
+    }
+
+    cursor_reads! {
+        read_u8, u8, from_be_bytes, 1, "Read a `u8`.";
+        read_i8, i8, from_be_bytes, 1, "Read an `i8`.";
+        read_be_i16, i16, from_be_bytes, 2, "Read a big-endian `i16`.";
+        read_be_u16, u16, from_be_bytes, 2, "Read a big-endian `u16`.";
+        read_be_i32, i32, from_be_bytes, 4, "Read a big-endian `i32`.";
+        read_be_u32, u32, from_be_bytes, 4, "Read a big-endian `u32`.";
+        read_be_i64, i64, from_be_bytes, 8, "Read a big-endian `i64`.";
+        read_be_u64, u64, from_be_bytes, 8, "Read a big-endian `u64`.";
+        read_be_f32, f32, from_be_bytes, 4, "Read a big-endian `f32`.";
+        read_be_f64, f64, from_be_bytes, 8, "Read a big-endian `f64`.";
+        read_le_i16, i16, from_le_bytes, 2, "Read a little-endian `i16`.";
+        read_le_u16, u16, from_le_bytes, 2, "Read a little-endian `u16`.";
+        read_le_i32, i32, from_le_bytes, 4, "Read a little-endian `i32`.";
+        read_le_u32, u32, from_le_bytes, 4, "Read a little-endian `u32`.";
+        read_le_i64, i64, from_le_bytes, 8, "Read a little-endian `i64`.";
+        read_le_u64, u64, from_le_bytes, 8, "Read a little-endian `u64`.";
+        read_le_f32, f32, from_le_bytes, 4, "Read a little-endian `f32`.";
+        read_le_f64, f64, from_le_bytes, 8, "Read a little-endian `f64`.";
+    }
+}
+
+/// Append *payload* to *out* as a framed record: a big-endian `u32` giving
+/// the payload length, followed by the payload itself.
+///
+/// Records written this way can be read back with `FramedRecords`.
+pub fn write_framed_record(out: &mut Vec<u8>, payload: &[u8]) -> Result<(), DecodeError> {
+    if payload.len() as u64 > u32::max_value() as u64 {
+        return Err(DecodeError::RecordTooLong {
+            length: payload.len(),
+        });
+    }
+
+    out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    out.extend_from_slice(payload);
+    Ok(())
+}
+
+/// An iterator over the records in a buffer written with
+/// `write_framed_record`.
+///
+/// Each item is the payload of one record. If the buffer is truncated, an
+/// error is yielded and the iteration stops.
+#[derive(Clone, Debug)]
+pub struct FramedRecords<'a> {
+    cursor: ByteCursor<'a>,
+    failed: bool,
+}
+
+impl<'a> FramedRecords<'a> {
+    /// Create an iterator over the records in *data*.
+    pub fn new(data: &'a [u8]) -> Self {
+        FramedRecords {
+            cursor: ByteCursor::new(data),
+            failed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for FramedRecords<'a> {
+    type Item = Result<&'a [u8], DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.cursor.is_empty() {
+            return None;
+        }
+
+        let result = self
+            .cursor
+            .read_be_u32()
+            .and_then(|n| self.cursor.take(n as usize));
+
+        if result.is_err() {
+            self.failed = true;
+        }
+
+        Some(result)
+    }
+}
+
+/// A checksum algorithm that can be computed incrementally as data stream
+/// past.
+///
+/// This trait is used by `io::ChecksummingReader` and
+/// `io::ChecksummingWriter`. Checksums of up to 64 bits are supported;
+/// narrower values are zero-extended.
+pub trait Checksum {
+    /// Update the checksum to include the data in *buf*.
+    fn update(&mut self, buf: &[u8]);
+
+    /// Get the checksum of all of the data processed so far.
+    fn value(&self) -> u64;
+
+    /// Reset the checksum to its initial state, as if no data had been
+    /// processed.
+    fn reset(&mut self);
+}
+
+/// The CRC-32 checksum, as used by zlib, gzip, PNG, and many others.
+#[derive(Clone, Debug, Default)]
+pub struct Crc32(crc32fast::Hasher);
+
+impl Crc32 {
+    /// Create a new CRC-32 checksum state.
+    pub fn new() -> Self {
+        Crc32(crc32fast::Hasher::new())
+    }
+}
+
+impl Checksum for Crc32 {
+    fn update(&mut self, buf: &[u8]) {
+        self.0.update(buf);
+    }
+
+    fn value(&self) -> u64 {
+        self.0.clone().finalize() as u64
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+/// The Adler-32 checksum, as used by zlib. It is faster but weaker than
+/// CRC-32.
+pub struct Adler32(RollingAdler32);
+
+impl Adler32 {
+    /// Create a new Adler-32 checksum state.
+    pub fn new() -> Self {
+        Adler32(RollingAdler32::new())
+    }
+}
+
+impl Checksum for Adler32 {
+    fn update(&mut self, buf: &[u8]) {
+        self.0.update_buffer(buf);
+    }
+
+    fn value(&self) -> u64 {
+        self.0.hash() as u64
+    }
+
+    fn reset(&mut self) {
+        self.0 = RollingAdler32::new();
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn cursor_and_framing() {
+    let data = [0u8, 1, 0xFF, 0xFE, 0, 0, 0, 0, 0x40, 0x49, 0x0F, 0xDB];
+    let mut c = ByteCursor::new(&data);
+    assert_eq!(c.read_be_i16().unwrap(), 1);
+    assert_eq!(c.read_le_u16().unwrap(), 0xFEFF);
+    assert!(!c.align_to(8).unwrap());
+    assert_eq!(c.offset(), 8);
+    assert_eq!(c.read_be_f32().unwrap(), 3.1415927);
+    assert!(c.is_empty());
+    assert_eq!(
+        c.read_u8(),
+        Err(DecodeError::UnexpectedEnd {
+            offset: 12,
+            needed: 1
+        })
+    );
+
+    let mut buf = Vec::new();
+    write_framed_record(&mut buf, b"abc").unwrap();
+    write_framed_record(&mut buf, b"").unwrap();
+    write_framed_record(&mut buf, b"de").unwrap();
+    assert_eq!(buf.len(), 17);
+
+    let records: Vec<_> = FramedRecords::new(&buf).map(|r| r.unwrap()).collect();
+    assert_eq!(records, [&b"abc"[..], &b""[..], &b"de"[..]]);
+
+    let mut truncated = FramedRecords::new(&buf[..15]);
+    assert_eq!(truncated.next(), Some(Ok(&b"abc"[..])));
+    assert_eq!(truncated.next(), Some(Ok(&b""[..])));
+    assert!(truncated.next().unwrap().is_err());
+    assert_eq!(truncated.next(), None);
+
+    let mut crc = Crc32::new();
+    crc.update(b"123456789");
+    assert_eq!(crc.value(), 0xCBF43926);
+}
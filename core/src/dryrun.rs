@@ -0,0 +1,152 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Support for "dry runs" of operations that modify data sets.
+
+APIs that modify data (CASA tables, MIRIAD data sets, …) implement the
+`DryRun` trait. When dry-run mode is enabled, their mutating operations do not
+touch the underlying data; instead, each change that *would* have been made
+is recorded in a `ChangePlan`, which can be retrieved afterwards and
+presented to the user. Because `ChangePlan` implements `Report`, it can be
+printed as text or as JSON.
+
+Command-line tools should add the standard `--dry-run` argument with
+`ClapDryRunArgsExt::rubbl_dry_run_args` and enable dry-run mode on every
+data set they open for writing when it is given. Tools that write their
+results to new files or data sets, rather than changing ones that they have
+open, should instead do their reading and computing as usual, record each
+output that they would create or replace in a `ChangePlan`, and report that
+plan in place of their usual output.
+
+*/
+
+use clap;
+use std::io::Write;
+
+use super::report::Report;
+use super::Result;
+
+/// A single change that a mutating operation would have made.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize)]
+pub struct PlannedChange {
+    /// The data set, file, or table that would be changed.
+    pub target: String,
+
+    /// A brief description of the change, e.g. "add rows".
+    pub action: String,
+
+    /// The number of rows that would be touched, if applicable.
+    pub rows: u64,
+
+    /// The number of bytes that would be written, if known.
+    pub bytes: Option<u64>,
+}
+
+/// A record of the changes that would be made by a series of operations.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize)]
+pub struct ChangePlan {
+    changes: Vec<PlannedChange>,
+}
+
+impl ChangePlan {
+    /// Create a new, empty plan.
+    pub fn new() -> Self {
+        ChangePlan::default()
+    }
+
+    /// Record a change.
+    pub fn record<T: Into<String>, A: Into<String>>(
+        &mut self,
+        target: T,
+        action: A,
+        rows: u64,
+        bytes: Option<u64>,
+    ) {
+        self.changes.push(PlannedChange {
+            target: target.into(),
+            action: action.into(),
+            rows: rows,
+            bytes: bytes,
+        });
+    }
+
+    /// Get the changes recorded so far.
+    pub fn changes(&self) -> &[PlannedChange] {
+        &self.changes[..]
+    }
+
+    /// Test whether no changes have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Append all of the changes recorded in another plan to this one.
+    pub fn merge(&mut self, mut other: ChangePlan) {
+        self.changes.append(&mut other.changes);
+    }
+}
+
+impl Report for ChangePlan {
+    fn write_text(&self, dest: &mut Write) -> Result<()> {
+        if self.changes.is_empty() {
+            writeln!(dest, "dry run: no changes would be made")?;
+            return Ok(());
+        }
+
+        writeln!(dest, "dry run: the following changes would be made:")?;
+
+        for c in &self.changes {
+            write!(dest, "    {}: {}", c.target, c.action)?;
+
+            if c.rows != 0 {
+                write!(dest, "; {} rows", c.rows)?;
+            }
+
+            if let Some(n) = c.bytes {
+                write!(dest, "; {} bytes", n)?;
+            }
+
+            writeln!(dest)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A trait for types whose mutating operations can be run in dry-run mode.
+pub trait DryRun {
+    /// Enable or disable dry-run mode. While it is enabled, mutating
+    /// operations record what they would do rather than doing it.
+    fn set_dry_run(&mut self, enabled: bool);
+
+    /// Test whether dry-run mode is enabled.
+    fn is_dry_run(&self) -> bool;
+
+    /// Take the changes recorded since dry-run mode was enabled or this
+    /// function was last called.
+    fn take_change_plan(&mut self) -> ChangePlan;
+}
+
+/// Extend a `clap::App` with the standard dry-run argument.
+pub trait ClapDryRunArgsExt {
+    /// Add the `--dry-run` argument to this App.
+    fn rubbl_dry_run_args(self) -> Self;
+}
+
+impl<'a, 'b> ClapDryRunArgsExt for clap::App<'a, 'b> {
+    fn rubbl_dry_run_args(self) -> Self {
+        self.arg(
+            clap::Arg::with_name("dry_run")
+                .long("dry-run")
+                .help("Report what would be changed without changing anything"),
+        )
+    }
+}
+
+/// Test whether the user asked for a dry run, using the argument added by
+/// `ClapDryRunArgsExt::rubbl_dry_run_args`.
+pub fn dry_run_requested(matches: &clap::ArgMatches) -> bool {
+    matches.is_present("dry_run")
+}
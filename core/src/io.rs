@@ -8,10 +8,19 @@ Basic I/O helpers.
  */
 
 use byteorder::{BigEndian, ByteOrder};
+use clap;
 use num_complex::Complex;
+use std::cmp;
+use std::env;
 use std::io;
-use std::io::{Read, Result, Write};
+use std::io::{BufRead, Read, Result, Seek, SeekFrom, Write};
 use std::result;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::budget::MemoryBudget;
+
+pub use decode::{Adler32, Checksum, Crc32};
 
 /// This struct wraps a Read type to equip it with hooks to track its
 /// alignment — that is, how many bytes into the stream the read has
@@ -21,36 +30,91 @@ use std::result;
 /// Streams often have alignment requirements so that they can safely be
 /// mapped into in-memory data structures. In particular, this is the case for
 /// MIRIAD files.
+///
+/// The reader also maintains a small pushback buffer so that upcoming data
+/// can be examined with `peek` without being consumed.
 #[derive(Debug)]
 pub struct AligningReader<R: Read> {
     inner: R,
     offset: u64,
+    pushback: Vec<u8>,
+    pushback_pos: usize,
 }
 
 impl<R: Read> AligningReader<R> {
     /// Create a new AligningReader that wraps the argument *inner*.
     pub fn new(inner: R) -> Self {
+        Self::with_offset(inner, 0)
+    }
+
+    /// Create a new AligningReader that wraps the argument *inner*, which
+    /// has already been advanced *offset* bytes into its stream.
+    ///
+    /// Alignments will be computed relative to the true beginning of the
+    /// stream rather than the position of *inner* at the time of this call.
+    pub fn with_offset(inner: R, offset: u64) -> Self {
         AligningReader {
             inner: inner,
-            offset: 0,
+            offset: offset,
+            pushback: Vec::new(),
+            pushback_pos: 0,
         }
     }
 
     /// Consume this struct, returning the underlying inner reader.
+    ///
+    /// Any data that have been peeked at but not consumed are lost.
     pub fn into_inner(self) -> R {
         self.inner
     }
 
-    /// Return how many bytes we have read since this struct was created.
+    /// Return how many bytes we have read since this struct was created,
+    /// plus any initial offset passed to `with_offset`.
     ///
     /// Note that this offset is tracked internally. If you open a file, read
-    /// part of it, and *then* create an AligningReader, the returned offset
-    /// will refer to the number of bytes read since creation, not the actual
-    /// file position as understood by the underlying OS.
+    /// part of it, and *then* create an AligningReader with `new`, the
+    /// returned offset will refer to the number of bytes read since
+    /// creation, not the actual file position as understood by the
+    /// underlying OS. Bytes that have been peeked at are not counted until
+    /// they are actually read.
     pub fn offset(&self) -> u64 {
         self.offset
     }
 
+    /// Look at the next *n* bytes of the stream without consuming them.
+    ///
+    /// The returned slice will be shorter than *n* bytes only if the end of
+    /// the stream is encountered. The bytes will be returned again by the
+    /// next read operation.
+    pub fn peek(&mut self, n: usize) -> Result<&[u8]> {
+        if self.pushback.len() - self.pushback_pos < n {
+            self.pushback.drain(..self.pushback_pos);
+            self.pushback_pos = 0;
+
+            let mut n_have = self.pushback.len();
+            self.pushback.resize(n, 0);
+
+            while n_have < n {
+                match self.inner.read(&mut self.pushback[n_have..]) {
+                    Ok(0) => break,
+                    Ok(n_read) => {
+                        n_have += n_read;
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        self.pushback.truncate(n_have);
+                        return Err(e);
+                    }
+                }
+            }
+
+            self.pushback.truncate(n_have);
+        }
+
+        let end = ::std::cmp::min(self.pushback.len(), self.pushback_pos + n);
+        Ok(&self.pushback[self.pushback_pos..end])
+    }
+
     /// Read and discard bytes to ensure that the stream is aligned as specified.
     ///
     /// The maximum allowed alignment value is 64 bytes.
@@ -70,20 +134,30 @@ impl<R: Read> AligningReader<R> {
             Ok(true)
         } else {
             let amount = alignment - excess;
-            let result = self.inner.eof_read_exact(&mut buf[..amount]);
-
-            if result.is_ok() {
-                self.offset += amount as u64;
-            }
-
-            result
+            self.eof_read_exact::<io::Error>(&mut buf[..amount])?;
+            Ok(false)
         }
     }
 }
 
 impl<R: Read> Read for AligningReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let result = self.inner.read(buf);
+        let n_buffered = self.pushback.len() - self.pushback_pos;
+
+        let result = if n_buffered > 0 {
+            let n = ::std::cmp::min(n_buffered, buf.len());
+            buf[..n].copy_from_slice(&self.pushback[self.pushback_pos..self.pushback_pos + n]);
+            self.pushback_pos += n;
+
+            if self.pushback_pos == self.pushback.len() {
+                self.pushback.clear();
+                self.pushback_pos = 0;
+            }
+
+            Ok(n)
+        } else {
+            self.inner.read(buf)
+        };
 
         if let Ok(n) = result {
             self.offset += n as u64;
@@ -93,6 +167,57 @@ impl<R: Read> Read for AligningReader<R> {
     }
 }
 
+impl<R: BufRead> BufRead for AligningReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pushback_pos < self.pushback.len() {
+            Ok(&self.pushback[self.pushback_pos..])
+        } else {
+            self.inner.fill_buf()
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.pushback_pos < self.pushback.len() {
+            self.pushback_pos += amt;
+
+            if self.pushback_pos >= self.pushback.len() {
+                self.pushback.clear();
+                self.pushback_pos = 0;
+            }
+        } else {
+            self.inner.consume(amt);
+        }
+
+        self.offset += amt as u64;
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn aligning_reader_peek() {
+    let data: Vec<u8> = (0..20).collect();
+    let mut ar = AligningReader::with_offset(&data[..], 3);
+
+    assert_eq!(ar.peek(2).unwrap(), &[0, 1]);
+    assert_eq!(ar.peek(4).unwrap(), &[0, 1, 2, 3]);
+    assert_eq!(ar.offset(), 3);
+
+    let mut buf = [0u8; 3];
+    ar.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, [0, 1, 2]);
+    assert_eq!(ar.offset(), 6);
+
+    assert_eq!(ar.align_to(8).unwrap(), false);
+    assert_eq!(ar.offset(), 8);
+    assert_eq!(ar.peek(1).unwrap(), &[5]);
+    assert_eq!(ar.peek(100).unwrap().len(), 15);
+
+    let mut line = Vec::new();
+    ar.read_until(9, &mut line).unwrap();
+    assert_eq!(line, [5, 6, 7, 8, 9]);
+    assert_eq!(ar.offset(), 13);
+}
+
 /// In analogoy with AligningReader, this struct wraps a Write type to equip
 /// it with hooks to track its alignment — that is, how many bytes into the
 /// stream the write has progressed, and whether the current offset is an
@@ -173,6 +298,603 @@ impl<W: Write> Write for AligningWriter<W> {
     }
 }
 
+/// The 64-bit xxHash checksum, with a seed of zero. This is not a
+/// cryptographic hash, but it is very fast and has good collision
+/// properties.
+///
+/// This type is only available if the `xxhash` feature of this crate is
+/// enabled.
+#[cfg(feature = "xxhash")]
+pub struct XxHash64(::twox_hash::XxHash64);
+
+#[cfg(feature = "xxhash")]
+impl XxHash64 {
+    /// Create a new xxHash64 checksum state.
+    pub fn new() -> Self {
+        XxHash64(::twox_hash::XxHash64::with_seed(0))
+    }
+}
+
+#[cfg(feature = "xxhash")]
+impl Checksum for XxHash64 {
+    fn update(&mut self, buf: &[u8]) {
+        use std::hash::Hasher;
+        self.0.write(buf);
+    }
+
+    fn value(&self) -> u64 {
+        use std::hash::Hasher;
+        self.0.finish()
+    }
+
+    fn reset(&mut self) {
+        self.0 = ::twox_hash::XxHash64::with_seed(0);
+    }
+}
+
+/// This struct wraps a Read type and computes a checksum of all of the bytes
+/// that are read through it.
+///
+/// The checksum algorithm is pluggable; `Crc32` is used if no other type is
+/// specified.
+pub struct ChecksummingReader<R: Read, C: Checksum = Crc32> {
+    inner: R,
+    checksum: C,
+}
+
+impl<R: Read> ChecksummingReader<R, Crc32> {
+    /// Create a new ChecksummingReader that wraps the argument *inner* and
+    /// computes a CRC-32 checksum.
+    pub fn new(inner: R) -> Self {
+        ChecksummingReader::with_checksum(inner, Crc32::new())
+    }
+}
+
+impl<R: Read, C: Checksum> ChecksummingReader<R, C> {
+    /// Create a new ChecksummingReader that wraps the argument *inner* and
+    /// computes its checksum using the state *checksum*.
+    pub fn with_checksum(inner: R, checksum: C) -> Self {
+        ChecksummingReader {
+            inner: inner,
+            checksum: checksum,
+        }
+    }
+
+    /// Get the checksum of all of the data read so far.
+    pub fn checksum(&self) -> u64 {
+        self.checksum.value()
+    }
+
+    /// Reset the checksum state, so that subsequent reads are checksummed
+    /// as if they were the beginning of the stream.
+    pub fn reset_checksum(&mut self) {
+        self.checksum.reset();
+    }
+
+    /// Consume this struct, returning the underlying inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, C: Checksum> Read for ChecksummingReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.checksum.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// This struct wraps a Write type and computes a checksum of all of the
+/// bytes that are written through it.
+///
+/// The checksum algorithm is pluggable; `Crc32` is used if no other type is
+/// specified.
+pub struct ChecksummingWriter<W: Write, C: Checksum = Crc32> {
+    inner: W,
+    checksum: C,
+}
+
+impl<W: Write> ChecksummingWriter<W, Crc32> {
+    /// Create a new ChecksummingWriter that wraps the argument *inner* and
+    /// computes a CRC-32 checksum.
+    pub fn new(inner: W) -> Self {
+        ChecksummingWriter::with_checksum(inner, Crc32::new())
+    }
+}
+
+impl<W: Write, C: Checksum> ChecksummingWriter<W, C> {
+    /// Create a new ChecksummingWriter that wraps the argument *inner* and
+    /// computes its checksum using the state *checksum*.
+    pub fn with_checksum(inner: W, checksum: C) -> Self {
+        ChecksummingWriter {
+            inner: inner,
+            checksum: checksum,
+        }
+    }
+
+    /// Get the checksum of all of the data written so far.
+    pub fn checksum(&self) -> u64 {
+        self.checksum.value()
+    }
+
+    /// Reset the checksum state, so that subsequent writes are checksummed
+    /// as if they were the beginning of the stream.
+    pub fn reset_checksum(&mut self) {
+        self.checksum.reset();
+    }
+
+    /// Consume this struct, returning the underlying inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write, C: Checksum> Write for ChecksummingWriter<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.checksum.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn checksum_known_values() {
+    let mut r = ChecksummingReader::new(&b"123456789"[..]);
+    let mut buf = Vec::new();
+    r.read_to_end(&mut buf).unwrap();
+    assert_eq!(r.checksum(), 0xCBF43926);
+
+    let mut w = ChecksummingWriter::with_checksum(Vec::new(), Adler32::new());
+    w.write_all(b"Wikipedia").unwrap();
+    assert_eq!(w.checksum(), 0x11E60398);
+    w.reset_checksum();
+    assert_eq!(w.checksum(), 1);
+    assert_eq!(w.into_inner(), b"Wikipedia");
+}
+
+/// The environment variable used to pass the number of I/O retries to
+/// sub-commands, as `rubbl pipeline` does.
+pub const IO_RETRIES_ENV_VAR: &str = "RUBBL_IO_RETRIES";
+
+/// The environment variable used to pass the I/O rate limit to
+/// sub-commands, as `rubbl pipeline` does.
+pub const IO_RATE_LIMIT_ENV_VAR: &str = "RUBBL_IO_RATE_LIMIT";
+
+/// The OS error codes that indicate a transient failure: `EIO`, `ESTALE`,
+/// and `EAGAIN`.
+#[cfg(target_os = "linux")]
+const TRANSIENT_OS_ERRORS: &[i32] = &[5, 116, 11];
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+const TRANSIENT_OS_ERRORS: &[i32] = &[5, 70, 35];
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+const TRANSIENT_OS_ERRORS: &[i32] = &[];
+
+/// Return whether *err* is the kind of error that network filesystems such
+/// as NFS and Lustre report during brief outages, so that the operation is
+/// worth retrying.
+pub fn is_transient(err: &io::Error) -> bool {
+    match err.kind() {
+        io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => true,
+        _ => match err.raw_os_error() {
+            Some(code) => TRANSIENT_OS_ERRORS.contains(&code),
+            None => false,
+        },
+    }
+}
+
+/// How to retry I/O operations that fail with transient errors.
+///
+/// After a failure, the operation is retried after *initial_delay*, and the
+/// delay doubles with each further failure up to *max_delay*. Errors that
+/// are not transient, as judged by `is_transient`, are never retried.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of times to retry an operation.
+    pub max_retries: u32,
+
+    /// The delay before the first retry.
+    pub initial_delay: Duration,
+
+    /// The longest delay between retries.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 5,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Run *op*, retrying it as this policy allows. *n_retries* is
+    /// incremented for each retry.
+    pub fn run<T, F>(&self, n_retries: &mut u64, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+    {
+        let mut delay = self.initial_delay;
+        let mut attempt = 0;
+
+        loop {
+            match op() {
+                Err(ref e) if attempt < self.max_retries && is_transient(e) => {
+                    thread::sleep(delay);
+                    delay = cmp::min(delay * 2, self.max_delay);
+                    attempt += 1;
+                    *n_retries += 1;
+                }
+
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A limit on the average rate at which data are transferred.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    bytes_per_second: u64,
+    start: Instant,
+    n_bytes: u64,
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing *bytes_per_second* bytes per second,
+    /// averaged since its creation.
+    pub fn new(bytes_per_second: u64) -> Self {
+        RateLimiter {
+            bytes_per_second: cmp::max(bytes_per_second, 1),
+            start: Instant::now(),
+            n_bytes: 0,
+        }
+    }
+
+    /// Record the transfer of *n_bytes* bytes, sleeping for as long as is
+    /// needed to bring the average rate back under the limit.
+    pub fn consume(&mut self, n_bytes: usize) {
+        self.n_bytes += n_bytes as u64;
+        let due = Duration::from_secs(self.n_bytes / self.bytes_per_second)
+            + Duration::from_nanos(
+                (self.n_bytes % self.bytes_per_second) * 1_000_000_000 / self.bytes_per_second,
+            );
+        let elapsed = self.start.elapsed();
+
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+    }
+}
+
+/// Settings for I/O on unreliable storage: how to retry failed operations,
+/// and an optional limit on throughput.
+///
+/// Long-running conversions that read from or write to network filesystems
+/// can be aborted by momentary outages; wrapping their streams with
+/// `IoPolicy::reader` and `IoPolicy::writer` lets them ride these out. A
+/// throughput limit keeps a single job from saturating a shared filesystem.
+///
+/// Command-line tools get the policy with `IoPolicy::from_clap`, which also
+/// honors the environment variables set by `rubbl pipeline`. CASA tables are
+/// read and written by casacore itself, so the policy cannot apply to them.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct IoPolicy {
+    /// How to retry operations that fail with transient errors.
+    pub retry: RetryPolicy,
+
+    /// The maximum average throughput of each stream, in bytes per second,
+    /// if it is limited.
+    pub max_bytes_per_second: Option<u64>,
+}
+
+impl IoPolicy {
+    /// Wrap *inner* in a reader that follows this policy.
+    pub fn reader<R: Read>(&self, inner: R) -> RetryingReader<R> {
+        RetryingReader {
+            inner: inner,
+            retry: self.retry,
+            limiter: self.max_bytes_per_second.map(RateLimiter::new),
+            n_retries: 0,
+        }
+    }
+
+    /// Wrap *inner* in a writer that follows this policy.
+    pub fn writer<W: Write>(&self, inner: W) -> RetryingWriter<W> {
+        RetryingWriter {
+            inner: inner,
+            retry: self.retry,
+            limiter: self.max_bytes_per_second.map(RateLimiter::new),
+            n_retries: 0,
+        }
+    }
+
+    /// Determine the policy from command-line arguments added with
+    /// `ClapIoPolicyArgsExt::rubbl_io_policy_args`, falling back to the
+    /// `RUBBL_IO_RETRIES` and `RUBBL_IO_RATE_LIMIT` environment variables
+    /// and then to the defaults. Rate limits are given as memory sizes per
+    /// second, as parsed by `MemoryBudget::parse`.
+    pub fn from_clap(matches: &clap::ArgMatches) -> super::Result<Self> {
+        let mut policy = IoPolicy::default();
+
+        let retries = matches
+            .value_of("io_retries")
+            .map(|s| s.to_owned())
+            .or_else(|| env::var(IO_RETRIES_ENV_VAR).ok());
+
+        if let Some(text) = retries {
+            policy.retry.max_retries = ctry!(text.trim().parse::<u32>();
+                                             "cannot parse \"{}\" as a number of retries", text);
+        }
+
+        let rate_limit = matches
+            .value_of("io_rate_limit")
+            .map(|s| s.to_owned())
+            .or_else(|| env::var(IO_RATE_LIMIT_ENV_VAR).ok());
+
+        if let Some(text) = rate_limit {
+            policy.max_bytes_per_second = Some(MemoryBudget::parse(&text)?.bytes());
+        }
+
+        Ok(policy)
+    }
+}
+
+/// Extend a `clap::App` with the standard I/O policy arguments.
+pub trait ClapIoPolicyArgsExt {
+    /// Add the `--io-retries` and `--io-rate-limit` arguments to this App.
+    fn rubbl_io_policy_args(self) -> Self;
+}
+
+impl<'a, 'b> ClapIoPolicyArgsExt for clap::App<'a, 'b> {
+    fn rubbl_io_policy_args(self) -> Self {
+        self.arg(
+            clap::Arg::with_name("io_retries")
+                .long("io-retries")
+                .value_name("N")
+                .help("How many times to retry I/O operations that fail transiently (default: 5)"),
+        )
+        .arg(
+            clap::Arg::with_name("io_rate_limit")
+                .long("io-rate-limit")
+                .value_name("SIZE")
+                .help("Limit the throughput of each file to SIZE per second (e.g. \"100M\")"),
+        )
+    }
+}
+
+/// A reader that retries reads that fail with transient errors and
+/// optionally limits its throughput. Created by `IoPolicy::reader`.
+///
+/// A failed read is simply reissued, which assumes that the underlying
+/// stream did not advance. This holds for files, where a read that fails
+/// transfers no data.
+#[derive(Debug)]
+pub struct RetryingReader<R: Read> {
+    inner: R,
+    retry: RetryPolicy,
+    limiter: Option<RateLimiter>,
+    n_retries: u64,
+}
+
+impl<R: Read> RetryingReader<R> {
+    /// Get the number of retries made so far.
+    pub fn n_retries(&self) -> u64 {
+        self.n_retries
+    }
+
+    /// Consume this struct, returning the underlying inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for RetryingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let inner = &mut self.inner;
+        let n = self.retry.run(&mut self.n_retries, || inner.read(buf))?;
+
+        if let Some(ref mut l) = self.limiter {
+            l.consume(n);
+        }
+
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for RetryingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let inner = &mut self.inner;
+        self.retry.run(&mut self.n_retries, || inner.seek(pos))
+    }
+}
+
+/// A writer that retries writes that fail with transient errors and
+/// optionally limits its throughput. Created by `IoPolicy::writer`.
+#[derive(Debug)]
+pub struct RetryingWriter<W: Write> {
+    inner: W,
+    retry: RetryPolicy,
+    limiter: Option<RateLimiter>,
+    n_retries: u64,
+}
+
+impl<W: Write> RetryingWriter<W> {
+    /// Get the number of retries made so far.
+    pub fn n_retries(&self) -> u64 {
+        self.n_retries
+    }
+
+    /// Consume this struct, returning the underlying inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for RetryingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let inner = &mut self.inner;
+        let n = self.retry.run(&mut self.n_retries, || inner.write(buf))?;
+
+        if let Some(ref mut l) = self.limiter {
+            l.consume(n);
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let inner = &mut self.inner;
+        self.retry.run(&mut self.n_retries, || inner.flush())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn retrying_reader() {
+    /// A reader that fails with `EIO` a given number of times.
+    struct Flaky {
+        failures: u32,
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::from_raw_os_error(5));
+            }
+
+            buf[0] = 42;
+            Ok(1)
+        }
+    }
+
+    let policy = IoPolicy {
+        retry: RetryPolicy {
+            max_retries: 2,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        },
+        max_bytes_per_second: None,
+    };
+
+    let mut buf = [0u8; 1];
+    let mut r = policy.reader(Flaky { failures: 2 });
+    assert_eq!(r.read(&mut buf).unwrap(), 1);
+    assert_eq!(r.n_retries(), 2);
+
+    let mut r = policy.reader(Flaky { failures: 3 });
+    assert!(r.read(&mut buf).is_err());
+
+    let mut r = policy.reader(&b"x"[..]);
+    assert_eq!(r.read(&mut buf).unwrap(), 1);
+    assert_eq!(r.n_retries(), 0);
+    assert!(!is_transient(&io::Error::new(
+        io::ErrorKind::NotFound,
+        "gone"
+    )));
+}
+
+/// A policy that bounds how much memory a decoder may allocate based on
+/// length fields that it reads from a data stream.
+///
+/// File formats frequently encode the sizes of upcoming data chunks in the
+/// data themselves. If a file is corrupt, trusting those sizes blindly can
+/// lead to enormous allocations and the process being killed. Decoders that
+/// size buffers based on such fields should validate them with
+/// `SizeLimit::check` first.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SizeLimit {
+    max_bytes: u64,
+}
+
+/// An error type used when a length field read from a data stream exceeds
+/// the `SizeLimit` in effect.
+#[derive(Fail, Debug)]
+#[fail(
+    display = "refusing to allocate {} bytes based on a length field in the data; the limit is {} bytes",
+    requested, limit
+)]
+pub struct SizeLimitExceededError {
+    /// The number of bytes that the data stream asked for.
+    pub requested: u64,
+
+    /// The limit that was in effect.
+    pub limit: u64,
+}
+
+impl SizeLimit {
+    /// The default maximum size of a single allocation, in bytes: 1 GiB.
+    pub const DEFAULT_MAX_BYTES: u64 = 1 << 30;
+
+    /// Create a new limit that allows allocations of up to *max_bytes*
+    /// bytes.
+    pub fn new(max_bytes: u64) -> Self {
+        SizeLimit {
+            max_bytes: max_bytes,
+        }
+    }
+
+    /// Create a limit that allows allocations of any size.
+    pub fn unlimited() -> Self {
+        SizeLimit {
+            max_bytes: u64::max_value(),
+        }
+    }
+
+    /// Get the maximum number of bytes that this limit allows.
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    /// Check whether an allocation of *n_bytes* bytes is allowed.
+    pub fn check(&self, n_bytes: u64) -> result::Result<(), SizeLimitExceededError> {
+        if n_bytes > self.max_bytes {
+            Err(SizeLimitExceededError {
+                requested: n_bytes,
+                limit: self.max_bytes,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for SizeLimit {
+    fn default() -> Self {
+        SizeLimit::new(Self::DEFAULT_MAX_BYTES)
+    }
+}
+
 /// This is an extension trait that makes it more convenient to handle errors
 /// when opening files that may be missing.
 ///
@@ -302,7 +1024,7 @@ pub trait EofReadExactExt: Read {
     where
         E: From<io::Error>,
     {
-        let mut buf = [0u8; 4];
+        let mut buf = [0u8; 8];
 
         if self.eof_read_exact(&mut buf)? {
             Ok(Some(BigEndian::read_f64(&buf)))
@@ -0,0 +1,226 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Inner-loop kernels for processing complex visibility data.
+
+Applying calibration gains and averaging visibilities both boil down to
+simple loops over slices of `Complex<f32>`, and these loops tend to dominate
+the runtime of the tools that perform them. This module provides those loops
+in one place so that they can be optimized once.
+
+If the `simd` feature of this crate is enabled, the kernels use AVX
+instructions on x86-64 processors that support them, as detected at runtime.
+Otherwise, or on other processors, portable scalar code is used; it is
+written so that the compiler has a good chance of auto-vectorizing it.
+
+All of the kernels panic if the slices that they are given have different
+lengths.
+
+*/
+
+use num_complex::Complex;
+
+/// Multiply each element of *data* by the corresponding element of
+/// *factors*, in place.
+pub fn multiply_in_place(data: &mut [Complex<f32>], factors: &[Complex<f32>]) {
+    assert_eq!(data.len(), factors.len());
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx") {
+            unsafe { avx::multiply_in_place(data, factors) };
+            return;
+        }
+    }
+
+    multiply_in_place_scalar(data, factors);
+}
+
+/// Multiply each element of *data* by *factor*, in place.
+///
+/// This is the operation of applying a baseline's gain, `g1 * conj(g2)`, to
+/// its visibilities.
+pub fn scale_in_place(data: &mut [Complex<f32>], factor: Complex<f32>) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx") {
+            unsafe { avx::scale_in_place(data, factor) };
+            return;
+        }
+    }
+
+    for d in data.iter_mut() {
+        *d *= factor;
+    }
+}
+
+/// Accumulate weighted data into running sums.
+///
+/// For each element, `weight * data` is added to *accum* and `weight` is
+/// added to *weight_sums*. Dividing the former by the latter afterwards
+/// yields the weighted average.
+pub fn accumulate_weighted(
+    accum: &mut [Complex<f32>],
+    weight_sums: &mut [f32],
+    data: &[Complex<f32>],
+    weights: &[f32],
+) {
+    assert_eq!(accum.len(), data.len());
+    assert_eq!(weight_sums.len(), data.len());
+    assert_eq!(weights.len(), data.len());
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx") {
+            unsafe { avx::accumulate_weighted(accum, weight_sums, data, weights) };
+            return;
+        }
+    }
+
+    accumulate_weighted_scalar(accum, weight_sums, data, weights);
+}
+
+fn multiply_in_place_scalar(data: &mut [Complex<f32>], factors: &[Complex<f32>]) {
+    for (d, f) in data.iter_mut().zip(factors) {
+        *d *= *f;
+    }
+}
+
+fn accumulate_weighted_scalar(
+    accum: &mut [Complex<f32>],
+    weight_sums: &mut [f32],
+    data: &[Complex<f32>],
+    weights: &[f32],
+) {
+    for i in 0..data.len() {
+        accum[i] += data[i] * weights[i];
+        weight_sums[i] += weights[i];
+    }
+}
+
+/// AVX implementations. Each 256-bit register holds four complex values as
+/// interleaved (re, im) pairs, which matches the memory layout of
+/// `Complex<f32>`. Leftover elements are handled with the scalar code.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod avx {
+    use num_complex::Complex;
+    use std::arch::x86_64::*;
+
+    /// Multiply four pairs of complex values.
+    #[inline]
+    #[target_feature(enable = "avx")]
+    unsafe fn cmul(a: __m256, b: __m256) -> __m256 {
+        let b_re = _mm256_moveldup_ps(b); // (br, br) pairs
+        let b_im = _mm256_movehdup_ps(b); // (bi, bi) pairs
+        let a_swapped = _mm256_permute_ps(a, 0xB1); // (ai, ar) pairs
+        _mm256_addsub_ps(_mm256_mul_ps(a, b_re), _mm256_mul_ps(a_swapped, b_im))
+    }
+
+    #[target_feature(enable = "avx")]
+    pub unsafe fn multiply_in_place(data: &mut [Complex<f32>], factors: &[Complex<f32>]) {
+        let n_vec = data.len() / 4;
+        let dp = data.as_mut_ptr() as *mut f32;
+        let fp = factors.as_ptr() as *const f32;
+
+        for i in 0..n_vec {
+            let d = _mm256_loadu_ps(dp.add(8 * i));
+            let f = _mm256_loadu_ps(fp.add(8 * i));
+            _mm256_storeu_ps(dp.add(8 * i), cmul(d, f));
+        }
+
+        super::multiply_in_place_scalar(&mut data[4 * n_vec..], &factors[4 * n_vec..]);
+    }
+
+    #[target_feature(enable = "avx")]
+    pub unsafe fn scale_in_place(data: &mut [Complex<f32>], factor: Complex<f32>) {
+        let n_vec = data.len() / 4;
+        let dp = data.as_mut_ptr() as *mut f32;
+        let f = _mm256_setr_ps(
+            factor.re, factor.im, factor.re, factor.im, factor.re, factor.im, factor.re, factor.im,
+        );
+
+        for i in 0..n_vec {
+            let d = _mm256_loadu_ps(dp.add(8 * i));
+            _mm256_storeu_ps(dp.add(8 * i), cmul(d, f));
+        }
+
+        for d in data[4 * n_vec..].iter_mut() {
+            *d *= factor;
+        }
+    }
+
+    #[target_feature(enable = "avx")]
+    pub unsafe fn accumulate_weighted(
+        accum: &mut [Complex<f32>],
+        weight_sums: &mut [f32],
+        data: &[Complex<f32>],
+        weights: &[f32],
+    ) {
+        let n_vec = data.len() / 4;
+        let ap = accum.as_mut_ptr() as *mut f32;
+        let sp = weight_sums.as_mut_ptr();
+        let dp = data.as_ptr() as *const f32;
+        let wp = weights.as_ptr();
+
+        for i in 0..n_vec {
+            let w = _mm_loadu_ps(wp.add(4 * i));
+            // Duplicate each weight so that it lines up with both halves of
+            // its complex value.
+            let w2 = _mm256_set_m128(_mm_unpackhi_ps(w, w), _mm_unpacklo_ps(w, w));
+
+            let d = _mm256_loadu_ps(dp.add(8 * i));
+            let a = _mm256_loadu_ps(ap.add(8 * i));
+            _mm256_storeu_ps(ap.add(8 * i), _mm256_add_ps(a, _mm256_mul_ps(d, w2)));
+
+            let s = _mm_loadu_ps(sp.add(4 * i));
+            _mm_storeu_ps(sp.add(4 * i), _mm_add_ps(s, w));
+        }
+
+        let k = 4 * n_vec;
+        super::accumulate_weighted_scalar(
+            &mut accum[k..],
+            &mut weight_sums[k..],
+            &data[k..],
+            &weights[k..],
+        );
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn kernels_match_naive() {
+    let n = 11; // exercise the leftover handling of the vector paths
+    let data: Vec<_> = (0..n)
+        .map(|i| Complex::new(i as f32 + 0.5, 2. - i as f32))
+        .collect();
+    let factors: Vec<_> = (0..n)
+        .map(|i| Complex::new(0.25 * i as f32, 1. + 0.5 * i as f32))
+        .collect();
+    let weights: Vec<_> = (0..n).map(|i| 1. + i as f32).collect();
+
+    let mut prod = data.clone();
+    multiply_in_place(&mut prod, &factors);
+
+    for i in 0..n {
+        assert_eq!(prod[i], data[i] * factors[i]);
+    }
+
+    let g = Complex::new(1.5, -0.5);
+    let mut scaled = data.clone();
+    scale_in_place(&mut scaled, g);
+
+    for i in 0..n {
+        assert_eq!(scaled[i], data[i] * g);
+    }
+
+    let mut accum = vec![Complex::new(1., 1.); n];
+    let mut wsum = vec![1.; n];
+    accumulate_weighted(&mut accum, &mut wsum, &data, &weights);
+
+    for i in 0..n {
+        assert_eq!(accum[i], Complex::new(1., 1.) + data[i] * weights[i]);
+        assert_eq!(wsum[i], 1. + weights[i]);
+    }
+}
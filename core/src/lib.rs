@@ -8,24 +8,59 @@ Core types and concepts of the Rubbl framework.
 This crate provides low-level types that are expected to be used throughout
 the Rubbl framework.
 
+The decoding machinery in this crate operates on generic streams and so
+works on `wasm32` targets. The `output` and `sidecar` modules, which
+manipulate files on disk, are only available with the `fs` feature, which is
+on by default.
+
+Without the default `std` feature, the crate is `no_std` and provides only
+the `decode` module, which requires nothing beyond `alloc`.
+
 */
 
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate adler32;
+extern crate alloc;
+#[cfg(feature = "std")]
 extern crate byteorder;
+#[cfg(feature = "std")]
 extern crate clap;
+extern crate crc32fast;
+#[cfg(feature = "std")]
 extern crate failure;
+#[cfg(feature = "lz4-codec")]
+extern crate lz4_flex;
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate failure_derive;
+#[cfg(feature = "std")]
 extern crate ndarray;
+#[cfg(feature = "std")]
 extern crate num_complex;
+#[cfg(feature = "std")]
+extern crate serde;
+#[cfg(feature = "std")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "std")]
+extern crate serde_json;
+#[cfg(feature = "std")]
 extern crate termcolor;
+#[cfg(feature = "xxhash")]
+extern crate twox_hash;
+#[cfg(feature = "zstd-codec")]
+extern crate zstd;
 
 // convenience re-exports -- these can make it so that you can skip putting
 // these crates in your Cargo.toml and the `extern crate` line in the toplevel
 // of your crate.
+#[cfg(feature = "std")]
 pub use failure::{Error, Fail, ResultExt};
+#[cfg(feature = "std")]
 pub use ndarray::Array;
+#[cfg(feature = "std")]
 pub use num_complex::Complex;
 
 /// A “contextualized try” macro.
@@ -51,10 +86,45 @@ macro_rules! ctry {
     }
 }
 
+#[cfg(feature = "std")]
+pub mod budget;
+#[cfg(feature = "std")]
+pub mod codec;
+#[cfg(feature = "std")]
+pub mod colormap;
+pub mod decode;
+#[cfg(feature = "std")]
+pub mod dryrun;
+#[cfg(feature = "std")]
 pub mod io;
+#[cfg(feature = "std")]
+pub mod kernels;
+#[cfg(feature = "std")]
+pub mod lm;
+#[cfg(feature = "std")]
+pub mod nan;
+#[cfg(feature = "std")]
 pub mod notify;
+#[cfg(feature = "std")]
 pub mod num;
+#[cfg(feature = "fs")]
+pub mod output;
+#[cfg(feature = "std")]
+pub mod report;
+#[cfg(feature = "std")]
+pub mod rng;
+#[cfg(feature = "std")]
+pub mod select;
+#[cfg(feature = "fs")]
+pub mod sidecar;
+#[cfg(feature = "std")]
+pub mod telescopes;
+#[cfg(feature = "std")]
+pub mod time;
+#[cfg(feature = "std")]
+pub mod units;
 
 /// A convenience Result type whose error half is fixed to be
 /// `failure::Error`.
+#[cfg(feature = "std")]
 pub type Result<T> = ::std::result::Result<T, failure::Error>;
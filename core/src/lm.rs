@@ -0,0 +1,469 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Non-linear least-squares fitting with the Levenberg–Marquardt algorithm.
+
+A fitting problem is described by implementing `LeastSquaresProblem`: given
+a vector of parameters *p*, it computes a vector of residuals *r*(*p*), and
+`LevenbergMarquardt::minimize` finds the parameters that minimize Σ *r*ᵢ².
+Residuals are usually (data − model) / σ, so that the minimum is the χ²
+best fit.
+
+Each iteration solves the damped normal equations
+
+  (JᵀJ + λ diag(JᵀJ)) δ = −Jᵀ*r*
+
+where J is the Jacobian matrix ∂*r*ᵢ/∂*p*ⱼ. Problems may compute J
+analytically; if they do not, it is estimated with forward differences.
+The damping λ shrinks after steps that reduce the sum of squares and grows
+after steps that do not, so that the algorithm moves smoothly between
+Gauss–Newton and gradient descent.
+
+The problems that Rubbl needs to solve — antenna gains, beam shapes, fringe
+delays and rates — have a modest number of parameters, so the normal
+equations are solved directly with a Cholesky decomposition.
+
+*/
+
+use std::f64;
+
+/// An error type for when a fit cannot be attempted.
+#[derive(Fail, Debug)]
+pub enum LeastSquaresError {
+    /// The problem has fewer residuals than parameters.
+    #[fail(display = "cannot fit {} parameters to only {} residuals", _0, _1)]
+    Underdetermined(usize, usize),
+
+    /// The residuals are not finite at the initial parameters.
+    #[fail(display = "the residuals are not finite at the initial parameters")]
+    NonFiniteResiduals,
+}
+
+/// A non-linear least-squares problem.
+pub trait LeastSquaresProblem {
+    /// Get the number of residuals.
+    fn n_residuals(&self) -> usize;
+
+    /// Compute the residuals for the parameters *params*, storing them in
+    /// *residuals*.
+    fn residuals(&self, params: &[f64], residuals: &mut [f64]);
+
+    /// Compute the Jacobian matrix of the residuals with respect to the
+    /// parameters, storing it in *jacobian* in row-major order: element
+    /// `[i * n_params + j]` is ∂*r*ᵢ/∂*p*ⱼ.
+    ///
+    /// Returns false if the problem does not compute the Jacobian
+    /// analytically, which is the default; it is then estimated
+    /// numerically.
+    fn jacobian(&self, _params: &[f64], _jacobian: &mut [f64]) -> bool {
+        false
+    }
+}
+
+/// The outcome of a fit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeastSquaresReport {
+    /// The number of iterations taken.
+    pub n_iterations: usize,
+
+    /// The sum of the squared residuals at the best-fit parameters.
+    pub sum_of_squares: f64,
+
+    /// Whether one of the convergence criteria was met before the maximum
+    /// number of iterations was reached.
+    pub converged: bool,
+
+    /// The covariance matrix of the parameters, (JᵀJ)⁻¹, in row-major
+    /// order. This is the uncertainty of the parameters if the residuals
+    /// are normalized by their uncertainties. It is `None` if JᵀJ is
+    /// singular at the best fit.
+    pub covariance: Option<Vec<f64>>,
+}
+
+/// The Levenberg–Marquardt fitting algorithm.
+#[derive(Clone, Debug)]
+pub struct LevenbergMarquardt {
+    max_iterations: usize,
+    ftol: f64,
+    xtol: f64,
+    gtol: f64,
+    initial_lambda: f64,
+}
+
+impl Default for LevenbergMarquardt {
+    fn default() -> Self {
+        LevenbergMarquardt {
+            max_iterations: 200,
+            ftol: 1e-10,
+            xtol: 1e-10,
+            gtol: 1e-12,
+            initial_lambda: 1e-3,
+        }
+    }
+}
+
+/// The largest damping tried before the fit is considered stuck.
+const MAX_LAMBDA: f64 = 1e16;
+
+impl LevenbergMarquardt {
+    /// Create a fitter with the default settings: at most 200 iterations,
+    /// relative tolerances of 10⁻¹⁰ on the sum of squares and on the
+    /// parameters, and a tolerance of 10⁻¹² on the gradient.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of iterations.
+    pub fn set_max_iterations(&mut self, max_iterations: usize) -> &mut Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Set the relative reduction of the sum of squares below which an
+    /// iteration counts as converged.
+    pub fn set_ftol(&mut self, ftol: f64) -> &mut Self {
+        self.ftol = ftol;
+        self
+    }
+
+    /// Set the relative change of the parameters below which an iteration
+    /// counts as converged.
+    pub fn set_xtol(&mut self, xtol: f64) -> &mut Self {
+        self.xtol = xtol;
+        self
+    }
+
+    /// Set the largest element of the gradient Jᵀ*r* below which the fit
+    /// counts as converged.
+    pub fn set_gtol(&mut self, gtol: f64) -> &mut Self {
+        self.gtol = gtol;
+        self
+    }
+
+    /// Set the initial damping parameter λ.
+    pub fn set_initial_lambda(&mut self, initial_lambda: f64) -> &mut Self {
+        self.initial_lambda = initial_lambda;
+        self
+    }
+
+    /// Fit *problem*, starting from the parameters in *params* and leaving
+    /// the best-fit parameters there.
+    pub fn minimize<P: LeastSquaresProblem + ?Sized>(
+        &self,
+        problem: &P,
+        params: &mut [f64],
+    ) -> Result<LeastSquaresReport, LeastSquaresError> {
+        let n_params = params.len();
+        let n_resid = problem.n_residuals();
+
+        if n_resid < n_params {
+            return Err(LeastSquaresError::Underdetermined(n_params, n_resid));
+        }
+
+        let mut resid = vec![0.; n_resid];
+        problem.residuals(params, &mut resid);
+        let mut sum_sq = sum_of_squares(&resid);
+
+        if !sum_sq.is_finite() {
+            return Err(LeastSquaresError::NonFiniteResiduals);
+        }
+
+        let mut jac = vec![0.; n_resid * n_params];
+        let mut alpha = vec![0.; n_params * n_params];
+        let mut beta = vec![0.; n_params];
+        let mut trial = vec![0.; n_params];
+        let mut trial_resid = vec![0.; n_resid];
+        let mut lambda = self.initial_lambda;
+        let mut n_iterations = 0;
+        let mut converged = false;
+
+        'outer: while n_iterations < self.max_iterations {
+            n_iterations += 1;
+            compute_jacobian(problem, params, &resid, &mut jac);
+            normal_equations(&jac, &resid, n_params, &mut alpha, &mut beta);
+
+            if beta.iter().all(|b| b.abs() <= self.gtol) {
+                converged = true;
+                break;
+            }
+
+            loop {
+                let mut damped = alpha.clone();
+
+                for j in 0..n_params {
+                    let d = alpha[j * n_params + j];
+                    damped[j * n_params + j] = d + lambda * if d > 0. { d } else { 1. };
+                }
+
+                let mut step: Vec<f64> = beta.iter().map(|b| -b).collect();
+
+                if cholesky_solve(&mut damped, n_params, &mut step) {
+                    for j in 0..n_params {
+                        trial[j] = params[j] + step[j];
+                    }
+
+                    problem.residuals(&trial, &mut trial_resid);
+                    let trial_sum_sq = sum_of_squares(&trial_resid);
+
+                    if trial_sum_sq < sum_sq {
+                        let reduction =
+                            (sum_sq - trial_sum_sq) / trial_sum_sq.max(f64::MIN_POSITIVE);
+                        let small_step = step
+                            .iter()
+                            .zip(params.iter())
+                            .all(|(s, p)| s.abs() <= self.xtol * (p.abs() + self.xtol));
+
+                        params.copy_from_slice(&trial);
+                        resid.copy_from_slice(&trial_resid);
+                        sum_sq = trial_sum_sq;
+                        lambda = (lambda * 0.1).max(1e-15);
+
+                        if reduction <= self.ftol || small_step {
+                            converged = true;
+                            break 'outer;
+                        }
+
+                        break;
+                    }
+                }
+
+                lambda *= 10.;
+
+                if lambda > MAX_LAMBDA {
+                    // No downhill step can be found: we are at a minimum, to
+                    // within the precision of the residuals.
+                    converged = true;
+                    break 'outer;
+                }
+            }
+        }
+
+        compute_jacobian(problem, params, &resid, &mut jac);
+        normal_equations(&jac, &resid, n_params, &mut alpha, &mut beta);
+
+        Ok(LeastSquaresReport {
+            n_iterations: n_iterations,
+            sum_of_squares: sum_sq,
+            converged: converged,
+            covariance: invert_symmetric(&alpha, n_params),
+        })
+    }
+}
+
+fn sum_of_squares(resid: &[f64]) -> f64 {
+    resid.iter().map(|r| r * r).sum()
+}
+
+/// Compute the Jacobian of *problem* at *params*, numerically if necessary.
+/// *resid* must hold the residuals at *params*.
+fn compute_jacobian<P: LeastSquaresProblem + ?Sized>(
+    problem: &P,
+    params: &[f64],
+    resid: &[f64],
+    jac: &mut [f64],
+) {
+    if problem.jacobian(params, jac) {
+        return;
+    }
+
+    let n_params = params.len();
+    let mut shifted = params.to_vec();
+    let mut shifted_resid = vec![0.; resid.len()];
+
+    for j in 0..n_params {
+        let h = f64::EPSILON.sqrt() * params[j].abs().max(1.);
+        shifted[j] = params[j] + h;
+        problem.residuals(&shifted, &mut shifted_resid);
+        shifted[j] = params[j];
+
+        for i in 0..resid.len() {
+            jac[i * n_params + j] = (shifted_resid[i] - resid[i]) / h;
+        }
+    }
+}
+
+/// Compute JᵀJ into *alpha* and Jᵀr into *beta*.
+fn normal_equations(
+    jac: &[f64],
+    resid: &[f64],
+    n_params: usize,
+    alpha: &mut [f64],
+    beta: &mut [f64],
+) {
+    for v in alpha.iter_mut().chain(beta.iter_mut()) {
+        *v = 0.;
+    }
+
+    for (i, r) in resid.iter().enumerate() {
+        let row = &jac[i * n_params..(i + 1) * n_params];
+
+        for j in 0..n_params {
+            beta[j] += row[j] * r;
+
+            for k in 0..n_params {
+                alpha[j * n_params + k] += row[j] * row[k];
+            }
+        }
+    }
+}
+
+/// Solve the symmetric positive-definite system *a* x = *b* in place,
+/// leaving the solution in *b* and overwriting *a* with its Cholesky
+/// factor. Returns false if *a* is not positive definite.
+fn cholesky_solve(a: &mut [f64], n: usize, b: &mut [f64]) -> bool {
+    for j in 0..n {
+        let mut d = a[j * n + j];
+
+        for k in 0..j {
+            d -= a[j * n + k] * a[j * n + k];
+        }
+
+        if d.is_nan() || d <= 0. {
+            return false;
+        }
+
+        let d = d.sqrt();
+        a[j * n + j] = d;
+
+        for i in (j + 1)..n {
+            let mut s = a[i * n + j];
+
+            for k in 0..j {
+                s -= a[i * n + k] * a[j * n + k];
+            }
+
+            a[i * n + j] = s / d;
+        }
+    }
+
+    // Forward substitution with L, then back substitution with Lᵀ.
+    for i in 0..n {
+        for k in 0..i {
+            b[i] -= a[i * n + k] * b[k];
+        }
+
+        b[i] /= a[i * n + i];
+    }
+
+    for i in (0..n).rev() {
+        for k in (i + 1)..n {
+            b[i] -= a[k * n + i] * b[k];
+        }
+
+        b[i] /= a[i * n + i];
+    }
+
+    true
+}
+
+/// Invert a symmetric positive-definite matrix, or return `None` if it is
+/// singular.
+fn invert_symmetric(a: &[f64], n: usize) -> Option<Vec<f64>> {
+    let mut inverse = vec![0.; n * n];
+
+    for j in 0..n {
+        let mut factor = a.to_vec();
+        let mut column = vec![0.; n];
+        column[j] = 1.;
+
+        if !cholesky_solve(&mut factor, n, &mut column) {
+            return None;
+        }
+
+        for i in 0..n {
+            inverse[i * n + j] = column[i];
+        }
+    }
+
+    Some(inverse)
+}
+
+#[cfg(test)]
+#[test]
+fn levenberg_marquardt() {
+    // An exponential decay, y = a exp(-b x), with a numerical Jacobian.
+    struct Decay(Vec<(f64, f64)>);
+
+    impl LeastSquaresProblem for Decay {
+        fn n_residuals(&self) -> usize {
+            self.0.len()
+        }
+
+        fn residuals(&self, p: &[f64], r: &mut [f64]) {
+            for (i, &(x, y)) in self.0.iter().enumerate() {
+                r[i] = y - p[0] * (-p[1] * x).exp();
+            }
+        }
+    }
+
+    let decay = Decay(
+        (0..20)
+            .map(|i| (i as f64 * 0.25, 3. * (-0.7 * i as f64 * 0.25).exp()))
+            .collect(),
+    );
+    let mut params = [1., 0.1];
+    let report = LevenbergMarquardt::new()
+        .minimize(&decay, &mut params)
+        .unwrap();
+    assert!(report.converged);
+    assert!((params[0] - 3.).abs() < 1e-6);
+    assert!((params[1] - 0.7).abs() < 1e-6);
+    assert!(report.sum_of_squares < 1e-12);
+    assert!(report.covariance.is_some());
+
+    // The Rosenbrock function, with an analytic Jacobian.
+    struct Rosenbrock;
+
+    impl LeastSquaresProblem for Rosenbrock {
+        fn n_residuals(&self) -> usize {
+            2
+        }
+
+        fn residuals(&self, p: &[f64], r: &mut [f64]) {
+            r[0] = 10. * (p[1] - p[0] * p[0]);
+            r[1] = 1. - p[0];
+        }
+
+        fn jacobian(&self, p: &[f64], j: &mut [f64]) -> bool {
+            j.copy_from_slice(&[-20. * p[0], 10., -1., 0.]);
+            true
+        }
+    }
+
+    let mut params = [-1.2, 1.];
+    let report = LevenbergMarquardt::new()
+        .minimize(&Rosenbrock, &mut params)
+        .unwrap();
+    assert!(report.converged);
+    assert!((params[0] - 1.).abs() < 1e-8 && (params[1] - 1.).abs() < 1e-8);
+
+    // A straight-line fit recovers the textbook parameter uncertainties.
+    struct Line;
+
+    impl LeastSquaresProblem for Line {
+        fn n_residuals(&self) -> usize {
+            3
+        }
+
+        fn residuals(&self, p: &[f64], r: &mut [f64]) {
+            for i in 0..3 {
+                r[i] = [1., 3., 2.][i] - (p[0] + p[1] * i as f64);
+            }
+        }
+    }
+
+    let mut params = [0., 0.];
+    let report = LevenbergMarquardt::new()
+        .minimize(&Line, &mut params)
+        .unwrap();
+    assert!((params[0] - 1.5).abs() < 1e-8 && (params[1] - 0.5).abs() < 1e-8);
+    let cov = report.covariance.unwrap();
+    assert!((cov[0] - 5. / 6.).abs() < 1e-6 && (cov[3] - 0.5).abs() < 1e-6);
+
+    let mut too_many = [0.; 4];
+    assert!(LevenbergMarquardt::new()
+        .minimize(&Line, &mut too_many)
+        .is_err());
+}
@@ -0,0 +1,213 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Handling non-finite visibilities.
+
+Correlators and earlier processing steps occasionally produce visibilities
+that are NaN or infinite. If they are left in place, a single one poisons
+any sum that it is part of — even one in which it has zero weight, since
+`0 × NaN` is NaN — so that averages, gain solutions, and images are
+silently corrupted. Different tools have historically made different
+choices about what to do with them. A `NanPolicy` makes the choice
+explicit: readers and writers that accept one apply it to every
+visibility that passes through them.
+
+- `NanPolicy::Propagate` leaves the values alone. This is the default, so
+  that data pass through unchanged unless asked otherwise.
+- `NanPolicy::Flag` flags each non-finite value and replaces it with zero,
+  so that it drops out of flag-aware computations and cannot poison
+  weighted sums.
+- `NanPolicy::Error` fails with a `NonFiniteDataError` on the first
+  non-finite value.
+
+*/
+
+use clap;
+use num_complex::Complex;
+use std::fmt;
+
+use super::Result;
+
+/// An error type for when a NaN policy cannot be parsed.
+#[derive(Fail, Debug)]
+#[fail(
+    display = "cannot parse \"{}\" as a NaN policy (expected \"propagate\", \"flag\", or \"error\")",
+    _0
+)]
+pub struct NanPolicyParseError(pub String);
+
+/// An error type for when non-finite data are found under
+/// `NanPolicy::Error`.
+#[derive(Fail, Debug)]
+#[fail(
+    display = "found a non-finite visibility ({}) at index {}",
+    value, index
+)]
+pub struct NonFiniteDataError {
+    /// The index of the value within the buffer that was checked.
+    pub index: usize,
+
+    /// The value itself.
+    pub value: Complex<f32>,
+}
+
+/// What to do with visibilities that are NaN or infinite.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum NanPolicy {
+    /// Leave them as they are.
+    #[default]
+    Propagate,
+
+    /// Flag them and set them to zero.
+    Flag,
+
+    /// Treat them as an error.
+    Error,
+}
+
+impl fmt::Display for NanPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match *self {
+            NanPolicy::Propagate => "propagate",
+            NanPolicy::Flag => "flag",
+            NanPolicy::Error => "error",
+        })
+    }
+}
+
+impl NanPolicy {
+    /// Parse a policy as given by a user: one of `propagate`, `flag`, or
+    /// `error`, not case-sensitive.
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(match text.trim().to_lowercase().as_str() {
+            "propagate" => NanPolicy::Propagate,
+            "flag" => NanPolicy::Flag,
+            "error" => NanPolicy::Error,
+            _ => return Err(NanPolicyParseError(text.to_owned()).into()),
+        })
+    }
+
+    /// Apply this policy to *data*, whose flags are *flags*. Returns the
+    /// number of non-finite values found, which under `Propagate` are merely
+    /// counted.
+    ///
+    /// This panics if the slices have different lengths.
+    pub fn apply(self, data: &mut [Complex<f32>], flags: &mut [bool]) -> Result<usize> {
+        assert_eq!(data.len(), flags.len());
+
+        match self {
+            NanPolicy::Propagate => Ok(count_non_finite(data)),
+            NanPolicy::Flag => Ok(flag_non_finite(data, flags)),
+            NanPolicy::Error => match find_non_finite(data) {
+                Some(index) => Err(NonFiniteDataError {
+                    index: index,
+                    value: data[index],
+                }
+                .into()),
+                None => Ok(0),
+            },
+        }
+    }
+
+    /// Determine the policy from command-line arguments added with
+    /// `ClapNanPolicyArgsExt::rubbl_nan_policy_args`, falling back to the
+    /// default.
+    pub fn from_clap(matches: &clap::ArgMatches) -> Result<Self> {
+        match matches.value_of("nan_policy") {
+            Some(text) => NanPolicy::parse(text),
+            None => Ok(NanPolicy::default()),
+        }
+    }
+}
+
+/// Return whether *value* is finite, i.e., neither part is NaN or infinite.
+#[inline]
+pub fn is_finite(value: Complex<f32>) -> bool {
+    value.re.is_finite() && value.im.is_finite()
+}
+
+/// Get the index of the first non-finite value in *data*, if there is one.
+pub fn find_non_finite(data: &[Complex<f32>]) -> Option<usize> {
+    data.iter().position(|&d| !is_finite(d))
+}
+
+/// Count the non-finite values in *data*.
+pub fn count_non_finite(data: &[Complex<f32>]) -> usize {
+    data.iter().filter(|&&d| !is_finite(d)).count()
+}
+
+/// Flag each non-finite value in *data* and set it to zero, returning the
+/// number of such values.
+///
+/// This panics if the slices have different lengths.
+pub fn flag_non_finite(data: &mut [Complex<f32>], flags: &mut [bool]) -> usize {
+    assert_eq!(data.len(), flags.len());
+    let mut n = 0;
+
+    for (d, f) in data.iter_mut().zip(flags.iter_mut()) {
+        if !is_finite(*d) {
+            *d = Complex::new(0., 0.);
+            *f = true;
+            n += 1;
+        }
+    }
+
+    n
+}
+
+/// Extend a `clap::App` with the standard NaN-policy argument.
+pub trait ClapNanPolicyArgsExt {
+    /// Add the `--nan-policy` argument to this App.
+    fn rubbl_nan_policy_args(self) -> Self;
+}
+
+impl<'a, 'b> ClapNanPolicyArgsExt for clap::App<'a, 'b> {
+    fn rubbl_nan_policy_args(self) -> Self {
+        self.arg(
+            clap::Arg::with_name("nan_policy")
+                .long("nan-policy")
+                .value_name("POLICY")
+                .possible_values(&["propagate", "flag", "error"])
+                .help("What to do with NaN or infinite visibilities"),
+        )
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn nan_policies() {
+    let nan = f32::NAN;
+    let good = [Complex::new(1., 2.), Complex::new(0., -1.)];
+    let bad = [
+        Complex::new(1., 2.),
+        Complex::new(nan, 0.),
+        Complex::new(0., f32::INFINITY),
+    ];
+
+    let mut data = good.to_vec();
+    let mut flags = vec![false; 2];
+    assert_eq!(NanPolicy::Error.apply(&mut data, &mut flags).unwrap(), 0);
+
+    let mut data = bad.to_vec();
+    let mut flags = vec![false; 3];
+    assert_eq!(
+        NanPolicy::Propagate.apply(&mut data, &mut flags).unwrap(),
+        2
+    );
+    assert!(data[1].re.is_nan());
+    assert_eq!(flags, vec![false; 3]);
+
+    assert_eq!(NanPolicy::Flag.apply(&mut data, &mut flags).unwrap(), 2);
+    assert_eq!(data[2], Complex::new(0., 0.));
+    assert_eq!(flags, vec![false, true, true]);
+
+    let mut data = bad.to_vec();
+    let err = NanPolicy::Error.apply(&mut data, &mut flags).unwrap_err();
+    assert_eq!(err.downcast::<NonFiniteDataError>().unwrap().index, 1);
+
+    assert_eq!(NanPolicy::parse(" Flag").unwrap(), NanPolicy::Flag);
+    assert!(NanPolicy::parse("ignore").is_err());
+    assert_eq!(format!("{}", NanPolicy::default()), "propagate");
+}
@@ -7,7 +7,7 @@ General helpers for numerics.
 
 */
 
-use ndarray::{IntoDimension, Ix0, Ix1, Ix2, Ix3, Ix4, Ix5, Ix6};
+use ndarray::{IntoDimension, Ix0, Ix1, Ix2, Ix3, Ix4, Ix5, Ix6, IxDyn};
 
 /// An error type used when two arrays should have the same dimensionality,
 /// but do not.
@@ -72,3 +72,18 @@ impl_dim_from_shape_slice! { Ix3; 3; 0;1;2 }
 impl_dim_from_shape_slice! { Ix4; 4; 0;1;2;3 }
 impl_dim_from_shape_slice! { Ix5; 5; 0;1;2;3;4 }
 impl_dim_from_shape_slice! { Ix6; 6; 0;1;2;3;4;5 }
+
+// Dynamic dimensionality accepts any shape.
+
+impl DimFromShapeSlice<u64> for IxDyn {
+    fn from_shape_slice(shape: &[u64]) -> Result<Self, DimensionMismatchError> {
+        let dims: Vec<usize> = shape.iter().map(|&n| n as usize).collect();
+        Ok(IxDyn(&dims))
+    }
+}
+
+impl DimFromShapeSlice<usize> for IxDyn {
+    fn from_shape_slice(shape: &[usize]) -> Result<Self, DimensionMismatchError> {
+        Ok(IxDyn(shape))
+    }
+}
@@ -0,0 +1,361 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Policies for where the results of data-modifying operations go.
+
+Operations that modify a data set (a CASA table, a MIRIAD data set, …) take an
+`OutputPolicy` specifying whether to modify the input in place or to write a
+modified copy somewhere else. In the latter case, the copy is assembled in a
+temporary location next to its final destination and only renamed into place
+once the operation has completed successfully, so that an interrupted
+operation never leaves a half-written data set at the destination.
+
+The usual pattern is:
+
+```rust,ignore
+let output = policy.prepare(&input_path)?;
+{
+    let mut table = Table::open(output.path(), TableOpenMode::ReadWrite)?;
+    // ... modify the table ...
+} // make sure the table is closed before committing!
+output.commit()?;
+```
+
+Operations that build a new data set from the input, rather than editing a
+copy of it, use `OutputPolicy::prepare_derived` instead, which copies
+nothing.
+
+*/
+
+use clap;
+use failure::err_msg;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use super::Result;
+
+/// An error type for when an operation would clobber an existing data set
+/// without permission.
+#[derive(Fail, Debug)]
+#[fail(
+    display = "output path \"{}\" already exists; use the overwrite option to replace it",
+    _0
+)]
+pub struct OutputExistsError(pub String);
+
+/// Where the results of a data-modifying operation should go.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum OutputPolicy {
+    /// Modify the input data set directly.
+    InPlace,
+
+    /// Write a modified copy to a new path, which must not already exist.
+    NewTable(PathBuf),
+
+    /// Write a modified copy to a path, replacing anything already there.
+    Overwrite(PathBuf),
+}
+
+impl OutputPolicy {
+    /// Set up the output of an operation on the data set at *input*.
+    ///
+    /// For the copying policies, the input is copied to a temporary location
+    /// and `PreparedOutput::path` refers to that copy.
+    pub fn prepare<P: AsRef<Path>>(&self, input: P) -> Result<PreparedOutput> {
+        let input = input.as_ref();
+
+        let prepared = match self.destination()? {
+            None => {
+                return Ok(PreparedOutput {
+                    work_path: input.to_owned(),
+                    dest: None,
+                    overwrite: false,
+                    committed: false,
+                });
+            }
+            Some(p) => p,
+        };
+
+        // If the copy fails, dropping `prepared` cleans up after us.
+        copy_tree(input, &prepared.work_path)?;
+        Ok(prepared)
+    }
+
+    /// Set up the output of an operation that derives a new data set from
+    /// the one at *input* rather than modifying it.
+    ///
+    /// Nothing is copied: the operation must create the data set at
+    /// `PreparedOutput::path`, which does not exist. With the in-place
+    /// policy, committing the output replaces the input.
+    pub fn prepare_derived<P: AsRef<Path>>(&self, input: P) -> Result<PreparedOutput> {
+        let input = input.as_ref();
+
+        match self.destination()? {
+            Some(p) => Ok(p),
+            None => {
+                let work_path = sibling_path(input, "tmp");
+                remove_path(&work_path)?;
+
+                Ok(PreparedOutput {
+                    work_path: work_path,
+                    dest: Some(input.to_owned()),
+                    overwrite: true,
+                    committed: false,
+                })
+            }
+        }
+    }
+
+    /// Set up an empty temporary location for the copying policies, or
+    /// return None for the in-place one.
+    fn destination(&self) -> Result<Option<PreparedOutput>> {
+        let (dest, overwrite) = match *self {
+            OutputPolicy::InPlace => return Ok(None),
+            OutputPolicy::NewTable(ref p) => (p, false),
+            OutputPolicy::Overwrite(ref p) => (p, true),
+        };
+
+        if !overwrite && fs::symlink_metadata(dest).is_ok() {
+            return Err(OutputExistsError(dest.display().to_string()).into());
+        }
+
+        let work_path = sibling_path(dest, "tmp");
+
+        // Make sure we don't try to write on top of debris from a previous
+        // attempt.
+        remove_path(&work_path)?;
+
+        Ok(Some(PreparedOutput {
+            work_path: work_path,
+            dest: Some(dest.to_owned()),
+            overwrite: overwrite,
+            committed: false,
+        }))
+    }
+
+    /// Determine the policy from command-line arguments added with
+    /// `ClapOutputArgsExt::rubbl_output_args`.
+    pub fn from_clap(matches: &clap::ArgMatches) -> Result<Self> {
+        if matches.is_present("output_in_place") {
+            return Ok(OutputPolicy::InPlace);
+        }
+
+        match matches.value_of_os("output_path") {
+            Some(p) => {
+                if matches.is_present("output_overwrite") {
+                    Ok(OutputPolicy::Overwrite(p.into()))
+                } else {
+                    Ok(OutputPolicy::NewTable(p.into()))
+                }
+            }
+
+            None => Err(err_msg(
+                "an output path must be specified (or request in-place modification)",
+            )),
+        }
+    }
+}
+
+/// The destination of a data-modifying operation, as set up by
+/// `OutputPolicy::prepare`.
+///
+/// If this value is dropped without `commit` having been called, any
+/// temporary copy is deleted.
+#[derive(Debug)]
+pub struct PreparedOutput {
+    work_path: PathBuf,
+    dest: Option<PathBuf>,
+    overwrite: bool,
+    committed: bool,
+}
+
+impl PreparedOutput {
+    /// Get the path of the data set that the operation should modify.
+    pub fn path(&self) -> &Path {
+        &self.work_path
+    }
+
+    /// Move the modified data set into its final location, returning that
+    /// location. Any handles onto the data set at `path()` must be closed
+    /// before this is called.
+    pub fn commit(mut self) -> Result<PathBuf> {
+        self.committed = true;
+
+        let dest = match self.dest.take() {
+            None => return Ok(self.work_path.clone()),
+            Some(d) => d,
+        };
+
+        if self.overwrite && fs::symlink_metadata(&dest).is_ok() {
+            // Rename the old data out of the way first, so that the
+            // destination is only ever missing for the instant between the
+            // two renames.
+            let old_path = sibling_path(&dest, "old");
+            remove_path(&old_path)?;
+            fs::rename(&dest, &old_path)?;
+
+            if let Err(e) = fs::rename(&self.work_path, &dest) {
+                let _r = fs::rename(&old_path, &dest);
+                self.committed = false;
+                return Err(e.into());
+            }
+
+            remove_path(&old_path)?;
+        } else {
+            if fs::symlink_metadata(&dest).is_ok() {
+                self.committed = false;
+                return Err(OutputExistsError(dest.display().to_string()).into());
+            }
+
+            if let Err(e) = fs::rename(&self.work_path, &dest) {
+                self.committed = false;
+                return Err(e.into());
+            }
+        }
+
+        Ok(dest)
+    }
+}
+
+impl Drop for PreparedOutput {
+    fn drop(&mut self) {
+        if !self.committed && self.dest.is_some() {
+            let _r = remove_path(&self.work_path);
+        }
+    }
+}
+
+/// Compute a hidden path in the same directory as *path*, so that renames
+/// between the two are atomic.
+fn sibling_path(path: &Path, tag: &str) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_else(|| "output".as_ref()));
+    name.push(format!(".rubbl-{}.{}", tag, process::id()));
+    path.with_file_name(name)
+}
+
+/// Remove a file or directory tree, if it exists.
+fn remove_path(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+        Ok(ref md) if md.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+    }
+}
+
+/// Recursively copy a file or directory tree.
+fn copy_tree(src: &Path, dest: &Path) -> io::Result<()> {
+    if !fs::metadata(src)?.is_dir() {
+        fs::copy(src, dest)?;
+        return Ok(());
+    }
+
+    fs::create_dir(dest)?;
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        copy_tree(&entry.path(), &dest.join(entry.file_name()))?;
+    }
+
+    Ok(())
+}
+
+/// Extend a `clap::App` with the standard output-policy arguments.
+pub trait ClapOutputArgsExt {
+    /// Add the `--output`, `--overwrite`, and `--in-place` arguments to this
+    /// App.
+    fn rubbl_output_args(self) -> Self;
+}
+
+impl<'a, 'b> ClapOutputArgsExt for clap::App<'a, 'b> {
+    fn rubbl_output_args(self) -> Self {
+        self.arg(
+            clap::Arg::with_name("output_path")
+                .long("output")
+                .short("o")
+                .value_name("PATH")
+                .help("Write the modified data to this path")
+                .conflicts_with("output_in_place"),
+        )
+        .arg(
+            clap::Arg::with_name("output_overwrite")
+                .long("overwrite")
+                .help("Allow the output path to be replaced if it exists")
+                .requires("output_path"),
+        )
+        .arg(
+            clap::Arg::with_name("output_in_place")
+                .long("in-place")
+                .help("Modify the input data directly"),
+        )
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn output_policies() {
+    let base = ::std::env::temp_dir().join(format!("rubbl-output-test-{}", process::id()));
+    let input = base.join("in.ms");
+    let output = base.join("out.ms");
+    fs::create_dir_all(input.join("sub")).unwrap();
+    fs::write(input.join("sub").join("f"), b"data").unwrap();
+
+    // An uncommitted copy is cleaned up.
+    let prep = OutputPolicy::NewTable(output.clone())
+        .prepare(&input)
+        .unwrap();
+    let work = prep.path().to_owned();
+    assert!(work.join("sub").join("f").exists());
+    drop(prep);
+    assert!(!work.exists());
+    assert!(!output.exists());
+
+    let prep = OutputPolicy::NewTable(output.clone())
+        .prepare(&input)
+        .unwrap();
+    assert_eq!(prep.commit().unwrap(), output);
+    assert_eq!(fs::read(output.join("sub").join("f")).unwrap(), b"data");
+
+    assert!(OutputPolicy::NewTable(output.clone())
+        .prepare(&input)
+        .is_err());
+
+    fs::write(input.join("sub").join("f"), b"new").unwrap();
+    let prep = OutputPolicy::Overwrite(output.clone())
+        .prepare(&input)
+        .unwrap();
+    prep.commit().unwrap();
+    assert_eq!(fs::read(output.join("sub").join("f")).unwrap(), b"new");
+
+    let prep = OutputPolicy::InPlace.prepare(&input).unwrap();
+    assert_eq!(prep.path(), input.as_path());
+    assert_eq!(prep.commit().unwrap(), input);
+
+    // A derived data set is written from scratch.
+    let prep = OutputPolicy::Overwrite(output.clone())
+        .prepare_derived(&input)
+        .unwrap();
+    assert!(!prep.path().exists());
+    fs::write(prep.path(), b"derived").unwrap();
+    prep.commit().unwrap();
+    assert_eq!(fs::read(&output).unwrap(), b"derived");
+
+    let prep = OutputPolicy::InPlace.prepare_derived(&input).unwrap();
+    assert_ne!(prep.path(), input.as_path());
+    fs::write(prep.path(), b"replaced").unwrap();
+    drop(prep);
+    assert!(input.join("sub").exists());
+
+    let prep = OutputPolicy::InPlace.prepare_derived(&input).unwrap();
+    fs::write(prep.path(), b"replaced").unwrap();
+    assert_eq!(prep.commit().unwrap(), input);
+    assert_eq!(fs::read(&input).unwrap(), b"replaced");
+
+    fs::remove_dir_all(&base).unwrap();
+}
@@ -0,0 +1,104 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Emitting the results of command-line tools in human- or machine-readable
+form.
+
+Tools that summarize data (table info, statistics, QA metrics, …) gather
+their results into a type implementing the `Report` trait. That type can then
+be printed as text for people, or as JSON for monitoring dashboards and other
+programs, depending on the `--format` option given by the user.
+
+The `rubbl` dispatcher accepts a global `--format` option and forwards it to
+external sub-commands through the `RUBBL_FORMAT` environment variable, which
+`OutputFormat::from_clap` consults when no explicit option is given.
+
+*/
+
+use clap;
+use serde::Serialize;
+use serde_json;
+use std::env;
+use std::io::Write;
+
+use super::Result;
+
+/// The environment variable used to pass the desired output format to
+/// sub-commands.
+pub const FORMAT_ENV_VAR: &str = "RUBBL_FORMAT";
+
+/// The form in which a report should be emitted.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    Text,
+
+    /// A single JSON document.
+    Json,
+}
+
+impl OutputFormat {
+    /// Parse a format name as given on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Determine the output format from command-line arguments added with
+    /// `ClapReportArgsExt::rubbl_report_args`, falling back to the
+    /// `RUBBL_FORMAT` environment variable and then to text.
+    pub fn from_clap(matches: &clap::ArgMatches) -> Self {
+        if let Some(name) = matches.value_of("output_format") {
+            return OutputFormat::from_name(name).unwrap(); // validated by clap
+        }
+
+        env::var(FORMAT_ENV_VAR)
+            .ok()
+            .and_then(|name| OutputFormat::from_name(&name))
+            .unwrap_or(OutputFormat::Text)
+    }
+}
+
+/// A type holding the results of a command-line tool.
+///
+/// The JSON form is derived from the type's `Serialize` implementation,
+/// while the text form is produced by `write_text`.
+pub trait Report: Serialize {
+    /// Write a human-readable version of this report.
+    fn write_text(&self, dest: &mut Write) -> Result<()>;
+
+    /// Write this report in the specified format.
+    fn emit(&self, format: OutputFormat, dest: &mut Write) -> Result<()> {
+        match format {
+            OutputFormat::Text => self.write_text(dest),
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut *dest, self)?;
+                writeln!(dest)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Extend a `clap::App` with the standard output-format argument.
+pub trait ClapReportArgsExt {
+    /// Add the `--format` argument to this App.
+    fn rubbl_report_args(self) -> Self;
+}
+
+impl<'a, 'b> ClapReportArgsExt for clap::App<'a, 'b> {
+    fn rubbl_report_args(self) -> Self {
+        self.arg(
+            clap::Arg::with_name("output_format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("The format in which to print results")
+                .possible_values(&["text", "json"]),
+        )
+    }
+}
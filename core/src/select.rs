@@ -0,0 +1,601 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Selecting subsets of visibility data with CASA-style selection expressions.
+
+The expression syntax follows the one used by CASA tasks, since that is what
+users already know:
+
+- **antenna**: semicolon-separated baseline specifications. `1&2` selects the
+  baseline between antennas 1 and 2; `3&*` or just `3` selects all baselines
+  involving antenna 3; `1,2~4&5` selects the baselines between any of
+  antennas 1–4 and antenna 5. By default only cross-correlations are
+  selected: `&&` also includes autocorrelations (`3&&*`), and `&&&` selects
+  *only* autocorrelations (`3&&&`). A leading `!` deselects the baselines
+  that a specification matches. Antennas are identified by number only.
+- **spw**: comma-separated spectral window specifications, each optionally
+  followed by a channel selection: `0:10~50`, `0~3`, `*:5~10;20~30`,
+  `1:0~63^2`. Channel ranges are inclusive and may carry a `^step`.
+- **timerange**: comma-separated time ranges: `T0~T1`, `>T0`, `<T1`, or
+  `T0+dT`. Times are written `YYYY/MM/DD[/hh[:mm[:ss.s]]]`; the end of a
+  range may omit the date, in which case it is taken from the start. Times
+  are represented as UTC MJD seconds, matching the convention of the
+  `TIME` column of a Measurement Set.
+
+An empty expression selects everything.
+
+*/
+
+use super::time::mjd_from_civil;
+use clap;
+use std::f64;
+
+/// An error type for when a selection expression cannot be parsed.
+#[derive(Fail, Debug)]
+#[fail(display = "invalid {} selection \"{}\": {}", kind, expr, message)]
+pub struct SelectionParseError {
+    /// The kind of selection being parsed, e.g. "antenna".
+    pub kind: &'static str,
+
+    /// The full expression that could not be parsed.
+    pub expr: String,
+
+    /// A description of the problem.
+    pub message: String,
+}
+
+type ParseResult<T> = ::std::result::Result<T, String>;
+
+/// A set of non-negative integer identifiers, such as antenna or spectral
+/// window numbers.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum IdSet {
+    /// Every identifier is included (written `*`).
+    All,
+
+    /// The identifiers in any of the listed inclusive ranges are included.
+    Ranges(Vec<(usize, usize)>),
+}
+
+impl IdSet {
+    /// Test whether an identifier is in this set.
+    pub fn contains(&self, id: usize) -> bool {
+        match *self {
+            IdSet::All => true,
+            IdSet::Ranges(ref ranges) => ranges.iter().any(|&(lo, hi)| id >= lo && id <= hi),
+        }
+    }
+
+    fn parse(text: &str) -> ParseResult<Self> {
+        let text = text.trim();
+
+        if text == "*" {
+            return Ok(IdSet::All);
+        }
+
+        let mut ranges = Vec::new();
+
+        for item in text.split(',') {
+            ranges.push(parse_range(item)?);
+        }
+
+        Ok(IdSet::Ranges(ranges))
+    }
+}
+
+fn parse_number(text: &str) -> ParseResult<usize> {
+    let text = text.trim();
+
+    text.parse::<usize>()
+        .map_err(|_| format!("expected a non-negative number but got \"{}\"", text))
+}
+
+fn parse_range(text: &str) -> ParseResult<(usize, usize)> {
+    let mut pieces = text.splitn(2, '~');
+    let lo = parse_number(pieces.next().unwrap())?;
+
+    let hi = match pieces.next() {
+        Some(t) => parse_number(t)?,
+        None => lo,
+    };
+
+    if hi < lo {
+        return Err(format!("range \"{}\" is backwards", text.trim()));
+    }
+
+    Ok((lo, hi))
+}
+
+/// How a baseline specification treats autocorrelations.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AutoCorrelations {
+    /// Only cross-correlations are selected (`&`).
+    Exclude,
+
+    /// Both cross- and autocorrelations are selected (`&&`).
+    Include,
+
+    /// Only autocorrelations are selected (`&&&`).
+    Only,
+}
+
+/// One term of an antenna selection expression.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct BaselineSelection {
+    /// If true, baselines matching this term are deselected.
+    pub negate: bool,
+
+    /// The antennas on one end of the baseline.
+    pub ants1: IdSet,
+
+    /// The antennas on the other end of the baseline.
+    pub ants2: IdSet,
+
+    /// How autocorrelations are treated.
+    pub autos: AutoCorrelations,
+}
+
+impl BaselineSelection {
+    fn parse(text: &str) -> ParseResult<Self> {
+        let mut text = text.trim();
+        let negate = text.starts_with('!');
+
+        if negate {
+            text = &text[1..];
+        }
+
+        let (ants1, ants2, autos) = if let Some(idx) = text.find("&&&") {
+            if !text[idx + 3..].trim().is_empty() {
+                return Err("nothing may follow \"&&&\"".to_owned());
+            }
+
+            (&text[..idx], "*", AutoCorrelations::Only)
+        } else if let Some(idx) = text.find("&&") {
+            (&text[..idx], &text[idx + 2..], AutoCorrelations::Include)
+        } else if let Some(idx) = text.find('&') {
+            (&text[..idx], &text[idx + 1..], AutoCorrelations::Exclude)
+        } else {
+            (text, "*", AutoCorrelations::Exclude)
+        };
+
+        Ok(BaselineSelection {
+            negate: negate,
+            ants1: IdSet::parse(ants1)?,
+            ants2: IdSet::parse(ants2)?,
+            autos: autos,
+        })
+    }
+
+    /// Test whether the baseline between two antennas matches this term,
+    /// ignoring its `negate` flag.
+    pub fn matches(&self, ant1: usize, ant2: usize) -> bool {
+        if ant1 == ant2 {
+            match self.autos {
+                AutoCorrelations::Exclude => false,
+                _ => self.ants1.contains(ant1) && self.ants2.contains(ant1),
+            }
+        } else {
+            match self.autos {
+                AutoCorrelations::Only => false,
+                _ => {
+                    (self.ants1.contains(ant1) && self.ants2.contains(ant2))
+                        || (self.ants1.contains(ant2) && self.ants2.contains(ant1))
+                }
+            }
+        }
+    }
+}
+
+/// An inclusive range of channels, possibly strided.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ChannelRange {
+    /// The first channel in the range.
+    pub start: usize,
+
+    /// The last channel in the range (inclusive).
+    pub end: usize,
+
+    /// The stride between selected channels.
+    pub step: usize,
+}
+
+impl ChannelRange {
+    fn parse(text: &str) -> ParseResult<Self> {
+        let mut pieces = text.splitn(2, '^');
+        let (start, end) = parse_range(pieces.next().unwrap())?;
+
+        let step = match pieces.next() {
+            Some(t) => parse_number(t)?,
+            None => 1,
+        };
+
+        if step == 0 {
+            return Err("channel step may not be zero".to_owned());
+        }
+
+        Ok(ChannelRange {
+            start: start,
+            end: end,
+            step: step,
+        })
+    }
+}
+
+/// One term of a spectral window selection expression.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SpwSelection {
+    /// The spectral windows selected by this term.
+    pub spws: IdSet,
+
+    /// The channels selected within those windows. If empty, all channels
+    /// are selected.
+    pub channels: Vec<ChannelRange>,
+}
+
+impl SpwSelection {
+    fn parse(text: &str) -> ParseResult<Self> {
+        let mut pieces = text.splitn(2, ':');
+        let spws = IdSet::parse(pieces.next().unwrap())?;
+        let mut channels = Vec::new();
+
+        if let Some(chans) = pieces.next() {
+            for item in chans.split(';') {
+                channels.push(ChannelRange::parse(item)?);
+            }
+        }
+
+        Ok(SpwSelection {
+            spws: spws,
+            channels: channels,
+        })
+    }
+}
+
+/// A range of times, in UTC MJD seconds. Either end may be infinite.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeRange {
+    /// The start of the range (inclusive).
+    pub start: f64,
+
+    /// The end of the range (inclusive).
+    pub end: f64,
+}
+
+impl TimeRange {
+    fn parse(text: &str) -> ParseResult<Self> {
+        let text = text.trim();
+
+        if text.starts_with('>') {
+            let (start, _) = parse_time(&text[1..], None)?;
+            return Ok(TimeRange {
+                start: start,
+                end: f64::INFINITY,
+            });
+        }
+
+        if text.starts_with('<') {
+            let (end, _) = parse_time(&text[1..], None)?;
+            return Ok(TimeRange {
+                start: f64::NEG_INFINITY,
+                end: end,
+            });
+        }
+
+        if let Some(idx) = text.find('~') {
+            let (start, day) = parse_time(&text[..idx], None)?;
+            let (end, _) = parse_time(&text[idx + 1..], Some(day))?;
+
+            if end < start {
+                return Err(format!("time range \"{}\" is backwards", text));
+            }
+
+            return Ok(TimeRange {
+                start: start,
+                end: end,
+            });
+        }
+
+        if let Some(idx) = text.find('+') {
+            let (start, _) = parse_time(&text[..idx], None)?;
+            let duration = parse_time_of_day(&text[idx + 1..])?;
+            return Ok(TimeRange {
+                start: start,
+                end: start + duration,
+            });
+        }
+
+        Err(format!(
+            "expected \"T0~T1\", \">T\", \"<T\", or \"T+dT\" but got \"{}\"",
+            text
+        ))
+    }
+
+    /// Test whether a time, in MJD seconds, falls in this range.
+    pub fn contains(&self, time: f64) -> bool {
+        time >= self.start && time <= self.end
+    }
+}
+
+/// Parse `hh[:mm[:ss.s]]` into a number of seconds.
+fn parse_time_of_day(text: &str) -> ParseResult<f64> {
+    let text = text.trim();
+    let mut seconds = 0.;
+    let mut scale = 3600.;
+
+    for (i, piece) in text.split(':').enumerate() {
+        if i > 2 {
+            return Err(format!("too many components in time \"{}\"", text));
+        }
+
+        let v = if i < 2 {
+            parse_number(piece)? as f64
+        } else {
+            piece
+                .parse::<f64>()
+                .map_err(|_| format!("cannot parse seconds in \"{}\"", text))?
+        };
+
+        seconds += v * scale;
+        scale /= 60.;
+    }
+
+    Ok(seconds)
+}
+
+/// Parse a time into MJD seconds, also returning its MJD day so that it can
+/// be used as the default date for the other end of a range.
+fn parse_time(text: &str, default_day: Option<i64>) -> ParseResult<(f64, i64)> {
+    let text = text.trim();
+    let pieces: Vec<_> = text.split('/').collect();
+
+    let (day, tod) = match pieces.len() {
+        1 => match default_day {
+            Some(d) => (d, pieces[0]),
+            None => {
+                return Err(format!("time \"{}\" must include a date", text));
+            }
+        },
+
+        3 | 4 => {
+            let year = parse_number(pieces[0])? as i64;
+            let month = parse_number(pieces[1])? as i64;
+            let day = parse_number(pieces[2])? as i64;
+
+            if month < 1 || month > 12 || day < 1 || day > 31 {
+                return Err(format!("illegal date in \"{}\"", text));
+            }
+
+            (
+                mjd_from_civil(year, month as u32, day as u32),
+                if pieces.len() == 4 { pieces[3] } else { "0" },
+            )
+        }
+
+        _ => {
+            return Err(format!("cannot parse time \"{}\"", text));
+        }
+    };
+
+    Ok((day as f64 * 86400. + parse_time_of_day(tod)?, day))
+}
+
+/// A compiled data selection.
+///
+/// An empty selection matches all data. Each category of selection is
+/// independent; data must match all of them to be selected.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selection {
+    /// The baseline selection terms.
+    pub baselines: Vec<BaselineSelection>,
+
+    /// The spectral window selection terms.
+    pub spws: Vec<SpwSelection>,
+
+    /// The time ranges to select.
+    pub timeranges: Vec<TimeRange>,
+}
+
+impl Selection {
+    /// Create a new selection that matches all data.
+    pub fn new() -> Self {
+        Selection::default()
+    }
+
+    /// Set the baseline selection from an antenna selection expression.
+    pub fn set_antenna(&mut self, expr: &str) -> Result<&mut Self, SelectionParseError> {
+        self.baselines = parse_terms(expr, ';', "antenna", BaselineSelection::parse)?;
+        Ok(self)
+    }
+
+    /// Set the spectral window selection from a spw selection expression.
+    pub fn set_spw(&mut self, expr: &str) -> Result<&mut Self, SelectionParseError> {
+        self.spws = parse_terms(expr, ',', "spw", SpwSelection::parse)?;
+        Ok(self)
+    }
+
+    /// Set the time selection from a timerange selection expression.
+    pub fn set_timerange(&mut self, expr: &str) -> Result<&mut Self, SelectionParseError> {
+        self.timeranges = parse_terms(expr, ',', "timerange", TimeRange::parse)?;
+        Ok(self)
+    }
+
+    /// Test whether the baseline between two antennas is selected.
+    pub fn matches_baseline(&self, ant1: usize, ant2: usize) -> bool {
+        let mut any_positive = false;
+        let mut selected = false;
+
+        for term in &self.baselines {
+            if term.negate {
+                if term.matches(ant1, ant2) {
+                    return false;
+                }
+            } else {
+                any_positive = true;
+                selected = selected || term.matches(ant1, ant2);
+            }
+        }
+
+        selected || !any_positive
+    }
+
+    /// Test whether any data in a spectral window are selected.
+    pub fn matches_spw(&self, spw: usize) -> bool {
+        self.spws.is_empty() || self.spws.iter().any(|t| t.spws.contains(spw))
+    }
+
+    /// Compute which channels of a spectral window are selected. Returns None
+    /// if the window is not selected at all.
+    pub fn channel_mask(&self, spw: usize, n_chan: usize) -> Option<Vec<bool>> {
+        if self.spws.is_empty() {
+            return Some(vec![true; n_chan]);
+        }
+
+        let mut mask = vec![false; n_chan];
+        let mut any = false;
+
+        for term in &self.spws {
+            if !term.spws.contains(spw) {
+                continue;
+            }
+
+            any = true;
+
+            if term.channels.is_empty() {
+                for m in &mut mask {
+                    *m = true;
+                }
+            } else {
+                for r in &term.channels {
+                    let mut c = r.start;
+
+                    while c <= r.end && c < n_chan {
+                        mask[c] = true;
+                        c += r.step;
+                    }
+                }
+            }
+        }
+
+        if any {
+            Some(mask)
+        } else {
+            None
+        }
+    }
+
+    /// Test whether a time, in MJD seconds, is selected.
+    pub fn matches_time(&self, time: f64) -> bool {
+        self.timeranges.is_empty() || self.timeranges.iter().any(|r| r.contains(time))
+    }
+
+    /// Build a selection from command-line arguments added with
+    /// `ClapSelectionArgsExt::rubbl_selection_args`.
+    pub fn from_clap(matches: &clap::ArgMatches) -> Result<Self, SelectionParseError> {
+        let mut sel = Selection::new();
+
+        if let Some(expr) = matches.value_of("select_antenna") {
+            sel.set_antenna(expr)?;
+        }
+
+        if let Some(expr) = matches.value_of("select_spw") {
+            sel.set_spw(expr)?;
+        }
+
+        if let Some(expr) = matches.value_of("select_timerange") {
+            sel.set_timerange(expr)?;
+        }
+
+        Ok(sel)
+    }
+}
+
+fn parse_terms<T, F>(
+    expr: &str,
+    sep: char,
+    kind: &'static str,
+    parse: F,
+) -> Result<Vec<T>, SelectionParseError>
+where
+    F: Fn(&str) -> ParseResult<T>,
+{
+    let mut terms = Vec::new();
+
+    if expr.trim().is_empty() {
+        return Ok(terms);
+    }
+
+    for text in expr.split(sep) {
+        match parse(text) {
+            Ok(t) => terms.push(t),
+            Err(message) => {
+                return Err(SelectionParseError {
+                    kind: kind,
+                    expr: expr.to_owned(),
+                    message: message,
+                });
+            }
+        }
+    }
+
+    Ok(terms)
+}
+
+/// Extend a `clap::App` with standard data-selection arguments.
+pub trait ClapSelectionArgsExt {
+    /// Add the `--antenna`, `--spw`, and `--timerange` arguments to this App.
+    fn rubbl_selection_args(self) -> Self;
+}
+
+impl<'a, 'b> ClapSelectionArgsExt for clap::App<'a, 'b> {
+    fn rubbl_selection_args(self) -> Self {
+        self.arg(
+            clap::Arg::with_name("select_antenna")
+                .long("antenna")
+                .value_name("EXPR")
+                .help("Select baselines, e.g. \"1&2;3&*\""),
+        )
+        .arg(
+            clap::Arg::with_name("select_spw")
+                .long("spw")
+                .value_name("EXPR")
+                .help("Select spectral windows and channels, e.g. \"0:10~50\""),
+        )
+        .arg(
+            clap::Arg::with_name("select_timerange")
+                .long("timerange")
+                .value_name("EXPR")
+                .help("Select times, e.g. \"2019/01/01/12:00~13:30\""),
+        )
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn selection_parsing() {
+    let mut sel = Selection::new();
+    sel.set_antenna("1&2; 3&&*; !3&4").unwrap();
+    assert!(sel.matches_baseline(1, 2));
+    assert!(sel.matches_baseline(2, 1));
+    assert!(!sel.matches_baseline(1, 1));
+    assert!(sel.matches_baseline(3, 3));
+    assert!(sel.matches_baseline(7, 3));
+    assert!(!sel.matches_baseline(3, 4));
+    assert!(!sel.matches_baseline(5, 6));
+
+    sel.set_spw("0:10~12;20~24^2,2~3").unwrap();
+    let mask = sel.channel_mask(0, 26).unwrap();
+    let selected: Vec<_> = (0..26).filter(|&i| mask[i]).collect();
+    assert_eq!(selected, [10, 11, 12, 20, 22, 24]);
+    assert!(sel.matches_spw(3));
+    assert!(sel.channel_mask(1, 26).is_none());
+
+    sel.set_timerange("2000/01/01/12:00~13:00:30").unwrap();
+    assert_eq!(sel.timeranges[0].start, 51544.5 * 86400.);
+    assert_eq!(sel.timeranges[0].end, 51544.5 * 86400. + 3630.);
+
+    assert!(sel.set_antenna("1&x").is_err());
+    assert!(sel.set_spw("3~1").is_err());
+    assert!(sel.set_timerange("12:00~13:00").is_err());
+}
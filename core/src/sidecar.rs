@@ -0,0 +1,252 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Tool state stored next to data sets.
+
+Some tools need to remember things about a data set between runs: how far
+a long operation got, which versions of its flags have been saved, the
+results of quality checks, and so on. Rather than each of them inventing a
+file format, they can share a *sidecar*: a JSON file named after the data
+set with `.rubbl.json` appended, in the same directory. For `obs/foo.ms`,
+it is `obs/foo.ms.rubbl.json`.
+
+The sidecar is a JSON object mapping keys to arbitrary values. Each tool
+should use its own key, or a few keys sharing a prefix such as
+`"flagging."`, and store whatever it likes under it. Values are converted
+with serde, so any serializable type can be stored.
+
+```rust,ignore
+#[derive(Deserialize, Serialize)]
+struct Progress { rows_done: u64 }
+
+Sidecar::update(&ms_path, |sc| sc.set("mytool.progress", &Progress { rows_done: 1000 }))?;
+let progress: Option<Progress> = Sidecar::load(&ms_path)?.get("mytool.progress")?;
+```
+
+`Sidecar::update` holds a lock for the duration of its read-modify-write
+cycle, so that concurrent updates from several processes do not lose each
+other's changes. The lock is a file next to the sidecar with `.lock`
+appended, created exclusively; if a process dies while holding it, it must
+be removed by hand. The sidecar itself is replaced atomically, so
+`Sidecar::load` never sees a half-written file and does not need the lock.
+
+*/
+
+use failure::err_msg;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Map, Value};
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::Result;
+
+/// The suffix appended to the name of a data set to get that of its sidecar.
+pub const SIDECAR_SUFFIX: &str = ".rubbl.json";
+
+/// How long `Sidecar::update` waits for another process to release the lock.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often `Sidecar::update` checks whether the lock has been released.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Get the path of the sidecar of the data set at *dataset*.
+pub fn sidecar_path<P: AsRef<Path>>(dataset: P) -> PathBuf {
+    // Going through the components drops any trailing slash.
+    let mut path = dataset
+        .as_ref()
+        .components()
+        .as_path()
+        .as_os_str()
+        .to_owned();
+    path.push(SIDECAR_SUFFIX);
+    PathBuf::from(path)
+}
+
+/// The tool state stored next to a data set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sidecar {
+    values: Map<String, Value>,
+}
+
+impl Sidecar {
+    /// Read the sidecar of the data set at *dataset*. If the data set has no
+    /// sidecar yet, the result is empty.
+    pub fn load<P: AsRef<Path>>(dataset: P) -> Result<Self> {
+        Self::read(&sidecar_path(dataset))
+    }
+
+    /// Modify the sidecar of the data set at *dataset* with *f*, saving the
+    /// result if *f* succeeds. The sidecar is created if it does not exist.
+    ///
+    /// Other processes are locked out of updating the sidecar until this
+    /// returns, so *f* should be quick.
+    pub fn update<P, F, T>(dataset: P, f: F) -> Result<T>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&mut Sidecar) -> Result<T>,
+    {
+        let path = sidecar_path(dataset);
+        let _lock = SidecarLock::acquire(&path)?;
+        let mut sidecar = Self::read(&path)?;
+        let result = f(&mut sidecar)?;
+        sidecar.write(&path)?;
+        Ok(result)
+    }
+
+    /// Get the value stored under *key*, or `None` if there is none.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.values.get(key) {
+            None => Ok(None),
+            Some(v) => Ok(Some(ctry!(T::deserialize(v);
+                                     "unexpected value for sidecar key \"{}\"", key))),
+        }
+    }
+
+    /// Store *value* under *key*, replacing any value that is already there.
+    pub fn set<T: Serialize>(&mut self, key: &str, value: &T) -> Result<()> {
+        self.values
+            .insert(key.to_owned(), serde_json::to_value(value)?);
+        Ok(())
+    }
+
+    /// Remove the value stored under *key*, returning whether there was one.
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.values.remove(key).is_some()
+    }
+
+    /// Get the keys that have values, in sorted order.
+    pub fn keys(&self) -> Vec<&str> {
+        self.values.keys().map(|k| k.as_str()).collect()
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let values = ctry!(serde_json::from_str(&text);
+                           "failed to parse the sidecar \"{}\"", path.display());
+        Ok(Sidecar { values: values })
+    }
+
+    /// Replace the file at *path* with the contents of the sidecar,
+    /// atomically.
+    fn write(&self, path: &Path) -> Result<()> {
+        let tmp_path = with_suffix(path, &format!(".tmp.{}", process::id()));
+        let mut text = serde_json::to_string_pretty(&self.values)?;
+        text.push('\n');
+        fs::write(&tmp_path, text)?;
+
+        if let Err(e) = fs::rename(&tmp_path, path) {
+            let _r = fs::remove_file(&tmp_path);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// An exclusive lock on updating a sidecar, released when dropped.
+#[derive(Debug)]
+struct SidecarLock {
+    path: PathBuf,
+}
+
+impl SidecarLock {
+    fn acquire(sidecar: &Path) -> Result<Self> {
+        let path = with_suffix(sidecar, ".lock");
+        let start = Instant::now();
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut f) => {
+                    // The process ID is only there to help people track down
+                    // stale locks.
+                    let _r = writeln!(f, "{}", process::id());
+                    return Ok(SidecarLock { path: path });
+                }
+
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+
+            if start.elapsed() > LOCK_TIMEOUT {
+                return Err(err_msg(format!(
+                    "timed out waiting for the lock \"{}\"; if no other process is using it, delete it",
+                    path.display()
+                )));
+            }
+
+            thread::sleep(LOCK_POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for SidecarLock {
+    fn drop(&mut self) {
+        let _r = fs::remove_file(&self.path);
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = OsString::from(path.as_os_str());
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+#[cfg(test)]
+#[test]
+fn sidecar_updates() {
+    let base = ::std::env::temp_dir().join(format!("rubbl-sidecar-test-{}", process::id()));
+    let dataset = base.join("foo.ms");
+    fs::create_dir_all(&dataset).unwrap();
+    assert_eq!(
+        sidecar_path(base.join("foo.ms/")),
+        base.join("foo.ms.rubbl.json")
+    );
+
+    assert_eq!(Sidecar::load(&dataset).unwrap(), Sidecar::default());
+
+    let n = Sidecar::update(&dataset, |sc| {
+        sc.set("b.rows", &12u64)?;
+        sc.set("a.names", &vec!["x", "y"])?;
+        Ok(sc.keys().len())
+    })
+    .unwrap();
+    assert_eq!(n, 2);
+
+    let sc = Sidecar::load(&dataset).unwrap();
+    assert_eq!(sc.keys(), vec!["a.names", "b.rows"]);
+    assert_eq!(sc.get::<u64>("b.rows").unwrap(), Some(12));
+    assert_eq!(
+        sc.get::<Vec<String>>("a.names").unwrap().unwrap(),
+        vec!["x", "y"]
+    );
+    assert_eq!(sc.get::<u64>("c").unwrap(), None);
+    assert!(sc.get::<String>("b.rows").is_err());
+
+    // A failed update leaves the sidecar alone and releases the lock.
+    assert!(Sidecar::update(&dataset, |sc| {
+        sc.remove("b.rows");
+        Err::<(), _>(err_msg("oops"))
+    })
+    .is_err());
+    Sidecar::update(&dataset, |sc| {
+        assert!(sc.remove("b.rows"));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(Sidecar::load(&dataset).unwrap().keys(), vec!["a.names"]);
+
+    fs::remove_dir_all(&base).unwrap();
+}
@@ -0,0 +1,465 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Antenna layouts of common interferometric arrays.
+
+`get` returns a built-in layout by name, such as `"vla-a"` or
+`"atca-6a"`. The built-in layouts are:
+
+- `vla-a`, `vla-b`, `vla-c`, and `vla-d`: idealized versions of the four
+  configurations of the VLA. The 27 antennas lie on three straight, level
+  arms at azimuths of 355°, 115°, and 236°, with the *n*th antenna of each
+  arm at a distance proportional to *n*^1.716, as in the design of the
+  array. The real pads deviate from this pattern by up to tens of meters.
+- `atca-6a`, `atca-6b`, `atca-1.5a`, and `atca-750a`: the east-west
+  configurations of the ATCA, with the antennas at their stations on the
+  railway track, 15.3061 m apart. Positions are relative to station W0,
+  which is placed at the observatory's reference position.
+
+The positions of arrays such as MeerKAT and the MWA can't be described so
+compactly. Their observatory sites are known to `site`, so their layouts can
+be read from the configuration files distributed with CASA's simulator
+using `ArrayLayout::parse_casa_cfg`.
+
+Positions are given in meters in the ITRF geocentric frame, as in the
+`POSITION` column of the `ANTENNA` table of a Measurement Set.
+
+*/
+
+use failure::err_msg;
+use std::f64::consts::PI;
+use std::io::BufRead;
+
+use Result;
+
+/// The names of the built-in layouts.
+pub const LAYOUT_NAMES: &[&str] = &[
+    "vla-a",
+    "vla-b",
+    "vla-c",
+    "vla-d",
+    "atca-6a",
+    "atca-6b",
+    "atca-1.5a",
+    "atca-750a",
+];
+
+/// The semi-major axis of the WGS84 ellipsoid, in meters.
+const WGS84_A: f64 = 6_378_137.;
+
+/// The flattening of the WGS84 ellipsoid.
+const WGS84_F: f64 = 1. / 298.257_223_563;
+
+/// A location on the Earth.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Site {
+    /// The geodetic latitude, in radians.
+    pub latitude: f64,
+
+    /// The longitude, in radians, positive to the east.
+    pub longitude: f64,
+
+    /// The height above the WGS84 ellipsoid, in meters.
+    pub height: f64,
+}
+
+impl Site {
+    /// Create a site from a latitude and longitude in degrees and a height
+    /// in meters.
+    pub fn from_degrees(latitude: f64, longitude: f64, height: f64) -> Self {
+        Site {
+            latitude: latitude * PI / 180.,
+            longitude: longitude * PI / 180.,
+            height: height,
+        }
+    }
+
+    /// Get the geocentric position of the site.
+    pub fn itrf(&self) -> [f64; 3] {
+        let e2 = WGS84_F * (2. - WGS84_F);
+        let (sin_lat, cos_lat) = self.latitude.sin_cos();
+        let (sin_lon, cos_lon) = self.longitude.sin_cos();
+        let n = WGS84_A / (1. - e2 * sin_lat * sin_lat).sqrt();
+
+        [
+            (n + self.height) * cos_lat * cos_lon,
+            (n + self.height) * cos_lat * sin_lon,
+            (n * (1. - e2) + self.height) * sin_lat,
+        ]
+    }
+
+    /// Convert a position in meters east, north, and up of the site into a
+    /// geocentric one.
+    pub fn enu_to_itrf(&self, enu: [f64; 3]) -> [f64; 3] {
+        let (sin_lat, cos_lat) = self.latitude.sin_cos();
+        let (sin_lon, cos_lon) = self.longitude.sin_cos();
+        let [e, n, u] = enu;
+        let origin = self.itrf();
+
+        [
+            origin[0] - sin_lon * e - sin_lat * cos_lon * n + cos_lat * cos_lon * u,
+            origin[1] + cos_lon * e - sin_lat * sin_lon * n + cos_lat * sin_lon * u,
+            origin[2] + cos_lat * n + sin_lat * u,
+        ]
+    }
+
+    /// Convert a geocentric position into one in meters east, north, and up
+    /// of the site.
+    pub fn itrf_to_enu(&self, itrf: [f64; 3]) -> [f64; 3] {
+        let (sin_lat, cos_lat) = self.latitude.sin_cos();
+        let (sin_lon, cos_lon) = self.longitude.sin_cos();
+        let origin = self.itrf();
+        let dx = itrf[0] - origin[0];
+        let dy = itrf[1] - origin[1];
+        let dz = itrf[2] - origin[2];
+
+        [
+            -sin_lon * dx + cos_lon * dy,
+            -sin_lat * cos_lon * dx - sin_lat * sin_lon * dy + cos_lat * dz,
+            cos_lat * cos_lon * dx + cos_lat * sin_lon * dy + sin_lat * dz,
+        ]
+    }
+}
+
+/// Get the reference position of the observatory named *name*, if it is
+/// known. Names are matched case-insensitively; the known observatories
+/// are the VLA, ATCA, MeerKAT, and MWA.
+pub fn site(name: &str) -> Option<Site> {
+    Some(match name.to_lowercase().as_str() {
+        "vla" | "evla" => Site::from_degrees(34.078_749_1, -107.617_727_5, 2115.),
+        "atca" => Site::from_degrees(-30.312_884_6, 149.550_138_8, 236.87),
+        "meerkat" => Site::from_degrees(-30.711_055_6, 21.443_888_9, 1035.),
+        "mwa" => Site::from_degrees(-26.703_319, 116.670_81, 377.827),
+        _ => return None,
+    })
+}
+
+/// One antenna of an array.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Antenna {
+    /// The name of the antenna.
+    pub name: String,
+
+    /// The name of the station or pad that the antenna is on.
+    pub station: String,
+
+    /// The geocentric position of the antenna, in meters.
+    pub position: [f64; 3],
+
+    /// The diameter of the antenna, in meters.
+    pub diameter: f64,
+}
+
+/// The layout of an array.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArrayLayout {
+    /// The name of the layout.
+    pub name: String,
+
+    /// The name of the observatory, as in the `TELESCOPE_NAME` column of
+    /// the `OBSERVATION` table of a Measurement Set.
+    pub observatory: String,
+
+    /// The reference position of the observatory.
+    pub site: Site,
+
+    /// The antennas.
+    pub antennas: Vec<Antenna>,
+}
+
+impl ArrayLayout {
+    /// Get the positions of the antennas in meters east, north, and up of
+    /// the observatory's reference position.
+    pub fn local_positions(&self) -> Vec<[f64; 3]> {
+        self.antennas
+            .iter()
+            .map(|a| self.site.itrf_to_enu(a.position))
+            .collect()
+    }
+
+    /// Get the length of the longest baseline, in meters.
+    pub fn max_baseline(&self) -> f64 {
+        let mut longest = 0f64;
+
+        for (i, a) in self.antennas.iter().enumerate() {
+            for b in &self.antennas[i + 1..] {
+                let d2: f64 = (0..3)
+                    .map(|k| (a.position[k] - b.position[k]).powi(2))
+                    .sum();
+                longest = longest.max(d2.sqrt());
+            }
+        }
+
+        longest
+    }
+
+    /// Read a layout from a configuration file of CASA's simulator.
+    ///
+    /// Such files have a header of comment lines of the form
+    /// `# key=value`, followed by one line per antenna giving its *x*, *y*,
+    /// and *z* coordinates, its diameter, and its name. The `observatory`
+    /// key names the observatory. The `coordsys` key is `XYZ` if the
+    /// coordinates are geocentric, or `LOC` if they are east, north, and
+    /// up of the observatory, in which case the position of the observatory
+    /// must be known to `site` or given by a `COFA` key of the form
+    /// `longitude,latitude` in degrees. UTM coordinates are not supported.
+    pub fn parse_casa_cfg<R: BufRead>(name: &str, reader: R) -> Result<Self> {
+        let mut observatory = None;
+        let mut coordsys = "XYZ".to_owned();
+        let mut cofa = None;
+        let mut rows = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+
+            if let Some(header) = line.strip_prefix('#') {
+                let mut pieces = header.splitn(2, '=');
+                let key = pieces.next().unwrap_or("").trim().to_lowercase();
+                let value = pieces.next().unwrap_or("").trim();
+
+                match key.as_str() {
+                    "observatory" => observatory = Some(value.to_owned()),
+                    "coordsys" => coordsys = value.to_uppercase(),
+                    "cofa" => {
+                        let lonlat: Vec<f64> = value
+                            .split(',')
+                            .map(|s| s.trim().parse::<f64>())
+                            .collect::<::std::result::Result<_, _>>()?;
+
+                        if lonlat.len() != 2 {
+                            return Err(err_msg(format!("malformed COFA value \"{}\"", value)));
+                        }
+
+                        cofa = Some(Site::from_degrees(lonlat[1], lonlat[0], 0.));
+                    }
+                    _ => {}
+                }
+
+                continue;
+            }
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let pieces: Vec<&str> = line.split_whitespace().collect();
+
+            if pieces.len() < 4 {
+                return Err(err_msg(format!("malformed antenna line \"{}\"", line)));
+            }
+
+            let mut values = [0.; 4];
+
+            for (v, p) in values.iter_mut().zip(&pieces) {
+                *v = p.parse()?;
+            }
+
+            let ant_name = match pieces.get(4) {
+                Some(n) => (*n).to_owned(),
+                None => format!("A{:02}", rows.len()),
+            };
+
+            rows.push((values, ant_name));
+        }
+
+        let observatory = match observatory {
+            Some(o) => o,
+            None => {
+                return Err(err_msg(
+                    "CASA array configuration does not name its observatory",
+                ))
+            }
+        };
+
+        let site = match (cofa, site(&observatory)) {
+            (Some(s), _) | (None, Some(s)) => s,
+            (None, None) if coordsys.starts_with("XYZ") => match rows.first() {
+                Some(&(v, _)) => geodetic_of(v[0], v[1], v[2]),
+                None => Site::from_degrees(0., 0., 0.),
+            },
+            (None, None) => {
+                return Err(err_msg(format!(
+                    "unknown observatory \"{}\" in CASA array configuration",
+                    observatory
+                )));
+            }
+        };
+
+        let local = if coordsys.starts_with("LOC") {
+            true
+        } else if coordsys.starts_with("XYZ") {
+            false
+        } else {
+            return Err(err_msg(format!(
+                "unsupported coordinate system \"{}\" in CASA array configuration",
+                coordsys
+            )));
+        };
+
+        let antennas = rows
+            .into_iter()
+            .map(|(v, ant_name)| Antenna {
+                station: ant_name.clone(),
+                name: ant_name,
+                position: if local {
+                    site.enu_to_itrf([v[0], v[1], v[2]])
+                } else {
+                    [v[0], v[1], v[2]]
+                },
+                diameter: v[3],
+            })
+            .collect();
+
+        Ok(ArrayLayout {
+            name: name.to_owned(),
+            observatory: observatory,
+            site: site,
+            antennas: antennas,
+        })
+    }
+}
+
+/// Get the built-in layout named *name*, if there is one. Names are matched
+/// case-insensitively; see `LAYOUT_NAMES` for the list.
+pub fn get(name: &str) -> Option<ArrayLayout> {
+    let lower = name.to_lowercase();
+
+    if let Some(config) = lower.strip_prefix("vla-") {
+        // The arm length of each configuration, in meters.
+        let arm_length = match config {
+            "a" => 21_030.,
+            "b" => 6_410.,
+            "c" => 1_960.,
+            "d" => 595.,
+            _ => return None,
+        };
+
+        return Some(vla_layout(&lower, arm_length));
+    }
+
+    if let Some(config) = lower.strip_prefix("atca-") {
+        let stations: [u32; 6] = match config {
+            "6a" => [4, 45, 102, 173, 195, 392],
+            "6b" => [2, 64, 147, 182, 196, 392],
+            "1.5a" => [100, 110, 147, 168, 196, 392],
+            "750a" => [147, 163, 172, 190, 195, 392],
+            _ => return None,
+        };
+
+        return Some(atca_layout(&lower, &stations));
+    }
+
+    None
+}
+
+fn vla_layout(name: &str, arm_length: f64) -> ArrayLayout {
+    let site = site("vla").unwrap();
+    let mut antennas = Vec::with_capacity(27);
+
+    for &(arm, azimuth) in &[("N", 355f64), ("E", 115.), ("W", 236.)] {
+        let (sin_az, cos_az) = (azimuth * PI / 180.).sin_cos();
+
+        for n in 1..10 {
+            let r = arm_length * (n as f64 / 9.).powf(1.716);
+            let station = format!("{}{}", arm, n);
+
+            antennas.push(Antenna {
+                name: station.clone(),
+                station: station,
+                position: site.enu_to_itrf([r * sin_az, r * cos_az, 0.]),
+                diameter: 25.,
+            });
+        }
+    }
+
+    ArrayLayout {
+        name: name.to_owned(),
+        observatory: "VLA".to_owned(),
+        site: site,
+        antennas: antennas,
+    }
+}
+
+fn atca_layout(name: &str, stations: &[u32]) -> ArrayLayout {
+    let site = site("atca").unwrap();
+
+    let antennas = stations
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| Antenna {
+            name: format!("CA{:02}", i + 1),
+            station: format!("W{}", s),
+            position: site.enu_to_itrf([15.3061 * s as f64, 0., 0.]),
+            diameter: 22.,
+        })
+        .collect();
+
+    ArrayLayout {
+        name: name.to_owned(),
+        observatory: "ATCA".to_owned(),
+        site: site,
+        antennas: antennas,
+    }
+}
+
+/// Find the geodetic coordinates of a geocentric position, by Bowring's
+/// method.
+fn geodetic_of(x: f64, y: f64, z: f64) -> Site {
+    let e2 = WGS84_F * (2. - WGS84_F);
+    let b = WGS84_A * (1. - WGS84_F);
+    let ep2 = (WGS84_A * WGS84_A - b * b) / (b * b);
+    let p = x.hypot(y);
+    let theta = (z * WGS84_A).atan2(p * b);
+    let (sin_t, cos_t) = theta.sin_cos();
+    let latitude = (z + ep2 * b * sin_t.powi(3)).atan2(p - e2 * WGS84_A * cos_t.powi(3));
+    let sin_lat = latitude.sin();
+    let n = WGS84_A / (1. - e2 * sin_lat * sin_lat).sqrt();
+
+    Site {
+        latitude: latitude,
+        longitude: y.atan2(x),
+        height: p / latitude.cos() - n,
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn layouts() {
+    for name in LAYOUT_NAMES {
+        assert!(get(name).is_some(), "{}", name);
+    }
+
+    assert!(get("vla-e").is_none());
+    assert!(get("meerkat").is_none());
+
+    let vla = get("VLA-A").unwrap();
+    assert_eq!(vla.antennas.len(), 27);
+    assert!((vla.max_baseline() - 36_400.).abs() < 400.);
+
+    // Local positions round-trip, and the VLA arms are level.
+    for enu in vla.local_positions() {
+        assert!(enu[2].abs() < 1e-6);
+    }
+
+    let site = vla.site;
+    let back = geodetic_of(site.itrf()[0], site.itrf()[1], site.itrf()[2]);
+    assert!((back.latitude - site.latitude).abs() < 1e-12);
+    assert!((back.height - site.height).abs() < 1e-3);
+
+    let atca = get("atca-6a").unwrap();
+    assert!((atca.max_baseline() - 15.3061 * 388.).abs() < 1e-3);
+
+    let cfg = "# observatory=ATCA\n\
+               # coordsys=LOC (local tangent plane)\n\
+               0. 0. 0. 22. CA01\n\
+               30.6122 0. 0. 22. CA02\n";
+    let layout = ArrayLayout::parse_casa_cfg("test", cfg.as_bytes()).unwrap();
+    assert_eq!(layout.antennas[1].name, "CA02");
+    assert!((layout.max_baseline() - 30.6122).abs() < 1e-6);
+
+    let cfg = "# observatory=Nowhere\n# coordsys=LOC\n0 0 0 10\n";
+    assert!(ArrayLayout::parse_casa_cfg("test", cfg.as_bytes()).is_err());
+}
@@ -0,0 +1,197 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Helpers for working with timestamps.
+
+Radio data formats commonly record times as Modified Julian Dates (MJDs),
+sometimes expressed in days and sometimes, as in CASA tables, in seconds.
+These helpers convert between such values and calendar dates. No time-scale
+conversions are performed: a UTC MJD becomes a UTC calendar date, a TAI MJD
+a TAI one, and so on.
+
+*/
+
+/// The number of seconds in a day.
+pub const SECONDS_PER_DAY: f64 = 86400.;
+
+/// The MJD of the Unix epoch, 1970 January 1.
+const MJD_OF_UNIX_EPOCH: i64 = 40587;
+
+/// Convert a count of days since 1970 January 1 into a (year, month, day)
+/// triple in the proleptic Gregorian calendar.
+///
+/// This is Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m as u32, d as u32)
+}
+
+/// Convert a (year, month, day) triple in the proleptic Gregorian calendar
+/// into a count of days since 1970 January 1.
+///
+/// This is Howard Hinnant's `days_from_civil` algorithm.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Compute the MJD of a (year, month, day) triple in the proleptic
+/// Gregorian calendar. The month and day are not checked for validity.
+pub fn mjd_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    days_from_civil(year, month, day) + MJD_OF_UNIX_EPOCH
+}
+
+/// An error type for when a date and time cannot be parsed.
+#[derive(Fail, Debug)]
+#[fail(
+    display = "cannot parse \"{}\" as an ISO 8601 date and time (expected something like \"2019-03-04T05:06:07.89\")",
+    _0
+)]
+pub struct TimeParseError(pub String);
+
+/// Convert a Unix timestamp, in seconds since 1970 January 1, into MJD
+/// seconds. Leap seconds are ignored, as they are by Unix time itself.
+pub fn unix_to_mjd_seconds(unix_seconds: f64) -> f64 {
+    unix_seconds + MJD_OF_UNIX_EPOCH as f64 * SECONDS_PER_DAY
+}
+
+/// Format a time given as MJD seconds as an ISO 8601 date and time with
+/// millisecond precision, such as `2019-03-04T05:06:07.890`.
+///
+/// Non-finite values are formatted as plain numbers (e.g., `NaN`) so that
+/// they remain recognizable.
+pub fn mjd_seconds_to_iso8601(mjd_seconds: f64) -> String {
+    if !mjd_seconds.is_finite() {
+        return format!("{}", mjd_seconds);
+    }
+
+    let total_ms = (mjd_seconds * 1000.).round() as i64;
+    let ms_per_day = (SECONDS_PER_DAY * 1000.) as i64;
+    let mjd = total_ms.div_euclid(ms_per_day);
+    let ms_of_day = total_ms.rem_euclid(ms_per_day);
+
+    let (year, month, day) = civil_from_days(mjd - MJD_OF_UNIX_EPOCH);
+    let hour = ms_of_day / 3_600_000;
+    let minute = (ms_of_day / 60_000) % 60;
+    let second = (ms_of_day / 1000) % 60;
+    let ms = ms_of_day % 1000;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}",
+        year, month, day, hour, minute, second, ms
+    )
+}
+
+/// Parse an ISO 8601 date and time into MJD seconds.
+///
+/// The accepted forms are `YYYY-MM-DD`, optionally followed by `T` or a
+/// space and `HH:MM`, `HH:MM:SS`, or `HH:MM:SS.fff`, optionally followed by
+/// `Z`. This is the inverse of `mjd_seconds_to_iso8601`.
+pub fn iso8601_to_mjd_seconds(text: &str) -> Result<f64, TimeParseError> {
+    let err = || TimeParseError(text.to_owned());
+    let trimmed = text.trim();
+    let trimmed = trimmed.trim_end_matches('Z');
+
+    let (date, time) = match trimmed.find(|c| c == 'T' || c == ' ') {
+        Some(idx) => (&trimmed[..idx], Some(&trimmed[idx + 1..])),
+        None => (trimmed, None),
+    };
+
+    let date_pieces: Vec<&str> = date.split('-').collect();
+
+    if date_pieces.len() != 3 {
+        return Err(err());
+    }
+
+    let year: i64 = date_pieces[0].parse().map_err(|_| err())?;
+    let month: u32 = date_pieces[1].parse().map_err(|_| err())?;
+    let day: u32 = date_pieces[2].parse().map_err(|_| err())?;
+
+    if month < 1 || month > 12 || day < 1 || day > 31 {
+        return Err(err());
+    }
+
+    let mut seconds = 0.;
+
+    if let Some(time) = time {
+        let time_pieces: Vec<&str> = time.split(':').collect();
+
+        if time_pieces.len() < 2 || time_pieces.len() > 3 {
+            return Err(err());
+        }
+
+        let hour: u32 = time_pieces[0].parse().map_err(|_| err())?;
+        let minute: u32 = time_pieces[1].parse().map_err(|_| err())?;
+        let second: f64 = match time_pieces.get(2) {
+            Some(s) => s.parse().map_err(|_| err())?,
+            None => 0.,
+        };
+
+        if hour > 23 || minute > 59 || !(second >= 0. && second < 61.) {
+            return Err(err());
+        }
+
+        seconds = 3600. * hour as f64 + 60. * minute as f64 + second;
+    }
+
+    let mjd = mjd_from_civil(year, month, day);
+    Ok(mjd as f64 * SECONDS_PER_DAY + seconds)
+}
+
+#[cfg(test)]
+#[test]
+fn iso8601_formatting() {
+    assert_eq!(mjd_seconds_to_iso8601(0.), "1858-11-17T00:00:00.000");
+    assert_eq!(
+        mjd_seconds_to_iso8601(51544.5 * SECONDS_PER_DAY),
+        "2000-01-01T12:00:00.000"
+    );
+    assert_eq!(
+        mjd_seconds_to_iso8601(58546. * SECONDS_PER_DAY + 18367.8904),
+        "2019-03-04T05:06:07.890"
+    );
+    assert_eq!(
+        mjd_seconds_to_iso8601(51603. * SECONDS_PER_DAY - 0.0001),
+        "2000-02-29T00:00:00.000"
+    );
+    assert_eq!(mjd_seconds_to_iso8601(::std::f64::NAN), "NaN");
+}
+
+#[cfg(test)]
+#[test]
+fn iso8601_parsing() {
+    assert_eq!(iso8601_to_mjd_seconds("1858-11-17").unwrap(), 0.);
+    assert_eq!(
+        iso8601_to_mjd_seconds("2000-01-01T12:00").unwrap(),
+        51544.5 * SECONDS_PER_DAY
+    );
+    assert_eq!(
+        iso8601_to_mjd_seconds("2000-03-01 00:00:01Z").unwrap(),
+        51604. * SECONDS_PER_DAY + 1.
+    );
+
+    let t = 58546. * SECONDS_PER_DAY + 18367.89;
+    assert_eq!(
+        iso8601_to_mjd_seconds(&mjd_seconds_to_iso8601(t)).unwrap(),
+        t
+    );
+
+    assert!(iso8601_to_mjd_seconds("2000-13-01").is_err());
+    assert!(iso8601_to_mjd_seconds("2000-01-01T25:00").is_err());
+    assert!(iso8601_to_mjd_seconds("yesterday").is_err());
+}
@@ -0,0 +1,168 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Newtypes for physical quantities.
+
+Measurement Sets store nearly everything as bare `f64`s whose units are
+given only by convention: times are MJD seconds, not days; frequencies are
+in hertz, not megahertz; angles are in radians, not degrees. Mixing these
+up does not fail loudly — it yields data that are subtly or wildly wrong.
+The types in this module carry the unit along with the value, so that the
+public APIs that take or return them cannot be handed the wrong kind of
+number. Each one wraps its value in a public field, and conversions to and
+from other units are explicit methods.
+
+*/
+
+use std::fmt;
+
+use super::time::{
+    iso8601_to_mjd_seconds, mjd_seconds_to_iso8601, unix_to_mjd_seconds, TimeParseError,
+    SECONDS_PER_DAY,
+};
+
+/// The speed of light, in meters per second.
+const SPEED_OF_LIGHT: f64 = 299_792_458.;
+
+/// A time, as a Modified Julian Date in seconds. This is how times are
+/// stored in CASA tables.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct MjdSeconds(pub f64);
+
+impl MjdSeconds {
+    /// Create a time from an MJD in days.
+    pub fn from_mjd_days(days: f64) -> Self {
+        MjdSeconds(days * SECONDS_PER_DAY)
+    }
+
+    /// Create a time from a Unix timestamp, in seconds since 1970 January 1.
+    pub fn from_unix_seconds(unix_seconds: f64) -> Self {
+        MjdSeconds(unix_to_mjd_seconds(unix_seconds))
+    }
+
+    /// Parse an ISO 8601 date and time, as with
+    /// `time::iso8601_to_mjd_seconds`.
+    pub fn from_iso8601(text: &str) -> Result<Self, TimeParseError> {
+        iso8601_to_mjd_seconds(text).map(MjdSeconds)
+    }
+
+    /// Get the time as an MJD in days.
+    pub fn mjd_days(self) -> f64 {
+        self.0 / SECONDS_PER_DAY
+    }
+
+    /// Get the time elapsed from *earlier* to this time.
+    pub fn seconds_since(self, earlier: MjdSeconds) -> Seconds {
+        Seconds(self.0 - earlier.0)
+    }
+
+    /// Get the time *duration* after this one.
+    pub fn plus_seconds(self, duration: Seconds) -> Self {
+        MjdSeconds(self.0 + duration.0)
+    }
+}
+
+impl fmt::Display for MjdSeconds {
+    /// Times are displayed in ISO 8601 format.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&mjd_seconds_to_iso8601(self.0))
+    }
+}
+
+/// A length of time, such as an integration time, in seconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Seconds(pub f64);
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} s", self.0)
+    }
+}
+
+/// A frequency, in hertz.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Hz(pub f64);
+
+impl Hz {
+    /// Create a frequency from a value in megahertz.
+    pub fn from_mhz(mhz: f64) -> Self {
+        Hz(mhz * 1e6)
+    }
+
+    /// Get the frequency in megahertz.
+    pub fn mhz(self) -> f64 {
+        self.0 * 1e-6
+    }
+
+    /// Get the wavelength corresponding to this frequency.
+    pub fn wavelength(self) -> Meters {
+        Meters(SPEED_OF_LIGHT / self.0)
+    }
+}
+
+impl fmt::Display for Hz {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} Hz", self.0)
+    }
+}
+
+/// A length, in meters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Meters(pub f64);
+
+impl Meters {
+    /// Get this length in units of the wavelength of *freq*.
+    pub fn wavelengths_at(self, freq: Hz) -> f64 {
+        self.0 / freq.wavelength().0
+    }
+}
+
+impl fmt::Display for Meters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} m", self.0)
+    }
+}
+
+/// An angle, in radians.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Radians(pub f64);
+
+impl Radians {
+    /// Create an angle from a value in degrees.
+    pub fn from_degrees(degrees: f64) -> Self {
+        Radians(degrees.to_radians())
+    }
+
+    /// Get the angle in degrees.
+    pub fn degrees(self) -> f64 {
+        self.0.to_degrees()
+    }
+}
+
+impl fmt::Display for Radians {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} rad", self.0)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn conversions() {
+    let t = MjdSeconds::from_mjd_days(58000.5);
+    assert_eq!(t.0, 58000.5 * 86400.);
+    assert_eq!(t.mjd_days(), 58000.5);
+    assert_eq!(format!("{}", t), "2017-09-04T12:00:00.000");
+    assert_eq!(MjdSeconds::from_iso8601("2017-09-04T12:00:00").unwrap(), t);
+    assert_eq!(MjdSeconds::from_unix_seconds(0.).mjd_days(), 40587.);
+    assert_eq!(t.plus_seconds(Seconds(30.)).seconds_since(t), Seconds(30.));
+
+    let f = Hz::from_mhz(150.);
+    assert_eq!(f.0, 150e6);
+    assert!((f.wavelength().0 - 1.998_616_386_666_666_6).abs() < 1e-12);
+    assert!((Meters(1000.).wavelengths_at(f) - 500.346_2).abs() < 1e-3);
+
+    assert!((Radians::from_degrees(180.).0 - std::f64::consts::PI).abs() < 1e-15);
+    assert!((Radians(std::f64::consts::FRAC_PI_2).degrees() - 90.).abs() < 1e-12);
+}
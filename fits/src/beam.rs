@@ -0,0 +1,393 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Primary beam models.
+
+The sensitivity of an interferometer falls off away from the pointing center
+following the primary beam of its antennas. Quick-look images are
+uncorrected: the pixel values are the sky brightness multiplied by the beam
+response. This module models the beam with implementations of the
+`PrimaryBeam` trait and provides `apply` and `correct` to multiply images by
+the beam response and to divide it out.
+
+Both operations work on chunks of whole image rows, so that they fit in with
+reading images a few planes or rows at a time.
+
+*/
+
+use failure::Error;
+use std::f64::consts::PI;
+
+use super::wcs::CelestialWcs;
+
+/// The speed of light, in meters per second.
+const SPEED_OF_LIGHT: f64 = 299_792_458.;
+
+/// A model of the primary beam response.
+pub trait PrimaryBeam {
+    /// Get the beam response at frequency *freq* (in Hz) at an offset from
+    /// the pointing center. *dx* and *dy* are the offsets toward east and
+    /// north, as direction cosines in the tangent plane at the pointing
+    /// center, converted to degrees. The response at the pointing center is
+    /// 1.
+    fn response(&self, dx: f64, dy: f64, freq: f64) -> f64;
+}
+
+/// The Airy pattern of a uniformly illuminated circular dish.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AiryBeam {
+    /// The dish diameter, in meters.
+    pub diameter: f64,
+}
+
+impl AiryBeam {
+    /// Create the beam of a dish with diameter *diameter*, in meters.
+    pub fn new(diameter: f64) -> Self {
+        AiryBeam { diameter: diameter }
+    }
+}
+
+impl PrimaryBeam for AiryBeam {
+    fn response(&self, dx: f64, dy: f64, freq: f64) -> f64 {
+        let sin_theta = dx.hypot(dy).to_radians();
+        let x = PI * self.diameter * sin_theta * freq / SPEED_OF_LIGHT;
+
+        if x < 1e-8 {
+            return 1.;
+        }
+
+        let a = 2. * bessel_j1(x) / x;
+        a * a
+    }
+}
+
+/// A circular Gaussian beam whose width scales inversely with frequency.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GaussianBeam {
+    /// The full width at half maximum at the reference frequency, in
+    /// degrees.
+    pub fwhm: f64,
+
+    /// The reference frequency, in Hz.
+    pub ref_freq: f64,
+}
+
+impl GaussianBeam {
+    /// Create a beam with FWHM *fwhm* (in degrees) at frequency *ref_freq*
+    /// (in Hz).
+    pub fn new(fwhm: f64, ref_freq: f64) -> Self {
+        GaussianBeam {
+            fwhm: fwhm,
+            ref_freq: ref_freq,
+        }
+    }
+
+    /// Create the Gaussian approximation to the beam of a dish with diameter
+    /// *diameter*, in meters: its FWHM is 1.13 λ/*D*, which is appropriate
+    /// for dishes with a typical tapered illumination.
+    pub fn for_dish(diameter: f64) -> Self {
+        let ref_freq = 1e9;
+        let lambda = SPEED_OF_LIGHT / ref_freq;
+        GaussianBeam::new((1.13 * lambda / diameter).to_degrees(), ref_freq)
+    }
+}
+
+impl PrimaryBeam for GaussianBeam {
+    fn response(&self, dx: f64, dy: f64, freq: f64) -> f64 {
+        let fwhm = self.fwhm * self.ref_freq / freq;
+        let r2 = (dx * dx + dy * dy) / (fwhm * fwhm);
+        (-4. * std::f64::consts::LN_2 * r2).exp()
+    }
+}
+
+/// A beam given by an image of its response, such as a measured or
+/// simulated beam pattern.
+///
+/// The image is scaled in size inversely with frequency, and interpolated
+/// bilinearly. The response is zero outside of the image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageBeam {
+    nx: usize,
+    ny: usize,
+    data: Vec<f64>,
+    center: (f64, f64),
+    cell: f64,
+    ref_freq: f64,
+}
+
+impl ImageBeam {
+    /// Create a beam from an image of *nx*×*ny* response values, in FITS
+    /// order with east toward lower *x* and north toward higher *y*.
+    ///
+    /// *center* gives the 0-based pixel coordinates of the beam center,
+    /// *cell* the pixel size in degrees, and *ref_freq* the frequency at
+    /// which the image applies, in Hz. The response is normalized so that it
+    /// is 1 at the center.
+    pub fn new(
+        nx: usize,
+        ny: usize,
+        data: Vec<f64>,
+        center: (f64, f64),
+        cell: f64,
+        ref_freq: f64,
+    ) -> Result<Self, Error> {
+        if data.len() != nx * ny || nx < 2 || ny < 2 {
+            return fitserr!(
+                "beam image must have at least 2×2 pixels and {}×{} values; got {}",
+                nx,
+                ny,
+                data.len()
+            );
+        }
+
+        let mut beam = ImageBeam {
+            nx: nx,
+            ny: ny,
+            data: data,
+            center: center,
+            cell: cell,
+            ref_freq: ref_freq,
+        };
+
+        let peak = beam.interpolate(center.0, center.1);
+
+        if peak.is_nan() || peak <= 0. {
+            return fitserr!("beam image response at its center must be positive");
+        }
+
+        for v in &mut beam.data {
+            *v /= peak;
+        }
+
+        Ok(beam)
+    }
+
+    fn interpolate(&self, x: f64, y: f64) -> f64 {
+        if x.is_nan() || y.is_nan() || x < 0. || y < 0. {
+            return 0.;
+        }
+
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+
+        if x0 >= self.nx || y0 >= self.ny {
+            return 0.;
+        }
+
+        let x0 = x0.min(self.nx - 2);
+        let y0 = y0.min(self.ny - 2);
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+        if fx > 1. || fy > 1. {
+            return 0.;
+        }
+
+        let v = |i: usize, j: usize| self.data[(y0 + j) * self.nx + x0 + i];
+
+        (1. - fx) * (1. - fy) * v(0, 0)
+            + fx * (1. - fy) * v(1, 0)
+            + (1. - fx) * fy * v(0, 1)
+            + fx * fy * v(1, 1)
+    }
+}
+
+impl PrimaryBeam for ImageBeam {
+    fn response(&self, dx: f64, dy: f64, freq: f64) -> f64 {
+        let scale = freq / self.ref_freq / self.cell;
+        self.interpolate(self.center.0 - dx * scale, self.center.1 + dy * scale)
+    }
+}
+
+/// The pointing of an observation, at which a beam is centered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pointing {
+    /// The pointing center, in degrees.
+    pub center: (f64, f64),
+
+    /// The observing frequency, in Hz.
+    pub freq: f64,
+}
+
+impl Pointing {
+    /// Create a pointing centered on *center* (in degrees), observing at
+    /// frequency *freq* (in Hz).
+    pub fn new(center: (f64, f64), freq: f64) -> Self {
+        Pointing {
+            center: center,
+            freq: freq,
+        }
+    }
+
+    /// Get the beam response at the sky position *pos*, in degrees.
+    pub fn response<B: PrimaryBeam + ?Sized>(&self, beam: &B, pos: (f64, f64)) -> f64 {
+        let (dx, dy) = pointing_offset(self.center, pos);
+        beam.response(dx, dy, self.freq)
+    }
+}
+
+/// Multiply a chunk of image rows by the primary beam response.
+///
+/// *chunk* contains whole rows of an image that is *nx* pixels wide,
+/// starting with row *first_row*; *wcs* is the celestial coordinate system
+/// of the image. The beam is centered on and evaluated at the frequency of
+/// *pointing*.
+pub fn apply<B: PrimaryBeam + ?Sized>(
+    beam: &B,
+    wcs: &CelestialWcs,
+    pointing: &Pointing,
+    nx: usize,
+    first_row: usize,
+    chunk: &mut [f64],
+) {
+    for_each_response(beam, wcs, pointing, nx, first_row, chunk, |v, r| {
+        *v *= r;
+    });
+}
+
+/// Divide a chunk of image rows by the primary beam response.
+///
+/// The arguments are as for `apply`. Pixels where the response is below
+/// *cutoff* (or that are off the sky) are set to NaN, since the correction
+/// would amplify their noise beyond usefulness. A typical cutoff is 0.2.
+pub fn correct<B: PrimaryBeam + ?Sized>(
+    beam: &B,
+    wcs: &CelestialWcs,
+    pointing: &Pointing,
+    cutoff: f64,
+    nx: usize,
+    first_row: usize,
+    chunk: &mut [f64],
+) {
+    for_each_response(beam, wcs, pointing, nx, first_row, chunk, |v, r| {
+        *v = if r >= cutoff && r > 0. {
+            *v / r
+        } else {
+            std::f64::NAN
+        };
+    });
+}
+
+/// Get the offset of the sky position *pos* from *pointing*, both in
+/// degrees, as direction cosines toward east and north in degrees. See
+/// `PrimaryBeam::response`.
+pub fn pointing_offset(pointing: (f64, f64), pos: (f64, f64)) -> (f64, f64) {
+    let (a0, d0) = (pointing.0.to_radians(), pointing.1.to_radians());
+    let (a, d) = (pos.0.to_radians(), pos.1.to_radians());
+    let l = d.cos() * (a - a0).sin();
+    let m = d.sin() * d0.cos() - d.cos() * d0.sin() * (a - a0).cos();
+    (l.to_degrees(), m.to_degrees())
+}
+
+fn for_each_response<B, F>(
+    beam: &B,
+    wcs: &CelestialWcs,
+    pointing: &Pointing,
+    nx: usize,
+    first_row: usize,
+    chunk: &mut [f64],
+    mut func: F,
+) where
+    B: PrimaryBeam + ?Sized,
+    F: FnMut(&mut f64, f64),
+{
+    for (i, v) in chunk.iter_mut().enumerate() {
+        let x = (i % nx) as f64;
+        let y = (first_row + i / nx) as f64;
+
+        let r = match wcs.pixel_to_world(x, y) {
+            Some(pos) => pointing.response(beam, pos),
+            None => 0.,
+        };
+
+        func(v, r);
+    }
+}
+
+/// The Bessel function of the first kind of order 1, using the rational
+/// approximations of Numerical Recipes (§6.5), accurate to about 1e-8.
+fn bessel_j1(x: f64) -> f64 {
+    let ax = x.abs();
+
+    if ax < 8. {
+        let y = x * x;
+        let num = x
+            * (72_362_614_232.
+                + y * (-7_895_059_235.
+                    + y * (242_396_853.1
+                        + y * (-2_972_611.439 + y * (15_704.482_60 + y * -30.160_366_06)))));
+        let den = 144_725_228_442.
+            + y * (2_300_535_178.
+                + y * (18_583_304.74 + y * (99_447.433_94 + y * (376.999_139_7 + y))));
+        num / den
+    } else {
+        let z = 8. / ax;
+        let y = z * z;
+        let xx = ax - 2.356_194_491;
+        let p = 1.
+            + y * (0.183_105e-2
+                + y * (-0.351_639_649_6e-4 + y * (0.245_752_017_4e-5 + y * -0.240_337_019e-6)));
+        let q = 0.046_874_999_95
+            + y * (-0.200_269_087_3e-3
+                + y * (0.844_919_909_6e-5 + y * (-0.882_289_87e-6 + y * 0.105_787_412e-6)));
+        let ans = (std::f64::consts::FRAC_2_PI / ax).sqrt() * (xx.cos() * p - z * xx.sin() * q);
+
+        if x < 0. {
+            -ans
+        } else {
+            ans
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn beam_models() {
+    // Known values of J1 and its first zero.
+    assert!((bessel_j1(1.) - 0.440_050_585_7).abs() < 1e-7);
+    assert!((bessel_j1(10.) - 0.043_472_746_2).abs() < 1e-7);
+    assert!(bessel_j1(3.831_705_97).abs() < 1e-7);
+
+    // The first null of a 25 m dish at 1.4 GHz.
+    let airy = AiryBeam::new(25.);
+    let freq = 1.4e9;
+    let null = (3.831_705_97 * SPEED_OF_LIGHT / (PI * 25. * freq)).to_degrees();
+    assert_eq!(airy.response(0., 0., freq), 1.);
+    assert!(airy.response(null * 0.6, null * 0.8, freq).abs() < 1e-7);
+
+    let gauss = GaussianBeam::new(0.5, 1e9);
+    assert!((gauss.response(0.25, 0., 1e9) - 0.5).abs() < 1e-12);
+    assert!((gauss.response(0., 0.125, 2e9) - 0.5).abs() < 1e-12);
+
+    // A 3×3 image beam falling off linearly to the east, with 0.1° pixels.
+    let data = vec![0., 0.5, 1., 0., 1., 2., 0., 0.5, 1.];
+    let image = ImageBeam::new(3, 3, data, (1., 1.), 0.1, 1e9).unwrap();
+    assert_eq!(image.response(0., 0., 1e9), 1.);
+    assert!((image.response(0.05, 0., 1e9) - 0.5).abs() < 1e-12);
+    assert!((image.response(0.025, 0., 2e9) - 0.5).abs() < 1e-12);
+    assert_eq!(image.response(0., 0.5, 1e9), 0.);
+    assert!(ImageBeam::new(3, 3, vec![0.; 9], (1., 1.), 0.1, 1e9).is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn beam_correction() {
+    use super::wcs::Projection;
+
+    // 0.1° pixels with the pointing center at pixel (1, 1).
+    let cd = [[-0.1, 0.], [0., 0.1]];
+    let wcs = CelestialWcs::new(Projection::Sin, [30., 40.], [2., 2.], cd).unwrap();
+    let beam = GaussianBeam::new(0.2, 1e9);
+    let pointing = Pointing::new((30., 40.), 1e9);
+
+    let mut rows = vec![1.; 6];
+    apply(&beam, &wcs, &pointing, 3, 1, &mut rows);
+    assert_eq!(rows[1], 1.);
+    assert!((rows[0] - 0.5).abs() < 1e-3);
+    assert!((rows[4] - 0.5).abs() < 1e-3);
+
+    correct(&beam, &wcs, &pointing, 0.4, 3, 1, &mut rows);
+    assert!((rows[0] - 1.).abs() < 1e-12);
+    assert_eq!(rows[1], 1.);
+    assert!(rows[3].is_nan());
+}
@@ -0,0 +1,176 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Co-add FITS images into a weighted mosaic.
+
+Each input is given as `IMAGE` or `IMAGE:WEIGHTS`, where `WEIGHTS` is a
+FITS weight map (inverse variance) on the same grid as the image. Inputs
+without a weight map get uniform weights, unless `--dish` is given, in which
+case they are weighted by the square of a Gaussian model of the primary
+beam of dishes of that diameter; the images must then already be corrected
+for the primary beam. The inputs must be on compatible grids; see the
+`rubbl_fits::mosaic` module.
+
+With `--dry-run`, the inputs are checked and the files that would be
+written are reported, but the mosaic is not computed.
+
+*/
+
+extern crate clap;
+#[macro_use]
+extern crate rubbl_core;
+extern crate rubbl_fits;
+
+use clap::{App, Arg};
+use rubbl_core::dryrun::{dry_run_requested, ChangePlan, ClapDryRunArgsExt};
+use rubbl_core::io::{ClapIoPolicyArgsExt, IoPolicy, RetryingReader};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use rubbl_fits::beam::GaussianBeam;
+use rubbl_fits::image::ImageCube;
+use rubbl_fits::mosaic::{InputWeights, Mosaic, MosaicInput};
+use rubbl_fits::FitsParser;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::process;
+
+fn open_image(path: &Path, policy: &IoPolicy) -> Result<ImageCube<RetryingReader<File>>, Error> {
+    let file = ctry!(File::open(path); "failed to open \"{}\"", path.display());
+    let parser =
+        ctry!(FitsParser::new(policy.reader(file)); "failed to parse \"{}\"", path.display());
+    Ok(ctry!(ImageCube::open_image(parser, 0);
+             "failed to open the image in \"{}\"", path.display()))
+}
+
+fn main() {
+    let matches = App::new("rubbl-imgcoadd")
+        .version("0.1.0")
+        .about("Co-add FITS images into a weighted mosaic")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .rubbl_dry_run_args()
+        .rubbl_io_policy_args()
+        .arg(
+            Arg::with_name("variance")
+                .long("variance")
+                .value_name("PATH")
+                .help("Also write the variance map of the mosaic to this path"),
+        )
+        .arg(
+            Arg::with_name("dish")
+                .long("dish")
+                .value_name("METERS")
+                .help("Weight inputs without weight maps by a model of the primary beam"),
+        )
+        .arg(
+            Arg::with_name("channel")
+                .long("channel")
+                .value_name("NUMBER")
+                .default_value("0")
+                .help("The 0-based channel of the inputs to co-add"),
+        )
+        .arg(
+            Arg::with_name("chunk_rows")
+                .long("chunk-rows")
+                .value_name("NUMBER")
+                .default_value("256")
+                .help("The number of mosaic rows to compute at once"),
+        )
+        .arg(
+            Arg::with_name("OUT-PATH")
+                .help("The path of the mosaic FITS file to create")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("INPUTS")
+                .help("The images to co-add, as IMAGE or IMAGE:WEIGHTS paths")
+                .required(true)
+                .multiple(true)
+                .index(2),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, _nbe| -> Result<i32, Error> {
+            let io_policy = IoPolicy::from_clap(&matches)?;
+            let channel = ctry!(matches.value_of("channel").unwrap().parse::<usize>();
+                                       "bad --channel value");
+            let chunk_rows = ctry!(matches.value_of("chunk_rows").unwrap().parse::<usize>();
+                                          "bad --chunk-rows value");
+            let dish = match matches.value_of("dish") {
+                Some(v) => Some(ctry!(v.parse::<f64>(); "bad --dish value \"{}\"", v)),
+                None => None,
+            };
+
+            let mut inputs = Vec::new();
+
+            for spec in matches.values_of("INPUTS").unwrap() {
+                let mut pieces = spec.splitn(2, ':');
+                let image_path = Path::new(pieces.next().unwrap());
+                let image = open_image(image_path, &io_policy)?;
+
+                let weights = if let Some(weights_path) = pieces.next() {
+                    InputWeights::Map(open_image(Path::new(weights_path), &io_policy)?)
+                } else if let Some(diameter) = dish {
+                    let pointing = ctry!(image.pointing(channel);
+                                         "cannot model the primary beam of \"{}\"",
+                                         image_path.display());
+                    InputWeights::Beam {
+                        beam: Box::new(GaussianBeam::for_dish(diameter)),
+                        pointing: pointing,
+                        sigma: 1.,
+                    }
+                } else {
+                    InputWeights::Uniform(1.)
+                };
+
+                inputs.push(ctry!(MosaicInput::new(image, channel, weights);
+                                  "cannot use \"{}\" in the mosaic", image_path.display()));
+            }
+
+            let mut mosaic = Mosaic::new(inputs)?;
+            mosaic.set_chunk_rows(chunk_rows);
+
+            let out_path = Path::new(matches.value_of_os("OUT-PATH").unwrap());
+
+            if dry_run_requested(&matches) {
+                let (nx, ny) = mosaic.shape();
+                let mut plan = ChangePlan::new();
+                plan.record(
+                    out_path.display().to_string(),
+                    format!("write a {}×{} mosaic", nx, ny),
+                    0,
+                    None,
+                );
+
+                if let Some(p) = matches.value_of_os("variance") {
+                    plan.record(
+                        Path::new(p).display().to_string(),
+                        "write the variance map of the mosaic",
+                        0,
+                        None,
+                    );
+                }
+
+                plan.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+                return Ok(0);
+            }
+
+            let out = ctry!(File::create(out_path);
+                            "failed to create \"{}\"", out_path.display());
+
+            let variance = match matches.value_of_os("variance") {
+                Some(p) => Some(BufWriter::new(io_policy.writer(ctry!(File::create(p);
+                    "failed to create \"{}\"", Path::new(p).display())))),
+                None => None,
+            };
+
+            mosaic.write(BufWriter::new(io_policy.writer(out)), variance)?;
+            Ok(0)
+        },
+    ));
+}
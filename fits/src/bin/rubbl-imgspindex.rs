@@ -0,0 +1,193 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*! Compute a spectral index map from two FITS images.
+
+The images must be on the same grid. Their frequencies are taken from their
+spectral axes unless they are given with `--freqs`, and their noise levels
+are estimated from their pixel values unless they are given with `--noise`.
+Pixels where either image is below the signal-to-noise threshold are
+blanked. See the `rubbl_fits::spindex` module.
+
+With `--dry-run`, the images are checked and the files that would be
+written are reported, but the map is not computed.
+
+*/
+
+extern crate clap;
+extern crate failure;
+#[macro_use]
+extern crate rubbl_core;
+extern crate rubbl_fits;
+
+use clap::{App, Arg};
+use failure::err_msg;
+use rubbl_core::dryrun::{dry_run_requested, ChangePlan, ClapDryRunArgsExt};
+use rubbl_core::io::{ClapIoPolicyArgsExt, IoPolicy, RetryingReader};
+use rubbl_core::notify::ClapNotificationArgsExt;
+use rubbl_core::report::{ClapReportArgsExt, OutputFormat, Report};
+use rubbl_core::Error;
+use rubbl_fits::image::ImageCube;
+use rubbl_fits::spindex::SpectralIndex;
+use rubbl_fits::FitsParser;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::process;
+
+fn open_image(path: &Path, policy: &IoPolicy) -> Result<ImageCube<RetryingReader<File>>, Error> {
+    let file = ctry!(File::open(path); "failed to open \"{}\"", path.display());
+    let parser =
+        ctry!(FitsParser::new(policy.reader(file)); "failed to parse \"{}\"", path.display());
+    Ok(ctry!(ImageCube::open_image(parser, 0);
+             "failed to open the image in \"{}\"", path.display()))
+}
+
+/// Parse a pair of comma-separated numbers.
+fn parse_pair(text: &str, what: &str) -> Result<(f64, f64), Error> {
+    let values = text
+        .split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>();
+
+    match values {
+        Ok(ref v) if v.len() == 2 => Ok((v[0], v[1])),
+        _ => Err(err_msg(format!(
+            "{} must be two comma-separated numbers; got \"{}\"",
+            what, text
+        ))),
+    }
+}
+
+fn main() {
+    let matches = App::new("rubbl-imgspindex")
+        .version("0.1.0")
+        .about("Compute a spectral index map from two FITS images")
+        .rubbl_notify_args()
+        .rubbl_report_args()
+        .rubbl_dry_run_args()
+        .rubbl_io_policy_args()
+        .arg(
+            Arg::with_name("error")
+                .long("error")
+                .value_name("PATH")
+                .help("Also write the map of spectral index uncertainties to this path"),
+        )
+        .arg(
+            Arg::with_name("freqs")
+                .long("freqs")
+                .value_name("HZ,HZ")
+                .help("The frequencies of the two images"),
+        )
+        .arg(
+            Arg::with_name("noise")
+                .long("noise")
+                .value_name("SIGMA,SIGMA")
+                .help("The noise levels of the two images"),
+        )
+        .arg(
+            Arg::with_name("snr")
+                .long("snr")
+                .value_name("NUMBER")
+                .default_value("5")
+                .help("The signal-to-noise ratio below which pixels are blanked"),
+        )
+        .arg(
+            Arg::with_name("IMAGE1")
+                .help("The path of the first image")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("IMAGE2")
+                .help("The path of the second image")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("OUT-PATH")
+                .help("The path of the spectral index FITS file to create")
+                .required(true)
+                .index(3),
+        )
+        .get_matches();
+
+    process::exit(rubbl_core::notify::run_with_notifications(
+        matches,
+        |matches, nbe| -> Result<i32, Error> {
+            let io_policy = IoPolicy::from_clap(&matches)?;
+            let first = open_image(
+                Path::new(matches.value_of_os("IMAGE1").unwrap()),
+                &io_policy,
+            )?;
+            let second = open_image(
+                Path::new(matches.value_of_os("IMAGE2").unwrap()),
+                &io_policy,
+            )?;
+
+            let freqs = match matches.value_of("freqs") {
+                Some(text) => parse_pair(text, "--freqs")?,
+                None => (
+                    ctry!(first.frequency(0); "cannot determine the frequency of IMAGE1"),
+                    ctry!(second.frequency(0); "cannot determine the frequency of IMAGE2"),
+                ),
+            };
+
+            let snr = ctry!(matches.value_of("snr").unwrap().parse::<f64>();
+                            "bad --snr value");
+
+            let mut spindex = SpectralIndex::new(first, second, freqs)?;
+            spindex.set_snr_threshold(snr);
+
+            if let Some(text) = matches.value_of("noise") {
+                spindex.set_noise(parse_pair(text, "--noise")?);
+            }
+
+            let noise = spindex.noise();
+            rn_note!(
+                nbe,
+                "frequencies {:.6e} and {:.6e} Hz; noise levels {:.3e} and {:.3e}",
+                freqs.0,
+                freqs.1,
+                noise.0,
+                noise.1
+            );
+
+            let out_path = Path::new(matches.value_of_os("OUT-PATH").unwrap());
+
+            if dry_run_requested(&matches) {
+                let mut plan = ChangePlan::new();
+                plan.record(
+                    out_path.display().to_string(),
+                    "write the spectral index map",
+                    0,
+                    None,
+                );
+
+                if let Some(p) = matches.value_of_os("error") {
+                    plan.record(
+                        Path::new(p).display().to_string(),
+                        "write the map of spectral index uncertainties",
+                        0,
+                        None,
+                    );
+                }
+
+                plan.emit(OutputFormat::from_clap(&matches), &mut io::stdout())?;
+                return Ok(0);
+            }
+
+            let out = ctry!(File::create(out_path);
+                            "failed to create \"{}\"", out_path.display());
+
+            let error = match matches.value_of_os("error") {
+                Some(p) => Some(BufWriter::new(io_policy.writer(ctry!(File::create(p);
+                    "failed to create \"{}\"", Path::new(p).display())))),
+                None => None,
+            };
+
+            spindex.write(BufWriter::new(io_policy.writer(out)), error)?;
+            Ok(0)
+        },
+    ));
+}
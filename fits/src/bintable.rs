@@ -0,0 +1,546 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Reading and writing FITS binary tables.
+
+A `BinaryTable` reads a whole `BINTABLE` extension into memory, so it is
+meant for the small tables of metadata that accompany many data sets rather
+than for bulk data. The columns are described by the `TTYPEn` and `TFORMn`
+keywords; cells can be fetched as integers, floats, or strings according to
+their type. Scaling with `TSCALn` and `TZEROn` is not applied, and
+variable-length array columns can be skipped over but not read.
+
+A `BinaryTableWriter` writes a FITS file consisting of an empty primary HDU
+followed by a single binary table, whose columns are described by
+`ColumnFormat`s. Rows are written a batch at a time, as the raw big-endian
+bytes of their cells, so tables larger than memory can be written.
+
+*/
+
+use failure::Error;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use super::{format_card, format_string, FitsParser, HduKind, Header, END_MARKER};
+
+/// One column of a binary table.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BinaryColumn {
+    /// The name of the column, from its `TTYPEn` keyword.
+    pub name: String,
+
+    /// The type code of the column's elements, such as `J` or `E`.
+    pub code: char,
+
+    /// The number of elements in each cell.
+    pub repeat: usize,
+
+    /// The offset of the column's cells within a row, in bytes.
+    offset: usize,
+
+    /// The width of each cell, in bytes.
+    width: usize,
+}
+
+/// A binary table read into memory.
+#[derive(Clone, Debug)]
+pub struct BinaryTable {
+    header: Header,
+    columns: Vec<BinaryColumn>,
+    row_size: usize,
+    n_rows: usize,
+    data: Vec<u8>,
+}
+
+impl BinaryTable {
+    /// Read the binary table in HDU number *hdu_num* of the file parsed by
+    /// *parser*.
+    pub fn read<R: Read + Seek>(parser: &mut FitsParser<R>, hdu_num: usize) -> Result<Self, Error> {
+        let header = parser.read_header(hdu_num)?;
+        let hdu = parser.hdus[hdu_num].clone();
+
+        if hdu.kind != HduKind::BinaryTableExtension {
+            return fitserr!("FITS HDU #{} is not a binary table", hdu_num);
+        }
+
+        if hdu.naxis.len() != 2 {
+            return fitserr!(
+                "FITS binary table in HDU #{} does not have two axes",
+                hdu_num
+            );
+        }
+
+        let row_size = hdu.naxis[0];
+        let n_rows = hdu.naxis[1];
+        let n_fields = header.int("TFIELDS")?.unwrap_or(0);
+        let mut columns = Vec::new();
+        let mut offset = 0;
+
+        for i in 1..=n_fields {
+            let tform = match header.string(&format!("TFORM{}", i))? {
+                Some(t) => t,
+                None => {
+                    return fitserr!("FITS binary table is missing TFORM{}", i);
+                }
+            };
+
+            let (repeat, code) = parse_tform(&tform)?;
+            let width = cell_width(code, repeat)?;
+
+            columns.push(BinaryColumn {
+                name: header
+                    .string(&format!("TTYPE{}", i))?
+                    .unwrap_or_else(|| format!("COL{}", i)),
+                code: code,
+                repeat: repeat,
+                offset: offset,
+                width: width,
+            });
+
+            offset += width;
+        }
+
+        if offset != row_size {
+            return fitserr!(
+                "FITS binary table columns total {} bytes, but its rows are {} bytes",
+                offset,
+                row_size
+            );
+        }
+
+        parser.inner.seek(SeekFrom::Start(hdu.data_offset()))?;
+        let mut data = vec![0u8; row_size * n_rows];
+        parser.inner.read_exact(&mut data)?;
+
+        Ok(BinaryTable {
+            header: header,
+            columns: columns,
+            row_size: row_size,
+            n_rows: n_rows,
+            data: data,
+        })
+    }
+
+    /// Get the header of the table's HDU.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Get the descriptions of the columns.
+    pub fn columns(&self) -> &[BinaryColumn] {
+        &self.columns
+    }
+
+    /// Get the number of rows.
+    pub fn n_rows(&self) -> usize {
+        self.n_rows
+    }
+
+    /// Get the index of the column named *name*, ignoring case.
+    pub fn column_index(&self, name: &str) -> Result<usize, Error> {
+        match self
+            .columns
+            .iter()
+            .position(|c| c.name.eq_ignore_ascii_case(name))
+        {
+            Some(i) => Ok(i),
+            None => fitserr!("FITS binary table has no column named {}", name),
+        }
+    }
+
+    /// Get the elements of a numeric cell as floats.
+    pub fn get_f64s(&self, row: usize, col: usize) -> Result<Vec<f64>, Error> {
+        let (column, bytes) = self.cell(row, col)?;
+        let n = column.repeat;
+
+        Ok(match column.code {
+            'B' => bytes.iter().map(|&b| b as f64).collect(),
+            'I' => (0..n)
+                .map(|i| i16::from_be_bytes([bytes[2 * i], bytes[2 * i + 1]]) as f64)
+                .collect(),
+            'J' => (0..n)
+                .map(|i| i32::from_be_bytes(be4(bytes, i)) as f64)
+                .collect(),
+            'K' => (0..n)
+                .map(|i| i64::from_be_bytes(be8(bytes, i)) as f64)
+                .collect(),
+            'E' => (0..n)
+                .map(|i| f32::from_be_bytes(be4(bytes, i)) as f64)
+                .collect(),
+            'D' => (0..n).map(|i| f64::from_be_bytes(be8(bytes, i))).collect(),
+            other => {
+                return fitserr!(
+                    "FITS binary table column {} has non-numeric type {:?}",
+                    column.name,
+                    other
+                );
+            }
+        })
+    }
+
+    /// Get the first element of a numeric cell as a float.
+    pub fn get_f64(&self, row: usize, col: usize) -> Result<f64, Error> {
+        match self.get_f64s(row, col)?.first() {
+            Some(&v) => Ok(v),
+            None => fitserr!("FITS binary table cell ({}, {}) is empty", row, col),
+        }
+    }
+
+    /// Get the first element of an integer or logical cell.
+    pub fn get_i64(&self, row: usize, col: usize) -> Result<i64, Error> {
+        let (column, bytes) = self.cell(row, col)?;
+
+        if column.repeat == 0 {
+            return fitserr!("FITS binary table cell ({}, {}) is empty", row, col);
+        }
+
+        Ok(match column.code {
+            'L' => (bytes[0] == b'T') as i64,
+            'B' => bytes[0] as i64,
+            'I' => i16::from_be_bytes([bytes[0], bytes[1]]) as i64,
+            'J' => i32::from_be_bytes(be4(bytes, 0)) as i64,
+            'K' => i64::from_be_bytes(be8(bytes, 0)),
+            other => {
+                return fitserr!(
+                    "FITS binary table column {} has non-integer type {:?}",
+                    column.name,
+                    other
+                );
+            }
+        })
+    }
+
+    /// Get the value of a character cell, without trailing spaces or NULs.
+    pub fn get_string(&self, row: usize, col: usize) -> Result<String, Error> {
+        let (column, bytes) = self.cell(row, col)?;
+
+        if column.code != 'A' {
+            return fitserr!(
+                "FITS binary table column {} does not contain characters",
+                column.name
+            );
+        }
+
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Ok(String::from_utf8_lossy(&bytes[..end]).trim_end().to_owned())
+    }
+
+    fn cell(&self, row: usize, col: usize) -> Result<(&BinaryColumn, &[u8]), Error> {
+        if row >= self.n_rows {
+            return fitserr!("no row #{} in FITS binary table", row);
+        }
+
+        let column = match self.columns.get(col) {
+            Some(c) => c,
+            None => {
+                return fitserr!("no column #{} in FITS binary table", col);
+            }
+        };
+
+        let start = row * self.row_size + column.offset;
+        Ok((column, &self.data[start..start + column.width]))
+    }
+}
+
+/// The format of a column of a binary table being written.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColumnFormat {
+    /// The name of the column, written as its `TTYPEn` keyword.
+    pub name: String,
+
+    /// The type code of the column's elements, such as `J` or `E`.
+    /// Variable-length array descriptors (`P` and `Q`) are not supported.
+    pub code: char,
+
+    /// The number of elements in each cell. For `A` columns, this is the
+    /// number of characters.
+    pub repeat: usize,
+
+    /// The shape of each cell, first axis varying fastest, written as the
+    /// `TDIMn` keyword if it is not empty. Its product must be *repeat*.
+    pub dims: Vec<usize>,
+
+    /// The units of the column's values, written as the `TUNITn` keyword if
+    /// it is not empty.
+    pub unit: String,
+}
+
+impl ColumnFormat {
+    /// Describe a column named *name* of cells of *repeat* elements of type
+    /// *code*, with no shape or units.
+    pub fn new(name: &str, code: char, repeat: usize) -> Self {
+        ColumnFormat {
+            name: name.to_owned(),
+            code: code,
+            repeat: repeat,
+            dims: Vec::new(),
+            unit: String::new(),
+        }
+    }
+
+    /// Get the `TFORMn` value of this column.
+    pub fn tform(&self) -> String {
+        format!("{}{}", self.repeat, self.code)
+    }
+
+    /// Get the width of each cell of this column, in bytes.
+    pub fn width(&self) -> Result<usize, Error> {
+        cell_width(self.code, self.repeat)
+    }
+}
+
+/// Writes a FITS file containing a single binary table.
+pub struct BinaryTableWriter<W: Write> {
+    dest: W,
+    row_size: usize,
+    n_remaining: usize,
+    n_bytes: usize,
+}
+
+impl<W: Write> BinaryTableWriter<W> {
+    /// Start writing a table of *n_rows* rows with the columns *columns* to
+    /// *dest*. *cards* are extra 80-byte header records to include in the
+    /// table's header.
+    pub fn new(
+        mut dest: W,
+        columns: &[ColumnFormat],
+        n_rows: usize,
+        cards: &[u8],
+    ) -> Result<Self, Error> {
+        if cards.len() % 80 != 0 {
+            return fitserr!("FITS header records must be 80 bytes long");
+        }
+
+        let mut row_size = 0;
+
+        for c in columns {
+            if c.code == 'P' || c.code == 'Q' {
+                return fitserr!(
+                    "cannot write variable-length FITS binary table column {}",
+                    c.name
+                );
+            }
+
+            if !c.dims.is_empty() && c.dims.iter().product::<usize>() != c.repeat {
+                return fitserr!(
+                    "shape {:?} of FITS binary table column {} does not match its {} elements",
+                    c.dims,
+                    c.name,
+                    c.repeat
+                );
+            }
+
+            row_size += c.width()?;
+        }
+
+        let mut header = Vec::new();
+        let logical = format!("{:>20}", "T");
+        let int = |v: usize| format!("{:>20}", v);
+
+        header.extend_from_slice(&format_card("SIMPLE", &logical));
+        header.extend_from_slice(&format_card("BITPIX", &int(8)));
+        header.extend_from_slice(&format_card("NAXIS", &int(0)));
+        header.extend_from_slice(&format_card("EXTEND", &logical));
+        header.extend_from_slice(END_MARKER);
+        pad_header(&mut header);
+
+        header.extend_from_slice(&format_card("XTENSION", &format_string("BINTABLE")));
+        header.extend_from_slice(&format_card("BITPIX", &int(8)));
+        header.extend_from_slice(&format_card("NAXIS", &int(2)));
+        header.extend_from_slice(&format_card("NAXIS1", &int(row_size)));
+        header.extend_from_slice(&format_card("NAXIS2", &int(n_rows)));
+        header.extend_from_slice(&format_card("PCOUNT", &int(0)));
+        header.extend_from_slice(&format_card("GCOUNT", &int(1)));
+        header.extend_from_slice(&format_card("TFIELDS", &int(columns.len())));
+
+        for (i, c) in columns.iter().enumerate() {
+            let n = i + 1;
+            header.extend_from_slice(&format_card(
+                &format!("TTYPE{}", n),
+                &format_string(&c.name),
+            ));
+            header.extend_from_slice(&format_card(
+                &format!("TFORM{}", n),
+                &format_string(&c.tform()),
+            ));
+
+            if !c.dims.is_empty() {
+                let dims: Vec<String> = c.dims.iter().map(|d| d.to_string()).collect();
+                header.extend_from_slice(&format_card(
+                    &format!("TDIM{}", n),
+                    &format_string(&format!("({})", dims.join(","))),
+                ));
+            }
+
+            if !c.unit.is_empty() {
+                header.extend_from_slice(&format_card(
+                    &format!("TUNIT{}", n),
+                    &format_string(&c.unit),
+                ));
+            }
+        }
+
+        header.extend_from_slice(cards);
+        header.extend_from_slice(END_MARKER);
+        pad_header(&mut header);
+        dest.write_all(&header)?;
+
+        Ok(BinaryTableWriter {
+            dest: dest,
+            row_size: row_size,
+            n_remaining: n_rows,
+            n_bytes: 0,
+        })
+    }
+
+    /// Get the size of each row, in bytes.
+    pub fn row_size(&self) -> usize {
+        self.row_size
+    }
+
+    /// Write the next rows, given as the big-endian bytes of their cells in
+    /// column order. The length of *data* must be a multiple of the row
+    /// size.
+    pub fn write_rows(&mut self, data: &[u8]) -> Result<(), Error> {
+        let n_rows = data.len().checked_div(self.row_size).unwrap_or(0);
+
+        if n_rows * self.row_size != data.len() {
+            return fitserr!(
+                "{} bytes is not a whole number of {}-byte FITS binary table rows",
+                data.len(),
+                self.row_size
+            );
+        }
+
+        if n_rows > self.n_remaining {
+            return fitserr!(
+                "tried to write {} rows to a FITS binary table with room for only {} more",
+                n_rows,
+                self.n_remaining
+            );
+        }
+
+        self.dest.write_all(data)?;
+        self.n_remaining -= n_rows;
+        self.n_bytes += data.len();
+        Ok(())
+    }
+
+    /// Finish writing the file, returning the underlying stream. All of the
+    /// rows of the table must have been written.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.n_remaining != 0 && self.row_size != 0 {
+            return fitserr!(
+                "FITS binary table was closed with {} rows left unwritten",
+                self.n_remaining
+            );
+        }
+
+        let padding = (2880 - self.n_bytes % 2880) % 2880;
+        self.dest.write_all(&vec![0u8; padding])?;
+        Ok(self.dest)
+    }
+}
+
+fn pad_header(header: &mut Vec<u8>) {
+    while header.len() % 2880 != 0 {
+        header.push(b' ');
+    }
+}
+
+/// Get the width of a binary table cell of *repeat* elements of type *code*,
+/// in bytes.
+fn cell_width(code: char, repeat: usize) -> Result<usize, Error> {
+    Ok(match code {
+        'L' | 'B' | 'A' => repeat,
+        'X' => (repeat + 7) / 8,
+        'I' => 2 * repeat,
+        'J' | 'E' => 4 * repeat,
+        'K' | 'D' | 'C' | 'P' => 8 * repeat,
+        'M' | 'Q' => 16 * repeat,
+        other => {
+            return fitserr!("unsupported FITS binary table type code {:?}", other);
+        }
+    })
+}
+
+/// Parse a `TFORMn` value into a repeat count and a type code.
+fn parse_tform(tform: &str) -> Result<(usize, char), Error> {
+    let tform = tform.trim();
+    let digits = tform.chars().take_while(|c| c.is_ascii_digit()).count();
+
+    let repeat = if digits == 0 {
+        1
+    } else {
+        match tform[..digits].parse() {
+            Ok(r) => r,
+            Err(_) => {
+                return fitserr!("malformed FITS TFORM value {:?}", tform);
+            }
+        }
+    };
+
+    match tform[digits..].chars().next() {
+        Some(c) => Ok((repeat, c)),
+        None => fitserr!("malformed FITS TFORM value {:?}", tform),
+    }
+}
+
+fn be4(bytes: &[u8], i: usize) -> [u8; 4] {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&bytes[4 * i..4 * i + 4]);
+    buf
+}
+
+fn be8(bytes: &[u8], i: usize) -> [u8; 8] {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[8 * i..8 * i + 8]);
+    buf
+}
+
+#[cfg(test)]
+#[test]
+fn bintable_round_trip() {
+    use std::io::Cursor;
+
+    let mut shaped = ColumnFormat::new("VIS", 'C', 6);
+    shaped.dims = vec![2, 3];
+    shaped.unit = "Jy".to_owned();
+    let columns = vec![
+        ColumnFormat::new("ANT", 'J', 1),
+        ColumnFormat::new("NAME", 'A', 4),
+        shaped,
+        ColumnFormat::new("FLAG", 'L', 1),
+    ];
+
+    let mut w = BinaryTableWriter::new(Vec::new(), &columns, 2, &[]).unwrap();
+    assert_eq!(w.row_size(), 4 + 4 + 48 + 1);
+    assert!(w.write_rows(&[0; 10]).is_err());
+
+    let mut rows = Vec::new();
+
+    for (i, name) in [&b"ab  "[..], &b"cdef"[..]].iter().enumerate() {
+        rows.extend_from_slice(&(i as i32 + 7).to_be_bytes());
+        rows.extend_from_slice(name);
+        rows.extend_from_slice(&[0; 48]);
+        rows.push(if i == 0 { b'T' } else { b'F' });
+    }
+
+    w.write_rows(&rows).unwrap();
+    let file = w.finish().unwrap();
+    assert_eq!(file.len(), 3 * 2880);
+
+    let mut parser = FitsParser::new(Cursor::new(file)).unwrap();
+    let table = BinaryTable::read(&mut parser, 1).unwrap();
+    assert_eq!(table.n_rows(), 2);
+    assert_eq!(table.columns()[2].repeat, 6);
+    assert_eq!(
+        table.header().string("TDIM3").unwrap(),
+        Some("(2,3)".to_owned())
+    );
+    assert_eq!(table.get_i64(1, 0).unwrap(), 8);
+    assert_eq!(table.get_string(0, 1).unwrap(), "ab");
+    assert_eq!(table.get_i64(0, 3).unwrap(), 1);
+}
@@ -0,0 +1,566 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Reading raw MWA correlator output ("gpubox" files).
+
+The MWA correlator writes each coarse channel of an observation to its own
+series of FITS files, with names like `1065880128_20131015134830_ch109_000.fits`
+or, from the older correlator, `..._gpubox01_00.fits`. Each file after the
+first of a series (the "batch" number at the end of the name) continues the
+time range of the one before. Within a file, every HDU after the primary one
+holds a single integration as an image with one row per baseline; each row
+has the four polarizations XX, XY, YX, and YY of every fine channel, as
+pairs of real and imaginary parts. The start time of the integration is
+given by the `TIME` (Unix seconds) and `MILLITIM` (milliseconds) keywords.
+
+`GpuboxReader` indexes a set of such files against an `MwaMetafits` and
+delivers the integrations in time order as `VisChunk`s spanning all of the
+coarse channels, in order of increasing frequency. It implements
+`LiveVisSource`, so its output can be fed straight to a Measurement Set
+writer. The files need not cover the same times: coarse channels that are
+missing from an integration are flagged, and integrations missing from every
+file are skipped — or, with `set_fill_gaps`, delivered fully flagged, so
+that the output has a regular time grid. Baselines involving flagged tiles
+are flagged too.
+
+The baselines are expected in the order used by the current (MWAX)
+correlator, running over the antennas of the metafits `TILEDATA` table with
+`ant1 <= ant2`. The older correlator's outputs need an additional
+reordering that depends on its PFB cabling; that is not implemented here.
+Per-baseline weights HDUs, which the MWAX correlator interleaves with the
+visibilities, are skipped.
+
+*/
+
+use byteorder::{BigEndian, ByteOrder};
+use failure::Error;
+use rubbl_core::io::{IoPolicy, RetryingReader};
+use rubbl_core::units::{MjdSeconds, Seconds};
+use rubbl_core::{Complex, Result as CoreResult};
+use rubbl_visdata::baseline::{TriangleOrder, TriangularBaselines};
+use rubbl_visdata::streaming::{LiveVisSource, VisChunk};
+use rubbl_visdata::{BasePol, VisPol};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use super::metafits::MwaMetafits;
+use super::{Bitpix, FitsParser};
+
+/// The polarizations of each baseline, in the order stored in the files.
+pub const GPUBOX_POLS: [VisPol; 4] = [VisPol::XX, VisPol::XY, VisPol::YX, VisPol::YY];
+
+/// How a gpubox file name identifies its coarse channel.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChannelId {
+    /// The older correlator numbers its files `gpubox01` onwards, indexing
+    /// the coarse channels in the order of the metafits `CHANNELS` list.
+    Gpubox(u32),
+
+    /// The MWAX correlator names its files with the receiver channel number,
+    /// as in `ch109`.
+    Receiver(u32),
+}
+
+/// Parse the name of a gpubox file, returning the coarse channel and the
+/// batch number, or `None` if the name does not have the expected form.
+pub fn parse_file_name(name: &str) -> Option<(ChannelId, usize)> {
+    let stem = name.strip_suffix(".fits").unwrap_or(name);
+
+    let mut pieces = stem.rsplit('_');
+    let batch = pieces.next()?.parse().ok()?;
+    let chan = pieces.next()?;
+
+    if let Some(n) = chan.strip_prefix("gpubox") {
+        Some((ChannelId::Gpubox(n.parse().ok()?), batch))
+    } else if let Some(n) = chan.strip_prefix("ch") {
+        Some((ChannelId::Receiver(n.parse().ok()?), batch))
+    } else {
+        None
+    }
+}
+
+/// The location of one integration of one coarse channel.
+#[derive(Clone, Copy, Debug)]
+struct HduRef {
+    file: usize,
+    hdu: usize,
+}
+
+/// Assembles integrations from a set of gpubox files.
+pub struct GpuboxReader<R: Read + Seek> {
+    files: Vec<FitsParser<R>>,
+    coarse_chans: Vec<u32>,
+    n_fine: usize,
+    baselines: TriangularBaselines,
+    flagged_ants: Vec<bool>,
+    int_time: f64,
+    timesteps: BTreeMap<i64, Vec<Option<HduRef>>>,
+    grid: Vec<i64>,
+    next: usize,
+    fill_gaps: bool,
+    n_skipped: usize,
+}
+
+impl GpuboxReader<File> {
+    /// Index the gpubox files at *paths*, which belong to the observation
+    /// described by *metafits*.
+    pub fn open<P: AsRef<Path>>(metafits: &MwaMetafits, paths: &[P]) -> Result<Self, Error> {
+        let mut files = Vec::with_capacity(paths.len());
+
+        for path in paths {
+            let path = path.as_ref();
+            files.push((file_name(path)?, File::open(path)?));
+        }
+
+        Self::new(metafits, files)
+    }
+}
+
+impl GpuboxReader<RetryingReader<File>> {
+    /// Index the gpubox files at *paths* like `open`, but read them
+    /// following *policy*, so that a conversion reading from a network
+    /// filesystem survives brief outages.
+    pub fn open_with_policy<P: AsRef<Path>>(
+        metafits: &MwaMetafits,
+        paths: &[P],
+        policy: &IoPolicy,
+    ) -> Result<Self, Error> {
+        let mut files = Vec::with_capacity(paths.len());
+
+        for path in paths {
+            let path = path.as_ref();
+            files.push((file_name(path)?, policy.reader(File::open(path)?)));
+        }
+
+        Self::new(metafits, files)
+    }
+}
+
+/// Get the file name of *path*, which identifies its coarse channel.
+fn file_name(path: &Path) -> Result<String, Error> {
+    match path.file_name() {
+        Some(n) => Ok(n.to_string_lossy().into_owned()),
+        None => fitserr!("not a gpubox file name: {}", path.display()),
+    }
+}
+
+impl<R: Read + Seek> GpuboxReader<R> {
+    /// Index a set of gpubox streams, given as pairs of file names, which
+    /// identify the coarse channels, and the streams themselves.
+    pub fn new(metafits: &MwaMetafits, files: Vec<(String, R)>) -> Result<Self, Error> {
+        let mut coarse_chans = metafits.coarse_chans.clone();
+        coarse_chans.sort();
+
+        if coarse_chans.is_empty() || metafits.n_fine_chans % coarse_chans.len() != 0 {
+            return fitserr!(
+                "MWA metafits lists {} fine channels in {} coarse channels",
+                metafits.n_fine_chans,
+                coarse_chans.len()
+            );
+        }
+
+        let n_fine = metafits.n_fine_chans / coarse_chans.len();
+        let baselines =
+            TriangularBaselines::new(metafits.tiles.len(), true, TriangleOrder::Ant2Fastest);
+        let n_baselines = baselines.n_baselines();
+        let vis_shape = [n_fine * GPUBOX_POLS.len() * 2, n_baselines];
+        let weights_shape = [GPUBOX_POLS.len(), n_baselines];
+        let int_ms = (metafits.int_time * 1000.).round() as i64;
+
+        let mut parsers = Vec::with_capacity(files.len());
+        let mut timesteps: BTreeMap<i64, Vec<Option<HduRef>>> = BTreeMap::new();
+
+        for (name, stream) in files {
+            let receiver = match parse_file_name(&name) {
+                Some((ChannelId::Receiver(n), _)) => n,
+                Some((ChannelId::Gpubox(n), _)) => {
+                    match metafits.coarse_chans.get((n as usize).wrapping_sub(1)) {
+                        Some(&r) => r,
+                        None => {
+                            return fitserr!("gpubox number of {} is out of range", name);
+                        }
+                    }
+                }
+                None => {
+                    return fitserr!("not a gpubox file name: {}", name);
+                }
+            };
+
+            let coarse = match coarse_chans.iter().position(|&c| c == receiver) {
+                Some(i) => i,
+                None => {
+                    return fitserr!(
+                        "{} contains coarse channel {}, which is not in the metafits",
+                        name,
+                        receiver
+                    );
+                }
+            };
+
+            let file = parsers.len();
+            let mut parser = FitsParser::new(stream)?;
+
+            for hdu in 1..parser.hdus().len() {
+                let (_, _, naxis) = parser.hdus()[hdu].shape();
+
+                if naxis == weights_shape {
+                    continue;
+                }
+
+                if naxis != vis_shape {
+                    return fitserr!(
+                        "HDU #{} of {} has shape {:?}, but expected {:?}",
+                        hdu,
+                        name,
+                        naxis,
+                        vis_shape
+                    );
+                }
+
+                match parser.hdus()[hdu].bitpix() {
+                    Bitpix::F32 | Bitpix::I32 => {}
+                    other => {
+                        return fitserr!(
+                            "HDU #{} of {} has unsupported BITPIX {:?}",
+                            hdu,
+                            name,
+                            other
+                        );
+                    }
+                }
+
+                let header = parser.read_header(hdu)?;
+                let time = match header.int("TIME")? {
+                    Some(t) => t,
+                    None => {
+                        return fitserr!("HDU #{} of {} has no TIME keyword", hdu, name);
+                    }
+                };
+                let start_ms = time * 1000 + header.int("MILLITIM")?.unwrap_or(0);
+
+                let slots = timesteps
+                    .entry(start_ms)
+                    .or_insert_with(|| vec![None; coarse_chans.len()]);
+
+                if slots[coarse].is_some() {
+                    return fitserr!(
+                        "coarse channel {} has more than one integration starting at {} ms",
+                        receiver,
+                        start_ms
+                    );
+                }
+
+                slots[coarse] = Some(HduRef {
+                    file: file,
+                    hdu: hdu,
+                });
+            }
+
+            parsers.push(parser);
+        }
+
+        // The regular time grid spanning the data, plus any integrations
+        // that fall off of it.
+        let mut grid: Vec<i64> = timesteps.keys().cloned().collect();
+
+        if let (Some(&first), Some(&last)) = (grid.first(), grid.last()) {
+            if int_ms > 0 {
+                let mut t = first;
+
+                while t < last {
+                    if !timesteps.contains_key(&t) {
+                        grid.push(t);
+                    }
+
+                    t += int_ms;
+                }
+
+                grid.sort();
+            }
+        }
+
+        Ok(GpuboxReader {
+            files: parsers,
+            coarse_chans: coarse_chans,
+            n_fine: n_fine,
+            baselines: baselines,
+            flagged_ants: metafits.tiles.iter().map(|t| t.flagged).collect(),
+            int_time: metafits.int_time,
+            timesteps: timesteps,
+            grid: grid,
+            next: 0,
+            fill_gaps: false,
+            n_skipped: 0,
+        })
+    }
+
+    /// Set whether integrations that are missing from every file are
+    /// delivered as fully flagged chunks (`true`) or skipped (`false`, the
+    /// default).
+    pub fn set_fill_gaps(&mut self, fill_gaps: bool) -> &mut Self {
+        self.fill_gaps = fill_gaps;
+        self
+    }
+
+    /// Get the receiver channel numbers of the coarse channels, in the order
+    /// in which they appear in each chunk.
+    pub fn coarse_chans(&self) -> &[u32] {
+        &self.coarse_chans
+    }
+
+    /// Get the number of channels in each chunk.
+    pub fn n_chan(&self) -> usize {
+        self.coarse_chans.len() * self.n_fine
+    }
+
+    /// Get the number of integrations on the time grid spanned by the
+    /// files, including any gaps.
+    pub fn n_timesteps(&self) -> usize {
+        self.grid.len()
+    }
+
+    /// Get the number of integrations that have been skipped because they
+    /// were missing from every file.
+    pub fn n_skipped(&self) -> usize {
+        self.n_skipped
+    }
+
+    fn read_chunk(&mut self, start_ms: i64) -> Result<VisChunk, Error> {
+        let n_chan = self.n_chan();
+        let n_pols = GPUBOX_POLS.len();
+        let time = MjdSeconds::from_unix_seconds(start_ms as f64 / 1000.)
+            .plus_seconds(Seconds(0.5 * self.int_time));
+        let mut chunk = VisChunk::new(time, Seconds(self.int_time), n_chan);
+
+        for pair in self.baselines.pairs() {
+            for &pol in &GPUBOX_POLS {
+                chunk.basepols.push(BasePol::new(pair.ant1, pair.ant2, pol));
+            }
+        }
+
+        let n_vis = chunk.basepols.len() * n_chan;
+        chunk.data = vec![Complex::new(0., 0.); n_vis];
+        chunk.flags = vec![true; n_vis];
+
+        let slots = match self.timesteps.get(&start_ms) {
+            Some(s) => s.clone(),
+            None => return Ok(chunk),
+        };
+
+        let row_len = self.n_fine * n_pols * 2;
+
+        for (coarse, slot) in slots.iter().enumerate() {
+            let r = match *slot {
+                Some(r) => r,
+                None => continue,
+            };
+
+            let parser = &mut self.files[r.file];
+            let hdu = parser.hdus[r.hdu].clone();
+            let bitpix = hdu.bitpix();
+            let mut raw = vec![0u8; row_len * self.baselines.n_baselines() * 4];
+            parser.inner.seek(SeekFrom::Start(hdu.data_offset()))?;
+            parser.inner.read_exact(&mut raw)?;
+
+            let value = |i: usize| -> f32 {
+                let b = &raw[4 * i..4 * i + 4];
+
+                match bitpix {
+                    Bitpix::I32 => BigEndian::read_i32(b) as f32,
+                    _ => BigEndian::read_f32(b),
+                }
+            };
+
+            for (bl, pair) in self.baselines.pairs().enumerate() {
+                let flagged =
+                    self.flagged_ants[pair.ant1 as usize] || self.flagged_ants[pair.ant2 as usize];
+
+                for fine in 0..self.n_fine {
+                    let chan = coarse * self.n_fine + fine;
+
+                    for p in 0..n_pols {
+                        let src = bl * row_len + (fine * n_pols + p) * 2;
+                        let dest = (bl * n_pols + p) * n_chan + chan;
+                        chunk.data[dest] = Complex::new(value(src), value(src + 1));
+                        chunk.flags[dest] = flagged;
+                    }
+                }
+            }
+        }
+
+        Ok(chunk)
+    }
+}
+
+impl<R: Read + Seek> LiveVisSource for GpuboxReader<R> {
+    fn next_chunk(&mut self) -> CoreResult<Option<VisChunk>> {
+        while self.next < self.grid.len() {
+            let start_ms = self.grid[self.next];
+            self.next += 1;
+
+            if self.fill_gaps || self.timesteps.contains_key(&start_ms) {
+                return Ok(Some(self.read_chunk(start_ms)?));
+            }
+
+            self.n_skipped += 1;
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn gpubox_assembly() {
+    use super::metafits::MwaTile;
+    use super::{format_card, format_string, Header};
+    use std::io::Cursor;
+
+    fn int(v: i64) -> String {
+        format!("{:>20}", v)
+    }
+
+    fn finish_block(buf: &mut Vec<u8>, fill: u8) {
+        while buf.len() % 2880 != 0 {
+            buf.push(fill);
+        }
+    }
+
+    // Write a gpubox file with integrations starting at each of the given
+    // Unix times, in milliseconds. Visibility values encode the time (to
+    // the half second), baseline, fine channel, and polarization.
+    fn gpubox(starts: &[i64], n_bl: usize, n_fine: usize) -> Cursor<Vec<u8>> {
+        let mut file = Vec::new();
+
+        for (kw, value) in &[
+            ("SIMPLE", format!("{:>20}", "T")),
+            ("BITPIX", int(8)),
+            ("NAXIS", int(0)),
+        ] {
+            file.extend_from_slice(&format_card(kw, value));
+        }
+
+        file.extend_from_slice(&format!("{:<80}", "END").into_bytes());
+        finish_block(&mut file, b' ');
+
+        for &start in starts {
+            for (kw, value) in &[
+                ("XTENSION", format_string("IMAGE")),
+                ("BITPIX", int(-32)),
+                ("NAXIS", int(2)),
+                ("NAXIS1", int(n_fine as i64 * 8)),
+                ("NAXIS2", int(n_bl as i64)),
+                ("PCOUNT", int(0)),
+                ("GCOUNT", int(1)),
+                ("TIME", int(start / 1000)),
+                ("MILLITIM", int(start % 1000)),
+            ] {
+                file.extend_from_slice(&format_card(kw, value));
+            }
+
+            file.extend_from_slice(&format!("{:<80}", "END").into_bytes());
+            finish_block(&mut file, b' ');
+
+            for bl in 0..n_bl {
+                for fine in 0..n_fine {
+                    for p in 0..4 {
+                        let re = (start % 10_000 / 500) as f32 * 1000.
+                            + (bl * 100 + fine * 10 + p) as f32;
+                        file.extend_from_slice(&re.to_bits().to_be_bytes());
+                        file.extend_from_slice(&(-re).to_bits().to_be_bytes());
+                    }
+                }
+            }
+
+            finish_block(&mut file, 0);
+        }
+
+        Cursor::new(file)
+    }
+
+    let tile = |antenna: usize, flagged: bool| MwaTile {
+        antenna: antenna,
+        tile_id: 11 + antenna as u32,
+        name: format!("Tile{:03}", 11 + antenna),
+        x_input: 2 * antenna,
+        y_input: 2 * antenna + 1,
+        receiver: 1,
+        enu: [0.; 3],
+        cable_length: 0.,
+        flagged: flagged,
+    };
+
+    let metafits = MwaMetafits {
+        header: Header {
+            records: Vec::new(),
+        },
+        obs_id: 1_065_880_128,
+        date_obs: "2013-10-15T13:48:32".to_owned(),
+        exposure: 2.,
+        int_time: 0.5,
+        fine_chan_width: 640e3,
+        n_fine_chans: 4,
+        bandwidth: 2.56e6,
+        center_freq: 140.16e6,
+        coarse_chans: vec![110, 109],
+        phase_center: (0., -27.),
+        tiles: vec![tile(0, false), tile(1, true)],
+    };
+
+    // Integrations start at 0, 500, and 1000 ms past the second; the middle
+    // one is missing entirely and channel 110 is missing from the last.
+    let t0 = 1_381_844_912_000;
+    let files = vec![
+        (
+            "1065880128_20131015134830_ch109_000.fits".to_owned(),
+            gpubox(&[t0, t0 + 1000], 3, 2),
+        ),
+        (
+            "1065880128_20131015134830_gpubox01_00.fits".to_owned(),
+            gpubox(&[t0], 3, 2),
+        ),
+    ];
+
+    let mut reader = GpuboxReader::new(&metafits, files).unwrap();
+    assert_eq!(reader.coarse_chans(), &[109, 110]);
+    assert_eq!(reader.n_chan(), 4);
+    assert_eq!(reader.n_timesteps(), 3);
+
+    let c0 = reader.next_chunk().unwrap().unwrap();
+    assert_eq!(c0.time, MjdSeconds::from_unix_seconds(1_381_844_912.25));
+    assert_eq!(c0.basepols.len(), 12);
+    assert_eq!(c0.basepols[5], BasePol::new(0, 1, VisPol::XY));
+
+    // Baseline 0-1 (the second), fine channel 1 of the first coarse
+    // channel, XY.
+    let base = (t0 % 10_000 / 500) as f32 * 1000.;
+    assert_eq!(c0.data_for(5)[1], Complex::new(base + 111., -(base + 111.)));
+    assert_eq!(c0.data_for(5)[3], Complex::new(base + 111., -(base + 111.)));
+    assert!(c0.flags_for(0).iter().all(|&f| !f));
+    assert!(c0.flags_for(5).iter().all(|&f| f));
+
+    let c2 = reader.next_chunk().unwrap().unwrap();
+    assert_eq!(c2.time, c0.time.plus_seconds(Seconds(1.)));
+    assert_eq!(c2.flags_for(0), &[false, false, true, true]);
+    assert!(reader.next_chunk().unwrap().is_none());
+    assert_eq!(reader.n_skipped(), 1);
+
+    let files = vec![(
+        "1065880128_20131015134830_ch110_000.fits".to_owned(),
+        gpubox(&[t0, t0 + 1000], 3, 2),
+    )];
+
+    let mut reader = GpuboxReader::new(&metafits, files).unwrap();
+    reader.set_fill_gaps(true);
+    let times: Vec<_> = (0..3)
+        .map(|_| reader.next_chunk().unwrap().unwrap())
+        .map(|c| (c.time.seconds_since(c0.time).0, c.flags.iter().all(|&f| f)))
+        .collect();
+    assert_eq!(times, vec![(0., false), (0.5, true), (1., false)]);
+    assert!(reader.next_chunk().unwrap().is_none());
+}
@@ -0,0 +1,830 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Spectral-line operations on FITS image cubes.
+
+The typical quality-assurance steps for a spectral-line image are to look at
+its moment maps and at the spectra of a few positions or regions. An
+`ImageCube` provides both. It reads the cube one channel plane at a time, so
+that only a few planes are ever held in memory no matter how many channels
+the cube has, and it can write the resulting maps out as FITS images that
+carry over the celestial coordinate system of the cube.
+
+The moments are the standard ones computed over a range of channels, where
+*I* is the pixel value and *v* is the spectral coordinate of the channel:
+
+- moment 0, the integrated intensity, is Σ *I* |Δ*v*|;
+- moment 1, the intensity-weighted mean coordinate, is Σ *I v* / Σ *I*;
+- moment 2, the intensity-weighted dispersion, is the square root of
+  Σ *I* (*v* − moment 1)² / Σ *I*.
+
+Blanked (NaN) pixels are ignored. The accumulation is done by a
+`MomentAccumulator`, which does not depend on the FITS format and can be fed
+planes from any source.
+
+*/
+
+use byteorder::{BigEndian, ByteOrder};
+use failure::Error;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::ops::Range;
+
+use super::beam::Pointing;
+use super::region::Region;
+use super::wcs::CelestialWcs;
+use super::{
+    format_card, format_string, parse_fixed_string, parse_free_float, Bitpix, FitsParser, HduKind,
+};
+
+/// Prefixes of the CTYPEn values that identify a spectral axis.
+const SPECTRAL_CTYPES: &[&str] = &["FREQ", "VELO", "VRAD", "VOPT", "FELO", "ZOPT", "WAVE"];
+
+/// Keywords that are copied from a cube to the two-dimensional images derived
+/// from it, in addition to the coordinate keywords of the first two axes.
+const COPIED_KEYWORDS: &[&str] = &[
+    "OBJECT", "TELESCOP", "INSTRUME", "OBSERVER", "DATE-OBS", "MJD-OBS", "EQUINOX", "EPOCH",
+    "RADESYS", "LONPOLE", "LATPOLE", "BMAJ", "BMIN", "BPA", "RESTFRQ", "RESTFREQ", "SPECSYS",
+    "OBSRA", "OBSDEC",
+];
+
+/// Axis keywords whose first- and second-axis versions are copied from a
+/// cube to the two-dimensional images derived from it.
+const COPIED_AXIS_KEYWORDS: &[&str] = &["CTYPE", "CRVAL", "CDELT", "CRPIX", "CUNIT", "CROTA"];
+
+/// The spectral axis of an image cube.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpectralAxis {
+    /// The axis type, from the `CTYPEn` keyword; for instance `FREQ`.
+    pub ctype: String,
+
+    /// The units of the axis, from the `CUNITn` keyword. Empty if the
+    /// keyword is missing.
+    pub cunit: String,
+
+    /// The coordinate value at the reference pixel.
+    pub crval: f64,
+
+    /// The coordinate increment per channel.
+    pub cdelt: f64,
+
+    /// The reference pixel. As always in FITS, this is 1-based.
+    pub crpix: f64,
+}
+
+impl SpectralAxis {
+    /// Get the coordinate value of the 0-based *channel*.
+    pub fn value(&self, channel: usize) -> f64 {
+        self.crval + (channel as f64 + 1. - self.crpix) * self.cdelt
+    }
+}
+
+/// Moment maps of an image cube. Each map is stored in FITS order: the first
+/// axis varies fastest.
+#[derive(Clone, Debug)]
+pub struct Moments {
+    /// The number of pixels along the first axis of the maps.
+    pub nx: usize,
+
+    /// The number of pixels along the second axis of the maps.
+    pub ny: usize,
+
+    /// The integrated intensity map.
+    pub moment0: Vec<f64>,
+
+    /// The intensity-weighted mean coordinate map.
+    pub moment1: Vec<f64>,
+
+    /// The intensity-weighted coordinate dispersion map.
+    pub moment2: Vec<f64>,
+}
+
+/// Accumulates moment maps one channel plane at a time.
+#[derive(Clone, Debug)]
+pub struct MomentAccumulator {
+    /// The spectral coordinate of the first plane. Coordinates are
+    /// accumulated relative to it, so that large values such as frequencies
+    /// do not cost precision in the dispersion.
+    origin: Option<f64>,
+    sum: Vec<f64>,
+    sum_v: Vec<f64>,
+    sum_v2: Vec<f64>,
+}
+
+impl MomentAccumulator {
+    /// Create an accumulator for planes of *n_pixels* pixels.
+    pub fn new(n_pixels: usize) -> Self {
+        MomentAccumulator {
+            origin: None,
+            sum: vec![0.; n_pixels],
+            sum_v: vec![0.; n_pixels],
+            sum_v2: vec![0.; n_pixels],
+        }
+    }
+
+    /// Add a plane whose spectral coordinate is *value*. NaN pixels are
+    /// ignored.
+    ///
+    /// Panics if *plane* does not have the size that this accumulator was
+    /// created with.
+    pub fn add_plane(&mut self, value: f64, plane: &[f64]) {
+        assert_eq!(plane.len(), self.sum.len());
+
+        let origin = *self.origin.get_or_insert(value);
+        let v = value - origin;
+
+        for (i, &p) in plane.iter().enumerate() {
+            if p.is_nan() {
+                continue;
+            }
+
+            self.sum[i] += p;
+            self.sum_v[i] += p * v;
+            self.sum_v2[i] += p * v * v;
+        }
+    }
+
+    /// Compute the moment maps, given the channel width *delta*. Pixels with
+    /// no intensity have NaN moment-1 and moment-2 values.
+    pub fn finish(self, nx: usize, ny: usize, delta: f64) -> Moments {
+        let origin = self.origin.unwrap_or(0.);
+        let n = self.sum.len();
+        let mut moment0 = Vec::with_capacity(n);
+        let mut moment1 = Vec::with_capacity(n);
+        let mut moment2 = Vec::with_capacity(n);
+
+        for i in 0..n {
+            let s = self.sum[i];
+            moment0.push(s * delta.abs());
+
+            if s == 0. {
+                moment1.push(std::f64::NAN);
+                moment2.push(std::f64::NAN);
+            } else {
+                let mean = self.sum_v[i] / s;
+                let var = self.sum_v2[i] / s - mean * mean;
+                moment1.push(origin + mean);
+                moment2.push(var.max(0.).sqrt());
+            }
+        }
+
+        Moments {
+            nx: nx,
+            ny: ny,
+            moment0: moment0,
+            moment1: moment1,
+            moment2: moment2,
+        }
+    }
+}
+
+/// Read access to an image, a block of rows of one channel plane at a time.
+///
+/// Analysis tools are written against this trait so that they do not depend
+/// on how the image is stored. Its methods have the same meanings as the
+/// methods of `ImageCube` with the same names.
+pub trait ImageAccess {
+    /// Get the shape of the image: `(nx, ny, n_chan)`.
+    fn shape(&self) -> (usize, usize, usize);
+
+    /// Get the units of the pixel values. Empty if they are unknown.
+    fn bunit(&self) -> &str;
+
+    /// Get the celestial coordinate system of the spatial axes.
+    fn celestial_wcs(&self) -> Result<CelestialWcs, Error>;
+
+    /// Get the frequency of the 0-based *channel*, in Hz.
+    fn frequency(&self, channel: usize) -> Result<f64, Error>;
+
+    /// Read rows *rows* of plane *channel* into *buf*, which must have `nx`
+    /// elements for each row. Blanked pixels are read as NaN.
+    fn read_rows(
+        &mut self,
+        channel: usize,
+        rows: Range<usize>,
+        buf: &mut [f64],
+    ) -> Result<(), Error>;
+}
+
+/// A spectral-line image cube stored in a FITS image HDU.
+///
+/// The first two axes of the image are the spatial axes. The spectral axis
+/// is the one among the rest whose `CTYPEn` identifies it as such, or the
+/// third axis if none does. All other axes, such as a Stokes axis, must have
+/// length 1.
+pub struct ImageCube<R: Read + Seek> {
+    inner: R,
+    records: Vec<u8>,
+    data_offset: u64,
+    bitpix: Bitpix,
+    nx: usize,
+    ny: usize,
+    n_chan: usize,
+    bscale: f64,
+    bzero: f64,
+    blank: Option<i64>,
+    bunit: String,
+    spectral_axis: SpectralAxis,
+}
+
+impl<R: Read + Seek> ImageCube<R> {
+    /// Open the image in HDU number *hdu_num* of the file parsed by
+    /// *parser*.
+    pub fn open(parser: FitsParser<R>, hdu_num: usize) -> Result<Self, Error> {
+        ImageCube::open_impl(parser, hdu_num, 3)
+    }
+
+    /// Open the image in HDU number *hdu_num* of the file parsed by
+    /// *parser*, allowing it to be a two-dimensional image. Such an image is
+    /// treated as a cube with a single channel and a default spectral axis.
+    pub fn open_image(parser: FitsParser<R>, hdu_num: usize) -> Result<Self, Error> {
+        ImageCube::open_impl(parser, hdu_num, 2)
+    }
+
+    fn open_impl(parser: FitsParser<R>, hdu_num: usize, min_axes: usize) -> Result<Self, Error> {
+        let hdu = match parser.hdus().get(hdu_num) {
+            Some(h) => h.clone(),
+            None => {
+                return fitserr!("no such FITS HDU #{}", hdu_num);
+            }
+        };
+
+        if hdu.kind != HduKind::PrimaryArray && hdu.kind != HduKind::ImageExtension {
+            return fitserr!("FITS HDU #{} is not an image", hdu_num);
+        }
+
+        let naxis = hdu.naxis.clone();
+
+        if naxis.len() < min_axes {
+            return fitserr!(
+                "FITS image in HDU #{} has {} axes; a cube needs at least {}",
+                hdu_num,
+                naxis.len(),
+                min_axes
+            );
+        }
+
+        let mut inner = parser.into_inner();
+        inner.seek(SeekFrom::Start(hdu.header_offset))?;
+        let mut records = vec![0u8; hdu.n_header_records * 80];
+        inner.read_exact(&mut records)?;
+
+        let mut cube = ImageCube {
+            inner: inner,
+            records: records,
+            data_offset: hdu.data_offset(),
+            bitpix: hdu.bitpix,
+            nx: naxis[0],
+            ny: naxis[1],
+            n_chan: 1,
+            bscale: 1.,
+            bzero: 0.,
+            blank: None,
+            bunit: String::new(),
+            spectral_axis: SpectralAxis {
+                ctype: String::new(),
+                cunit: String::new(),
+                crval: 0.,
+                cdelt: 1.,
+                crpix: 1.,
+            },
+        };
+
+        cube.bscale = cube.float_keyword("BSCALE")?.unwrap_or(1.);
+        cube.bzero = cube.float_keyword("BZERO")?.unwrap_or(0.);
+        cube.blank = cube.float_keyword("BLANK")?.map(|b| b as i64);
+        cube.bunit = cube.string_keyword("BUNIT")?.unwrap_or_default();
+
+        if naxis.len() == 2 {
+            return Ok(cube);
+        }
+
+        let mut spec_axis = None;
+
+        for i in 2..naxis.len() {
+            let ctype = cube
+                .string_keyword(&format!("CTYPE{}", i + 1))?
+                .unwrap_or_default();
+
+            if SPECTRAL_CTYPES.iter().any(|p| ctype.starts_with(p)) {
+                spec_axis = Some(i);
+                break;
+            }
+        }
+
+        let spec_axis = spec_axis.unwrap_or(2);
+
+        for (i, &n) in naxis.iter().enumerate().skip(2) {
+            if i != spec_axis && n != 1 {
+                return fitserr!(
+                    "FITS image in HDU #{} has non-degenerate axis {} besides its spectral axis",
+                    hdu_num,
+                    i + 1
+                );
+            }
+        }
+
+        let n = spec_axis + 1;
+        cube.n_chan = naxis[spec_axis];
+        cube.spectral_axis = SpectralAxis {
+            ctype: cube
+                .string_keyword(&format!("CTYPE{}", n))?
+                .unwrap_or_default(),
+            cunit: cube
+                .string_keyword(&format!("CUNIT{}", n))?
+                .unwrap_or_default(),
+            crval: cube.float_keyword(&format!("CRVAL{}", n))?.unwrap_or(0.),
+            cdelt: cube.float_keyword(&format!("CDELT{}", n))?.unwrap_or(1.),
+            crpix: cube.float_keyword(&format!("CRPIX{}", n))?.unwrap_or(1.),
+        };
+
+        Ok(cube)
+    }
+
+    /// Get the shape of the cube: `(nx, ny, n_chan)`.
+    pub fn shape(&self) -> (usize, usize, usize) {
+        (self.nx, self.ny, self.n_chan)
+    }
+
+    /// Get the spectral axis of the cube.
+    pub fn spectral_axis(&self) -> &SpectralAxis {
+        &self.spectral_axis
+    }
+
+    /// Get the units of the pixel values, from the `BUNIT` keyword. Empty if
+    /// the keyword is missing.
+    pub fn bunit(&self) -> &str {
+        &self.bunit
+    }
+
+    /// Read channel plane *channel* into *plane*, which must have `nx * ny`
+    /// elements. Blanked pixels are read as NaN.
+    pub fn read_plane(&mut self, channel: usize, plane: &mut [f64]) -> Result<(), Error> {
+        self.read_rows(channel, 0..self.ny, plane)
+    }
+
+    /// Read rows *rows* of plane *channel* into *buf*, which must have
+    /// `nx` elements for each row. Blanked pixels are read as NaN.
+    pub fn read_rows(
+        &mut self,
+        channel: usize,
+        rows: Range<usize>,
+        buf: &mut [f64],
+    ) -> Result<(), Error> {
+        if channel >= self.n_chan {
+            return fitserr!(
+                "channel {} is out of range for a cube with {} channels",
+                channel,
+                self.n_chan
+            );
+        }
+
+        if rows.start > rows.end || rows.end > self.ny {
+            return fitserr!(
+                "invalid row range {}..{} for a cube with {} rows",
+                rows.start,
+                rows.end,
+                self.ny
+            );
+        }
+
+        let n_values = (rows.end - rows.start) * self.nx;
+        assert_eq!(buf.len(), n_values);
+
+        let item_size = self.bitpix.n_bytes();
+        let first = (channel * self.ny + rows.start) * self.nx;
+        self.inner.seek(SeekFrom::Start(
+            self.data_offset + (first * item_size) as u64,
+        ))?;
+
+        let mut raw = vec![0u8; n_values * item_size];
+        self.inner.read_exact(&mut raw)?;
+
+        for (i, v) in buf.iter_mut().enumerate() {
+            let b = &raw[i * item_size..];
+
+            let value = match self.bitpix {
+                Bitpix::U8 => self.scale_int(i64::from(b[0])),
+                Bitpix::I16 => self.scale_int(i64::from(BigEndian::read_i16(b))),
+                Bitpix::I32 => self.scale_int(i64::from(BigEndian::read_i32(b))),
+                Bitpix::I64 => self.scale_int(BigEndian::read_i64(b)),
+                Bitpix::F32 => self.scale_float(f64::from(BigEndian::read_f32(b))),
+                Bitpix::F64 => self.scale_float(BigEndian::read_f64(b)),
+            };
+
+            *v = value;
+        }
+
+        Ok(())
+    }
+
+    /// Compute the moment maps over the channels in *channels*.
+    pub fn moments(&mut self, channels: Range<usize>) -> Result<Moments, Error> {
+        self.check_channels(&channels)?;
+
+        let mut acc = MomentAccumulator::new(self.nx * self.ny);
+        let mut plane = vec![0.; self.nx * self.ny];
+
+        for chan in channels {
+            self.read_plane(chan, &mut plane)?;
+            acc.add_plane(self.spectral_axis.value(chan), &plane);
+        }
+
+        Ok(acc.finish(self.nx, self.ny, self.spectral_axis.cdelt))
+    }
+
+    /// Get the units of moment map *order*, which must be 0, 1, or 2.
+    pub fn moment_unit(&self, order: usize) -> String {
+        let cunit = &self.spectral_axis.cunit;
+
+        match order {
+            0 if self.bunit.is_empty() => cunit.clone(),
+            0 if cunit.is_empty() => self.bunit.clone(),
+            0 => format!("{}.{}", self.bunit, cunit),
+            1 | 2 => cunit.clone(),
+            _ => panic!("unsupported moment order {}", order),
+        }
+    }
+
+    /// Extract the spectrum of the pixel at *x*, *y* (0-based).
+    pub fn pixel_spectrum(&mut self, x: usize, y: usize) -> Result<Vec<f64>, Error> {
+        self.spectrum(&[(x, y)])
+    }
+
+    /// Extract the mean spectrum of the region made up of *pixels*, given
+    /// as 0-based `(x, y)` pairs. Blanked pixels are left out of the mean;
+    /// channels in which every pixel is blanked are NaN.
+    ///
+    /// Only the rows of each plane that the region touches are read.
+    pub fn spectrum(&mut self, pixels: &[(usize, usize)]) -> Result<Vec<f64>, Error> {
+        if pixels.is_empty() {
+            return fitserr!("cannot extract the spectrum of an empty region");
+        }
+
+        for &(x, y) in pixels {
+            if x >= self.nx || y >= self.ny {
+                return fitserr!(
+                    "pixel ({}, {}) is outside of the {}×{} image",
+                    x,
+                    y,
+                    self.nx,
+                    self.ny
+                );
+            }
+        }
+
+        let y0 = pixels.iter().map(|p| p.1).min().unwrap();
+        let y1 = pixels.iter().map(|p| p.1).max().unwrap() + 1;
+        let mut rows = vec![0.; (y1 - y0) * self.nx];
+        let mut spectrum = Vec::with_capacity(self.n_chan);
+
+        for chan in 0..self.n_chan {
+            self.read_rows(chan, y0..y1, &mut rows)?;
+
+            let mut sum = 0.;
+            let mut n = 0;
+
+            for &(x, y) in pixels {
+                let v = rows[(y - y0) * self.nx + x];
+
+                if !v.is_nan() {
+                    sum += v;
+                    n += 1;
+                }
+            }
+
+            spectrum.push(if n == 0 {
+                std::f64::NAN
+            } else {
+                sum / n as f64
+            });
+        }
+
+        Ok(spectrum)
+    }
+
+    /// Extract the mean spectrum of *region*. See `spectrum`.
+    pub fn region_spectrum(&mut self, region: &Region) -> Result<Vec<f64>, Error> {
+        let wcs = self.celestial_wcs().ok();
+        let pixels = region.pixels(wcs.as_ref(), self.nx, self.ny)?;
+        self.spectrum(&pixels)
+    }
+
+    /// Get the celestial coordinate system of the spatial axes of the cube.
+    pub fn celestial_wcs(&self) -> Result<CelestialWcs, Error> {
+        let ctype1 = self.string_keyword("CTYPE1")?.unwrap_or_default();
+        CelestialWcs::from_keywords(&ctype1, |k| self.float_keyword(k))
+    }
+
+    /// Get the pointing of the observation at the 0-based *channel*, for
+    /// use with a primary beam model.
+    ///
+    /// The pointing center is given by the `OBSRA` and `OBSDEC` keywords,
+    /// falling back to the reference point of the celestial coordinate
+    /// system. The spectral axis must be a frequency axis.
+    pub fn pointing(&self, channel: usize) -> Result<Pointing, Error> {
+        let center = match (self.float_keyword("OBSRA")?, self.float_keyword("OBSDEC")?) {
+            (Some(ra), Some(dec)) => (ra, dec),
+            _ => self.celestial_wcs()?.reference(),
+        };
+
+        Ok(Pointing::new(center, self.frequency(channel)?))
+    }
+
+    /// Get the frequency of the 0-based *channel*, in Hz. The spectral axis
+    /// must be a frequency axis.
+    pub fn frequency(&self, channel: usize) -> Result<f64, Error> {
+        if !self.spectral_axis.ctype.starts_with("FREQ") {
+            return fitserr!(
+                "the spectral axis of the cube is {:?}, not frequency",
+                self.spectral_axis.ctype
+            );
+        }
+
+        let scale = match self.spectral_axis.cunit.as_ref() {
+            "" | "Hz" => 1.,
+            "kHz" => 1e3,
+            "MHz" => 1e6,
+            "GHz" => 1e9,
+            other => {
+                return fitserr!("unsupported frequency unit {:?}", other);
+            }
+        };
+
+        Ok(self.spectral_axis.value(channel) * scale)
+    }
+
+    /// Write a two-dimensional image derived from this cube, such as a moment
+    /// map, to *dest* as a FITS file.
+    ///
+    /// The image is written as 64-bit floats, with the celestial coordinate
+    /// system and general observation keywords of the cube and the given
+    /// *bunit*. *data* must have `nx * ny` elements in FITS order.
+    pub fn write_image<W: Write>(&self, dest: W, data: &[f64], bunit: &str) -> Result<(), Error> {
+        if data.len() != self.nx * self.ny {
+            return fitserr!(
+                "image has {} pixels but the cube planes have {}",
+                data.len(),
+                self.nx * self.ny
+            );
+        }
+
+        let mut cards = Vec::new();
+
+        for record in self.records.chunks(80) {
+            let keyword = String::from_utf8_lossy(&record[..8]);
+            let keyword = keyword.trim_end();
+
+            if is_copied_keyword(keyword) {
+                cards.extend_from_slice(record);
+            }
+        }
+
+        if !bunit.is_empty() {
+            cards.extend_from_slice(&format_card("BUNIT", &format_string(bunit)));
+        }
+
+        write_f64_image(dest, &[self.nx, self.ny], &cards, data)
+    }
+
+    fn check_channels(&self, channels: &Range<usize>) -> Result<(), Error> {
+        if channels.start >= channels.end || channels.end > self.n_chan {
+            return fitserr!(
+                "invalid channel range {}..{} for a cube with {} channels",
+                channels.start,
+                channels.end,
+                self.n_chan
+            );
+        }
+
+        Ok(())
+    }
+
+    fn scale_int(&self, raw: i64) -> f64 {
+        if self.blank == Some(raw) {
+            std::f64::NAN
+        } else {
+            self.scale_float(raw as f64)
+        }
+    }
+
+    fn scale_float(&self, raw: f64) -> f64 {
+        self.bzero + self.bscale * raw
+    }
+
+    fn find_record(&self, keyword: &str) -> Option<&[u8]> {
+        self.records
+            .chunks(80)
+            .find(|r| r[8] == b'=' && String::from_utf8_lossy(&r[..8]).trim_end() == keyword)
+    }
+
+    fn string_keyword(&self, keyword: &str) -> Result<Option<String>, Error> {
+        match self.find_record(keyword) {
+            Some(r) => Ok(Some(parse_fixed_string(r)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn float_keyword(&self, keyword: &str) -> Result<Option<f64>, Error> {
+        match self.find_record(keyword) {
+            Some(r) => Ok(Some(parse_free_float(r)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: Read + Seek> ImageAccess for ImageCube<R> {
+    fn shape(&self) -> (usize, usize, usize) {
+        ImageCube::shape(self)
+    }
+
+    fn bunit(&self) -> &str {
+        ImageCube::bunit(self)
+    }
+
+    fn celestial_wcs(&self) -> Result<CelestialWcs, Error> {
+        ImageCube::celestial_wcs(self)
+    }
+
+    fn frequency(&self, channel: usize) -> Result<f64, Error> {
+        ImageCube::frequency(self, channel)
+    }
+
+    fn read_rows(
+        &mut self,
+        channel: usize,
+        rows: Range<usize>,
+        buf: &mut [f64],
+    ) -> Result<(), Error> {
+        ImageCube::read_rows(self, channel, rows, buf)
+    }
+}
+
+fn is_copied_keyword(keyword: &str) -> bool {
+    if COPIED_KEYWORDS.contains(&keyword) {
+        return true;
+    }
+
+    for prefix in COPIED_AXIS_KEYWORDS {
+        if keyword.starts_with(prefix) {
+            let suffix = &keyword[prefix.len()..];
+            return suffix == "1" || suffix == "2";
+        }
+    }
+
+    // Linear transformation matrix elements such as PC1_2 or CD2_1.
+    if keyword.starts_with("PC") || keyword.starts_with("CD") {
+        let indices = &keyword[2..];
+        return indices == "1_1" || indices == "1_2" || indices == "2_1" || indices == "2_2";
+    }
+
+    false
+}
+
+/// Writes a single-HDU FITS file containing an array of 64-bit floats, a
+/// chunk of values at a time.
+///
+/// This allows large images to be written without holding all of their
+/// pixels in memory. The values are given in FITS order, the first axis
+/// varying fastest.
+pub struct ImageWriter<W: Write> {
+    dest: W,
+    n_remaining: usize,
+    n_written: usize,
+}
+
+impl<W: Write> ImageWriter<W> {
+    /// Start writing an array of shape *naxis* to *dest*. *cards* are extra
+    /// 80-byte header records to include.
+    pub fn new(mut dest: W, naxis: &[usize], cards: &[u8]) -> Result<Self, Error> {
+        if cards.len() % 80 != 0 {
+            return fitserr!("FITS header records must be 80 bytes long");
+        }
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&format_card("SIMPLE", &format!("{:>20}", "T")));
+        header.extend_from_slice(&format_card("BITPIX", &format!("{:>20}", -64)));
+        header.extend_from_slice(&format_card("NAXIS", &format!("{:>20}", naxis.len())));
+
+        for (i, n) in naxis.iter().enumerate() {
+            header.extend_from_slice(&format_card(
+                &format!("NAXIS{}", i + 1),
+                &format!("{:>20}", n),
+            ));
+        }
+
+        header.extend_from_slice(cards);
+        header.extend_from_slice(super::END_MARKER);
+
+        while header.len() % 2880 != 0 {
+            header.push(b' ');
+        }
+
+        dest.write_all(&header)?;
+
+        Ok(ImageWriter {
+            dest: dest,
+            n_remaining: naxis.iter().product(),
+            n_written: 0,
+        })
+    }
+
+    /// Write the next chunk of values.
+    pub fn write(&mut self, data: &[f64]) -> Result<(), Error> {
+        if data.len() > self.n_remaining {
+            return fitserr!(
+                "tried to write {} values to a FITS image with room for only {} more",
+                data.len(),
+                self.n_remaining
+            );
+        }
+
+        let mut buf = vec![0u8; data.len() * 8];
+        BigEndian::write_f64_into(data, &mut buf);
+        self.dest.write_all(&buf)?;
+        self.n_remaining -= data.len();
+        self.n_written += data.len();
+        Ok(())
+    }
+
+    /// Finish writing the file, returning the underlying stream. All of the
+    /// values of the array must have been written.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if self.n_remaining != 0 {
+            return fitserr!(
+                "FITS image was closed with {} values left unwritten",
+                self.n_remaining
+            );
+        }
+
+        let padding = (2880 - (self.n_written * 8) % 2880) % 2880;
+        self.dest.write_all(&vec![0u8; padding])?;
+        Ok(self.dest)
+    }
+}
+
+/// Write a single-HDU FITS file containing *data* as a 64-bit floating-point
+/// array of shape *naxis*. *cards* are extra header records to include.
+fn write_f64_image<W: Write>(
+    dest: W,
+    naxis: &[usize],
+    cards: &[u8],
+    data: &[f64],
+) -> Result<(), Error> {
+    let mut writer = ImageWriter::new(dest, naxis, cards)?;
+    writer.write(data)?;
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn cube_operations() {
+    use std::io::Cursor;
+
+    // A 2×1 cube with 3 channels. The first pixel has a Gaussian-ish line
+    // centered on the middle channel; the second is blanked in channel 0.
+    let mut cards = Vec::new();
+    cards.extend_from_slice(&format_card("CTYPE1", &format_string("RA---SIN")));
+    cards.extend_from_slice(&format_card("CTYPE3", &format_string("VRAD")));
+    cards.extend_from_slice(&format_card("CUNIT3", &format_string("km/s")));
+    cards.extend_from_slice(&format_card("CRVAL3", &format!("{:>20}", "1.0D2")));
+    cards.extend_from_slice(&format_card("CDELT3", &format!("{:>20}", "-2.0")));
+    cards.extend_from_slice(&format_card("CRPIX3", &format!("{:>20}", "1.0")));
+    cards.extend_from_slice(&format_card("BUNIT", &format_string("Jy/beam")));
+
+    let nan = std::f64::NAN;
+    let data = [1., nan, 2., 4., 1., 4.];
+    let mut file = Vec::new();
+    write_f64_image(&mut file, &[2, 1, 3, 1], &cards, &data).unwrap();
+
+    let parser = FitsParser::new(Cursor::new(file)).unwrap();
+    let mut cube = ImageCube::open(parser, 0).unwrap();
+    assert_eq!(cube.shape(), (2, 1, 3));
+    assert_eq!(cube.spectral_axis().value(2), 96.);
+    assert_eq!(cube.moment_unit(0), "Jy/beam.km/s");
+    assert!(cube.pointing(0).is_err());
+
+    let m = cube.moments(0..3).unwrap();
+    assert_eq!(m.moment0, vec![8., 16.]);
+    assert_eq!(m.moment1[0], 98.);
+    assert!((m.moment2[0] - 2f64.sqrt()).abs() < 1e-12);
+    assert_eq!(m.moment1[1], 97.);
+
+    assert_eq!(cube.pixel_spectrum(0, 0).unwrap(), vec![1., 2., 1.]);
+    let s = cube.spectrum(&[(0, 0), (1, 0)]).unwrap();
+    assert_eq!(s, vec![1., 3., 2.5]);
+    assert!(cube.spectrum(&[(2, 0)]).is_err());
+    assert!(cube.moments(2..4).is_err());
+
+    let mut out = Vec::new();
+    cube.write_image(&mut out, &m.moment0, &cube.moment_unit(0))
+        .unwrap();
+    let parser = FitsParser::new(Cursor::new(out)).unwrap();
+    assert_eq!(parser.hdus()[0].shape().2, &[2, 1]);
+
+    // The output is a plain image, not a cube.
+    assert!(ImageCube::open(parser, 0).is_err());
+}
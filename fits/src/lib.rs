@@ -17,7 +17,7 @@ extern crate failure;
 #[macro_use]
 extern crate failure_derive;
 extern crate rubbl_core;
-//extern crate rubbl_visdata;
+extern crate rubbl_visdata;
 
 use failure::Error;
 use rubbl_core::io::EofReadExactExt;
@@ -32,6 +32,16 @@ macro_rules! fitserr {
     }
 }
 
+pub mod beam;
+pub mod bintable;
+pub mod gpubox;
+pub mod image;
+pub mod metafits;
+pub mod mosaic;
+pub mod region;
+pub mod spindex;
+pub mod wcs;
+
 /// An error type for when a FITS file is malformed.
 #[derive(Debug, Fail)]
 #[fail(display = "{}", _0)]
@@ -288,18 +298,15 @@ impl<R: Read> FitsDecoder<R> {
 
                 self.gcount = n as usize;
             } else if record == END_MARKER {
-                let group_size = if self.hdu_num == 0 && self.primary_seen_groups {
-                    self.pcount + self.naxis.iter().skip(1).fold(1, |p, n| p * n) as isize
+                let axes = if self.hdu_num == 0 && self.primary_seen_groups && self.naxis.len() > 0
+                {
+                    &self.naxis[1..]
                 } else {
-                    self.pcount + self.naxis.iter().fold(1, |p, n| p * n) as isize
+                    &self.naxis[..]
                 };
 
-                if group_size < 0 {
-                    return fitserr!("illegal negative FITS group size");
-                }
-
                 self.offset = 2880;
-                self.data_remaining = self.bitpix.n_bytes() * self.gcount * group_size as usize;
+                self.data_remaining = hdu_data_size(self.bitpix, self.pcount, self.gcount, axes)?;
 
                 if self.data_remaining != 0 {
                     self.state = DecoderState::Data;
@@ -555,28 +562,23 @@ impl<R: Read + Seek> FitsParser<R> {
 
             // OK, we're at the END record.
 
+            // EXTNAME is optional, and some writers (such as the MWA
+            // correlator) leave it out.
             let extname = if hdus.len() == 0 {
                 "".to_owned()
             } else {
-                match extname {
-                    Some(s) => s,
-                    None => {
-                        return fitserr!("illegal extension HDU without EXTNAME header");
-                    }
-                }
+                extname.unwrap_or_default()
             };
 
             if seen_groups && hdus.len() == 0 {
-                naxis.remove(0); // dummy 0 value when primary HDU is random-groups
-            }
-
-            let group_size = pcount + naxis.iter().fold(1, |p, n| p * n) as isize;
+                if naxis.len() == 0 {
+                    return fitserr!("illegal random-groups primary HDU with NAXIS = 0");
+                }
 
-            if group_size < 0 {
-                return fitserr!("illegal negative FITS group size");
+                naxis.remove(0); // dummy 0 value when primary HDU is random-groups
             }
 
-            let data_size = bitpix.n_bytes() * gcount * group_size as usize;
+            let data_size = hdu_data_size(bitpix, pcount, gcount, &naxis)?;
 
             if hdus.len() == 0 {
                 kind = if data_size == 0 {
@@ -602,12 +604,20 @@ impl<R: Read + Seek> FitsParser<R> {
             // If there's more stuff in the file, skip up to the next HDU
             // beginning (or maaaybe "special records").
 
-            hdu_header_offset = cur_offset + (((data_size + 2879) / 2880) * 2880) as u64;
+            let n_data_records = (data_size / 2880 + (data_size % 2880 != 0) as usize) as u64;
+            hdu_header_offset = cur_offset + n_data_records * 2880;
 
             if hdu_header_offset == file_size {
                 break;
             }
 
+            if hdu_header_offset > file_size {
+                return fitserr!(
+                    "FITS file is truncated: HDU #{} data extend past end of file",
+                    hdus.len() - 1
+                );
+            }
+
             inner.seek(SeekFrom::Start(hdu_header_offset))?;
         }
 
@@ -623,16 +633,100 @@ impl<R: Read + Seek> FitsParser<R> {
         &self.hdus[..]
     }
 
+    /// Read the header records of HDU number *hdu_num*.
+    pub fn read_header(&mut self, hdu_num: usize) -> Result<Header, Error> {
+        let hdu = match self.hdus.get(hdu_num) {
+            Some(h) => h,
+            None => {
+                return fitserr!("no such FITS HDU #{}", hdu_num);
+            }
+        };
+
+        self.inner.seek(SeekFrom::Start(hdu.header_offset))?;
+        let mut records = vec![0u8; hdu.n_header_records * 80];
+        self.inner.read_exact(&mut records)?;
+        Ok(Header { records: records })
+    }
+
     /// Consume this parser and return the inner stream.
     pub fn into_inner(self) -> R {
         self.inner
     }
 }
 
+/// The header records of an HDU, for looking up keyword values.
+#[derive(Clone, Debug)]
+pub struct Header {
+    records: Vec<u8>,
+}
+
+impl Header {
+    /// Get the raw 80-byte records.
+    pub fn records(&self) -> &[u8] {
+        &self.records
+    }
+
+    /// Get the value of a string-valued keyword, if it is present.
+    ///
+    /// Strings continued over several records with the `CONTINUE`
+    /// convention are joined up.
+    pub fn string(&self, keyword: &str) -> Result<Option<String>, Error> {
+        let index = match self.find_index(keyword) {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+
+        let mut value = parse_fixed_string(&self.records[index * 80..(index + 1) * 80])?;
+
+        for record in self.records[(index + 1) * 80..].chunks(80) {
+            if !value.ends_with('&') || &record[..8] != b"CONTINUE" {
+                break;
+            }
+
+            // Reuse the fixed-format parser by making the record look like a
+            // normal keyword record.
+            let mut fake = [b' '; 80];
+            fake[8] = b'=';
+            fake[10..].copy_from_slice(&record[10..]);
+            value.pop();
+            value.push_str(&parse_fixed_string(&fake)?);
+        }
+
+        Ok(Some(value))
+    }
+
+    /// Get the value of a numeric keyword, if it is present.
+    pub fn float(&self, keyword: &str) -> Result<Option<f64>, Error> {
+        match self.find_index(keyword) {
+            Some(i) => Ok(Some(parse_free_float(&self.records[i * 80..(i + 1) * 80])?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the value of an integer keyword, if it is present.
+    pub fn int(&self, keyword: &str) -> Result<Option<i64>, Error> {
+        match self.float(keyword)? {
+            Some(v) if v.fract() == 0. && v.abs() < 9.0e15 => Ok(Some(v as i64)),
+            Some(v) => fitserr!(
+                "expected an integer for FITS keyword {} but got {}",
+                keyword,
+                v
+            ),
+            None => Ok(None),
+        }
+    }
+
+    fn find_index(&self, keyword: &str) -> Option<usize> {
+        self.records
+            .chunks(80)
+            .position(|r| r[8] == b'=' && String::from_utf8_lossy(&r[..8]).trim_end() == keyword)
+    }
+}
+
 impl ParsedHdu {
     /// Get the "name" of this HDU. If this is an extension HDU, this is the
-    /// value of the EXTNAME header keyword. For the primary HDU, it is an
-    /// empty string.
+    /// value of the EXTNAME header keyword, or an empty string if there is
+    /// none. For the primary HDU, it is an empty string.
     pub fn extname(&self) -> &str {
         &self.name
     }
@@ -652,6 +746,13 @@ impl ParsedHdu {
     pub fn shape(&self) -> (usize, isize, &[usize]) {
         (self.gcount, self.pcount, &self.naxis[..])
     }
+
+    /// Get the file offset at which this HDU's data start: the first
+    /// 2880-byte block after the END record of its headers.
+    fn data_offset(&self) -> u64 {
+        let header_size = (self.n_header_records as u64 + 1) * 80;
+        self.header_offset + (header_size + 2879) / 2880 * 2880
+    }
 }
 
 fn parse_fixed_int(record: &[u8]) -> Result<isize, Error> {
@@ -686,53 +787,40 @@ fn parse_fixed_int(record: &[u8]) -> Result<isize, Error> {
         return fitserr!("empty record that should have been a fixed-format integer");
     }
 
-    let mut value = 0;
+    let mut value: isize = 0;
 
     while i < 30 {
-        value *= 10;
-
-        match record[i] {
-            b'0' => {}
-            b'1' => {
-                value += 1;
-            }
-            b'2' => {
-                value += 2;
-            }
-            b'3' => {
-                value += 3;
-            }
-            b'4' => {
-                value += 4;
-            }
-            b'5' => {
-                value += 5;
-            }
-            b'6' => {
-                value += 6;
-            }
-            b'7' => {
-                value += 7;
-            }
-            b'8' => {
-                value += 8;
-            }
-            b'9' => {
-                value += 9;
-            }
+        let digit = match record[i] {
+            c @ b'0'..=b'9' => (c - b'0') as isize,
             other => {
                 return fitserr!(
                     "expected digit but got ASCII {:?} in fixed-format integer",
                     other
                 );
             }
-        }
+        };
+
+        // Accumulate negative values so that the most negative integer can
+        // be represented, and so that oversized values are caught rather than
+        // overflowing.
+
+        value = match value.checked_mul(10).and_then(|v| v.checked_sub(digit)) {
+            Some(v) => v,
+            None => {
+                return fitserr!("fixed-format integer is too large to be represented");
+            }
+        };
 
         i += 1;
     }
 
-    if negate {
-        value *= -1;
+    if !negate {
+        value = match value.checked_neg() {
+            Some(v) => v,
+            None => {
+                return fitserr!("fixed-format integer is too large to be represented");
+            }
+        };
     }
 
     Ok(value)
@@ -765,6 +853,66 @@ fn fixed_int_parsing() {
     assert!(parse_fixed_int(r).is_err());
     let r = b"NAXIS   =                    9A / comment                                       ";
     assert!(parse_fixed_int(r).is_err());
+    let r = b"NAXIS   = 99999999999999999999 / comment                                        ";
+    assert!(parse_fixed_int(r).is_err());
+}
+
+/// Compute the number of data bytes associated with an HDU, given its sizing
+/// headers. The *naxis* slice should not include the dummy zero-valued
+/// NAXIS1 of a random-groups primary HDU.
+///
+/// The header values come straight from the file, so all of the arithmetic
+/// is checked: a corrupted file yields an error rather than an overflow.
+fn hdu_data_size(
+    bitpix: Bitpix,
+    pcount: isize,
+    gcount: usize,
+    naxis: &[usize],
+) -> Result<usize, Error> {
+    // NAXIS = 0 means that there is no data array at all, not a scalar.
+    if naxis.is_empty() {
+        return Ok(0);
+    }
+
+    let n_elements = naxis
+        .iter()
+        .try_fold(1usize, |p, n| p.checked_mul(*n))
+        .and_then(|n| {
+            if n > isize::max_value() as usize {
+                None
+            } else {
+                Some(n as isize)
+            }
+        })
+        .and_then(|n| n.checked_add(pcount));
+
+    let group_size = match n_elements {
+        Some(n) if n < 0 => {
+            return fitserr!("illegal negative FITS group size");
+        }
+        Some(n) => n as usize,
+        None => {
+            return fitserr!("FITS data size is too large to be represented");
+        }
+    };
+
+    match group_size
+        .checked_mul(gcount)
+        .and_then(|n| n.checked_mul(bitpix.n_bytes()))
+    {
+        Some(n) => Ok(n),
+        None => fitserr!("FITS data size is too large to be represented"),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn data_size_overflow() {
+    assert_eq!(hdu_data_size(Bitpix::F32, 0, 1, &[4, 8]).unwrap(), 128);
+    assert_eq!(hdu_data_size(Bitpix::I16, 3, 2, &[5]).unwrap(), 32);
+    assert!(hdu_data_size(Bitpix::F64, 0, 1, &[1 << 40, 1 << 40]).is_err());
+    assert!(hdu_data_size(Bitpix::U8, -10, 1, &[4]).is_err());
+    assert!(hdu_data_size(Bitpix::F64, 0, usize::max_value(), &[2]).is_err());
 }
 
 fn parse_fixed_string(record: &[u8]) -> Result<String, Error> {
@@ -901,6 +1049,34 @@ fn fixed_string_parsing() {
     assert!(parse_fixed_string(r).is_err());
 }
 
+/// Parse the value of a free-format numeric header record.
+fn parse_free_float(record: &[u8]) -> Result<f64, Error> {
+    let value = String::from_utf8_lossy(&record[10..]);
+    let value = value.split('/').next().unwrap_or("").trim();
+
+    match value.replace('D', "E").parse() {
+        Ok(v) => Ok(v),
+        Err(_) => fitserr!(
+            "expected a number in FITS header record but got {:?}",
+            value
+        ),
+    }
+}
+
+/// Format a header record with the given keyword and already-formatted
+/// value.
+fn format_card(keyword: &str, value: &str) -> [u8; 80] {
+    let mut card = [b' '; 80];
+    let text = format!("{:<8}= {}", keyword, value);
+    let n = text.len().min(80);
+    card[..n].copy_from_slice(&text.as_bytes()[..n]);
+    card
+}
+
+fn format_string(value: &str) -> String {
+    format!("'{:<8}'", value.replace('\'', "''"))
+}
+
 /// Returns Ok(true) if this record in question was the appropriate NAXISnnn
 /// header; Ok(false) if it was some other valid-looking header; Err(_) if it
 /// looks like it should have been a NAXIS header but something went wrong.
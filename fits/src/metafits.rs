@@ -0,0 +1,477 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Reading MWA "metafits" files.
+
+The MWA correlator writes its raw visibilities without much description of
+the observation. That comes in a separate FITS file, the metafits, whose
+primary header describes the timing and frequency setup and whose
+`TILEDATA` binary table lists the correlator inputs: which tile and
+polarization each one carries, where the tile is, and whether it has been
+flagged. `MwaMetafits` gathers that information up so that it can be
+attached to visibilities converted from the raw files, for instance with
+`ArrayLayout` to fill in the antennas of a new Measurement Set.
+
+*/
+
+use failure::Error;
+use rubbl_core::telescopes::{self, Antenna, ArrayLayout};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use super::bintable::BinaryTable;
+use super::{FitsParser, Header};
+
+/// The diameter that we assign to MWA tiles, in meters. A tile is a 4×4
+/// grid of dipoles spaced by 1.1 m.
+pub const TILE_DIAMETER: f64 = 4.;
+
+/// Information about one MWA tile, combining the two `TILEDATA` rows for
+/// its X and Y inputs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MwaTile {
+    /// The index of the tile in the correlator's antenna ordering.
+    pub antenna: usize,
+
+    /// The tile's identifying number.
+    pub tile_id: u32,
+
+    /// The tile's name, such as `Tile011`.
+    pub name: String,
+
+    /// The correlator input carrying the X polarization.
+    pub x_input: usize,
+
+    /// The correlator input carrying the Y polarization.
+    pub y_input: usize,
+
+    /// The receiver to which the tile is connected.
+    pub receiver: u32,
+
+    /// The tile's position relative to the array center, in meters east,
+    /// north, and up.
+    pub enu: [f64; 3],
+
+    /// The electrical length of the tile's cables, in meters.
+    pub cable_length: f64,
+
+    /// Whether either of the tile's inputs has been flagged.
+    pub flagged: bool,
+}
+
+/// The contents of an MWA metafits file.
+#[derive(Clone, Debug)]
+pub struct MwaMetafits {
+    /// The primary header, for keywords not otherwise exposed.
+    pub header: Header,
+
+    /// The GPS time of the start of the observation, which also serves as
+    /// its identifier.
+    pub obs_id: u64,
+
+    /// The UTC start time of the observation, as an ISO-8601 string.
+    pub date_obs: String,
+
+    /// The duration of the observation, in seconds.
+    pub exposure: f64,
+
+    /// The correlator integration time, in seconds.
+    pub int_time: f64,
+
+    /// The width of each fine channel, in Hz.
+    pub fine_chan_width: f64,
+
+    /// The total number of fine channels.
+    pub n_fine_chans: usize,
+
+    /// The total bandwidth, in Hz.
+    pub bandwidth: f64,
+
+    /// The center frequency of the observation, in Hz.
+    pub center_freq: f64,
+
+    /// The receiver channel numbers of the coarse channels, in the order
+    /// listed in the file.
+    pub coarse_chans: Vec<u32>,
+
+    /// The phase center, as RA and declination in degrees.
+    pub phase_center: (f64, f64),
+
+    /// The tiles of the array, sorted by antenna index.
+    pub tiles: Vec<MwaTile>,
+}
+
+impl MwaMetafits {
+    /// Read the metafits file at *path*.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let parser = FitsParser::new(File::open(path)?)?;
+        Self::read(parser)
+    }
+
+    /// Read a metafits file from an already-parsed FITS stream.
+    pub fn read<R: Read + Seek>(mut parser: FitsParser<R>) -> Result<Self, Error> {
+        let header = parser.read_header(0)?;
+
+        let tiledata = match parser.hdus().iter().position(|h| h.extname() == "TILEDATA") {
+            Some(i) => i,
+            None => {
+                return fitserr!("MWA metafits file has no TILEDATA extension");
+            }
+        };
+
+        let table = BinaryTable::read(&mut parser, tiledata)?;
+
+        let coarse_chans = match header.string("CHANNELS")? {
+            Some(s) => {
+                let mut chans = Vec::new();
+
+                for item in s.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()) {
+                    match item.parse() {
+                        Ok(c) => chans.push(c),
+                        Err(_) => {
+                            return fitserr!("malformed MWA metafits CHANNELS entry {:?}", item);
+                        }
+                    }
+                }
+
+                chans
+            }
+            None => Vec::new(),
+        };
+
+        let ra = match header.float("RAPHASE")? {
+            Some(v) => v,
+            None => required_float(&header, "RA")?,
+        };
+
+        let dec = match header.float("DECPHASE")? {
+            Some(v) => v,
+            None => required_float(&header, "DEC")?,
+        };
+
+        Ok(MwaMetafits {
+            obs_id: required_float(&header, "GPSTIME")? as u64,
+            date_obs: header.string("DATE-OBS")?.unwrap_or_default(),
+            exposure: required_float(&header, "EXPOSURE")?,
+            int_time: required_float(&header, "INTTIME")?,
+            fine_chan_width: required_float(&header, "FINECHAN")? * 1e3,
+            n_fine_chans: required_float(&header, "NCHANS")? as usize,
+            bandwidth: required_float(&header, "BANDWDTH")? * 1e6,
+            center_freq: required_float(&header, "FREQCENT")? * 1e6,
+            coarse_chans: coarse_chans,
+            phase_center: (ra, dec),
+            tiles: read_tiles(&table)?,
+            header: header,
+        })
+    }
+
+    /// Get the tiles that have not been flagged.
+    pub fn good_tiles(&self) -> impl Iterator<Item = &MwaTile> {
+        self.tiles.iter().filter(|t| !t.flagged)
+    }
+
+    /// Describe the array as an `ArrayLayout`, with one antenna per tile in
+    /// antenna order. Tile heights in the metafits are above sea level, so
+    /// they are referred to the height of the array center.
+    pub fn array_layout(&self) -> Result<ArrayLayout, Error> {
+        let site = match telescopes::site("mwa") {
+            Some(s) => s,
+            None => {
+                return fitserr!("the MWA site position is not known");
+            }
+        };
+
+        let antennas = self
+            .tiles
+            .iter()
+            .map(|t| Antenna {
+                name: t.name.clone(),
+                station: format!("{}", t.tile_id),
+                position: site.enu_to_itrf([t.enu[0], t.enu[1], t.enu[2] - site.height]),
+                diameter: TILE_DIAMETER,
+            })
+            .collect();
+
+        Ok(ArrayLayout {
+            name: format!("MWA {}", self.obs_id),
+            observatory: "MWA".to_owned(),
+            site: site,
+            antennas: antennas,
+        })
+    }
+}
+
+fn required_float(header: &Header, keyword: &str) -> Result<f64, Error> {
+    match header.float(keyword)? {
+        Some(v) => Ok(v),
+        None => fitserr!("MWA metafits file is missing the {} keyword", keyword),
+    }
+}
+
+/// Combine the per-input rows of the TILEDATA table into tiles.
+fn read_tiles(table: &BinaryTable) -> Result<Vec<MwaTile>, Error> {
+    let c_input = table.column_index("Input")?;
+    let c_antenna = table.column_index("Antenna")?;
+    let c_tile = table.column_index("Tile")?;
+    let c_name = table.column_index("TileName")?;
+    let c_pol = table.column_index("Pol")?;
+    let c_rx = table.column_index("Rx")?;
+    let c_flag = table.column_index("Flag")?;
+    let c_length = table.column_index("Length")?;
+    let c_north = table.column_index("North")?;
+    let c_east = table.column_index("East")?;
+    let c_height = table.column_index("Height")?;
+
+    let mut tiles: BTreeMap<usize, (MwaTile, bool, bool)> = BTreeMap::new();
+
+    for row in 0..table.n_rows() {
+        let antenna = table.get_i64(row, c_antenna)? as usize;
+        let input = table.get_i64(row, c_input)? as usize;
+        let flagged = table.get_i64(row, c_flag)? != 0;
+
+        // The Length column is a string like "EL_123.45", the electrical
+        // length of the cable in meters.
+        let length = table.get_string(row, c_length)?;
+        let length = length.trim_start_matches("EL_").parse().unwrap_or(0.);
+
+        let entry = tiles.entry(antenna).or_insert_with(|| {
+            (
+                MwaTile {
+                    antenna: antenna,
+                    tile_id: 0,
+                    name: String::new(),
+                    x_input: 0,
+                    y_input: 0,
+                    receiver: 0,
+                    enu: [0.; 3],
+                    cable_length: 0.,
+                    flagged: false,
+                },
+                false,
+                false,
+            )
+        });
+
+        let tile = &mut entry.0;
+        tile.tile_id = table.get_i64(row, c_tile)? as u32;
+        tile.name = table.get_string(row, c_name)?;
+        tile.receiver = table.get_i64(row, c_rx)? as u32;
+        tile.enu = [
+            table.get_f64(row, c_east)?,
+            table.get_f64(row, c_north)?,
+            table.get_f64(row, c_height)?,
+        ];
+        tile.cable_length = length;
+        tile.flagged |= flagged;
+
+        match table.get_string(row, c_pol)?.as_str() {
+            "X" => {
+                tile.x_input = input;
+                entry.1 = true;
+            }
+            "Y" => {
+                tile.y_input = input;
+                entry.2 = true;
+            }
+            other => {
+                return fitserr!("unexpected MWA metafits polarization {:?}", other);
+            }
+        }
+    }
+
+    let mut result = Vec::with_capacity(tiles.len());
+
+    for (tile, have_x, have_y) in tiles.into_values() {
+        if !(have_x && have_y) {
+            return fitserr!(
+                "MWA metafits file does not list both inputs of tile {}",
+                tile.name
+            );
+        }
+
+        result.push(tile);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+#[test]
+fn metafits() {
+    use super::{format_card, format_string};
+    use std::io::Cursor;
+
+    fn int(v: i64) -> String {
+        format!("{:>20}", v)
+    }
+
+    fn pad(buf: &mut Vec<u8>, fill: u8) {
+        while buf.len() % 2880 != 0 {
+            buf.push(fill);
+        }
+    }
+
+    let mut file = Vec::new();
+    let primary: &[(&str, String)] = &[
+        ("SIMPLE", format!("{:>20}", "T")),
+        ("BITPIX", int(8)),
+        ("NAXIS", int(0)),
+        ("GPSTIME", int(1_065_880_128)),
+        ("DATE-OBS", format_string("2013-10-15T13:48:32")),
+        ("EXPOSURE", int(112)),
+        ("INTTIME", format!("{:>20}", "0.5")),
+        ("FINECHAN", format!("{:>20}", "40.0")),
+        ("NCHANS", int(768)),
+        ("BANDWDTH", format!("{:>20}", "30.72")),
+        ("FREQCENT", format!("{:>20}", "154.24")),
+        ("CHANNELS", format_string("109,110,111")),
+        ("RA", format!("{:>20}", "0.0")),
+        ("DEC", format!("{:>20}", "-27.0")),
+        ("RAPHASE", format!("{:>20}", "1.5")),
+    ];
+
+    for (kw, value) in primary {
+        file.extend_from_slice(&format_card(kw, value));
+    }
+
+    file.extend_from_slice(&format!("{:<80}", "END").into_bytes());
+    pad(&mut file, b' ');
+
+    // Input(I) Antenna(I) Tile(I) TileName(8A) Pol(A) Rx(I) Flag(I)
+    // Length(14A) North(E) East(E) Height(E) = 45 bytes per row.
+    let columns = [
+        ("Input", "I"),
+        ("Antenna", "I"),
+        ("Tile", "I"),
+        ("TileName", "8A"),
+        ("Pol", "A"),
+        ("Rx", "I"),
+        ("Flag", "I"),
+        ("Length", "14A"),
+        ("North", "E"),
+        ("East", "E"),
+        ("Height", "E"),
+    ];
+
+    let rows: &[(i16, i16, i16, &str, &str, i16, i16, &str, f32, f32, f32)] = &[
+        (
+            2,
+            1,
+            12,
+            "Tile012",
+            "X",
+            1,
+            0,
+            "EL_-756.49",
+            100.,
+            -50.,
+            378.,
+        ),
+        (
+            3,
+            1,
+            12,
+            "Tile012",
+            "Y",
+            1,
+            1,
+            "EL_-756.49",
+            100.,
+            -50.,
+            378.,
+        ),
+        (
+            0,
+            0,
+            11,
+            "Tile011",
+            "X",
+            1,
+            0,
+            "EL_-756.49",
+            0.,
+            0.,
+            377.827,
+        ),
+        (
+            1,
+            0,
+            11,
+            "Tile011",
+            "Y",
+            1,
+            0,
+            "EL_-756.49",
+            0.,
+            0.,
+            377.827,
+        ),
+    ];
+
+    let mut ext = vec![
+        ("XTENSION".to_owned(), format_string("BINTABLE")),
+        ("BITPIX".to_owned(), int(8)),
+        ("NAXIS".to_owned(), int(2)),
+        ("NAXIS1".to_owned(), int(45)),
+        ("NAXIS2".to_owned(), int(rows.len() as i64)),
+        ("PCOUNT".to_owned(), int(0)),
+        ("GCOUNT".to_owned(), int(1)),
+        ("TFIELDS".to_owned(), int(columns.len() as i64)),
+    ];
+
+    for (i, (name, form)) in columns.iter().enumerate() {
+        ext.push((format!("TTYPE{}", i + 1), format_string(name)));
+        ext.push((format!("TFORM{}", i + 1), format_string(form)));
+    }
+
+    ext.push(("EXTNAME".to_owned(), format_string("TILEDATA")));
+
+    for (kw, value) in &ext {
+        file.extend_from_slice(&format_card(kw, value));
+    }
+
+    file.extend_from_slice(&format!("{:<80}", "END").into_bytes());
+    pad(&mut file, b' ');
+
+    for r in rows {
+        file.extend_from_slice(&r.0.to_be_bytes());
+        file.extend_from_slice(&r.1.to_be_bytes());
+        file.extend_from_slice(&r.2.to_be_bytes());
+        file.extend_from_slice(format!("{:<8}", r.3).as_bytes());
+        file.extend_from_slice(r.4.as_bytes());
+        file.extend_from_slice(&r.5.to_be_bytes());
+        file.extend_from_slice(&r.6.to_be_bytes());
+        file.extend_from_slice(format!("{:<14}", r.7).as_bytes());
+        file.extend_from_slice(&r.8.to_be_bytes());
+        file.extend_from_slice(&r.9.to_be_bytes());
+        file.extend_from_slice(&r.10.to_be_bytes());
+    }
+
+    pad(&mut file, 0);
+
+    let meta = MwaMetafits::read(FitsParser::new(Cursor::new(file)).unwrap()).unwrap();
+    assert_eq!(meta.obs_id, 1_065_880_128);
+    assert_eq!(meta.date_obs, "2013-10-15T13:48:32");
+    assert_eq!(meta.fine_chan_width, 40e3);
+    assert_eq!(meta.n_fine_chans, 768);
+    assert_eq!(meta.coarse_chans, vec![109, 110, 111]);
+    assert_eq!(meta.phase_center, (1.5, -27.));
+
+    assert_eq!(meta.tiles.len(), 2);
+    assert_eq!(meta.tiles[0].name, "Tile011");
+    assert_eq!((meta.tiles[1].x_input, meta.tiles[1].y_input), (2, 3));
+    assert_eq!(meta.tiles[1].cable_length, -756.49);
+    assert!(meta.tiles[1].flagged);
+    assert_eq!(meta.good_tiles().count(), 1);
+
+    let layout = meta.array_layout().unwrap();
+    let local = layout.local_positions();
+    assert!(local[0].iter().all(|x| x.abs() < 1e-3));
+    assert!((local[1][0] + 50.).abs() < 1e-3);
+    assert!((local[1][1] - 100.).abs() < 1e-3);
+    assert!((layout.max_baseline() - (50f64.powi(2) + 100f64.powi(2)).sqrt()).abs() < 1e-2);
+}
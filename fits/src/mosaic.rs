@@ -0,0 +1,469 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Weighted co-addition of images into a mosaic.
+
+A `Mosaic` combines several images of overlapping fields into one image on a
+grid that covers all of them. Each input pixel *I* carries a weight *w*,
+which should be its inverse variance, and the mosaic is
+
+- the weighted mean Σ *w I* / Σ *w*, with
+- the variance 1 / Σ *w*.
+
+The weights can be uniform, read from a weight map on the same grid as the
+image, or derived from a primary beam model as *B*² / σ², which is the
+right weighting for images that have been corrected for the beam.
+
+No real reprojection is done. The inputs must be on compatible grids: the
+same projection and the same pixel scale and orientation. The output grid
+is that of the first input, enlarged to cover all of the others, and each
+output pixel takes the value of the nearest pixel of each input. When the
+inputs share a projection center, as is usual for images made for a mosaic,
+this is exact.
+
+The mosaic is computed a chunk of output rows at a time, reading only the
+corresponding rows of the inputs, so that memory use is bounded no matter
+how large the images are.
+
+*/
+
+use failure::Error;
+use std::io::prelude::*;
+use std::ops::Range;
+
+use super::beam::{Pointing, PrimaryBeam};
+use super::image::{ImageCube, ImageWriter};
+use super::wcs::CelestialWcs;
+use super::{format_card, format_string};
+
+/// The default number of output rows computed at once.
+const DEFAULT_CHUNK_ROWS: usize = 256;
+
+/// The source of the weights of the pixels of a mosaic input.
+pub enum InputWeights<R: Read + Seek> {
+    /// The same weight for every pixel, typically 1/σ².
+    Uniform(f64),
+
+    /// A weight map on the same grid as the image. If the map has a single
+    /// plane, it is used for every channel; otherwise the plane matching
+    /// the channel of the image is used.
+    Map(ImageCube<R>),
+
+    /// Weights of *B*² / σ², where *B* is the response of *beam* at
+    /// *pointing* and σ is the noise level of the image at the pointing
+    /// center.
+    Beam {
+        /// The primary beam model.
+        beam: Box<dyn PrimaryBeam>,
+
+        /// The pointing of the observation.
+        pointing: Pointing,
+
+        /// The noise level of the image at the pointing center.
+        sigma: f64,
+    },
+}
+
+/// One of the images combined into a mosaic.
+pub struct MosaicInput<R: Read + Seek> {
+    image: ImageCube<R>,
+    channel: usize,
+    wcs: CelestialWcs,
+    weights: InputWeights<R>,
+}
+
+impl<R: Read + Seek> MosaicInput<R> {
+    /// Create an input from plane *channel* of *image*, with pixel weights
+    /// given by *weights*.
+    pub fn new(
+        image: ImageCube<R>,
+        channel: usize,
+        weights: InputWeights<R>,
+    ) -> Result<Self, Error> {
+        let (nx, ny, n_chan) = image.shape();
+
+        if channel >= n_chan {
+            return fitserr!(
+                "channel {} is out of range for an image with {} channels",
+                channel,
+                n_chan
+            );
+        }
+
+        match weights {
+            InputWeights::Uniform(w) if w.is_nan() || w <= 0. => {
+                return fitserr!("mosaic weights must be positive; got {}", w);
+            }
+
+            InputWeights::Map(ref map) => {
+                let (wnx, wny, wn_chan) = map.shape();
+
+                if wnx != nx || wny != ny || (wn_chan != 1 && wn_chan != n_chan) {
+                    return fitserr!(
+                        "weight map of shape {:?} does not match its image of shape {:?}",
+                        map.shape(),
+                        image.shape()
+                    );
+                }
+            }
+
+            InputWeights::Beam { sigma, .. } if sigma.is_nan() || sigma <= 0. => {
+                return fitserr!("mosaic noise levels must be positive; got {}", sigma);
+            }
+
+            _ => {}
+        }
+
+        let wcs = image.celestial_wcs()?;
+
+        Ok(MosaicInput {
+            image: image,
+            channel: channel,
+            wcs: wcs,
+            weights: weights,
+        })
+    }
+}
+
+/// A weighted co-addition of images. See the module documentation.
+pub struct Mosaic<R: Read + Seek> {
+    inputs: Vec<MosaicInput<R>>,
+    wcs: CelestialWcs,
+    nx: usize,
+    ny: usize,
+    bunit: String,
+    chunk_rows: usize,
+}
+
+impl<R: Read + Seek> Mosaic<R> {
+    /// Set up a mosaic of *inputs*, which must be on compatible grids.
+    pub fn new(inputs: Vec<MosaicInput<R>>) -> Result<Self, Error> {
+        let (reference, bunit) = match inputs.first() {
+            Some(i) => (i.wcs.clone(), i.image.bunit().to_owned()),
+            None => {
+                return fitserr!("a mosaic needs at least one input image");
+            }
+        };
+
+        let cd = reference.cd();
+        let tolerance = 1e-6 * reference.pixel_scale();
+        let mut x_range = (std::f64::INFINITY, std::f64::NEG_INFINITY);
+        let mut y_range = x_range;
+
+        for (num, input) in inputs.iter().enumerate() {
+            let icd = input.wcs.cd();
+            let compatible = input.wcs.projection() == reference.projection()
+                && (0..4).all(|k| (icd[k / 2][k % 2] - cd[k / 2][k % 2]).abs() <= tolerance);
+
+            if !compatible {
+                return fitserr!(
+                    "mosaic input #{} is not on a grid compatible with that of the first",
+                    num
+                );
+            }
+
+            // Sampling the corners and edge midpoints is enough for grids
+            // with the same orientation.
+            let (nx, ny, _) = input.image.shape();
+            let (xmax, ymax) = ((nx - 1) as f64, (ny - 1) as f64);
+
+            for &(fx, fy) in &[
+                (0., 0.),
+                (0.5, 0.),
+                (1., 0.),
+                (0., 0.5),
+                (1., 0.5),
+                (0., 1.),
+                (0.5, 1.),
+                (1., 1.),
+            ] {
+                let pixel = input
+                    .wcs
+                    .pixel_to_world(fx * xmax, fy * ymax)
+                    .and_then(|(lon, lat)| reference.world_to_pixel(lon, lat));
+
+                let (x, y) = match pixel {
+                    Some(p) => p,
+                    None => {
+                        return fitserr!(
+                            "mosaic input #{} extends beyond the projection of the first",
+                            num
+                        );
+                    }
+                };
+
+                x_range = (x_range.0.min(x.round()), x_range.1.max(x.round()));
+                y_range = (y_range.0.min(y.round()), y_range.1.max(y.round()));
+            }
+        }
+
+        Ok(Mosaic {
+            inputs: inputs,
+            wcs: reference.shifted(x_range.0, y_range.0),
+            nx: (x_range.1 - x_range.0) as usize + 1,
+            ny: (y_range.1 - y_range.0) as usize + 1,
+            bunit: bunit,
+            chunk_rows: DEFAULT_CHUNK_ROWS,
+        })
+    }
+
+    /// Set the number of output rows computed at once. Memory use is
+    /// roughly proportional to this number times the width of the mosaic.
+    pub fn set_chunk_rows(&mut self, chunk_rows: usize) -> &mut Self {
+        self.chunk_rows = chunk_rows.max(1);
+        self
+    }
+
+    /// Get the shape of the mosaic: `(nx, ny)`.
+    pub fn shape(&self) -> (usize, usize) {
+        (self.nx, self.ny)
+    }
+
+    /// Get the celestial coordinate system of the mosaic.
+    pub fn wcs(&self) -> &CelestialWcs {
+        &self.wcs
+    }
+
+    /// Compute the mosaic, a chunk of rows at a time.
+    ///
+    /// *func* is called for each chunk in order, with the range of rows in
+    /// the chunk and the mosaic and variance values of those rows. Pixels
+    /// that no input covers are NaN.
+    pub fn process<F>(&mut self, mut func: F) -> Result<(), Error>
+    where
+        F: FnMut(Range<usize>, &[f64], &[f64]) -> Result<(), Error>,
+    {
+        let mut start = 0;
+
+        while start < self.ny {
+            let rows = start..(start + self.chunk_rows).min(self.ny);
+            let n = (rows.end - rows.start) * self.nx;
+            let mut sum_w = vec![0.; n];
+            let mut sum_wi = vec![0.; n];
+
+            for input in &mut self.inputs {
+                accumulate(input, &self.wcs, self.nx, &rows, &mut sum_w, &mut sum_wi)?;
+            }
+
+            for (wi, w) in sum_wi.iter_mut().zip(sum_w.iter_mut()) {
+                if *w > 0. {
+                    *wi /= *w;
+                    *w = 1. / *w;
+                } else {
+                    *wi = std::f64::NAN;
+                    *w = std::f64::NAN;
+                }
+            }
+
+            func(rows.clone(), &sum_wi, &sum_w)?;
+            start = rows.end;
+        }
+
+        Ok(())
+    }
+
+    /// Compute the mosaic and write it to *image* as a FITS file, along with
+    /// its variance map to *variance* if it is given.
+    pub fn write<W1: Write, W2: Write>(
+        &mut self,
+        image: W1,
+        variance: Option<W2>,
+    ) -> Result<(), Error> {
+        let naxis = [self.nx, self.ny];
+        let cards = self.wcs.to_header();
+
+        let mut image_cards = cards.clone();
+        let mut variance_cards = cards;
+
+        if !self.bunit.is_empty() {
+            let squared = format!("({})**2", self.bunit);
+            image_cards.extend_from_slice(&format_card("BUNIT", &format_string(&self.bunit)));
+            variance_cards.extend_from_slice(&format_card("BUNIT", &format_string(&squared)));
+        }
+
+        let mut image = ImageWriter::new(image, &naxis, &image_cards)?;
+        let mut variance = match variance {
+            Some(dest) => Some(ImageWriter::new(dest, &naxis, &variance_cards)?),
+            None => None,
+        };
+
+        self.process(|_rows, mosaic, var| {
+            image.write(mosaic)?;
+
+            if let Some(ref mut v) = variance {
+                v.write(var)?;
+            }
+
+            Ok(())
+        })?;
+
+        image.finish()?;
+
+        if let Some(v) = variance {
+            v.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Add the contributions of *input* to the output rows *rows*.
+fn accumulate<R: Read + Seek>(
+    input: &mut MosaicInput<R>,
+    wcs: &CelestialWcs,
+    nx: usize,
+    rows: &Range<usize>,
+    sum_w: &mut [f64],
+    sum_wi: &mut [f64],
+) -> Result<(), Error> {
+    let (inx, iny, _) = input.image.shape();
+
+    // Map each output pixel to the nearest input pixel, if there is one.
+    let mut mapping = Vec::with_capacity(sum_w.len());
+    let mut in_rows = (usize::max_value(), 0);
+
+    for y in rows.clone() {
+        for x in 0..nx {
+            let found = wcs.pixel_to_world(x as f64, y as f64).and_then(|pos| {
+                let (ix, iy) = input.wcs.world_to_pixel(pos.0, pos.1)?;
+                let (ix, iy) = (ix.round(), iy.round());
+
+                if ix < 0. || iy < 0. || ix >= inx as f64 || iy >= iny as f64 {
+                    None
+                } else {
+                    Some((ix as usize, iy as usize, pos))
+                }
+            });
+
+            if let Some((_, iy, _)) = found {
+                in_rows = (in_rows.0.min(iy), in_rows.1.max(iy + 1));
+            }
+
+            mapping.push(found);
+        }
+    }
+
+    if in_rows.0 >= in_rows.1 {
+        return Ok(());
+    }
+
+    let in_rows = in_rows.0..in_rows.1;
+    let mut values = vec![0.; (in_rows.end - in_rows.start) * inx];
+    input
+        .image
+        .read_rows(input.channel, in_rows.clone(), &mut values)?;
+
+    let weight_map = if let InputWeights::Map(ref mut map) = input.weights {
+        let channel = if map.shape().2 == 1 { 0 } else { input.channel };
+        let mut buf = vec![0.; values.len()];
+        map.read_rows(channel, in_rows.clone(), &mut buf)?;
+        Some(buf)
+    } else {
+        None
+    };
+
+    for (i, m) in mapping.iter().enumerate() {
+        let (ix, iy, pos) = match *m {
+            Some(m) => m,
+            None => continue,
+        };
+
+        let k = (iy - in_rows.start) * inx + ix;
+
+        let w = match input.weights {
+            InputWeights::Uniform(w) => w,
+            InputWeights::Map(_) => weight_map.as_ref().map(|b| b[k]).unwrap_or(0.),
+            InputWeights::Beam {
+                ref beam,
+                ref pointing,
+                sigma,
+            } => {
+                let b = pointing.response(beam.as_ref(), pos);
+                b * b / (sigma * sigma)
+            }
+        };
+
+        let v = values[k];
+
+        if w > 0. && v.is_finite() {
+            sum_w[i] += w;
+            sum_wi[i] += w * v;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn coaddition() {
+    use super::FitsParser;
+    use std::io::Cursor;
+
+    // Two 3×2 images with 1' pixels on a common projection; the second is
+    // shifted two pixels to the west, so that they overlap in one column.
+    let make_image = |crpix1: f64, value: f64| {
+        let mut cards = Vec::new();
+        cards.extend_from_slice(&format_card("CTYPE1", &format_string("RA---SIN")));
+        cards.extend_from_slice(&format_card("CRVAL1", &format!("{:>20}", "30.0")));
+        cards.extend_from_slice(&format_card("CRVAL2", &format!("{:>20}", "-20.0")));
+        cards.extend_from_slice(&format_card("CRPIX1", &format!("{:>20}", crpix1)));
+        cards.extend_from_slice(&format_card("CRPIX2", &format!("{:>20}", "1.0")));
+        cards.extend_from_slice(&format_card("CDELT1", &format!("{:>20}", -1. / 60.)));
+        cards.extend_from_slice(&format_card("CDELT2", &format!("{:>20}", 1. / 60.)));
+        cards.extend_from_slice(&format_card("BUNIT", &format_string("Jy/beam")));
+
+        let mut file = Vec::new();
+        let mut writer = ImageWriter::new(&mut file, &[3, 2], &cards).unwrap();
+        writer.write(&[value; 6]).unwrap();
+        writer.finish().unwrap();
+        let parser = FitsParser::new(Cursor::new(file)).unwrap();
+        ImageCube::open_image(parser, 0).unwrap()
+    };
+
+    let inputs = vec![
+        MosaicInput::new(make_image(1., 1.), 0, InputWeights::Uniform(1.)).unwrap(),
+        MosaicInput::new(make_image(-1., 3.), 0, InputWeights::Uniform(3.)).unwrap(),
+    ];
+
+    let mut mosaic = Mosaic::new(inputs).unwrap();
+    assert_eq!(mosaic.shape(), (5, 2));
+
+    let mut values = Vec::new();
+    let mut variances = Vec::new();
+    mosaic.set_chunk_rows(1);
+    mosaic
+        .process(|rows, m, v| {
+            assert_eq!(rows.end - rows.start, 1);
+            values.extend_from_slice(m);
+            variances.extend_from_slice(v);
+            Ok(())
+        })
+        .unwrap();
+
+    assert_eq!(&values[..5], &[1., 1., 2.5, 3., 3.]);
+    assert_eq!(&variances[..5], &[1., 1., 0.25, 1. / 3., 1. / 3.]);
+    assert_eq!(&values[5..], &values[..5]);
+
+    let mut image = Vec::new();
+    mosaic.write(&mut image, None::<Vec<u8>>).unwrap();
+    let parser = FitsParser::new(Cursor::new(image)).unwrap();
+    let mut out = ImageCube::open_image(parser, 0).unwrap();
+    assert_eq!(out.shape(), (5, 2, 1));
+    assert_eq!(out.bunit(), "Jy/beam");
+    let mut plane = vec![0.; 10];
+    out.read_plane(0, &mut plane).unwrap();
+    assert_eq!(plane, values);
+    let (x, y) = out
+        .celestial_wcs()
+        .unwrap()
+        .world_to_pixel(30., -20.)
+        .unwrap();
+    assert!(x.abs() < 1e-9 && y.abs() < 1e-9);
+
+    let bad = MosaicInput::new(make_image(1., 1.), 1, InputWeights::Uniform(1.));
+    assert!(bad.is_err());
+    assert!(Mosaic::<Cursor<Vec<u8>>>::new(Vec::new()).is_err());
+}
@@ -0,0 +1,1076 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Region specifications.
+
+Users describe the parts of an image they care about with region files,
+written by hand or saved from an image viewer. This module parses a
+practical subset of the two common formats:
+
+- [ds9](http://ds9.si.edu/doc/ref/region.html) region files, with the
+  `image`, `physical`, `fk5`, `icrs`, and `j2000` coordinate systems and the
+  `circle`, `ellipse`, `box`, and `polygon` shapes;
+- CASA region text format ([CRTF](https://casa.nrao.edu/casadocs/latest/imaging/image-analysis/region-files/crtf-format)),
+  with the `J2000` and `ICRS` frames and the `circle`, `ellipse`, `box`,
+  `centerbox`, `rotbox`, and `poly` shapes.
+
+In both, a leading `-` excludes a shape rather than including it. Everything
+else, such as display properties, is ignored. A parsed `Region` is turned
+into a pixel mask for a particular image with `Region::mask`, using the
+image's celestial coordinate system for shapes given in sky coordinates.
+
+*/
+
+use failure::Error;
+use std::f64::consts::PI;
+
+use super::wcs::CelestialWcs;
+
+/// An error type for when a region specification is malformed or cannot be
+/// applied to an image.
+#[derive(Debug, Fail)]
+#[fail(display = "{}", _0)]
+pub struct RegionError(String);
+
+macro_rules! regerr {
+    ($( $fmt_args:expr ),*) => {
+        Err(RegionError(format!($( $fmt_args ),*)).into())
+    }
+}
+
+/// A position in a region specification.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    /// 0-based pixel coordinates.
+    Pixel(f64, f64),
+
+    /// A sky position: right ascension and declination, in degrees.
+    Sky(f64, f64),
+}
+
+/// A length in a region specification.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Length {
+    /// A length in pixels.
+    Pixels(f64),
+
+    /// An angular length on the sky, in degrees.
+    Degrees(f64),
+}
+
+/// A shape in a region specification.
+///
+/// Shape orientations are given by an angle in degrees, measured
+/// counterclockwise. For shapes centered on a pixel position it is measured
+/// from the first image axis. For shapes centered on a sky position it is
+/// measured from west, which is the first image axis in an image with north
+/// up and east to the left.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// A circle.
+    Circle {
+        /// The center of the circle.
+        center: Position,
+
+        /// The radius of the circle.
+        radius: Length,
+    },
+
+    /// An ellipse.
+    Ellipse {
+        /// The center of the ellipse.
+        center: Position,
+
+        /// The semi-axis lengths of the ellipse. The first is oriented along
+        /// *angle*.
+        radii: (Length, Length),
+
+        /// The orientation of the ellipse.
+        angle: f64,
+    },
+
+    /// A rectangle.
+    Box {
+        /// The center of the rectangle.
+        center: Position,
+
+        /// The side lengths of the rectangle. The first is oriented along
+        /// *angle*.
+        size: (Length, Length),
+
+        /// The orientation of the rectangle.
+        angle: f64,
+    },
+
+    /// A polygon, given by its vertices.
+    Polygon(Vec<Position>),
+}
+
+/// One shape of a region, with whether it is included or excluded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionItem {
+    /// The shape.
+    pub shape: Shape,
+
+    /// If true, pixels inside this shape are excluded from the region.
+    pub exclude: bool,
+}
+
+/// A region: a combination of shapes.
+///
+/// A pixel is in the region if it is inside any of the included shapes and
+/// not inside any of the excluded ones. If there are no included shapes,
+/// every pixel that is not excluded is in the region.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Region {
+    /// The shapes that make up the region.
+    pub items: Vec<RegionItem>,
+}
+
+impl Region {
+    /// Parse region text, detecting whether it is in CRTF or ds9 format.
+    /// CRTF files must start with their `#CRTF` header line.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        if text.trim_start().starts_with("#CRTF") {
+            Self::parse_crtf(text)
+        } else {
+            Self::parse_ds9(text)
+        }
+    }
+
+    /// Parse a ds9 region file.
+    pub fn parse_ds9(text: &str) -> Result<Self, Error> {
+        let mut region = Region::default();
+        let mut sky = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+
+            for item in line.split(';') {
+                let item = item.trim();
+
+                if item.is_empty() || item.starts_with("global") {
+                    continue;
+                }
+
+                match item.to_lowercase().as_ref() {
+                    "image" | "physical" => {
+                        sky = false;
+                        continue;
+                    }
+                    "fk5" | "icrs" | "j2000" | "wcs" => {
+                        sky = true;
+                        continue;
+                    }
+                    "fk4" | "b1950" | "galactic" | "ecliptic" | "linear" | "amplifier"
+                    | "detector" => {
+                        return regerr!("unsupported ds9 coordinate system \"{}\"", item);
+                    }
+                    _ => {}
+                }
+
+                region.items.push(parse_ds9_shape(item, sky)?);
+            }
+        }
+
+        Ok(region)
+    }
+
+    /// Parse a region file in the CASA region text format.
+    pub fn parse_crtf(text: &str) -> Result<Self, Error> {
+        let mut region = Region::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with("ann ") {
+                continue;
+            }
+
+            if line.starts_with("global ") {
+                check_crtf_properties(&line[7..])?;
+                continue;
+            }
+
+            region.items.push(parse_crtf_shape(line)?);
+        }
+
+        Ok(region)
+    }
+
+    /// Compute the mask of the pixels in this region for an image of
+    /// *nx*×*ny* pixels, in FITS order. *wcs* is the celestial coordinate
+    /// system of the image; it is needed if any shape is given in sky
+    /// coordinates.
+    ///
+    /// Pixels are tested at their centers.
+    pub fn mask(
+        &self,
+        wcs: Option<&CelestialWcs>,
+        nx: usize,
+        ny: usize,
+    ) -> Result<Vec<bool>, Error> {
+        let any_included = self.items.iter().any(|i| !i.exclude);
+        let mut included = vec![!any_included; nx * ny];
+        let mut excluded = vec![false; nx * ny];
+
+        for item in &self.items {
+            let shape = PixelShape::new(&item.shape, wcs)?;
+            let dest = if item.exclude {
+                &mut excluded
+            } else {
+                &mut included
+            };
+
+            let (x0, x1, y0, y1) = shape.bounds();
+            let x0 = x0.ceil().max(0.) as usize;
+            let y0 = y0.ceil().max(0.) as usize;
+            let x1 = (x1.floor() + 1.).max(0.).min(nx as f64) as usize;
+            let y1 = (y1.floor() + 1.).max(0.).min(ny as f64) as usize;
+
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    if shape.contains(x as f64, y as f64) {
+                        dest[y * nx + x] = true;
+                    }
+                }
+            }
+        }
+
+        Ok(included
+            .into_iter()
+            .zip(excluded)
+            .map(|(i, e)| i && !e)
+            .collect())
+    }
+
+    /// Get the 0-based `(x, y)` coordinates of the pixels in this region for
+    /// an image of *nx*×*ny* pixels. See `mask`.
+    pub fn pixels(
+        &self,
+        wcs: Option<&CelestialWcs>,
+        nx: usize,
+        ny: usize,
+    ) -> Result<Vec<(usize, usize)>, Error> {
+        Ok(self
+            .mask(wcs, nx, ny)?
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, m)| m)
+            .map(|(i, _)| (i % nx, i / nx))
+            .collect())
+    }
+}
+
+/// A shape converted into pixel coordinates.
+enum PixelShape {
+    Ellipse {
+        cx: f64,
+        cy: f64,
+        a: f64,
+        b: f64,
+        angle: f64,
+    },
+
+    Box {
+        cx: f64,
+        cy: f64,
+        half_w: f64,
+        half_h: f64,
+        angle: f64,
+    },
+
+    Polygon(Vec<(f64, f64)>),
+}
+
+impl PixelShape {
+    fn new(shape: &Shape, wcs: Option<&CelestialWcs>) -> Result<Self, Error> {
+        Ok(match *shape {
+            Shape::Circle { center, radius } => {
+                let (cx, cy) = to_pixel(center, wcs)?;
+                let r = to_pixels(radius, wcs)?;
+                PixelShape::Ellipse {
+                    cx: cx,
+                    cy: cy,
+                    a: r,
+                    b: r,
+                    angle: 0.,
+                }
+            }
+
+            Shape::Ellipse {
+                center,
+                radii,
+                angle,
+            } => {
+                let (cx, cy) = to_pixel(center, wcs)?;
+                PixelShape::Ellipse {
+                    cx: cx,
+                    cy: cy,
+                    a: to_pixels(radii.0, wcs)?,
+                    b: to_pixels(radii.1, wcs)?,
+                    angle: to_pixel_angle(center, angle, wcs)?,
+                }
+            }
+
+            Shape::Box {
+                center,
+                size,
+                angle,
+            } => {
+                let (cx, cy) = to_pixel(center, wcs)?;
+                PixelShape::Box {
+                    cx: cx,
+                    cy: cy,
+                    half_w: 0.5 * to_pixels(size.0, wcs)?,
+                    half_h: 0.5 * to_pixels(size.1, wcs)?,
+                    angle: to_pixel_angle(center, angle, wcs)?,
+                }
+            }
+
+            Shape::Polygon(ref vertices) => {
+                if vertices.len() < 3 {
+                    return regerr!("a polygon needs at least three vertices");
+                }
+
+                let mut pv = Vec::with_capacity(vertices.len());
+
+                for &v in vertices {
+                    pv.push(to_pixel(v, wcs)?);
+                }
+
+                PixelShape::Polygon(pv)
+            }
+        })
+    }
+
+    /// Get the bounding box of the shape: `(xmin, xmax, ymin, ymax)`.
+    fn bounds(&self) -> (f64, f64, f64, f64) {
+        match *self {
+            PixelShape::Ellipse { cx, cy, a, b, .. } => {
+                let r = a.max(b);
+                (cx - r, cx + r, cy - r, cy + r)
+            }
+
+            PixelShape::Box {
+                cx,
+                cy,
+                half_w,
+                half_h,
+                ..
+            } => {
+                let r = half_w.hypot(half_h);
+                (cx - r, cx + r, cy - r, cy + r)
+            }
+
+            PixelShape::Polygon(ref vertices) => vertices.iter().fold(
+                (
+                    std::f64::INFINITY,
+                    std::f64::NEG_INFINITY,
+                    std::f64::INFINITY,
+                    std::f64::NEG_INFINITY,
+                ),
+                |(x0, x1, y0, y1), &(x, y)| (x0.min(x), x1.max(x), y0.min(y), y1.max(y)),
+            ),
+        }
+    }
+
+    fn contains(&self, x: f64, y: f64) -> bool {
+        match *self {
+            PixelShape::Ellipse {
+                cx,
+                cy,
+                a,
+                b,
+                angle,
+            } => {
+                let (u, v) = rotate(x - cx, y - cy, angle);
+                (u / a).powi(2) + (v / b).powi(2) <= 1.
+            }
+
+            PixelShape::Box {
+                cx,
+                cy,
+                half_w,
+                half_h,
+                angle,
+            } => {
+                let (u, v) = rotate(x - cx, y - cy, angle);
+                u.abs() <= half_w && v.abs() <= half_h
+            }
+
+            PixelShape::Polygon(ref vertices) => {
+                // Even-odd ray casting.
+                let mut inside = false;
+                let mut j = vertices.len() - 1;
+
+                for i in 0..vertices.len() {
+                    let (xi, yi) = vertices[i];
+                    let (xj, yj) = vertices[j];
+
+                    if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                        inside = !inside;
+                    }
+
+                    j = i;
+                }
+
+                inside
+            }
+        }
+    }
+}
+
+/// Express the offset *dx*, *dy* in a frame rotated by *angle* radians.
+fn rotate(dx: f64, dy: f64, angle: f64) -> (f64, f64) {
+    let (s, c) = angle.sin_cos();
+    (dx * c + dy * s, -dx * s + dy * c)
+}
+
+fn need_wcs(wcs: Option<&CelestialWcs>) -> Result<&CelestialWcs, Error> {
+    match wcs {
+        Some(w) => Ok(w),
+        None => regerr!("region uses sky coordinates but the image has no celestial coordinates"),
+    }
+}
+
+fn to_pixel(pos: Position, wcs: Option<&CelestialWcs>) -> Result<(f64, f64), Error> {
+    match pos {
+        Position::Pixel(x, y) => Ok((x, y)),
+        Position::Sky(ra, dec) => match need_wcs(wcs)?.world_to_pixel(ra, dec) {
+            Some(p) => Ok(p),
+            None => regerr!(
+                "region position ({}, {}) cannot be projected onto the image",
+                ra,
+                dec
+            ),
+        },
+    }
+}
+
+fn to_pixels(len: Length, wcs: Option<&CelestialWcs>) -> Result<f64, Error> {
+    match len {
+        Length::Pixels(p) => Ok(p),
+        Length::Degrees(d) => Ok(d / need_wcs(wcs)?.pixel_scale()),
+    }
+}
+
+/// Convert a shape orientation in degrees into a pixel-frame angle in
+/// radians. See the documentation of `Shape`.
+fn to_pixel_angle(center: Position, angle: f64, wcs: Option<&CelestialWcs>) -> Result<f64, Error> {
+    match center {
+        Position::Pixel(..) => Ok(angle.to_radians()),
+
+        Position::Sky(ra, dec) => {
+            // Find which way north points at the center of the shape.
+            let step = 1. / 3600.;
+            let (x0, y0) = to_pixel(center, wcs)?;
+            let (x1, y1) = to_pixel(Position::Sky(ra, dec + step), wcs)?;
+            let north = (y1 - y0).atan2(x1 - x0);
+            Ok(north - 0.5 * PI + angle.to_radians())
+        }
+    }
+}
+
+// ds9 format
+
+fn parse_ds9_shape(item: &str, sky: bool) -> Result<RegionItem, Error> {
+    let (exclude, item) = split_sign(item);
+    let name_end = item
+        .find(|c: char| c == '(' || c.is_whitespace())
+        .unwrap_or(item.len());
+    let name = item[..name_end].to_lowercase();
+    let rest = &item[name_end..];
+
+    let args = match (rest.find('('), rest.find(')')) {
+        (Some(open), Some(close)) if open < close => &rest[open + 1..close],
+        (None, None) => rest,
+        _ => {
+            return regerr!("malformed ds9 region \"{}\"", item);
+        }
+    };
+
+    let args: Vec<_> = args
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    let n_args = |n: usize| -> Result<(), Error> {
+        if args.len() != n {
+            regerr!("ds9 {} region needs {} values; got {}", name, n, args.len())
+        } else {
+            Ok(())
+        }
+    };
+
+    let shape = match name.as_ref() {
+        "circle" => {
+            n_args(3)?;
+            Shape::Circle {
+                center: ds9_position(args[0], args[1], sky)?,
+                radius: ds9_length(args[2], sky)?,
+            }
+        }
+
+        "ellipse" => {
+            n_args(5)?;
+            Shape::Ellipse {
+                center: ds9_position(args[0], args[1], sky)?,
+                radii: (ds9_length(args[2], sky)?, ds9_length(args[3], sky)?),
+                angle: parse_number(args[4])?,
+            }
+        }
+
+        "box" => {
+            if args.len() != 4 {
+                n_args(5)?;
+            }
+
+            Shape::Box {
+                center: ds9_position(args[0], args[1], sky)?,
+                size: (ds9_length(args[2], sky)?, ds9_length(args[3], sky)?),
+                angle: match args.get(4) {
+                    Some(a) => parse_number(a)?,
+                    None => 0.,
+                },
+            }
+        }
+
+        "polygon" => {
+            if args.len() < 6 || args.len() % 2 != 0 {
+                return regerr!("ds9 polygon region needs at least three pairs of values");
+            }
+
+            let mut vertices = Vec::new();
+
+            for pair in args.chunks(2) {
+                vertices.push(ds9_position(pair[0], pair[1], sky)?);
+            }
+
+            Shape::Polygon(vertices)
+        }
+
+        _ => {
+            return regerr!("unsupported ds9 region shape \"{}\"", name);
+        }
+    };
+
+    Ok(RegionItem {
+        shape: shape,
+        exclude: exclude,
+    })
+}
+
+fn ds9_position(x: &str, y: &str, sky: bool) -> Result<Position, Error> {
+    if !sky {
+        // ds9 image coordinates are 1-based.
+        return Ok(Position::Pixel(
+            parse_number(x)? - 1.,
+            parse_number(y)? - 1.,
+        ));
+    }
+
+    let ra = if x.contains(':') {
+        15. * parse_sexagesimal(x, ':')?
+    } else {
+        parse_number(x.trim_end_matches('d'))?
+    };
+
+    let dec = if y.contains(':') {
+        parse_sexagesimal(y, ':')?
+    } else {
+        parse_number(y.trim_end_matches('d'))?
+    };
+
+    Ok(Position::Sky(ra, dec))
+}
+
+fn ds9_length(text: &str, sky: bool) -> Result<Length, Error> {
+    let (number, scale) = if text.ends_with('"') {
+        (&text[..text.len() - 1], Some(1. / 3600.))
+    } else if text.ends_with('\'') {
+        (&text[..text.len() - 1], Some(1. / 60.))
+    } else if text.ends_with('d') {
+        (&text[..text.len() - 1], Some(1.))
+    } else if text.ends_with('r') {
+        (&text[..text.len() - 1], Some(180. / PI))
+    } else if text.ends_with('p') || text.ends_with('i') {
+        (&text[..text.len() - 1], None)
+    } else if sky {
+        (text, Some(1.))
+    } else {
+        (text, None)
+    };
+
+    let value = parse_number(number)?;
+
+    Ok(match scale {
+        Some(s) => Length::Degrees(value * s),
+        None => Length::Pixels(value),
+    })
+}
+
+// CRTF format
+
+/// A parsed CRTF value: an atom or a bracketed list.
+#[derive(Clone, Debug, PartialEq)]
+enum CrtfValue {
+    Atom(String),
+    List(Vec<CrtfValue>),
+}
+
+impl CrtfValue {
+    fn atom(&self) -> Result<&str, Error> {
+        match *self {
+            CrtfValue::Atom(ref s) => Ok(s),
+            CrtfValue::List(_) => regerr!("expected a single value in CRTF region but got a list"),
+        }
+    }
+
+    fn list(&self) -> Result<&[CrtfValue], Error> {
+        match *self {
+            CrtfValue::List(ref l) => Ok(l),
+            CrtfValue::Atom(ref s) => regerr!("expected a list in CRTF region but got \"{}\"", s),
+        }
+    }
+
+    /// Interpret this value as a `[x, y]` pair.
+    fn pair(&self) -> Result<(&str, &str), Error> {
+        let l = self.list()?;
+
+        if l.len() != 2 {
+            return regerr!("expected a pair of values in CRTF region");
+        }
+
+        Ok((l[0].atom()?, l[1].atom()?))
+    }
+}
+
+/// Parse a bracketed CRTF list starting at the beginning of *text*,
+/// returning it and the unparsed remainder.
+fn parse_crtf_list(text: &str) -> Result<(CrtfValue, &str), Error> {
+    let mut stack: Vec<Vec<CrtfValue>> = Vec::new();
+    let mut atom = String::new();
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '[' => {
+                if !atom.trim().is_empty() {
+                    return regerr!("unexpected \"[\" in CRTF region");
+                }
+
+                stack.push(Vec::new());
+            }
+
+            ']' | ',' => {
+                let top = match stack.last_mut() {
+                    Some(t) => t,
+                    None => {
+                        return regerr!("unbalanced brackets in CRTF region");
+                    }
+                };
+
+                let a = atom.trim();
+
+                if !a.is_empty() {
+                    top.push(CrtfValue::Atom(a.to_owned()));
+                }
+
+                atom.clear();
+
+                if c == ']' {
+                    let done = CrtfValue::List(stack.pop().unwrap());
+
+                    match stack.last_mut() {
+                        Some(t) => t.push(done),
+                        None => return Ok((done, &text[i + 1..])),
+                    }
+                }
+            }
+
+            _ => {
+                if stack.is_empty() {
+                    return regerr!("expected \"[\" in CRTF region");
+                }
+
+                atom.push(c);
+            }
+        }
+    }
+
+    regerr!("unbalanced brackets in CRTF region")
+}
+
+fn parse_crtf_shape(line: &str) -> Result<RegionItem, Error> {
+    let (exclude, line) = split_sign(line);
+
+    let name_end = match line.find('[') {
+        Some(i) => i,
+        None => {
+            return regerr!("malformed CRTF region \"{}\"", line);
+        }
+    };
+
+    let name = line[..name_end].trim().to_lowercase();
+    let (value, rest) = parse_crtf_list(&line[name_end..])?;
+    let args = value.list()?;
+
+    let rest = rest.trim();
+
+    if rest.starts_with(',') {
+        check_crtf_properties(&rest[1..])?;
+    } else if !rest.is_empty() {
+        return regerr!("unexpected trailing text \"{}\" in CRTF region", rest);
+    }
+
+    let n_args = |n: usize| -> Result<(), Error> {
+        if args.len() != n {
+            regerr!(
+                "CRTF {} region needs {} values; got {}",
+                name,
+                n,
+                args.len()
+            )
+        } else {
+            Ok(())
+        }
+    };
+
+    let shape = match name.as_ref() {
+        "circle" => {
+            n_args(2)?;
+            Shape::Circle {
+                center: crtf_position(&args[0])?,
+                radius: crtf_length(args[1].atom()?)?,
+            }
+        }
+
+        "ellipse" => {
+            n_args(3)?;
+            let (a, b) = args[1].pair()?;
+            Shape::Ellipse {
+                center: crtf_position(&args[0])?,
+                radii: (crtf_length(a)?, crtf_length(b)?),
+                // The position angle is measured from north, which is 90°
+                // from west.
+                angle: crtf_angle(args[2].atom()?)? + 90.,
+            }
+        }
+
+        "box" => {
+            n_args(2)?;
+            let c1 = crtf_position(&args[0])?;
+            let c2 = crtf_position(&args[1])?;
+
+            let corners = match (c1, c2) {
+                (Position::Pixel(x1, y1), Position::Pixel(x2, y2)) => {
+                    vec![c1, Position::Pixel(x2, y1), c2, Position::Pixel(x1, y2)]
+                }
+                (Position::Sky(x1, y1), Position::Sky(x2, y2)) => {
+                    vec![c1, Position::Sky(x2, y1), c2, Position::Sky(x1, y2)]
+                }
+                _ => {
+                    return regerr!("CRTF box corners must use the same kind of coordinates");
+                }
+            };
+
+            Shape::Polygon(corners)
+        }
+
+        "centerbox" | "rotbox" => {
+            n_args(if name == "rotbox" { 3 } else { 2 })?;
+            let (w, h) = args[1].pair()?;
+            Shape::Box {
+                center: crtf_position(&args[0])?,
+                size: (crtf_length(w)?, crtf_length(h)?),
+                angle: match args.get(2) {
+                    Some(a) => crtf_angle(a.atom()?)?,
+                    None => 0.,
+                },
+            }
+        }
+
+        "poly" => {
+            if args.len() < 3 {
+                return regerr!("CRTF poly region needs at least three vertices");
+            }
+
+            let mut vertices = Vec::new();
+
+            for v in args {
+                vertices.push(crtf_position(v)?);
+            }
+
+            Shape::Polygon(vertices)
+        }
+
+        _ => {
+            return regerr!("unsupported CRTF region shape \"{}\"", name);
+        }
+    };
+
+    Ok(RegionItem {
+        shape: shape,
+        exclude: exclude,
+    })
+}
+
+/// Check the `key=value` properties of a CRTF line. Only the coordinate
+/// frame matters to us.
+fn check_crtf_properties(text: &str) -> Result<(), Error> {
+    for prop in text.split(',') {
+        let mut pieces = prop.splitn(2, '=');
+        let key = pieces.next().unwrap_or("").trim().to_lowercase();
+        let value = pieces.next().unwrap_or("").trim().to_uppercase();
+
+        if key == "coord" && value != "J2000" && value != "ICRS" {
+            return regerr!("unsupported CRTF coordinate frame \"{}\"", value);
+        }
+    }
+
+    Ok(())
+}
+
+fn crtf_position(value: &CrtfValue) -> Result<Position, Error> {
+    let (x, y) = value.pair()?;
+
+    match (x.ends_with("pix"), y.ends_with("pix")) {
+        (true, true) => Ok(Position::Pixel(
+            parse_number(&x[..x.len() - 3])?,
+            parse_number(&y[..y.len() - 3])?,
+        )),
+        (false, false) => Ok(Position::Sky(crtf_ra(x)?, crtf_dec(y)?)),
+        _ => regerr!(
+            "CRTF position [{}, {}] mixes pixel and sky coordinates",
+            x,
+            y
+        ),
+    }
+}
+
+fn crtf_ra(text: &str) -> Result<f64, Error> {
+    if text.contains(':') {
+        Ok(15. * parse_sexagesimal(text, ':')?)
+    } else if text.contains('h') {
+        let t = text.replace(&['h', 'm'][..], ":");
+        Ok(15. * parse_sexagesimal(t.trim_end_matches('s'), ':')?)
+    } else {
+        crtf_angle(text)
+    }
+}
+
+fn crtf_dec(text: &str) -> Result<f64, Error> {
+    if text.matches('.').count() > 1 {
+        parse_sexagesimal(text, '.')
+    } else if text.contains('d') && text.contains('m') {
+        let t = text.replace(&['d', 'm'][..], ":");
+        parse_sexagesimal(t.trim_end_matches('s'), ':')
+    } else {
+        crtf_angle(text)
+    }
+}
+
+/// Parse a CRTF angle, returning degrees.
+fn crtf_angle(text: &str) -> Result<f64, Error> {
+    let units: &[(&str, f64)] = &[
+        ("arcsec", 1. / 3600.),
+        ("arcmin", 1. / 60.),
+        ("deg", 1.),
+        ("rad", 180. / PI),
+        ("\"", 1. / 3600.),
+        ("'", 1. / 60.),
+    ];
+
+    for &(suffix, scale) in units {
+        if text.ends_with(suffix) {
+            return Ok(parse_number(&text[..text.len() - suffix.len()])? * scale);
+        }
+    }
+
+    parse_number(text)
+}
+
+fn crtf_length(text: &str) -> Result<Length, Error> {
+    if text.ends_with("pix") {
+        Ok(Length::Pixels(parse_number(&text[..text.len() - 3])?))
+    } else {
+        Ok(Length::Degrees(crtf_angle(text)?))
+    }
+}
+
+// Shared helpers
+
+/// Split off a leading `+` or `-`, returning whether the item is excluded.
+fn split_sign(text: &str) -> (bool, &str) {
+    if text.starts_with('-') {
+        (true, text[1..].trim_start())
+    } else if text.starts_with('+') {
+        (false, text[1..].trim_start())
+    } else {
+        (false, text)
+    }
+}
+
+fn parse_number(text: &str) -> Result<f64, Error> {
+    match text.trim().parse() {
+        Ok(v) => Ok(v),
+        Err(_) => regerr!("expected a number in region but got \"{}\"", text),
+    }
+}
+
+/// Parse a sexagesimal value such as `-30:12:00.5`, where the fields are
+/// separated by *sep*.
+fn parse_sexagesimal(text: &str, sep: char) -> Result<f64, Error> {
+    let text = text.trim();
+    let negative = text.starts_with('-');
+    let mut pieces = text.trim_start_matches(&['-', '+'][..]).splitn(3, sep);
+    let mut value = 0.;
+    let mut scale = 1.;
+    let mut n = 0;
+
+    for piece in &mut pieces {
+        value += parse_number(piece)? * scale;
+        scale /= 60.;
+        n += 1;
+    }
+
+    if n == 0 {
+        return regerr!("empty sexagesimal value in region");
+    }
+
+    Ok(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+#[test]
+fn ds9_parsing() {
+    let r = Region::parse(
+        "# Region file format: DS9 version 4.1\n\
+         global color=green\n\
+         image\n\
+         circle(11,21,5) # text={src}\n\
+         -box 11 21 2 4 30\n\
+         fk5;ellipse(12:30:00,-30:00:00,3\",1',45)\n\
+         polygon(10,20,10.5,20.5d,11,-20)",
+    )
+    .unwrap();
+
+    assert_eq!(r.items.len(), 4);
+    assert_eq!(
+        r.items[0].shape,
+        Shape::Circle {
+            center: Position::Pixel(10., 20.),
+            radius: Length::Pixels(5.),
+        }
+    );
+    assert!(r.items[1].exclude);
+    assert_eq!(
+        r.items[2].shape,
+        Shape::Ellipse {
+            center: Position::Sky(187.5, -30.),
+            radii: (
+                Length::Degrees(3. * (1. / 3600.)),
+                Length::Degrees(1. / 60.)
+            ),
+            angle: 45.,
+        }
+    );
+    assert_eq!(
+        r.items[3].shape,
+        Shape::Polygon(vec![
+            Position::Sky(10., 20.),
+            Position::Sky(10.5, 20.5),
+            Position::Sky(11., -20.)
+        ])
+    );
+
+    assert!(Region::parse_ds9("galactic;circle(1,2,3)").is_err());
+    assert!(Region::parse_ds9("image;point(1,2)").is_err());
+    assert!(Region::parse_ds9("image;circle(1,2)").is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn crtf_parsing() {
+    let r = Region::parse(
+        "#CRTFv0 CASA Region Text Format version 0\n\
+         global coord=J2000\n\
+         circle[[10pix, 20pix], 5pix]\n\
+         -centerbox[[12h30m00s, -30d00m00s], [3arcsec, 1arcmin]], color=red\n\
+         ellipse[[12:30:00, -30.00.00], [1deg, 0.5deg], 10deg]\n\
+         box[[0pix, 0pix], [4pix, 2pix]]",
+    )
+    .unwrap();
+
+    assert_eq!(r.items.len(), 4);
+    assert_eq!(
+        r.items[0].shape,
+        Shape::Circle {
+            center: Position::Pixel(10., 20.),
+            radius: Length::Pixels(5.),
+        }
+    );
+    assert!(r.items[1].exclude);
+    assert_eq!(
+        r.items[1].shape,
+        Shape::Box {
+            center: Position::Sky(187.5, -30.),
+            size: (
+                Length::Degrees(3. * (1. / 3600.)),
+                Length::Degrees(1. / 60.)
+            ),
+            angle: 0.,
+        }
+    );
+    assert_eq!(
+        r.items[2].shape,
+        Shape::Ellipse {
+            center: Position::Sky(187.5, -30.),
+            radii: (Length::Degrees(1.), Length::Degrees(0.5)),
+            angle: 100.,
+        }
+    );
+
+    assert!(Region::parse_crtf("circle[[1pix, 2deg], 3pix]").is_err());
+    assert!(Region::parse_crtf("circle[[1pix, 2pix], 3pix], coord=GALACTIC").is_err());
+    assert!(Region::parse_crtf("circle[[1pix, 2pix], 3pix").is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn region_masks() {
+    use super::wcs::Projection;
+
+    // A 5×4 image, drawn with y increasing downwards.
+    fn render(r: &Region, wcs: Option<&CelestialWcs>) -> String {
+        let mask = r.mask(wcs, 5, 4).unwrap();
+        mask.chunks(5)
+            .map(|row| {
+                row.iter()
+                    .map(|&m| if m { '#' } else { '.' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    let r = Region::parse_ds9("image;circle(3,2,1.5);-box(3,2,1,1)").unwrap();
+    assert_eq!(render(&r, None), ".###./.#.#./.###./.....");
+
+    let r = Region::parse_ds9("image;-circle(3,2,1.5)").unwrap();
+    assert_eq!(render(&r, None), "#...#/#...#/#...#/#####");
+
+    let r = Region::parse_crtf("poly[[-0.5pix, -0.5pix], [4.5pix, -0.5pix], [-0.5pix, 3.5pix]]")
+        .unwrap();
+    assert_eq!(render(&r, None), "####./###../##.../#....");
+
+    // One-arcsecond pixels, north up, with the reference point at pixel
+    // (2, 1).
+    let cd = [[-1. / 3600., 0.], [0., 1. / 3600.]];
+    let wcs = CelestialWcs::new(Projection::Sin, [150., 20.], [3., 2.], cd).unwrap();
+    let r = Region::parse_ds9("fk5;box(150,20,2.2\",0.5\",90)").unwrap();
+    assert_eq!(render(&r, Some(&wcs)), "..#../..#../..#../.....");
+    assert!(r.mask(None, 5, 4).is_err());
+}
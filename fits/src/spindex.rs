@@ -0,0 +1,311 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Spectral index maps from pairs of images.
+
+Given images *S*₁ and *S*₂ of the same field at frequencies ν₁ and ν₂, the
+spectral index α of each pixel, defined by *S* ∝ ν^α, is
+
+- α = ln(*S*₁ / *S*₂) / ln(ν₁ / ν₂), with uncertainty
+- σ_α = √((σ₁ / *S*₁)² + (σ₂ / *S*₂)²) / |ln(ν₁ / ν₂)|,
+
+where σ₁ and σ₂ are the noise levels of the images. Pixels where either
+image is below a signal-to-noise threshold are masked (set to NaN), since
+the index is meaningless there.
+
+The images must be on the same grid; regrid them first if they are not. The
+maps are computed a chunk of rows at a time from any pair of
+`ImageAccess` implementations.
+
+*/
+
+use failure::Error;
+use std::io::prelude::*;
+use std::ops::Range;
+
+use super::image::{ImageAccess, ImageWriter};
+use super::wcs::CelestialWcs;
+
+/// The default number of rows computed at once.
+const DEFAULT_CHUNK_ROWS: usize = 256;
+
+/// The maximum number of pixels used to estimate the noise level of an image.
+const MAX_NOISE_SAMPLES: usize = 1 << 20;
+
+/// Computes the spectral index map of a pair of images.
+pub struct SpectralIndex<A: ImageAccess, B: ImageAccess> {
+    first: A,
+    second: B,
+    wcs: CelestialWcs,
+    nx: usize,
+    ny: usize,
+    freqs: (f64, f64),
+    noise: (f64, f64),
+    snr_threshold: f64,
+    chunk_rows: usize,
+}
+
+impl<A: ImageAccess, B: ImageAccess> SpectralIndex<A, B> {
+    /// Set up the computation for the first channels of *first* and
+    /// *second*, at the frequencies *freqs* in Hz.
+    ///
+    /// The noise levels of the images are estimated from their pixel values;
+    /// see `estimate_noise`. The default signal-to-noise threshold is 5.
+    pub fn new(mut first: A, mut second: B, freqs: (f64, f64)) -> Result<Self, Error> {
+        let (nx, ny, _) = first.shape();
+        let (nx2, ny2, _) = second.shape();
+
+        if nx != nx2 || ny != ny2 {
+            return fitserr!(
+                "spectral index images must have the same shape; got {}×{} and {}×{}",
+                nx,
+                ny,
+                nx2,
+                ny2
+            );
+        }
+
+        let wcs = first.celestial_wcs()?;
+
+        if second.celestial_wcs()? != wcs {
+            return fitserr!("spectral index images must be on the same grid");
+        }
+
+        if freqs.0.is_nan()
+            || freqs.1.is_nan()
+            || freqs.0 <= 0.
+            || freqs.1 <= 0.
+            || freqs.0 == freqs.1
+        {
+            return fitserr!(
+                "spectral index frequencies must be positive and different; got {} and {}",
+                freqs.0,
+                freqs.1
+            );
+        }
+
+        let noise = (estimate_noise(&mut first)?, estimate_noise(&mut second)?);
+
+        Ok(SpectralIndex {
+            first: first,
+            second: second,
+            wcs: wcs,
+            nx: nx,
+            ny: ny,
+            freqs: freqs,
+            noise: noise,
+            snr_threshold: 5.,
+            chunk_rows: DEFAULT_CHUNK_ROWS,
+        })
+    }
+
+    /// Set the noise levels of the two images, overriding the estimates.
+    pub fn set_noise(&mut self, noise: (f64, f64)) -> &mut Self {
+        self.noise = noise;
+        self
+    }
+
+    /// Get the noise levels of the two images.
+    pub fn noise(&self) -> (f64, f64) {
+        self.noise
+    }
+
+    /// Set the signal-to-noise ratio below which pixels are masked.
+    pub fn set_snr_threshold(&mut self, snr_threshold: f64) -> &mut Self {
+        self.snr_threshold = snr_threshold;
+        self
+    }
+
+    /// Set the number of rows computed at once.
+    pub fn set_chunk_rows(&mut self, chunk_rows: usize) -> &mut Self {
+        self.chunk_rows = chunk_rows.max(1);
+        self
+    }
+
+    /// Compute the maps, a chunk of rows at a time.
+    ///
+    /// *func* is called for each chunk in order, with the range of rows in
+    /// the chunk and the spectral index and uncertainty values of those
+    /// rows.
+    pub fn process<F>(&mut self, mut func: F) -> Result<(), Error>
+    where
+        F: FnMut(Range<usize>, &[f64], &[f64]) -> Result<(), Error>,
+    {
+        let log_ratio = (self.freqs.0 / self.freqs.1).ln();
+        let mut start = 0;
+
+        while start < self.ny {
+            let rows = start..(start + self.chunk_rows).min(self.ny);
+            let n = (rows.end - rows.start) * self.nx;
+            let mut index = vec![0.; n];
+            let mut error = vec![0.; n];
+
+            self.first.read_rows(0, rows.clone(), &mut index)?;
+            self.second.read_rows(0, rows.clone(), &mut error)?;
+
+            for (alpha, sigma) in index.iter_mut().zip(error.iter_mut()) {
+                let (s1, s2) = (*alpha, *sigma);
+                let (r1, r2) = (self.noise.0 / s1, self.noise.1 / s2);
+
+                // This also masks NaNs.
+                if s1 > 0.
+                    && s2 > 0.
+                    && s1 >= self.snr_threshold * self.noise.0
+                    && s2 >= self.snr_threshold * self.noise.1
+                {
+                    *alpha = (s1 / s2).ln() / log_ratio;
+                    *sigma = (r1 * r1 + r2 * r2).sqrt() / log_ratio.abs();
+                } else {
+                    *alpha = std::f64::NAN;
+                    *sigma = std::f64::NAN;
+                }
+            }
+
+            func(rows.clone(), &index, &error)?;
+            start = rows.end;
+        }
+
+        Ok(())
+    }
+
+    /// Compute the maps and write the spectral index map to *index* as a
+    /// FITS file, along with its uncertainty map to *error* if it is given.
+    pub fn write<W1: Write, W2: Write>(
+        &mut self,
+        index: W1,
+        error: Option<W2>,
+    ) -> Result<(), Error> {
+        let naxis = [self.nx, self.ny];
+        let cards = self.wcs.to_header();
+        let mut index = ImageWriter::new(index, &naxis, &cards)?;
+        let mut error = match error {
+            Some(dest) => Some(ImageWriter::new(dest, &naxis, &cards)?),
+            None => None,
+        };
+
+        self.process(|_rows, alpha, sigma| {
+            index.write(alpha)?;
+
+            if let Some(ref mut e) = error {
+                e.write(sigma)?;
+            }
+
+            Ok(())
+        })?;
+
+        index.finish()?;
+
+        if let Some(e) = error {
+            e.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Estimate the noise level of the first channel of *image* robustly, as
+/// 1.4826 times the median absolute deviation of its pixel values.
+///
+/// The image is read a row at a time, and if it is large only a regular
+/// subsample of its pixels is used.
+pub fn estimate_noise<A: ImageAccess + ?Sized>(image: &mut A) -> Result<f64, Error> {
+    let (nx, ny, _) = image.shape();
+    let stride = (nx * ny + MAX_NOISE_SAMPLES - 1) / MAX_NOISE_SAMPLES;
+    let mut row = vec![0.; nx];
+    let mut samples = Vec::new();
+    let mut k = 0;
+
+    for y in 0..ny {
+        image.read_rows(0, y..y + 1, &mut row)?;
+
+        for &v in &row {
+            if k % stride == 0 && v.is_finite() {
+                samples.push(v);
+            }
+
+            k += 1;
+        }
+    }
+
+    if samples.is_empty() {
+        return fitserr!("cannot estimate the noise of an image with no valid pixels");
+    }
+
+    let center = median(&mut samples);
+
+    for v in &mut samples {
+        *v = (*v - center).abs();
+    }
+
+    Ok(1.4826 * median(&mut samples))
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let n = values.len();
+
+    if n % 2 == 1 {
+        values[n / 2]
+    } else {
+        0.5 * (values[n / 2 - 1] + values[n / 2])
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn spectral_index_maps() {
+    use super::image::ImageCube;
+    use super::wcs::Projection;
+    use super::FitsParser;
+    use std::io::Cursor;
+
+    let wcs = CelestialWcs::new(
+        Projection::Sin,
+        [10., 20.],
+        [1., 1.],
+        [[-0.01, 0.], [0., 0.01]],
+    )
+    .unwrap();
+
+    let make_image = |data: &[f64]| {
+        let mut file = Vec::new();
+        let mut writer = ImageWriter::new(&mut file, &[2, 2], &wcs.to_header()).unwrap();
+        writer.write(data).unwrap();
+        writer.finish().unwrap();
+        let parser = FitsParser::new(Cursor::new(file)).unwrap();
+        ImageCube::open_image(parser, 0).unwrap()
+    };
+
+    // A spectral index of -0.7 between 1 and 2 GHz, a faint pixel, a
+    // blanked pixel, and a flat-spectrum pixel.
+    let s2 = 10. * 2f64.powf(-0.7);
+    let first = make_image(&[10., 0.1, std::f64::NAN, 4.]);
+    let second = make_image(&[s2, 0.1, 1., 4.]);
+
+    let mut spindex = SpectralIndex::new(first, second, (1e9, 2e9)).unwrap();
+    spindex.set_noise((0.1, 0.1)).set_chunk_rows(1);
+
+    let mut alpha = Vec::new();
+    let mut sigma = Vec::new();
+    spindex
+        .process(|_rows, a, s| {
+            alpha.extend_from_slice(a);
+            sigma.extend_from_slice(s);
+            Ok(())
+        })
+        .unwrap();
+
+    assert!((alpha[0] + 0.7).abs() < 1e-12);
+    assert!(alpha[1].is_nan() && alpha[2].is_nan());
+    assert!(alpha[3].abs() < 1e-12);
+    let expected = (0.01f64.powi(2) + (0.1 / s2).powi(2)).sqrt() / 2f64.ln();
+    assert!((sigma[0] - expected).abs() < 1e-12);
+
+    let mut image = make_image(&[1., 2., 3., 100.]);
+    assert!((estimate_noise(&mut image).unwrap() - 1.4826).abs() < 1e-12);
+
+    let other = make_image(&[1.; 4]);
+    assert!(SpectralIndex::new(other, make_image(&[1.; 4]), (1e9, 1e9)).is_err());
+}
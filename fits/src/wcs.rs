@@ -0,0 +1,338 @@
+// Copyright 2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Celestial coordinate systems of FITS images.
+
+This implements the small part of the FITS World Coordinate System standard
+that is needed to relate sky positions to the pixels of typical radio images:
+the zenithal `SIN` and `TAN` projections of an equatorial (RA/Dec)
+coordinate system onto the first two image axes, with the pixel scale and
+rotation given by `CDELTn`, `CROTA2`, `PCi_j`, or `CDi_j` keywords. Reference
+points at the celestial poles and non-default `LONPOLE` values are not
+supported.
+
+*/
+
+use failure::Error;
+
+use super::{format_card, format_string};
+
+/// A projection from the celestial sphere to the image plane.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Projection {
+    /// The orthographic projection, normal for synthesis images.
+    Sin,
+
+    /// The gnomonic projection.
+    Tan,
+}
+
+/// The celestial coordinate system of the first two axes of an image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CelestialWcs {
+    projection: Projection,
+
+    /// The longitude and latitude of the reference point, in radians.
+    crval: [f64; 2],
+
+    /// The 0-based pixel coordinates of the reference point.
+    crpix: [f64; 2],
+
+    /// The linear transformation from pixel offsets to intermediate world
+    /// coordinates, in degrees per pixel.
+    cd: [[f64; 2]; 2],
+
+    /// The inverse of `cd`.
+    inv_cd: [[f64; 2]; 2],
+}
+
+impl CelestialWcs {
+    /// Create a coordinate system.
+    ///
+    /// *crval* is the position of the reference point in degrees, *crpix*
+    /// its 1-based pixel coordinates as in FITS headers, and *cd* the matrix
+    /// of `CDi_j` values, in degrees per pixel.
+    pub fn new(
+        projection: Projection,
+        crval: [f64; 2],
+        crpix: [f64; 2],
+        cd: [[f64; 2]; 2],
+    ) -> Result<Self, Error> {
+        let det = cd[0][0] * cd[1][1] - cd[0][1] * cd[1][0];
+
+        if det == 0. || !det.is_finite() {
+            return fitserr!("singular celestial coordinate transformation matrix");
+        }
+
+        if crval[1].abs() >= 90. {
+            return fitserr!("celestial reference points at the poles are not supported");
+        }
+
+        Ok(CelestialWcs {
+            projection: projection,
+            crval: [crval[0].to_radians(), crval[1].to_radians()],
+            crpix: [crpix[0] - 1., crpix[1] - 1.],
+            cd: cd,
+            inv_cd: [
+                [cd[1][1] / det, -cd[0][1] / det],
+                [-cd[1][0] / det, cd[0][0] / det],
+            ],
+        })
+    }
+
+    /// Create a coordinate system from the values of FITS header keywords.
+    ///
+    /// *ctype1* is the value of `CTYPE1`, and *keyword* looks up the
+    /// numerical value of a keyword, returning None if it is missing.
+    pub fn from_keywords<F>(ctype1: &str, mut keyword: F) -> Result<Self, Error>
+    where
+        F: FnMut(&str) -> Result<Option<f64>, Error>,
+    {
+        if !ctype1.starts_with("RA--") || ctype1.len() != 8 {
+            return fitserr!("unsupported celestial axis type {:?}", ctype1);
+        }
+
+        let projection = match &ctype1[5..] {
+            "SIN" => Projection::Sin,
+            "TAN" => Projection::Tan,
+            other => {
+                return fitserr!("unsupported celestial projection {:?}", other);
+            }
+        };
+
+        let crval = [
+            required("CRVAL1", keyword("CRVAL1")?)?,
+            required("CRVAL2", keyword("CRVAL2")?)?,
+        ];
+        let crpix = [
+            required("CRPIX1", keyword("CRPIX1")?)?,
+            required("CRPIX2", keyword("CRPIX2")?)?,
+        ];
+
+        let cd = if let Some(cd11) = keyword("CD1_1")? {
+            [
+                [cd11, keyword("CD1_2")?.unwrap_or(0.)],
+                [
+                    keyword("CD2_1")?.unwrap_or(0.),
+                    keyword("CD2_2")?.unwrap_or(0.),
+                ],
+            ]
+        } else {
+            let cdelt = [
+                required("CDELT1", keyword("CDELT1")?)?,
+                required("CDELT2", keyword("CDELT2")?)?,
+            ];
+
+            let pc = if let Some(pc11) = keyword("PC1_1")? {
+                [
+                    [pc11, keyword("PC1_2")?.unwrap_or(0.)],
+                    [
+                        keyword("PC2_1")?.unwrap_or(0.),
+                        keyword("PC2_2")?.unwrap_or(1.),
+                    ],
+                ]
+            } else {
+                let rho = keyword("CROTA2")?.unwrap_or(0.).to_radians();
+                [[rho.cos(), -rho.sin()], [rho.sin(), rho.cos()]]
+            };
+
+            [
+                [cdelt[0] * pc[0][0], cdelt[0] * pc[0][1]],
+                [cdelt[1] * pc[1][0], cdelt[1] * pc[1][1]],
+            ]
+        };
+
+        CelestialWcs::new(projection, crval, crpix, cd)
+    }
+
+    /// Get the projection of this coordinate system.
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    /// Get the position of the reference point, in degrees.
+    pub fn reference(&self) -> (f64, f64) {
+        (self.crval[0].to_degrees(), self.crval[1].to_degrees())
+    }
+
+    /// Get the geometric mean pixel size, in degrees.
+    pub fn pixel_scale(&self) -> f64 {
+        let det = self.cd[0][0] * self.cd[1][1] - self.cd[0][1] * self.cd[1][0];
+        det.abs().sqrt()
+    }
+
+    /// Get the matrix of `CDi_j` values, in degrees per pixel.
+    pub fn cd(&self) -> [[f64; 2]; 2] {
+        self.cd
+    }
+
+    /// Get the coordinate system of a grid whose pixel (0, 0) is at the
+    /// 0-based pixel coordinates *x0*, *y0* of this one, such as a
+    /// sub-image or an enlarged image.
+    pub fn shifted(&self, x0: f64, y0: f64) -> CelestialWcs {
+        let mut shifted = self.clone();
+        shifted.crpix = [self.crpix[0] - x0, self.crpix[1] - y0];
+        shifted
+    }
+
+    /// Format this coordinate system as FITS header records.
+    pub fn to_header(&self) -> Vec<u8> {
+        let suffix = match self.projection {
+            Projection::Sin => "SIN",
+            Projection::Tan => "TAN",
+        };
+
+        let (lon, lat) = self.reference();
+        let number = |v: f64| format!("{:>20}", format!("{:E}", v));
+        let mut cards = Vec::new();
+        cards.extend_from_slice(&format_card(
+            "CTYPE1",
+            &format_string(&format!("RA---{}", suffix)),
+        ));
+        cards.extend_from_slice(&format_card(
+            "CTYPE2",
+            &format_string(&format!("DEC--{}", suffix)),
+        ));
+        cards.extend_from_slice(&format_card("CRVAL1", &number(lon)));
+        cards.extend_from_slice(&format_card("CRVAL2", &number(lat)));
+        cards.extend_from_slice(&format_card("CRPIX1", &number(self.crpix[0] + 1.)));
+        cards.extend_from_slice(&format_card("CRPIX2", &number(self.crpix[1] + 1.)));
+
+        for i in 0..2 {
+            for j in 0..2 {
+                cards.extend_from_slice(&format_card(
+                    &format!("CD{}_{}", i + 1, j + 1),
+                    &number(self.cd[i][j]),
+                ));
+            }
+        }
+
+        cards
+    }
+
+    /// Convert the sky position *lon*, *lat* (in degrees) to 0-based pixel
+    /// coordinates. Returns None if the position cannot be projected, for
+    /// instance because it is on the far side of the sky for the `SIN`
+    /// projection.
+    pub fn world_to_pixel(&self, lon: f64, lat: f64) -> Option<(f64, f64)> {
+        let (a, d) = (lon.to_radians(), lat.to_radians());
+        let (a0, d0) = (self.crval[0], self.crval[1]);
+
+        // Direction cosines of the position in the native frame, whose pole
+        // is at the reference point. Working with these rather than native
+        // spherical angles keeps full precision close to the reference
+        // point.
+        let l = d.cos() * (a - a0).sin();
+        let m = d.sin() * d0.cos() - d.cos() * d0.sin() * (a - a0).cos();
+        let n = d.sin() * d0.sin() + d.cos() * d0.cos() * (a - a0).cos();
+
+        let (x, y) = match self.projection {
+            Projection::Sin if n < 0. => return None,
+            Projection::Sin => (l, m),
+            Projection::Tan if n <= 0. => return None,
+            Projection::Tan => (l / n, m / n),
+        };
+
+        let (x, y) = (x.to_degrees(), y.to_degrees());
+
+        Some((
+            self.crpix[0] + self.inv_cd[0][0] * x + self.inv_cd[0][1] * y,
+            self.crpix[1] + self.inv_cd[1][0] * x + self.inv_cd[1][1] * y,
+        ))
+    }
+
+    /// Convert the 0-based pixel coordinates *x*, *y* to a sky position in
+    /// degrees. Returns None if the pixel is outside of the projected sky.
+    pub fn pixel_to_world(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let (dx, dy) = (x - self.crpix[0], y - self.crpix[1]);
+        let ix = (self.cd[0][0] * dx + self.cd[0][1] * dy).to_radians();
+        let iy = (self.cd[1][0] * dx + self.cd[1][1] * dy).to_radians();
+
+        let (l, m, n) = match self.projection {
+            Projection::Sin => {
+                let r2 = ix * ix + iy * iy;
+
+                if r2 > 1. {
+                    return None;
+                }
+
+                (ix, iy, (1. - r2).sqrt())
+            }
+
+            Projection::Tan => {
+                let n = 1. / (1. + ix * ix + iy * iy).sqrt();
+                (ix * n, iy * n, n)
+            }
+        };
+
+        let (a0, d0) = (self.crval[0], self.crval[1]);
+        let c = n * d0.cos() - m * d0.sin();
+        let d = (m * d0.cos() + n * d0.sin()).atan2(l.hypot(c));
+        let mut lon = (a0 + l.atan2(c)).to_degrees() % 360.;
+
+        if lon < 0. {
+            lon += 360.;
+        }
+
+        Some((lon, d.to_degrees()))
+    }
+}
+
+fn required(name: &str, value: Option<f64>) -> Result<f64, Error> {
+    match value {
+        Some(v) => Ok(v),
+        None => fitserr!("missing FITS header keyword {}", name),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn projections() {
+    let cd = [[-1. / 3600., 0.], [0., 1. / 3600.]];
+
+    for &proj in &[Projection::Sin, Projection::Tan] {
+        let wcs = CelestialWcs::new(proj, [150., -30.], [101., 51.], cd).unwrap();
+        let (x, y) = wcs.world_to_pixel(150., -30.).unwrap();
+        assert!((x - 100.).abs() < 1e-6 && (y - 50.).abs() < 1e-6);
+
+        // One arcsecond north is one pixel up; east is to the left.
+        let (x, y) = wcs.world_to_pixel(150., -30. + 1. / 3600.).unwrap();
+        assert!((x - 100.).abs() < 1e-6 && (y - 51.).abs() < 1e-6);
+        let (x, y) = wcs.world_to_pixel(150. + 10. / 3600., -30.).unwrap();
+        assert!(x < 100. && (y - 50.).abs() < 0.01);
+
+        let (lon, lat) = wcs.pixel_to_world(37.5, 80.25).unwrap();
+        let (x, y) = wcs.world_to_pixel(lon, lat).unwrap();
+        assert!((x - 37.5).abs() < 1e-6 && (y - 80.25).abs() < 1e-6);
+    }
+
+    assert!(
+        (CelestialWcs::new(Projection::Sin, [0., 0.], [1., 1.], cd)
+            .unwrap()
+            .pixel_scale()
+            - 1. / 3600.)
+            .abs()
+            < 1e-15
+    );
+    assert!(CelestialWcs::new(Projection::Sin, [0., 90.], [1., 1.], cd).is_err());
+
+    // The header records round-trip, including for shifted grids.
+    let wcs = CelestialWcs::new(Projection::Tan, [150., -30.], [101., 51.], cd).unwrap();
+    let shifted = wcs.shifted(-10., 5.);
+    let header = shifted.to_header();
+    let records: Vec<_> = header.chunks(80).collect();
+    let parsed = CelestialWcs::from_keywords("RA---TAN", |k| {
+        Ok(records
+            .iter()
+            .find(|r| String::from_utf8_lossy(&r[..8]).trim_end() == k)
+            .map(|r| String::from_utf8_lossy(&r[10..]).trim().parse().unwrap()))
+    })
+    .unwrap();
+    assert_eq!(parsed.projection(), Projection::Tan);
+    assert_eq!(parsed.cd(), cd);
+    let (x, y) = parsed.world_to_pixel(150., -30.).unwrap();
+    assert!((x - 110.).abs() < 1e-6 && (y - 45.).abs() < 1e-6);
+    assert!(CelestialWcs::new(Projection::Sin, [0., 0.], [1., 1.], [[0.; 2]; 2]).is_err());
+}
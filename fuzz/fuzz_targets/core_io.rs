@@ -0,0 +1,44 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+//! Exercise the alignment-tracking and EOF-aware readers in `rubbl_core::io`.
+//!
+//! The first byte of the input selects the alignment used between values;
+//! the rest is decoded as a sequence of big-endian values.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rubbl_core;
+
+use rubbl_core::io::{AligningReader, EofReadExactExt};
+use rubbl_core::Error;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 1 {
+        return;
+    }
+
+    let alignment = (data[0] as usize % 8) + 1;
+    let mut ar = AligningReader::new(&data[1..]);
+
+    loop {
+        let keep_going = match ar.offset() % 6 {
+            0 => ar.eof_read_be_i16::<Error>().map(|o| o.is_some()),
+            1 => ar.eof_read_be_i32::<Error>().map(|o| o.is_some()),
+            2 => ar.eof_read_be_i64::<Error>().map(|o| o.is_some()),
+            3 => ar.eof_read_be_f32::<Error>().map(|o| o.is_some()),
+            4 => ar.eof_read_be_f64::<Error>().map(|o| o.is_some()),
+            _ => ar.eof_read_be_c64::<Error>().map(|o| o.is_some()),
+        };
+
+        match keep_going {
+            Ok(true) => {}
+            _ => break,
+        }
+
+        if ar.align_to(alignment).is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,22 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+//! Stream arbitrary bytes through the low-level FITS decoder.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rubbl_fits;
+
+use rubbl_fits::FitsDecoder;
+
+fuzz_target!(|data: &[u8]| {
+    let mut dec = FitsDecoder::new(data);
+
+    loop {
+        match dec.next() {
+            Ok(Some(_)) => {}
+            _ => break,
+        }
+    }
+});
@@ -0,0 +1,20 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+//! Parse arbitrary bytes as a seekable FITS file.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rubbl_fits;
+
+use rubbl_fits::FitsParser;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(fits) = FitsParser::new(Cursor::new(data)) {
+        for hdu in fits.hdus() {
+            let _ = hdu.shape();
+        }
+    }
+});
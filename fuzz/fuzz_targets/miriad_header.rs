@@ -0,0 +1,62 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+//! Use arbitrary bytes as the "header" item of a MIRIAD data set and read
+//! back all of the small items that it defines.
+//!
+//! MIRIAD data sets are directories, so we have to go through the
+//! filesystem. Each fuzzer process gets its own scratch directory.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rubbl_core;
+extern crate rubbl_miriad;
+
+use rubbl_core::Complex;
+use rubbl_miriad::{AnyMiriadValue, DataSet, Type};
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+fn scratch_dir() -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rubbl-fuzz-miriad-header-{}", process::id()));
+    let _ = fs::create_dir(&path);
+    path
+}
+
+fuzz_target!(|data: &[u8]| {
+    let path = scratch_dir();
+
+    if fs::write(path.join("header"), data).is_err() {
+        return;
+    }
+
+    let mut ds = match DataSet::open(&path) {
+        Ok(ds) => ds,
+        Err(_) => return,
+    };
+
+    let names: Vec<String> = match ds.items() {
+        Ok(items) => items.map(|i| i.name().to_owned()).collect(),
+        Err(_) => return,
+    };
+
+    for name in &names {
+        if let Ok(Some(item)) = ds.get(name) {
+            let _ = match item.type_() {
+                Type::Binary => item.read_vector::<u8>().map(AnyMiriadValue::Binary),
+                Type::Int8 => item.read_vector::<i8>().map(AnyMiriadValue::Int8),
+                Type::Int16 => item.read_vector::<i16>().map(AnyMiriadValue::Int16),
+                Type::Int32 => item.read_vector::<i32>().map(AnyMiriadValue::Int32),
+                Type::Int64 => item.read_vector::<i64>().map(AnyMiriadValue::Int64),
+                Type::Float32 => item.read_vector::<f32>().map(AnyMiriadValue::Float32),
+                Type::Float64 => item.read_vector::<f64>().map(AnyMiriadValue::Float64),
+                Type::Complex64 => item
+                    .read_vector::<Complex<f32>>()
+                    .map(AnyMiriadValue::Complex64),
+                Type::Text => item.read_scalar::<String>().map(AnyMiriadValue::Text),
+            };
+        }
+    }
+});
@@ -0,0 +1,64 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+//! Use arbitrary bytes as the "visdata" item of a MIRIAD UV data set with a
+//! fixed variable table, and decode all of the records it contains.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate rubbl_core;
+extern crate rubbl_miriad;
+
+use rubbl_core::Error;
+use rubbl_miriad::{DataSet, Type};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+
+const VARTABLE: &str = "r baseline\nd time\na source\nc corr\ni nschan\nj wcorr\nl lvar\nb bvar\n";
+
+fn scratch_dir() -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rubbl-fuzz-miriad-visdata-{}", process::id()));
+    let _ = fs::create_dir(&path);
+    path
+}
+
+fn setup(path: &PathBuf, data: &[u8]) -> Result<(), Error> {
+    fs::write(path.join("header"), b"")?;
+
+    let mut ds = DataSet::open(path)?;
+    ds.set_scalar_item("vislen", data.len() as i64 + 4)?;
+    ds.create_large_item("vartable", Type::Text)?
+        .write_all(VARTABLE.as_bytes())?;
+    ds.create_large_item("visdata", Type::Binary)?
+        .write_all(data)?;
+    ds.flush()?;
+    Ok(())
+}
+
+fuzz_target!(|data: &[u8]| {
+    let path = scratch_dir();
+
+    if setup(&path, data).is_err() {
+        return;
+    }
+
+    let mut ds = match DataSet::open(&path) {
+        Ok(ds) => ds,
+        Err(_) => return,
+    };
+
+    let mut uv = match ds.open_uv() {
+        Ok(uv) => uv,
+        Err(_) => return,
+    };
+
+    loop {
+        match uv.next() {
+            Ok(true) => {}
+            _ => break,
+        }
+    }
+});
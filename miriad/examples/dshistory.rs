@@ -34,14 +34,7 @@ fn main() {
         }
     };
 
-    for maybe_line in ds
-        .get("history")
-        .expect("cannot probe history")
-        .expect("no history item")
-        .into_lines()
-        .expect("cannot open history")
-    {
-        let line = maybe_line.expect("error reading history");
-        println!("{}", line);
+    for entry in ds.read_history().expect("cannot read history") {
+        println!("{}", entry);
     }
 }
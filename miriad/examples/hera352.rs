@@ -9,11 +9,16 @@ extern crate clap;
 #[macro_use]
 extern crate failure;
 extern crate pbr;
+extern crate rubbl_core;
 extern crate rubbl_miriad;
 
 use clap::{App, Arg};
 use failure::{Error, ResultExt};
+use rubbl_core::dryrun::{dry_run_requested, ClapDryRunArgsExt, DryRun};
+use rubbl_core::io::{ClapIoPolicyArgsExt, IoPolicy};
+use rubbl_core::report::{OutputFormat, Report};
 use rubbl_miriad::mask::{MaskDecoder, MaskEncoder};
+use rubbl_miriad::text::HistoryEntry;
 use rubbl_miriad::visdata::{
     decode_baseline, encode_baseline, Decoder, Encoder, UvVariableReference,
 };
@@ -30,6 +35,8 @@ fn main() {
     let matches = App::new("hera352")
         .version("0.1.0")
         .about("Make a fake 352-antenna HERA UV dataset")
+        .rubbl_dry_run_args()
+        .rubbl_io_policy_args()
         .arg(
             Arg::with_name("INPATH")
                 .help("The path to the input dataset directory")
@@ -46,9 +53,14 @@ fn main() {
 
     let in_path = matches.value_of_os("INPATH").unwrap();
     let out_path = matches.value_of_os("OUTPATH").unwrap();
+    let dry_run = dry_run_requested(&matches);
+    let io_policy = IoPolicy::from_clap(&matches).unwrap_or_else(|e| {
+        println!("fatal error: {}", e);
+        process::exit(1)
+    });
 
     process::exit(
-        match UvInflator::process(in_path.as_ref(), out_path.as_ref()) {
+        match UvInflator::process(in_path.as_ref(), out_path.as_ref(), dry_run, &io_policy) {
             Ok(code) => code,
 
             Err(e) => {
@@ -115,10 +127,15 @@ struct Record {
 }
 
 impl UvInflator {
-    fn process(in_path: &OsStr, out_path: &OsStr) -> Result<i32, Error> {
+    fn process(
+        in_path: &OsStr,
+        out_path: &OsStr,
+        dry_run: bool,
+        io_policy: &IoPolicy,
+    ) -> Result<i32, Error> {
         let t0 = Instant::now();
 
-        let mut inst = Self::new(in_path, out_path)?;
+        let mut inst = Self::new(in_path, out_path, dry_run, io_policy)?;
         inst.mainloop()?;
 
         let in_mib = inst.in_uv.visdata_bytes() as f64 / (1024. * 1024.);
@@ -146,11 +163,24 @@ impl UvInflator {
             out_mib,
             out_mib / dur_secs
         );
+
+        if inst.out_ds.is_dry_run() {
+            inst.out_ds
+                .take_change_plan()
+                .emit(OutputFormat::Text, &mut io::stdout())?;
+        }
+
         Ok(0)
     }
 
-    fn new(in_path: &OsStr, out_path: &OsStr) -> Result<Self, Error> {
-        let mut in_ds = DataSet::open(in_path).context("error opening input dataset")?;
+    fn new(
+        in_path: &OsStr,
+        out_path: &OsStr,
+        dry_run: bool,
+        io_policy: &IoPolicy,
+    ) -> Result<Self, Error> {
+        let mut in_ds =
+            DataSet::open_with_policy(in_path, io_policy).context("error opening input dataset")?;
         let mut in_uv = in_ds
             .open_uv()
             .context("could not open input as UV dataset")?;
@@ -161,13 +191,21 @@ impl UvInflator {
                 .into_byte_stream()?,
         );
 
-        let mut out_ds =
-            rubbl_miriad::DataSet::open(out_path).context("error opening output dataset")?;
+        let mut out_ds = DataSet::open_with_policy(out_path, io_policy)
+            .context("error opening output dataset")?;
+        out_ds.set_dry_run(dry_run);
         let mut out_uv = out_ds
             .new_uv_like(&in_uv)
             .context("could not open output for writing UV data")?;
         let out_flags = MaskEncoder::new(out_ds.create_large_item("flags", Type::Int32)?);
 
+        let mut history = in_ds.read_history()?;
+        history.push(HistoryEntry::new(
+            "HERA352",
+            format!("converted from {}", in_path.to_string_lossy()),
+        ));
+        out_ds.append_history(&history)?;
+
         // Progress bar
 
         let mut pb = pbr::ProgressBar::new(in_uv.visdata_bytes());
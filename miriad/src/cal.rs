@@ -0,0 +1,606 @@
+// Copyright 2017-2019 Peter Williams and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Reading MIRIAD calibration tables: the "gains", "bandpass", and "leakage"
+items written by tasks such as `mfcal` and `gpcal`.
+
+All of these items share a layout in which eight bytes of header are followed
+by a stream of big-endian binary values. Their dimensions are recorded in
+small header items ("ngains", "nfeeds", and so on) alongside them.
+
+A `Calibration` combines the gains and bandpass of a data set and applies
+them to the spectra of its UV records, as MIRIAD tasks do when they read
+data. A tool that converts MIRIAD data into another format, such as a
+Measurement Set, can offer to write calibrated data by passing each record
+through `Calibration::apply` before writing it out. Polarization leakages
+are not applied.
+
+ */
+
+use failure::Error;
+use rubbl_core::io::{EofReadExactExt, OpenResultExt};
+use rubbl_core::Complex;
+use std::io::prelude::*;
+
+use super::{DataSet, ReadStream, Type};
+
+/// Open a calibration item as a byte stream positioned just past its
+/// eight-byte header.
+fn open_cal_item(ds: &mut DataSet, name: &str) -> Result<ReadStream, Error> {
+    let item = ds.get(name).require_found()?;
+
+    // Calibration tables usually have a zero, "binary" type code, which
+    // `into_byte_stream` does not skip.
+    let untyped = matches!(item.type_(), Type::Text | Type::Binary);
+
+    let mut stream = item.into_byte_stream()?;
+
+    if untyped {
+        let mut type_buf = [0u8; 4];
+        stream.read_exact(&mut type_buf)?;
+    }
+
+    stream.align_to(8)?;
+    Ok(stream)
+}
+
+/// Compute the number of bytes occupied by a table of eight-byte values
+/// with the specified dimensions, failing if hostile header values make it
+/// overflow.
+fn table_size(dims: &[usize], item_name: &str) -> Result<u64, Error> {
+    let mut size = 8u64;
+
+    for &d in dims {
+        size = match size.checked_mul(d as u64) {
+            Some(s) => s,
+            None => {
+                return mirerr!("dimensions of \"{}\" item are too large", item_name);
+            }
+        };
+    }
+
+    Ok(size)
+}
+
+/// Read a scalar integer header item that gives a dimension of a calibration
+/// table.
+fn read_dimension(ds: &mut DataSet, name: &str) -> Result<usize, Error> {
+    let value = ds.get(name).require_found()?.read_scalar::<i32>()?;
+
+    if value < 0 {
+        return mirerr!("illegal \"{}\" value {}", name, value);
+    }
+
+    Ok(value as usize)
+}
+
+/// Like `read_dimension`, but returns a default if the item is absent.
+fn read_optional_dimension(ds: &mut DataSet, name: &str, default: usize) -> Result<usize, Error> {
+    if ds.get(name)?.is_none() {
+        return Ok(default);
+    }
+
+    read_dimension(ds, name)
+}
+
+/// Read exactly *n* complex values from a stream.
+fn read_complex_values<R: Read>(
+    stream: &mut R,
+    n: usize,
+    item_name: &str,
+) -> Result<Vec<Complex<f32>>, Error> {
+    let mut values = Vec::with_capacity(n);
+
+    for _ in 0..n {
+        match stream.eof_read_be_c64::<Error>()? {
+            Some(v) => values.push(v),
+            None => {
+                return mirerr!("premature end of \"{}\" item", item_name);
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+/// Antenna-based, time-variable complex gain solutions, as stored in the
+/// "gains" item.
+///
+/// Each solution consists of `nfeeds` gains for every antenna followed by
+/// `ntau` delay terms for every antenna.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GainsTable {
+    /// The number of antennas.
+    pub nants: usize,
+
+    /// The number of feeds (polarizations) per antenna; either 1 or 2.
+    pub nfeeds: usize,
+
+    /// The number of delay terms per antenna; either 0 or 1.
+    pub ntau: usize,
+
+    /// The nominal solution interval, in days.
+    pub interval: f64,
+
+    /// The timestamp of each solution, as a Julian date.
+    pub times: Vec<f64>,
+
+    /// The solutions themselves, flattened. See `gain()` for the indexing.
+    pub gains: Vec<Complex<f32>>,
+}
+
+impl GainsTable {
+    /// Read the gains table of a data set.
+    pub fn read(ds: &mut DataSet) -> Result<Self, Error> {
+        let ngains = read_dimension(ds, "ngains")?;
+        let nfeeds = read_optional_dimension(ds, "nfeeds", 1)?;
+        let ntau = read_optional_dimension(ds, "ntau", 0)?;
+        let nsols = read_dimension(ds, "nsols")?;
+        let interval = ds.get("interval").require_found()?.read_scalar::<f64>()?;
+
+        if nfeeds + ntau == 0 || ngains % (nfeeds + ntau) != 0 {
+            return mirerr!(
+                "inconsistent gains dimensions: ngains={} nfeeds={} ntau={}",
+                ngains,
+                nfeeds,
+                ntau
+            );
+        }
+
+        ds.size_limit()
+            .check(table_size(&[ngains + 1, nsols], "gains")?)?;
+
+        let mut stream = open_cal_item(ds, "gains")?;
+        let mut times = Vec::with_capacity(nsols);
+        let mut gains = Vec::with_capacity(ngains * nsols);
+
+        for _ in 0..nsols {
+            match stream.eof_read_be_f64::<Error>()? {
+                Some(t) => times.push(t),
+                None => {
+                    return mirerr!("premature end of \"gains\" item");
+                }
+            }
+
+            gains.append(&mut read_complex_values(&mut stream, ngains, "gains")?);
+        }
+
+        Ok(GainsTable {
+            nants: ngains / (nfeeds + ntau),
+            nfeeds: nfeeds,
+            ntau: ntau,
+            interval: interval,
+            times: times,
+            gains: gains,
+        })
+    }
+
+    /// Get the number of solutions in the table.
+    pub fn n_solutions(&self) -> usize {
+        self.times.len()
+    }
+
+    /// Get the gain of the specified feed of the specified antenna in the
+    /// specified solution. Antenna numbers are zero-based.
+    pub fn gain(&self, solution: usize, ant: usize, feed: usize) -> Complex<f32> {
+        let ngains = self.nants * (self.nfeeds + self.ntau);
+        self.gains[solution * ngains + ant * (self.nfeeds + self.ntau) + feed]
+    }
+
+    /// Find the solution that applies to the specified time, if any. We pick
+    /// the solution closest in time, as long as it is within one solution
+    /// interval of the requested time.
+    pub fn solution_for_time(&self, time: f64) -> Option<usize> {
+        let mut best = None;
+        let mut best_dt = self.interval;
+
+        for (i, t) in self.times.iter().enumerate() {
+            let dt = (t - time).abs();
+
+            if dt <= best_dt {
+                best = Some(i);
+                best_dt = dt;
+            }
+        }
+
+        best
+    }
+}
+
+/// The frequency setup of one spectral window of a bandpass table, as
+/// stored in the "freqs" item.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BandpassWindow {
+    /// The number of channels in this window.
+    pub nschan: usize,
+
+    /// The frequency of the first channel, in GHz.
+    pub sfreq: f64,
+
+    /// The channel increment, in GHz.
+    pub sdf: f64,
+}
+
+/// Antenna-based complex bandpass solutions, as stored in the "bandpass"
+/// item.
+///
+/// Only time-invariant bandpasses are supported; tables with multiple
+/// bandpass solutions ("nbpsols" > 1) are rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BandpassTable {
+    /// The number of antennas.
+    pub nants: usize,
+
+    /// The number of feeds (polarizations) per antenna.
+    pub nfeeds: usize,
+
+    /// The spectral windows that the channels are divided into.
+    pub windows: Vec<BandpassWindow>,
+
+    /// The solutions, flattened. See `gain()` for the indexing.
+    pub gains: Vec<Complex<f32>>,
+}
+
+impl BandpassTable {
+    /// Read the bandpass table of a data set.
+    pub fn read(ds: &mut DataSet) -> Result<Self, Error> {
+        let ngains = read_dimension(ds, "ngains")?;
+        let nfeeds = read_optional_dimension(ds, "nfeeds", 1)?;
+        let ntau = read_optional_dimension(ds, "ntau", 0)?;
+        let nspect = read_dimension(ds, "nspect0")?;
+        let nchan = read_dimension(ds, "nchan0")?;
+        let nbpsols = read_optional_dimension(ds, "nbpsols", 0)?;
+
+        if nbpsols > 1 {
+            return mirerr!("time-variable bandpass tables are not supported");
+        }
+
+        if nfeeds + ntau == 0 || ngains % (nfeeds + ntau) != 0 {
+            return mirerr!(
+                "inconsistent bandpass dimensions: ngains={} nfeeds={} ntau={}",
+                ngains,
+                nfeeds,
+                ntau
+            );
+        }
+
+        let nants = ngains / (nfeeds + ntau);
+
+        // The frequency setup: each window is an int32 channel count, four
+        // bytes of padding, and two float64s.
+
+        let mut stream = open_cal_item(ds, "freqs")?;
+        let mut windows = Vec::with_capacity(::std::cmp::min(nspect, 1024));
+        let mut total_chans = 0;
+        let mut pad = [0u8; 4];
+
+        for _ in 0..nspect {
+            let nschan = match stream.eof_read_be_i32::<Error>()? {
+                Some(n) if n >= 0 => n as usize,
+                Some(n) => {
+                    return mirerr!("illegal bandpass window size {}", n);
+                }
+                None => {
+                    return mirerr!("premature end of \"freqs\" item");
+                }
+            };
+
+            stream.read_exact(&mut pad)?;
+
+            let sfreq = stream.eof_read_be_f64::<Error>()?;
+            let sdf = stream.eof_read_be_f64::<Error>()?;
+
+            match (sfreq, sdf) {
+                (Some(sfreq), Some(sdf)) => {
+                    windows.push(BandpassWindow {
+                        nschan: nschan,
+                        sfreq: sfreq,
+                        sdf: sdf,
+                    });
+                }
+                _ => {
+                    return mirerr!("premature end of \"freqs\" item");
+                }
+            }
+
+            total_chans += nschan;
+        }
+
+        if total_chans != nchan {
+            return mirerr!(
+                "bandpass windows contain {} channels but expected {}",
+                total_chans,
+                nchan
+            );
+        }
+
+        ds.size_limit()
+            .check(table_size(&[nants, nfeeds, nchan], "bandpass")?)?;
+        let n_values = nants * nfeeds * nchan;
+
+        let mut stream = open_cal_item(ds, "bandpass")?;
+        let gains = read_complex_values(&mut stream, n_values, "bandpass")?;
+
+        Ok(BandpassTable {
+            nants: nants,
+            nfeeds: nfeeds,
+            windows: windows,
+            gains: gains,
+        })
+    }
+
+    /// Get the total number of channels in the table.
+    pub fn n_channels(&self) -> usize {
+        self.windows.iter().map(|w| w.nschan).sum()
+    }
+
+    /// Get the bandpass gain of the specified feed of the specified antenna
+    /// at the specified channel. Antenna and channel numbers are zero-based.
+    pub fn gain(&self, ant: usize, feed: usize, chan: usize) -> Complex<f32> {
+        self.gains[(ant * self.nfeeds + feed) * self.n_channels() + chan]
+    }
+}
+
+/// Antenna-based polarization leakage terms, as stored in the "leakage"
+/// item.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeakageTable {
+    /// The leakage terms, two per antenna (X/R then Y/L).
+    pub leakages: Vec<Complex<f32>>,
+}
+
+impl LeakageTable {
+    /// Read the leakage table of a data set.
+    pub fn read(ds: &mut DataSet) -> Result<Self, Error> {
+        let mut stream = open_cal_item(ds, "leakage")?;
+        let mut leakages = Vec::new();
+
+        while let Some(v) = stream.eof_read_be_c64::<Error>()? {
+            leakages.push(v);
+        }
+
+        if leakages.len() % 2 != 0 {
+            return mirerr!("odd number of values in \"leakage\" item");
+        }
+
+        Ok(LeakageTable { leakages: leakages })
+    }
+
+    /// Get the number of antennas in the table.
+    pub fn n_ants(&self) -> usize {
+        self.leakages.len() / 2
+    }
+
+    /// Get the leakage of the specified feed of the specified antenna.
+    /// Antenna numbers are zero-based.
+    pub fn leakage(&self, ant: usize, feed: usize) -> Complex<f32> {
+        self.leakages[2 * ant + feed]
+    }
+}
+
+/// Get the indices of the feeds of the two antennas of a baseline that
+/// contribute to the MIRIAD polarization code *pol*: (0, 1) for XY or RL,
+/// for instance. Returns None for Stokes parameters, to which antenna-based
+/// gains cannot be applied.
+pub fn pol_feeds(pol: i32) -> Option<(usize, usize)> {
+    match pol {
+        -1 | -5 => Some((0, 0)),
+        -2 | -6 => Some((1, 1)),
+        -3 | -7 => Some((0, 1)),
+        -4 | -8 => Some((1, 0)),
+        _ => None,
+    }
+}
+
+/// The antenna gains and bandpass of a data set, to be applied together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    /// The time-variable gains, if any.
+    pub gains: Option<GainsTable>,
+
+    /// The bandpass, if any.
+    pub bandpass: Option<BandpassTable>,
+}
+
+impl Calibration {
+    /// Read whichever of the gains and bandpass tables a data set has. It is
+    /// an error if it has neither.
+    pub fn read(ds: &mut DataSet) -> Result<Self, Error> {
+        let gains = if ds.get("gains")?.is_some() {
+            Some(GainsTable::read(ds)?)
+        } else {
+            None
+        };
+
+        let bandpass = if ds.get("bandpass")?.is_some() {
+            Some(BandpassTable::read(ds)?)
+        } else {
+            None
+        };
+
+        if gains.is_none() && bandpass.is_none() {
+            return mirerr!("the data set has no \"gains\" or \"bandpass\" items");
+        }
+
+        if let Some(ref g) = gains {
+            if g.ntau != 0 {
+                return mirerr!("applying gains with delay terms is not supported");
+            }
+        }
+
+        Ok(Calibration {
+            gains: gains,
+            bandpass: bandpass,
+        })
+    }
+
+    /// Calibrate the spectrum of one UV record in place. *time* is the Julian
+    /// date of the record, *ant1* and *ant2* its zero-based antenna numbers,
+    /// and *pol* its MIRIAD polarization code. As in `UvRecord`, *flags* are
+    /// true for good data.
+    ///
+    /// MIRIAD stores the factors that correct the data, so each visibility
+    /// is multiplied by the gain of the first antenna and the conjugate gain
+    /// of the second, and likewise for the bandpass. Data for which there is
+    /// no gain solution within one solution interval, or whose correction
+    /// is zero, are flagged.
+    pub fn apply(
+        &self,
+        time: f64,
+        ant1: usize,
+        ant2: usize,
+        pol: i32,
+        data: &mut [Complex<f32>],
+        flags: &mut [bool],
+    ) -> Result<(), Error> {
+        let (feed1, feed2) = match pol_feeds(pol) {
+            Some(f) => f,
+            None => {
+                return mirerr!("cannot apply antenna gains to polarization code {}", pol);
+            }
+        };
+
+        if let Some(ref g) = self.gains {
+            if ant1 >= g.nants || ant2 >= g.nants {
+                return mirerr!(
+                    "gains table has {} antennas but the data include baseline {}-{}",
+                    g.nants,
+                    ant1,
+                    ant2
+                );
+            }
+
+            let factor = match g.solution_for_time(time) {
+                Some(i) => {
+                    g.gain(i, ant1, feed1.min(g.nfeeds - 1))
+                        * g.gain(i, ant2, feed2.min(g.nfeeds - 1)).conj()
+                }
+                None => Complex::new(0., 0.),
+            };
+
+            for (v, f) in data.iter_mut().zip(flags.iter_mut()) {
+                *v = *v * factor;
+                *f = *f && factor.norm_sqr() > 0.;
+            }
+        }
+
+        if let Some(ref bp) = self.bandpass {
+            if ant1 >= bp.nants || ant2 >= bp.nants {
+                return mirerr!(
+                    "bandpass table has {} antennas but the data include baseline {}-{}",
+                    bp.nants,
+                    ant1,
+                    ant2
+                );
+            }
+
+            if data.len() != bp.n_channels() {
+                return mirerr!(
+                    "bandpass table has {} channels but the data have {}",
+                    bp.n_channels(),
+                    data.len()
+                );
+            }
+
+            let f1 = feed1.min(bp.nfeeds - 1);
+            let f2 = feed2.min(bp.nfeeds - 1);
+
+            for (chan, (v, f)) in data.iter_mut().zip(flags.iter_mut()).enumerate() {
+                let factor = bp.gain(ant1, f1, chan) * bp.gain(ant2, f2, chan).conj();
+                *v = *v * factor;
+                *f = *f && factor.norm_sqr() > 0.;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+#[test]
+fn gains_items() {
+    use byteorder::{BigEndian, WriteBytesExt};
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("rubbl-miriad-gains-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("header"), b"").unwrap();
+
+    // Gains are written after an all-zero header, so the item looks binary.
+    let mut gains = vec![0u8; 8];
+    gains.write_f64::<BigEndian>(2458000.5).unwrap();
+    for v in &[1f32, 0., 0., 1.] {
+        gains.write_f32::<BigEndian>(*v).unwrap();
+    }
+    fs::write(dir.join("gains"), &gains).unwrap();
+
+    let mut ds = DataSet::open(&dir).unwrap();
+    ds.set_scalar_item("ngains", 2i32).unwrap();
+    ds.set_scalar_item("nsols", 1i32).unwrap();
+    ds.set_scalar_item("interval", 0.1f64).unwrap();
+
+    let table = GainsTable::read(&mut ds).unwrap();
+    assert_eq!(table.nants, 2);
+    assert_eq!(table.times, vec![2458000.5]);
+    assert_eq!(table.gain(0, 1, 0), Complex::new(0., 1.));
+    assert_eq!(table.solution_for_time(2458000.55), Some(0));
+
+    // Hostile dimensions must be rejected rather than overflow.
+    ds.set_scalar_item("ngains", i32::max_value() - 1).unwrap();
+    ds.set_scalar_item("nsols", i32::max_value()).unwrap();
+    assert!(table_size(&[usize::max_value(), 2], "gains").is_err());
+    assert!(GainsTable::read(&mut ds).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn calibration() {
+    let c = |re: f32, im: f32| Complex::new(re, im);
+
+    let cal = Calibration {
+        gains: Some(GainsTable {
+            nants: 2,
+            nfeeds: 1,
+            ntau: 0,
+            interval: 0.1,
+            times: vec![2458000.5],
+            gains: vec![c(2., 0.), c(0., 1.)],
+        }),
+        bandpass: Some(BandpassTable {
+            nants: 2,
+            nfeeds: 1,
+            windows: vec![BandpassWindow {
+                nschan: 2,
+                sfreq: 1.4,
+                sdf: 0.001,
+            }],
+            gains: vec![c(1., 0.), c(0., 0.), c(1., 0.), c(1., 0.)],
+        }),
+    };
+
+    let mut data = vec![c(1., 0.), c(1., 0.)];
+    let mut flags = vec![true, true];
+    cal.apply(2458000.5, 0, 1, -5, &mut data, &mut flags)
+        .unwrap();
+    assert_eq!(data, vec![c(0., -2.), c(0., 0.)]);
+    assert_eq!(flags, vec![true, false]);
+
+    // No solution within an interval of the time.
+    let mut data = vec![c(1., 0.), c(1., 0.)];
+    let mut flags = vec![true, true];
+    cal.apply(2458001.5, 0, 1, -5, &mut data, &mut flags)
+        .unwrap();
+    assert_eq!(flags, vec![false, false]);
+
+    assert_eq!(pol_feeds(-7), Some((0, 1)));
+    assert!(cal
+        .apply(2458000.5, 0, 1, 1, &mut data, &mut flags)
+        .is_err());
+    assert!(cal
+        .apply(2458000.5, 0, 2, -5, &mut data, &mut flags)
+        .is_err());
+}
@@ -0,0 +1,246 @@
+// Copyright 2017-2019 Peter Williams
+// Licensed under the MIT License.
+
+/*!
+
+Abstracted access to the files that make up a MIRIAD data set.
+
+A MIRIAD data set is a directory containing one file per "item". `DataSet`
+does all of its I/O through the `DatasetIo` trait, so that data sets need not
+live on a local filesystem. On Unix, `DirIo` accesses a data set directory
+on disk. `MemoryIo` holds the items of a data set in memory, which lets the
+decoders be used in environments with no filesystem at all, such as
+WebAssembly in the browser, given data that have been fetched by some other
+means. `PolicyIo` wraps another backend so that its streams retry transient
+failures and limit their throughput as an `IoPolicy` says.
+
+*/
+
+use rubbl_core::io::IoPolicy;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+
+/// A stream for reading an item's contents.
+pub trait ItemRead: io::Read + fmt::Debug {}
+
+impl<T: io::Read + fmt::Debug> ItemRead for T {}
+
+/// A stream for writing an item's contents.
+pub trait ItemWrite: io::Write + fmt::Debug {}
+
+impl<T: io::Write + fmt::Debug> ItemWrite for T {}
+
+/// A backend providing access to the items of a MIRIAD data set.
+pub trait DatasetIo: fmt::Debug {
+    /// Open the named item for reading.
+    ///
+    /// Returns an error of kind `NotFound` if there is no such item.
+    fn open_item(&self, name: &str) -> io::Result<Box<ItemRead>>;
+
+    /// Get the size of the named item, in bytes.
+    fn item_size(&self, name: &str) -> io::Result<u64>;
+
+    /// List the names of all of the items in the data set.
+    fn list_items(&self) -> io::Result<Vec<String>>;
+
+    /// Create the named item, replacing it if it already exists, and open it
+    /// for writing.
+    fn create_item(&mut self, name: &str) -> io::Result<Box<ItemWrite>>;
+
+    /// Open the named item for appending, creating it if necessary.
+    fn append_item(&mut self, name: &str) -> io::Result<Box<ItemWrite>>;
+}
+
+/// Access to a data set stored as a directory on the local filesystem.
+///
+/// The directory is opened once, so that the data set remains accessible
+/// even if it is renamed while in use.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct DirIo(::openat::Dir);
+
+#[cfg(unix)]
+impl DirIo {
+    /// Open the data set directory at *path*.
+    pub fn open<P: ::openat::AsPath>(path: P) -> io::Result<Self> {
+        Ok(DirIo(::openat::Dir::open(path)?))
+    }
+}
+
+#[cfg(unix)]
+impl DatasetIo for DirIo {
+    fn open_item(&self, name: &str) -> io::Result<Box<ItemRead>> {
+        Ok(Box::new(self.0.open_file(name)?))
+    }
+
+    fn item_size(&self, name: &str) -> io::Result<u64> {
+        Ok(self.0.open_file(name)?.metadata()?.len())
+    }
+
+    fn list_items(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+
+        for maybe_entry in self.0.list_dir(".")? {
+            // Item names are always ASCII, so we can ignore anything else.
+            if let Some(s) = maybe_entry?.file_name().to_str() {
+                names.push(s.to_owned());
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn create_item(&mut self, name: &str) -> io::Result<Box<ItemWrite>> {
+        Ok(Box::new(self.0.write_file(name, 0o666)?))
+    }
+
+    fn append_item(&mut self, name: &str) -> io::Result<Box<ItemWrite>> {
+        Ok(Box::new(self.0.append_file(name, 0o666)?))
+    }
+}
+
+/// Access to the items of a data set through another backend, with every
+/// item stream following an `IoPolicy`.
+///
+/// Reads and writes that fail transiently are retried, and the throughput
+/// of each stream may be limited, so that long conversions of data sets on
+/// network filesystems survive brief outages. Listing items and getting
+/// their sizes are done once, without retries.
+#[derive(Debug)]
+pub struct PolicyIo<I: DatasetIo> {
+    inner: I,
+    policy: IoPolicy,
+}
+
+impl<I: DatasetIo> PolicyIo<I> {
+    /// Access the items of *inner* following *policy*.
+    pub fn new(inner: I, policy: IoPolicy) -> Self {
+        PolicyIo {
+            inner: inner,
+            policy: policy,
+        }
+    }
+}
+
+impl<I: DatasetIo> DatasetIo for PolicyIo<I> {
+    fn open_item(&self, name: &str) -> io::Result<Box<ItemRead>> {
+        Ok(Box::new(self.policy.reader(self.inner.open_item(name)?)))
+    }
+
+    fn item_size(&self, name: &str) -> io::Result<u64> {
+        self.inner.item_size(name)
+    }
+
+    fn list_items(&self) -> io::Result<Vec<String>> {
+        self.inner.list_items()
+    }
+
+    fn create_item(&mut self, name: &str) -> io::Result<Box<ItemWrite>> {
+        Ok(Box::new(self.policy.writer(self.inner.create_item(name)?)))
+    }
+
+    fn append_item(&mut self, name: &str) -> io::Result<Box<ItemWrite>> {
+        Ok(Box::new(self.policy.writer(self.inner.append_item(name)?)))
+    }
+}
+
+/// A read-only data set held in memory.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryIo {
+    items: HashMap<String, Rc<[u8]>>,
+}
+
+impl MemoryIo {
+    /// Create a new, empty data set.
+    pub fn new() -> Self {
+        MemoryIo::default()
+    }
+
+    /// Add an item to the data set, replacing any existing item of the same
+    /// name.
+    pub fn insert<S: Into<String>>(&mut self, name: S, data: Vec<u8>) {
+        self.items.insert(name.into(), Rc::from(data));
+    }
+}
+
+impl DatasetIo for MemoryIo {
+    fn open_item(&self, name: &str) -> io::Result<Box<ItemRead>> {
+        match self.items.get(name) {
+            Some(data) => Ok(Box::new(io::Cursor::new(data.clone()))),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no such item \"{}\"", name),
+            )),
+        }
+    }
+
+    fn item_size(&self, name: &str) -> io::Result<u64> {
+        match self.items.get(name) {
+            Some(data) => Ok(data.len() as u64),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no such item \"{}\"", name),
+            )),
+        }
+    }
+
+    fn list_items(&self) -> io::Result<Vec<String>> {
+        Ok(self.items.keys().cloned().collect())
+    }
+
+    fn create_item(&mut self, _name: &str) -> io::Result<Box<ItemWrite>> {
+        Err(read_only_error())
+    }
+
+    fn append_item(&mut self, _name: &str) -> io::Result<Box<ItemWrite>> {
+        Err(read_only_error())
+    }
+}
+
+fn read_only_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "in-memory data sets are read-only",
+    )
+}
+
+#[cfg(test)]
+#[test]
+fn memory_data_set() {
+    use super::{DataSet, Type};
+
+    // One small item, "nchan" = 5 as an int32, in the header.
+    let mut header = vec![0u8; 24];
+    header[..5].copy_from_slice(b"nchan");
+    header[15] = 8;
+    header[19] = 2;
+    header[23] = 5;
+
+    let mut mem = MemoryIo::new();
+    mem.insert("header", header);
+    mem.insert("history", b"line one\nline two\n".to_vec());
+
+    let mut ds =
+        DataSet::open_with(Box::new(PolicyIo::new(mem.clone(), IoPolicy::default()))).unwrap();
+    let nchan: i32 = ds.get("nchan").unwrap().unwrap().read_scalar().unwrap();
+    assert_eq!(nchan, 5);
+
+    let mut ds = DataSet::open_with(Box::new(mem)).unwrap();
+    let nchan: i32 = ds.get("nchan").unwrap().unwrap().read_scalar().unwrap();
+    assert_eq!(nchan, 5);
+
+    let lines: Vec<String> = ds
+        .get("history")
+        .unwrap()
+        .unwrap()
+        .into_lines()
+        .unwrap()
+        .map(|l| l.unwrap())
+        .collect();
+    assert_eq!(lines, vec!["line one", "line two"]);
+
+    assert!(ds.get("corr").unwrap().is_none());
+    assert!(ds.create_large_item("wcorr", Type::Int32).is_err());
+}
@@ -11,16 +11,19 @@ extern crate byteorder;
 extern crate failure;
 #[macro_use]
 extern crate failure_derive;
+#[cfg(unix)]
 extern crate openat;
 extern crate rubbl_core;
 extern crate rubbl_visdata;
 
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 use failure::Error;
-use rubbl_core::io::{AligningReader, AligningWriter, EofReadExactExt};
+use rubbl_core::dryrun::{ChangePlan, DryRun};
+use rubbl_core::io::{
+    AligningReader, AligningWriter, EofReadExactExt, IoPolicy, OpenResultExt, SizeLimit,
+};
 use rubbl_core::Complex;
 use std::collections::HashMap;
-use std::fs;
 use std::io;
 use std::io::prelude::*;
 
@@ -31,7 +34,10 @@ macro_rules! mirerr {
     }
 }
 
+pub mod cal;
+pub mod dsio;
 pub mod mask;
+pub mod text;
 pub mod visdata;
 
 /// The maximum length of the name of a dataset "item", in bytes.
@@ -643,8 +649,8 @@ impl InternalItemInfo {
         }
     }
 
-    pub fn new_large(dir: &mut openat::Dir, name: &str) -> Result<Self, Error> {
-        let mut f = dir.open_file(name)?;
+    pub fn new_large(io: &dsio::DatasetIo, name: &str) -> Result<Self, Error> {
+        let mut f = io.open_item(name)?;
         let mut size_offset = 4;
         let mut type_buf = [0u8; 4];
 
@@ -678,7 +684,7 @@ impl InternalItemInfo {
             }
         };
 
-        let data_size = f.metadata()?.len() - size_offset;
+        let data_size = io.item_size(name)? - size_offset;
 
         if data_size % ty.size() as u64 != 0 {
             return mirerr!("non-integral number of elements in {}", name);
@@ -702,8 +708,8 @@ impl InternalItemInfo {
     }
 }
 
-pub type ReadStream = AligningReader<io::BufReader<fs::File>>;
-pub type WriteStream = AligningWriter<io::BufWriter<fs::File>>;
+pub type ReadStream = AligningReader<io::BufReader<Box<dsio::ItemRead>>>;
+pub type WriteStream = AligningWriter<io::BufWriter<Box<dsio::ItemWrite>>>;
 
 #[derive(Debug)]
 pub struct Item<'a> {
@@ -744,8 +750,12 @@ impl<'a> Item<'a> {
 
         match self.info.storage {
             ItemStorage::Small(ref data) => T::vec_from_miriad_bytes(data),
-            ItemStorage::Large(_) => {
-                let mut f = self.dset.dir.open_file(self.name)?;
+            ItemStorage::Large(n) => {
+                self.dset
+                    .size_limit
+                    .check(n as u64 * self.info.ty.size() as u64)?;
+
+                let mut f = self.dset.io.open_item(self.name)?;
 
                 if self.info.ty != Type::Text {
                     let align = std::cmp::max(4, self.info.ty.alignment()) as usize;
@@ -772,7 +782,7 @@ impl<'a> Item<'a> {
         Ok(vec.into_iter().next().unwrap())
     }
 
-    pub fn into_lines(self) -> Result<io::Lines<io::BufReader<fs::File>>, Error> {
+    pub fn into_lines(self) -> Result<io::Lines<io::BufReader<Box<dsio::ItemRead>>>, Error> {
         if self.info.ty != Type::Text {
             return mirerr!("cannot read lines of non-text item {}", self.name);
         }
@@ -783,7 +793,7 @@ impl<'a> Item<'a> {
 
         // Text items don't need any alignment futzing so we don't have to
         // skip initial bytes.
-        Ok(io::BufReader::new(self.dset.dir.open_file(self.name)?).lines())
+        Ok(io::BufReader::new(self.dset.io.open_item(self.name)?).lines())
     }
 
     pub fn into_byte_stream(self) -> Result<ReadStream, Error> {
@@ -793,11 +803,18 @@ impl<'a> Item<'a> {
             return mirerr!("cannot turn small item {} into byte stream", self.name);
         }
 
-        let f = self.dset.dir.open_file(self.name)?;
+        let f = self.dset.io.open_item(self.name)?;
         let mut ar = AligningReader::new(io::BufReader::new(f));
 
-        if self.info.ty != Type::Text {
-            ar.align_to(std::cmp::max(4, self.info.ty.alignment()) as usize)?;
+        // Typed items begin with a four-byte type code, which we skip along
+        // with any padding that aligns the data after it.
+        match self.info.ty {
+            Type::Text | Type::Binary => {}
+            ty => {
+                let mut type_buf = [0u8; 4];
+                ar.read_exact(&mut type_buf)?;
+                ar.align_to(ty.alignment() as usize)?;
+            }
         }
 
         Ok(ar)
@@ -806,24 +823,48 @@ impl<'a> Item<'a> {
 
 #[derive(Debug)]
 pub struct DataSet {
-    dir: openat::Dir,
+    io: Box<dsio::DatasetIo>,
     items: HashMap<String, InternalItemInfo>,
     large_items_scanned: bool,
     needs_flush: bool,
+    size_limit: SizeLimit,
+    dry_run: Option<ChangePlan>,
 }
 
 impl DataSet {
+    /// Open the data set stored in the directory at *path*.
+    #[cfg(unix)]
     pub fn open<P: openat::AsPath>(path: P) -> Result<Self, Error> {
+        Self::open_with(Box::new(dsio::DirIo::open(path)?))
+    }
+
+    /// Open the data set stored in the directory at *path*, reading and
+    /// writing its items following *policy*.
+    #[cfg(unix)]
+    pub fn open_with_policy<P: openat::AsPath>(path: P, policy: &IoPolicy) -> Result<Self, Error> {
+        Self::open_with(Box::new(dsio::PolicyIo::new(
+            dsio::DirIo::open(path)?,
+            *policy,
+        )))
+    }
+
+    /// Open a data set whose items are accessed through *io*.
+    ///
+    /// This is how data sets are read when they do not live on a local
+    /// filesystem, e.g. from a `dsio::MemoryIo` in a WebAssembly build.
+    pub fn open_with(io: Box<dsio::DatasetIo>) -> Result<Self, Error> {
         let mut ds = DataSet {
-            dir: openat::Dir::open(path)?,
+            io: io,
             items: HashMap::new(),
             large_items_scanned: false,
             needs_flush: false,
+            size_limit: SizeLimit::default(),
+            dry_run: None,
         };
 
         // Parse the header
 
-        let mut header = AligningReader::new(io::BufReader::new(ds.dir.open_file("header")?));
+        let mut header = AligningReader::new(io::BufReader::new(ds.io.open_item("header")?));
         let mut buf = [0u8; 16];
 
         loop {
@@ -845,7 +886,14 @@ impl DataSet {
             let aligned_len = buf[15];
 
             let name = std::str::from_utf8(&buf[..name_len])?;
-            // TODO: validate "len": must be between 5 and 64
+
+            if aligned_len > 64 {
+                return mirerr!(
+                    "illegal header item {}: data length {} exceeds 64 bytes",
+                    name,
+                    aligned_len
+                );
+            }
 
             let (ty, data) = if aligned_len == 0 {
                 (Type::Binary, Vec::new())
@@ -864,6 +912,16 @@ impl DataSet {
                 // the type alignment values.
 
                 let align = std::cmp::max(4, ty.size());
+
+                if (aligned_len as usize) < align {
+                    return mirerr!(
+                        "illegal header item {}: data length {} is too small for type {}",
+                        name,
+                        aligned_len,
+                        ty
+                    );
+                }
+
                 header.align_to(align)?;
                 let n_bytes = aligned_len as usize - align;
 
@@ -892,24 +950,37 @@ impl DataSet {
         Ok(ds)
     }
 
-    fn scan_large_items(&mut self) -> Result<(), Error> {
-        for maybe_item in self.dir.list_dir(".")? {
-            let item = maybe_item?;
+    /// Get the limit on the size of the buffers that will be allocated when
+    /// reading data from this data set.
+    pub fn size_limit(&self) -> SizeLimit {
+        self.size_limit
+    }
 
-            if let Some(s) = item.file_name().to_str() {
-                if s == "header" {
-                    continue;
-                }
+    /// Set the limit on the size of the buffers that will be allocated when
+    /// reading data from this data set.
+    ///
+    /// Item reads and UV decoders created after this call will refuse to
+    /// allocate more than the specified amount of memory for any single
+    /// value, returning an error instead. This protects against corrupt
+    /// files with nonsensical length fields.
+    pub fn set_size_limit(&mut self, limit: SizeLimit) {
+        self.size_limit = limit;
+    }
 
-                if s.starts_with(".") {
-                    continue;
-                }
+    fn scan_large_items(&mut self) -> Result<(), Error> {
+        for s in self.io.list_items()? {
+            if s == "header" {
+                continue;
+            }
 
-                // TODO: could/should warn if a large item shadowing a small
-                // item is encountered.
-                let iii = InternalItemInfo::new_large(&mut self.dir, s)?;
-                self.items.insert(s.to_owned(), iii);
+            if s.starts_with(".") {
+                continue;
             }
+
+            // TODO: could/should warn if a large item shadowing a small
+            // item is encountered.
+            let iii = InternalItemInfo::new_large(&*self.io, &s)?;
+            self.items.insert(s, iii);
         }
 
         self.large_items_scanned = true;
@@ -945,7 +1016,7 @@ impl DataSet {
 
         if !self.items.contains_key(item_name) {
             // Assume it's an as-yet-unprobed large item on the filesystem.
-            let iii = match InternalItemInfo::new_large(&mut self.dir, item_name) {
+            let iii = match InternalItemInfo::new_large(&*self.io, item_name) {
                 Ok(iii) => iii,
                 Err(e) => {
                     match e.downcast::<io::Error>() {
@@ -972,6 +1043,59 @@ impl DataSet {
         }))
     }
 
+    /// Read and parse the "vartable" item of this data set, which lists the
+    /// UV variables used in the "visdata" item.
+    pub fn read_vartable(&mut self) -> Result<Vec<text::VarTableEntry>, Error> {
+        let mut entries = Vec::new();
+
+        for maybe_line in self.get("vartable").require_found()?.into_lines()? {
+            entries.push(text::VarTableEntry::parse(&maybe_line?)?);
+        }
+
+        Ok(entries)
+    }
+
+    /// Read and parse the "history" item of this data set. If the data set
+    /// has no history, an empty vector is returned.
+    pub fn read_history(&mut self) -> Result<Vec<text::HistoryEntry>, Error> {
+        let mut entries = Vec::new();
+
+        if let Some(item) = self.get("history")? {
+            for maybe_line in item.into_lines()? {
+                entries.push(text::HistoryEntry::parse(&maybe_line?));
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Append entries to the "history" item of this data set, creating it if
+    /// necessary.
+    pub fn append_history(&mut self, entries: &[text::HistoryEntry]) -> Result<(), Error> {
+        if let Some(ref mut plan) = self.dry_run {
+            let n_bytes = entries.iter().map(|e| e.to_string().len() as u64 + 1).sum();
+            plan.record(
+                "history",
+                "append lines",
+                entries.len() as u64,
+                Some(n_bytes),
+            );
+            return Ok(());
+        }
+
+        let mut stream = io::BufWriter::new(self.io.append_item("history")?);
+
+        for entry in entries {
+            writeln!(stream, "{}", entry)?;
+        }
+
+        stream.flush()?;
+
+        // Re-probe the size of the item next time someone asks for it.
+        self.items.remove("history");
+        Ok(())
+    }
+
     pub fn open_uv(&mut self) -> Result<visdata::Decoder, Error> {
         visdata::Decoder::create(self)
     }
@@ -995,7 +1119,16 @@ impl DataSet {
             return mirerr!("cannot create an item with a non-ASCII name");
         }
 
-        let mut stream = AligningWriter::new(io::BufWriter::new(self.dir.write_file(name, 0o666)?));
+        // In dry-run mode, we hand back a stream that discards its data so that
+        // callers can proceed as usual.
+        let file = if let Some(ref mut plan) = self.dry_run {
+            plan.record(name, format!("create {} item", ty), 0, None);
+            Box::new(io::sink())
+        } else {
+            self.io.create_item(name)?
+        };
+
+        let mut stream = AligningWriter::new(io::BufWriter::new(file));
 
         match ty {
             Type::Text | Type::Binary => {}
@@ -1051,6 +1184,15 @@ impl DataSet {
             );
         }
 
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record(
+                name,
+                format!("set {} header item", T::TYPE),
+                0,
+                Some(values.len() as u64 * T::TYPE.size() as u64),
+            );
+        }
+
         iii.ty = T::TYPE;
         self.needs_flush = true;
         Ok(())
@@ -1071,8 +1213,13 @@ impl DataSet {
             return Ok(());
         }
 
-        let mut stream =
-            AligningWriter::new(io::BufWriter::new(self.dir.write_file("header", 0o666)?));
+        if let Some(ref mut plan) = self.dry_run {
+            plan.record("header", "rewrite", 0, None);
+            self.needs_flush = false;
+            return Ok(());
+        }
+
+        let mut stream = AligningWriter::new(io::BufWriter::new(self.io.create_item("header")?));
 
         for (name, item) in &self.items {
             if let ItemStorage::Small(ref data) = item.storage {
@@ -1116,6 +1263,27 @@ impl DataSet {
     }
 }
 
+impl DryRun for DataSet {
+    fn set_dry_run(&mut self, enabled: bool) {
+        if !enabled {
+            self.dry_run = None;
+        } else if self.dry_run.is_none() {
+            self.dry_run = Some(ChangePlan::new());
+        }
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.dry_run.is_some()
+    }
+
+    fn take_change_plan(&mut self) -> ChangePlan {
+        match self.dry_run {
+            Some(ref mut plan) => std::mem::replace(plan, ChangePlan::new()),
+            None => ChangePlan::new(),
+        }
+    }
+}
+
 impl Drop for DataSet {
     fn drop(&mut self) {
         // cf: https://github.com/rust-lang/rust/issues/32677
@@ -1174,3 +1342,35 @@ impl<'a> Iterator for DataSetItemsIterator<'a> {
         })
     }
 }
+
+#[cfg(all(test, unix))]
+#[test]
+fn history_round_trip() {
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("rubbl-miriad-history-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("header"), b"").unwrap();
+
+    let mut ds = DataSet::open(&dir).unwrap();
+    assert_eq!(ds.read_history().unwrap(), Vec::new());
+
+    let first = vec![
+        text::HistoryEntry::new("ATLOD", "Miriad atlod: version 1.0"),
+        text::HistoryEntry::parse("a line with no task"),
+    ];
+    ds.append_history(&first).unwrap();
+
+    let second = vec![text::HistoryEntry::new("RUBBL", "appended")];
+    ds.append_history(&second).unwrap();
+
+    let mut expected = first.clone();
+    expected.extend(second);
+    assert_eq!(ds.read_history().unwrap(), expected);
+    assert_eq!(
+        DataSet::open(&dir).unwrap().read_history().unwrap(),
+        expected
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -3,13 +3,18 @@
 
 /*!
 
-Reading MIRIAD mask-format files, such as UV data flags.
+Reading and writing MIRIAD mask-format files, such as UV data flags.
+
+Masks are packed into big-endian 32-bit integers, of which only the low 31
+bits are used. The first flag in each word is stored in bit 0. A set bit
+means that the corresponding datum is good.
 
  */
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::io;
 
+/// Decode a stream of MIRIAD mask data into boolean flags.
 #[derive(Debug)]
 pub struct MaskDecoder<R: io::Read> {
     stream: R,
@@ -26,6 +31,7 @@ impl<R: io::Read> MaskDecoder<R> {
         }
     }
 
+    /// Fill *dest* with the next `dest.len()` flags from the stream.
     pub fn expand(&mut self, dest: &mut [bool]) -> Result<(), io::Error> {
         let mut ofs = 0;
         let mut cur = self.current_val;
@@ -61,6 +67,7 @@ impl<R: io::Read> MaskDecoder<R> {
     }
 }
 
+/// Encode boolean flags into a stream of MIRIAD mask data.
 #[derive(Debug)]
 pub struct MaskEncoder<W: io::Write> {
     stream: W,
@@ -74,11 +81,12 @@ impl<W: io::Write> MaskEncoder<W> {
         MaskEncoder {
             stream: stream,
             current_val: 0,
-            bits_left_in_current: 0,
+            bits_left_in_current: 31,
             closed: false,
         }
     }
 
+    /// Append the flags in *data* to the stream.
     pub fn append_mask(&mut self, data: &[bool]) -> Result<(), io::Error> {
         if self.closed {
             panic!("cannot append to mask after closing it");
@@ -123,6 +131,9 @@ impl<W: io::Write> MaskEncoder<W> {
         return Ok(());
     }
 
+    /// Write out any partially-filled final word and flush the stream. This
+    /// is done automatically when the encoder is dropped, but errors are
+    /// then ignored.
     pub fn close(&mut self) -> Result<(), io::Error> {
         if self.closed {
             return Ok(());
@@ -145,3 +156,26 @@ impl<W: io::Write> Drop for MaskEncoder<W> {
         }
     }
 }
+
+#[cfg(test)]
+#[test]
+fn mask_round_trip() {
+    let flags: Vec<bool> = (0..100).map(|i| i % 3 == 0 || i % 7 == 0).collect();
+    let mut buf = Vec::new();
+
+    {
+        let mut enc = MaskEncoder::new(&mut buf);
+        enc.append_mask(&flags[..10]).unwrap();
+        enc.append_mask(&flags[10..62]).unwrap();
+        enc.append_mask(&flags[62..]).unwrap();
+        enc.close().unwrap();
+    }
+
+    assert_eq!(buf.len(), 16);
+
+    let mut dec = MaskDecoder::new(&buf[..]);
+    let mut result = vec![false; 100];
+    dec.expand(&mut result[..40]).unwrap();
+    dec.expand(&mut result[40..]).unwrap();
+    assert_eq!(result, flags);
+}
@@ -0,0 +1,152 @@
+// Copyright 2017-2019 Peter Williams
+// Licensed under the MIT License.
+
+/*!
+
+Structured access to MIRIAD's text items.
+
+A few MIRIAD items, notably "history" and "vartable", are plain text files
+with one entry per line. This module parses those lines into more useful
+structures and formats them back out again.
+
+ */
+
+use failure::Error;
+use std::fmt;
+
+use super::Type;
+
+/// One line of a data set's "vartable" item, which declares the name and
+/// type of a UV variable.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct VarTableEntry {
+    /// The type of the variable.
+    pub ty: Type,
+
+    /// The name of the variable.
+    pub name: String,
+}
+
+impl VarTableEntry {
+    /// Parse a line of a vartable item.
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        let pieces: Vec<_> = line.split_whitespace().collect();
+
+        if pieces.len() != 2 {
+            return mirerr!("illegal vartable line: {}", line);
+        }
+
+        Ok(VarTableEntry {
+            ty: Type::try_from_abbrev(pieces[0])?,
+            name: pieces[1].to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for VarTableEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.ty.abbrev_char(), self.name)
+    }
+}
+
+/// One line of a data set's "history" item.
+///
+/// By convention, MIRIAD tasks prefix their history lines with their name in
+/// upper case followed by a colon, e.g. `UVCAT: Miriad UvCat: version 1.0`.
+/// When such a prefix is present it is split out into the `task` field.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct HistoryEntry {
+    /// The name of the task that wrote this entry, if it could be determined.
+    pub task: Option<String>,
+
+    /// The text of the entry, not including the task prefix.
+    pub text: String,
+}
+
+impl HistoryEntry {
+    /// Create a new history entry attributed to the named task.
+    pub fn new<S: Into<String>, T: Into<String>>(task: S, text: T) -> Self {
+        HistoryEntry {
+            task: Some(task.into()),
+            text: text.into(),
+        }
+    }
+
+    /// Parse a line of a history item.
+    ///
+    /// This never fails: lines without a recognizable task prefix are
+    /// returned with `task` set to None.
+    pub fn parse(line: &str) -> Self {
+        if let Some(idx) = line.find(':') {
+            let task = &line[..idx];
+
+            if !task.is_empty()
+                && task
+                    .bytes()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == b'_')
+            {
+                // The text is conventionally separated from the prefix by a
+                // single space, which we don't preserve.
+                let mut text = &line[idx + 1..];
+
+                if text.starts_with(' ') {
+                    text = &text[1..];
+                }
+
+                return HistoryEntry {
+                    task: Some(task.to_owned()),
+                    text: text.to_owned(),
+                };
+            }
+        }
+
+        HistoryEntry {
+            task: None,
+            text: line.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.task {
+            Some(ref task) => write!(f, "{}: {}", task, self.text),
+            None => f.write_str(&self.text),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn parse_text_lines() {
+    let e = VarTableEntry::parse("c corr").unwrap();
+    assert_eq!(
+        e,
+        VarTableEntry {
+            ty: Type::Complex64,
+            name: "corr".to_owned(),
+        }
+    );
+    assert_eq!(e.to_string(), "c corr");
+    assert_eq!(VarTableEntry::parse("d  ut ").unwrap().ty, Type::Float64);
+    assert!(VarTableEntry::parse("").is_err());
+    assert!(VarTableEntry::parse("i nchan extra").is_err());
+    assert!(VarTableEntry::parse("x nchan").is_err());
+
+    let h = HistoryEntry::parse("UVCAT: Miriad UvCat: version 1.0 17-Jan-2018");
+    assert_eq!(h.task.as_ref().map(|s| s.as_str()), Some("UVCAT"));
+    assert_eq!(h.text, "Miriad UvCat: version 1.0 17-Jan-2018");
+    assert_eq!(
+        h.to_string(),
+        "UVCAT: Miriad UvCat: version 1.0 17-Jan-2018"
+    );
+
+    let h = HistoryEntry::parse("ATLOD: vis=2018-01-17_0322.C3145");
+    assert_eq!(h, HistoryEntry::new("ATLOD", "vis=2018-01-17_0322.C3145"));
+
+    for line in &["", "no prefix here", ": empty task", "uvcat: lower case"] {
+        let h = HistoryEntry::parse(line);
+        assert_eq!(h.task, None);
+        assert_eq!(h.text, *line);
+    }
+}
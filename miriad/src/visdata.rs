@@ -16,15 +16,15 @@ TODO:
 
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 use failure::Error;
-use rubbl_core::io::{AligningReader, AligningWriter, OpenResultExt};
+use rubbl_core::io::{OpenResultExt, SizeLimit};
+use rubbl_visdata::baseline::{AntPair, MiriadBaselines};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io;
 use std::io::prelude::*;
 use std::slice;
 
-use super::{AnyMiriadValue, DataSet, MiriadMappedType, Type};
-use mask::MaskDecoder;
+use super::{AnyMiriadValue, DataSet, MiriadMappedType, ReadStream, Type, WriteStream};
+use mask::{MaskDecoder, MaskEncoder};
+use text::VarTableEntry;
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum ObsType {
@@ -90,36 +90,27 @@ pub struct Decoder {
     eff_vislen: u64,
     vars: Vec<UvVariable>,
     vars_by_name: HashMap<String, u8>,
-    stream: AligningReader<io::BufReader<File>>,
+    stream: ReadStream,
+    size_limit: SizeLimit,
 }
 
 impl Decoder {
     pub fn create(ds: &mut DataSet) -> Result<Self, Error> {
         let vislen = ds.get("vislen").require_found()?.read_scalar::<i64>()?;
+
+        if vislen < 4 {
+            return mirerr!("illegal \"vislen\" value {}", vislen);
+        }
+
         let mut vars = Vec::new();
         let mut vars_by_name = HashMap::new();
         let mut var_num = 0u8;
 
-        for maybe_line in ds.get("vartable").require_found()?.into_lines()? {
-            let line = maybe_line?;
-
-            if line.len() < 3 {
-                return mirerr!("illegal vartable line: {}", line);
-            }
-
-            let pieces: Vec<_> = line.split_whitespace().collect();
-
-            if pieces.len() != 2 {
-                return mirerr!("illegal vartable line: {}", line);
-            }
-
-            let ty = Type::try_from_abbrev(pieces[0])?;
-            let name = pieces[1];
-
-            vars.push(UvVariable::new(ty, name, var_num));
+        for entry in ds.read_vartable()? {
+            vars.push(UvVariable::new(entry.ty, &entry.name, var_num));
 
             // TODO: check for duplicates
-            vars_by_name.insert(name.to_owned(), var_num);
+            vars_by_name.insert(entry.name, var_num);
 
             if var_num == 255 {
                 return mirerr!("too many UV variables");
@@ -128,6 +119,7 @@ impl Decoder {
             var_num += 1;
         }
 
+        let size_limit = ds.size_limit();
         let stream = ds.get("visdata").require_found()?.into_byte_stream()?;
 
         Ok(Decoder {
@@ -135,9 +127,16 @@ impl Decoder {
             vars: vars,
             vars_by_name: vars_by_name,
             stream: stream,
+            size_limit: size_limit,
         })
     }
 
+    /// Set the limit on the size of any single UV variable value that will
+    /// be decoded. By default, this is inherited from the parent data set.
+    pub fn set_size_limit(&mut self, limit: SizeLimit) {
+        self.size_limit = limit;
+    }
+
     /// Get the current position into the bulk visibility data
     pub fn position(&self) -> u64 {
         self.stream.offset()
@@ -185,6 +184,7 @@ impl Decoder {
                         );
                     }
 
+                    self.size_limit.check(n_bytes as u64)?;
                     var.n_vals = (n_bytes / (var.ty.size() as i32)) as isize;
                     var.data.resize(n_bytes as usize, 0); // bit of slowness: zeroing out the data
                 }
@@ -294,6 +294,7 @@ impl Decoder {
                         );
                     }
 
+                    self.size_limit.check(n_bytes as u64)?;
                     var.n_vals = (n_bytes / (var.ty.size() as i32)) as isize;
                     var.data.resize(n_bytes as usize, 0); // bit of slowness: zeroing out the data
                     writeln!(dest, "size {}({}) = {}", var.name, varnum, var.n_vals)?;
@@ -363,95 +364,41 @@ impl<'a> Iterator for UvVariablesIterator<'a> {
 ///
 /// Denote the (validated) return value (ant1, ant2). Ant1 is always supposed
 /// to be less than or equal to ant2. The maximum allowed value of each is
-/// 2047. In Rubbl's convention, antenna numbers begin at 0; this is different
-/// than MIRIAD!
+/// 2046. In Rubbl's convention, antenna numbers begin at 0; this is different
+/// than MIRIAD! See `rubbl_visdata::baseline::MiriadBaselines`, which this
+/// wraps.
 ///
 /// Because of the antnum limitation we could return u16s, but ant numbers are
 /// often used as array indices, so it's more convenient to keep them as
 /// usizes.
 pub fn decode_baseline(bl_float: f32) -> Result<(usize, usize), Error> {
-    let bl = bl_float as isize;
-
-    let (ant1, ant2) = if bl > 65536 {
-        let ofs = bl - 65536;
-        let ant1 = ofs / 2048;
-        (ant1, (ofs - 2048 * ant1))
-    } else {
-        let ant1 = bl / 256;
-        (ant1, (bl - 256 * ant1))
-    };
-
-    if ant1 < 1 {
-        return mirerr!(
-            "illegal baseline value {:?}: resulting ant1 is < 1",
-            bl_float
-        );
-    }
-
-    if ant2 < 1 {
-        return mirerr!(
-            "illegal baseline value {:?}: resulting ant2 is < 1",
-            bl_float
-        );
-    }
-
-    if ant1 > 2048 {
-        return mirerr!(
-            "illegal baseline value {:?}: resulting ant1 is > 2048",
-            bl_float
-        );
-    }
-
-    if ant2 > 2048 {
-        return mirerr!(
-            "illegal baseline value {:?}: resulting ant2 is > 2048",
-            bl_float
-        );
-    }
-
-    if ant1 > ant2 {
-        return mirerr!(
-            "illegal baseline value {:?}: resulting ant1 is > ant2",
-            bl_float
-        );
+    match MiriadBaselines.decode_f32(bl_float) {
+        Ok(pair) => Ok((pair.ant1 as usize, pair.ant2 as usize)),
+        Err(e) => mirerr!("{}", e),
     }
-
-    Ok((ant1 as usize - 1, ant2 as usize - 1))
 }
 
 /// Encode a MIRIAD baseline value.
 ///
-/// Antenna numbers may be between 0 and 2047, and ant1 must be less than or
+/// Antenna numbers may be between 0 and 2046, and ant1 must be less than or
 /// equal to ant2. In Rubbl's convention, antenna numbers begin at 0; this is
 /// different than MIRIAD!
 pub fn encode_baseline(ant1: usize, ant2: usize) -> Result<f32, Error> {
-    if ant1 > 2047 {
-        return mirerr!(
-            "illegal baseline value: antenna1 is {} but limit is 2047",
-            ant1
-        );
-    }
-
-    if ant2 > 2047 {
-        return mirerr!(
-            "illegal baseline value: antenna2 is {} but limit is 2047",
-            ant2
-        );
-    }
+    let limit = MiriadBaselines::MAX_ANT as usize;
 
-    if ant1 > ant2 {
+    if ant1 > limit || ant2 > limit {
         return mirerr!(
-            "illegal baseline pair ({}, {}); ant1 may not exceed ant2",
+            "illegal baseline pair ({}, {}); the limit is {}",
             ant1,
-            ant2
+            ant2,
+            limit
         );
     }
 
-    Ok(if ant2 > 254 {
-        2048 * (ant1 + 1) + (ant2 + 1) + 65536
-    } else {
-        256 * (ant1 + 1) + (ant2 + 1)
-    } as f32)
+    match MiriadBaselines.encode_f32(AntPair::new(ant1 as u16, ant2 as u16)) {
+        Ok(bl) => Ok(bl),
+        Err(e) => mirerr!("{}", e),
+    }
 }
 
 /// A struct that adapts the MIRIAD uv format into our VisStream interface.
@@ -461,8 +408,9 @@ pub struct Reader {
     ncorr: u64,
     nwcorr: u64,
     decoder: Decoder,
-    flags: Option<MaskDecoder<AligningReader<io::BufReader<File>>>>,
-    wflags: Option<MaskDecoder<AligningReader<io::BufReader<File>>>>,
+    flags: Option<MaskDecoder<ReadStream>>,
+    wflags: Option<MaskDecoder<ReadStream>>,
+    record: UvRecord,
 }
 
 impl Reader {
@@ -501,8 +449,94 @@ impl Reader {
             decoder: decoder,
             flags: flags,
             wflags: wflags,
+            record: UvRecord::default(),
         })
     }
+
+    /// Read the next record, decoding its flags. As with `Decoder::next`,
+    /// returns Ok(false) if this was the last record in the data set.
+    ///
+    /// A data set without "flags" or "wflags" items has all of its data
+    /// marked good.
+    pub fn next(&mut self) -> Result<bool, Error> {
+        let more = self.decoder.next()?;
+        let nchan = channel_count(&self.decoder, "nchan", "corr")?;
+        let nwide = channel_count(&self.decoder, "nwide", "wcorr")?;
+
+        self.record.flags.clear();
+        self.record.flags.resize(nchan, true);
+        self.record.wide_flags.clear();
+        self.record.wide_flags.resize(nwide, true);
+
+        if let Some(ref mut flags) = self.flags {
+            flags.expand(&mut self.record.flags)?;
+        }
+
+        if let Some(ref mut wflags) = self.wflags {
+            wflags.expand(&mut self.record.wide_flags)?;
+        }
+
+        Ok(more)
+    }
+
+    /// Get the type of correlations stored in the data set.
+    pub fn obstype(&self) -> ObsType {
+        self.obstype
+    }
+
+    /// Get the decoder that holds the UV variables of the current record.
+    pub fn decoder(&self) -> &Decoder {
+        &self.decoder
+    }
+
+    /// Get the flags of the current record.
+    pub fn record(&self) -> &UvRecord {
+        &self.record
+    }
+}
+
+/// The per-channel flags of a UV record. As in MIRIAD, `true` means that the
+/// corresponding datum is good.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UvRecord {
+    /// The flags of the spectral channels, one per value of "corr".
+    pub flags: Vec<bool>,
+
+    /// The flags of the wideband channels, one per value of "wcorr".
+    pub wide_flags: Vec<bool>,
+}
+
+/// Get the number of channels in the current record from the UV variable
+/// *count_name*, falling back to the number of complex values in
+/// *data_name*.
+fn channel_count(decoder: &Decoder, count_name: &str, data_name: &str) -> Result<usize, Error> {
+    if let Some(var) = decoder.lookup_variable(count_name) {
+        if decoder.get_var(var).n_vals() > 0 {
+            if decoder.get_var(var).type_() != Type::Int32 {
+                return mirerr!("UV variable \"{}\" is not an integer", count_name);
+            }
+
+            let n = decoder.get_scalar::<i32>(var);
+
+            if n < 0 {
+                return mirerr!("illegal \"{}\" value {}", count_name, n);
+            }
+
+            return Ok(n as usize);
+        }
+    }
+
+    Ok(match decoder.lookup_variable(data_name) {
+        Some(var) => {
+            let var = decoder.get_var(var);
+
+            match var.type_() {
+                Type::Complex64 if var.n_vals() > 0 => var.n_vals() as usize,
+                _ => 0,
+            }
+        }
+        None => 0,
+    })
 }
 
 /// A struct that holds state for writing a variable stream in the MIRIAD UV
@@ -512,12 +546,14 @@ pub struct Encoder {
     eff_vislen: u64,
     vars: Vec<UvVariable>,
     vars_by_name: HashMap<String, u8>,
-    stream: AligningWriter<io::BufWriter<File>>,
+    stream: WriteStream,
     tot_nschan: i64,
     tot_nwchan: i64,
     ncorr: i64,
     nwcorr: i64,
     flushed: bool,
+    flags: Option<MaskEncoder<WriteStream>>,
+    wflags: Option<MaskEncoder<WriteStream>>,
 }
 
 impl Encoder {
@@ -531,7 +567,14 @@ impl Encoder {
         for var in &mut vars {
             var.n_vals = -1;
             var.data.clear();
-            writeln!(vartable, "{} {}", var.ty.abbrev_char(), var.name)?;
+            writeln!(
+                vartable,
+                "{}",
+                VarTableEntry {
+                    ty: var.ty,
+                    name: var.name.clone(),
+                }
+            )?;
         }
 
         let stream = ds.create_large_item("visdata", Type::Binary)?;
@@ -546,6 +589,8 @@ impl Encoder {
             ncorr: 0,
             nwcorr: 0,
             flushed: false,
+            flags: None,
+            wflags: None,
         })
     }
 
@@ -669,6 +714,45 @@ impl Encoder {
         Ok(self.stream.write_all(EOR)?)
     }
 
+    /// Create the "flags" and "wflags" items of the data set, so that the
+    /// flags of each record can be written with `write_flags`.
+    pub fn create_flags(&mut self, ds: &mut DataSet) -> Result<(), Error> {
+        self.flags = Some(MaskEncoder::new(
+            ds.create_large_item("flags", Type::Int32)?,
+        ));
+        self.wflags = Some(MaskEncoder::new(
+            ds.create_large_item("wflags", Type::Int32)?,
+        ));
+        Ok(())
+    }
+
+    /// Write the flags of the current record. `create_flags` must have been
+    /// called first.
+    pub fn write_flags(&mut self, record: &UvRecord) -> Result<(), Error> {
+        match (&mut self.flags, &mut self.wflags) {
+            (&mut Some(ref mut flags), &mut Some(ref mut wflags)) => {
+                flags.append_mask(&record.flags)?;
+                wflags.append_mask(&record.wide_flags)?;
+                Ok(())
+            }
+            _ => mirerr!("cannot write UV flags without first creating the flag items"),
+        }
+    }
+
+    /// Finish writing the "flags" and "wflags" items. This happens when the
+    /// encoder is dropped, but errors are then ignored.
+    pub fn close_flags(&mut self) -> Result<(), Error> {
+        if let Some(ref mut flags) = self.flags {
+            flags.close()?;
+        }
+
+        if let Some(ref mut wflags) = self.wflags {
+            wflags.close()?;
+        }
+
+        Ok(())
+    }
+
     /// Returns the number of visdata bytes written thus far.
     pub fn flush(&mut self, ds: &mut DataSet) -> Result<u64, Error> {
         ds.set_scalar_item("ncorr", self.ncorr)?;
@@ -684,3 +768,78 @@ impl Encoder {
 // anything that panics inside drop() because that can lead to aborts if
 // something is dropped during unwinding. So we just silently let things go
 // wrong. Cf. https://github.com/rust-lang/rust/issues/32677 .
+
+#[cfg(all(test, unix))]
+#[test]
+fn flagged_records() {
+    use rubbl_core::Complex;
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("rubbl-miriad-flags-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("header"), b"").unwrap();
+
+    // An encoder is made from a template decoder, so start with an empty
+    // UV data set that has the variables we want.
+    let mut ds = DataSet::open(&dir).unwrap();
+
+    {
+        let mut vartable = ds.create_large_item("vartable", Type::Text).unwrap();
+        for (ty, name) in &[(Type::Int32, "nchan"), (Type::Complex64, "corr")] {
+            let entry = VarTableEntry {
+                ty: *ty,
+                name: name.to_string(),
+            };
+            writeln!(vartable, "{}", entry).unwrap();
+        }
+        ds.create_large_item("visdata", Type::Binary).unwrap();
+    }
+
+    ds.set_scalar_item("vislen", 4i64).unwrap();
+    let template = Decoder::create(&mut ds).unwrap();
+
+    let records = vec![
+        UvRecord {
+            flags: vec![true, false, true],
+            wide_flags: Vec::new(),
+        },
+        UvRecord {
+            flags: (0..40).map(|i| i % 3 != 0).collect(),
+            wide_flags: Vec::new(),
+        },
+    ];
+
+    {
+        let mut enc = ds.new_uv_like(&template).unwrap();
+        enc.create_flags(&mut ds).unwrap();
+
+        for rec in &records {
+            let n = rec.flags.len();
+            enc.write_scalar("nchan", n as i32).unwrap();
+            enc.write("corr", &vec![Complex::new(1f32, 0.); n]).unwrap();
+            enc.write_flags(rec).unwrap();
+            enc.finish_record().unwrap();
+        }
+
+        enc.flush(&mut ds).unwrap();
+        enc.close_flags().unwrap();
+    }
+
+    ds.set_scalar_item("obstype", "crosscorrelation".to_owned())
+        .unwrap();
+    ds.flush().unwrap();
+
+    let mut ds = DataSet::open(&dir).unwrap();
+    let mut reader = Reader::create(&mut ds).unwrap();
+    let mut seen = Vec::new();
+    let mut more = true;
+
+    while more {
+        more = reader.next().unwrap();
+        seen.push(reader.record().clone());
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(reader.obstype(), ObsType::Cross);
+    assert_eq!(seen, records);
+}
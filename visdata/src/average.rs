@@ -0,0 +1,281 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Averaging visibilities into bins, with control over partial flagging.
+
+Averaging in time or frequency combines several input samples into each
+output sample. When some of the inputs of a bin are flagged, there is a
+choice to make, and it materially changes the results: the output can be
+flagged outright, or be the average of the remaining inputs, which then
+represent less data and possibly a different effective time or frequency.
+`BinAverager` accumulates the inputs of a set of bins and combines them
+according to a `FlagPolicy`:
+
+- `drop` flags every output that has a flagged input;
+- `renormalize` averages the unflagged inputs, giving the output the sum of
+  their weights, and flags only outputs whose inputs are all flagged;
+- `threshold=FRACTION` does the same, but also flags outputs for which less
+  than FRACTION of the inputs are unflagged.
+
+Bins whose inputs are all flagged are always flagged, with zero data and
+zero weight. Since the policy affects the science, tools should record it in
+the output's history; `FlagPolicy::history_message` describes it in words.
+
+*/
+
+use failure::err_msg;
+use rubbl_core::kernels;
+use rubbl_core::{Complex, Result};
+use std::fmt;
+
+/// How bins with some flagged inputs are averaged.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FlagPolicy {
+    /// Flag the output if any of its inputs is flagged.
+    Drop,
+
+    /// Average the unflagged inputs, weighting the output by the sum of
+    /// their weights. This is what CASA does.
+    #[default]
+    Renormalize,
+
+    /// Like `Renormalize`, but flag the output if the fraction of its inputs
+    /// that are unflagged is less than the value given, which is between 0
+    /// and 1.
+    Threshold(f64),
+}
+
+impl FlagPolicy {
+    /// Parse a policy, in the syntax described in the module documentation.
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+
+        match text {
+            "drop" => return Ok(FlagPolicy::Drop),
+            "renormalize" => return Ok(FlagPolicy::Renormalize),
+            _ => {}
+        }
+
+        if let Some(arg) = text.strip_prefix("threshold=") {
+            return match arg.trim().parse::<f64>() {
+                Ok(f) if (0. ..=1.).contains(&f) => Ok(FlagPolicy::Threshold(f)),
+                _ => Err(err_msg(format!(
+                    "invalid flagged fraction threshold \"{}\": it must be between 0 and 1",
+                    arg
+                ))),
+            };
+        }
+
+        Err(err_msg(format!(
+            "unrecognized flag averaging policy \"{}\"; expected \"drop\", \"renormalize\", \
+             or \"threshold=FRACTION\"",
+            text
+        )))
+    }
+
+    /// Describe the policy in a sentence, for recording in the history of
+    /// the data that it was used to produce.
+    pub fn history_message(self) -> String {
+        match self {
+            FlagPolicy::Drop => {
+                "Averaging flag policy drop: outputs with any flagged input were flagged."
+                    .to_owned()
+            }
+            FlagPolicy::Renormalize => "Averaging flag policy renormalize: outputs are the \
+                                        weighted average of their unflagged inputs."
+                .to_owned(),
+            FlagPolicy::Threshold(f) => format!(
+                "Averaging flag policy threshold={}: outputs are the weighted average of their \
+                 unflagged inputs, and were flagged if fewer than {}% of them were unflagged.",
+                f,
+                100. * f
+            ),
+        }
+    }
+
+    /// Decide whether an output with *n_good* unflagged inputs out of
+    /// *n_inputs* is flagged.
+    fn flags_output(self, n_good: u32, n_inputs: u32) -> bool {
+        if n_good == 0 {
+            return true;
+        }
+
+        match self {
+            FlagPolicy::Drop => n_good < n_inputs,
+            FlagPolicy::Renormalize => false,
+            FlagPolicy::Threshold(f) => f64::from(n_good) < f * f64::from(n_inputs),
+        }
+    }
+}
+
+impl fmt::Display for FlagPolicy {
+    /// Formats the policy in the syntax accepted by `FlagPolicy::parse`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FlagPolicy::Drop => f.write_str("drop"),
+            FlagPolicy::Renormalize => f.write_str("renormalize"),
+            FlagPolicy::Threshold(t) => write!(f, "threshold={}", t),
+        }
+    }
+}
+
+/// The averaged data of a set of bins, as returned by
+/// `BinAverager::finish`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AveragedBins {
+    /// The averaged visibilities.
+    pub data: Vec<Complex<f32>>,
+
+    /// The weights of the averaged visibilities.
+    pub weights: Vec<f32>,
+
+    /// The flags of the averaged visibilities: true if bad.
+    pub flags: Vec<bool>,
+}
+
+/// An accumulator of the inputs of a fixed number of bins.
+///
+/// Each call to `add` adds one input to every bin: to average spectra in
+/// time, for instance, make an averager with one bin per channel and add
+/// each spectrum in the time interval. The sums are accumulated with the
+/// kernels of `rubbl_core::kernels`.
+#[derive(Clone, Debug)]
+pub struct BinAverager {
+    sums: Vec<Complex<f32>>,
+    weight_sums: Vec<f32>,
+    n_inputs: Vec<u32>,
+    n_good: Vec<u32>,
+    good_data: Vec<Complex<f32>>,
+    good_weights: Vec<f32>,
+}
+
+impl BinAverager {
+    /// Create an averager of *n_bins* bins, with no inputs.
+    pub fn new(n_bins: usize) -> Self {
+        BinAverager {
+            sums: vec![Complex::new(0., 0.); n_bins],
+            weight_sums: vec![0.; n_bins],
+            n_inputs: vec![0; n_bins],
+            n_good: vec![0; n_bins],
+            good_data: vec![Complex::new(0., 0.); n_bins],
+            good_weights: vec![0.; n_bins],
+        }
+    }
+
+    /// Get the number of bins.
+    pub fn n_bins(&self) -> usize {
+        self.sums.len()
+    }
+
+    /// Add an input to each bin. Flagged inputs count towards the policy's
+    /// decisions but do not contribute to the averages.
+    ///
+    /// Panics if the slices do not each have one element per bin.
+    pub fn add(&mut self, data: &[Complex<f32>], weights: &[f32], flags: &[bool]) {
+        assert_eq!(data.len(), self.n_bins());
+        assert_eq!(weights.len(), self.n_bins());
+        assert_eq!(flags.len(), self.n_bins());
+
+        // Flagged data may be garbage, even NaN, so they are zeroed as well
+        // as given zero weight.
+        for i in 0..data.len() {
+            self.n_inputs[i] += 1;
+
+            if flags[i] {
+                self.good_data[i] = Complex::new(0., 0.);
+                self.good_weights[i] = 0.;
+            } else {
+                self.good_data[i] = data[i];
+                self.good_weights[i] = weights[i];
+                self.n_good[i] += 1;
+            }
+        }
+
+        kernels::accumulate_weighted(
+            &mut self.sums,
+            &mut self.weight_sums,
+            &self.good_data,
+            &self.good_weights,
+        );
+    }
+
+    /// Combine the inputs of each bin according to *policy*, and reset the
+    /// averager so that it can be used for the next set of bins.
+    ///
+    /// Outputs that the policy flags keep the average of their unflagged
+    /// inputs, if they have any, so that they can be inspected.
+    pub fn finish(&mut self, policy: FlagPolicy) -> AveragedBins {
+        let n = self.n_bins();
+        let mut out = AveragedBins {
+            data: self.sums.clone(),
+            weights: Vec::with_capacity(n),
+            flags: Vec::with_capacity(n),
+        };
+
+        for i in 0..n {
+            let wsum = self.weight_sums[i];
+            let norm = if self.n_good[i] > 0 && wsum > 0. {
+                1. / wsum
+            } else {
+                0.
+            };
+
+            self.good_data[i] = Complex::new(norm, 0.);
+            out.weights.push(if self.n_good[i] > 0 { wsum } else { 0. });
+            out.flags
+                .push(policy.flags_output(self.n_good[i], self.n_inputs[i]));
+
+            self.sums[i] = Complex::new(0., 0.);
+            self.weight_sums[i] = 0.;
+            self.n_inputs[i] = 0;
+            self.n_good[i] = 0;
+        }
+
+        kernels::multiply_in_place(&mut out.data, &self.good_data);
+        out
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn partial_flag_policies() {
+    let c = |re: f32| Complex::new(re, 0.);
+    let mut avg = BinAverager::new(3);
+
+    // Bin 0 has one of four inputs flagged, bin 1 three of four, bin 2 all.
+    let inputs = [
+        ([c(1.), c(1.), c(1.)], [false, false, true]),
+        ([c(2.), c(2.), c(2.)], [false, true, true]),
+        ([c(3.), c(3.), c(3.)], [false, true, true]),
+        ([c(9.), c(9.), c(9.)], [true, true, true]),
+    ];
+
+    let run = |avg: &mut BinAverager, policy| {
+        for (data, flags) in &inputs {
+            avg.add(data, &[1., 1., 1.], flags);
+        }
+
+        avg.finish(policy)
+    };
+
+    let out = run(&mut avg, FlagPolicy::Renormalize);
+    assert_eq!(out.data, vec![c(2.), c(1.), c(0.)]);
+    assert_eq!(out.weights, vec![3., 1., 0.]);
+    assert_eq!(out.flags, vec![false, false, true]);
+
+    let out = run(&mut avg, FlagPolicy::Drop);
+    assert_eq!(out.data, vec![c(2.), c(1.), c(0.)]);
+    assert_eq!(out.flags, vec![true, true, true]);
+
+    let out = run(&mut avg, FlagPolicy::Threshold(0.5));
+    assert_eq!(out.flags, vec![false, true, true]);
+
+    for text in &["drop", "renormalize", "threshold=0.25"] {
+        assert_eq!(FlagPolicy::parse(text).unwrap().to_string(), *text);
+    }
+
+    assert!(FlagPolicy::parse("threshold=1.5").is_err());
+    assert!(FlagPolicy::parse("keep").is_err());
+}
@@ -0,0 +1,358 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Numbering the baselines of an array.
+
+Data formats and correlators identify baselines by single numbers in
+several different ways, and converting between those numbers and antenna
+pairs is a rich source of off-by-one errors. This module gathers the
+conversions in one place. Throughout Rubbl, antenna numbers begin at 0, and
+the pairs that these schemes work with are *canonical*, with `ant1 <=
+ant2`; a reversed pair is an error rather than being silently swapped,
+since swapping the antennas conjugates the visibility.
+
+Each numbering scheme implements the `BaselineIndexing` trait:
+
+- `MiriadBaselines` is MIRIAD's `256 * (ant1 + 1) + (ant2 + 1)`, switching
+  to `2048 * (ant1 + 1) + (ant2 + 1) + 65536` for large antenna numbers.
+- `TriangularBaselines` numbers the baselines of an array of known size
+  consecutively from 0, optionally including autocorrelations, with either
+  antenna varying fastest. This is how correlators such as those of the MWA
+  and ALMA lay out their outputs.
+
+*/
+
+use failure::err_msg;
+use rubbl_core::Result;
+
+use super::{AntNum, BasePol};
+
+/// A pair of antennas making up a baseline.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct AntPair {
+    pub ant1: AntNum,
+    pub ant2: AntNum,
+}
+
+impl AntPair {
+    pub fn new(ant1: AntNum, ant2: AntNum) -> Self {
+        AntPair {
+            ant1: ant1,
+            ant2: ant2,
+        }
+    }
+
+    /// Test whether this pair is an autocorrelation.
+    pub fn is_auto(self) -> bool {
+        self.ant1 == self.ant2
+    }
+
+    /// Test whether this pair is in canonical order, with `ant1 <= ant2`.
+    pub fn is_canonical(self) -> bool {
+        self.ant1 <= self.ant2
+    }
+
+    /// Get this pair in canonical order, along with whether the antennas
+    /// had to be swapped, in which case the visibility must be conjugated.
+    pub fn canonical(self) -> (Self, bool) {
+        if self.is_canonical() {
+            (self, false)
+        } else {
+            (AntPair::new(self.ant2, self.ant1), true)
+        }
+    }
+}
+
+impl From<(AntNum, AntNum)> for AntPair {
+    fn from(t: (AntNum, AntNum)) -> Self {
+        AntPair::new(t.0, t.1)
+    }
+}
+
+impl From<AntPair> for (AntNum, AntNum) {
+    fn from(p: AntPair) -> Self {
+        (p.ant1, p.ant2)
+    }
+}
+
+impl From<BasePol> for AntPair {
+    fn from(bp: BasePol) -> Self {
+        AntPair::new(bp.ant1, bp.ant2)
+    }
+}
+
+/// A scheme for numbering baselines.
+pub trait BaselineIndexing {
+    /// Get the number of the baseline between the antennas of *pair*, which
+    /// must be in canonical order.
+    fn index(&self, pair: AntPair) -> Result<usize>;
+
+    /// Get the antenna pair of baseline number *index*.
+    fn pair(&self, index: usize) -> Result<AntPair>;
+}
+
+fn check_canonical(pair: AntPair) -> Result<()> {
+    if !pair.is_canonical() {
+        return Err(err_msg(format!(
+            "baseline {}-{} is not in canonical order",
+            pair.ant1, pair.ant2
+        )));
+    }
+
+    Ok(())
+}
+
+/// MIRIAD's baseline numbering, as used by its `baseline` UV variable.
+///
+/// Antenna numbers up to 2046 can be encoded: MIRIAD itself numbers antennas
+/// from 1, which is taken care of here, and its antenna 2048 cannot be told
+/// apart from antenna 0 of the next row of the extended encoding.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct MiriadBaselines;
+
+impl MiriadBaselines {
+    /// The largest antenna number that can be encoded.
+    pub const MAX_ANT: AntNum = 2046;
+
+    /// Encode *pair* as the floating-point value stored in MIRIAD files.
+    pub fn encode_f32(self, pair: AntPair) -> Result<f32> {
+        Ok(self.index(pair)? as f32)
+    }
+
+    /// Decode the floating-point value stored in MIRIAD files.
+    pub fn decode_f32(self, value: f32) -> Result<AntPair> {
+        if value < 0. || value.fract() != 0. {
+            return Err(err_msg(format!(
+                "illegal MIRIAD baseline value {:?}",
+                value
+            )));
+        }
+
+        self.pair(value as usize)
+    }
+}
+
+impl BaselineIndexing for MiriadBaselines {
+    fn index(&self, pair: AntPair) -> Result<usize> {
+        check_canonical(pair)?;
+
+        if pair.ant2 > Self::MAX_ANT {
+            return Err(err_msg(format!(
+                "antenna {} is too large for a MIRIAD baseline number; the limit is {}",
+                pair.ant2,
+                Self::MAX_ANT
+            )));
+        }
+
+        let (m1, m2) = (pair.ant1 as usize + 1, pair.ant2 as usize + 1);
+
+        Ok(if m2 > 255 {
+            2048 * m1 + m2 + 65536
+        } else {
+            256 * m1 + m2
+        })
+    }
+
+    fn pair(&self, index: usize) -> Result<AntPair> {
+        let (m1, m2) = if index > 65536 {
+            let ofs = index - 65536;
+            (ofs / 2048, ofs % 2048)
+        } else {
+            (index / 256, index % 256)
+        };
+
+        if m1 < 1 || m2 < 1 || m1 > m2 || m2 > Self::MAX_ANT as usize + 1 {
+            return Err(err_msg(format!("illegal MIRIAD baseline number {}", index)));
+        }
+
+        Ok(AntPair::new((m1 - 1) as AntNum, (m2 - 1) as AntNum))
+    }
+}
+
+/// The order in which `TriangularBaselines` are numbered.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TriangleOrder {
+    /// The second antenna varies fastest: 0-0, 0-1, 0-2, …, 1-1, 1-2, ….
+    /// The MWA correlators use this order.
+    Ant2Fastest,
+
+    /// The first antenna varies fastest: 0-0, 0-1, 1-1, 0-2, 1-2, 2-2, ….
+    /// ALMA binary data use this order. The number of a baseline does not
+    /// depend on the size of the array.
+    Ant1Fastest,
+}
+
+/// Consecutive numbering of all of the baselines of an array.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TriangularBaselines {
+    n_ant: usize,
+    autos: bool,
+    order: TriangleOrder,
+}
+
+impl TriangularBaselines {
+    /// Number the baselines of an array of *n_ant* antennas, including the
+    /// autocorrelations if *autos* is true, in the order *order*.
+    pub fn new(n_ant: usize, autos: bool, order: TriangleOrder) -> Self {
+        TriangularBaselines {
+            n_ant: n_ant,
+            autos: autos,
+            order: order,
+        }
+    }
+
+    /// Get the number of baselines.
+    pub fn n_baselines(&self) -> usize {
+        if self.autos {
+            self.n_ant * (self.n_ant + 1) / 2
+        } else {
+            self.n_ant * self.n_ant.saturating_sub(1) / 2
+        }
+    }
+
+    /// Iterate over the antenna pairs of the baselines, in order.
+    pub fn pairs<'a>(&'a self) -> impl Iterator<Item = AntPair> + 'a {
+        (0..self.n_baselines()).map(move |i| self.pair_unchecked(i))
+    }
+
+    /// 0 if autocorrelations are included, 1 if not: the smallest possible
+    /// value of `ant2 - ant1`.
+    fn gap(&self) -> usize {
+        if self.autos {
+            0
+        } else {
+            1
+        }
+    }
+
+    fn pair_unchecked(&self, index: usize) -> AntPair {
+        let d = self.gap();
+
+        match self.order {
+            TriangleOrder::Ant2Fastest => {
+                // Row a1 holds the n - a1 - d baselines starting at a1-(a1+d).
+                let mut a1 = 0;
+                let mut start = 0;
+
+                loop {
+                    let len = self.n_ant - a1 - d;
+
+                    if index < start + len {
+                        return AntPair::new(a1 as AntNum, (a1 + d + index - start) as AntNum);
+                    }
+
+                    start += len;
+                    a1 += 1;
+                }
+            }
+
+            TriangleOrder::Ant1Fastest => {
+                // Column a2 holds the a2 + 1 - d baselines ending at
+                // (a2-d)-a2, starting at index (a2 - d)(a2 + 1 - d) / 2.
+                let col = |a2: usize| (a2 - d) * (a2 + 1 - d) / 2;
+                let mut a2 = ((((8 * index + 1) as f64).sqrt() - 1.) / 2.) as usize + d;
+
+                while a2 > d && col(a2) > index {
+                    a2 -= 1;
+                }
+
+                while col(a2 + 1) <= index {
+                    a2 += 1;
+                }
+
+                AntPair::new((index - col(a2)) as AntNum, a2 as AntNum)
+            }
+        }
+    }
+}
+
+impl BaselineIndexing for TriangularBaselines {
+    fn index(&self, pair: AntPair) -> Result<usize> {
+        check_canonical(pair)?;
+
+        let (a1, a2) = (pair.ant1 as usize, pair.ant2 as usize);
+        let d = self.gap();
+
+        if a2 >= self.n_ant || a2 - a1 < d {
+            return Err(err_msg(format!(
+                "baseline {}-{} is not one of the {} baselines of the array",
+                a1,
+                a2,
+                self.n_baselines()
+            )));
+        }
+
+        Ok(match self.order {
+            TriangleOrder::Ant2Fastest => {
+                a1 * (self.n_ant - d) - a1 * a1.saturating_sub(1) / 2 + (a2 - a1 - d)
+            }
+            TriangleOrder::Ant1Fastest => (a2 - d) * (a2 + 1 - d) / 2 + a1,
+        })
+    }
+
+    fn pair(&self, index: usize) -> Result<AntPair> {
+        if index >= self.n_baselines() {
+            return Err(err_msg(format!(
+                "baseline number {} is out of range; there are only {} baselines",
+                index,
+                self.n_baselines()
+            )));
+        }
+
+        Ok(self.pair_unchecked(index))
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn baseline_numbering() {
+    let mir = MiriadBaselines;
+    assert_eq!(mir.index(AntPair::new(0, 1)).unwrap(), 258);
+    assert_eq!(
+        mir.index(AntPair::new(3, 300)).unwrap(),
+        2048 * 4 + 301 + 65536
+    );
+    assert_eq!(mir.decode_f32(258.).unwrap(), AntPair::new(0, 1));
+    assert!(mir.index(AntPair::new(1, 0)).is_err());
+    assert!(mir.pair(257 + 256).is_err());
+    assert!(mir.decode_f32(258.5).is_err());
+
+    for &pair in &[(0, 0), (0, 254), (254, 254), (0, 255), (2046, 2046)] {
+        let pair = AntPair::from(pair);
+        assert_eq!(mir.pair(mir.index(pair).unwrap()).unwrap(), pair);
+    }
+
+    assert_eq!(AntPair::new(5, 2).canonical(), (AntPair::new(2, 5), true));
+
+    for &autos in &[false, true] {
+        for &order in &[TriangleOrder::Ant2Fastest, TriangleOrder::Ant1Fastest] {
+            let tri = TriangularBaselines::new(5, autos, order);
+            let pairs: Vec<AntPair> = tri.pairs().collect();
+            assert_eq!(pairs.len(), tri.n_baselines());
+
+            for (i, &pair) in pairs.iter().enumerate() {
+                assert_eq!(tri.index(pair).unwrap(), i);
+                assert!(pair.is_canonical() && (autos || !pair.is_auto()));
+            }
+
+            let mut sorted = pairs.clone();
+            sorted.sort();
+            sorted.dedup();
+            assert_eq!(sorted.len(), pairs.len());
+            assert!(tri.pair(tri.n_baselines()).is_err());
+            assert!(tri.index(AntPair::new(0, 5)).is_err());
+        }
+    }
+
+    let tri = TriangularBaselines::new(4, false, TriangleOrder::Ant1Fastest);
+    assert_eq!(
+        tri.pairs()
+            .take(4)
+            .map(|p| p.into())
+            .collect::<Vec<(u16, u16)>>(),
+        vec![(0, 1), (0, 2), (1, 2), (0, 3)]
+    );
+    assert!(tri.index(AntPair::new(2, 2)).is_err());
+}
@@ -10,10 +10,20 @@ the files I'm working with.
 
  */
 
+extern crate failure;
 extern crate rubbl_core;
+#[macro_use]
+extern crate serde_json;
 
 use rubbl_core::Result;
 
+pub mod average;
+pub mod baseline;
+pub mod noise;
+pub mod phasescreen;
+pub mod streaming;
+pub mod waterfall;
+
 /// A "feed pol(arization)" is the polarization component sampled by a
 /// particular receptor on an radio antenna.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
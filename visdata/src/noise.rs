@@ -0,0 +1,178 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Models of the noise in visibilities, for simulating data.
+
+A `NoiseModel` gives the noise level of the visibilities of each baseline
+and adds random noise at that level to simulated data, drawing on any
+`rubbl_core::rng::RandomSource`; seed the source to make the noise
+reproducible. `ThermalNoise` implements the radiometer equation: given the
+system equivalent flux density (SEFD) of each antenna, the channel
+bandwidth Δν, and the integration time τ, the noise in each of the real and
+imaginary parts of a cross-correlation of antennas *i* and *j* is
+
+```text
+σ_ij = sqrt(SEFD_i SEFD_j / (2 Δν τ)) / η
+```
+
+where η is the correlator efficiency. Autocorrelations are real, so their
+noise is all in the real part, and is larger by a factor of √2. The weight
+of a visibility with this noise is 1/σ².
+
+*/
+
+use failure::err_msg;
+use rubbl_core::rng::RandomSource;
+use rubbl_core::units::Hz;
+use rubbl_core::{Complex, Result};
+
+/// A model of the noise in the visibilities of each baseline.
+pub trait NoiseModel {
+    /// Get the standard deviation of the noise in each of the real and
+    /// imaginary parts of the visibilities of the baseline between antennas
+    /// *ant1* and *ant2*. For autocorrelations, where *ant1* and *ant2* are
+    /// the same, this is the noise in the real part alone.
+    fn sigma(&self, ant1: usize, ant2: usize) -> f64;
+
+    /// Get the weight of the visibilities of a baseline: 1/σ².
+    fn weight(&self, ant1: usize, ant2: usize) -> f64 {
+        let sigma = self.sigma(ant1, ant2);
+        1. / (sigma * sigma)
+    }
+
+    /// Add random noise to the visibilities *data* of the baseline between
+    /// antennas *ant1* and *ant2*.
+    fn add_noise<R: RandomSource>(
+        &self,
+        rng: &mut R,
+        ant1: usize,
+        ant2: usize,
+        data: &mut [Complex<f32>],
+    ) {
+        let sigma = self.sigma(ant1, ant2);
+
+        for d in data {
+            let re = sigma * rng.normal();
+            let im = if ant1 == ant2 {
+                0.
+            } else {
+                sigma * rng.normal()
+            };
+            *d += Complex::new(re as f32, im as f32);
+        }
+    }
+}
+
+/// Thermal noise set by the SEFDs of the antennas, following the
+/// radiometer equation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThermalNoise {
+    sefds: Vec<f64>,
+    bandwidth: Hz,
+    integration_time: f64,
+    efficiency: f64,
+}
+
+impl ThermalNoise {
+    /// Create a model from the SEFD of each antenna in janskys, indexed by
+    /// antenna number, the bandwidth of a channel, and the integration time
+    /// in seconds. The correlator efficiency starts out as 1.
+    pub fn new(sefds: &[f64], bandwidth: Hz, integration_time: f64) -> Result<Self> {
+        if let Some(s) = sefds.iter().find(|&&s| !(s.is_finite() && s >= 0.)) {
+            return Err(err_msg(format!(
+                "SEFDs must be finite and nonnegative; got {}",
+                s
+            )));
+        }
+
+        if !(bandwidth.0 > 0. && integration_time > 0.) {
+            return Err(err_msg(format!(
+                "the bandwidth and integration time of a noise model must be positive; got {} \
+                 and {} s",
+                bandwidth, integration_time
+            )));
+        }
+
+        Ok(ThermalNoise {
+            sefds: sefds.to_vec(),
+            bandwidth: bandwidth,
+            integration_time: integration_time,
+            efficiency: 1.,
+        })
+    }
+
+    /// Set the correlator efficiency η, which is between 0 and 1.
+    pub fn set_efficiency(&mut self, efficiency: f64) -> Result<&mut Self> {
+        if !(efficiency > 0. && efficiency <= 1.) {
+            return Err(err_msg(format!(
+                "the correlator efficiency must be between 0 and 1; got {}",
+                efficiency
+            )));
+        }
+
+        self.efficiency = efficiency;
+        Ok(self)
+    }
+
+    /// Get the number of antennas.
+    pub fn n_antennas(&self) -> usize {
+        self.sefds.len()
+    }
+}
+
+impl NoiseModel for ThermalNoise {
+    /// Panics if either antenna number is out of range.
+    fn sigma(&self, ant1: usize, ant2: usize) -> f64 {
+        let n = (2. * self.bandwidth.0 * self.integration_time).sqrt();
+        let sigma = (self.sefds[ant1] * self.sefds[ant2]).sqrt() / (n * self.efficiency);
+
+        if ant1 == ant2 {
+            sigma * std::f64::consts::SQRT_2
+        } else {
+            sigma
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn thermal_noise() {
+    use rubbl_core::rng::SeededRng;
+
+    let mut model = ThermalNoise::new(&[400., 100.], Hz::from_mhz(1.), 2.).unwrap();
+    assert!((model.sigma(0, 1) - 0.1).abs() < 1e-12);
+    assert!((model.weight(0, 1) - 100.).abs() < 1e-9);
+    assert!((model.sigma(1, 1) - 0.05 * std::f64::consts::SQRT_2).abs() < 1e-12);
+
+    model.set_efficiency(0.5).unwrap();
+    assert!((model.sigma(0, 1) - 0.2).abs() < 1e-12);
+    assert!(model.set_efficiency(1.5).is_err());
+    assert!(ThermalNoise::new(&[-1.], Hz(1.), 1.).is_err());
+    assert!(ThermalNoise::new(&[1.], Hz(0.), 1.).is_err());
+
+    let add = |seed| {
+        let mut data = vec![Complex::new(1., 0.); 20_000];
+        model.add_noise(&mut SeededRng::new(seed), 0, 1, &mut data);
+        data
+    };
+
+    let data = add(7);
+    assert_eq!(data, add(7));
+    assert_ne!(data, add(8));
+
+    let n = data.len() as f64;
+    let var_re = data
+        .iter()
+        .map(|d| f64::from(d.re - 1.).powi(2))
+        .sum::<f64>()
+        / n;
+    let var_im = data.iter().map(|d| f64::from(d.im).powi(2)).sum::<f64>() / n;
+    assert!((var_re.sqrt() - 0.2).abs() < 0.005);
+    assert!((var_im.sqrt() - 0.2).abs() < 0.005);
+
+    let mut auto = vec![Complex::new(0., 0.); 4];
+    model.add_noise(&mut SeededRng::new(1), 1, 1, &mut auto);
+    assert!(auto.iter().all(|d| d.im == 0. && d.re != 0.));
+}
@@ -0,0 +1,281 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Simulated ionospheric and tropospheric phase screens.
+
+Turbulence in the ionosphere and troposphere delays the signals that reach
+each antenna by amounts that vary with time and with the direction of the
+source. Calibration algorithms must undo these corruptions, so simulated
+data that are to test them need to contain them. A `PhaseScreen` is a
+random phase pattern with Kolmogorov statistics, whose structure function
+is
+
+```text
+D(r) = <(φ(x + r) - φ(x))²> = 6.88 (r / r₀)^(5/3)
+```
+
+for separations between the inner and outer scales of the turbulence, where
+r₀ is the Fried parameter. The screen is a sum of many plane waves with
+random directions and phases, their wavenumbers spread logarithmically
+between the scales and their amplitudes set by the Kolmogorov power
+spectrum, so that it can be evaluated anywhere without a grid.
+
+The screen lies flat at a fixed height and is blown past the array at a
+fixed velocity ("frozen flow"). The phase seen by an antenna looking in a
+given direction is that of the screen where the line of sight pierces it,
+computed in the small-angle approximation. Ionospheric phases scale
+inversely with frequency and tropospheric ones in proportion to it. A
+visibility of antennas *i* and *j* is corrupted by multiplying it by
+exp(*i*(φ_i − φ_j)).
+
+Screens are generated from a `rubbl_core::rng::RandomSource`, so seeding it
+makes them reproducible.
+
+*/
+
+use failure::err_msg;
+use rubbl_core::rng::RandomSource;
+use rubbl_core::units::{Hz, Meters};
+use rubbl_core::{Complex, Result};
+use std::f64::consts::PI;
+
+/// The layer of the atmosphere that a screen models, which determines how
+/// its phases scale with frequency.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Medium {
+    /// A dispersive screen, with phases inversely proportional to
+    /// frequency.
+    Ionosphere,
+
+    /// A nondispersive screen, with phases proportional to frequency.
+    Troposphere,
+}
+
+/// The parameters of a phase screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhaseScreenOptions {
+    /// The layer of the atmosphere modeled.
+    pub medium: Medium,
+
+    /// The Fried parameter r₀ at the reference frequency: the separation
+    /// over which the RMS phase difference is about 1 radian.
+    pub fried_length: Meters,
+
+    /// The frequency at which the screen has the Fried parameter given.
+    pub reference_frequency: Hz,
+
+    /// The smallest scale of the turbulence.
+    pub inner_scale: Meters,
+
+    /// The largest scale of the turbulence.
+    pub outer_scale: Meters,
+
+    /// The height of the screen above the array.
+    pub height: Meters,
+
+    /// The velocity of the screen, east and north, in meters per second.
+    pub velocity: (f64, f64),
+
+    /// The number of plane waves summed to make the screen.
+    pub n_modes: usize,
+}
+
+impl Default for PhaseScreenOptions {
+    /// A moderately active ionosphere, as seen at 150 MHz.
+    fn default() -> Self {
+        PhaseScreenOptions {
+            medium: Medium::Ionosphere,
+            fried_length: Meters(10_000.),
+            reference_frequency: Hz::from_mhz(150.),
+            inner_scale: Meters(10.),
+            outer_scale: Meters(100_000.),
+            height: Meters(300_000.),
+            velocity: (100., 0.),
+            n_modes: 1000,
+        }
+    }
+}
+
+/// One plane wave of a screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Mode {
+    k: (f64, f64),
+    amplitude: f64,
+    phase: f64,
+}
+
+/// A random turbulent phase screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhaseScreen {
+    options: PhaseScreenOptions,
+    modes: Vec<Mode>,
+}
+
+impl PhaseScreen {
+    /// Generate a screen with the parameters *options*, drawing its random
+    /// structure from *rng*.
+    pub fn new<R: RandomSource>(rng: &mut R, options: &PhaseScreenOptions) -> Result<Self> {
+        let o = options;
+
+        if !(o.fried_length.0 > 0.
+            && o.reference_frequency.0 > 0.
+            && o.inner_scale.0 > 0.
+            && o.outer_scale.0 > o.inner_scale.0
+            && o.height.0 >= 0.)
+        {
+            return Err(err_msg(
+                "phase screens need a positive Fried length, reference frequency, and height, \
+                 and an outer scale larger than a positive inner scale",
+            ));
+        }
+
+        if o.n_modes == 0 {
+            return Err(err_msg("phase screens need at least one mode"));
+        }
+
+        // Each mode represents the power of the Kolmogorov spectrum,
+        // 0.023 r₀^(-5/3) f^(-11/3) in terms of the spatial frequency f =
+        // k/2π, integrated over one of a set of logarithmically spaced
+        // annuli in the wavenumber plane.
+        let k_min = 2. * PI / o.outer_scale.0;
+        let k_max = 2. * PI / o.inner_scale.0;
+        let log_step = (k_max / k_min).ln() / o.n_modes as f64;
+        let norm = 0.6 * 0.023 * (2. * PI).powf(8. / 3.) * o.fried_length.0.powf(-5. / 3.);
+
+        let modes = (0..o.n_modes)
+            .map(|i| {
+                let k_lo = k_min * (log_step * i as f64).exp();
+                let k_hi = k_min * (log_step * (i + 1) as f64).exp();
+                let variance = norm * (k_lo.powf(-5. / 3.) - k_hi.powf(-5. / 3.));
+                let k = (k_lo * k_hi).sqrt();
+                let theta = 2. * PI * rng.uniform();
+
+                Mode {
+                    k: (k * theta.cos(), k * theta.sin()),
+                    amplitude: (2. * variance).sqrt(),
+                    phase: 2. * PI * rng.uniform(),
+                }
+            })
+            .collect();
+
+        Ok(PhaseScreen {
+            options: options.clone(),
+            modes: modes,
+        })
+    }
+
+    /// Get the parameters of the screen.
+    pub fn options(&self) -> &PhaseScreenOptions {
+        &self.options
+    }
+
+    /// Get the phase of the screen at the reference frequency, in radians,
+    /// at the point *position* (east and north, in meters) of the screen's
+    /// own frame.
+    fn reference_phase(&self, position: (f64, f64)) -> f64 {
+        self.modes
+            .iter()
+            .map(|m| m.amplitude * (m.k.0 * position.0 + m.k.1 * position.1 + m.phase).cos())
+            .sum()
+    }
+
+    /// Get the phase, in radians, added at frequency *freq* to the signal
+    /// that reaches the antenna at *antenna* (east and north, in meters)
+    /// from the direction with direction cosines *direction* (*l* east and
+    /// *m* north of the zenith), *time* seconds after the start of the
+    /// simulation.
+    pub fn phase(&self, antenna: (f64, f64), direction: (f64, f64), time: f64, freq: Hz) -> f64 {
+        let o = &self.options;
+        let pierce = (
+            antenna.0 + o.height.0 * direction.0 - o.velocity.0 * time,
+            antenna.1 + o.height.0 * direction.1 - o.velocity.1 * time,
+        );
+        let scale = match o.medium {
+            Medium::Ionosphere => o.reference_frequency.0 / freq.0,
+            Medium::Troposphere => freq.0 / o.reference_frequency.0,
+        };
+
+        scale * self.reference_phase(pierce)
+    }
+
+    /// Corrupt the visibilities *data* of the baseline between the antennas
+    /// at *ant1* and *ant2*, one per channel at the frequencies *freqs*,
+    /// with the screen as seen in *direction* at *time*. See `phase` for
+    /// the meanings of the arguments.
+    ///
+    /// Panics if *data* and *freqs* have different lengths.
+    pub fn corrupt(
+        &self,
+        data: &mut [Complex<f32>],
+        freqs: &[Hz],
+        ant1: (f64, f64),
+        ant2: (f64, f64),
+        direction: (f64, f64),
+        time: f64,
+    ) {
+        assert_eq!(data.len(), freqs.len());
+
+        for (d, &freq) in data.iter_mut().zip(freqs) {
+            let dphi =
+                self.phase(ant1, direction, time, freq) - self.phase(ant2, direction, time, freq);
+            *d *= Complex::new(dphi.cos() as f32, dphi.sin() as f32);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn kolmogorov_screens() {
+    use rubbl_core::rng::SeededRng;
+
+    let options = PhaseScreenOptions {
+        fried_length: Meters(1000.),
+        inner_scale: Meters(1.),
+        outer_scale: Meters(1e7),
+        ..PhaseScreenOptions::default()
+    };
+    let screen = PhaseScreen::new(&mut SeededRng::new(3), &options).unwrap();
+    assert_eq!(
+        screen,
+        PhaseScreen::new(&mut SeededRng::new(3), &options).unwrap()
+    );
+
+    // The structure function at 100 m should be 6.88 (0.1)^(5/3) ≈ 0.148.
+    let mut rng = SeededRng::new(4);
+    let n = 4000;
+    let mut d = 0.;
+
+    for _ in 0..n {
+        let x = (1e5 * rng.uniform(), 1e5 * rng.uniform());
+        let theta = 2. * PI * rng.uniform();
+        let y = (x.0 + 100. * theta.cos(), x.1 + 100. * theta.sin());
+        let dphi = screen.reference_phase(x) - screen.reference_phase(y);
+        d += dphi * dphi;
+    }
+
+    let expected = 6.88 * 0.1f64.powf(5. / 3.);
+    assert!((d / n as f64 / expected - 1.).abs() < 0.25);
+
+    // Frozen flow, frequency scaling, and corruption.
+    let f = options.reference_frequency;
+    let p = screen.phase((0., 0.), (0., 0.), 0., f);
+    assert!((screen.phase((100., 0.), (0., 0.), 1., f) - p).abs() < 1e-9);
+    assert!((screen.phase((0., 0.), (0., 0.), 0., Hz(2. * f.0)) - p / 2.).abs() < 1e-9);
+
+    let mut data = vec![Complex::new(1., 0.); 2];
+    screen.corrupt(&mut data, &[f, f], (0., 0.), (0., 0.), (0.1, 0.), 5.);
+    assert_eq!(data, vec![Complex::new(1., 0.); 2]);
+    screen.corrupt(&mut data, &[f, f], (0., 0.), (500., 0.), (0.1, 0.), 5.);
+    assert!(data.iter().all(|d| (d.norm() - 1.).abs() < 1e-6));
+
+    assert!(PhaseScreen::new(
+        &mut rng,
+        &PhaseScreenOptions {
+            n_modes: 0,
+            ..PhaseScreenOptions::default()
+        }
+    )
+    .is_err());
+}
@@ -0,0 +1,490 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Ingesting visibilities from a live correlator.
+
+A correlator emits its output one integration at a time. A `LiveVisSource`
+delivers each integration as a `VisChunk`, and `ChunkVisStream` adapts any
+such source into a `VisStream`, so that live data can be processed and
+written out in the same way as data read from files.
+
+With the `tcp` feature enabled, `FramedVisSource` reads chunks from a byte
+stream such as a TCP connection. Each chunk is sent as a record framed in
+the manner of `rubbl_core::decode::write_framed_record` — a big-endian `u32`
+length followed by the payload — and `VisChunk::encode` produces such
+records, so that a sender can be written against this crate too. All
+numbers in the payload are big-endian:
+
+- the magic number `0x52564331` (`RVC1`);
+- the time and integration time, as `f64`s;
+- the number of channels and the number of basepols, as `u32`s;
+- for each basepol, its two antenna numbers as `u16`s and its polarization
+  as a `u8` (the index of the `VisPol` variant, in declaration order);
+- the visibilities of each channel of each basepol, as pairs of `f32`s;
+- the flags of each channel of each basepol, as `u8`s.
+
+Many correlators instead send their output using the SPEAD protocol. The
+`spead` submodule decodes it.
+
+To write chunks out in the zarr format, use the `ZarrSink` of the `zarr`
+submodule.
+
+Fixes such as conjugation or rescaling can be applied to the chunks of any
+source as they are read with the `transform` submodule.
+
+*/
+
+pub mod spead;
+pub mod transform;
+pub mod zarr;
+
+use failure::err_msg;
+use rubbl_core::decode::{write_framed_record, ByteCursor};
+use rubbl_core::nan::NanPolicy;
+use rubbl_core::units::{MjdSeconds, Seconds};
+use rubbl_core::{Complex, Result};
+
+use super::{BasePol, VisPol, VisStream};
+
+/// The magic number that begins an encoded `VisChunk`.
+const CHUNK_MAGIC: u32 = 0x5256_4331;
+
+/// The polarizations in the order of their codes in an encoded `VisChunk`.
+const VISPOL_CODES: [VisPol; 12] = [
+    VisPol::XX,
+    VisPol::XY,
+    VisPol::YX,
+    VisPol::YY,
+    VisPol::RR,
+    VisPol::RL,
+    VisPol::LR,
+    VisPol::LL,
+    VisPol::I,
+    VisPol::Q,
+    VisPol::U,
+    VisPol::V,
+];
+
+/// The visibilities of one integration.
+///
+/// The data and flags are stored basepol by basepol, with `n_chan` values
+/// for each.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VisChunk {
+    /// The midpoint of the integration (as in the `TIME` column of a
+    /// Measurement Set).
+    pub time: MjdSeconds,
+
+    /// The length of the integration.
+    pub int_time: Seconds,
+
+    /// The number of spectral channels.
+    pub n_chan: usize,
+
+    /// The basepols in the chunk.
+    pub basepols: Vec<BasePol>,
+
+    /// The visibilities.
+    pub data: Vec<Complex<f32>>,
+
+    /// The flags: true if a visibility is bad.
+    pub flags: Vec<bool>,
+}
+
+impl VisChunk {
+    /// Create an empty chunk.
+    pub fn new(time: MjdSeconds, int_time: Seconds, n_chan: usize) -> Self {
+        VisChunk {
+            time: time,
+            int_time: int_time,
+            n_chan: n_chan,
+            basepols: Vec::new(),
+            data: Vec::new(),
+            flags: Vec::new(),
+        }
+    }
+
+    /// Add the spectrum of a basepol to the chunk.
+    ///
+    /// Panics if *data* or *flags* do not have `n_chan` elements.
+    pub fn push(&mut self, basepol: BasePol, data: &[Complex<f32>], flags: &[bool]) {
+        assert_eq!(data.len(), self.n_chan);
+        assert_eq!(flags.len(), self.n_chan);
+        self.basepols.push(basepol);
+        self.data.extend_from_slice(data);
+        self.flags.extend_from_slice(flags);
+    }
+
+    /// Get the visibilities of the basepol at *index*.
+    pub fn data_for(&self, index: usize) -> &[Complex<f32>] {
+        &self.data[index * self.n_chan..(index + 1) * self.n_chan]
+    }
+
+    /// Get the flags of the basepol at *index*.
+    pub fn flags_for(&self, index: usize) -> &[bool] {
+        &self.flags[index * self.n_chan..(index + 1) * self.n_chan]
+    }
+
+    /// Apply *policy* to the visibilities of the chunk, returning the number
+    /// of non-finite values found.
+    pub fn apply_nan_policy(&mut self, policy: NanPolicy) -> Result<usize> {
+        policy.apply(&mut self.data, &mut self.flags)
+    }
+
+    /// Append the chunk to *out* as a framed record.
+    pub fn encode(&self, out: &mut Vec<u8>) -> Result<()> {
+        let mut payload = Vec::with_capacity(32 + 5 * self.basepols.len() + 9 * self.data.len());
+
+        payload.extend_from_slice(&CHUNK_MAGIC.to_be_bytes());
+        payload.extend_from_slice(&self.time.0.to_be_bytes());
+        payload.extend_from_slice(&self.int_time.0.to_be_bytes());
+        payload.extend_from_slice(&(self.n_chan as u32).to_be_bytes());
+        payload.extend_from_slice(&(self.basepols.len() as u32).to_be_bytes());
+
+        for bp in &self.basepols {
+            let code = VISPOL_CODES.iter().position(|&p| p == bp.pol).unwrap();
+            payload.extend_from_slice(&bp.ant1.to_be_bytes());
+            payload.extend_from_slice(&bp.ant2.to_be_bytes());
+            payload.push(code as u8);
+        }
+
+        for v in &self.data {
+            payload.extend_from_slice(&v.re.to_be_bytes());
+            payload.extend_from_slice(&v.im.to_be_bytes());
+        }
+
+        payload.extend(self.flags.iter().map(|&f| f as u8));
+        write_framed_record(out, &payload)?;
+        Ok(())
+    }
+
+    /// Decode a chunk from the payload of a framed record.
+    pub fn decode(payload: &[u8]) -> Result<Self> {
+        let mut cursor = ByteCursor::new(payload);
+
+        let magic = cursor.read_be_u32()?;
+
+        if magic != CHUNK_MAGIC {
+            return Err(err_msg(format!(
+                "bad magic number {:#010x} in visibility chunk",
+                magic
+            )));
+        }
+
+        let mut chunk = VisChunk::new(
+            MjdSeconds(cursor.read_be_f64()?),
+            Seconds(cursor.read_be_f64()?),
+            cursor.read_be_u32()? as usize,
+        );
+        let n_basepols = cursor.read_be_u32()? as usize;
+        let n_vis = n_basepols as u64 * chunk.n_chan as u64;
+
+        // Check the size before allocating anything, so that a corrupt
+        // header can't make us reserve a huge amount of memory.
+        let expected = n_vis
+            .checked_mul(9)
+            .and_then(|n| n.checked_add(5 * n_basepols as u64));
+
+        if expected != Some(cursor.remaining().len() as u64) {
+            return Err(err_msg(format!(
+                "visibility chunk of {} channels and {} basepols has the wrong size",
+                chunk.n_chan, n_basepols
+            )));
+        }
+
+        let n_vis = n_vis as usize;
+
+        for _ in 0..n_basepols {
+            let ant1 = cursor.read_be_u16()?;
+            let ant2 = cursor.read_be_u16()?;
+            let code = cursor.read_u8()?;
+            let pol = match VISPOL_CODES.get(code as usize) {
+                Some(p) => *p,
+                None => {
+                    return Err(err_msg(format!(
+                        "bad polarization code {} in visibility chunk",
+                        code
+                    )));
+                }
+            };
+            chunk.basepols.push(BasePol::new(ant1, ant2, pol));
+        }
+
+        chunk.data.reserve(n_vis);
+
+        for _ in 0..n_vis {
+            let re = cursor.read_be_f32()?;
+            let im = cursor.read_be_f32()?;
+            chunk.data.push(Complex::new(re, im));
+        }
+
+        chunk
+            .flags
+            .extend(cursor.take(n_vis)?.iter().map(|&b| b != 0));
+        Ok(chunk)
+    }
+}
+
+/// A source of visibilities that arrive one integration at a time.
+pub trait LiveVisSource {
+    /// Get the next chunk of visibilities, blocking until it is available.
+    ///
+    /// Returns `None` when the stream has ended cleanly.
+    fn next_chunk(&mut self) -> Result<Option<VisChunk>>;
+}
+
+/// A `VisStream` over the chunks delivered by a `LiveVisSource`.
+///
+/// Each record is one basepol of one chunk.
+#[derive(Debug)]
+pub struct ChunkVisStream<S> {
+    source: S,
+    chunk: Option<VisChunk>,
+    index: usize,
+    nan_policy: NanPolicy,
+    n_non_finite: u64,
+}
+
+impl<S: LiveVisSource> ChunkVisStream<S> {
+    /// Create a stream reading from *source*.
+    pub fn new(source: S) -> Self {
+        ChunkVisStream {
+            source: source,
+            chunk: None,
+            index: 0,
+            nan_policy: NanPolicy::default(),
+            n_non_finite: 0,
+        }
+    }
+
+    /// Set the policy applied to each chunk as it arrives. The default is
+    /// `NanPolicy::Propagate`.
+    pub fn set_nan_policy(&mut self, policy: NanPolicy) -> &mut Self {
+        self.nan_policy = policy;
+        self
+    }
+
+    /// Get the number of non-finite visibilities seen so far.
+    pub fn n_non_finite(&self) -> u64 {
+        self.n_non_finite
+    }
+
+    /// Get the chunk containing the current record, if there is one.
+    pub fn chunk(&self) -> Option<&VisChunk> {
+        self.chunk.as_ref()
+    }
+
+    /// Get the visibilities of the current record. This panics unless the
+    /// last call to `next` returned true.
+    pub fn data(&self) -> &[Complex<f32>] {
+        self.current_chunk().data_for(self.index)
+    }
+
+    /// Get the flags of the current record. This panics unless the last
+    /// call to `next` returned true.
+    pub fn flags(&self) -> &[bool] {
+        self.current_chunk().flags_for(self.index)
+    }
+
+    /// Get back the underlying source.
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+    fn current_chunk(&self) -> &VisChunk {
+        self.chunk.as_ref().expect("no current record in VisStream")
+    }
+}
+
+impl<S: LiveVisSource> VisStream for ChunkVisStream<S> {
+    fn next(&mut self) -> Result<bool> {
+        if let Some(ref chunk) = self.chunk {
+            if self.index + 1 < chunk.basepols.len() {
+                self.index += 1;
+                return Ok(true);
+            }
+        }
+
+        self.index = 0;
+
+        loop {
+            self.chunk = self.source.next_chunk()?;
+
+            if let Some(ref mut c) = self.chunk {
+                self.n_non_finite += c.apply_nan_policy(self.nan_policy)? as u64;
+            }
+
+            match self.chunk {
+                None => return Ok(false),
+                Some(ref c) if !c.basepols.is_empty() => return Ok(true),
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// Get the basepol of the current record. This panics unless the last
+    /// call to `next` returned true.
+    fn basepol(&self) -> BasePol {
+        self.current_chunk().basepols[self.index]
+    }
+}
+
+#[cfg(feature = "tcp")]
+pub use self::tcp::FramedVisSource;
+
+#[cfg(feature = "tcp")]
+mod tcp {
+    use failure::err_msg;
+    use rubbl_core::Result;
+    use std::io::{self, BufReader, Read};
+    use std::net::{TcpStream, ToSocketAddrs};
+
+    use super::{LiveVisSource, VisChunk};
+
+    /// A `LiveVisSource` that reads framed chunks from a byte stream.
+    ///
+    /// See the module documentation for the format of the stream.
+    #[derive(Debug)]
+    pub struct FramedVisSource<R: Read> {
+        inner: BufReader<R>,
+        max_record_size: usize,
+        buf: Vec<u8>,
+    }
+
+    impl FramedVisSource<TcpStream> {
+        /// Connect to a correlator that sends chunks over TCP.
+        pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+            let stream = TcpStream::connect(addr)?;
+            stream.set_nodelay(true)?;
+            Ok(Self::new(stream))
+        }
+    }
+
+    impl<R: Read> FramedVisSource<R> {
+        /// Create a source reading from *inner*. Records larger than 256 MiB
+        /// are rejected as corrupt.
+        pub fn new(inner: R) -> Self {
+            FramedVisSource {
+                inner: BufReader::new(inner),
+                max_record_size: 256 << 20,
+                buf: Vec::new(),
+            }
+        }
+
+        /// Set the size of the largest record that will be accepted, in
+        /// bytes.
+        pub fn set_max_record_size(&mut self, max_record_size: usize) -> &mut Self {
+            self.max_record_size = max_record_size;
+            self
+        }
+
+        /// Get back the underlying reader.
+        pub fn into_inner(self) -> R {
+            self.inner.into_inner()
+        }
+    }
+
+    impl<R: Read> LiveVisSource for FramedVisSource<R> {
+        fn next_chunk(&mut self) -> Result<Option<VisChunk>> {
+            let mut len = [0u8; 4];
+            let mut n_read = 0;
+
+            // The stream may only end at a record boundary.
+            while n_read < 4 {
+                match self.inner.read(&mut len[n_read..]) {
+                    Ok(0) if n_read == 0 => return Ok(None),
+                    Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                    Ok(n) => n_read += n,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            let len = u32::from_be_bytes(len) as usize;
+
+            if len > self.max_record_size {
+                return Err(err_msg(format!(
+                    "visibility record of {} bytes exceeds the limit of {}",
+                    len, self.max_record_size
+                )));
+            }
+
+            self.buf.resize(len, 0);
+            self.inner.read_exact(&mut self.buf)?;
+            VisChunk::decode(&self.buf).map(Some)
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn chunk_stream() {
+    use rubbl_core::decode::FramedRecords;
+
+    struct ListSource(Vec<VisChunk>);
+
+    impl LiveVisSource for ListSource {
+        fn next_chunk(&mut self) -> Result<Option<VisChunk>> {
+            if self.0.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(self.0.remove(0)))
+            }
+        }
+    }
+
+    // The second chunk is empty and should be skipped over by the stream.
+    let mut c1 = VisChunk::new(MjdSeconds(5.0e9), Seconds(8.), 2);
+    c1.push(
+        BasePol::new(0, 1, VisPol::XX),
+        &[Complex::new(1., -1.), Complex::new(2., 0.5)],
+        &[false, true],
+    );
+    c1.push(
+        BasePol::new(0, 1, VisPol::YY),
+        &[Complex::new(3., 0.), Complex::new(-4., 4.)],
+        &[false, false],
+    );
+    let c2 = VisChunk::new(MjdSeconds(5.0e9 + 8.), Seconds(8.), 2);
+    let mut c3 = VisChunk::new(MjdSeconds(5.0e9 + 16.), Seconds(8.), 2);
+    c3.push(
+        BasePol::new(1, 2, VisPol::LR),
+        &[Complex::new(0., 0.), Complex::new(7., 7.)],
+        &[true, true],
+    );
+    let chunks = vec![c1, c2, c3];
+
+    let mut buf = Vec::new();
+
+    for c in &chunks {
+        c.encode(&mut buf).unwrap();
+    }
+
+    let decoded: Vec<_> = FramedRecords::new(&buf)
+        .map(|r| VisChunk::decode(r.unwrap()).unwrap())
+        .collect();
+    assert_eq!(decoded, chunks);
+
+    let record = FramedRecords::new(&buf).next().unwrap().unwrap();
+    assert!(VisChunk::decode(&record[..record.len() - 1]).is_err());
+    assert!(VisChunk::decode(&record[1..]).is_err());
+
+    let mut stream = ChunkVisStream::new(ListSource(chunks));
+    let mut seen = Vec::new();
+
+    while stream.next().unwrap() {
+        seen.push((stream.basepol(), stream.data()[1], stream.flags()[1]));
+    }
+
+    assert_eq!(
+        seen,
+        vec![
+            (BasePol::new(0, 1, VisPol::XX), Complex::new(2., 0.5), true),
+            (BasePol::new(0, 1, VisPol::YY), Complex::new(-4., 4.), false),
+            (BasePol::new(1, 2, VisPol::LR), Complex::new(7., 7.), true),
+        ]
+    );
+    assert!(!stream.next().unwrap());
+}
@@ -0,0 +1,674 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Decoding the SPEAD protocol.
+
+SPEAD (the Streaming Protocol for Exchanging Astronomical Data) is used by
+MeerKAT-style correlators to send their output over UDP. The unit of data is
+the *heap*, a set of *items*, which may be split over several packets. Each
+packet begins with an eight-byte header and a list of 64-bit *item
+pointers*. The top bit of a pointer says whether the item's value is
+*immediate* — stored in the pointer itself — or is stored in the heap's
+payload at the address given by the pointer. The rest of the pointer is
+split between the item ID and the value or address, according to the
+address width given in the header. Addressed items extend up to the next
+address in the heap, or to its end.
+
+Some item IDs are reserved for the protocol itself: they say which heap a
+packet belongs to, how big the heap is, and where in it the packet's
+payload goes. Others carry *item descriptors*, which give the name, shape,
+and type of the items that will appear in later heaps. An `ItemGroup` keeps
+track of the descriptors and the latest value of each item, in the manner of
+the `ItemGroup` class of the spead2 library.
+
+This module decodes packets with `SpeadPacket::parse`, assembles them into
+heaps with `HeapAssembler`, and decodes the heaps with `Heap` and
+`ItemGroup`. Receiving the packets from the network is left to the caller.
+
+*/
+
+use failure::err_msg;
+use rubbl_core::decode::ByteCursor;
+use rubbl_core::Result;
+use std::collections::BTreeMap;
+
+/// The first byte of every SPEAD packet.
+const MAGIC: u8 = 0x53;
+
+/// The version of the protocol that is supported.
+const VERSION: u8 = 4;
+
+/// The reserved item ID of the heap counter.
+pub const HEAP_CNT_ID: u64 = 0x01;
+
+/// The reserved item ID of the heap size.
+pub const HEAP_SIZE_ID: u64 = 0x02;
+
+/// The reserved item ID of the offset of a packet's payload in its heap.
+pub const HEAP_OFFSET_ID: u64 = 0x03;
+
+/// The reserved item ID of the length of a packet's payload.
+pub const PAYLOAD_LENGTH_ID: u64 = 0x04;
+
+/// The reserved item ID of an item descriptor.
+pub const DESCRIPTOR_ID: u64 = 0x05;
+
+/// The reserved item ID of stream control messages.
+pub const STREAM_CTRL_ID: u64 = 0x06;
+
+/// The value of a stream control item that marks the end of the stream.
+pub const STREAM_CTRL_STOP: u64 = 2;
+
+const DESCRIPTOR_NAME_ID: u64 = 0x10;
+const DESCRIPTOR_DESCRIPTION_ID: u64 = 0x11;
+const DESCRIPTOR_SHAPE_ID: u64 = 0x12;
+const DESCRIPTOR_FORMAT_ID: u64 = 0x13;
+const DESCRIPTOR_ID_ID: u64 = 0x14;
+const DESCRIPTOR_DTYPE_ID: u64 = 0x15;
+
+/// One item pointer of a packet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ItemPointer {
+    /// The item ID.
+    pub id: u64,
+
+    /// Whether the value of the item is stored in the pointer.
+    pub immediate: bool,
+
+    /// The value of the item if it is immediate, or its address in the heap
+    /// payload if not.
+    pub value: u64,
+}
+
+/// A decoded SPEAD packet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SpeadPacket<'a> {
+    /// The number of bytes used for immediate values and addresses.
+    pub heap_address_width: usize,
+
+    /// The counter of the heap that the packet belongs to.
+    pub heap_cnt: u64,
+
+    /// The total size of the heap's payload, if given.
+    pub heap_size: Option<u64>,
+
+    /// The offset of this packet's payload in the heap's payload.
+    pub heap_offset: u64,
+
+    /// The item pointers, other than those of the reserved items that
+    /// describe the packet itself.
+    pub items: Vec<ItemPointer>,
+
+    /// The packet's part of the heap payload.
+    pub payload: &'a [u8],
+}
+
+impl<'a> SpeadPacket<'a> {
+    /// Decode the packet in *data*.
+    pub fn parse(data: &'a [u8]) -> Result<Self> {
+        let mut cursor = ByteCursor::new(data);
+
+        if cursor.read_u8()? != MAGIC {
+            return Err(err_msg("not a SPEAD packet: bad magic number"));
+        }
+
+        let version = cursor.read_u8()?;
+
+        if version != VERSION {
+            return Err(err_msg(format!("unsupported SPEAD version {}", version)));
+        }
+
+        let item_pointer_width = cursor.read_u8()? as usize;
+        let heap_address_width = cursor.read_u8()? as usize;
+
+        if item_pointer_width + heap_address_width != 8 || heap_address_width == 0 {
+            return Err(err_msg(format!(
+                "unsupported SPEAD flavour with {}-byte item IDs and {}-byte addresses",
+                item_pointer_width, heap_address_width
+            )));
+        }
+
+        cursor.take(2)?;
+        let n_items = cursor.read_be_u16()?;
+
+        let address_bits = 8 * heap_address_width as u32;
+        let value_mask = (1u64 << address_bits) - 1;
+        let id_mask = (1u64 << (63 - address_bits)) - 1;
+
+        let mut packet = SpeadPacket {
+            heap_address_width: heap_address_width,
+            heap_cnt: 0,
+            heap_size: None,
+            heap_offset: 0,
+            items: Vec::with_capacity(n_items as usize),
+            payload: &[],
+        };
+        let mut heap_cnt = None;
+        let mut payload_length = None;
+
+        for _ in 0..n_items {
+            let raw = cursor.read_be_u64()?;
+            let item = ItemPointer {
+                id: (raw >> address_bits) & id_mask,
+                immediate: raw >> 63 != 0,
+                value: raw & value_mask,
+            };
+
+            match (item.id, item.immediate) {
+                (0, _) => {}
+                (HEAP_CNT_ID, true) => heap_cnt = Some(item.value),
+                (HEAP_SIZE_ID, true) => packet.heap_size = Some(item.value),
+                (HEAP_OFFSET_ID, true) => packet.heap_offset = item.value,
+                (PAYLOAD_LENGTH_ID, true) => payload_length = Some(item.value),
+                _ => packet.items.push(item),
+            }
+        }
+
+        packet.heap_cnt = match heap_cnt {
+            Some(c) => c,
+            None => return Err(err_msg("SPEAD packet has no heap counter")),
+        };
+
+        packet.payload = match payload_length {
+            Some(n) => {
+                if n > cursor.remaining().len() as u64 {
+                    return Err(err_msg(format!(
+                        "SPEAD packet claims a {}-byte payload but only has {} bytes",
+                        n,
+                        cursor.remaining().len()
+                    )));
+                }
+
+                cursor.take(n as usize)?
+            }
+            None => cursor.remaining(),
+        };
+
+        Ok(packet)
+    }
+}
+
+/// The value of an item in a heap.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ItemValue<'a> {
+    /// An immediate value.
+    Immediate(u64),
+
+    /// A value stored in the heap payload.
+    Bytes(&'a [u8]),
+}
+
+impl<'a> ItemValue<'a> {
+    /// Get the value as bytes. Immediate values are written big-endian with
+    /// *width* bytes, as the spead2 library does.
+    pub fn to_bytes(&self, width: usize) -> Vec<u8> {
+        match *self {
+            ItemValue::Immediate(v) => v.to_be_bytes()[8 - width.min(8)..].to_vec(),
+            ItemValue::Bytes(b) => b.to_vec(),
+        }
+    }
+}
+
+/// A complete heap.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Heap {
+    /// The heap counter.
+    pub cnt: u64,
+
+    /// The number of bytes used for immediate values and addresses.
+    pub heap_address_width: usize,
+
+    /// The item pointers of all of the heap's packets.
+    pub items: Vec<ItemPointer>,
+
+    /// The heap payload.
+    pub payload: Vec<u8>,
+}
+
+impl Heap {
+    /// Create a heap from a packet that holds all of it.
+    pub fn from_packet(packet: &SpeadPacket) -> Self {
+        Heap {
+            cnt: packet.heap_cnt,
+            heap_address_width: packet.heap_address_width,
+            items: packet.items.clone(),
+            payload: packet.payload.to_vec(),
+        }
+    }
+
+    /// Get the value of the first item with ID *id*, if there is one.
+    pub fn get(&self, id: u64) -> Option<ItemValue<'_>> {
+        self.items
+            .iter()
+            .find(|p| p.id == id)
+            .map(|p| self.value_of(p))
+    }
+
+    /// Iterate over the IDs and values of the items in the heap.
+    pub fn values<'a>(&'a self) -> impl Iterator<Item = (u64, ItemValue<'a>)> + 'a {
+        self.items.iter().map(move |p| (p.id, self.value_of(p)))
+    }
+
+    /// Return true if the heap marks the end of the stream.
+    pub fn is_stream_stop(&self) -> bool {
+        self.get(STREAM_CTRL_ID) == Some(ItemValue::Immediate(STREAM_CTRL_STOP))
+    }
+
+    /// Decode the item descriptors in the heap.
+    pub fn descriptors(&self) -> Result<Vec<ItemDescriptor>> {
+        let mut descriptors = Vec::new();
+
+        for (id, value) in self.values() {
+            if id != DESCRIPTOR_ID {
+                continue;
+            }
+
+            match value {
+                ItemValue::Bytes(b) => descriptors.push(ItemDescriptor::parse(b)?),
+                ItemValue::Immediate(_) => {
+                    return Err(err_msg("SPEAD item descriptor is not a heap"));
+                }
+            }
+        }
+
+        Ok(descriptors)
+    }
+
+    fn value_of(&self, pointer: &ItemPointer) -> ItemValue<'_> {
+        if pointer.immediate {
+            return ItemValue::Immediate(pointer.value);
+        }
+
+        let len = self.payload.len() as u64;
+        let start = pointer.value.min(len);
+        let end = self
+            .items
+            .iter()
+            .filter(|p| !p.immediate && p.value > pointer.value)
+            .map(|p| p.value)
+            .min()
+            .unwrap_or(len)
+            .min(len);
+
+        ItemValue::Bytes(&self.payload[start as usize..end as usize])
+    }
+}
+
+/// Assembles packets into heaps.
+///
+/// Packets of several heaps may arrive interleaved. A heap is complete once
+/// as many bytes of payload have arrived as its size; heaps whose packets
+/// do not give their size must arrive in a single packet. Duplicated
+/// packets are not detected. If too many heaps
+/// are incomplete, the oldest are discarded, on the assumption that their
+/// missing packets have been lost.
+#[derive(Clone, Debug)]
+pub struct HeapAssembler {
+    max_pending: usize,
+    max_heap_size: u64,
+    pending: BTreeMap<u64, PendingHeap>,
+    n_discarded: usize,
+}
+
+#[derive(Clone, Debug)]
+struct PendingHeap {
+    heap: Heap,
+    received: u64,
+}
+
+impl Default for HeapAssembler {
+    fn default() -> Self {
+        HeapAssembler {
+            max_pending: 8,
+            max_heap_size: 256 << 20,
+            pending: BTreeMap::new(),
+            n_discarded: 0,
+        }
+    }
+}
+
+impl HeapAssembler {
+    /// Create an assembler with the default settings: at most 8 incomplete
+    /// heaps, of up to 256 MiB each.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of incomplete heaps to keep.
+    pub fn set_max_pending(&mut self, max_pending: usize) -> &mut Self {
+        self.max_pending = max_pending.max(1);
+        self
+    }
+
+    /// Set the size of the largest heap that will be accepted, in bytes.
+    pub fn set_max_heap_size(&mut self, max_heap_size: u64) -> &mut Self {
+        self.max_heap_size = max_heap_size;
+        self
+    }
+
+    /// Get the number of incomplete heaps that have been discarded.
+    pub fn n_discarded(&self) -> usize {
+        self.n_discarded
+    }
+
+    /// Add the packet in *data*, returning its heap if it is now complete.
+    pub fn add_packet(&mut self, data: &[u8]) -> Result<Option<Heap>> {
+        let packet = SpeadPacket::parse(data)?;
+
+        let size = match packet.heap_size {
+            Some(s) => s,
+            None if packet.heap_offset == 0 => return Ok(Some(Heap::from_packet(&packet))),
+            None => {
+                return Err(err_msg(
+                    "SPEAD heap split over several packets does not give its size",
+                ));
+            }
+        };
+
+        if size > self.max_heap_size {
+            return Err(err_msg(format!(
+                "SPEAD heap of {} bytes exceeds the limit of {}",
+                size, self.max_heap_size
+            )));
+        }
+
+        let end = packet.heap_offset + packet.payload.len() as u64;
+
+        if end > size {
+            return Err(err_msg(format!(
+                "SPEAD packet extends to byte {} of a {}-byte heap",
+                end, size
+            )));
+        }
+
+        if !self.pending.contains_key(&packet.heap_cnt) {
+            while self.pending.len() >= self.max_pending {
+                let oldest = *self.pending.keys().next().unwrap();
+                self.pending.remove(&oldest);
+                self.n_discarded += 1;
+            }
+        }
+
+        let entry = self
+            .pending
+            .entry(packet.heap_cnt)
+            .or_insert_with(|| PendingHeap {
+                heap: Heap {
+                    cnt: packet.heap_cnt,
+                    heap_address_width: packet.heap_address_width,
+                    items: Vec::new(),
+                    payload: vec![0; size as usize],
+                },
+                received: 0,
+            });
+
+        if entry.heap.payload.len() as u64 != size {
+            return Err(err_msg(format!(
+                "packets of SPEAD heap {} disagree about its size",
+                packet.heap_cnt
+            )));
+        }
+
+        entry.heap.items.extend_from_slice(&packet.items);
+        entry.heap.payload[packet.heap_offset as usize..end as usize]
+            .copy_from_slice(packet.payload);
+        entry.received += packet.payload.len() as u64;
+
+        if entry.received < size {
+            return Ok(None);
+        }
+
+        Ok(self.pending.remove(&packet.heap_cnt).map(|p| p.heap))
+    }
+}
+
+/// The description of an item that may appear in later heaps.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ItemDescriptor {
+    /// The ID of the item.
+    pub id: u64,
+
+    /// The name of the item.
+    pub name: String,
+
+    /// A description of the item.
+    pub description: String,
+
+    /// The size of each dimension of the item, or `None` for dimensions of
+    /// variable size.
+    pub shape: Vec<Option<u64>>,
+
+    /// The type and number of bits of each field of the item's elements, if
+    /// it is described that way. The types are characters such as `u`
+    /// (unsigned integer), `i` (signed integer), `f` (float), and `c`
+    /// (character).
+    pub format: Vec<(char, u32)>,
+
+    /// The NumPy header describing the item's type and shape, if it is
+    /// described that way.
+    pub numpy_header: Option<String>,
+}
+
+impl ItemDescriptor {
+    /// Decode a descriptor from the value of an item descriptor item, which
+    /// is itself a SPEAD packet.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let heap = Heap::from_packet(&SpeadPacket::parse(data)?);
+        let width = heap.heap_address_width;
+
+        let id = match heap.get(DESCRIPTOR_ID_ID) {
+            Some(ItemValue::Immediate(v)) => v,
+            Some(v @ ItemValue::Bytes(_)) => {
+                let bytes = v.to_bytes(width);
+
+                if bytes.len() > 8 {
+                    return Err(err_msg("SPEAD item descriptor has an overlong ID"));
+                }
+
+                bytes.iter().fold(0, |acc, &b| (acc << 8) | b as u64)
+            }
+            None => return Err(err_msg("SPEAD item descriptor does not give an ID")),
+        };
+
+        let string = |item_id| {
+            heap.get(item_id)
+                .map(|v| String::from_utf8_lossy(&v.to_bytes(width)).into_owned())
+        };
+
+        let mut shape = Vec::new();
+
+        if let Some(v) = heap.get(DESCRIPTOR_SHAPE_ID) {
+            // Each dimension is a flag byte, nonzero if the dimension is
+            // variable, followed by its size.
+            for dim in v.to_bytes(width).chunks(width + 1) {
+                if dim.len() != width + 1 {
+                    return Err(err_msg("SPEAD item descriptor has a truncated shape"));
+                }
+
+                if dim[0] != 0 {
+                    shape.push(None);
+                } else {
+                    shape.push(Some(
+                        dim[1..].iter().fold(0, |acc, &b| (acc << 8) | b as u64),
+                    ));
+                }
+            }
+        }
+
+        let mut format = Vec::new();
+
+        if let Some(v) = heap.get(DESCRIPTOR_FORMAT_ID) {
+            // Each field is a type character followed by a 24-bit length.
+            for field in v.to_bytes(width).chunks(4) {
+                if field.len() != 4 {
+                    return Err(err_msg("SPEAD item descriptor has a truncated format"));
+                }
+
+                let bits = field[1..].iter().fold(0, |acc, &b| (acc << 8) | b as u32);
+                format.push((field[0] as char, bits));
+            }
+        }
+
+        Ok(ItemDescriptor {
+            id: id,
+            name: string(DESCRIPTOR_NAME_ID).unwrap_or_default(),
+            description: string(DESCRIPTOR_DESCRIPTION_ID).unwrap_or_default(),
+            shape: shape,
+            format: format,
+            numpy_header: string(DESCRIPTOR_DTYPE_ID),
+        })
+    }
+}
+
+/// The descriptors and latest values of the items of a stream.
+#[derive(Clone, Debug, Default)]
+pub struct ItemGroup {
+    descriptors: BTreeMap<u64, ItemDescriptor>,
+    values: BTreeMap<u64, Vec<u8>>,
+}
+
+impl ItemGroup {
+    /// Create an empty item group.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update the group with the descriptors and values in *heap*.
+    ///
+    /// Returns the IDs of the described items whose values were updated.
+    /// Values of items that have not been described are ignored.
+    pub fn update(&mut self, heap: &Heap) -> Result<Vec<u64>> {
+        for descriptor in heap.descriptors()? {
+            self.descriptors.insert(descriptor.id, descriptor);
+        }
+
+        let mut updated = Vec::new();
+
+        for (id, value) in heap.values() {
+            if id <= STREAM_CTRL_ID || !self.descriptors.contains_key(&id) {
+                continue;
+            }
+
+            self.values
+                .insert(id, value.to_bytes(heap.heap_address_width));
+            updated.push(id);
+        }
+
+        Ok(updated)
+    }
+
+    /// Get the descriptor of the item named *name*.
+    pub fn descriptor(&self, name: &str) -> Option<&ItemDescriptor> {
+        self.descriptors.values().find(|d| d.name == name)
+    }
+
+    /// Get the latest value of the item named *name*, if it has been
+    /// described and has had a value.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.descriptor(name)
+            .and_then(|d| self.values.get(&d.id))
+            .map(|v| &v[..])
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn heap_decoding() {
+    // Build packets in the SPEAD-64-48 flavour used by MeerKAT.
+    fn packet(items: &[(bool, u64, u64)], payload: &[u8]) -> Vec<u8> {
+        let mut p = vec![MAGIC, VERSION, 2, 6, 0, 0];
+        p.extend_from_slice(&(items.len() as u16).to_be_bytes());
+
+        for &(immediate, id, value) in items {
+            let raw = ((immediate as u64) << 63) | (id << 48) | value;
+            p.extend_from_slice(&raw.to_be_bytes());
+        }
+
+        p.extend_from_slice(payload);
+        p
+    }
+
+    // A descriptor of item 0x1000, "vis": 2 x variable 32-bit floats.
+    let mut desc_payload = b"vis".to_vec();
+    desc_payload.extend_from_slice(&[0, 0, 0, 0, 0, 0, 2, 1, 0, 0, 0, 0, 0, 0, b'f', 0, 0, 32]);
+    let desc = packet(
+        &[
+            (true, HEAP_CNT_ID, 1),
+            (true, DESCRIPTOR_ID_ID, 0x1000),
+            (false, DESCRIPTOR_NAME_ID, 0),
+            (false, DESCRIPTOR_SHAPE_ID, 3),
+            (false, DESCRIPTOR_FORMAT_ID, 17),
+        ],
+        &desc_payload,
+    );
+
+    let mut assembler = HeapAssembler::new();
+    let heap = assembler
+        .add_packet(&packet(
+            &[
+                (true, HEAP_CNT_ID, 1),
+                (true, HEAP_SIZE_ID, desc.len() as u64),
+                (true, HEAP_OFFSET_ID, 0),
+                (true, PAYLOAD_LENGTH_ID, desc.len() as u64),
+                (false, DESCRIPTOR_ID, 0),
+            ],
+            &desc,
+        ))
+        .unwrap()
+        .unwrap();
+
+    let mut group = ItemGroup::new();
+    assert_eq!(group.update(&heap).unwrap(), Vec::<u64>::new());
+    let d = group.descriptor("vis").unwrap();
+    assert_eq!(d.id, 0x1000);
+    assert_eq!(d.shape, vec![Some(2), None]);
+    assert_eq!(d.format, vec![('f', 32)]);
+
+    // A heap with the value of "vis" split over two packets, plus an
+    // undescribed immediate item.
+    let value: Vec<u8> = (0..16).collect();
+    let first = packet(
+        &[
+            (true, HEAP_CNT_ID, 2),
+            (true, HEAP_SIZE_ID, 16),
+            (true, HEAP_OFFSET_ID, 0),
+            (true, PAYLOAD_LENGTH_ID, 10),
+            (false, 0x1000, 0),
+            (true, 0x1001, 42),
+        ],
+        &value[..10],
+    );
+    let second = packet(
+        &[
+            (true, HEAP_CNT_ID, 2),
+            (true, HEAP_SIZE_ID, 16),
+            (true, HEAP_OFFSET_ID, 10),
+            (true, PAYLOAD_LENGTH_ID, 6),
+        ],
+        &value[10..],
+    );
+
+    assert_eq!(assembler.add_packet(&second).unwrap(), None);
+    let heap = assembler.add_packet(&first).unwrap().unwrap();
+    assert_eq!(heap.get(0x1001), Some(ItemValue::Immediate(42)));
+    assert_eq!(group.update(&heap).unwrap(), vec![0x1000]);
+    assert_eq!(group.get("vis"), Some(&value[..]));
+    assert!(!heap.is_stream_stop());
+
+    let stop = packet(
+        &[
+            (true, HEAP_CNT_ID, 3),
+            (true, STREAM_CTRL_ID, STREAM_CTRL_STOP),
+        ],
+        &[],
+    );
+    assert!(assembler
+        .add_packet(&stop)
+        .unwrap()
+        .unwrap()
+        .is_stream_stop());
+    assert!(SpeadPacket::parse(&stop[1..]).is_err());
+    assert_eq!(assembler.n_discarded(), 0);
+}
@@ -0,0 +1,263 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Corrections applied to visibilities as they are read.
+
+Data converted from older systems often need simple fixes before they can
+be used: visibilities with the wrong sign convention must be conjugated,
+polarizations that were mislabeled must be swapped, and data in arbitrary
+correlator units must be scaled. Rather than building such fixes into each
+reader, wrap the reader in a `TransformedSource` and register a
+`TransformHook` for each; the hooks are applied to every chunk, in the
+order that they were added.
+
+The common fixes are provided by `Transform`, which can be parsed from a
+short text description:
+
+- `conjugate` conjugates every visibility;
+- `scale=FACTOR` multiplies every visibility by FACTOR;
+- `swap-pols=A,B` relabels the polarization A as B and vice versa, e.g.
+  `swap-pols=XX,YY`.
+
+*/
+
+use failure::err_msg;
+use rubbl_core::kernels;
+use rubbl_core::{Complex, Result};
+use std::fmt;
+
+use super::super::VisPol;
+use super::{LiveVisSource, VisChunk, VISPOL_CODES};
+
+/// A correction applied to each chunk of visibilities as it is read.
+///
+/// Closures taking a `&mut VisChunk` can be used as hooks directly.
+pub trait TransformHook {
+    /// Modify *chunk* in place.
+    fn apply(&self, chunk: &mut VisChunk) -> Result<()>;
+}
+
+impl<F: Fn(&mut VisChunk) -> Result<()>> TransformHook for F {
+    fn apply(&self, chunk: &mut VisChunk) -> Result<()> {
+        self(chunk)
+    }
+}
+
+/// A standard correction to visibility data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    /// Replace each visibility with its complex conjugate.
+    Conjugate,
+
+    /// Multiply each visibility by a constant.
+    Scale(f32),
+
+    /// Exchange the labels of two polarizations.
+    SwapPols(VisPol, VisPol),
+}
+
+impl Transform {
+    /// Parse a description of a transform, in the syntax described in the
+    /// module documentation.
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let (name, arg) = match text.find('=') {
+            Some(i) => (text[..i].trim(), Some(text[i + 1..].trim())),
+            None => (text, None),
+        };
+
+        match (name, arg) {
+            ("conjugate", None) => Ok(Transform::Conjugate),
+
+            ("scale", Some(arg)) => match arg.parse::<f32>() {
+                Ok(f) if f.is_finite() => Ok(Transform::Scale(f)),
+                _ => Err(err_msg(format!("invalid scale factor \"{}\"", arg))),
+            },
+
+            ("swap-pols", Some(arg)) => {
+                let pols: Vec<&str> = arg.split(',').map(|s| s.trim()).collect();
+
+                if pols.len() != 2 {
+                    return Err(err_msg(format!(
+                        "expected two polarizations to swap, got \"{}\"",
+                        arg
+                    )));
+                }
+
+                Ok(Transform::SwapPols(
+                    parse_vispol(pols[0])?,
+                    parse_vispol(pols[1])?,
+                ))
+            }
+
+            _ => Err(err_msg(format!("unrecognized transform \"{}\"", text))),
+        }
+    }
+
+    /// Parse a list of transforms separated by semicolons. Empty entries are
+    /// ignored.
+    pub fn parse_list(text: &str) -> Result<Vec<Self>> {
+        text.split(';')
+            .filter(|s| !s.trim().is_empty())
+            .map(Transform::parse)
+            .collect()
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Transform::Conjugate => write!(f, "conjugate"),
+            Transform::Scale(factor) => write!(f, "scale={}", factor),
+            Transform::SwapPols(a, b) => write!(f, "swap-pols={:?},{:?}", a, b),
+        }
+    }
+}
+
+impl TransformHook for Transform {
+    fn apply(&self, chunk: &mut VisChunk) -> Result<()> {
+        match *self {
+            Transform::Conjugate => {
+                for v in &mut chunk.data {
+                    *v = v.conj();
+                }
+            }
+
+            Transform::Scale(factor) => {
+                kernels::scale_in_place(&mut chunk.data, Complex::new(factor, 0.));
+            }
+
+            Transform::SwapPols(a, b) => {
+                for bp in &mut chunk.basepols {
+                    if bp.pol == a {
+                        bp.pol = b;
+                    } else if bp.pol == b {
+                        bp.pol = a;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_vispol(text: &str) -> Result<VisPol> {
+    let upper = text.to_uppercase();
+
+    VISPOL_CODES
+        .iter()
+        .find(|p| format!("{:?}", p) == upper)
+        .cloned()
+        .ok_or_else(|| err_msg(format!("unrecognized polarization \"{}\"", text)))
+}
+
+/// A `LiveVisSource` that applies transform hooks to the chunks of another.
+pub struct TransformedSource<S> {
+    source: S,
+    hooks: Vec<Box<TransformHook>>,
+}
+
+impl<S: LiveVisSource> TransformedSource<S> {
+    /// Wrap *source*, initially with no hooks.
+    pub fn new(source: S) -> Self {
+        TransformedSource {
+            source: source,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Add a hook, to be applied after those already added.
+    pub fn add_hook<H: TransformHook + 'static>(&mut self, hook: H) -> &mut Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Get the number of hooks.
+    pub fn n_hooks(&self) -> usize {
+        self.hooks.len()
+    }
+
+    /// Get back the underlying source.
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: LiveVisSource> LiveVisSource for TransformedSource<S> {
+    fn next_chunk(&mut self) -> Result<Option<VisChunk>> {
+        let mut chunk = match self.source.next_chunk()? {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        for hook in &self.hooks {
+            hook.apply(&mut chunk)?;
+        }
+
+        Ok(Some(chunk))
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn transforms() {
+    use super::super::BasePol;
+    use rubbl_core::units::{MjdSeconds, Seconds};
+
+    struct OneChunk(Option<VisChunk>);
+
+    impl LiveVisSource for OneChunk {
+        fn next_chunk(&mut self) -> Result<Option<VisChunk>> {
+            Ok(self.0.take())
+        }
+    }
+
+    let list = Transform::parse_list("conjugate; scale = 2 ;swap-pols=xx,YY;").unwrap();
+    assert_eq!(
+        list,
+        vec![
+            Transform::Conjugate,
+            Transform::Scale(2.),
+            Transform::SwapPols(VisPol::XX, VisPol::YY),
+        ]
+    );
+    assert_eq!(list[2].to_string(), "swap-pols=XX,YY");
+    assert!(Transform::parse("conjugate=1").is_err());
+    assert!(Transform::parse("scale=nan").is_err());
+    assert!(Transform::parse("swap-pols=XX").is_err());
+    assert!(Transform::parse("swap-pols=XX,ZZ").is_err());
+
+    let mut chunk = VisChunk::new(MjdSeconds(0.), Seconds(1.), 1);
+    chunk.push(
+        BasePol::new(0, 1, VisPol::XX),
+        &[Complex::new(1., 1.)],
+        &[false],
+    );
+    chunk.push(
+        BasePol::new(0, 1, VisPol::XY),
+        &[Complex::new(0., -3.)],
+        &[false],
+    );
+
+    let mut source = TransformedSource::new(OneChunk(Some(chunk)));
+
+    for t in list {
+        source.add_hook(t);
+    }
+
+    source.add_hook(|c: &mut VisChunk| {
+        c.flags[1] = true;
+        Ok(())
+    });
+    assert_eq!(source.n_hooks(), 4);
+
+    let out = source.next_chunk().unwrap().unwrap();
+    assert_eq!(out.data, vec![Complex::new(2., -2.), Complex::new(0., 6.)]);
+    assert_eq!(out.basepols[0].pol, VisPol::YY);
+    assert_eq!(out.basepols[1].pol, VisPol::XY);
+    assert_eq!(out.flags, vec![false, true]);
+    assert!(source.next_chunk().unwrap().is_none());
+}
@@ -0,0 +1,508 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Writing streams of visibilities as zarr.
+
+A `ZarrSink` writes `VisChunk`s into a new zarr (version 3) group on disk,
+the layout favored by cloud-native processing systems. The group contains
+these arrays:
+
+- `data`, the visibilities, as `complex64`, with dimensions `time`,
+  `basepol`, and `channel`;
+- `flags`, the flags, as `bool`, with the same dimensions;
+- `weights`, the weights, as `float32`, with the same dimensions. Chunks do
+  not carry weights, so these are one for unflagged visibilities and zero for
+  flagged ones;
+- `time` and `int_time`, the midpoint and length of each integration, in MJD
+  seconds and seconds, as `float64`;
+- `antenna1`, `antenna2`, and `polarization`, which describe the basepols,
+  as `uint16`, `uint16`, and `uint8`. The polarization codes index the
+  `polarization_names` attribute of the `polarization` array.
+
+The first chunk written sets the basepols and the number of channels, and
+every later chunk must match it. The arrays along the time dimension are
+split into chunks of `ZarrSinkOptions::chunk_times` integrations, which are
+buffered in memory and written out as each one fills up; the metadata are
+rewritten at the same time, so that if the process dies, the group on disk
+holds the integrations written so far. `finalize` writes out the last,
+partial chunk, padded with fill values, as the zarr format requires.
+
+Each array is compressed with the codec that `ZarrSinkOptions::codecs`
+chooses for its name. Zstandard and LZ4 are recorded as the `zstd` and
+`numcodecs.lz4` codecs, respectively; bitshuffle-plus-LZ4 has no zarr
+equivalent and is rejected.
+
+*/
+
+use failure::err_msg;
+use rubbl_core::codec::{Codec, CodecOptions, CodecSpec};
+use rubbl_core::Result;
+use serde_json::{self, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{LiveVisSource, VisChunk, VISPOL_CODES};
+use BasePol;
+
+/// The version of the zarr format that is written.
+const ZARR_FORMAT: u32 = 3;
+
+/// Options for a `ZarrSink`.
+#[derive(Clone, Debug)]
+pub struct ZarrSinkOptions {
+    /// The number of integrations in each chunk of the arrays.
+    pub chunk_times: usize,
+
+    /// The codecs with which to compress the arrays, by array name.
+    pub codecs: CodecOptions,
+}
+
+impl Default for ZarrSinkOptions {
+    fn default() -> Self {
+        ZarrSinkOptions {
+            chunk_times: 16,
+            codecs: CodecOptions::default(),
+        }
+    }
+}
+
+/// The element type of an array.
+#[derive(Clone, Copy, Debug)]
+enum DataType {
+    Bool,
+    UInt8,
+    UInt16,
+    Float32,
+    Float64,
+    Complex64,
+}
+
+impl DataType {
+    fn name(self) -> &'static str {
+        match self {
+            DataType::Bool => "bool",
+            DataType::UInt8 => "uint8",
+            DataType::UInt16 => "uint16",
+            DataType::Float32 => "float32",
+            DataType::Float64 => "float64",
+            DataType::Complex64 => "complex64",
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            DataType::Bool | DataType::UInt8 => 1,
+            DataType::UInt16 => 2,
+            DataType::Float32 => 4,
+            DataType::Float64 | DataType::Complex64 => 8,
+        }
+    }
+
+    fn fill_value(self) -> Value {
+        match self {
+            DataType::Bool => json!(false),
+            DataType::Complex64 => json!([0.0, 0.0]),
+            DataType::Float32 | DataType::Float64 => json!(0.0),
+            DataType::UInt8 | DataType::UInt16 => json!(0),
+        }
+    }
+}
+
+/// One array of the group, and the codec used for its chunks.
+struct ZarrArray {
+    name: &'static str,
+    data_type: DataType,
+    dimension_names: &'static [&'static str],
+    spec: CodecSpec,
+    codec: Box<Codec>,
+}
+
+impl ZarrArray {
+    fn new(
+        name: &'static str,
+        data_type: DataType,
+        dimension_names: &'static [&'static str],
+        options: &CodecOptions,
+    ) -> Result<Self> {
+        let spec = options.for_dataset(name);
+
+        if let CodecSpec::BitshuffleLz4 = spec {
+            return Err(err_msg(format!(
+                "the {} codec cannot be used for zarr outputs",
+                spec
+            )));
+        }
+
+        Ok(ZarrArray {
+            name: name,
+            data_type: data_type,
+            dimension_names: dimension_names,
+            spec: spec,
+            codec: spec.build(data_type.size())?,
+        })
+    }
+
+    /// Get the metadata of the array as it appears in its `zarr.json`.
+    fn metadata(&self, shape: &[usize], chunk_shape: &[usize], attributes: Value) -> Value {
+        let mut codecs = vec![json!({
+            "name": "bytes",
+            "configuration": { "endian": "little" },
+        })];
+
+        match self.spec {
+            CodecSpec::Zstd(level) => codecs.push(json!({
+                "name": "zstd",
+                "configuration": { "level": level, "checksum": false },
+            })),
+            CodecSpec::Lz4 => codecs.push(json!({
+                "name": "numcodecs.lz4",
+                "configuration": { "acceleration": 1 },
+            })),
+            CodecSpec::None | CodecSpec::BitshuffleLz4 => {}
+        }
+
+        json!({
+            "zarr_format": ZARR_FORMAT,
+            "node_type": "array",
+            "shape": shape,
+            "data_type": self.data_type.name(),
+            "chunk_grid": {
+                "name": "regular",
+                "configuration": { "chunk_shape": chunk_shape },
+            },
+            "chunk_key_encoding": {
+                "name": "default",
+                "configuration": { "separator": "/" },
+            },
+            "fill_value": self.data_type.fill_value(),
+            "codecs": codecs,
+            "dimension_names": self.dimension_names,
+            "attributes": attributes,
+        })
+    }
+}
+
+/// Writes visibilities into a new zarr group.
+pub struct ZarrSink {
+    path: PathBuf,
+    chunk_times: usize,
+    data: ZarrArray,
+    flags: ZarrArray,
+    weights: ZarrArray,
+    time: ZarrArray,
+    int_time: ZarrArray,
+    antenna1: ZarrArray,
+    antenna2: ZarrArray,
+    polarization: ZarrArray,
+    layout: Option<(Vec<BasePol>, usize)>,
+    pending: Vec<VisChunk>,
+    n_chunks_written: usize,
+    n_times: usize,
+}
+
+impl ZarrSink {
+    /// Create a new zarr group at *path*.
+    ///
+    /// It is an error if something already exists at *path*, or if
+    /// *options* choose a codec that is unavailable or that zarr does not
+    /// support.
+    pub fn create<P: AsRef<Path>>(path: P, options: &ZarrSinkOptions) -> Result<Self> {
+        let path = path.as_ref();
+        let codecs = &options.codecs;
+
+        if options.chunk_times == 0 {
+            return Err(err_msg("zarr chunks must contain at least one integration"));
+        }
+
+        const VIS_DIMS: &[&str] = &["time", "basepol", "channel"];
+
+        let sink = ZarrSink {
+            path: path.to_owned(),
+            chunk_times: options.chunk_times,
+            data: ZarrArray::new("data", DataType::Complex64, VIS_DIMS, codecs)?,
+            flags: ZarrArray::new("flags", DataType::Bool, VIS_DIMS, codecs)?,
+            weights: ZarrArray::new("weights", DataType::Float32, VIS_DIMS, codecs)?,
+            time: ZarrArray::new("time", DataType::Float64, &["time"], codecs)?,
+            int_time: ZarrArray::new("int_time", DataType::Float64, &["time"], codecs)?,
+            antenna1: ZarrArray::new("antenna1", DataType::UInt16, &["basepol"], codecs)?,
+            antenna2: ZarrArray::new("antenna2", DataType::UInt16, &["basepol"], codecs)?,
+            polarization: ZarrArray::new("polarization", DataType::UInt8, &["basepol"], codecs)?,
+            layout: None,
+            pending: Vec::new(),
+            n_chunks_written: 0,
+            n_times: 0,
+        };
+
+        fs::create_dir(path)?;
+        write_json(
+            &path.join("zarr.json"),
+            &json!({
+                "zarr_format": ZARR_FORMAT,
+                "node_type": "group",
+                "attributes": {},
+            }),
+        )?;
+        Ok(sink)
+    }
+
+    /// Get the path of the group.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the number of integrations written so far, including those
+    /// still buffered.
+    pub fn n_times(&self) -> usize {
+        self.n_times
+    }
+
+    /// Add an integration to the group.
+    ///
+    /// It is an error if its basepols or number of channels differ from
+    /// those of the first chunk written.
+    pub fn write_chunk(&mut self, chunk: &VisChunk) -> Result<()> {
+        match self.layout {
+            Some((ref basepols, n_chan)) => {
+                if chunk.n_chan != n_chan || chunk.basepols != *basepols {
+                    return Err(err_msg(format!(
+                        "the visibility chunk at time {} has a different layout than the first one",
+                        chunk.time
+                    )));
+                }
+            }
+
+            None => {
+                self.layout = Some((chunk.basepols.clone(), chunk.n_chan));
+                self.write_basepols()?;
+            }
+        }
+
+        self.pending.push(chunk.clone());
+        self.n_times += 1;
+
+        if self.pending.len() == self.chunk_times {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write all of the chunks of *source* into the group.
+    pub fn write_source<S: LiveVisSource>(&mut self, source: &mut S) -> Result<()> {
+        while let Some(chunk) = source.next_chunk()? {
+            self.write_chunk(&chunk)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write out the buffered integrations, if there are any, and close the
+    /// group. Returns the number of integrations written.
+    pub fn finalize(mut self) -> Result<usize> {
+        if !self.pending.is_empty() {
+            self.flush()?;
+        }
+
+        Ok(self.n_times())
+    }
+
+    /// Write out the buffered integrations as the next chunk along the time
+    /// dimension, padding it if it is not full, and update the metadata.
+    fn flush(&mut self) -> Result<()> {
+        let (n_bp, n_chan) = match self.layout {
+            Some((ref basepols, n_chan)) => (basepols.len(), n_chan),
+            None => return Ok(()),
+        };
+
+        let n_times = self.n_times;
+        let n_pad = self.chunk_times - self.pending.len();
+        let n_vis = n_bp * n_chan;
+        let key = format!("{}/0/0", self.n_chunks_written);
+
+        let mut data = Vec::with_capacity(self.chunk_times * n_vis * 8);
+        let mut flags = Vec::with_capacity(self.chunk_times * n_vis);
+        let mut weights = Vec::with_capacity(self.chunk_times * n_vis * 4);
+        let mut time = Vec::with_capacity(self.chunk_times * 8);
+        let mut int_time = Vec::with_capacity(self.chunk_times * 8);
+
+        for chunk in &self.pending {
+            for v in &chunk.data {
+                data.extend_from_slice(&v.re.to_le_bytes());
+                data.extend_from_slice(&v.im.to_le_bytes());
+            }
+
+            for &f in &chunk.flags {
+                flags.push(f as u8);
+                weights.extend_from_slice(&(if f { 0f32 } else { 1f32 }).to_le_bytes());
+            }
+
+            time.extend_from_slice(&chunk.time.0.to_le_bytes());
+            int_time.extend_from_slice(&chunk.int_time.0.to_le_bytes());
+        }
+
+        data.resize(data.len() + n_pad * n_vis * 8, 0);
+        flags.resize(flags.len() + n_pad * n_vis, 0);
+        weights.resize(weights.len() + n_pad * n_vis * 4, 0);
+        time.resize(time.len() + n_pad * 8, 0);
+        int_time.resize(int_time.len() + n_pad * 8, 0);
+
+        let index = self.n_chunks_written.to_string();
+        self.write_array_chunk(&self.data, &key, &data)?;
+        self.write_array_chunk(&self.flags, &key, &flags)?;
+        self.write_array_chunk(&self.weights, &key, &weights)?;
+        self.write_array_chunk(&self.time, &index, &time)?;
+        self.write_array_chunk(&self.int_time, &index, &int_time)?;
+
+        let vis_shape = [n_times, n_bp, n_chan];
+        let vis_chunk_shape = [self.chunk_times, n_bp, n_chan];
+
+        for array in &[&self.data, &self.flags, &self.weights] {
+            self.write_metadata(array, &vis_shape, &vis_chunk_shape, json!({}))?;
+        }
+
+        self.write_metadata(
+            &self.time,
+            &[n_times],
+            &[self.chunk_times],
+            json!({ "units": "MJD seconds" }),
+        )?;
+        self.write_metadata(
+            &self.int_time,
+            &[n_times],
+            &[self.chunk_times],
+            json!({ "units": "seconds" }),
+        )?;
+
+        self.pending.clear();
+        self.n_chunks_written += 1;
+        Ok(())
+    }
+
+    /// Write the arrays describing the basepols, which are stored in a
+    /// single chunk.
+    fn write_basepols(&self) -> Result<()> {
+        let basepols = match self.layout {
+            Some((ref basepols, _)) => basepols,
+            None => return Ok(()),
+        };
+
+        let mut ant1 = Vec::with_capacity(2 * basepols.len());
+        let mut ant2 = Vec::with_capacity(2 * basepols.len());
+        let mut pols = Vec::with_capacity(basepols.len());
+
+        for bp in basepols {
+            ant1.extend_from_slice(&bp.ant1.to_le_bytes());
+            ant2.extend_from_slice(&bp.ant2.to_le_bytes());
+            pols.push(VISPOL_CODES.iter().position(|&p| p == bp.pol).unwrap() as u8);
+        }
+
+        // A chunk may not be empty, even if the array is.
+        let shape = [basepols.len()];
+        let chunk_shape = [::std::cmp::max(basepols.len(), 1)];
+        let pol_names: Vec<_> = VISPOL_CODES.iter().map(|p| format!("{:?}", p)).collect();
+
+        self.write_metadata(&self.antenna1, &shape, &chunk_shape, json!({}))?;
+        self.write_metadata(&self.antenna2, &shape, &chunk_shape, json!({}))?;
+        self.write_metadata(
+            &self.polarization,
+            &shape,
+            &chunk_shape,
+            json!({ "polarization_names": pol_names }),
+        )?;
+
+        if !basepols.is_empty() {
+            self.write_array_chunk(&self.antenna1, "0", &ant1)?;
+            self.write_array_chunk(&self.antenna2, "0", &ant2)?;
+            self.write_array_chunk(&self.polarization, "0", &pols)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_metadata(
+        &self,
+        array: &ZarrArray,
+        shape: &[usize],
+        chunk_shape: &[usize],
+        attributes: Value,
+    ) -> Result<()> {
+        let dir = self.path.join(array.name);
+        fs::create_dir_all(&dir)?;
+        write_json(
+            &dir.join("zarr.json"),
+            &array.metadata(shape, chunk_shape, attributes),
+        )
+    }
+
+    /// Compress and write the chunk of *array* whose key, relative to the
+    /// array's `c` directory, is *key*.
+    fn write_array_chunk(&self, array: &ZarrArray, key: &str, bytes: &[u8]) -> Result<()> {
+        let path = self.path.join(array.name).join("c").join(key);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&path, array.codec.encode(bytes)?)?;
+        Ok(())
+    }
+}
+
+/// Write *value* to *path* as pretty-printed JSON.
+fn write_json(path: &Path, value: &Value) -> Result<()> {
+    let mut text = serde_json::to_string_pretty(value)?;
+    text.push('\n');
+    fs::write(path, text)?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn zarr_layout() {
+    use rubbl_core::units::{MjdSeconds, Seconds};
+    use rubbl_core::Complex;
+    use VisPol;
+
+    let dir = ::std::env::temp_dir().join(format!("rubbl-zarr-test-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let options = ZarrSinkOptions {
+        chunk_times: 2,
+        ..ZarrSinkOptions::default()
+    };
+    let mut sink = ZarrSink::create(&dir, &options).unwrap();
+
+    for i in 0..3 {
+        let mut chunk = VisChunk::new(MjdSeconds(1000. + i as f64), Seconds(1.), 2);
+        chunk.push(
+            BasePol::new(0, 1, VisPol::XX),
+            &[Complex::new(i as f32, 1.), Complex::new(2., 3.)],
+            &[false, i == 1],
+        );
+        sink.write_chunk(&chunk).unwrap();
+    }
+
+    let bad = VisChunk::new(MjdSeconds(1003.), Seconds(1.), 3);
+    assert!(sink.write_chunk(&bad).is_err());
+    assert_eq!(sink.finalize().unwrap(), 3);
+
+    let meta: Value =
+        serde_json::from_slice(&fs::read(dir.join("data").join("zarr.json")).unwrap()).unwrap();
+    assert_eq!(meta["shape"], json!([3, 1, 2]));
+    assert_eq!(
+        meta["chunk_grid"]["configuration"]["chunk_shape"],
+        json!([2, 1, 2])
+    );
+
+    // The last chunk is padded to a full two integrations.
+    let weights = fs::read(dir.join("weights/c/1/0/0")).unwrap();
+    assert_eq!(weights.len(), 2 * 2 * 4);
+    let flags = fs::read(dir.join("flags/c/0/0/0")).unwrap();
+    assert_eq!(flags, vec![0, 0, 0, 1]);
+    let time = fs::read(dir.join("time/c/1")).unwrap();
+    assert_eq!(&time[..8], &1002f64.to_le_bytes());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,209 @@
+// Copyright 2019 Peter Williams <peter@newton.cx> and collaborators
+// Licensed under the MIT License.
+
+/*!
+
+Time–frequency “waterfall” images of visibilities.
+
+A `Waterfall` collects the spectra of one basepol as they are read, in any
+order, and renders them as an image with frequency increasing to the right
+and time increasing downwards. Spectra with the same timestamp are
+averaged, ignoring flagged channels; channels that are flagged in all of
+them are drawn in `colormap::BAD_COLOR`.
+
+*/
+
+use failure::err_msg;
+use rubbl_core::colormap::{Colormap, RgbImage, BAD_COLOR};
+use rubbl_core::{Complex, Result};
+use std::collections::BTreeMap;
+use std::f64;
+
+/// The fraction of amplitudes that are allowed to saturate at each end of
+/// the color scale, so that a few outliers do not wash out the image.
+const AMPLITUDE_CLIP: f64 = 0.01;
+
+/// The quantity that a waterfall shows.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum WaterfallQuantity {
+    /// The visibility amplitude, scaled between its 1st and 99th
+    /// percentiles.
+    Amplitude,
+
+    /// The visibility phase, from −π to π.
+    Phase,
+}
+
+impl WaterfallQuantity {
+    /// Get a short name for this quantity, suitable for use in file names.
+    pub fn name(self) -> &'static str {
+        match self {
+            WaterfallQuantity::Amplitude => "amp",
+            WaterfallQuantity::Phase => "phase",
+        }
+    }
+}
+
+/// The averaged spectrum of one timestamp.
+#[derive(Clone, Debug)]
+struct Row {
+    sums: Vec<Complex<f64>>,
+    counts: Vec<u32>,
+}
+
+/// An accumulator of the spectra of one basepol.
+#[derive(Clone, Debug)]
+pub struct Waterfall {
+    n_chan: usize,
+    rows: BTreeMap<u64, Row>,
+}
+
+impl Waterfall {
+    /// Create an empty waterfall for spectra of *n_chan* channels.
+    pub fn new(n_chan: usize) -> Self {
+        Waterfall {
+            n_chan: n_chan,
+            rows: BTreeMap::new(),
+        }
+    }
+
+    /// Get the number of distinct timestamps seen so far, which is the height
+    /// of the rendered image.
+    pub fn n_times(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Add the spectrum *data* at *time*, with *flags* set for the channels
+    /// that are bad. Timestamps are compared exactly, so they should come
+    /// straight from the data set.
+    pub fn push(&mut self, time: f64, data: &[Complex<f32>], flags: &[bool]) -> Result<()> {
+        if data.len() != self.n_chan || flags.len() != self.n_chan {
+            return Err(err_msg(format!(
+                "expected a spectrum of {} channels, got {} data and {} flags",
+                self.n_chan,
+                data.len(),
+                flags.len()
+            )));
+        }
+
+        let n_chan = self.n_chan;
+        let row = self.rows.entry(time_key(time)).or_insert_with(|| Row {
+            sums: vec![Complex::new(0., 0.); n_chan],
+            counts: vec![0; n_chan],
+        });
+
+        for (i, (d, &f)) in data.iter().zip(flags).enumerate() {
+            if !f && d.re.is_finite() && d.im.is_finite() {
+                row.sums[i] += Complex::new(d.re as f64, d.im as f64);
+                row.counts[i] += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Render the waterfall as an image of *quantity*, one pixel per channel
+    /// and timestamp.
+    pub fn render(&self, quantity: WaterfallQuantity, colormap: Colormap) -> RgbImage {
+        let mut image = RgbImage::new(self.n_chan, self.rows.len());
+        let values: Vec<Vec<f64>> = self
+            .rows
+            .values()
+            .map(|row| {
+                row.sums
+                    .iter()
+                    .zip(&row.counts)
+                    .map(|(s, &n)| {
+                        if n == 0 {
+                            f64::NAN
+                        } else {
+                            match quantity {
+                                WaterfallQuantity::Amplitude => s.norm() / n as f64,
+                                WaterfallQuantity::Phase => s.arg(),
+                            }
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (lo, hi) = match quantity {
+            WaterfallQuantity::Amplitude => amplitude_range(&values),
+            WaterfallQuantity::Phase => (-f64::consts::PI, f64::consts::PI),
+        };
+        let scale = if hi > lo { 1. / (hi - lo) } else { 0. };
+
+        for (y, row) in values.iter().enumerate() {
+            for (x, &v) in row.iter().enumerate() {
+                let rgb = if v.is_nan() {
+                    BAD_COLOR
+                } else {
+                    colormap.rgb((v - lo) * scale)
+                };
+                image.set_pixel(x, y, rgb);
+            }
+        }
+
+        image
+    }
+}
+
+/// Map a timestamp to a key that sorts in the same order. Negative zero is
+/// folded into zero so that it does not start a separate row.
+fn time_key(time: f64) -> u64 {
+    let bits = (time + 0.).to_bits();
+
+    if bits >> 63 == 0 {
+        bits | (1 << 63)
+    } else {
+        !bits
+    }
+}
+
+/// Find the range of the color scale of an amplitude waterfall, excluding
+/// the most extreme values.
+fn amplitude_range(values: &[Vec<f64>]) -> (f64, f64) {
+    let mut all: Vec<f64> = values
+        .iter()
+        .flat_map(|row| row.iter().cloned())
+        .filter(|v| !v.is_nan())
+        .collect();
+
+    if all.is_empty() {
+        return (0., 1.);
+    }
+
+    all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let last = all.len() - 1;
+    let clip = (AMPLITUDE_CLIP * last as f64).round() as usize;
+    (all[clip], all[last - clip])
+}
+
+#[cfg(test)]
+#[test]
+fn waterfall_rendering() {
+    let mut wf = Waterfall::new(3);
+    let one = Complex::new(1., 0.);
+    let minus_i = Complex::new(0., -2.);
+
+    // Two spectra at t = 20, averaged; one at t = 10, which sorts first.
+    wf.push(20., &[one, minus_i, one], &[false, false, true])
+        .unwrap();
+    wf.push(20., &[one, minus_i, one], &[false, false, true])
+        .unwrap();
+    wf.push(10., &[minus_i, one, one], &[false, false, false])
+        .unwrap();
+    assert!(wf.push(10., &[one], &[false]).is_err());
+    assert_eq!(wf.n_times(), 2);
+
+    let amp = wf.render(WaterfallQuantity::Amplitude, Colormap::Gray);
+    assert_eq!((amp.width(), amp.height()), (3, 2));
+    assert_eq!(amp.pixel(0, 0), [255, 255, 255]);
+    assert_eq!(amp.pixel(1, 0), [0, 0, 0]);
+    assert_eq!(amp.pixel(1, 1), [255, 255, 255]);
+    assert_eq!(amp.pixel(2, 1), BAD_COLOR);
+
+    let phase = wf.render(WaterfallQuantity::Phase, Colormap::Gray);
+    assert_eq!(phase.pixel(1, 0), [128, 128, 128]);
+    assert_eq!(phase.pixel(0, 0), [64, 64, 64]);
+}